@@ -0,0 +1,78 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Stable client API of the Pub Sub Service.
+//!
+//! Publishers and subscribers outside of this repository should depend on this crate rather than
+//! on the other crates of the workspace. It re-exports the generated gRPC messages and clients, and
+//! the types that interpret the fields of the messages, such as the subscription metadata that
+//! publishers hand out. Everything reachable from this crate follows the semantic versioning
+//! policy in `docs/api-stability.md`: breaking changes are only made in a new major version.
+//!
+//! The enums and the structs that may gain fields are `#[non_exhaustive]`, so that variants and
+//! fields can be added in a minor version. Match them with a wildcard arm, and build the structs
+//! with their constructors.
+
+/// The generated messages and clients of the Pub Sub Service gRPC interfaces, along with the
+/// `tonic`, `prost` and `prost_types` versions they are generated with.
+///
+/// The items are re-exported by name rather than as a whole crate, so that each of them is part of
+/// the checked public API.
+pub mod proto {
+    pub use ::proto::{prost, prost_types, tonic};
+
+    /// Messages and client of the Pub Sub Service interface.
+    pub mod pubsub {
+        pub mod v1 {
+            pub use ::proto::pubsub::v1::{
+                pub_sub_client, AddTopicPublisherRequest, AddTopicPublisherResponse,
+                AdoptTopicRequest, AdoptTopicResponse, ClaimTopicRequest, ClaimTopicResponse,
+                CreateResponseTopicRequest, CreateResponseTopicResponse, CreateTopicRequest,
+                CreateTopicResponse, DeleteTopicRequest, DeleteTopicResponse, GetSchemaRequest,
+                GetSchemaResponse, PayloadCompression, ProtocolKind, RegisterPublisherRequest,
+                RegisterPublisherResponse, ReleaseTopicRequest, ReleaseTopicResponse,
+                RenewSubscriptionLeaseRequest, RenewSubscriptionLeaseResponse, RequestTopicRequest,
+                RequestTopicResponse, TopicClass, FILE_DESCRIPTOR_SET,
+            };
+        }
+    }
+
+    /// Messages and client of the publisher callback interface.
+    pub mod publisher {
+        pub mod v1 {
+            pub use ::proto::publisher::v1::{
+                publisher_callback_client, ManageTopicRequest, ManageTopicResponse,
+            };
+        }
+    }
+
+    /// Messages and client of the admin interface.
+    pub mod admin {
+        pub mod v1 {
+            pub use ::proto::admin::v1::{
+                admin_client, CallbackBreaker, DeleteTopicsWhereRequest, DeleteTopicsWhereResponse,
+                DumpStateRequest, DumpStateResponse, ExportTopologyRequest, ExportTopologyResponse,
+                GetBrokerStatsRequest, GetBrokerStatsResponse, GetCallbackBreakersRequest,
+                GetCallbackBreakersResponse, GetCleanupReportRequest, GetCleanupReportResponse,
+                GetHealthRequest, GetHealthResponse, GetMetricsRequest, GetMetricsResponse,
+                GetTopicInfoRequest, GetTopicInfoResponse, ListPlaceholderTopicsRequest,
+                ListPlaceholderTopicsResponse, MonitorSubscription, PlaceholderTopic,
+                RunSelfTestRequest, RunSelfTestResponse, SelfTestStep, SetFaultInjectionRequest,
+                SetFaultInjectionResponse, SetPowerStateRequest, SetPowerStateResponse, TaskHealth,
+                TopicActionRecord, TopicEvent, TopologyFormat, WatchTopicsRequest,
+            };
+        }
+    }
+}
+
+/// Types that interpret the fields of the Pub Sub Service and publisher messages.
+pub mod types {
+    pub use common::action_casing::{normalize_action, ActionCasing};
+    pub use common::payload_compression::{ParsePayloadCompressionError, PayloadCompression};
+    pub use common::protocol_kind::{ParseProtocolKindError, ProtocolKind};
+    pub use common::subscription_metadata::{
+        AuthHints, AuthMechanism, SchemaReference, SubscriptionMetadata,
+    };
+    pub use common::topic_class::TopicClass;
+}
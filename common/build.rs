@@ -0,0 +1,112 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Generates compile-time constants from the default constants file.
+//!
+//! Writes `constants.rs`, a Rust module with a constant for every entry of the file, and
+//! `constants.json`, the same entries for clients written in other languages, to `OUT_DIR`.
+
+use std::{env, error::Error, fs, path::PathBuf};
+
+use yaml_rust::{Yaml, YamlLoader};
+
+/// The constants file that the constants are generated from.
+const CONSTANTS_FILE: &str = "../config/constants.default.yaml";
+
+/// Collects the comment lines directly above each top level key of the constants file.
+///
+/// # Arguments
+/// * `contents` - The contents of the constants file.
+fn collect_key_docs(contents: &str) -> Vec<(String, Vec<String>)> {
+    let mut docs = Vec::new();
+    let mut comment = Vec::new();
+
+    for line in contents.lines() {
+        if let Some(text) = line.strip_prefix('#') {
+            // Section headers start with more than one '#'.
+            if !text.starts_with('#') && !text.trim().is_empty() {
+                comment.push(text.trim().to_string());
+            }
+        } else if let Some((key, _)) = line.split_once(':') {
+            if !key.starts_with(' ') && !key.is_empty() {
+                docs.push((key.trim().to_string(), std::mem::take(&mut comment)));
+            }
+        } else {
+            comment.clear();
+        }
+    }
+
+    docs
+}
+
+/// Converts a constant value to a Rust type and literal.
+///
+/// # Arguments
+/// * `key` - The key of the constant, used in error messages.
+/// * `value` - The value of the constant.
+fn rust_literal(key: &str, value: &Yaml) -> Result<(&'static str, String), Box<dyn Error>> {
+    match value {
+        Yaml::String(value) => Ok(("&str", format!("{value:?}"))),
+        Yaml::Integer(value) if *value >= 0 => Ok(("u64", value.to_string())),
+        Yaml::Integer(value) => Ok(("i64", value.to_string())),
+        Yaml::Real(value) => Ok(("f64", value.clone())),
+        Yaml::Boolean(value) => Ok(("bool", value.to_string())),
+        _ => Err(format!("constant '{key}' must be a string, number or boolean").into()),
+    }
+}
+
+/// Converts a constant value to a JSON value.
+///
+/// # Arguments
+/// * `value` - The value of the constant.
+fn json_value(value: &Yaml) -> serde_json::Value {
+    match value {
+        Yaml::String(value) => serde_json::Value::from(value.as_str()),
+        Yaml::Integer(value) => serde_json::Value::from(*value),
+        Yaml::Real(value) => value
+            .parse::<f64>()
+            .map(serde_json::Value::from)
+            .unwrap_or_default(),
+        Yaml::Boolean(value) => serde_json::Value::from(*value),
+        _ => serde_json::Value::Null,
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    println!("cargo:rerun-if-changed={CONSTANTS_FILE}");
+
+    let contents = fs::read_to_string(CONSTANTS_FILE)?;
+    let document = YamlLoader::load_from_str(&contents)?
+        .into_iter()
+        .next()
+        .ok_or("the constants file is empty")?;
+
+    let mut rust_module = String::from("// Generated from config/constants.default.yaml.\n");
+    let mut json_object = serde_json::Map::new();
+
+    for (key, docs) in collect_key_docs(&contents) {
+        let value = &document[key.as_str()];
+        let (rust_type, literal) = rust_literal(&key, value)?;
+
+        rust_module.push('\n');
+        for doc in docs {
+            rust_module.push_str(&format!("/// {doc}\n"));
+        }
+        rust_module.push_str(&format!(
+            "pub const {}: {rust_type} = {literal};\n",
+            key.to_uppercase()
+        ));
+
+        json_object.insert(key, json_value(value));
+    }
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+    fs::write(out_dir.join("constants.rs"), rust_module)?;
+    fs::write(
+        out_dir.join("constants.json"),
+        serde_json::to_string_pretty(&json_object)?,
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,74 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use serde_derive::{Deserialize, Serialize};
+
+/// Casing of the topic management actions sent to publishers.
+///
+/// Actions are upper-case (such as `START`) by default. Some publisher frameworks expect
+/// lower-case actions instead.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum ActionCasing {
+    /// Upper-case actions, such as `SUBSCRIBER_JOINED`.
+    #[default]
+    Upper,
+    /// Lower-case actions, such as `subscriber_joined`.
+    Lower,
+}
+
+impl ActionCasing {
+    /// Returns the given action with this casing.
+    ///
+    /// # Arguments
+    /// * `action` - The action to format.
+    pub fn format(self, action: &str) -> String {
+        match self {
+            ActionCasing::Upper => action.to_ascii_uppercase(),
+            ActionCasing::Lower => action.to_ascii_lowercase(),
+        }
+    }
+}
+
+/// Normalizes an action received from a peer to the upper-case form it is parsed from, so that
+/// actions are accepted regardless of their casing. Surrounding whitespace is trimmed and `-`
+/// separators are accepted in place of `_`.
+///
+/// # Arguments
+/// * `action` - The action to normalize.
+pub fn normalize_action(action: &str) -> String {
+    action.trim().to_ascii_uppercase().replace('-', "_")
+}
+
+#[cfg(test)]
+mod action_casing_tests {
+    use super::*;
+
+    #[test]
+    fn format_action_test() {
+        assert_eq!("START", ActionCasing::Upper.format("start"));
+        assert_eq!(
+            "subscriber_joined",
+            ActionCasing::Lower.format("SUBSCRIBER_JOINED")
+        );
+        assert_eq!(ActionCasing::Upper, ActionCasing::default());
+    }
+
+    #[test]
+    fn normalize_action_test() {
+        assert_eq!("START", normalize_action("START"));
+        assert_eq!("STOP", normalize_action("stop"));
+        assert_eq!("DELETE", normalize_action(" Delete\n"));
+        assert_eq!("SUBSCRIBER_JOINED", normalize_action("subscriber-joined"));
+    }
+
+    #[test]
+    fn deserialize_action_casing_test() {
+        let casing: ActionCasing = serde_json::from_str("\"lower\"").unwrap();
+        assert_eq!(ActionCasing::Lower, casing);
+
+        assert!(serde_json::from_str::<ActionCasing>("\"camel\"").is_err());
+    }
+}
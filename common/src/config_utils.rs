@@ -86,6 +86,36 @@ impl ConfigFileMetadata {
 
         Ok(ConfigFileMetadata { name, ext })
     }
+
+    /// Returns the metadata of the delta file of a deployment ring, named
+    /// `{file_stem}.{deployment_ring}.{ext}`, which overrides this file for that ring.
+    /// Will result in an error if the ring name is not made of letters, digits, `-` and `_`.
+    ///
+    /// # Arguments
+    /// * `deployment_ring` - The name of the deployment ring, such as "cloud" or "vehicle".
+    pub fn for_deployment_ring(
+        &self,
+        deployment_ring: &str,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let is_valid_ring = !deployment_ring.is_empty()
+            && deployment_ring
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if !is_valid_ring {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid deployment ring '{deployment_ring}'. Expected letters, digits, '-' and '_'."),
+            )));
+        }
+
+        // The name is known to have a file stem and an extension.
+        let (file_stem, ext) = self.name.rsplit_once(FILE_SEPARATOR).unwrap_or_default();
+
+        ConfigFileMetadata::new(&format!(
+            "{file_stem}{FILE_SEPARATOR}{deployment_ring}{FILE_SEPARATOR}{ext}"
+        ))
+    }
 }
 
 /// Loads default config source for the given configuration file.
@@ -192,7 +222,9 @@ where
 /// list taking higher precedence:
 ///
 /// - default config file
+/// - default delta file of the deployment ring, if any
 /// - config file
+/// - delta file of the deployment ring
 /// - commandline args
 ///
 /// Since the configuration is layered, config can be partially defined. Any unspecified
@@ -205,12 +237,14 @@ where
 ///                   by the `include_dir!` macro.
 /// * `svc_home_metadata` - Metadata related to the service's home and config directories. Used to
 ///                         get path to provided config file.
+/// * `deployment_ring` - Optional deployment ring whose delta files override the config files.
 /// * `cmdline_args` - Optional commandline config arguments.
 pub fn load_config<TConfig, TArgs>(
     config_file: &ConfigFileMetadata,
     default_config_file: &ConfigFileMetadata,
     default_dir: &Dir,
     svc_home_metadata: &SvcConfigHomeMetadata,
+    deployment_ring: Option<&str>,
     cmdline_args: Option<TArgs>,
 ) -> Result<TConfig, Box<dyn std::error::Error + Send + Sync>>
 where
@@ -221,10 +255,29 @@ where
 
     // Find and read configuration file for any overrides.
     let config_path = get_config_home_path_from_env(svc_home_metadata)?;
-    let file_source = read_config_from_file(config_file, config_path)?;
+    let file_source = read_config_from_file(config_file, &config_path)?;
 
     // Create source list from lowest to highest priority.
-    let mut sources = vec![default_source, file_source];
+    let mut sources = vec![default_source];
+
+    match deployment_ring {
+        Some(deployment_ring) => {
+            // Only some rings have a default delta file, while any ring can have a delta file
+            // next to the config file.
+            let default_ring_file = default_config_file.for_deployment_ring(deployment_ring)?;
+            if default_dir.get_file(&default_ring_file.name).is_some() {
+                sources.push(load_default_config_from_file(
+                    &default_ring_file,
+                    default_dir,
+                )?);
+            }
+
+            let ring_file = config_file.for_deployment_ring(deployment_ring)?;
+            sources.push(file_source);
+            sources.push(read_config_from_file(&ring_file, &config_path)?);
+        }
+        None => sources.push(file_source),
+    }
 
     // If commandline args are present, add them to the source list.
     // Commandline args will override any config from file sources.
@@ -346,6 +399,99 @@ mod config_utils_tests {
         assert_eq!(err_4.kind(), std::io::ErrorKind::NotFound);
     }
 
+    #[test]
+    fn config_metadata_for_deployment_ring() {
+        let config_file = ConfigFileMetadata::new("constants.yaml").unwrap();
+        let ring_file = config_file.for_deployment_ring("cloud").unwrap();
+        assert_eq!(ring_file.name, "constants.cloud.yaml");
+        assert_eq!(ring_file.ext, FileFormat::Yaml);
+
+        let default_file = ConfigFileMetadata::new("constants.default.yaml").unwrap();
+        let default_ring_file = default_file.for_deployment_ring("vehicle-eu").unwrap();
+        assert_eq!(default_ring_file.name, "constants.default.vehicle-eu.yaml");
+
+        for invalid_ring in ["", "cloud.yaml", "../cloud"] {
+            let err = config_file
+                .for_deployment_ring(invalid_ring)
+                .err()
+                .unwrap()
+                .downcast::<std::io::Error>()
+                .unwrap();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn load_config_layers_deployment_ring_deltas() {
+        let env_var_key = "TEST_RING_ENV_VAR";
+        let config_dir = "config";
+        let svc_home = env::temp_dir().join(format!("agemo-ring-test-{}", std::process::id()));
+        std::fs::create_dir_all(svc_home.join(config_dir)).unwrap();
+
+        // The config directory overrides one of the values of the cloud ring.
+        std::fs::write(
+            svc_home.join(config_dir).join("constants.cloud.yaml"),
+            "grpc_kind: override",
+        )
+        .unwrap();
+
+        let config_file = ConfigFileMetadata::new("constants.yaml").unwrap();
+        let default_config_file = ConfigFileMetadata::new("constants.default.yaml").unwrap();
+
+        // Only the cloud ring has a default delta file.
+        let default_file = File::new(
+            "constants.default.yaml",
+            b"retry_interval_secs: 5\ngrpc_kind: grpc",
+        );
+        let default_cloud_file =
+            File::new("constants.default.cloud.yaml", b"retry_interval_secs: 30");
+        let entries = &[
+            DirEntry::File(default_file),
+            DirEntry::File(default_cloud_file),
+        ];
+        let dir = Dir::new("", entries);
+
+        env::set_var(env_var_key, &svc_home);
+
+        let svc_home_metadata = SvcConfigHomeMetadata {
+            home_env_var: env_var_key.to_string(),
+            home_dir: ".svc".to_string(),
+            config_dir: config_dir.to_string(),
+        };
+
+        let load = |deployment_ring| {
+            load_config::<serde_json::Value, config::Environment>(
+                &config_file,
+                &default_config_file,
+                &dir,
+                &svc_home_metadata,
+                deployment_ring,
+                None,
+            )
+        };
+
+        let cloud = load(Some("cloud"));
+        let vehicle = load(Some("vehicle"));
+        let no_ring = load(None);
+        let invalid_ring = load(Some("../cloud"));
+
+        // Clean up before asserting, so that a failure doesn't leak the environment variable.
+        env::remove_var(env_var_key);
+        std::fs::remove_dir_all(&svc_home).unwrap();
+
+        let cloud = cloud.unwrap();
+        assert_eq!(cloud["retry_interval_secs"], serde_json::json!(30));
+        assert_eq!(cloud["grpc_kind"], serde_json::json!("override"));
+
+        // A ring without delta files uses the config files as is.
+        let vehicle = vehicle.unwrap();
+        assert_eq!(vehicle["retry_interval_secs"], serde_json::json!(5));
+        assert_eq!(vehicle["grpc_kind"], serde_json::json!("grpc"));
+        assert_eq!(vehicle, no_ring.unwrap());
+
+        assert!(invalid_ring.is_err());
+    }
+
     #[test]
     fn load_default_config_from_file_success() {
         let file_name = "config.yaml";
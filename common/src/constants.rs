@@ -0,0 +1,52 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Compile-time constants generated from `config/constants.default.yaml`, so that the values
+//! can't drift between the services that use them.
+
+include!(concat!(env!("OUT_DIR"), "/constants.rs"));
+
+/// The generated constants as a JSON object, for clients written in other languages.
+pub const CONSTANTS_JSON: &str = include_str!(concat!(env!("OUT_DIR"), "/constants.json"));
+
+#[cfg(test)]
+mod constants_tests {
+    use config::{Config, File, FileFormat};
+
+    use super::*;
+
+    /// Loads a constants file into a map of its values.
+    fn load_constants_file(contents: &str) -> serde_json::Value {
+        Config::builder()
+            .add_source(File::from_str(contents, FileFormat::Yaml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    #[test]
+    fn generated_constants_match_constants_file_test() {
+        assert_eq!("TOPIC DELETED", TOPIC_DELETION_MESSAGE);
+        assert_eq!("publisher/disconnect", DISCONNECT_TOPIC);
+        assert_eq!("grpc+proto", GRPC_KIND);
+        assert_eq!(5, RETRY_INTERVAL_SECS);
+
+        let json: serde_json::Value = serde_json::from_str(CONSTANTS_JSON).unwrap();
+        assert_eq!(
+            load_constants_file(include_str!("../../config/constants.default.yaml")),
+            json
+        );
+    }
+
+    #[test]
+    fn samples_constants_match_service_constants_test() {
+        assert_eq!(
+            load_constants_file(include_str!("../../config/constants.default.yaml")),
+            load_constants_file(include_str!(
+                "../../.agemo-samples/config/constants.default.yaml"
+            ))
+        );
+    }
+}
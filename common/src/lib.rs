@@ -2,4 +2,13 @@
 // Licensed under the MIT license.
 // SPDX-License-Identifier: MIT
 
+pub mod action_casing;
 pub mod config_utils;
+pub mod constants;
+pub mod name_policy;
+pub mod payload_compression;
+pub mod protocol_kind;
+pub mod shared_state;
+pub mod subscription_metadata;
+pub mod topic_class;
+pub mod uri;
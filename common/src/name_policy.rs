@@ -0,0 +1,136 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use regex::Regex;
+
+/// Topic filters reserved by the messaging broker, denied by default.
+pub const DEFAULT_RESERVED_TOPIC_FILTERS: [&str; 2] = ["$SYS/#", "$CONTROL/#"];
+
+/// Matches a topic against an MQTT style topic filter, where `+` matches a single level and `#`
+/// matches any number of levels, including the parent level.
+///
+/// # Arguments
+/// * `filter` - The topic filter to match against.
+/// * `topic` - The topic to match.
+pub fn topic_matches_filter(filter: &str, topic: &str) -> bool {
+    let mut filter_levels = filter.split('/');
+    let mut topic_levels = topic.split('/');
+
+    loop {
+        match (filter_levels.next(), topic_levels.next()) {
+            (Some("#"), _) => return true,
+            (Some("+"), Some(_)) => continue,
+            (Some(filter_level), Some(topic_level)) if filter_level == topic_level => continue,
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+/// Policy that denies names colliding with reserved topics, such as broker-internal topics.
+///
+/// A name is reserved if it matches one of the topic filters or one of the regular expressions of
+/// the policy. The default policy reserves nothing.
+#[derive(Clone, Debug, Default)]
+pub struct ReservedNamePolicy {
+    topic_filters: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl ReservedNamePolicy {
+    /// Creates a new ReservedNamePolicy.
+    ///
+    /// Returns an error if one of the patterns is not a valid regular expression.
+    ///
+    /// # Arguments
+    /// * `topic_filters` - MQTT style topic filters of reserved names, such as `$SYS/#`.
+    /// * `patterns` - Regular expressions of reserved names.
+    pub fn new(topic_filters: Vec<String>, patterns: &[String]) -> Result<Self, regex::Error> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| Regex::new(pattern))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ReservedNamePolicy {
+            topic_filters,
+            patterns,
+        })
+    }
+
+    /// Returns the rule that reserves the given name, or None if the name is allowed.
+    ///
+    /// # Arguments
+    /// * `name` - The topic or subject name to check.
+    pub fn reserved_by(&self, name: &str) -> Option<String> {
+        self.topic_filters
+            .iter()
+            .find(|filter| topic_matches_filter(filter, name))
+            .cloned()
+            .or_else(|| {
+                self.patterns
+                    .iter()
+                    .find(|pattern| pattern.is_match(name))
+                    .map(|pattern| pattern.as_str().to_string())
+            })
+    }
+}
+
+#[cfg(test)]
+mod name_policy_tests {
+    use super::*;
+
+    #[test]
+    fn topic_matches_filter_test() {
+        assert!(topic_matches_filter(
+            "$SYS/#",
+            "$SYS/broker/clients/connected"
+        ));
+        assert!(topic_matches_filter("$SYS/#", "$SYS"));
+        assert!(topic_matches_filter("agemo/+/status", "agemo/pub_1/status"));
+        assert!(topic_matches_filter(
+            "publisher/disconnect",
+            "publisher/disconnect"
+        ));
+
+        assert!(!topic_matches_filter("$SYS/#", "sys/broker"));
+        assert!(!topic_matches_filter("agemo/+/status", "agemo/pub_1/state"));
+        assert!(!topic_matches_filter("agemo/+", "agemo/pub_1/status"));
+        assert!(!topic_matches_filter("publisher/disconnect", "publisher"));
+    }
+
+    #[test]
+    fn reserved_by_test() {
+        let policy = ReservedNamePolicy::new(
+            DEFAULT_RESERVED_TOPIC_FILTERS
+                .iter()
+                .map(|filter| filter.to_string())
+                .collect(),
+            &["^internal_".to_string()],
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some("$SYS/#".to_string()),
+            policy.reserved_by("$SYS/broker/uptime")
+        );
+        assert_eq!(
+            Some("^internal_".to_string()),
+            policy.reserved_by("internal_speed")
+        );
+        assert_eq!(None, policy.reserved_by("vehicle/speed"));
+    }
+
+    #[test]
+    fn default_policy_reserves_nothing_test() {
+        assert_eq!(
+            None,
+            ReservedNamePolicy::default().reserved_by("$SYS/broker")
+        );
+    }
+
+    #[test]
+    fn invalid_pattern_test() {
+        assert!(ReservedNamePolicy::new(Vec::new(), &["(".to_string()]).is_err());
+    }
+}
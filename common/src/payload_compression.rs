@@ -0,0 +1,125 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use std::{fmt, str::FromStr};
+
+use serde_derive::{Deserialize, Serialize};
+
+/// Enum defining the compressions a publisher can apply to the payloads of a topic.
+///
+/// The discriminants match the values of the `PayloadCompression` proto enum.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum PayloadCompression {
+    /// Payloads are published as is.
+    #[default]
+    None = 0,
+    /// Payloads are compressed with gzip.
+    Gzip = 1,
+    /// Payloads are compressed with Zstandard.
+    Zstd = 2,
+}
+
+impl PayloadCompression {
+    /// Returns the string the compression is known by.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PayloadCompression::None => "none",
+            PayloadCompression::Gzip => "gzip",
+            PayloadCompression::Zstd => "zstd",
+        }
+    }
+
+    /// Converts a value of the `PayloadCompression` proto enum. Returns None for an unknown
+    /// compression.
+    ///
+    /// # Arguments
+    /// * `value` - The proto enum value.
+    pub fn from_proto(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(PayloadCompression::None),
+            1 => Some(PayloadCompression::Gzip),
+            2 => Some(PayloadCompression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the `PayloadCompression` proto enum for the compression.
+    pub fn to_proto(self) -> i32 {
+        self as i32
+    }
+
+    /// Returns whether payloads are compressed.
+    pub fn is_compressed(self) -> bool {
+        self != PayloadCompression::None
+    }
+}
+
+impl fmt::Display for PayloadCompression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Error returned when parsing an unknown payload compression.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParsePayloadCompressionError(String);
+
+impl fmt::Display for ParsePayloadCompressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown payload compression '{}'", self.0)
+    }
+}
+
+impl std::error::Error for ParsePayloadCompressionError {}
+
+impl FromStr for PayloadCompression {
+    type Err = ParsePayloadCompressionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(PayloadCompression::None),
+            "gzip" => Ok(PayloadCompression::Gzip),
+            "zstd" => Ok(PayloadCompression::Zstd),
+            _ => Err(ParsePayloadCompressionError(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod payload_compression_tests {
+    use super::*;
+
+    const COMPRESSIONS: [PayloadCompression; 3] = [
+        PayloadCompression::None,
+        PayloadCompression::Gzip,
+        PayloadCompression::Zstd,
+    ];
+
+    #[test]
+    fn payload_compression_round_trips_test() {
+        for compression in COMPRESSIONS {
+            assert_eq!(
+                Some(compression),
+                PayloadCompression::from_proto(compression.to_proto())
+            );
+            assert_eq!(Ok(compression), compression.as_str().parse());
+        }
+
+        assert_eq!(None, PayloadCompression::from_proto(3));
+        assert!("brotli".parse::<PayloadCompression>().is_err());
+        assert_eq!(PayloadCompression::None, PayloadCompression::default());
+    }
+
+    #[test]
+    fn is_compressed_test() {
+        assert!(!PayloadCompression::None.is_compressed());
+        assert!(PayloadCompression::Gzip.is_compressed());
+        assert_eq!(
+            "\"zstd\"",
+            serde_json::to_string(&PayloadCompression::Zstd).unwrap()
+        );
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use std::{fmt, str::FromStr};
+
+use crate::constants;
+
+/// Enum defining the protocols used between the services.
+///
+/// The discriminants match the values of the `ProtocolKind` proto enum, where zero is reserved
+/// for an unspecified protocol.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum ProtocolKind {
+    /// gRPC with protobuf messages.
+    Grpc = 1,
+    /// MQTT v5.
+    MqttV5 = 2,
+}
+
+/// Error returned when a string or proto value does not name a known protocol.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseProtocolKindError(String);
+
+impl fmt::Display for ParseProtocolKindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown protocol kind '{}'", self.0)
+    }
+}
+
+impl std::error::Error for ParseProtocolKindError {}
+
+impl ProtocolKind {
+    /// Returns the string the protocol is known by, as set in the constants file.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProtocolKind::Grpc => constants::GRPC_KIND,
+            ProtocolKind::MqttV5 => constants::MQTT_V5_KIND,
+        }
+    }
+
+    /// Converts a value of the `ProtocolKind` proto enum. Returns None for an unspecified or
+    /// unknown protocol.
+    ///
+    /// # Arguments
+    /// * `value` - The proto enum value.
+    pub fn from_proto(value: i32) -> Option<Self> {
+        match value {
+            1 => Some(ProtocolKind::Grpc),
+            2 => Some(ProtocolKind::MqttV5),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the `ProtocolKind` proto enum for the protocol.
+    pub fn to_proto(self) -> i32 {
+        self as i32
+    }
+
+    /// Resolves the protocol of a message that carries both the proto enum and the older string
+    /// field. The enum is used if it is set, otherwise the string is parsed, so that clients that
+    /// only set the string keep working.
+    ///
+    /// # Arguments
+    /// * `value` - The proto enum value.
+    /// * `kind` - The string naming the protocol.
+    pub fn resolve(value: i32, kind: &str) -> Result<Self, ParseProtocolKindError> {
+        match Self::from_proto(value) {
+            Some(protocol_kind) => Ok(protocol_kind),
+            None => kind.parse(),
+        }
+    }
+}
+
+impl fmt::Display for ProtocolKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ProtocolKind {
+    type Err = ParseProtocolKindError;
+
+    /// Parses a protocol from its string, ignoring case. Also accepts the shorter names used
+    /// before the protocol strings were unified, such as `grpc` and `mqtt`.
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        let normalized = kind.trim().to_lowercase();
+
+        if normalized == constants::GRPC_KIND || normalized == "grpc" {
+            Ok(ProtocolKind::Grpc)
+        } else if normalized == constants::MQTT_V5_KIND
+            || ["mqtt", "mqttv5", "mqtt5"].contains(&normalized.as_str())
+        {
+            Ok(ProtocolKind::MqttV5)
+        } else {
+            Err(ParseProtocolKindError(kind.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod protocol_kind_tests {
+    use super::*;
+
+    #[test]
+    fn parse_protocol_kind_test() {
+        assert_eq!(Ok(ProtocolKind::Grpc), "grpc+proto".parse());
+        assert_eq!(Ok(ProtocolKind::Grpc), "grpc".parse());
+        assert_eq!(Ok(ProtocolKind::Grpc), " GRPC ".parse());
+        assert_eq!(Ok(ProtocolKind::MqttV5), "mqtt_v5".parse());
+        assert_eq!(Ok(ProtocolKind::MqttV5), "mqtt".parse());
+        assert!("amqp".parse::<ProtocolKind>().is_err());
+        assert!("".parse::<ProtocolKind>().is_err());
+    }
+
+    #[test]
+    fn protocol_kind_round_trips_test() {
+        for protocol_kind in [ProtocolKind::Grpc, ProtocolKind::MqttV5] {
+            assert_eq!(
+                Some(protocol_kind),
+                ProtocolKind::from_proto(protocol_kind.to_proto())
+            );
+            assert_eq!(Ok(protocol_kind), protocol_kind.to_string().parse());
+        }
+
+        assert_eq!(None, ProtocolKind::from_proto(0));
+    }
+
+    #[test]
+    fn resolve_prefers_proto_value_test() {
+        assert_eq!(
+            Ok(ProtocolKind::MqttV5),
+            ProtocolKind::resolve(ProtocolKind::MqttV5.to_proto(), "grpc")
+        );
+        assert_eq!(Ok(ProtocolKind::Grpc), ProtocolKind::resolve(0, "grpc"));
+        assert!(ProtocolKind::resolve(0, "").is_err());
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use std::{
+    fmt,
+    sync::{Mutex, MutexGuard, PoisonError, TryLockError},
+};
+
+/// State shared between threads and tasks, guarded by a mutex that doesn't poison.
+///
+/// A thread that panics while holding the lock of a [`Mutex`] poisons it, and every later lock
+/// then fails, which turns a single panic into a panic in every thread sharing the state. The
+/// state guarded by a `SharedState` is instead handed out as is after a panic, as the state of the
+/// service stays usable even if an update to it was cut short.
+///
+/// # Holding the lock in async code
+///
+/// The guards returned by [`lock`](Self::lock) and [`try_lock`](Self::try_lock) are std
+/// [`MutexGuard`]s, which must not be held across an `.await`, as if the `must_not_suspend` lint
+/// applied to them: a task suspended while holding the lock blocks every other thread that locks
+/// the state, including the runtime threads. Drop the guard, or scope it in a block, before
+/// awaiting. State that has to stay locked across an `.await` is guarded by a
+/// `tokio::sync::Mutex` instead. The workspace denies `clippy::await_holding_lock`, which catches
+/// a guard held across an `.await`.
+#[derive(Default)]
+pub struct SharedState<T: ?Sized> {
+    /// The mutex guarding the state.
+    inner: Mutex<T>,
+}
+
+impl<T> SharedState<T> {
+    /// Creates a new SharedState.
+    ///
+    /// # Arguments
+    /// * `value` - The initial state.
+    pub const fn new(value: T) -> Self {
+        SharedState {
+            inner: Mutex::new(value),
+        }
+    }
+
+    /// Consumes the SharedState, returning the state.
+    pub fn into_inner(self) -> T {
+        self.inner
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: ?Sized> SharedState<T> {
+    /// Locks the state, blocking the current thread until the lock is acquired. The state is
+    /// returned even if a thread panicked while holding the lock. The guard must not be held
+    /// across an `.await`.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the state if it is not locked already, without blocking. Returns `None` if the state
+    /// is locked. The guard must not be held across an `.await`.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        match self.inner.try_lock() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(err)) => Some(err.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    /// Returns a mutable reference to the state, which needs no locking as the SharedState is
+    /// borrowed mutably.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T> From<T> for SharedState<T> {
+    fn from(value: T) -> Self {
+        SharedState::new(value)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for SharedState<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("SharedState");
+        match self.try_lock() {
+            Some(guard) => debug.field("data", &&*guard),
+            None => debug.field("data", &format_args!("<locked>")),
+        };
+
+        debug.finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod shared_state_tests {
+    use std::{sync::Arc, thread};
+
+    use super::*;
+
+    #[test]
+    fn lock_after_panic_test() {
+        let state = Arc::new(SharedState::new(vec![1]));
+
+        let panicking_state = state.clone();
+        let result = thread::spawn(move || {
+            let mut values = panicking_state.lock();
+            values.push(2);
+            panic!("panic while holding the lock");
+        })
+        .join();
+        assert!(result.is_err());
+
+        // The update made before the panic is kept, and the state can still be locked.
+        state.lock().push(3);
+        assert_eq!(vec![1, 2, 3], *state.lock());
+    }
+
+    #[test]
+    fn try_lock_test() {
+        let state = SharedState::new(0);
+
+        {
+            let _guard = state.lock();
+            assert!(state.try_lock().is_none());
+            assert_eq!("SharedState { data: <locked>, .. }", format!("{state:?}"));
+        }
+
+        *state.try_lock().unwrap() += 1;
+        assert_eq!("SharedState { data: 1, .. }", format!("{state:?}"));
+        assert_eq!(1, state.into_inner());
+    }
+}
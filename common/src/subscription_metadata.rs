@@ -0,0 +1,170 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::payload_compression::PayloadCompression;
+
+/// The subscription metadata a publisher returns to subscribers, as the JSON object of the
+/// `subscriptionMetadata` field of a `SubscriptionInfoResponse`:
+///
+/// ```json
+/// {
+///   "topic": "09285f6c-9a86-49db-9159-0d91f8f4d3bb",
+///   "qos": 1,
+///   "schema": { "kind": "json", "uri": "https://schemas.example.com/gps.json" },
+///   "auth": { "mechanism": "token", "issuer": "https://auth.example.com" },
+///   "compression": "gzip",
+///   "lifecycle_epoch": 3
+/// }
+/// ```
+///
+/// Only `topic` is required. Fields that a subscriber doesn't know are ignored, so that fields
+/// can be added without breaking existing subscribers. For the same reason the struct can't be
+/// built with a struct expression outside of this crate; start from [`SubscriptionMetadata::new`]
+/// and set the optional fields instead.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct SubscriptionMetadata {
+    /// The topic to subscribe to on the messaging broker.
+    pub topic: String,
+    /// Quality of service, from 0 to 2, that subscribers should use on the topic.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qos: Option<u8>,
+    /// The schema of the payloads published on the topic.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<SchemaReference>,
+    /// How subscribers authenticate with the messaging broker.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<AuthHints>,
+    /// The compression the publisher applies to the payloads of the topic. Payloads are
+    /// uncompressed if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<PayloadCompression>,
+    /// The lifecycle epoch of the topic, which the Pub Sub Service increases each time the
+    /// publisher is started after being stopped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lifecycle_epoch: Option<u64>,
+}
+
+/// Reference to the schema of the payloads of a topic.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct SchemaReference {
+    /// The kind of schema, such as `json` or `protobuf`.
+    pub kind: String,
+    /// Where the schema can be retrieved from, if it is published.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+}
+
+/// Hints on how subscribers authenticate with the messaging broker.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct AuthHints {
+    /// The authentication mechanism expected by the broker.
+    pub mechanism: AuthMechanism,
+    /// Where subscribers obtain their credentials, such as the uri of a token issuer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+}
+
+/// Enum defining the mechanisms subscribers authenticate with the messaging broker with.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum AuthMechanism {
+    /// The broker accepts unauthenticated subscribers.
+    #[default]
+    None,
+    /// A username and password.
+    UsernamePassword,
+    /// A token, such as a JWT, in place of a password.
+    Token,
+    /// A client certificate.
+    Mtls,
+}
+
+impl SubscriptionMetadata {
+    /// Creates new subscription metadata for a topic, without any optional fields.
+    ///
+    /// # Arguments
+    /// * `topic` - The topic to subscribe to on the messaging broker.
+    pub fn new(topic: String) -> Self {
+        SubscriptionMetadata {
+            topic,
+            ..Default::default()
+        }
+    }
+
+    /// Parses subscription metadata from its JSON representation.
+    ///
+    /// # Arguments
+    /// * `json` - The JSON representation of the metadata.
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Returns the JSON representation of the subscription metadata.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("subscription metadata always serializes")
+    }
+}
+
+impl SchemaReference {
+    /// Creates a new reference to a schema that isn't published.
+    ///
+    /// # Arguments
+    /// * `kind` - The kind of schema, such as `json` or `protobuf`.
+    pub fn new(kind: String) -> Self {
+        SchemaReference { kind, uri: None }
+    }
+}
+
+impl AuthHints {
+    /// Creates new authentication hints, without a credentials issuer.
+    ///
+    /// # Arguments
+    /// * `mechanism` - The authentication mechanism expected by the broker.
+    pub fn new(mechanism: AuthMechanism) -> Self {
+        AuthHints {
+            mechanism,
+            issuer: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod subscription_metadata_tests {
+    use super::*;
+
+    #[test]
+    fn subscription_metadata_round_trips_test() {
+        let metadata = SubscriptionMetadata {
+            topic: "topic-0".to_string(),
+            qos: Some(1),
+            schema: Some(SchemaReference::new("json".to_string())),
+            auth: Some(AuthHints::new(AuthMechanism::UsernamePassword)),
+            compression: Some(PayloadCompression::Zstd),
+            lifecycle_epoch: Some(3),
+        };
+
+        let json = metadata.to_json();
+        assert_eq!(
+            r#"{"topic":"topic-0","qos":1,"schema":{"kind":"json"},"auth":{"mechanism":"username_password"},"compression":"zstd","lifecycle_epoch":3}"#,
+            json
+        );
+        assert_eq!(metadata, SubscriptionMetadata::from_json(&json).unwrap());
+    }
+
+    #[test]
+    fn subscription_metadata_accepts_legacy_json_test() {
+        let metadata =
+            SubscriptionMetadata::from_json(r#"{"topic": "topic-0", "extra": true}"#).unwrap();
+        assert_eq!(SubscriptionMetadata::new("topic-0".to_string()), metadata);
+
+        assert!(SubscriptionMetadata::from_json(r#"{"qos": 1}"#).is_err());
+        assert!(SubscriptionMetadata::from_json("not json").is_err());
+    }
+}
@@ -0,0 +1,84 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use std::fmt;
+
+/// Enum defining the classes of topics, which change how the messaging broker handles the
+/// messages of a topic.
+///
+/// The discriminants match the values of the `TopicClass` proto enum.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum TopicClass {
+    /// A regular topic, whose messages are only delivered to its current subscribers.
+    #[default]
+    Standard = 0,
+    /// A configuration topic, whose latest message is retained by the messaging broker and
+    /// delivered to every subscriber that joins later.
+    ConfigRetained = 1,
+}
+
+impl TopicClass {
+    /// Returns the string the topic class is known by.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TopicClass::Standard => "standard",
+            TopicClass::ConfigRetained => "config/retained",
+        }
+    }
+
+    /// Converts a value of the `TopicClass` proto enum. Returns None for an unknown class.
+    ///
+    /// # Arguments
+    /// * `value` - The proto enum value.
+    pub fn from_proto(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(TopicClass::Standard),
+            1 => Some(TopicClass::ConfigRetained),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the `TopicClass` proto enum for the topic class.
+    pub fn to_proto(self) -> i32 {
+        self as i32
+    }
+
+    /// Returns whether the messages of topics of this class are published with the retained
+    /// flag.
+    pub fn retains_messages(self) -> bool {
+        self == TopicClass::ConfigRetained
+    }
+}
+
+impl fmt::Display for TopicClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod topic_class_tests {
+    use super::*;
+
+    #[test]
+    fn topic_class_round_trips_test() {
+        for topic_class in [TopicClass::Standard, TopicClass::ConfigRetained] {
+            assert_eq!(
+                Some(topic_class),
+                TopicClass::from_proto(topic_class.to_proto())
+            );
+        }
+
+        assert_eq!(None, TopicClass::from_proto(2));
+        assert_eq!(TopicClass::Standard, TopicClass::default());
+    }
+
+    #[test]
+    fn retains_messages_test() {
+        assert!(TopicClass::ConfigRetained.retains_messages());
+        assert!(!TopicClass::Standard.retains_messages());
+        assert_eq!("config/retained", TopicClass::ConfigRetained.to_string());
+    }
+}
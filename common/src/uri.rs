@@ -0,0 +1,132 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Parsing and normalization of the uris of services.
+//!
+//! Services are configured with their authority, such as `0.0.0.0:50061`, and publishers hand
+//! over management callbacks in varying forms. These helpers turn both into uris that can be
+//! compared and connected to.
+
+use std::net::Ipv6Addr;
+
+use url::Url;
+
+/// Scheme assumed for a uri that is given without one.
+pub const DEFAULT_URI_SCHEME: &str = "http";
+
+/// Returns the uri of a service from its authority, such as `0.0.0.0:50061`, with the default
+/// scheme. A bare IPv6 address, such as `::1`, is enclosed in brackets as a uri requires.
+///
+/// # Arguments
+/// * `authority` - The authority of the service.
+pub fn authority_uri(authority: &str) -> String {
+    let authority = authority.trim();
+
+    if authority.parse::<Ipv6Addr>().is_ok() {
+        format!("{DEFAULT_URI_SCHEME}://[{authority}]")
+    } else {
+        format!("{DEFAULT_URI_SCHEME}://{authority}")
+    }
+}
+
+/// Parses and normalizes a uri.
+///
+/// Surrounding whitespace is trimmed, a uri without a scheme (such as `0.0.0.0:50061`) is given the
+/// default scheme, a bare IPv6 address is enclosed in brackets, the scheme and host are lowercased,
+/// IPv6 addresses are compressed and an empty path is removed.
+///
+/// # Arguments
+/// * `uri` - The uri to normalize.
+pub fn normalize_uri(uri: &str) -> Result<String, url::ParseError> {
+    let trimmed = uri.trim();
+
+    let parsed = if trimmed.contains("://") {
+        Url::parse(trimmed)?
+    } else {
+        Url::parse(&authority_uri(trimmed))?
+    };
+
+    let mut normalized = parsed.to_string();
+
+    // The parser adds a root path to uris without one, which is not part of the given uri.
+    if parsed.path() == "/"
+        && parsed.query().is_none()
+        && parsed.fragment().is_none()
+        && !trimmed.ends_with('/')
+    {
+        normalized.pop();
+    }
+
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod uri_tests {
+    use super::*;
+
+    #[test]
+    fn normalize_uri_test() {
+        assert_eq!(
+            "http://0.0.0.0:50061",
+            normalize_uri("http://0.0.0.0:50061").unwrap()
+        );
+        assert_eq!(
+            "http://0.0.0.0:50061",
+            normalize_uri("  0.0.0.0:50061 ").unwrap()
+        );
+        assert_eq!(
+            "http://localhost:50061",
+            normalize_uri("HTTP://LocalHost:50061").unwrap()
+        );
+        assert_eq!(
+            "https://publisher.local/callback",
+            normalize_uri("https://publisher.local/callback").unwrap()
+        );
+        assert_eq!(
+            "http://localhost:50061/",
+            normalize_uri("http://localhost:50061/").unwrap()
+        );
+        assert_eq!(
+            "http://localhost:50061/callback?retry=1",
+            normalize_uri("localhost:50061/callback?retry=1").unwrap()
+        );
+        assert_eq!(
+            "http://publisher.local",
+            normalize_uri("http://publisher.local:80").unwrap()
+        );
+    }
+
+    #[test]
+    fn normalize_ipv6_uri_test() {
+        assert_eq!("http://[::1]:50061", normalize_uri("[::1]:50061").unwrap());
+        assert_eq!("http://[::1]", normalize_uri("::1").unwrap());
+        assert_eq!(
+            "http://[fe80::1]:50061",
+            normalize_uri("http://[FE80:0:0:0:0:0:0:1]:50061").unwrap()
+        );
+        assert_eq!(
+            "https://[2001:db8::2]/callback",
+            normalize_uri("https://[2001:db8::2]/callback").unwrap()
+        );
+    }
+
+    #[test]
+    fn authority_uri_test() {
+        assert_eq!("http://0.0.0.0:50061", authority_uri("0.0.0.0:50061"));
+        assert_eq!("http://localhost:50061", authority_uri(" localhost:50061 "));
+        assert_eq!("http://[::1]:50061", authority_uri("[::1]:50061"));
+        assert_eq!("http://[::1]", authority_uri("::1"));
+        assert_eq!("http://[fe80::1]", authority_uri("fe80::1"));
+    }
+
+    #[test]
+    fn normalize_invalid_uri_test() {
+        assert!(normalize_uri("").is_err());
+        assert!(normalize_uri("http://").is_err());
+        assert!(normalize_uri("http://[::1").is_err());
+        assert!(normalize_uri("0.0.0.0:not_a_port").is_err());
+        assert!(normalize_uri("http://::1:50061").is_err());
+        assert!(normalize_uri("0.0.0.0:65536").is_err());
+    }
+}
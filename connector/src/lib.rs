@@ -0,0 +1,325 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Trait that a publish/subscribe connector must implement, versioned separately from the service.
+//!
+//! The [`PubSubConnector`] trait is required to be implemented by a broker connector to enable the
+//! dynamic topic management in the pub sub service. Below is a list of requirements that a broker
+//! must meet to be integrated with no changes to the service:
+//! - Broker must provide a way to monitor subscribe requests to a topic.
+//! - Broker must provide a way to monitor unsubscribe requests to a topic.
+//! - Broker must provide a way to monitor clients that disconnect from the broker unexpectedly.
+//!   This is to enable the service to notify subscribers to drop the topic.
+//!
+//! If a broker you want to use does not meet the above requirements, please reach out via an
+//! issue on GitHub.
+
+use async_trait::async_trait;
+use std::{
+    error::Error,
+    fmt,
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+use strum_macros::{Display, EnumString};
+
+/// Enum representing an action that happens in the messaging broker, reported by the connector.
+///
+/// New actions may be added in minor versions, so matches on this enum need a wildcard arm.
+#[derive(Clone, Copy, Debug, Display, EnumString, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum PubSubAction {
+    /// Represents a subscribe to a topic.
+    #[strum(serialize = "SUBSCRIBE")]
+    Subscribe,
+    /// Represents an unsubscribe to a topic.
+    #[strum(serialize = "UNSUBSCRIBE")]
+    Unsubscribe,
+    /// Represents a notification that a topic has no subscribers after a period of time.
+    #[strum(serialize = "TIMEOUT")]
+    Timeout,
+    /// Represents a deletion of a topic.
+    #[strum(serialize = "DELETE")]
+    Delete,
+    /// Represents an unclean publisher disconnect.
+    #[strum(serialize = "PUBDISCONNECT")]
+    PubDisconnect,
+    /// Represents an unclean subscriber disconnect.
+    #[strum(serialize = "SUBDISCONNECT")]
+    SubDisconnect,
+}
+
+/// Structure defining a message returned from the broker connector when an action happens.
+#[derive(Debug)]
+pub struct MonitorMessage {
+    /// A string that provides the context relevant to the action that triggered the message.
+    pub context: String,
+    /// The action that triggered the message from the broker connector.
+    pub action: PubSubAction,
+    /// The id of the client that caused the action, if known. For subscribe and unsubscribe
+    /// actions this is the id of the subscriber.
+    pub client_id: Option<String>,
+    /// The identity the subscriber of a subscribe or unsubscribe action authenticated as, such as
+    /// its username, if the connector identifies subscribers by their identity and it is known.
+    /// Several clients may share an identity, so subscribers are still counted by client id.
+    pub identity: Option<String>,
+}
+
+/// Error returned by the default implementations of the optional [`PubSubConnector`] functions,
+/// for connectors whose messaging broker doesn't support the feature. Holds the name of the
+/// feature.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Unsupported(pub &'static str);
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "this connector doesn't support {}", self.0)
+    }
+}
+
+impl Error for Unsupported {}
+
+/// Trait that needs to be implmented by a broker connector for the pub sub service to get
+/// the necessary information from the messaging broker to implement dynamic topic management.
+#[async_trait]
+pub trait PubSubConnector {
+    /// Configuration handed to the connector by the service when it connects.
+    type Config;
+
+    /// Error returned by the connector when it fails to connect.
+    type Error: Error + Send + Sync + 'static;
+
+    /// Creates a new instance of the struct implementing this trait and connects it to the
+    /// messaging broker.
+    ///
+    /// Returns a [`PubSubConnector::Error`] if the configuration is invalid or the broker can't be
+    /// reached, leaving it to the caller to decide how to handle the failure.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Configuration used to create and connect the broker client.
+    async fn connect(config: Self::Config) -> Result<Self, Self::Error>
+    where
+        Self: Sized;
+
+    /// Function that monitors the messaging broker for changes and forwards those changes back
+    /// over the callback channel.
+    ///
+    /// This function monitors changes to topics and connections on the messaging broker. Every
+    /// update to the broker is sent to the provided callback channel in the format of a
+    /// [`MonitorMessage`]. The types of updates that are monitored are listed out in the
+    /// [`PubSubAction`] enum. Connectors should report losing and regaining the connection to the
+    /// broker through their configuration, so that topics aren't timed out while updates can't
+    /// flow. Connectors whose subscriptions to the broker's monitor topics are rejected should
+    /// retry them and report them to the service through their configuration rather than fail.
+    ///
+    /// # Arguments
+    ///
+    /// * `cb_channel` - Callback channel used to forward messages from the connector to the rest
+    ///                  of the pub sub service logic.
+    async fn monitor_topics(
+        &mut self,
+        cb_channel: mpsc::Sender<MonitorMessage>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+
+    /// Function that deletes a topic from the messaging broker.
+    ///
+    /// This function deletes a topic from the messaging broker. In addition, it sends a topic
+    /// deletion message across the topic channel to inform any subscribers that the topic is being
+    /// deleted. The deletion message is shared across every deleted topic, so implementations
+    /// should avoid copying it until it is handed to the broker.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - Generated topic to be deleted from the service.
+    /// * `deletion_msg` - Deletion message to be sent to any subscribers on the given topic.
+    async fn delete_topic(
+        &self,
+        topic: String,
+        deletion_msg: Arc<str>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+
+    /// Function that clears the message retained on a topic by the messaging broker, so that it
+    /// isn't delivered to subscribers once the topic is deleted.
+    ///
+    /// Brokers that don't retain messages keep the default implementation, which returns an
+    /// error.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - Generated topic to clear the retained message of.
+    async fn clear_retained_message(
+        &self,
+        _topic: String,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>
+    where
+        Self: Sync,
+    {
+        Err(Box::new(Unsupported("retained messages")))
+    }
+
+    /// Function that restricts publishing on a topic to the registered publisher.
+    ///
+    /// This function asks the messaging broker to reject publishes to the given topic from any
+    /// client other than the registered publisher, so other broker clients can't inject data
+    /// into a generated topic. Brokers that don't support this keep the default implementation,
+    /// which returns an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - Generated topic to restrict publishing on.
+    /// * `publisher_id` - The client id of the publisher allowed to publish to the topic.
+    async fn authorize_publisher(
+        &self,
+        _topic: String,
+        _publisher_id: String,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>
+    where
+        Self: Sync,
+    {
+        Err(Box::new(Unsupported("publisher permissions")))
+    }
+
+    /// Function that removes the publishing restriction placed on a topic by
+    /// [`PubSubConnector::authorize_publisher`].
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - Generated topic to remove the restriction from.
+    async fn revoke_publisher(&self, _topic: String) -> Result<(), Box<dyn Error + Send + Sync>>
+    where
+        Self: Sync,
+    {
+        Err(Box::new(Unsupported("publisher permissions")))
+    }
+
+    /// Function that lists the topics that currently hold state on the messaging broker, such as
+    /// retained messages. Used to find topics that are unknown to the service.
+    ///
+    /// Brokers that don't support listing topics keep the default implementation, which returns
+    /// an error.
+    async fn list_broker_topics(&self) -> Result<Vec<String>, Box<dyn Error + Send + Sync>>
+    where
+        Self: Sync,
+    {
+        Err(Box::new(Unsupported("listing topics")))
+    }
+
+    /// Function that publishes a probe message on a topic and waits for the message to come back
+    /// through a subscription to the topic, verifying that the messaging broker delivers messages.
+    /// Returns how long the probe message took to come back.
+    ///
+    /// Brokers that don't support probing keep the default implementation, which returns an
+    /// error.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The self-test topic to probe.
+    /// * `timeout` - Maximum time to wait for the probe message.
+    async fn probe_topic(
+        &self,
+        _topic: String,
+        _timeout: Duration,
+    ) -> Result<Duration, Box<dyn Error + Send + Sync>>
+    where
+        Self: Sync,
+    {
+        Err(Box::new(Unsupported("probing topics")))
+    }
+
+    /// Function that checks that the messaging broker can still be reached, such as after the
+    /// system resumed from suspend, when the connection may have been lost without the connector
+    /// noticing.
+    ///
+    /// Brokers that can't be checked keep the default implementation, which reports the broker as
+    /// reachable.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Maximum time to wait for the broker.
+    async fn check_connection(&self, _timeout: Duration) -> Result<(), Box<dyn Error + Send + Sync>>
+    where
+        Self: Sync,
+    {
+        Ok(())
+    }
+
+    /// Function that removes the state of a topic that is unknown to the service from the
+    /// messaging broker, and informs any subscribers that the topic is deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic to remove from the broker.
+    /// * `deletion_msg` - Deletion message to be sent to any subscribers on the given topic.
+    async fn remove_orphan_topic(
+        &self,
+        _topic: String,
+        _deletion_msg: Arc<str>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>
+    where
+        Self: Sync,
+    {
+        Err(Box::new(Unsupported("removing topics")))
+    }
+}
+
+/// Function that is used to send a [`MonitorMessage`] to the given channel.
+///
+/// This function is a common function that can be utilized while implementing the
+/// [`PubSubConnector`] trait. Returns an error holding the message if the rest of the Pub Sub
+/// Service no longer receives updates, such as while it shuts down.
+///
+/// # Arguments
+///
+/// * `update_msg` - Message collected by the connector related to an update to a topic.
+/// * `channel` - Channel used to forward the given update_msg to the rest of the Pub Sub Service.
+pub fn update_topic_information(
+    update_msg: MonitorMessage,
+    channel: mpsc::Sender<MonitorMessage>,
+) -> Result<(), mpsc::SendError<MonitorMessage>> {
+    channel.send(update_msg)
+}
+
+#[cfg(test)]
+mod pubsub_action_tests {
+    use super::*;
+
+    #[test]
+    fn action_to_string() {
+        assert_eq!("SUBSCRIBE".to_string(), PubSubAction::Subscribe.to_string());
+        assert_eq!(
+            "UNSUBSCRIBE".to_string(),
+            PubSubAction::Unsubscribe.to_string()
+        );
+        assert_eq!("TIMEOUT".to_string(), PubSubAction::Timeout.to_string());
+        assert_eq!("DELETE".to_string(), PubSubAction::Delete.to_string());
+        assert_eq!(
+            "PUBDISCONNECT".to_string(),
+            PubSubAction::PubDisconnect.to_string()
+        );
+        assert_eq!(
+            "SUBDISCONNECT".to_string(),
+            PubSubAction::SubDisconnect.to_string()
+        );
+    }
+
+    #[test]
+    fn update_topic_information_test() {
+        let (sender, receiver) = mpsc::channel::<MonitorMessage>();
+        let message = |context: &str| MonitorMessage {
+            context: context.to_string(),
+            action: PubSubAction::Subscribe,
+            client_id: None,
+            identity: None,
+        };
+
+        assert!(update_topic_information(message("topic_1"), sender.clone()).is_ok());
+        assert_eq!("topic_1", receiver.recv().unwrap().context);
+
+        // The message is handed back once the service no longer receives updates.
+        drop(receiver);
+        let err = update_topic_information(message("topic_2"), sender).unwrap_err();
+        assert_eq!("topic_2", err.0.context);
+    }
+}
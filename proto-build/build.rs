@@ -2,23 +2,104 @@
 // Licensed under the MIT license.
 // SPDX-License-Identifier: MIT
 
-use std::{error::Error, path::Path};
+use std::{env, error::Error, path::Path, path::PathBuf};
 
-use tonic_build::configure;
+use tonic_build::{configure, Builder};
+
+/// Name of the file, without extension, that the pub sub descriptor set is written to.
+const PUBSUB_DESCRIPTOR: &str = "pubsub_descriptor";
+
+/// Directory of the protos copied into the crate when it is packaged.
+const PACKAGED_PROTO_DIR: &str = "proto";
+
+/// Directory of the protos in the repository.
+const REPOSITORY_PROTO_DIR: &str = "../proto";
+
+/// Directory of the Chariott service discovery protos.
+const SERVICE_DISCOVERY_PROTO_DIR: &str = "../external/chariott/service_discovery/proto";
+
+/// Directory of the Chariott intent brokering protos.
+const INTENT_BROKERING_PROTO_DIR: &str = "../external/chariott/intent_brokering/proto";
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("../proto/pubsub/v1/pubsub.proto")?;
-    tonic_build::compile_protos("../proto/publisher/v1/publisher.proto")?;
-    compile_external_protos(
-        "../external/chariott/service_discovery/proto",
-        "../external/chariott/service_discovery/proto/core/v1/service_registry.proto",
-    )?;
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+    let proto_dir = proto_dir();
+
+    // The pub sub descriptor set is kept so that the service can serve its own schema. The
+    // third party protos provide the HTTP annotations used for gRPC-JSON transcoding.
+    configure_features()
+        .file_descriptor_set_path(out_dir.join(format!("{PUBSUB_DESCRIPTOR}.bin")))
+        .compile(
+            &[proto_dir.join("pubsub/v1/pubsub.proto")],
+            &[proto_dir.join("pubsub/v1"), proto_dir.join("third_party")],
+        )?;
+    compile_protos(&proto_dir, "publisher/v1", "publisher.proto")?;
+    compile_protos(&proto_dir, "admin/v1", "admin.proto")?;
+
+    if env::var_os("CARGO_FEATURE_CHARIOTT").is_some() {
+        compile_external_protos(
+            SERVICE_DISCOVERY_PROTO_DIR,
+            "core/v1/service_registry.proto",
+        )?;
+        compile_external_protos(
+            INTENT_BROKERING_PROTO_DIR,
+            "intent_brokering/provider/v1/provider.proto",
+        )?;
+        compile_external_protos(
+            INTENT_BROKERING_PROTO_DIR,
+            "intent_brokering/runtime/v1/runtime.proto",
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Returns the directory of the protos, which are packaged with the crate when it is built from
+/// crates.io and are found at the root of the repository otherwise.
+fn proto_dir() -> PathBuf {
+    let packaged = Path::new(PACKAGED_PROTO_DIR);
+
+    if packaged.is_dir() {
+        packaged.to_path_buf()
+    } else {
+        PathBuf::from(REPOSITORY_PROTO_DIR)
+    }
+}
+
+/// Returns a builder that generates the clients and servers enabled by the crate features.
+fn configure_features() -> Builder {
+    configure()
+        .build_client(env::var_os("CARGO_FEATURE_CLIENT").is_some())
+        .build_server(env::var_os("CARGO_FEATURE_SERVER").is_some())
+}
+
+/// Compiles a proto of the repository.
+///
+/// # Arguments
+///
+/// * `proto_dir` - The directory of the protos.
+/// * `folder_path` - The folder of the proto, relative to the directory of the protos.
+/// * `file_name` - The file name of the proto.
+fn compile_protos(
+    proto_dir: &Path,
+    folder_path: &str,
+    file_name: &str,
+) -> Result<(), Box<dyn Error>> {
+    let folder = proto_dir.join(folder_path);
+    configure_features().compile(&[folder.join(file_name)], &[folder])?;
 
     Ok(())
 }
 
+/// Compiles a proto of the Chariott submodule.
+///
+/// # Arguments
+///
+/// * `folder_path` - The directory of the protos the proto imports from.
+/// * `file_path` - The path of the proto, relative to `folder_path`.
 fn compile_external_protos(folder_path: &str, file_path: &str) -> Result<(), Box<dyn Error>> {
-    configure().compile(&[Path::new(file_path)], &[Path::new(folder_path)])?;
+    let folder = Path::new(folder_path);
+    configure_features().compile(&[folder.join(file_path)], &[folder])?;
 
     Ok(())
 }
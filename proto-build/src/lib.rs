@@ -2,9 +2,26 @@
 // Licensed under the MIT license.
 // SPDX-License-Identifier: MIT
 
+//! Generated messages, clients and servers of the Pub Sub Service gRPC interfaces.
+//!
+//! The clients and servers are generated by the `client` and `server` features, which are both
+//! enabled by default. A publisher that only calls the service can depend on the crate with
+//! `default-features = false, features = ["client"]`. The messages are always generated.
+//!
+//! The crate re-exports the versions of [`tonic`], [`prost`] and [`prost_types`] that the code is
+//! generated with, so that dependents don't need to keep their own versions in sync.
+
+pub use prost;
+pub use prost_types;
+pub use tonic;
+
 pub mod pubsub {
     pub mod v1 {
         tonic::include_proto!("pubsub");
+
+        /// The serialized `FileDescriptorSet` of the pub sub service.
+        pub const FILE_DESCRIPTOR_SET: &[u8] =
+            tonic::include_file_descriptor_set!("pubsub_descriptor");
     }
 }
 
@@ -14,8 +31,36 @@ pub mod publisher {
     }
 }
 
+pub mod admin {
+    pub mod v1 {
+        tonic::include_proto!("admin");
+    }
+}
+
+#[cfg(feature = "chariott")]
 pub mod service_registry {
     pub mod v1 {
         tonic::include_proto!("service_registry");
     }
 }
+
+#[cfg(feature = "chariott")]
+pub mod intent_brokering {
+    pub mod common {
+        pub mod v1 {
+            tonic::include_proto!("intent_brokering.common.v1");
+        }
+    }
+
+    pub mod provider {
+        pub mod v1 {
+            tonic::include_proto!("intent_brokering.provider.v1");
+        }
+    }
+
+    pub mod runtime {
+        pub mod v1 {
+            tonic::include_proto!("intent_brokering.runtime.v1");
+        }
+    }
+}
@@ -0,0 +1,247 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Wire format compatibility tests between the generated code of the Pub Sub Service and of the
+//! samples.
+//!
+//! The service uses the `agemo-proto` crate, while the samples generate their own code from the
+//! protos in `samples/proto-build`, the way an application outside of the repository would. The
+//! tests catch the two drifting apart:
+//! - Golden vectors: messages of both crates must encode to, and decode from, the same bytes.
+//! - Cross calls: the clients of one crate must be able to call the servers of the other.
+
+#[cfg(test)]
+mod wire_compat_tests {
+    use std::sync::{Arc, Mutex};
+
+    use agemo_test_utils::publisher_callback::{FakePublisherCallback, RecordedCallback};
+    use prost::Message;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::{transport::Server, Request, Response, Status};
+
+    use samples_proto::publisher::v1::publisher_callback_server::{
+        PublisherCallback as SamplesPublisherCallback,
+        PublisherCallbackServer as SamplesPublisherCallbackServer,
+    };
+
+    /// Decodes a hex string into bytes.
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// Asserts that a message of the service crate encodes to the golden bytes, and that the
+    /// golden bytes survive a decode and encode through the message of both crates. A field known
+    /// to only one of the crates is dropped when decoding with the other, failing the assertion.
+    fn assert_wire_compatible<S: Message + Default, T: Message + Default>(
+        service_message: S,
+        golden_hex: &str,
+    ) {
+        let golden = from_hex(golden_hex);
+
+        assert_eq!(golden, service_message.encode_to_vec(), "service encoding");
+        assert_eq!(
+            golden,
+            T::decode(golden.as_slice()).unwrap().encode_to_vec(),
+            "samples round trip"
+        );
+        assert_eq!(
+            golden,
+            S::decode(golden.as_slice()).unwrap().encode_to_vec(),
+            "service round trip"
+        );
+    }
+
+    #[test]
+    fn create_topic_request_golden_test() {
+        let request = proto::pubsub::v1::CreateTopicRequest {
+            publisher_id: "pub".to_string(),
+            management_callback: "http://cb".to_string(),
+            management_protocol: "grpc".to_string(),
+            idempotency_key: String::new(),
+            management_protocol_kind: proto::pubsub::v1::ProtocolKind::Grpc as i32,
+            single_consumer: true,
+            pre_warm: false,
+            notify_subscriber_joined: false,
+            sequence_numbers: true,
+            topic_class: proto::pubsub::v1::TopicClass::ConfigRetained as i32,
+            topic_template: "t".to_string(),
+            deletion_message: None,
+            suppress_deletion_message: false,
+            tags: Vec::new(),
+            payload_compression: 0,
+        };
+
+        assert_wire_compatible::<_, samples_proto::pubsub::v1::CreateTopicRequest>(
+            request,
+            "0a03707562\
+             1209687474703a2f2f6362\
+             1a0467727063\
+             2801\
+             3001\
+             4801\
+             5001\
+             5a0174",
+        );
+    }
+
+    #[test]
+    fn create_topic_response_golden_test() {
+        let response = proto::pubsub::v1::CreateTopicResponse {
+            generated_topic: "t1".to_string(),
+            broker_uri: "mqtt://b".to_string(),
+            broker_protocol: "mqtt".to_string(),
+            broker_protocol_kind: proto::pubsub::v1::ProtocolKind::MqttV5 as i32,
+            sequence_epoch: 300,
+            topic_class: proto::pubsub::v1::TopicClass::Standard as i32,
+            broker_topic: "t1".to_string(),
+            qos: 1,
+            schema_kind: String::new(),
+            payload_compression: 0,
+        };
+
+        assert_wire_compatible::<_, samples_proto::pubsub::v1::CreateTopicResponse>(
+            response,
+            "0a027431\
+             12086d7174743a2f2f62\
+             1a046d717474\
+             2002\
+             28ac02\
+             3a027431\
+             4001",
+        );
+    }
+
+    #[test]
+    fn register_publisher_request_golden_test() {
+        let request = proto::pubsub::v1::RegisterPublisherRequest {
+            publisher_id: "p".to_string(),
+            management_callback: String::new(),
+            subjects: vec!["a".to_string(), "b".to_string()],
+        };
+
+        assert_wire_compatible::<_, samples_proto::pubsub::v1::RegisterPublisherRequest>(
+            request,
+            "0a0170\
+             1a0161\
+             1a0162",
+        );
+    }
+
+    #[test]
+    fn manage_topic_request_golden_test() {
+        let request = proto::publisher::v1::ManageTopicRequest {
+            topic: "t1".to_string(),
+            action: "START".to_string(),
+            subject: "s".to_string(),
+            lifecycle_epoch: 3,
+            subscriber_count: 2,
+            reconciliation: true,
+        };
+
+        assert_wire_compatible::<_, samples_proto::publisher::v1::ManageTopicRequest>(
+            request,
+            "0a027431\
+             12055354415254\
+             1a0173\
+             2003\
+             2802\
+             3001",
+        );
+    }
+
+    /// Publisher callback server of the samples crate that records the callbacks it receives.
+    #[derive(Clone, Default)]
+    struct SamplesPublisher {
+        callbacks: Arc<Mutex<Vec<RecordedCallback>>>,
+    }
+
+    #[tonic::async_trait]
+    impl SamplesPublisherCallback for SamplesPublisher {
+        async fn manage_topic_callback(
+            &self,
+            request: Request<samples_proto::publisher::v1::ManageTopicRequest>,
+        ) -> Result<Response<samples_proto::publisher::v1::ManageTopicResponse>, Status> {
+            let request = request.into_inner();
+            self.callbacks.lock().unwrap().push(RecordedCallback {
+                topic: request.topic,
+                action: request.action,
+            });
+
+            Ok(Response::new(
+                samples_proto::publisher::v1::ManageTopicResponse {},
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn service_client_calls_samples_server_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("http://{}", listener.local_addr().unwrap());
+        let publisher = SamplesPublisher::default();
+
+        let server = tokio::spawn(
+            Server::builder()
+                .add_service(SamplesPublisherCallbackServer::new(publisher.clone()))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let mut client =
+            proto::publisher::v1::publisher_callback_client::PublisherCallbackClient::connect(uri)
+                .await
+                .unwrap();
+        client
+            .manage_topic_callback(proto::publisher::v1::ManageTopicRequest {
+                topic: "topic".to_string(),
+                action: "STOP".to_string(),
+                subject: String::new(),
+                lifecycle_epoch: 0,
+                subscriber_count: 0,
+                reconciliation: false,
+            })
+            .await
+            .unwrap();
+        server.abort();
+
+        let expected = vec![RecordedCallback {
+            topic: "topic".to_string(),
+            action: "STOP".to_string(),
+        }];
+        assert_eq!(expected, *publisher.callbacks.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn samples_client_calls_service_server_test() {
+        let publisher = FakePublisherCallback::new();
+        let (uri, server) = publisher.serve().await.unwrap();
+
+        let mut client =
+            samples_proto::publisher::v1::publisher_callback_client::PublisherCallbackClient::connect(
+                uri,
+            )
+            .await
+            .unwrap();
+        client
+            .manage_topic_callback(samples_proto::publisher::v1::ManageTopicRequest {
+                topic: "topic".to_string(),
+                action: "START".to_string(),
+                subject: String::new(),
+                lifecycle_epoch: 0,
+                subscriber_count: 0,
+                reconciliation: false,
+            })
+            .await
+            .unwrap();
+        server.abort();
+
+        let expected = vec![RecordedCallback {
+            topic: "topic".to_string(),
+            action: "START".to_string(),
+        }];
+        assert_eq!(expected, publisher.callbacks());
+    }
+}
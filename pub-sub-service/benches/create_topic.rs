@@ -0,0 +1,161 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Benchmarks of topic creation, as when many publishers register on startup.
+//!
+//! The benchmarks start the service binary with the in-memory loopback connector, so no messaging
+//! broker is needed, and create topics through its gRPC interface. Run them with:
+//!
+//! ```shell
+//! cargo bench -p pub-sub-service --bench create_topic
+//! ```
+
+use std::{
+    env,
+    net::TcpListener,
+    process::{Child, Command, Stdio},
+    time::Duration,
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures::future::try_join_all;
+use proto::pubsub::v1::{pub_sub_client::PubSubClient, CreateTopicRequest};
+use tokio::runtime::Runtime;
+use tonic::transport::Channel;
+
+/// Number of publishers creating topics at once in the storm benchmark.
+const PUBLISHERS: usize = 64;
+/// Maximum time to wait for the service to accept connections.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+/// Interval between attempts to connect to the starting service.
+const STARTUP_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The service binary, stopped when dropped.
+struct Service(Child);
+
+impl Service {
+    /// Starts the service binary with the loopback connector on the given authority. The
+    /// configuration home points to an empty directory, so that only the default settings apply.
+    ///
+    /// # Arguments
+    ///
+    /// * `authority` - The authority the service listens on.
+    fn start(authority: &str) -> Self {
+        let child = Command::new(env!("CARGO_BIN_EXE_pub-sub-service"))
+            .args(["--pub-sub-authority", authority])
+            .args(["--messaging-uri", "memory://bench"])
+            .args(["--log-level", "warn"])
+            .env(
+                "AGEMO_HOME",
+                env::temp_dir().join(format!("agemo-bench-{}", std::process::id())),
+            )
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("failed to start the service");
+
+        Service(child)
+    }
+}
+
+impl Drop for Service {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Returns a local authority with a free port.
+fn free_authority() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to find a free port");
+    listener.local_addr().unwrap().to_string()
+}
+
+/// Connects to the service, retrying until it accepts connections.
+///
+/// # Arguments
+///
+/// * `authority` - The authority the service listens on.
+async fn connect(authority: &str) -> PubSubClient<Channel> {
+    let uri = format!("http://{authority}");
+    let start = tokio::time::Instant::now();
+
+    loop {
+        match PubSubClient::connect(uri.clone()).await {
+            Ok(client) => return client,
+            Err(err) if start.elapsed() > STARTUP_TIMEOUT => {
+                panic!("the service did not start within {STARTUP_TIMEOUT:?}: {err}")
+            }
+            Err(_) => tokio::time::sleep(STARTUP_RETRY_INTERVAL).await,
+        }
+    }
+}
+
+/// Returns a request to create a topic for a publisher.
+///
+/// # Arguments
+///
+/// * `publisher_id` - The id of the publisher.
+/// * `idempotency_key` - The idempotency key of the request, empty for none.
+fn create_request(publisher_id: String, idempotency_key: String) -> CreateTopicRequest {
+    CreateTopicRequest {
+        publisher_id,
+        management_callback: "http://0.0.0.0:50061".to_string(),
+        management_protocol: "grpc+proto".to_string(),
+        idempotency_key,
+        ..Default::default()
+    }
+}
+
+fn create_topic_benchmark(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let authority = free_authority();
+    let _service = Service::start(&authority);
+    let client = runtime.block_on(connect(&authority));
+
+    let mut group = c.benchmark_group("create_topic");
+
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("single", |b| {
+        b.to_async(&runtime).iter(|| {
+            let mut client = client.clone();
+            async move {
+                client
+                    .create_topic(create_request("pub_single".to_string(), String::new()))
+                    .await
+                    .unwrap()
+            }
+        })
+    });
+
+    // Every fourth publisher sets an idempotency key, which it claims in the idempotency cache.
+    group.throughput(Throughput::Elements(PUBLISHERS as u64));
+    group.bench_with_input(
+        BenchmarkId::new("storm", PUBLISHERS),
+        &PUBLISHERS,
+        |b, &publishers| {
+            b.to_async(&runtime).iter(|| {
+                let requests: Vec<_> = (0..publishers)
+                    .map(|publisher| {
+                        let mut client = client.clone();
+                        let idempotency_key = if publisher % 4 == 0 {
+                            uuid::Uuid::new_v4().to_string()
+                        } else {
+                            String::new()
+                        };
+                        let request = create_request(format!("pub_{publisher}"), idempotency_key);
+
+                        async move { client.create_topic(request).await }
+                    })
+                    .collect();
+
+                async move { try_join_all(requests).await.unwrap() }
+            })
+        },
+    );
+
+    group.finish();
+}
+
+criterion_group!(benches, create_topic_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,60 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+// C ABI implemented by broker connector libraries that the Pub Sub Service loads with the
+// `dynamic-connectors` feature. See `src/connectors/dynamic_connector.rs` for details.
+
+#ifndef AGEMO_CONNECTOR_H
+#define AGEMO_CONNECTOR_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+// Version of the ABI declared in this header.
+#define AGEMO_CONNECTOR_ABI_VERSION 1
+
+// Callback used to report an update from the messaging broker. `action` is one of `SUBSCRIBE`,
+// `UNSUBSCRIBE`, `PUBDISCONNECT`, `SUBDISCONNECT`, `BROKERCONNECTED` or `BROKERDISCONNECTED`,
+// `context` is the topic or client the update is for, and `client_id` may be NULL. The strings
+// only need to stay valid for the duration of the call.
+typedef void (*agemo_monitor_cb)(void* ctx, const char* action, const char* context,
+                                 const char* client_id);
+
+// Returns AGEMO_CONNECTOR_ABI_VERSION.
+uint32_t agemo_connector_abi_version(void);
+
+// Connects to the messaging broker. `config_json` is a JSON object with the `client_id`, `uri`,
+// `username`, `secret`, `disconnect_topic`, `ignored_client_ids`, `subscriber_identification` and
+// `options` of the connector. If `subscriber_identification` is `identity`, the connector reports
+// the identity subscribers authenticated as in place of their client id, if it can resolve it.
+// Returns a handle to the connector, or NULL on failure.
+void* agemo_connector_create(const char* config_json);
+
+// Starts monitoring the messaging broker, calling `cb` with `ctx` for every update until the
+// connector is destroyed. `cb` may be called from any thread. Returns zero on success.
+int32_t agemo_connector_monitor(void* connector, agemo_monitor_cb cb, void* ctx);
+
+// Sends `deletion_msg` to the subscribers of `topic` and deletes it from the messaging broker.
+// Returns zero on success.
+int32_t agemo_connector_delete_topic(void* connector, const char* topic, const char* deletion_msg);
+
+// Optional. Restricts publishing on `topic` to `publisher_id`. Returns zero on success.
+int32_t agemo_connector_authorize_publisher(void* connector, const char* topic,
+                                            const char* publisher_id);
+
+// Optional. Removes the publishing restriction on `topic`. Returns zero on success.
+int32_t agemo_connector_revoke_publisher(void* connector, const char* topic);
+
+// Disconnects from the messaging broker and frees the connector. `cb` must not be called once
+// this returns.
+void agemo_connector_destroy(void* connector);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif // AGEMO_CONNECTOR_H
@@ -0,0 +1,215 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Prioritized dispatch of the updates handled by the topic manager.
+//!
+//! The topic manager handles one update at a time, and handling an update can wait for a callback
+//! slot. When updates pile up, such as when the cleanup loop sends a batch of STOP reminders, a
+//! subscribe that starts a topic would otherwise wait behind all of them, delaying the first
+//! message to the first subscriber. The reminders are instead deferred until every other update
+//! received so far is handled. Every other update is handled in the order it was received, so that
+//! the updates of a topic, such as an unsubscribe followed by a subscribe, are never reordered.
+
+use std::{
+    collections::VecDeque,
+    sync::mpsc::{Receiver, RecvError},
+};
+
+use crate::pubsub_connector::{MonitorMessage, PubSubAction};
+
+/// Priorities of the updates handled by the topic manager, from the most to the least urgent.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ActionPriority {
+    /// Updates that change the state of topics or of the service, handled in the order they
+    /// were received.
+    Normal = 0,
+    /// Reminders sent by the cleanup loop, which can be handled late.
+    Low = 1,
+}
+
+impl ActionPriority {
+    /// Returns the priority of an update.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The action of the update.
+    pub fn of(action: PubSubAction) -> Self {
+        match action {
+            PubSubAction::Timeout
+            | PubSubAction::DeferredStop
+            | PubSubAction::ExpirePlaceholder
+            | PubSubAction::IdleExpired => ActionPriority::Low,
+            _ => ActionPriority::Normal,
+        }
+    }
+}
+
+/// Receiver of the updates handled by the topic manager, which returns the most urgent update
+/// received so far.
+pub struct PriorityReceiver {
+    /// The channel the updates are received on.
+    receiver: Receiver<MonitorMessage>,
+    /// The received updates that were not returned yet, by priority.
+    queues: [VecDeque<MonitorMessage>; 2],
+}
+
+impl PriorityReceiver {
+    /// Creates a new PriorityReceiver.
+    ///
+    /// # Arguments
+    ///
+    /// * `receiver` - The channel the updates are received on.
+    pub fn new(receiver: Receiver<MonitorMessage>) -> Self {
+        PriorityReceiver {
+            receiver,
+            queues: Default::default(),
+        }
+    }
+
+    /// Returns the most urgent update received so far, blocking until an update is received if
+    /// none is queued. Fails once every sender is dropped and no update is queued.
+    pub fn recv(&mut self) -> Result<MonitorMessage, RecvError> {
+        if self.is_empty() {
+            let msg = self.receiver.recv()?;
+            self.push(msg);
+        }
+
+        // Updates that are already waiting on the channel are queued, so that a more urgent
+        // update among them is returned first.
+        while let Ok(msg) = self.receiver.try_recv() {
+            self.push(msg);
+        }
+
+        self.queues
+            .iter_mut()
+            .find_map(VecDeque::pop_front)
+            .ok_or(RecvError)
+    }
+
+    /// Returns the number of updates received that were not returned yet.
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    /// Returns whether every update received was returned.
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(VecDeque::is_empty)
+    }
+
+    /// Queues an update by its priority.
+    ///
+    /// # Arguments
+    ///
+    /// * `msg` - The update.
+    fn push(&mut self, msg: MonitorMessage) {
+        self.queues[ActionPriority::of(msg.action) as usize].push_back(msg);
+    }
+}
+
+#[cfg(test)]
+mod action_priority_tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    fn message(topic: &str, action: PubSubAction) -> MonitorMessage {
+        MonitorMessage {
+            context: topic.to_string(),
+            action,
+            client_id: None,
+            identity: None,
+        }
+    }
+
+    #[test]
+    fn recv_defers_cleanup_reminders_test() {
+        let (sender, receiver) = mpsc::channel();
+        let mut receiver = PriorityReceiver::new(receiver);
+
+        for topic in ["topic-0", "topic-1", "topic-2"] {
+            sender.send(message(topic, PubSubAction::Timeout)).unwrap();
+        }
+        sender
+            .send(message("topic-3", PubSubAction::Unsubscribe))
+            .unwrap();
+        sender
+            .send(message("topic-4", PubSubAction::Subscribe))
+            .unwrap();
+
+        let first = receiver.recv().unwrap();
+        assert_eq!(PubSubAction::Unsubscribe, first.action);
+        assert_eq!("topic-3", first.context);
+        assert_eq!(PubSubAction::Subscribe, receiver.recv().unwrap().action);
+        assert_eq!(3, receiver.len());
+
+        // A subscribe received later still goes ahead of the queued reminders.
+        sender.send(message("topic-5", PubSubAction::Warm)).unwrap();
+        assert_eq!("topic-5", receiver.recv().unwrap().context);
+
+        // Reminders keep their order.
+        for topic in ["topic-0", "topic-1", "topic-2"] {
+            assert_eq!(topic, receiver.recv().unwrap().context);
+        }
+        assert!(receiver.is_empty());
+    }
+
+    #[test]
+    fn recv_keeps_unsubscribe_before_subscribe_test() {
+        let (sender, receiver) = mpsc::channel();
+        let mut receiver = PriorityReceiver::new(receiver);
+
+        for action in [
+            PubSubAction::Unsubscribe,
+            PubSubAction::Subscribe,
+            PubSubAction::Delete,
+            PubSubAction::Subscribe,
+        ] {
+            sender.send(message("topic-0", action)).unwrap();
+        }
+
+        for action in [
+            PubSubAction::Unsubscribe,
+            PubSubAction::Subscribe,
+            PubSubAction::Delete,
+            PubSubAction::Subscribe,
+        ] {
+            assert_eq!(action, receiver.recv().unwrap().action);
+        }
+        assert!(receiver.is_empty());
+    }
+
+    #[test]
+    fn recv_returns_queued_updates_after_disconnect_test() {
+        let (sender, receiver) = mpsc::channel();
+        let mut receiver = PriorityReceiver::new(receiver);
+
+        sender
+            .send(message("topic-0", PubSubAction::Timeout))
+            .unwrap();
+        sender
+            .send(message("topic-1", PubSubAction::Subscribe))
+            .unwrap();
+        drop(sender);
+
+        assert_eq!("topic-1", receiver.recv().unwrap().context);
+        assert_eq!("topic-0", receiver.recv().unwrap().context);
+        assert!(receiver.recv().is_err());
+    }
+
+    #[test]
+    fn action_priority_test() {
+        assert_eq!(
+            ActionPriority::Normal,
+            ActionPriority::of(PubSubAction::Subscribe)
+        );
+        assert_eq!(
+            ActionPriority::Normal,
+            ActionPriority::of(PubSubAction::Delete)
+        );
+        assert_eq!(
+            ActionPriority::Low,
+            ActionPriority::of(PubSubAction::DeferredStop)
+        );
+    }
+}
@@ -0,0 +1,1240 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Module containing gRPC service implementation based on [`proto::admin`].
+//!
+//! Provides a gRPC endpoint for operators to inspect the state of the pub sub service.
+
+use std::{
+    pin::Pin,
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+
+use common::shared_state::SharedState;
+use futures::{stream, Stream};
+use log::{info, warn};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tonic::{Request, Response, Status};
+
+use proto::admin::v1::admin_server::Admin;
+use proto::admin::v1::{
+    CallbackBreaker, DeleteTopicsWhereRequest, DeleteTopicsWhereResponse, DumpStateRequest,
+    DumpStateResponse, ExportTopologyRequest, ExportTopologyResponse, GetBrokerStatsRequest,
+    GetBrokerStatsResponse, GetCallbackBreakersRequest, GetCallbackBreakersResponse,
+    GetCleanupReportRequest, GetCleanupReportResponse, GetHealthRequest, GetHealthResponse,
+    GetMetricsRequest, GetMetricsResponse, GetTopicInfoRequest, GetTopicInfoResponse,
+    ListPlaceholderTopicsRequest, ListPlaceholderTopicsResponse, MonitorSubscription,
+    PlaceholderTopic, RunSelfTestRequest, RunSelfTestResponse, SelfTestStep,
+    SetFaultInjectionRequest, SetFaultInjectionResponse, SetPowerStateRequest,
+    SetPowerStateResponse, TaskHealth, TopicActionRecord, TopicEvent, WatchTopicsRequest,
+};
+
+#[cfg(feature = "fault-injection")]
+use crate::fault_injection::{FaultConfig, FaultInjector};
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::{
+    callback_breaker::CallbackBreakers,
+    latency_probe::LatencyProbesHandle,
+    power,
+    providers::Clock,
+    publisher_metrics::PublisherMetrics,
+    pubsub_connector::{BrokerStatsHandle, MonitorHealth, MonitorMessage},
+    self_test::SelfTester,
+    state_dump::{StateDumpSource, TopicSnapshot},
+    supervisor::Supervisor,
+    topic_manager::{ActiveTopicsMap, CleanupStats, TopicEventSender},
+    topic_spill::TopicSpill,
+    topology::{Topology, TopologyFormat},
+};
+
+/// Stream of topic lifecycle events sent to a watcher.
+pub type WatchTopicsStream = Pin<Box<dyn Stream<Item = Result<TopicEvent, Status>> + Send>>;
+
+/// Base structure for the admin gRPC service.
+pub struct AdminImpl {
+    /// Handle that points to a shared active topics map.
+    pub active_topics: Arc<SharedState<ActiveTopicsMap>>,
+    /// Handle that points to the stats recorded when topics are deleted.
+    pub cleanup_stats: Arc<SharedState<CleanupStats>>,
+    /// Source of time used to determine the report window.
+    pub clock: Arc<dyn Clock>,
+    /// Handle that points to the latest statistics reported by the messaging broker.
+    pub broker_stats: BrokerStatsHandle,
+    /// Channel that topic lifecycle events are broadcast on.
+    pub topic_events: TopicEventSender,
+    /// Channel used to report power state changes to the topic manager.
+    pub monitor_sender: mpsc::Sender<MonitorMessage>,
+    /// Handles to the state captured in a state snapshot.
+    pub state_dump: StateDumpSource,
+    /// Handle to the supervisor of the background tasks of the service.
+    pub supervisor: Supervisor,
+    /// Handle that points to the circuit breakers of the publisher callbacks.
+    pub callback_breakers: Arc<SharedState<CallbackBreakers>>,
+    /// Handle that points to the statistics of the callbacks made to each publisher.
+    pub publisher_metrics: Arc<SharedState<PublisherMetrics>>,
+    /// Runs self-tests of the full loop of the service.
+    pub self_tester: SelfTester,
+    /// Handle that points to the health of the broker connector's monitor subscriptions.
+    pub monitor_health: MonitorHealth,
+    /// Handle that points to the round trips of the latency probes.
+    pub latency_probes: LatencyProbesHandle,
+    /// Spill of the cold topics that are not held in memory. Topics are not spilled if not set.
+    pub topic_spill: Option<Arc<TopicSpill>>,
+    /// Handle that points to the faults injected into the service.
+    #[cfg(feature = "fault-injection")]
+    pub fault_injector: Arc<FaultInjector>,
+}
+
+#[tonic::async_trait]
+impl Admin for AdminImpl {
+    type WatchTopicsStream = WatchTopicsStream;
+
+    /// Gets statistics about topic cleanup activity.
+    ///
+    /// Returns a [`GetCleanupReportResponse`] describing the topics deleted in the last hour and
+    /// the topics still waiting to be deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `_request` - Empty request for a cleanup report.
+    async fn get_cleanup_report(
+        &self,
+        _request: Request<GetCleanupReportRequest>,
+    ) -> Result<Response<GetCleanupReportResponse>, Status> {
+        let report = {
+            let active_topics = self.active_topics.lock();
+            self.cleanup_stats.lock().report(
+                &active_topics,
+                self.topic_spill.as_deref(),
+                self.clock.now(),
+            )
+        };
+
+        let reply = GetCleanupReportResponse {
+            topics_reaped_last_hour: report.topics_reaped,
+            average_idle_time_ms: u64::try_from(report.average_idle_time.as_millis())
+                .unwrap_or(u64::MAX),
+            pending_deletions: report.pending_deletions,
+            spilled_topics: report.spilled_topics,
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Sets the faults injected into the service for resilience testing.
+    ///
+    /// Returns an unimplemented status if the service is built without the `fault-injection`
+    /// feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The faults to inject.
+    async fn set_fault_injection(
+        &self,
+        request: Request<SetFaultInjectionRequest>,
+    ) -> Result<Response<SetFaultInjectionResponse>, Status> {
+        #[cfg(feature = "fault-injection")]
+        {
+            let request_inner = request.into_inner();
+            let config = FaultConfig {
+                monitor_drop_percent: request_inner.monitor_drop_percent,
+                callback_delay: std::time::Duration::from_millis(request_inner.callback_delay_ms),
+                create_topic_failure_percent: request_inner.create_topic_failure_percent,
+            };
+
+            log::warn!("Injecting faults: {config:?}");
+            self.fault_injector.set_config(config);
+
+            Ok(Response::new(SetFaultInjectionResponse {}))
+        }
+
+        #[cfg(not(feature = "fault-injection"))]
+        {
+            let _ = request;
+
+            Err(Status::unimplemented(
+                "fault injection requires the 'fault-injection' feature",
+            ))
+        }
+    }
+
+    /// Gets the latest statistics reported by the messaging broker.
+    ///
+    /// Returns a [`GetBrokerStatsResponse`], leaving out statistics the broker has not reported.
+    ///
+    /// # Arguments
+    ///
+    /// * `_request` - Empty request for the broker statistics.
+    async fn get_broker_stats(
+        &self,
+        _request: Request<GetBrokerStatsRequest>,
+    ) -> Result<Response<GetBrokerStatsResponse>, Status> {
+        let stats = self.broker_stats.lock().clone();
+
+        let reply = GetBrokerStatsResponse {
+            connected_clients: stats.connected_clients,
+            messages_received_per_min: stats.messages_received_per_min,
+            messages_sent_per_min: stats.messages_sent_per_min,
+            messages_dropped: stats.messages_dropped,
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Gets the service metrics in the Prometheus text exposition format.
+    ///
+    /// Returns an unimplemented status if the service is built without the `metrics` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `_request` - Empty request for the service metrics.
+    async fn get_metrics(
+        &self,
+        _request: Request<GetMetricsRequest>,
+    ) -> Result<Response<GetMetricsResponse>, Status> {
+        #[cfg(feature = "metrics")]
+        {
+            let active_topics = self.active_topics.lock();
+            let cleanup_report = self.cleanup_stats.lock().report(
+                &active_topics,
+                self.topic_spill.as_deref(),
+                self.clock.now(),
+            );
+            let broker_stats = self.broker_stats.lock().clone();
+            let breakers = self.callback_breakers.lock().status(self.clock.now());
+
+            let reply = GetMetricsResponse {
+                metrics: metrics::render_metrics(
+                    &active_topics,
+                    &cleanup_report,
+                    &broker_stats,
+                    &self.supervisor.task_health(),
+                    &breakers,
+                    &self.publisher_metrics.lock(),
+                    &self.latency_probes.lock(),
+                ),
+            };
+
+            Ok(Response::new(reply))
+        }
+
+        #[cfg(not(feature = "metrics"))]
+        Err(Status::unimplemented(
+            "metrics require the 'metrics' feature",
+        ))
+    }
+
+    /// Watches the lifecycle events of topics.
+    ///
+    /// Returns a stream of [`TopicEvent`]s matching the filters in the request. A watcher that
+    /// falls too far behind skips the events it missed.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The filters to apply to the topic events.
+    async fn watch_topics(
+        &self,
+        request: Request<WatchTopicsRequest>,
+    ) -> Result<Response<Self::WatchTopicsStream>, Status> {
+        let request_inner = request.into_inner();
+        let receiver = self.topic_events.subscribe();
+
+        let event_stream = stream::unfold(
+            (receiver, request_inner),
+            |(mut receiver, filter)| async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(event) => {
+                            if event.matches(&filter.publisher_id, &filter.topic_prefix) {
+                                let reply = TopicEvent {
+                                    topic: event.topic,
+                                    publisher_id: event.publisher_id,
+                                    action: event.kind.to_string(),
+                                    timestamp_ms: event.timestamp_ms,
+                                };
+
+                                return Some((Ok(reply), (receiver, filter)));
+                            }
+                        }
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("Topic watcher fell behind, skipped {skipped} events.");
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            },
+        );
+
+        Ok(Response::new(Box::pin(event_stream)))
+    }
+
+    /// Reports that the system is suspending or resuming.
+    ///
+    /// Topic cleanup and publisher callbacks are paused while the system is suspended. Returns an
+    /// unavailable status if the topic manager is no longer running.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The power state of the system.
+    async fn set_power_state(
+        &self,
+        request: Request<SetPowerStateRequest>,
+    ) -> Result<Response<SetPowerStateResponse>, Status> {
+        let suspended = request.into_inner().suspended;
+        info!("Power state reported, suspended: {suspended}.");
+
+        self.monitor_sender
+            .send(power::power_message(suspended))
+            .map_err(|_| Status::unavailable("the topic manager is not running"))?;
+
+        Ok(Response::new(SetPowerStateResponse {}))
+    }
+
+    /// Gets a snapshot of the internal state of the service for debugging.
+    ///
+    /// Returns a [`DumpStateResponse`] with the snapshot serialized as JSON.
+    ///
+    /// # Arguments
+    ///
+    /// * `_request` - Empty request for a state snapshot.
+    async fn dump_state(
+        &self,
+        _request: Request<DumpStateRequest>,
+    ) -> Result<Response<DumpStateResponse>, Status> {
+        let state = self
+            .state_dump
+            .snapshot_json(true)
+            .map_err(|e| Status::internal(format!("unable to serialize the state: {e}")))?;
+
+        Ok(Response::new(DumpStateResponse { state }))
+    }
+
+    /// Gets the state of a single topic, including its recent lifecycle actions.
+    ///
+    /// Returns a [`GetTopicInfoResponse`], or a not found status if the topic is unknown.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The topic to get the state of.
+    async fn get_topic_info(
+        &self,
+        request: Request<GetTopicInfoRequest>,
+    ) -> Result<Response<GetTopicInfoResponse>, Status> {
+        let topic = request.into_inner().topic;
+
+        let snapshot = {
+            let active_topics = self.active_topics.lock();
+            let now = self.clock.now();
+
+            match active_topics.get(&topic) {
+                Some(metadata) => TopicSnapshot::new(&topic, metadata, metadata.idle_duration(now)),
+                None => {
+                    // A cold topic is read back from the spill without restoring it.
+                    let metadata = self
+                        .topic_spill
+                        .as_ref()
+                        .and_then(|topic_spill| topic_spill.get(&topic))
+                        .ok_or_else(|| Status::not_found(format!("unknown topic '{topic}'")))?;
+
+                    TopicSnapshot::spilled(&topic, &metadata, metadata.idle_duration(now))
+                }
+            }
+        };
+
+        let action_history = snapshot
+            .action_history
+            .into_iter()
+            .map(|record| TopicActionRecord {
+                action: record.action,
+                cause: record.cause,
+                outcome: record.outcome,
+                detail: record.detail,
+                timestamp_ms: record.timestamp_ms,
+            })
+            .collect();
+
+        let reply = GetTopicInfoResponse {
+            topic: snapshot.topic,
+            publisher_id: snapshot.publisher_id,
+            subscriber_count: snapshot.subscriber_count,
+            deleted: snapshot.deleted,
+            idle_ms: snapshot.idle_ms,
+            action_history,
+            spilled: snapshot.spilled,
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Lists the topics that subscribers subscribed to but no publisher has created.
+    ///
+    /// Returns a [`ListPlaceholderTopicsResponse`] with the placeholder topics, sorted by name.
+    ///
+    /// # Arguments
+    ///
+    /// * `_request` - Empty request for the placeholder topics.
+    async fn list_placeholder_topics(
+        &self,
+        _request: Request<ListPlaceholderTopicsRequest>,
+    ) -> Result<Response<ListPlaceholderTopicsResponse>, Status> {
+        let now = self.clock.now();
+
+        let mut topics: Vec<PlaceholderTopic> = self
+            .active_topics
+            .lock()
+            .iter()
+            .filter(|(_, metadata)| metadata.is_placeholder())
+            .map(|(topic, metadata)| {
+                let mut subscribers: Vec<String> = metadata.subscribers().cloned().collect();
+                subscribers.sort();
+
+                PlaceholderTopic {
+                    topic: topic.clone(),
+                    subscriber_count: metadata.count,
+                    subscribers,
+                    age_ms: u64::try_from(metadata.age(now).as_millis()).unwrap_or(u64::MAX),
+                }
+            })
+            .collect();
+        topics.sort_by(|a, b| a.topic.cmp(&b.topic));
+
+        Ok(Response::new(ListPlaceholderTopicsResponse { topics }))
+    }
+
+    /// Gets the health of the background tasks and of the topic monitoring of the service.
+    ///
+    /// Returns a [`GetHealthResponse`] that reports the service as degraded while a background
+    /// task that panicked or failed has not yet recovered, or while the broker connector is retrying a
+    /// rejected subscription to a monitor topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `_request` - Empty request for the health of the service.
+    async fn get_health(
+        &self,
+        _request: Request<GetHealthRequest>,
+    ) -> Result<Response<GetHealthResponse>, Status> {
+        let tasks: Vec<TaskHealth> = self
+            .supervisor
+            .task_health()
+            .into_iter()
+            .map(|(name, health)| TaskHealth {
+                name,
+                panics: health.panics,
+                degraded: health.degraded,
+                last_panic: health.last_panic.unwrap_or_default(),
+                failures: health.failures,
+                last_failure: health.last_failure.unwrap_or_default(),
+                stopped: health.stopped,
+            })
+            .collect();
+
+        let failed_monitor_subscriptions: Vec<MonitorSubscription> = self
+            .monitor_health
+            .failed_subscriptions()
+            .into_iter()
+            .map(|(topic, error)| MonitorSubscription { topic, error })
+            .collect();
+
+        let reply = GetHealthResponse {
+            degraded: tasks.iter().any(|task| task.degraded)
+                || !failed_monitor_subscriptions.is_empty(),
+            tasks,
+            failed_monitor_subscriptions,
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Gets the circuit breakers of the publisher management callbacks.
+    ///
+    /// Returns a [`GetCallbackBreakersResponse`] with the breaker of each management uri whose
+    /// callbacks have been failing.
+    ///
+    /// # Arguments
+    ///
+    /// * `_request` - Empty request for the circuit breakers.
+    async fn get_callback_breakers(
+        &self,
+        _request: Request<GetCallbackBreakersRequest>,
+    ) -> Result<Response<GetCallbackBreakersResponse>, Status> {
+        let breakers = self
+            .callback_breakers
+            .lock()
+            .status(self.clock.now())
+            .into_iter()
+            .map(|status| CallbackBreaker {
+                uri: status.uri,
+                state: status.state.to_string(),
+                consecutive_failures: status.consecutive_failures,
+                failing_for_ms: u64::try_from(status.failing_for.as_millis()).unwrap_or(u64::MAX),
+            })
+            .collect();
+
+        Ok(Response::new(GetCallbackBreakersResponse { breakers }))
+    }
+
+    /// Runs a self-test of the full loop of the service.
+    ///
+    /// Returns a [`RunSelfTestResponse`] with the outcome of each step. A failed step is reported
+    /// in the response rather than as an error status.
+    ///
+    /// # Arguments
+    ///
+    /// * `_request` - Empty request to run a self-test.
+    async fn run_self_test(
+        &self,
+        _request: Request<RunSelfTestRequest>,
+    ) -> Result<Response<RunSelfTestResponse>, Status> {
+        let report = self
+            .self_tester
+            .run(&self.active_topics, self.clock.now())
+            .await;
+
+        if report.passed() {
+            info!("Self-test on topic '{}' passed.", report.topic);
+        } else {
+            warn!("Self-test on topic '{}' failed.", report.topic);
+        }
+
+        let passed = report.passed();
+        let steps = report
+            .steps
+            .into_iter()
+            .map(|step| SelfTestStep {
+                name: step.name.to_string(),
+                passed: step.passed,
+                duration_ms: u64::try_from(step.duration.as_millis()).unwrap_or(u64::MAX),
+                detail: step.detail,
+            })
+            .collect();
+
+        Ok(Response::new(RunSelfTestResponse {
+            passed,
+            topic: report.topic,
+            steps,
+        }))
+    }
+
+    /// Marks every topic matching a filter for deletion.
+    ///
+    /// The topics are deleted by the topic manager the same way as topics deleted by their
+    /// publisher, which notifies the publishers and the subscribers of the topics. Returns a
+    /// [`DeleteTopicsWhereResponse`] with the topics marked for deletion, or an invalid argument
+    /// status if no filter is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The filter of the topics to delete.
+    async fn delete_topics_where(
+        &self,
+        request: Request<DeleteTopicsWhereRequest>,
+    ) -> Result<Response<DeleteTopicsWhereResponse>, Status> {
+        let request_inner = request.into_inner();
+        let publisher_id = request_inner.publisher_id;
+        let topic_prefix = request_inner.topic_prefix;
+        let idle_longer_than = request_inner.idle_longer_than_ms.map(Duration::from_millis);
+
+        if publisher_id.is_empty() && topic_prefix.is_empty() && idle_longer_than.is_none() {
+            return Err(Status::invalid_argument(
+                "at least one of 'publisherId', 'topicPrefix' or 'idleLongerThanMs' is required",
+            ));
+        }
+
+        let now = self.clock.now();
+
+        let mut topics: Vec<String> = {
+            let mut active_topics = self.active_topics.lock();
+            if let Some(topic_spill) = &self.topic_spill {
+                topic_spill.restore_matching(&mut active_topics, &publisher_id, &topic_prefix);
+            }
+
+            active_topics
+                .iter_mut()
+                .filter(|(topic, metadata)| {
+                    !metadata.is_deleted()
+                        && (publisher_id.is_empty() || metadata.client_id == publisher_id)
+                        && topic.starts_with(&topic_prefix)
+                        && idle_longer_than.map_or(true, |idle| metadata.idle_duration(now) > idle)
+                })
+                .map(|(topic, metadata)| {
+                    metadata.delete();
+                    topic.clone()
+                })
+                .collect()
+        };
+        topics.sort();
+
+        info!(
+            "Marked {} topics for deletion matching publisher_id={publisher_id:?}, topic_prefix={topic_prefix:?}, idle_longer_than={idle_longer_than:?}.",
+            topics.len()
+        );
+
+        Ok(Response::new(DeleteTopicsWhereResponse { topics }))
+    }
+
+    /// Exports the publishers, topics and subscribers known to the service.
+    ///
+    /// Returns an [`ExportTopologyResponse`] with the topology in the requested format, or an
+    /// invalid argument status if the format is unknown.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The format to export the topology in.
+    async fn export_topology(
+        &self,
+        request: Request<ExportTopologyRequest>,
+    ) -> Result<Response<ExportTopologyResponse>, Status> {
+        let format_value = request.into_inner().format;
+        let format = TopologyFormat::from_proto(format_value).ok_or_else(|| {
+            Status::invalid_argument(format!("unknown topology format {format_value}"))
+        })?;
+
+        let topology = {
+            let active_topics = self.active_topics.lock();
+            // Read while the active topics are locked, so that a topic restored meanwhile isn't
+            // exported twice.
+            let spilled_topics = self
+                .topic_spill
+                .as_ref()
+                .map(|topic_spill| topic_spill.spilled_topics())
+                .unwrap_or_default();
+
+            Topology::new(
+                active_topics.iter().chain(
+                    spilled_topics
+                        .iter()
+                        .map(|(topic, metadata)| (topic, metadata)),
+                ),
+            )
+        };
+        let topology = topology
+            .export(format)
+            .map_err(|e| Status::internal(format!("unable to serialize the topology: {e}")))?;
+
+        Ok(Response::new(ExportTopologyResponse { topology }))
+    }
+}
+
+#[cfg(test)]
+mod admin_impl_tests {
+    use std::{fs, time::Instant};
+
+    use futures::StreamExt;
+
+    use crate::{
+        callback_breaker::PublisherCallbacks,
+        providers::ManualClock,
+        pubsub_connector::PubSubAction,
+        self_test::{PendingProbes, ProbeResult, SELF_TEST_TOPIC_PREFIX},
+        state_store::JsonFileStore,
+        topic_manager::{
+            ActionOutcome, ActionRecord, TopicEvent as ManagedTopicEvent, TopicEventKind,
+            TopicManager, TopicMetadata, TOPIC_EVENT_CAPACITY,
+        },
+    };
+
+    use super::*;
+
+    /// Returns an AdminImpl without any topic, with the defaults of the tests. Tests override the
+    /// fields they need with struct update syntax.
+    fn test_admin() -> AdminImpl {
+        AdminImpl {
+            active_topics: Arc::new(SharedState::new(ActiveTopicsMap::new())),
+            cleanup_stats: Arc::new(SharedState::new(CleanupStats::default())),
+            clock: Arc::new(ManualClock::new()),
+            broker_stats: BrokerStatsHandle::default(),
+            topic_events: broadcast::channel(TOPIC_EVENT_CAPACITY).0,
+            monitor_sender: mpsc::channel().0,
+            state_dump: TopicManager::new().get_state_dump_source(),
+            supervisor: Supervisor::new(),
+            callback_breakers: PublisherCallbacks::default().breakers,
+            publisher_metrics: PublisherCallbacks::default().metrics,
+            self_tester: SelfTester::new(mpsc::channel().0, PendingProbes::default()),
+            monitor_health: MonitorHealth::default(),
+            latency_probes: LatencyProbesHandle::default(),
+            topic_spill: None,
+            #[cfg(feature = "fault-injection")]
+            fault_injector: Arc::new(FaultInjector::new()),
+        }
+    }
+
+    #[tokio::test]
+    async fn get_cleanup_report_test() {
+        let clock = Arc::new(ManualClock::new());
+        let active_topics = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+        let cleanup_stats = Arc::new(SharedState::new(CleanupStats::default()));
+
+        {
+            let mut pending = TopicMetadata::new_at(String::new(), 0, None, clock.now());
+            pending.delete();
+            active_topics.lock().insert("pending".to_string(), pending);
+        }
+
+        cleanup_stats
+            .lock()
+            .record(clock.now(), Duration::from_millis(1500));
+
+        let admin = AdminImpl {
+            active_topics,
+            cleanup_stats,
+            clock,
+            ..test_admin()
+        };
+
+        let actual = admin
+            .get_cleanup_report(Request::new(GetCleanupReportRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(1, actual.topics_reaped_last_hour);
+        assert_eq!(1500, actual.average_idle_time_ms);
+        assert_eq!(1, actual.pending_deletions);
+    }
+
+    #[cfg(feature = "fault-injection")]
+    #[tokio::test]
+    async fn set_fault_injection_test() {
+        let fault_injector = Arc::new(FaultInjector::new());
+
+        let admin = AdminImpl {
+            fault_injector: fault_injector.clone(),
+            ..test_admin()
+        };
+
+        let request = Request::new(SetFaultInjectionRequest {
+            monitor_drop_percent: 10,
+            callback_delay_ms: 500,
+            create_topic_failure_percent: 100,
+        });
+
+        assert!(admin.set_fault_injection(request).await.is_ok());
+
+        let expected = FaultConfig {
+            monitor_drop_percent: 10,
+            callback_delay: Duration::from_millis(500),
+            create_topic_failure_percent: 100,
+        };
+        assert_eq!(expected, fault_injector.get_config());
+    }
+
+    #[tokio::test]
+    async fn get_broker_stats_test() {
+        let broker_stats = BrokerStatsHandle::default();
+        broker_stats.lock().connected_clients = Some(3);
+
+        let admin = AdminImpl {
+            broker_stats,
+            ..test_admin()
+        };
+
+        let actual = admin
+            .get_broker_stats(Request::new(GetBrokerStatsRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(Some(3), actual.connected_clients);
+        assert_eq!(None, actual.messages_dropped);
+    }
+
+    #[tokio::test]
+    async fn watch_topics_test() {
+        let topic_events = broadcast::channel(TOPIC_EVENT_CAPACITY).0;
+
+        let admin = AdminImpl {
+            topic_events: topic_events.clone(),
+            ..test_admin()
+        };
+
+        let request = Request::new(WatchTopicsRequest {
+            publisher_id: String::new(),
+            topic_prefix: "vehicle/".to_string(),
+        });
+        let mut event_stream = admin.watch_topics(request).await.unwrap().into_inner();
+
+        topic_events
+            .send(ManagedTopicEvent::new(
+                TopicEventKind::Create,
+                "cabin/temp".to_string(),
+                "pub_1".to_string(),
+            ))
+            .unwrap();
+        topic_events
+            .send(ManagedTopicEvent::new(
+                TopicEventKind::Start,
+                "vehicle/speed".to_string(),
+                "pub_1".to_string(),
+            ))
+            .unwrap();
+
+        let actual = event_stream.next().await.unwrap().unwrap();
+        assert_eq!("vehicle/speed", actual.topic);
+        assert_eq!("pub_1", actual.publisher_id);
+        assert_eq!("START", actual.action);
+
+        drop(admin);
+        drop(topic_events);
+        assert!(event_stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn set_power_state_test() {
+        let (monitor_sender, monitor_receiver) = mpsc::channel();
+
+        let admin = AdminImpl {
+            monitor_sender,
+            ..test_admin()
+        };
+
+        let request = Request::new(SetPowerStateRequest { suspended: true });
+        assert!(admin.set_power_state(request).await.is_ok());
+        assert_eq!(
+            PubSubAction::PowerSuspend,
+            monitor_receiver.try_recv().unwrap().action
+        );
+
+        drop(monitor_receiver);
+        let request = Request::new(SetPowerStateRequest { suspended: false });
+        assert!(admin.set_power_state(request).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn dump_state_test() {
+        let topic_manager = TopicManager::new();
+        topic_manager.get_active_topics_handle().lock().insert(
+            "topic-0".to_string(),
+            TopicMetadata::new("pub_1".to_string(), 0, None),
+        );
+
+        let admin = AdminImpl {
+            active_topics: topic_manager.get_active_topics_handle(),
+            cleanup_stats: topic_manager.get_cleanup_stats_handle(),
+            clock: topic_manager.get_clock_handle(),
+            topic_events: topic_manager.get_topic_events_handle(),
+            state_dump: topic_manager.get_state_dump_source(),
+            ..test_admin()
+        };
+
+        let actual = admin
+            .dump_state(Request::new(DumpStateRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let state: serde_json::Value = serde_json::from_str(&actual.state).unwrap();
+        assert_eq!(Some("topic-0"), state["topics"][0]["topic"].as_str());
+        assert_eq!(Some("pub_1"), state["topics"][0]["publisher_id"].as_str());
+    }
+
+    #[tokio::test]
+    async fn get_topic_info_test() {
+        let topic_manager = TopicManager::new();
+        {
+            let mut metadata = TopicMetadata::new("pub_1".to_string(), 0, None);
+            metadata.record_action(ActionRecord::new(
+                TopicEventKind::Stop,
+                "UNSUBSCRIBE".to_string(),
+                ActionOutcome::Failed,
+                "connection refused".to_string(),
+            ));
+            topic_manager
+                .get_active_topics_handle()
+                .lock()
+                .insert("topic-0".to_string(), metadata);
+        }
+
+        let admin = AdminImpl {
+            active_topics: topic_manager.get_active_topics_handle(),
+            cleanup_stats: topic_manager.get_cleanup_stats_handle(),
+            clock: topic_manager.get_clock_handle(),
+            topic_events: topic_manager.get_topic_events_handle(),
+            state_dump: topic_manager.get_state_dump_source(),
+            ..test_admin()
+        };
+
+        let actual = admin
+            .get_topic_info(Request::new(GetTopicInfoRequest {
+                topic: "topic-0".to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!("pub_1", actual.publisher_id);
+        assert_eq!(1, actual.action_history.len());
+        assert_eq!("STOP", actual.action_history[0].action);
+        assert_eq!("UNSUBSCRIBE", actual.action_history[0].cause);
+        assert_eq!("FAILED", actual.action_history[0].outcome);
+        assert_eq!("connection refused", actual.action_history[0].detail);
+        assert!(!actual.spilled);
+
+        let status = admin
+            .get_topic_info(Request::new(GetTopicInfoRequest {
+                topic: "unknown".to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::NotFound, status.code());
+    }
+
+    #[tokio::test]
+    async fn get_topic_info_of_spilled_topic_test() {
+        let dir = std::env::temp_dir().join(format!("agemo-admin-spill-{}", std::process::id()));
+        let topic_spill = Arc::new(TopicSpill::new(
+            Box::new(JsonFileStore::open(dir.clone()).unwrap()),
+            0,
+        ));
+        let topic_manager = TopicManager::new().with_topic_spill(topic_spill.clone());
+        {
+            let active_topics_handle = topic_manager.get_active_topics_handle();
+            let mut active_topics = active_topics_handle.lock();
+            active_topics.insert(
+                "cold".to_string(),
+                TopicMetadata::new("pub_1".to_string(), 0, None),
+            );
+            topic_spill.spill_cold_topics(&mut active_topics, Instant::now());
+        }
+
+        let admin = AdminImpl {
+            active_topics: topic_manager.get_active_topics_handle(),
+            cleanup_stats: topic_manager.get_cleanup_stats_handle(),
+            clock: topic_manager.get_clock_handle(),
+            topic_events: topic_manager.get_topic_events_handle(),
+            state_dump: topic_manager.get_state_dump_source(),
+            topic_spill: Some(topic_spill.clone()),
+            ..test_admin()
+        };
+
+        let actual = admin
+            .get_topic_info(Request::new(GetTopicInfoRequest {
+                topic: "cold".to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!("pub_1", actual.publisher_id);
+        assert!(actual.spilled);
+        // Reading the topic doesn't restore it.
+        assert!(topic_spill.is_spilled("cold"));
+
+        let report = admin
+            .get_cleanup_report(Request::new(GetCleanupReportRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(1, report.spilled_topics);
+
+        let topology = admin
+            .export_topology(Request::new(ExportTopologyRequest { format: 0 }))
+            .await
+            .unwrap()
+            .into_inner()
+            .topology;
+        assert!(topology.contains("\"cold\""));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn list_placeholder_topics_test() {
+        let clock = Arc::new(ManualClock::new());
+        let active_topics = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+
+        {
+            let mut map_lock = active_topics.lock();
+            let mut placeholder = TopicMetadata::new_at(String::new(), 1, None, clock.now());
+            placeholder.add_subscriber("sub_1".to_string());
+            map_lock.insert("typo".to_string(), placeholder);
+            map_lock.insert(
+                "created".to_string(),
+                TopicMetadata::new_at(
+                    "pub_1".to_string(),
+                    0,
+                    Some("http://0.0.0.0:50061".to_string()),
+                    clock.now(),
+                ),
+            );
+        }
+        clock.advance(Duration::from_secs(3));
+
+        let admin = AdminImpl {
+            active_topics,
+            clock,
+            ..test_admin()
+        };
+
+        let actual = admin
+            .list_placeholder_topics(Request::new(ListPlaceholderTopicsRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let expected = vec![PlaceholderTopic {
+            topic: "typo".to_string(),
+            subscriber_count: 1,
+            subscribers: vec!["sub_1".to_string()],
+            age_ms: 3000,
+        }];
+        assert_eq!(expected, actual.topics);
+    }
+
+    #[tokio::test]
+    async fn delete_topics_where_test() {
+        let clock = Arc::new(ManualClock::new());
+        let active_topics = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+        let cb = Some("http://0.0.0.0:50061".to_string());
+
+        {
+            let mut map_lock = active_topics.lock();
+            map_lock.insert(
+                "cabin/a".to_string(),
+                TopicMetadata::new_at("pub_1".to_string(), 0, cb.clone(), clock.now()),
+            );
+            map_lock.insert(
+                "cabin/b".to_string(),
+                TopicMetadata::new_at("pub_2".to_string(), 0, cb.clone(), clock.now()),
+            );
+            map_lock.insert(
+                "engine/a".to_string(),
+                TopicMetadata::new_at("pub_1".to_string(), 1, cb.clone(), clock.now()),
+            );
+        }
+        clock.advance(Duration::from_secs(60));
+
+        let admin = AdminImpl {
+            active_topics: active_topics.clone(),
+            clock,
+            ..test_admin()
+        };
+
+        let status = admin
+            .delete_topics_where(Request::new(DeleteTopicsWhereRequest::default()))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::InvalidArgument, status.code());
+
+        // Topics with subscribers are never idle.
+        let actual = admin
+            .delete_topics_where(Request::new(DeleteTopicsWhereRequest {
+                publisher_id: "pub_1".to_string(),
+                topic_prefix: String::new(),
+                idle_longer_than_ms: Some(30_000),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(vec!["cabin/a".to_string()], actual.topics);
+
+        let actual = admin
+            .delete_topics_where(Request::new(DeleteTopicsWhereRequest {
+                publisher_id: String::new(),
+                topic_prefix: "cabin/".to_string(),
+                idle_longer_than_ms: None,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(vec!["cabin/b".to_string()], actual.topics);
+
+        let map_lock = active_topics.lock();
+        assert!(map_lock["cabin/a"].is_deleted());
+        assert!(map_lock["cabin/b"].is_deleted());
+        assert!(!map_lock["engine/a"].is_deleted());
+    }
+
+    #[tokio::test]
+    async fn export_topology_test() {
+        let topic_manager = TopicManager::new();
+        topic_manager.get_active_topics_handle().lock().insert(
+            "topic-0".to_string(),
+            TopicMetadata::new("pub_1".to_string(), 0, None),
+        );
+
+        let admin = AdminImpl {
+            active_topics: topic_manager.get_active_topics_handle(),
+            cleanup_stats: topic_manager.get_cleanup_stats_handle(),
+            clock: topic_manager.get_clock_handle(),
+            topic_events: topic_manager.get_topic_events_handle(),
+            state_dump: topic_manager.get_state_dump_source(),
+            ..test_admin()
+        };
+
+        let actual = admin
+            .export_topology(Request::new(ExportTopologyRequest { format: 0 }))
+            .await
+            .unwrap()
+            .into_inner();
+        let topology: serde_json::Value = serde_json::from_str(&actual.topology).unwrap();
+        assert_eq!(
+            Some("pub_1"),
+            topology["publishers"][0]["publisher_id"].as_str()
+        );
+
+        let actual = admin
+            .export_topology(Request::new(ExportTopologyRequest { format: 1 }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(actual
+            .topology
+            .contains("\"publisher:pub_1\" -> \"topic:topic-0\";"));
+
+        let status = admin
+            .export_topology(Request::new(ExportTopologyRequest { format: 7 }))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::InvalidArgument, status.code());
+    }
+
+    #[tokio::test]
+    async fn get_health_test() {
+        let supervisor = Supervisor::new();
+        supervisor.supervise("task", || async {}).await.unwrap();
+
+        let admin = AdminImpl {
+            supervisor,
+            ..test_admin()
+        };
+
+        let actual = admin
+            .get_health(Request::new(GetHealthRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert!(!actual.degraded);
+        assert_eq!(1, actual.tasks.len());
+        assert_eq!("task", actual.tasks[0].name);
+        assert_eq!(0, actual.tasks[0].panics);
+        assert!(actual.tasks[0].last_panic.is_empty());
+        assert!(!actual.tasks[0].stopped);
+        assert!(actual.failed_monitor_subscriptions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_health_reports_degraded_monitoring_test() {
+        let monitor_health = MonitorHealth::default();
+        monitor_health.record_failure("$SYS/broker/log/M/subscribe", "not authorized".into());
+
+        let admin = AdminImpl {
+            monitor_health: monitor_health.clone(),
+            ..test_admin()
+        };
+
+        let actual = admin
+            .get_health(Request::new(GetHealthRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert!(actual.degraded);
+        assert_eq!(1, actual.failed_monitor_subscriptions.len());
+        assert_eq!(
+            "$SYS/broker/log/M/subscribe",
+            actual.failed_monitor_subscriptions[0].topic
+        );
+        assert_eq!(
+            "not authorized",
+            actual.failed_monitor_subscriptions[0].error
+        );
+
+        // The subscription was made on a retry.
+        monitor_health.record_success("$SYS/broker/log/M/subscribe");
+
+        let actual = admin
+            .get_health(Request::new(GetHealthRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert!(!actual.degraded);
+    }
+
+    #[tokio::test]
+    async fn get_callback_breakers_test() {
+        let clock = Arc::new(ManualClock::new());
+        let callback_breakers = PublisherCallbacks::default().breakers;
+        callback_breakers
+            .lock()
+            .record_failure("http://0.0.0.0:50061", clock.now());
+        clock.advance(Duration::from_secs(2));
+
+        let admin = AdminImpl {
+            clock,
+            callback_breakers,
+            ..test_admin()
+        };
+
+        let actual = admin
+            .get_callback_breakers(Request::new(GetCallbackBreakersRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let expected = vec![CallbackBreaker {
+            uri: "http://0.0.0.0:50061".to_string(),
+            state: "CLOSED".to_string(),
+            consecutive_failures: 1,
+            failing_for_ms: 2000,
+        }];
+        assert_eq!(expected, actual.breakers);
+    }
+
+    #[tokio::test]
+    async fn run_self_test_test() {
+        let (connector_sender, connector_receiver) = mpsc::channel::<MonitorMessage>();
+        let probes = PendingProbes::default();
+
+        // Fake broker connector that fails to delete the test topic.
+        let connector_probes = probes.clone();
+        std::thread::spawn(move || {
+            for msg in connector_receiver.iter() {
+                connector_probes.report(
+                    &msg.context,
+                    ProbeResult {
+                        delivery: Ok(Duration::from_millis(3)),
+                        deletion: Err("broker unavailable".to_string()),
+                    },
+                );
+            }
+        });
+
+        let active_topics = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+        let admin = AdminImpl {
+            active_topics: active_topics.clone(),
+            self_tester: SelfTester::new(connector_sender, probes),
+            ..test_admin()
+        };
+
+        let actual = admin
+            .run_self_test(Request::new(RunSelfTestRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert!(!actual.passed);
+        assert!(actual.topic.starts_with(SELF_TEST_TOPIC_PREFIX));
+        let outcomes: Vec<(&str, bool)> = actual
+            .steps
+            .iter()
+            .map(|step| (step.name.as_str(), step.passed))
+            .collect();
+        assert_eq!(
+            vec![
+                ("create", true),
+                ("deliver", true),
+                ("delete", false),
+                ("cleanup", true)
+            ],
+            outcomes
+        );
+        assert_eq!("broker unavailable", actual.steps[2].detail);
+        assert!(active_topics.lock().is_empty());
+    }
+}
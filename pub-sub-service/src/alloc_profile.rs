@@ -0,0 +1,332 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Allocation profiling used to track memory behavior regressions on embedded targets.
+//!
+//! Only compiled with the `alloc-profiling` feature, which swaps in a [`CountingAllocator`] as the
+//! global allocator. Each allocation is attributed to the [`Subsystem`] running on the current
+//! thread, set with [`enter`] for synchronous code and [`instrument`] for futures, since a task
+//! can move between threads whenever it yields. Allocations outside of any subsystem, such as
+//! those made on threads owned by the broker client library, are counted as [`Subsystem::Other`].
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+
+use strum_macros::Display;
+
+/// Number of subsystems allocations are attributed to.
+const SUBSYSTEM_COUNT: usize = 4;
+
+/// The allocator used by the service, counting allocations per subsystem.
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator::new();
+
+thread_local! {
+    /// The subsystem that allocations on the current thread are attributed to.
+    static CURRENT_SUBSYSTEM: Cell<Subsystem> = const { Cell::new(Subsystem::Other) };
+}
+
+/// A part of the service that allocations are attributed to.
+#[derive(Clone, Copy, Debug, Display, Eq, PartialEq)]
+pub enum Subsystem {
+    /// Allocations that are not attributed to a subsystem.
+    #[strum(serialize = "other")]
+    Other = 0,
+    /// Handling of gRPC requests.
+    #[strum(serialize = "grpc")]
+    Grpc = 1,
+    /// The topic manager monitoring and cleaning up topics.
+    #[strum(serialize = "topic_manager")]
+    TopicManager = 2,
+    /// The connector to the messaging broker.
+    #[strum(serialize = "connector")]
+    Connector = 3,
+}
+
+impl Subsystem {
+    /// Every subsystem, in the order their counters are stored.
+    pub const ALL: [Subsystem; SUBSYSTEM_COUNT] = [
+        Subsystem::Other,
+        Subsystem::Grpc,
+        Subsystem::TopicManager,
+        Subsystem::Connector,
+    ];
+}
+
+/// Allocation counts of a single subsystem since the service started.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct AllocationCounts {
+    /// Number of allocations made.
+    pub allocations: u64,
+    /// Number of bytes allocated.
+    pub allocated_bytes: u64,
+    /// Number of deallocations made.
+    pub deallocations: u64,
+}
+
+/// Counters of a single subsystem.
+struct SubsystemCounters {
+    allocations: AtomicU64,
+    allocated_bytes: AtomicU64,
+    deallocations: AtomicU64,
+}
+
+impl SubsystemCounters {
+    /// Instantiates counters starting at zero.
+    const fn new() -> Self {
+        Self {
+            allocations: AtomicU64::new(0),
+            allocated_bytes: AtomicU64::new(0),
+            deallocations: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Allocator that forwards to the [`System`] allocator and counts allocations per subsystem.
+pub struct CountingAllocator {
+    counters: [SubsystemCounters; SUBSYSTEM_COUNT],
+}
+
+impl CountingAllocator {
+    /// Instantiates a new CountingAllocator with every count at zero.
+    pub const fn new() -> Self {
+        Self {
+            counters: [
+                SubsystemCounters::new(),
+                SubsystemCounters::new(),
+                SubsystemCounters::new(),
+                SubsystemCounters::new(),
+            ],
+        }
+    }
+
+    /// Records an allocation of the given size for the current subsystem.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The number of bytes allocated.
+    fn record_alloc(&self, size: usize) {
+        let counters = &self.counters[current_subsystem() as usize];
+        counters.allocations.fetch_add(1, Ordering::Relaxed);
+        counters
+            .allocated_bytes
+            .fetch_add(size as u64, Ordering::Relaxed);
+    }
+
+    /// Records a deallocation for the current subsystem.
+    fn record_dealloc(&self) {
+        self.counters[current_subsystem() as usize]
+            .deallocations
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the allocation counts of the given subsystem.
+    ///
+    /// # Arguments
+    ///
+    /// * `subsystem` - The subsystem to get the counts of.
+    fn counts(&self, subsystem: Subsystem) -> AllocationCounts {
+        let counters = &self.counters[subsystem as usize];
+
+        AllocationCounts {
+            allocations: counters.allocations.load(Ordering::Relaxed),
+            allocated_bytes: counters.allocated_bytes.load(Ordering::Relaxed),
+            deallocations: counters.deallocations.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for CountingAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.record_alloc(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.record_alloc(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.record_dealloc();
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.record_alloc(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Returns the subsystem running on the current thread. Falls back to [`Subsystem::Other`] while
+/// the thread is being torn down.
+fn current_subsystem() -> Subsystem {
+    CURRENT_SUBSYSTEM
+        .try_with(Cell::get)
+        .unwrap_or(Subsystem::Other)
+}
+
+/// Attributes allocations on the current thread to a subsystem until it is dropped, then restores
+/// the previous subsystem.
+#[must_use]
+pub struct SubsystemGuard {
+    previous: Subsystem,
+}
+
+impl Drop for SubsystemGuard {
+    fn drop(&mut self) {
+        let _ = CURRENT_SUBSYSTEM.try_with(|current| current.set(self.previous));
+    }
+}
+
+/// Attributes allocations on the current thread to a subsystem until the returned guard is
+/// dropped. The guard must not be held across an `.await`, use [`instrument`] instead.
+///
+/// # Arguments
+///
+/// * `subsystem` - The subsystem to attribute allocations to.
+pub fn enter(subsystem: Subsystem) -> SubsystemGuard {
+    let previous = CURRENT_SUBSYSTEM
+        .try_with(|current| current.replace(subsystem))
+        .unwrap_or(Subsystem::Other);
+
+    SubsystemGuard { previous }
+}
+
+/// Future that attributes the allocations made while it is polled to a subsystem.
+pub struct Instrumented<F> {
+    subsystem: Subsystem,
+    inner: F,
+}
+
+impl<F: Future> Future for Instrumented<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let _guard = enter(self.subsystem);
+
+        // SAFETY: `inner` is never moved out of the pinned `Instrumented`.
+        let inner = unsafe { self.map_unchecked_mut(|instrumented| &mut instrumented.inner) };
+        inner.poll(cx)
+    }
+}
+
+/// Attributes the allocations made while polling a future to a subsystem, wherever it runs.
+///
+/// # Arguments
+///
+/// * `subsystem` - The subsystem to attribute allocations to.
+/// * `future` - The future to instrument.
+pub fn instrument<F: Future>(subsystem: Subsystem, future: F) -> Instrumented<F> {
+    Instrumented {
+        subsystem,
+        inner: future,
+    }
+}
+
+/// Service wrapper that attributes the allocations made while handling requests to a subsystem.
+/// Used to profile the gRPC server.
+#[derive(Clone, Debug)]
+pub struct InstrumentedService<S> {
+    subsystem: Subsystem,
+    inner: S,
+}
+
+impl<S> InstrumentedService<S> {
+    /// Wraps a service so that its allocations are attributed to a subsystem.
+    ///
+    /// # Arguments
+    ///
+    /// * `subsystem` - The subsystem to attribute allocations to.
+    /// * `inner` - The service to wrap.
+    pub fn new(subsystem: Subsystem, inner: S) -> Self {
+        Self { subsystem, inner }
+    }
+}
+
+impl<S, R> tower::Service<R> for InstrumentedService<S>
+where
+    S: tower::Service<R>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Instrumented<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let _guard = enter(self.subsystem);
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        let _guard = enter(self.subsystem);
+        instrument(self.subsystem, self.inner.call(request))
+    }
+}
+
+/// Returns the allocation counts of every subsystem since the service started.
+pub fn snapshot() -> Vec<(Subsystem, AllocationCounts)> {
+    Subsystem::ALL
+        .iter()
+        .map(|subsystem| (*subsystem, ALLOCATOR.counts(*subsystem)))
+        .collect()
+}
+
+#[cfg(test)]
+mod alloc_profile_tests {
+    use super::*;
+
+    fn counts_of(subsystem: Subsystem) -> AllocationCounts {
+        ALLOCATOR.counts(subsystem)
+    }
+
+    #[test]
+    fn enter_attributes_allocations_test() {
+        let before = counts_of(Subsystem::Connector);
+
+        {
+            let _guard = enter(Subsystem::Connector);
+            let allocated = vec![0u8; 128];
+            drop(allocated);
+        }
+
+        let after = counts_of(Subsystem::Connector);
+        assert!(after.allocations > before.allocations);
+        assert!(after.allocated_bytes >= before.allocated_bytes + 128);
+        assert!(after.deallocations > before.deallocations);
+        assert_eq!(Subsystem::Other, current_subsystem());
+    }
+
+    #[tokio::test]
+    async fn instrument_attributes_allocations_test() {
+        let before = counts_of(Subsystem::TopicManager);
+
+        let len = instrument(Subsystem::TopicManager, async {
+            assert_eq!(Subsystem::TopicManager, current_subsystem());
+            vec![0u8; 64].len()
+        })
+        .await;
+
+        assert_eq!(64, len);
+        assert!(counts_of(Subsystem::TopicManager).allocations > before.allocations);
+        assert_eq!(Subsystem::Other, current_subsystem());
+    }
+
+    #[test]
+    fn snapshot_test() {
+        let actual: Vec<Subsystem> = snapshot().into_iter().map(|(s, _)| s).collect();
+        assert_eq!(Subsystem::ALL.to_vec(), actual);
+    }
+}
@@ -0,0 +1,504 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Module containing the circuit breakers around publisher callbacks.
+//!
+//! A publisher that stops handling its management callbacks would otherwise be called for every
+//! topic action, each call waiting out the callback timeout. After a number of consecutive failed
+//! callbacks to a management uri its breaker opens, and the uri is not called until a cool-down
+//! period has passed. A single trial callback is then let through, which closes the breaker if it
+//! succeeds. A trial that does not report back within the callback timeout counts as failed, and
+//! another trial is let through after the next cool-down period. If the callbacks keep failing for long enough, the topics of the publisher can be
+//! deleted.
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use common::action_casing::ActionCasing;
+use common::shared_state::SharedState;
+use strum_macros::Display;
+use tokio::sync::{oneshot, Semaphore};
+
+use crate::{
+    callback_security::CallbackSecurity,
+    grpc_compression::GrpcCompressionKind,
+    load_config::PublisherCallbackSettings,
+    publisher_metrics::PublisherMetrics,
+    topic_manager::{ActionHysteresis, PUBLISHER_CALLBACK_TIMEOUT},
+};
+
+/// State of the circuit breaker of a management uri.
+#[derive(Clone, Copy, Debug, Display, Eq, PartialEq)]
+pub enum BreakerState {
+    /// Callbacks are made.
+    #[strum(serialize = "CLOSED")]
+    Closed,
+    /// Callbacks are skipped until the cool-down period has passed.
+    #[strum(serialize = "OPEN")]
+    Open,
+    /// The cool-down period has passed and a trial callback is in progress.
+    #[strum(serialize = "HALF_OPEN")]
+    HalfOpen,
+}
+
+/// What to do with a callback to a management uri.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BreakerDecision {
+    /// Make the callback.
+    Call,
+    /// Skip the callback, as the breaker of the uri is open.
+    Skip,
+    /// Skip the callback and delete the topics of the uri, as its callbacks have been failing for
+    /// longer than the escalation period.
+    Escalate,
+}
+
+/// Status of the circuit breaker of a management uri, reported to operators.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BreakerStatus {
+    /// The management uri the breaker guards.
+    pub uri: String,
+    /// The state of the breaker.
+    pub state: BreakerState,
+    /// The number of consecutive failed callbacks to the uri.
+    pub consecutive_failures: u32,
+    /// How long callbacks to the uri have been failing.
+    pub failing_for: Duration,
+}
+
+/// Failure history of a single management uri.
+#[derive(Clone, Debug)]
+struct Breaker {
+    /// The number of consecutive failed callbacks.
+    consecutive_failures: u32,
+    /// When the first of the consecutive failures happened.
+    failing_since: Instant,
+    /// When the breaker last opened or let a trial callback through.
+    opened_at: Option<Instant>,
+    /// Whether a trial callback is in progress.
+    trial: bool,
+}
+
+/// The circuit breakers of the management uris that had failed callbacks.
+#[derive(Clone, Debug)]
+pub struct CallbackBreakers {
+    /// Number of consecutive failed callbacks after which a breaker opens.
+    failure_threshold: u32,
+    /// How long an open breaker skips callbacks before letting a trial callback through.
+    cooldown: Duration,
+    /// How long callbacks must fail before the topics of the uri are deleted. Topics are never
+    /// deleted if not set.
+    escalate_after: Option<Duration>,
+    /// The breakers of each management uri with failed callbacks.
+    breakers: HashMap<String, Breaker>,
+}
+
+impl CallbackBreakers {
+    /// Creates new CallbackBreakers.
+    ///
+    /// # Arguments
+    ///
+    /// * `failure_threshold` - Number of consecutive failed callbacks after which a breaker opens.
+    /// * `cooldown` - How long an open breaker skips callbacks before letting a trial through.
+    /// * `escalate_after` - How long callbacks must fail before the topics of the uri are deleted.
+    pub fn new(
+        failure_threshold: u32,
+        cooldown: Duration,
+        escalate_after: Option<Duration>,
+    ) -> Self {
+        CallbackBreakers {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            escalate_after,
+            breakers: HashMap::new(),
+        }
+    }
+
+    /// Returns whether the callbacks of a breaker have been failing for longer than the
+    /// escalation period.
+    ///
+    /// # Arguments
+    ///
+    /// * `breaker` - The breaker to check.
+    /// * `now` - The current time.
+    fn is_escalated(&self, breaker: &Breaker, now: Instant) -> bool {
+        self.escalate_after.is_some_and(|escalate_after| {
+            now.saturating_duration_since(breaker.failing_since) >= escalate_after
+        })
+    }
+
+    /// Returns whether a trial callback of a breaker is in progress. A trial that has not reported
+    /// back within the callback timeout was cancelled before it could, and is no longer waited on.
+    ///
+    /// # Arguments
+    ///
+    /// * `breaker` - The breaker to check.
+    /// * `now` - The current time.
+    fn trial_in_progress(breaker: &Breaker, now: Instant) -> bool {
+        breaker.trial
+            && breaker.opened_at.is_some_and(|opened_at| {
+                now.saturating_duration_since(opened_at) < PUBLISHER_CALLBACK_TIMEOUT
+            })
+    }
+
+    /// Decides what to do with a callback to a management uri.
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - The management uri to call.
+    /// * `now` - The current time.
+    pub fn check(&mut self, uri: &str, now: Instant) -> BreakerDecision {
+        let Some(breaker) = self.breakers.get(uri) else {
+            return BreakerDecision::Call;
+        };
+
+        if self.is_escalated(breaker, now) {
+            // The topics of the uri are deleted, so its history is no longer needed.
+            self.breakers.remove(uri);
+            return BreakerDecision::Escalate;
+        }
+
+        let cooldown = self.cooldown;
+        let breaker = self.breakers.get_mut(uri).unwrap();
+        match breaker.opened_at {
+            None => BreakerDecision::Call,
+            Some(_) if Self::trial_in_progress(breaker, now) => BreakerDecision::Skip,
+            Some(opened_at) if now.saturating_duration_since(opened_at) >= cooldown => {
+                breaker.trial = true;
+                breaker.opened_at = Some(now);
+                BreakerDecision::Call
+            }
+            Some(_) => BreakerDecision::Skip,
+        }
+    }
+
+    /// Records a successful callback to a management uri, closing its breaker. Returns whether
+    /// the breaker had opened, in which case callbacks to the uri may have been skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - The management uri that was called.
+    pub fn record_success(&mut self, uri: &str) -> bool {
+        self.breakers
+            .remove(uri)
+            .is_some_and(|breaker| breaker.opened_at.is_some())
+    }
+
+    /// Records a failed callback to a management uri, opening its breaker once the failure
+    /// threshold is reached. Returns whether the topics of the uri should be deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - The management uri that was called.
+    /// * `now` - The time the callback failed.
+    pub fn record_failure(&mut self, uri: &str, now: Instant) -> bool {
+        let failure_threshold = self.failure_threshold;
+        let breaker = self
+            .breakers
+            .entry(uri.to_string())
+            .or_insert_with(|| Breaker {
+                consecutive_failures: 0,
+                failing_since: now,
+                opened_at: None,
+                trial: false,
+            });
+
+        breaker.consecutive_failures += 1;
+        breaker.trial = false;
+        if breaker.consecutive_failures >= failure_threshold {
+            breaker.opened_at = Some(now);
+        }
+
+        let breaker = breaker.clone();
+        if self.is_escalated(&breaker, now) {
+            self.breakers.remove(uri);
+            return true;
+        }
+
+        false
+    }
+
+    /// Returns the status of the breaker of each management uri with failed callbacks, sorted by
+    /// uri.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time.
+    pub fn status(&self, now: Instant) -> Vec<BreakerStatus> {
+        let mut status: Vec<BreakerStatus> = self
+            .breakers
+            .iter()
+            .map(|(uri, breaker)| BreakerStatus {
+                uri: uri.clone(),
+                state: match breaker.opened_at {
+                    None => BreakerState::Closed,
+                    Some(_) if Self::trial_in_progress(breaker, now) => BreakerState::HalfOpen,
+                    Some(_) => BreakerState::Open,
+                },
+                consecutive_failures: breaker.consecutive_failures,
+                failing_for: now.saturating_duration_since(breaker.failing_since),
+            })
+            .collect();
+        status.sort_by(|a, b| a.uri.cmp(&b.uri));
+
+        status
+    }
+}
+
+/// A place in the queue of callbacks to a management uri.
+#[derive(Debug)]
+pub struct CallbackTurn {
+    /// Completes once the previous callback to the uri is done.
+    previous: Option<oneshot::Receiver<()>>,
+    /// Tells the next callback to the uri that this one is done, once dropped.
+    _done: oneshot::Sender<()>,
+}
+
+impl CallbackTurn {
+    /// Waits until the previous callback to the management uri is done.
+    pub async fn wait(&mut self) {
+        if let Some(previous) = self.previous.as_mut() {
+            // The sender is dropped once the previous callback is done, whether or not it
+            // succeeded.
+            let _ = previous.await;
+            self.previous = None;
+        }
+    }
+}
+
+/// Queues of the callbacks to each management uri, so that a publisher receives the actions on
+/// its topics in the order they were decided, such as a START before the STOP that follows it.
+#[derive(Debug, Default)]
+pub struct CallbackQueues {
+    /// Completes once the last queued callback to each uri is done.
+    last_callbacks: HashMap<String, oneshot::Receiver<()>>,
+}
+
+impl CallbackQueues {
+    /// Queues a callback to a management uri, returning its turn. The callback is made once
+    /// [`CallbackTurn::wait`] completes, and the next callback waits until the turn is dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - The management uri that is called.
+    pub fn enqueue(&mut self, uri: &str) -> CallbackTurn {
+        let (done, last_callback) = oneshot::channel();
+        let previous = self.last_callbacks.insert(uri.to_string(), last_callback);
+
+        CallbackTurn {
+            previous,
+            _done: done,
+        }
+    }
+}
+
+/// Limits on the callbacks made to publishers, shared by the tasks that make them.
+#[derive(Clone, Debug)]
+pub struct PublisherCallbacks {
+    /// The circuit breakers of the management uris.
+    pub breakers: Arc<SharedState<CallbackBreakers>>,
+    /// Permits for the callbacks in progress.
+    pub permits: Arc<Semaphore>,
+    /// The order of the callbacks to each management uri.
+    pub queues: Arc<SharedState<CallbackQueues>>,
+    /// Casing of the actions sent to publishers.
+    pub action_casing: ActionCasing,
+    /// The statistics of the callbacks made to each publisher.
+    pub metrics: Arc<SharedState<PublisherMetrics>>,
+    /// The hysteresis applied to the STOP actions sent to publishers.
+    pub hysteresis: ActionHysteresis,
+    /// Whether the topics of a publisher are reconciled once its callbacks recover.
+    pub reconcile_on_recovery: bool,
+    /// Encoding used to compress the callbacks. Callbacks are not compressed if not set.
+    pub compression: Option<GrpcCompressionKind>,
+    /// TLS and authentication of the connections to the callback servers of publishers.
+    pub security: CallbackSecurity,
+}
+
+impl PublisherCallbacks {
+    /// Creates new PublisherCallbacks from the configuration settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The publisher callback settings.
+    pub fn new(settings: &PublisherCallbackSettings) -> Self {
+        PublisherCallbacks {
+            breakers: Arc::new(SharedState::new(CallbackBreakers::new(
+                settings.failure_threshold,
+                Duration::from_secs(settings.cooldown_secs),
+                settings.delete_after_secs.map(Duration::from_secs),
+            ))),
+            permits: Arc::new(Semaphore::new(settings.max_concurrent_callbacks.max(1))),
+            queues: Arc::new(SharedState::new(CallbackQueues::default())),
+            action_casing: settings.action_casing,
+            metrics: Arc::new(SharedState::new(PublisherMetrics::new(
+                settings.metrics_max_publishers,
+            ))),
+            hysteresis: ActionHysteresis::new(
+                Duration::from_secs(settings.min_active_secs),
+                Duration::from_secs(settings.stop_debounce_secs),
+            ),
+            reconcile_on_recovery: settings.reconcile_on_recovery,
+            compression: settings.compression,
+            security: CallbackSecurity::default(),
+        }
+    }
+
+    /// Sets the TLS and authentication of the connections to the callback servers of publishers.
+    ///
+    /// # Arguments
+    ///
+    /// * `security` - The connection options of the callbacks.
+    pub fn with_security(mut self, security: CallbackSecurity) -> Self {
+        self.security = security;
+        self
+    }
+}
+
+impl Default for PublisherCallbacks {
+    fn default() -> Self {
+        Self::new(&PublisherCallbackSettings::default())
+    }
+}
+
+#[cfg(test)]
+mod callback_breaker_tests {
+    use super::*;
+
+    const URI: &str = "http://0.0.0.0:50061";
+
+    #[test]
+    fn breaker_opens_after_threshold_test() {
+        let now = Instant::now();
+        let mut breakers = CallbackBreakers::new(2, Duration::from_secs(30), None);
+
+        assert_eq!(BreakerDecision::Call, breakers.check(URI, now));
+        assert!(!breakers.record_failure(URI, now));
+        assert_eq!(BreakerDecision::Call, breakers.check(URI, now));
+        assert_eq!(BreakerState::Closed, breakers.status(now)[0].state);
+
+        assert!(!breakers.record_failure(URI, now));
+        assert_eq!(BreakerDecision::Skip, breakers.check(URI, now));
+        assert_eq!(BreakerState::Open, breakers.status(now)[0].state);
+        assert_eq!(2, breakers.status(now)[0].consecutive_failures);
+
+        // Other uris are unaffected.
+        let other_uri = "http://0.0.0.0:50062";
+        assert_eq!(BreakerDecision::Call, breakers.check(other_uri, now));
+
+        // A success before the breaker opened is not a recovery.
+        assert!(!breakers.record_failure(other_uri, now));
+        assert!(!breakers.record_success(other_uri));
+    }
+
+    #[test]
+    fn breaker_lets_trial_through_after_cooldown_test() {
+        let now = Instant::now();
+        let mut breakers = CallbackBreakers::new(1, Duration::from_secs(30), None);
+        breakers.record_failure(URI, now);
+
+        let after_cooldown = now + Duration::from_secs(30);
+        assert_eq!(BreakerDecision::Call, breakers.check(URI, after_cooldown));
+        assert_eq!(
+            BreakerState::HalfOpen,
+            breakers.status(after_cooldown)[0].state
+        );
+
+        // Only a single trial callback is let through.
+        assert_eq!(BreakerDecision::Skip, breakers.check(URI, after_cooldown));
+
+        // A failed trial opens the breaker for another cool-down period.
+        breakers.record_failure(URI, after_cooldown);
+        assert_eq!(BreakerDecision::Skip, breakers.check(URI, after_cooldown));
+
+        // A successful trial closes the breaker.
+        let after_second_cooldown = after_cooldown + Duration::from_secs(30);
+        assert_eq!(
+            BreakerDecision::Call,
+            breakers.check(URI, after_second_cooldown)
+        );
+        assert!(breakers.record_success(URI));
+        assert!(breakers.status(after_second_cooldown).is_empty());
+        assert!(!breakers.record_success(URI));
+    }
+
+    #[test]
+    fn breaker_expires_unreported_trial_test() {
+        let now = Instant::now();
+        let mut breakers = CallbackBreakers::new(1, Duration::from_secs(30), None);
+        breakers.record_failure(URI, now);
+
+        let after_cooldown = now + Duration::from_secs(30);
+        assert_eq!(BreakerDecision::Call, breakers.check(URI, after_cooldown));
+
+        // The trial never reports back, as if its task was cancelled.
+        let before_timeout = after_cooldown + PUBLISHER_CALLBACK_TIMEOUT - Duration::from_millis(1);
+        assert_eq!(BreakerDecision::Skip, breakers.check(URI, before_timeout));
+
+        // The expired trial counts as failed, so the breaker stays open for another cool-down.
+        let after_timeout = after_cooldown + PUBLISHER_CALLBACK_TIMEOUT;
+        assert_eq!(BreakerState::Open, breakers.status(after_timeout)[0].state);
+        assert_eq!(BreakerDecision::Skip, breakers.check(URI, after_timeout));
+
+        let after_second_cooldown = after_cooldown + Duration::from_secs(30);
+        assert_eq!(
+            BreakerDecision::Call,
+            breakers.check(URI, after_second_cooldown)
+        );
+        assert_eq!(
+            BreakerState::HalfOpen,
+            breakers.status(after_second_cooldown)[0].state
+        );
+    }
+
+    #[test]
+    fn breaker_escalates_after_prolonged_failure_test() {
+        let now = Instant::now();
+        let mut breakers =
+            CallbackBreakers::new(1, Duration::from_secs(30), Some(Duration::from_secs(60)));
+
+        assert!(!breakers.record_failure(URI, now));
+        assert_eq!(
+            BreakerDecision::Skip,
+            breakers.check(URI, now + Duration::from_secs(10))
+        );
+        assert_eq!(
+            BreakerDecision::Escalate,
+            breakers.check(URI, now + Duration::from_secs(60))
+        );
+
+        // The history of the uri is cleared once escalated.
+        assert_eq!(
+            BreakerDecision::Call,
+            breakers.check(URI, now + Duration::from_secs(60))
+        );
+        assert!(!breakers.record_failure(URI, now + Duration::from_secs(60)));
+        assert!(breakers.record_failure(URI, now + Duration::from_secs(120)));
+    }
+
+    #[tokio::test]
+    async fn callback_queues_order_callbacks_per_uri_test() {
+        let mut queues = CallbackQueues::default();
+        let first = queues.enqueue(URI);
+        let mut second = queues.enqueue(URI);
+        let mut other = queues.enqueue("http://0.0.0.0:50062");
+
+        // Callbacks to other uris don't wait.
+        other.wait().await;
+
+        // The second callback waits until the first is done.
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), second.wait())
+                .await
+                .is_err()
+        );
+        drop(first);
+        tokio::time::timeout(Duration::from_secs(1), second.wait())
+            .await
+            .unwrap();
+    }
+}
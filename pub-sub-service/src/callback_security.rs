@@ -0,0 +1,309 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! TLS and authentication of the management callbacks made to publishers.
+//!
+//! Publishers whose callback servers require TLS or authentication are called with the options of
+//! the `security` setting of the publisher callbacks, or with the options configured for the
+//! publisher in `publisher_security`. Certificates are read once on startup, so that a missing
+//! file is reported before any callback is made.
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use proto::publisher::v1::publisher_callback_client::PublisherCallbackClient;
+#[cfg(feature = "tls")]
+use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+use tonic::{
+    metadata::{Ascii, MetadataValue},
+    transport::{Channel, Endpoint},
+    Request,
+};
+
+use crate::{
+    error::AgemoError,
+    load_config::{CallbackSecuritySettings, PublisherCallbackSettings},
+};
+
+/// Connection options of the callbacks made to a publisher.
+#[derive(Clone, Default)]
+pub struct CallbackClientConfig {
+    /// TLS configuration of the connection to the callback server.
+    #[cfg(feature = "tls")]
+    tls: Option<ClientTlsConfig>,
+    /// Value of the `authorization` metadata sent with every callback.
+    authorization: Option<MetadataValue<Ascii>>,
+}
+
+impl CallbackClientConfig {
+    /// Creates a new CallbackClientConfig, reading the certificates it refers to.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The TLS and authentication options.
+    pub fn from_settings(settings: &CallbackSecuritySettings) -> Result<Self, AgemoError> {
+        if settings.client_cert.is_some() != settings.client_key.is_some() {
+            return Err(AgemoError::InvalidConfiguration(
+                "'client_cert' and 'client_key' of publisher callbacks must be set together"
+                    .to_string(),
+            ));
+        }
+
+        let authorization = settings
+            .bearer_token
+            .as_ref()
+            .map(|token| format!("Bearer {token}").parse::<MetadataValue<Ascii>>())
+            .transpose()
+            .map_err(|_| {
+                AgemoError::InvalidConfiguration(
+                    "the bearer token of publisher callbacks is not a valid metadata value"
+                        .to_string(),
+                )
+            })?
+            .map(|mut authorization| {
+                // Keeps the token out of the logs of the HTTP/2 stack.
+                authorization.set_sensitive(true);
+                authorization
+            });
+
+        #[cfg(feature = "tls")]
+        let tls = settings
+            .uses_tls()
+            .then(|| tls_config(settings))
+            .transpose()?;
+
+        #[cfg(not(feature = "tls"))]
+        if settings.uses_tls() {
+            return Err(AgemoError::InvalidConfiguration(
+                "the TLS options of publisher callbacks require the service to be built with the \
+                 'tls' feature"
+                    .to_string(),
+            ));
+        }
+
+        Ok(CallbackClientConfig {
+            #[cfg(feature = "tls")]
+            tls,
+            authorization,
+        })
+    }
+
+    /// Adds the authorization of the callbacks to a request, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The callback request.
+    pub fn authorize<T>(&self, request: &mut Request<T>) {
+        if let Some(authorization) = &self.authorization {
+            request
+                .metadata_mut()
+                .insert("authorization", authorization.clone());
+        }
+    }
+}
+
+impl fmt::Debug for CallbackClientConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The authorization is redacted so the bearer token can't leak through logs.
+        let mut debug = f.debug_struct("CallbackClientConfig");
+        #[cfg(feature = "tls")]
+        debug.field("tls", &self.tls);
+        debug
+            .field(
+                "authorization",
+                &self.authorization.as_ref().map(|_| "<redacted>"),
+            )
+            .finish()
+    }
+}
+
+/// Returns the TLS configuration of the given options.
+///
+/// # Arguments
+///
+/// * `settings` - The TLS and authentication options.
+#[cfg(feature = "tls")]
+fn tls_config(settings: &CallbackSecuritySettings) -> Result<ClientTlsConfig, AgemoError> {
+    let mut tls = ClientTlsConfig::new();
+
+    if let Some(ca_bundle) = &settings.ca_bundle {
+        tls = tls.ca_certificate(Certificate::from_pem(read_pem(ca_bundle)?));
+    }
+    if let (Some(client_cert), Some(client_key)) = (&settings.client_cert, &settings.client_key) {
+        tls = tls.identity(Identity::from_pem(
+            read_pem(client_cert)?,
+            read_pem(client_key)?,
+        ));
+    }
+    if let Some(domain_name) = &settings.domain_name {
+        tls = tls.domain_name(domain_name.clone());
+    }
+
+    Ok(tls)
+}
+
+/// Reads a PEM file.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file.
+#[cfg(feature = "tls")]
+fn read_pem(path: &str) -> Result<Vec<u8>, AgemoError> {
+    std::fs::read(path)
+        .map_err(|err| AgemoError::InvalidConfiguration(format!("unable to read '{path}': {err}")))
+}
+
+/// Connection options of the callbacks made to every publisher.
+#[derive(Clone, Debug, Default)]
+pub struct CallbackSecurity {
+    /// The options of publishers without options of their own.
+    default: Option<Arc<CallbackClientConfig>>,
+    /// The options of specific publishers, by publisher id.
+    publishers: HashMap<String, Arc<CallbackClientConfig>>,
+}
+
+impl CallbackSecurity {
+    /// Creates a new CallbackSecurity from the configuration settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The publisher callback settings.
+    pub fn from_settings(settings: &PublisherCallbackSettings) -> Result<Self, AgemoError> {
+        let default = settings
+            .security
+            .as_ref()
+            .map(|security| CallbackClientConfig::from_settings(security).map(Arc::new))
+            .transpose()?;
+
+        let publishers = settings
+            .publisher_security
+            .iter()
+            .map(|(publisher_id, security)| {
+                Ok((
+                    publisher_id.clone(),
+                    Arc::new(CallbackClientConfig::from_settings(security)?),
+                ))
+            })
+            .collect::<Result<_, AgemoError>>()?;
+
+        Ok(CallbackSecurity {
+            default,
+            publishers,
+        })
+    }
+
+    /// Returns the connection options of the callbacks made to a publisher, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `publisher_id` - The id of the publisher.
+    pub fn for_publisher(&self, publisher_id: &str) -> Option<Arc<CallbackClientConfig>> {
+        self.publishers
+            .get(publisher_id)
+            .or(self.default.as_ref())
+            .cloned()
+    }
+}
+
+/// Connects to the callback server of a publisher.
+///
+/// # Arguments
+///
+/// * `uri` - The management uri of the publisher.
+/// * `config` - The connection options of the callbacks made to the publisher, if any.
+pub async fn connect(
+    uri: String,
+    config: Option<&CallbackClientConfig>,
+) -> Result<PublisherCallbackClient<Channel>, Box<dyn std::error::Error + Send + Sync>> {
+    let endpoint = Endpoint::from_shared(uri)?;
+
+    #[cfg(feature = "tls")]
+    let endpoint = match config.and_then(|config| config.tls.clone()) {
+        Some(tls) => endpoint.tls_config(tls)?,
+        None => endpoint,
+    };
+    #[cfg(not(feature = "tls"))]
+    let _ = config;
+
+    Ok(PublisherCallbackClient::new(endpoint.connect().await?))
+}
+
+#[cfg(test)]
+mod callback_security_tests {
+    use super::*;
+
+    fn bearer_settings(token: &str) -> CallbackSecuritySettings {
+        CallbackSecuritySettings {
+            bearer_token: Some(token.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn authorize_adds_bearer_token_test() {
+        let config = CallbackClientConfig::from_settings(&bearer_settings("secret")).unwrap();
+        let mut request = Request::new(());
+
+        config.authorize(&mut request);
+
+        assert_eq!(
+            "Bearer secret",
+            request.metadata().get("authorization").unwrap()
+        );
+    }
+
+    #[test]
+    fn debug_redacts_bearer_token_test() {
+        let settings = bearer_settings("secret");
+        let config = CallbackClientConfig::from_settings(&settings).unwrap();
+
+        assert!(!format!("{settings:?}").contains("secret"));
+        assert!(!format!("{config:?}").contains("secret"));
+        assert!(config.authorization.unwrap().is_sensitive());
+    }
+
+    #[test]
+    fn from_settings_rejects_invalid_settings_test() {
+        let result = CallbackClientConfig::from_settings(&bearer_settings("line\nbreak"));
+        assert!(matches!(result, Err(AgemoError::InvalidConfiguration(_))));
+
+        let result = CallbackClientConfig::from_settings(&CallbackSecuritySettings {
+            client_cert: Some("client.pem".to_string()),
+            ..Default::default()
+        });
+        assert!(matches!(result, Err(AgemoError::InvalidConfiguration(_))));
+
+        let result = CallbackClientConfig::from_settings(&CallbackSecuritySettings {
+            ca_bundle: Some("/nonexistent/ca.pem".to_string()),
+            ..Default::default()
+        });
+        assert!(matches!(result, Err(AgemoError::InvalidConfiguration(_))));
+    }
+
+    #[test]
+    fn for_publisher_prefers_publisher_settings_test() {
+        let settings = PublisherCallbackSettings {
+            security: Some(bearer_settings("shared")),
+            publisher_security: HashMap::from([(
+                "pub_1".to_string(),
+                bearer_settings("pub_1_token"),
+            )]),
+            ..Default::default()
+        };
+        let security = CallbackSecurity::from_settings(&settings).unwrap();
+
+        for (publisher_id, expected) in
+            [("pub_1", "Bearer pub_1_token"), ("pub_2", "Bearer shared")]
+        {
+            let mut request = Request::new(());
+            security
+                .for_publisher(publisher_id)
+                .unwrap()
+                .authorize(&mut request);
+
+            assert_eq!(expected, request.metadata().get("authorization").unwrap());
+        }
+
+        assert!(CallbackSecurity::default().for_publisher("pub_1").is_none());
+    }
+}
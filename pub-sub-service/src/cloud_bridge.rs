@@ -0,0 +1,344 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Bridging of tagged topics to cloud telemetry ingestion.
+//!
+//! Publishers tag the topics whose data should reach the cloud, `cloud_telemetry` by default. The
+//! bridge follows the lifecycle events of the topics: when a tagged topic is created it provisions
+//! a route for the topic, and when the topic is deleted it tears the route down again. Routes are
+//! provisioned over HTTP, in one of two forms:
+//!
+//! * Eclipse Hono - The topic is registered as a device of the configured tenant through the
+//!   device registry management API.
+//! * Azure IoT Hub - A message route for the topic is created through a bridge that manages the
+//!   routes of the hub, delivering to the configured endpoint.
+
+use std::{collections::HashSet, sync::Arc};
+
+use common::shared_state::SharedState;
+use hyper::{client::HttpConnector, header, Body, Client, Method, Request, StatusCode};
+use log::{info, warn};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    error::AgemoError,
+    load_config::{CloudBridgeKind, CloudBridgeSettings},
+    topic_manager::{ActiveTopicsMap, TopicEventKind, TopicEventSender},
+};
+
+/// Prefix of the names of the routes of bridged topics.
+const ROUTE_NAME_PREFIX: &str = "agemo-";
+
+/// Maximum length of the name of a route, as limited by IoT Hub.
+const MAX_ROUTE_NAME_LEN: usize = 64;
+
+/// An HTTP request to the API that provisions the bridged topics.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BridgeRequest {
+    /// The HTTP method.
+    pub method: Method,
+    /// Path of the request, relative to the endpoint of the API.
+    pub path: String,
+    /// JSON body of the request, if any.
+    pub body: Option<serde_json::Value>,
+}
+
+/// Returns the name of the route of a topic, which is the topic with the characters not allowed
+/// in device ids and route names replaced. Topics too long for a route name keep their end, where
+/// generated topics have their unique id.
+///
+/// # Arguments
+///
+/// * `topic` - The name of the topic.
+pub fn route_name(topic: &str) -> String {
+    let name: Vec<char> = topic
+        .chars()
+        .map(|c| match c {
+            '/' => '.',
+            c if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' => c,
+            _ => '_',
+        })
+        .collect();
+    let max_len = MAX_ROUTE_NAME_LEN - ROUTE_NAME_PREFIX.len();
+    let start = name.len().saturating_sub(max_len);
+
+    format!(
+        "{ROUTE_NAME_PREFIX}{}",
+        name[start..].iter().collect::<String>()
+    )
+}
+
+/// Returns the request that provisions the route of a topic.
+///
+/// # Arguments
+///
+/// * `settings` - The bridge settings.
+/// * `topic` - The name of the topic.
+/// * `publisher_id` - The id of the publisher of the topic.
+pub fn provision_request(
+    settings: &CloudBridgeSettings,
+    topic: &str,
+    publisher_id: &str,
+) -> BridgeRequest {
+    let name = route_name(topic);
+
+    match settings.kind {
+        CloudBridgeKind::Hono => BridgeRequest {
+            method: Method::POST,
+            path: hono_device_path(settings, &name),
+            body: Some(serde_json::json!({
+                "enabled": true,
+                "ext": { "agemo-topic": topic, "agemo-publisher": publisher_id },
+            })),
+        },
+        CloudBridgeKind::IotHub => BridgeRequest {
+            method: Method::PUT,
+            path: format!("/routes/{name}"),
+            body: Some(serde_json::json!({
+                "name": name,
+                "source": "DeviceMessages",
+                "condition": format!("agemo_topic = '{}'", topic.replace('\'', "''")),
+                "endpointNames": [settings.route_endpoint.as_deref().unwrap_or_default()],
+                "isEnabled": true,
+            })),
+        },
+    }
+}
+
+/// Returns the request that tears down the route of a topic.
+///
+/// # Arguments
+///
+/// * `settings` - The bridge settings.
+/// * `topic` - The name of the topic.
+pub fn teardown_request(settings: &CloudBridgeSettings, topic: &str) -> BridgeRequest {
+    let name = route_name(topic);
+
+    let path = match settings.kind {
+        CloudBridgeKind::Hono => hono_device_path(settings, &name),
+        CloudBridgeKind::IotHub => format!("/routes/{name}"),
+    };
+
+    BridgeRequest {
+        method: Method::DELETE,
+        path,
+        body: None,
+    }
+}
+
+/// Returns the path of a device in the Hono device registry management API.
+///
+/// # Arguments
+///
+/// * `settings` - The bridge settings.
+/// * `device_id` - The id of the device.
+fn hono_device_path(settings: &CloudBridgeSettings, device_id: &str) -> String {
+    format!(
+        "/v1/devices/{}/{device_id}",
+        settings.tenant.as_deref().unwrap_or_default()
+    )
+}
+
+/// Sends a request to the API that provisions the bridged topics. A route that is already gone
+/// counts as torn down.
+///
+/// # Arguments
+///
+/// * `client` - The HTTP client.
+/// * `settings` - The bridge settings.
+/// * `bridge_request` - The request to send.
+async fn send(
+    client: &Client<HttpConnector>,
+    settings: &CloudBridgeSettings,
+    bridge_request: BridgeRequest,
+) -> Result<(), AgemoError> {
+    let uri = format!(
+        "{}{}",
+        settings.endpoint.trim_end_matches('/'),
+        bridge_request.path
+    );
+
+    let mut builder = Request::builder()
+        .method(bridge_request.method.clone())
+        .uri(&uri)
+        .header(header::CONTENT_TYPE, "application/json");
+    if let Some(token) = &settings.auth_token {
+        builder = builder.header(header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    let request = builder
+        .body(
+            bridge_request
+                .body
+                .map_or_else(Body::empty, |body| Body::from(body.to_string())),
+        )
+        .map_err(|err| AgemoError::InvalidConfiguration(format!("invalid uri '{uri}': {err}")))?;
+
+    let response = client
+        .request(request)
+        .await
+        .map_err(|err| AgemoError::Connection(format!("unable to reach '{uri}': {err}")))?;
+
+    let status = response.status();
+    let gone = bridge_request.method == Method::DELETE && status == StatusCode::NOT_FOUND;
+    if status.is_success() || gone {
+        Ok(())
+    } else {
+        Err(AgemoError::Connection(format!(
+            "{} '{uri}' returned {status}",
+            bridge_request.method
+        )))
+    }
+}
+
+/// Provisions a route for every topic created with the bridge tag, and tears it down once the
+/// topic is deleted, until the service stops.
+///
+/// # Arguments
+///
+/// * `settings` - The bridge settings.
+/// * `topic_events` - Channel that topic lifecycle events are broadcast on.
+/// * `active_topics` - Handle that points to the shared active topics map.
+pub async fn run_cloud_bridge(
+    settings: CloudBridgeSettings,
+    topic_events: TopicEventSender,
+    active_topics: Arc<SharedState<ActiveTopicsMap>>,
+) {
+    let mut receiver = topic_events.subscribe();
+    let client = Client::new();
+    // Topics with a provisioned route, so that only their deletion tears a route down.
+    let mut bridged: HashSet<String> = HashSet::new();
+
+    loop {
+        let event = match receiver.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                warn!(
+                    "Cloud bridge missed {missed} topic events, routes of topics created or \
+                     deleted in the meantime may be missing or left over."
+                );
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+
+        match event.kind {
+            TopicEventKind::Create => {
+                let tagged = active_topics
+                    .lock()
+                    .get(&event.topic)
+                    .is_some_and(|metadata| metadata.has_tag(&settings.tag));
+                if !tagged {
+                    continue;
+                }
+
+                let request = provision_request(&settings, &event.topic, &event.publisher_id);
+                match send(&client, &settings, request).await {
+                    Ok(()) => {
+                        info!("Bridged topic '{}' to the cloud.", event.topic);
+                        bridged.insert(event.topic);
+                    }
+                    Err(err) => warn!("Unable to bridge topic '{}': {err}", event.topic),
+                }
+            }
+            TopicEventKind::Delete if bridged.remove(&event.topic) => {
+                let request = teardown_request(&settings, &event.topic);
+                match send(&client, &settings, request).await {
+                    Ok(()) => info!("Removed the cloud route of topic '{}'.", event.topic),
+                    Err(err) => warn!(
+                        "Unable to remove the cloud route of topic '{}': {err}",
+                        event.topic
+                    ),
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod cloud_bridge_tests {
+    use super::*;
+
+    fn bridge_settings(kind: CloudBridgeKind) -> CloudBridgeSettings {
+        CloudBridgeSettings {
+            kind,
+            endpoint: "http://0.0.0.0:28080".to_string(),
+            tenant: Some("vehicles".to_string()),
+            route_endpoint: Some("telemetry".to_string()),
+            auth_token: None,
+            tag: "cloud_telemetry".to_string(),
+        }
+    }
+
+    #[test]
+    fn route_name_test() {
+        assert_eq!(
+            "agemo-vehicle.signals.topic-0",
+            route_name("vehicle/signals/topic-0")
+        );
+        assert_eq!("agemo-a_b", route_name("a b"));
+
+        let long_topic = format!("{}/5f0b1c2d", "x".repeat(80));
+        let name = route_name(&long_topic);
+        assert_eq!(MAX_ROUTE_NAME_LEN, name.len());
+        assert!(name.starts_with(ROUTE_NAME_PREFIX));
+        assert!(name.ends_with(".5f0b1c2d"));
+    }
+
+    #[test]
+    fn hono_requests_test() {
+        let settings = bridge_settings(CloudBridgeKind::Hono);
+
+        let request = provision_request(&settings, "vehicle/topic-0", "pub_1");
+        assert_eq!(Method::POST, request.method);
+        assert_eq!("/v1/devices/vehicles/agemo-vehicle.topic-0", request.path);
+        assert_eq!(
+            Some(serde_json::json!({
+                "enabled": true,
+                "ext": { "agemo-topic": "vehicle/topic-0", "agemo-publisher": "pub_1" },
+            })),
+            request.body
+        );
+
+        let request = teardown_request(&settings, "vehicle/topic-0");
+        assert_eq!(Method::DELETE, request.method);
+        assert_eq!("/v1/devices/vehicles/agemo-vehicle.topic-0", request.path);
+        assert_eq!(None, request.body);
+    }
+
+    #[test]
+    fn iot_hub_requests_test() {
+        let settings = bridge_settings(CloudBridgeKind::IotHub);
+
+        let request = provision_request(&settings, "vehicle/topic-0", "pub_1");
+        assert_eq!(Method::PUT, request.method);
+        assert_eq!("/routes/agemo-vehicle.topic-0", request.path);
+        assert_eq!(
+            Some(serde_json::json!({
+                "name": "agemo-vehicle.topic-0",
+                "source": "DeviceMessages",
+                "condition": "agemo_topic = 'vehicle/topic-0'",
+                "endpointNames": ["telemetry"],
+                "isEnabled": true,
+            })),
+            request.body
+        );
+
+        let request = teardown_request(&settings, "vehicle/topic-0");
+        assert_eq!(Method::DELETE, request.method);
+        assert_eq!("/routes/agemo-vehicle.topic-0", request.path);
+    }
+
+    #[test]
+    fn settings_debug_redacts_auth_token_test() {
+        let settings = CloudBridgeSettings {
+            auth_token: Some("secret".to_string()),
+            ..bridge_settings(CloudBridgeKind::Hono)
+        };
+
+        let output = format!("{settings:?}");
+        assert!(output.contains("http://0.0.0.0:28080"));
+        assert!(!output.contains("secret"));
+    }
+}
@@ -0,0 +1,278 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Registry of the broker connectors built into the service.
+//!
+//! Each connector is registered under the name of its messaging kind, and the `messaging_kind`
+//! setting selects the connector the service runs at startup, so that the broker can be swapped
+//! without recompiling the service. If `messaging_kind` is not set, a `memory://` messaging uri
+//! selects the loopback connector, and otherwise the connector is selected from the connector
+//! settings, with the Mosquitto connector as the default.
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    sync::{mpsc, Arc},
+};
+
+use common::shared_state::SharedState;
+use log::warn;
+
+use crate::{
+    connectors::loopback_connector::{self, LoopbackConnector},
+    error::AgemoError,
+    load_config::{ConnectorKind, ConnectorSettings},
+    pubsub_connector::{ChannelFailurePolicy, ConnectorConfig, MonitorMessage, PubSubConnector},
+    supervisor::TaskExit,
+    topic_manager::OrphanReconciliation,
+};
+
+/// Messaging kind of the built in Mosquitto connector, used if no other connector is selected.
+pub const MOSQUITTO: &str = "mosquitto";
+/// Messaging kind of the built in Kafka connector.
+pub const KAFKA: &str = "kafka";
+/// Messaging kind of the built in DDS connector.
+pub const DDS: &str = "dds";
+/// Messaging kind of the connectors loaded from a shared library.
+pub const LIBRARY: &str = "library";
+/// Messaging kind of the built in loopback connector, which runs a message bus inside the service.
+pub const LOOPBACK: &str = "loopback";
+
+/// Everything a broker connector needs to run.
+pub struct ConnectorRun {
+    /// Configuration used to connect to the messaging broker.
+    pub connector_config: ConnectorConfig,
+    /// Channel used to forward updates from the broker to the topic manager.
+    pub monitor_sender: mpsc::Sender<MonitorMessage>,
+    /// Channel used to receive topic deletion and registration requests.
+    pub connector_receiver: Arc<SharedState<mpsc::Receiver<MonitorMessage>>>,
+    /// Message sent to subscribers of a deleted topic.
+    pub topic_deletion_message: Arc<str>,
+    /// Whether publishing on deleted topics should be released.
+    pub enforce_publisher_permissions: bool,
+    /// The topics known to the service, compared against the broker topics on reconciliation.
+    pub orphan_reconciliation: OrphanReconciliation,
+    /// What the connector does once the request channel is closed.
+    pub channel_failure: ChannelFailurePolicy,
+}
+
+/// Future of a running broker connector, which completes once the connector ends.
+pub type ConnectorFuture = Pin<Box<dyn Future<Output = TaskExit> + Send>>;
+
+/// Starts a broker connector.
+pub type ConnectorFactory = fn(ConnectorRun) -> ConnectorFuture;
+
+/// Starts the broker connector `C`. Used as the factory of a built in connector.
+///
+/// # Arguments
+///
+/// * `run` - Everything the connector needs to run.
+fn run<C>(run: ConnectorRun) -> ConnectorFuture
+where
+    C: PubSubConnector<Config = ConnectorConfig> + Send + Sync + 'static,
+{
+    Box::pin(crate::run_connector::<C>(run))
+}
+
+/// Registry of the broker connectors, by messaging kind.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectorRegistry {
+    /// The factory of each connector, by messaging kind.
+    factories: BTreeMap<&'static str, ConnectorFactory>,
+}
+
+impl ConnectorRegistry {
+    /// Creates a new ConnectorRegistry without any connector.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new ConnectorRegistry with the connectors the service was built with.
+    pub fn built_in() -> Self {
+        let registry = Self::new().with_connector(LOOPBACK, run::<LoopbackConnector>);
+
+        #[cfg(feature = "mqtt")]
+        let registry = registry.with_connector(
+            MOSQUITTO,
+            run::<crate::connectors::mosquitto_connector::MqttFiveBrokerConnector>,
+        );
+        #[cfg(feature = "kafka")]
+        let registry = registry.with_connector(
+            KAFKA,
+            run::<crate::connectors::kafka_connector::KafkaConnector>,
+        );
+        #[cfg(feature = "dds")]
+        let registry =
+            registry.with_connector(DDS, run::<crate::connectors::dds_connector::DdsConnector>);
+        #[cfg(feature = "dynamic-connectors")]
+        let registry = registry.with_connector(
+            LIBRARY,
+            run::<crate::connectors::dynamic_connector::DynamicConnector>,
+        );
+
+        registry
+    }
+
+    /// Registers a connector, replacing any connector registered under the same messaging kind.
+    ///
+    /// # Arguments
+    ///
+    /// * `messaging_kind` - The messaging kind that selects the connector.
+    /// * `factory` - Starts the connector.
+    pub fn with_connector(
+        mut self,
+        messaging_kind: &'static str,
+        factory: ConnectorFactory,
+    ) -> Self {
+        self.factories.insert(messaging_kind, factory);
+        self
+    }
+
+    /// Returns the messaging kinds of the registered connectors, sorted by name.
+    pub fn messaging_kinds(&self) -> Vec<&'static str> {
+        self.factories.keys().copied().collect()
+    }
+
+    /// Returns the factory of the connector of a messaging kind, if it is registered.
+    ///
+    /// # Arguments
+    ///
+    /// * `messaging_kind` - The messaging kind of the connector.
+    pub fn get(&self, messaging_kind: &str) -> Option<ConnectorFactory> {
+        self.factories.get(messaging_kind).copied()
+    }
+
+    /// Returns the messaging kind of the connector to run.
+    ///
+    /// A set `messaging_kind` must name a registered connector. Otherwise a `memory://` messaging
+    /// uri selects the loopback connector, and a connector library takes precedence over the
+    /// connector of the connector kind, Kafka or DDS, which takes precedence over the Mosquitto
+    /// connector, among the registered connectors.
+    ///
+    /// # Arguments
+    ///
+    /// * `messaging_kind` - The `messaging_kind` setting, if set.
+    /// * `messaging_uri` - The uri of the messaging broker.
+    /// * `connector_settings` - The settings of the broker connector.
+    pub fn select(
+        &self,
+        messaging_kind: Option<&str>,
+        messaging_uri: &str,
+        connector_settings: &ConnectorSettings,
+    ) -> Result<String, AgemoError> {
+        if let Some(messaging_kind) = messaging_kind {
+            if self.get(messaging_kind).is_none() {
+                return Err(AgemoError::InvalidConfiguration(format!(
+                    "unknown 'messaging_kind' '{messaging_kind}', the service was built with {:?}",
+                    self.messaging_kinds()
+                )));
+            }
+
+            return Ok(messaging_kind.to_string());
+        }
+
+        if loopback_connector::is_memory_uri(messaging_uri) && self.get(LOOPBACK).is_some() {
+            return Ok(LOOPBACK.to_string());
+        }
+
+        if connector_settings.library.is_some() {
+            if self.get(LIBRARY).is_some() {
+                return Ok(LIBRARY.to_string());
+            }
+            warn!("A connector library is set, but the service was built without the 'dynamic-connectors' feature.");
+        }
+
+        if connector_settings.kind == ConnectorKind::Kafka {
+            if self.get(KAFKA).is_some() {
+                return Ok(KAFKA.to_string());
+            }
+            warn!("'connector.kind' is 'kafka', but the service was built without the 'kafka' feature.");
+        }
+
+        if connector_settings.kind == ConnectorKind::Dds {
+            if self.get(DDS).is_some() {
+                return Ok(DDS.to_string());
+            }
+            warn!(
+                "'connector.kind' is 'dds', but the service was built without the 'dds' feature."
+            );
+        }
+
+        Ok(MOSQUITTO.to_string())
+    }
+}
+
+#[cfg(test)]
+mod connector_registry_tests {
+    use super::*;
+
+    const MQTT_URI: &str = "mqtt://0.0.0.0:1883";
+
+    fn finished(_run: ConnectorRun) -> ConnectorFuture {
+        Box::pin(async { TaskExit::Finished })
+    }
+
+    fn connector_settings(kind: ConnectorKind) -> ConnectorSettings {
+        serde_json::from_value(serde_json::json!({ "kind": kind })).unwrap()
+    }
+
+    #[test]
+    fn select_messaging_kind_test() {
+        let registry = ConnectorRegistry::new()
+            .with_connector(MOSQUITTO, finished)
+            .with_connector(KAFKA, finished)
+            .with_connector(DDS, finished)
+            .with_connector(LOOPBACK, finished);
+
+        assert_eq!(
+            vec![DDS, KAFKA, LOOPBACK, MOSQUITTO],
+            registry.messaging_kinds()
+        );
+
+        // The configured messaging kind selects the connector.
+        let mqtt_settings = connector_settings(ConnectorKind::Mqtt);
+        assert_eq!(
+            DDS,
+            registry
+                .select(Some(DDS), MQTT_URI, &mqtt_settings)
+                .unwrap()
+        );
+        assert!(matches!(
+            registry.select(Some("nats"), MQTT_URI, &mqtt_settings),
+            Err(AgemoError::InvalidConfiguration(_))
+        ));
+
+        // A memory uri selects the loopback connector.
+        assert_eq!(
+            LOOPBACK,
+            registry.select(None, "memory://", &mqtt_settings).unwrap()
+        );
+
+        // Otherwise the connector follows the connector settings, defaulting to Mosquitto.
+        assert_eq!(
+            MOSQUITTO,
+            registry.select(None, MQTT_URI, &mqtt_settings).unwrap()
+        );
+        assert_eq!(
+            DDS,
+            registry
+                .select(None, MQTT_URI, &connector_settings(ConnectorKind::Dds))
+                .unwrap()
+        );
+        assert_eq!(
+            KAFKA,
+            registry
+                .select(None, MQTT_URI, &connector_settings(ConnectorKind::Kafka))
+                .unwrap()
+        );
+        assert_eq!(
+            MOSQUITTO,
+            ConnectorRegistry::new()
+                .with_connector(MOSQUITTO, finished)
+                .select(None, MQTT_URI, &connector_settings(ConnectorKind::Dds))
+                .unwrap()
+        );
+    }
+}
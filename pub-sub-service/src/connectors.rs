@@ -2,7 +2,19 @@
 // Licensed under the MIT license.
 // SPDX-License-Identifier: MIT
 
-//! Connectors to external services, like Chariott or Mosquitto MQTT broker.
+//! Connectors to external services, like Chariott, Mosquitto MQTT broker, a Kafka cluster, a DDS
+//! domain or the message bus inside the service.
 
+#[cfg(feature = "chariott")]
 pub mod chariott_connector;
+#[cfg(feature = "dds")]
+pub mod dds_connector;
+#[cfg(feature = "dynamic-connectors")]
+pub mod dynamic_connector;
+#[cfg(feature = "kafka")]
+pub mod kafka_connector;
+pub mod loopback_connector;
+#[cfg(test)]
+pub mod mock_connector;
+#[cfg(feature = "mqtt")]
 pub mod mosquitto_connector;
@@ -4,6 +4,7 @@
 
 //! Set of helper functions for interacting with Chariott through the generated gRPC client.
 
+use common::uri;
 use log::{info, warn};
 use std::{thread, time::Duration};
 use tonic::{transport::Channel, Code, Request, Status};
@@ -13,6 +14,13 @@ use proto::{
     service_registry::v1::{RegisterRequest, ServiceMetadata},
 };
 
+#[cfg(feature = "chariott-intents")]
+use proto::intent_brokering::runtime::v1::{
+    intent_brokering_service_client::IntentBrokeringServiceClient, intent_registration::Intent,
+    intent_service_registration::ExecutionLocality, AnnounceRequest, IntentRegistration,
+    IntentServiceRegistration, RegisterRequest, RegistrationState,
+};
+
 type ChariottClient = ServiceRegistryClient<Channel>;
 
 /// Object that contains the necessary information for identifying a specific service.
@@ -79,7 +87,7 @@ pub async fn register_with_chariott(
     communication_kind: &str,
     communication_reference: &str,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let provider_uri_str = format!("http://{provider_authority}"); // Devskim: ignore DS137138
+    let provider_uri_str = uri::authority_uri(provider_authority);
 
     let service_metadata = ServiceMetadata {
         namespace: service_identifier.namespace,
@@ -100,3 +108,64 @@ pub async fn register_with_chariott(
 
     Ok(())
 }
+
+/// Announces the service to Chariott's intent broker periodically, and registers the intents the
+/// service provides whenever the intent broker does not know the service yet, such as after the
+/// intent broker restarted. Only returns if the intent broker can't be reached at first.
+///
+/// # Arguments
+///
+/// * `intent_broker_uri` - The uri of Chariott's intent broker.
+/// * `provider_authority` - The authority where the provider service hosts the gRPC server.
+/// * `service_identifier` - Information needed for uniquely identifying the service in Chariott.
+/// * `announce_interval` - The interval between announcements of the service.
+#[cfg(feature = "chariott-intents")]
+pub async fn announce_intents_to_chariott(
+    intent_broker_uri: &str,
+    provider_authority: &str,
+    service_identifier: ServiceIdentifier,
+    announce_interval: Duration,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let service = IntentServiceRegistration {
+        name: service_identifier.name,
+        url: uri::authority_uri(provider_authority),
+        version: service_identifier.version,
+        locality: ExecutionLocality::Local as i32,
+    };
+    let intents: Vec<IntentRegistration> = [Intent::Discover, Intent::Read, Intent::Subscribe]
+        .into_iter()
+        .map(|intent| IntentRegistration {
+            namespace: service_identifier.namespace.clone(),
+            intent: intent as i32,
+        })
+        .collect();
+
+    let mut client = IntentBrokeringServiceClient::connect(intent_broker_uri.to_owned()).await?;
+
+    loop {
+        let announce_request = Request::new(AnnounceRequest {
+            service: Some(service.clone()),
+        });
+
+        match client.announce(announce_request).await {
+            Ok(response)
+                if response.into_inner().registration_state
+                    == RegistrationState::Announced as i32 =>
+            {
+                let register_request = Request::new(RegisterRequest {
+                    service: Some(service.clone()),
+                    intents: intents.clone(),
+                });
+
+                match client.register(register_request).await {
+                    Ok(_) => info!("Registered intents with Chariott's intent broker."),
+                    Err(status) => warn!("Unable to register intents with Chariott: {status}"),
+                }
+            }
+            Ok(_) => {}
+            Err(status) => warn!("Unable to announce the service to Chariott: {status}"),
+        }
+
+        tokio::time::sleep(announce_interval).await;
+    }
+}
@@ -0,0 +1,457 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Implements the [`PubSubConnector`][`crate::pubsub_connector`] trait for a
+//! [DDS](https://www.omg.org/spec/DDS/) domain, using
+//! [RustDDS](https://github.com/jhelovuo/RustDDS).
+//!
+//! DDS has no broker: publishers and subscribers find each other through the discovery protocol
+//! of the domain. The connector joins the domain as a participant and follows discovery to learn
+//! which participants read the managed topics, reporting each discovered reader as a subscriber.
+//! Managed topics are DDS topics of the [`MESSAGE_TYPE_NAME`] type, and topics of any other type
+//! are not tracked.
+
+use agemo_connector::{MonitorMessage, PubSubAction};
+use async_trait::async_trait;
+use common::shared_state::SharedState;
+use futures::StreamExt;
+use log::{info, warn};
+use rustdds::{
+    no_key::{DataReader, DataWriter},
+    policy, CDRDeserializerAdapter, CDRSerializerAdapter, DomainParticipant,
+    DomainParticipantStatusEvent, Publisher, QosPolicies, QosPolicyBuilder, Subscriber, Topic,
+    TopicKind,
+};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::AgemoError,
+    pubsub_connector::{self, ConnectorConfig, PubSubConnector},
+};
+
+/// Name of the DDS type of managed topics, the IDL struct `agemo::Message` with a single
+/// `sequence<octet> payload` member.
+pub const MESSAGE_TYPE_NAME: &str = "agemo::Message";
+/// Payload of the probe messages published on self-test topics.
+const PROBE_PAYLOAD: &[u8] = b"agemo self-test probe";
+/// Interval at which the probe reader is polled for the probe message.
+const PROBE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Time to wait for subscribers to acknowledge the deletion message of a topic before its writer
+/// is dropped.
+const DELETION_ACK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Message on a managed topic.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct DdsMessage {
+    /// The data of the message.
+    pub payload: Vec<u8>,
+}
+
+/// Readers of managed topics found through discovery, each tracked as a subscriber of its topic
+/// with the participant it belongs to as the subscriber's id.
+#[derive(Debug, Default)]
+struct DiscoveredReaders {
+    /// The topic and participant of each reader, by reader.
+    readers: HashMap<String, (String, String)>,
+}
+
+impl DiscoveredReaders {
+    /// Tracks a discovered reader. Returns the subscribe message of the reader if it reads a
+    /// managed topic and was not already tracked.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The GUID of the reader.
+    /// * `participant` - The GUID prefix of the participant of the reader.
+    /// * `topic` - The DDS topic the reader reads.
+    /// * `type_name` - The DDS type of the topic.
+    fn reader_detected(
+        &mut self,
+        reader: String,
+        participant: String,
+        topic: &str,
+        type_name: &str,
+    ) -> Option<MonitorMessage> {
+        if type_name != MESSAGE_TYPE_NAME || self.readers.contains_key(&reader) {
+            return None;
+        }
+
+        info!("Added a subscriber to topic '{topic}'.");
+        self.readers
+            .insert(reader, (topic.to_string(), participant.clone()));
+
+        Some(MonitorMessage {
+            context: topic.to_string(),
+            action: PubSubAction::Subscribe,
+            client_id: Some(participant),
+            identity: None,
+        })
+    }
+
+    /// Stops tracking a reader that left the domain. Returns the unsubscribe message of the
+    /// reader if it was tracked.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The GUID of the reader.
+    fn reader_lost(&mut self, reader: &str) -> Option<MonitorMessage> {
+        let (topic, participant) = self.readers.remove(reader)?;
+        info!("Removed a subscriber from topic '{topic}'.");
+
+        Some(MonitorMessage {
+            context: topic,
+            action: PubSubAction::Unsubscribe,
+            client_id: Some(participant),
+            identity: None,
+        })
+    }
+
+    /// Stops tracking the readers of a participant that left the domain, whose readers are not
+    /// reported lost one by one. Returns the unsubscribe messages of the readers.
+    ///
+    /// # Arguments
+    ///
+    /// * `participant` - The GUID prefix of the participant.
+    fn participant_lost(&mut self, participant: &str) -> Vec<MonitorMessage> {
+        let readers: Vec<String> = self
+            .readers
+            .iter()
+            .filter(|(_, (_, reader_participant))| reader_participant == participant)
+            .map(|(reader, _)| reader.clone())
+            .collect();
+
+        readers
+            .iter()
+            .filter_map(|reader| self.reader_lost(reader))
+            .collect()
+    }
+}
+
+/// DDS connector, joining a domain as a participant.
+pub struct DdsConnector {
+    participant: DomainParticipant,
+    publisher: Publisher,
+    subscriber: Subscriber,
+    qos: QosPolicies,
+    /// Managed topics created on the participant, by name.
+    topics: SharedState<HashMap<String, Topic>>,
+}
+
+impl DdsConnector {
+    /// Returns the quality of service of the writers and readers of managed topics. Messages are
+    /// delivered reliably, and the last message of a writer is kept for readers that match it
+    /// late, so that subscribers still receive a deletion message published as they join.
+    fn message_qos() -> QosPolicies {
+        QosPolicyBuilder::new()
+            .reliability(policy::Reliability::Reliable {
+                max_blocking_time: rustdds::Duration::from_millis(100),
+            })
+            .durability(policy::Durability::TransientLocal)
+            .history(policy::History::KeepLast { depth: 1 })
+            .build()
+    }
+
+    /// Returns the DDS topic of a managed topic, creating it on the participant if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the topic.
+    fn topic(&self, name: &str) -> Result<Topic, AgemoError> {
+        let mut topics = self.topics.lock();
+        if let Some(topic) = topics.get(name) {
+            return Ok(topic.clone());
+        }
+
+        let topic = self
+            .participant
+            .create_topic(
+                name.to_string(),
+                MESSAGE_TYPE_NAME.to_string(),
+                &self.qos,
+                TopicKind::NoKey,
+            )
+            .map_err(|err| {
+                AgemoError::Broker(format!("unable to create topic '{name}': {err:?}"))
+            })?;
+        topics.insert(name.to_string(), topic.clone());
+
+        Ok(topic)
+    }
+
+    /// Creates a writer on a managed topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the topic.
+    fn writer(&self, name: &str) -> Result<DataWriter<DdsMessage>, AgemoError> {
+        self.publisher
+            .create_datawriter_no_key::<DdsMessage, CDRSerializerAdapter<DdsMessage>>(
+                &self.topic(name)?,
+                None,
+            )
+            .map_err(|err| {
+                AgemoError::Broker(format!("unable to create a writer on '{name}': {err:?}"))
+            })
+    }
+
+    /// Creates a reader on a managed topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the topic.
+    fn reader(&self, name: &str) -> Result<DataReader<DdsMessage>, AgemoError> {
+        self.subscriber
+            .create_datareader_no_key::<DdsMessage, CDRDeserializerAdapter<DdsMessage>>(
+                &self.topic(name)?,
+                None,
+            )
+            .map_err(|err| {
+                AgemoError::Broker(format!("unable to create a reader on '{name}': {err:?}"))
+            })
+    }
+}
+
+#[async_trait]
+impl PubSubConnector for DdsConnector {
+    type Config = ConnectorConfig;
+    type Error = AgemoError;
+
+    async fn connect(config: ConnectorConfig) -> Result<Self, AgemoError> {
+        let domain_id = config.dds.unwrap_or_default().domain_id;
+        let qos = Self::message_qos();
+
+        let participant = DomainParticipant::new(domain_id).map_err(|err| {
+            AgemoError::Connection(format!("unable to join DDS domain {domain_id}: {err:?}"))
+        })?;
+        let publisher = participant.create_publisher(&qos).map_err(|err| {
+            AgemoError::Connection(format!("unable to create a DDS publisher: {err:?}"))
+        })?;
+        let subscriber = participant.create_subscriber(&qos).map_err(|err| {
+            AgemoError::Connection(format!("unable to create a DDS subscriber: {err:?}"))
+        })?;
+
+        info!(
+            "Joined DDS domain {domain_id} as participant {:?}",
+            participant.guid().prefix
+        );
+
+        Ok(DdsConnector {
+            participant,
+            publisher,
+            subscriber,
+            qos,
+            topics: SharedState::new(HashMap::new()),
+        })
+    }
+
+    async fn monitor_topics(
+        &mut self,
+        cb_channel: mpsc::Sender<MonitorMessage>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let status = self.participant.status_listener();
+        let own_prefix = self.participant.guid().prefix;
+
+        // Discovery reports the readers of other participants as they are matched and lost.
+        tokio::spawn(async move {
+            let mut events = status.as_async_status_stream();
+            let mut discovered = DiscoveredReaders::default();
+
+            while let Some(event) = events.next().await {
+                let messages = match event {
+                    DomainParticipantStatusEvent::ReaderDetected { reader }
+                        if reader.guid.prefix != own_prefix =>
+                    {
+                        discovered
+                            .reader_detected(
+                                format!("{:?}", reader.guid),
+                                format!("{:?}", reader.guid.prefix),
+                                &reader.topic_name,
+                                &reader.type_name,
+                            )
+                            .into_iter()
+                            .collect()
+                    }
+                    DomainParticipantStatusEvent::ReaderLost { guid, .. } => discovered
+                        .reader_lost(&format!("{guid:?}"))
+                        .into_iter()
+                        .collect(),
+                    DomainParticipantStatusEvent::ParticipantLost { id, .. } => {
+                        discovered.participant_lost(&format!("{id:?}"))
+                    }
+                    _ => Vec::new(),
+                };
+
+                // The service no longer receives updates once it shuts down.
+                let delivered = messages.into_iter().all(|message| {
+                    pubsub_connector::update_topic_information(message, cb_channel.clone()).is_ok()
+                });
+                if !delivered {
+                    break;
+                }
+            }
+
+            warn!("DDS discovery stopped, subscribers are no longer tracked.");
+        });
+
+        Ok(())
+    }
+
+    async fn delete_topic(
+        &self,
+        topic: String,
+        deletion_msg: Arc<str>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let writer = self.writer(&topic)?;
+        writer
+            .write(
+                DdsMessage {
+                    payload: deletion_msg.as_bytes().to_vec(),
+                },
+                None,
+            )
+            .map_err(|err| {
+                AgemoError::Broker(format!("unable to publish to '{topic}': {err:?}"))
+            })?;
+
+        // The deletion message is only kept while the writer lives, so the writer waits for the
+        // subscribers to receive it before it is dropped.
+        let acknowledged = tokio::task::spawn_blocking(move || {
+            writer
+                .wait_for_acknowledgments(rustdds::Duration::from_millis(
+                    DELETION_ACK_TIMEOUT.as_millis() as i64,
+                ))
+                .unwrap_or(false)
+        })
+        .await?;
+        if !acknowledged {
+            warn!("Not every subscriber of topic '{topic}' acknowledged the deletion message.");
+        }
+
+        self.topics.lock().remove(&topic);
+
+        Ok(())
+    }
+
+    async fn probe_topic(
+        &self,
+        topic: String,
+        timeout: Duration,
+    ) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+        let mut reader = self.reader(&topic)?;
+        let writer = self.writer(&topic)?;
+
+        let start = Instant::now();
+        writer
+            .write(
+                DdsMessage {
+                    payload: PROBE_PAYLOAD.to_vec(),
+                },
+                None,
+            )
+            .map_err(|err| {
+                AgemoError::Broker(format!("unable to publish to '{topic}': {err:?}"))
+            })?;
+
+        let delivery = async {
+            loop {
+                let sample = reader.take_next_sample().map_err(|err| {
+                    AgemoError::Broker(format!("unable to read from '{topic}': {err:?}"))
+                })?;
+                if sample.is_some_and(|sample| sample.value().payload == PROBE_PAYLOAD) {
+                    return Ok::<Duration, AgemoError>(start.elapsed());
+                }
+
+                tokio::time::sleep(PROBE_POLL_INTERVAL).await;
+            }
+        };
+
+        let result = tokio::time::timeout(timeout, delivery).await.map_err(|_| {
+            AgemoError::Broker(format!(
+                "probe message on '{topic}' was not delivered within {timeout:?}"
+            ))
+        })?;
+
+        self.topics.lock().remove(&topic);
+
+        Ok(result?)
+    }
+}
+
+#[cfg(test)]
+mod dds_connector_tests {
+    use super::*;
+
+    #[test]
+    fn discovered_readers_track_managed_topics_test() {
+        let mut discovered = DiscoveredReaders::default();
+
+        let message = discovered
+            .reader_detected(
+                "reader_1".to_string(),
+                "participant_1".to_string(),
+                "vehicle/speed",
+                MESSAGE_TYPE_NAME,
+            )
+            .unwrap();
+        assert_eq!("vehicle/speed", message.context);
+        assert_eq!(PubSubAction::Subscribe, message.action);
+        assert_eq!(Some("participant_1".to_string()), message.client_id);
+
+        // Readers are only reported once, and readers of other types are not tracked.
+        assert!(discovered
+            .reader_detected(
+                "reader_1".to_string(),
+                "participant_1".to_string(),
+                "vehicle/speed",
+                MESSAGE_TYPE_NAME,
+            )
+            .is_none());
+        assert!(discovered
+            .reader_detected(
+                "reader_2".to_string(),
+                "participant_1".to_string(),
+                "rt/chatter",
+                "std_msgs::msg::dds_::String_",
+            )
+            .is_none());
+
+        let message = discovered.reader_lost("reader_1").unwrap();
+        assert_eq!("vehicle/speed", message.context);
+        assert_eq!(PubSubAction::Unsubscribe, message.action);
+        assert!(discovered.reader_lost("reader_1").is_none());
+    }
+
+    #[test]
+    fn discovered_readers_participant_lost_test() {
+        let mut discovered = DiscoveredReaders::default();
+
+        for (reader, participant, topic) in [
+            ("reader_1", "participant_1", "topic_1"),
+            ("reader_2", "participant_1", "topic_2"),
+            ("reader_3", "participant_2", "topic_1"),
+        ] {
+            discovered.reader_detected(
+                reader.to_string(),
+                participant.to_string(),
+                topic,
+                MESSAGE_TYPE_NAME,
+            );
+        }
+
+        let mut topics: Vec<String> = discovered
+            .participant_lost("participant_1")
+            .into_iter()
+            .map(|message| message.context)
+            .collect();
+        topics.sort();
+
+        assert_eq!(vec!["topic_1", "topic_2"], topics);
+        assert_eq!(1, discovered.readers.len());
+    }
+}
@@ -0,0 +1,521 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Implements the [`PubSubConnector`][`crate::pubsub_connector`] trait on top of a broker
+//! connector loaded from a shared library.
+//!
+//! This allows broker integrations to be shipped separately from the service, without forking it.
+//! The library is declared with `connector_library` in the service configuration and must export
+//! the following functions with the C ABI, declared in `pub-sub-service/include/agemo_connector.h`:
+//!
+//! - `uint32_t agemo_connector_abi_version(void)`: Returns the ABI version the library implements,
+//!   which must match [`ABI_VERSION`].
+//! - `void* agemo_connector_create(const char* config_json)`: Connects to the messaging broker and
+//!   returns a handle to the connector, or null on failure.
+//! - `int32_t agemo_connector_monitor(void* connector, agemo_monitor_cb cb, void* ctx)`: Starts
+//!   monitoring the broker, calling `cb` with `ctx` for every update until the connector is
+//!   destroyed. Returns zero on success.
+//! - `int32_t agemo_connector_delete_topic(void* connector, const char* topic, const char* msg)`:
+//!   Deletes a topic, sending the deletion message to its subscribers. Returns zero on success.
+//! - `void agemo_connector_destroy(void* connector)`: Disconnects from the broker. No callbacks
+//!   may be made once it returns.
+//!
+//! Libraries can also export `agemo_connector_authorize_publisher` and
+//! `agemo_connector_revoke_publisher` to support publisher permissions. The connector handle may
+//! be used from multiple threads at once.
+
+use agemo_connector::MonitorMessage;
+use async_trait::async_trait;
+use libloading::Library;
+use log::warn;
+use std::{
+    ffi::{c_char, c_void, CStr, CString},
+    panic,
+    str::FromStr,
+    sync::{mpsc, Arc},
+};
+
+use crate::{
+    error::AgemoError,
+    load_config::ConnectorLibrarySettings,
+    pubsub_connector::{BrokerConnection, ConnectorConfig, PubSubAction, PubSubConnector},
+    subscriber_acl::SubscriberIdentification,
+};
+
+/// Version of the C ABI that connector libraries must implement.
+pub const ABI_VERSION: u32 = 1;
+
+/// Callback that a connector library calls with each update from the messaging broker. The action
+/// is the string form of an [`agemo_connector::PubSubAction`], or `BROKERCONNECTED` and
+/// `BROKERDISCONNECTED` to report the connection to the broker, and the client id may be null.
+pub type MonitorCallback = extern "C" fn(
+    ctx: *mut c_void,
+    action: *const c_char,
+    context: *const c_char,
+    client_id: *const c_char,
+);
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type CreateFn = unsafe extern "C" fn(config_json: *const c_char) -> *mut c_void;
+type MonitorFn =
+    unsafe extern "C" fn(connector: *mut c_void, cb: MonitorCallback, ctx: *mut c_void) -> i32;
+type DeleteTopicFn = unsafe extern "C" fn(
+    connector: *mut c_void,
+    topic: *const c_char,
+    deletion_msg: *const c_char,
+) -> i32;
+type AuthorizePublisherFn = unsafe extern "C" fn(
+    connector: *mut c_void,
+    topic: *const c_char,
+    publisher_id: *const c_char,
+) -> i32;
+type RevokePublisherFn = unsafe extern "C" fn(connector: *mut c_void, topic: *const c_char) -> i32;
+type DestroyFn = unsafe extern "C" fn(connector: *mut c_void);
+
+/// The functions exported by a connector library.
+struct ConnectorVtable {
+    monitor: MonitorFn,
+    delete_topic: DeleteTopicFn,
+    authorize_publisher: Option<AuthorizePublisherFn>,
+    revoke_publisher: Option<RevokePublisherFn>,
+    destroy: DestroyFn,
+}
+
+/// The context handed to the connector library along with the monitor callback.
+struct MonitorContext {
+    /// The channel updates are forwarded to.
+    sender: mpsc::Sender<MonitorMessage>,
+    /// How the library identifies subscribers.
+    subscriber_identification: SubscriberIdentification,
+    /// Where changes to the connection to the broker are reported.
+    broker_connection: BrokerConnection,
+}
+
+/// Connector that forwards every request to a connector loaded from a shared library.
+pub struct DynamicConnector {
+    handle: *mut c_void,
+    vtable: ConnectorVtable,
+    subscriber_identification: SubscriberIdentification,
+    broker_connection: BrokerConnection,
+    monitor_ctx: Option<*mut MonitorContext>,
+    // Kept last so that the library is unloaded after the connector is destroyed.
+    _library: Library,
+}
+
+// SAFETY: Connector libraries are required to support using the connector handle from multiple
+// threads, and the monitor context is only freed once the connector is destroyed.
+unsafe impl Send for DynamicConnector {}
+// SAFETY: See above.
+unsafe impl Sync for DynamicConnector {}
+
+impl DynamicConnector {
+    /// Loads a connector library and the functions it exports.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the shared library to load.
+    fn load(path: &str) -> Result<(Library, ConnectorVtable, CreateFn), AgemoError> {
+        let load_err = |err: libloading::Error| {
+            AgemoError::InvalidConfiguration(format!(
+                "unable to load connector library '{path}': {err}"
+            ))
+        };
+
+        // SAFETY: Loading a library runs its initialization code, the library is trusted as it is
+        // declared in the service configuration.
+        let library = unsafe { Library::new(path) }.map_err(load_err)?;
+
+        // SAFETY: The symbols are only used while the library is loaded, since the library is
+        // kept alive by the connector, and the ABI of each function is defined above.
+        unsafe {
+            let abi_version = *library
+                .get::<AbiVersionFn>(b"agemo_connector_abi_version\0")
+                .map_err(load_err)?;
+
+            if abi_version() != ABI_VERSION {
+                return Err(AgemoError::InvalidConfiguration(format!(
+                    "connector library '{path}' implements ABI version {}, expected {ABI_VERSION}",
+                    abi_version()
+                )));
+            }
+
+            let create = *library
+                .get::<CreateFn>(b"agemo_connector_create\0")
+                .map_err(load_err)?;
+            let vtable = ConnectorVtable {
+                monitor: *library
+                    .get::<MonitorFn>(b"agemo_connector_monitor\0")
+                    .map_err(load_err)?,
+                delete_topic: *library
+                    .get::<DeleteTopicFn>(b"agemo_connector_delete_topic\0")
+                    .map_err(load_err)?,
+                authorize_publisher: library
+                    .get::<AuthorizePublisherFn>(b"agemo_connector_authorize_publisher\0")
+                    .ok()
+                    .map(|symbol| *symbol),
+                revoke_publisher: library
+                    .get::<RevokePublisherFn>(b"agemo_connector_revoke_publisher\0")
+                    .ok()
+                    .map(|symbol| *symbol),
+                destroy: *library
+                    .get::<DestroyFn>(b"agemo_connector_destroy\0")
+                    .map_err(load_err)?,
+            };
+
+            Ok((library, vtable, create))
+        }
+    }
+
+    /// Maps a status code returned by the connector library to a result.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - Description of the operation that returned the status code.
+    /// * `code` - The status code, where zero indicates success.
+    fn check_status(
+        operation: &str,
+        code: i32,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if code == 0 {
+            Ok(())
+        } else {
+            Err(Box::new(AgemoError::Broker(format!(
+                "connector library failed to {operation} with code {code}"
+            ))))
+        }
+    }
+}
+
+/// Converts a string to a C string, failing if it contains a nul character.
+///
+/// # Arguments
+///
+/// * `value` - The string to convert.
+fn to_c_string(value: &str) -> Result<CString, AgemoError> {
+    CString::new(value)
+        .map_err(|_| AgemoError::Broker(format!("'{value}' contains a nul character")))
+}
+
+/// Renders the configuration passed to a connector library as a JSON object.
+///
+/// # Arguments
+///
+/// * `config` - The connector configuration.
+/// * `library_settings` - The settings of the connector library.
+fn library_config_json(
+    config: &ConnectorConfig,
+    library_settings: &ConnectorLibrarySettings,
+) -> String {
+    let credentials = config.credentials.clone().unwrap_or_default();
+
+    serde_json::json!({
+        "client_id": config.client_id,
+        "uri": config.uri,
+        "username": credentials.username,
+        "secret": credentials.secret(),
+        "disconnect_topic": config.disconnect_topic,
+        "ignored_client_ids": config.ignored_client_ids,
+        "subscriber_identification": config.subscriber_identification,
+        "options": library_settings.options,
+    })
+    .to_string()
+}
+
+/// Callback handed to the connector library, forwarding each update to the topic manager.
+/// Libraries report a single id for the subscriber of an update, which is its identity if
+/// subscribers are identified by their identity.
+///
+/// # Arguments
+///
+/// * `ctx` - The [`MonitorContext`] of the connector.
+/// * `action` - The string form of the [`PubSubAction`] that happened.
+/// * `context` - The topic the update is for.
+/// * `client_id` - The client that caused the update, or null if unknown.
+extern "C" fn forward_monitor_message(
+    ctx: *mut c_void,
+    action: *const c_char,
+    context: *const c_char,
+    client_id: *const c_char,
+) {
+    if ctx.is_null() || action.is_null() || context.is_null() {
+        return;
+    }
+
+    // Panics must not unwind into the connector library.
+    let _ = panic::catch_unwind(|| {
+        // SAFETY: The library passes the context it was given and nul terminated strings that
+        // stay valid for the duration of the call.
+        let (monitor_ctx, action, context, client_id) = unsafe {
+            (
+                &*(ctx as *const MonitorContext),
+                CStr::from_ptr(action).to_string_lossy(),
+                CStr::from_ptr(context).to_string_lossy(),
+                (!client_id.is_null())
+                    .then(|| CStr::from_ptr(client_id).to_string_lossy().into_owned()),
+            )
+        };
+
+        let Ok(action) = agemo_connector::PubSubAction::from_str(&action) else {
+            match PubSubAction::from_str(&action) {
+                Ok(PubSubAction::BrokerConnected) => monitor_ctx.broker_connection.report(true),
+                Ok(PubSubAction::BrokerDisconnected) => monitor_ctx.broker_connection.report(false),
+                _ => warn!("Connector library reported unknown action '{action}'."),
+            }
+            return;
+        };
+
+        let identity = (monitor_ctx.subscriber_identification
+            == SubscriberIdentification::Identity
+            && matches!(
+                action,
+                agemo_connector::PubSubAction::Subscribe
+                    | agemo_connector::PubSubAction::Unsubscribe
+            ))
+        .then(|| client_id.clone())
+        .flatten();
+
+        let _ = monitor_ctx.sender.send(MonitorMessage {
+            context: context.into_owned(),
+            action,
+            client_id,
+            identity,
+        });
+    });
+}
+
+#[async_trait]
+impl PubSubConnector for DynamicConnector {
+    type Config = ConnectorConfig;
+    type Error = AgemoError;
+
+    async fn connect(config: ConnectorConfig) -> Result<Self, AgemoError> {
+        let library_settings = config.connector_library.clone().ok_or_else(|| {
+            AgemoError::InvalidConfiguration("'connector_library' is not set".to_string())
+        })?;
+
+        let (library, vtable, create) = Self::load(&library_settings.path)?;
+        let config_json = to_c_string(&library_config_json(&config, &library_settings))?;
+
+        // SAFETY: The configuration is a nul terminated string that outlives the call.
+        let handle = unsafe { create(config_json.as_ptr()) };
+
+        if handle.is_null() {
+            return Err(AgemoError::Connection(format!(
+                "connector library '{}' failed to connect to '{}'",
+                library_settings.path, config.uri
+            )));
+        }
+
+        Ok(DynamicConnector {
+            handle,
+            vtable,
+            subscriber_identification: config.subscriber_identification,
+            broker_connection: config.broker_connection,
+            monitor_ctx: None,
+            _library: library,
+        })
+    }
+
+    async fn monitor_topics(
+        &mut self,
+        cb_channel: mpsc::Sender<MonitorMessage>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self.monitor_ctx.is_some() {
+            return Err(Box::new(AgemoError::Broker(
+                "the connector library is already monitoring topics".to_string(),
+            )));
+        }
+
+        let ctx = Box::into_raw(Box::new(MonitorContext {
+            sender: cb_channel,
+            subscriber_identification: self.subscriber_identification,
+            broker_connection: self.broker_connection.clone(),
+        }));
+        self.monitor_ctx = Some(ctx);
+
+        // SAFETY: The context stays valid until the connector is destroyed.
+        let code =
+            unsafe { (self.vtable.monitor)(self.handle, forward_monitor_message, ctx.cast()) };
+
+        Self::check_status("monitor topics", code)
+    }
+
+    async fn delete_topic(
+        &self,
+        topic: String,
+        deletion_msg: Arc<str>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let topic = to_c_string(&topic)?;
+        let deletion_msg = to_c_string(&deletion_msg)?;
+
+        // SAFETY: The strings are nul terminated and outlive the call.
+        let code = unsafe {
+            (self.vtable.delete_topic)(self.handle, topic.as_ptr(), deletion_msg.as_ptr())
+        };
+
+        Self::check_status("delete topic", code)
+    }
+
+    async fn authorize_publisher(
+        &self,
+        topic: String,
+        publisher_id: String,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(authorize_publisher) = self.vtable.authorize_publisher else {
+            return Err(Box::new(AgemoError::Broker(
+                "publisher permissions are not supported by this connector library".to_string(),
+            )));
+        };
+
+        let topic = to_c_string(&topic)?;
+        let publisher_id = to_c_string(&publisher_id)?;
+
+        // SAFETY: The strings are nul terminated and outlive the call.
+        let code =
+            unsafe { authorize_publisher(self.handle, topic.as_ptr(), publisher_id.as_ptr()) };
+
+        Self::check_status("authorize publisher", code)
+    }
+
+    async fn revoke_publisher(
+        &self,
+        topic: String,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(revoke_publisher) = self.vtable.revoke_publisher else {
+            return Err(Box::new(AgemoError::Broker(
+                "publisher permissions are not supported by this connector library".to_string(),
+            )));
+        };
+
+        let topic = to_c_string(&topic)?;
+
+        // SAFETY: The string is nul terminated and outlives the call.
+        let code = unsafe { revoke_publisher(self.handle, topic.as_ptr()) };
+
+        Self::check_status("revoke publisher", code)
+    }
+}
+
+impl Drop for DynamicConnector {
+    fn drop(&mut self) {
+        // SAFETY: The handle was created by the library and is destroyed once. The library makes
+        // no callbacks after it is destroyed, so the monitor context can be freed afterwards.
+        unsafe {
+            (self.vtable.destroy)(self.handle);
+
+            if let Some(ctx) = self.monitor_ctx.take() {
+                drop(Box::from_raw(ctx));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod dynamic_connector_tests {
+    use std::collections::HashMap;
+
+    use crate::{pubsub_connector::BrokerCredentials, topic_name_codec::TopicNaming};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn connect_missing_library_test() {
+        let config = ConnectorConfig {
+            connector_library: Some(ConnectorLibrarySettings {
+                path: "/nonexistent/libagemo_connector.so".to_string(),
+                options: HashMap::new(),
+                topic_naming: TopicNaming::default(),
+            }),
+            ..Default::default()
+        };
+
+        let result = DynamicConnector::connect(config).await;
+        assert!(matches!(result, Err(AgemoError::InvalidConfiguration(_))));
+
+        let result = DynamicConnector::connect(ConnectorConfig::default()).await;
+        assert!(matches!(result, Err(AgemoError::InvalidConfiguration(_))));
+    }
+
+    #[test]
+    fn library_config_json_test() {
+        let library_settings = ConnectorLibrarySettings {
+            path: "libagemo_connector.so".to_string(),
+            options: HashMap::from([("region".to_string(), "eu".to_string())]),
+            topic_naming: TopicNaming::default(),
+        };
+        let config = ConnectorConfig {
+            client_id: "agemo".to_string(),
+            uri: "tcp://localhost:1883".to_string(),
+            credentials: Some(BrokerCredentials {
+                username: Some("user".to_string()),
+                password: Some("password".to_string()),
+                token: None,
+            }),
+            ..Default::default()
+        };
+
+        let actual: serde_json::Value =
+            serde_json::from_str(&library_config_json(&config, &library_settings)).unwrap();
+
+        assert_eq!("agemo", actual["client_id"]);
+        assert_eq!("tcp://localhost:1883", actual["uri"]);
+        assert_eq!("user", actual["username"]);
+        assert_eq!("password", actual["secret"]);
+        assert_eq!("eu", actual["options"]["region"]);
+        assert_eq!("client_id", actual["subscriber_identification"]);
+    }
+
+    #[test]
+    fn forward_monitor_message_test() {
+        let (sender, receiver) = mpsc::channel::<MonitorMessage>();
+        let (connection_sender, connection_receiver) = mpsc::channel();
+        let ctx = Box::into_raw(Box::new(MonitorContext {
+            sender,
+            subscriber_identification: SubscriberIdentification::Identity,
+            broker_connection: BrokerConnection::new(connection_sender, "agemo".to_string()),
+        }));
+
+        let action = CString::new("SUBSCRIBE").unwrap();
+        let context = CString::new("topic").unwrap();
+        let client_id = CString::new("sub_1").unwrap();
+        forward_monitor_message(
+            ctx.cast(),
+            action.as_ptr(),
+            context.as_ptr(),
+            client_id.as_ptr(),
+        );
+
+        let actual = receiver.try_recv().unwrap();
+        assert_eq!(agemo_connector::PubSubAction::Subscribe, actual.action);
+        assert_eq!("topic", actual.context);
+        assert_eq!(Some("sub_1".to_string()), actual.client_id);
+        assert_eq!(Some("sub_1".to_string()), actual.identity);
+
+        // Unknown actions are dropped.
+        let action = CString::new("UNKNOWN").unwrap();
+        forward_monitor_message(
+            ctx.cast(),
+            action.as_ptr(),
+            context.as_ptr(),
+            std::ptr::null(),
+        );
+        assert!(receiver.try_recv().is_err());
+
+        // Changes to the connection are reported to the service, not forwarded as updates.
+        let action = CString::new("BROKERDISCONNECTED").unwrap();
+        forward_monitor_message(
+            ctx.cast(),
+            action.as_ptr(),
+            context.as_ptr(),
+            std::ptr::null(),
+        );
+        assert!(receiver.try_recv().is_err());
+        let actual = connection_receiver.try_recv().unwrap();
+        assert_eq!(PubSubAction::BrokerDisconnected, actual.action);
+        assert_eq!("agemo", actual.context);
+
+        // SAFETY: The context was created above and is no longer used.
+        unsafe { drop(Box::from_raw(ctx)) };
+    }
+}
@@ -0,0 +1,560 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Implements the [`PubSubConnector`][`crate::pubsub_connector`] trait for a
+//! [Kafka](https://kafka.apache.org/) cluster, using
+//! [rust-rdkafka](https://github.com/fede1024/rust-rdkafka).
+//!
+//! Kafka doesn't report subscriptions as they happen, so the connector polls the consumer groups
+//! of the cluster and follows which topics their members subscribe to. Each consumer group is
+//! tracked as a subscriber of the topics it subscribes to, with the group id as the subscriber's
+//! id: the group subscribes to a topic once any of its members does, and unsubscribes once none of
+//! them does or the group is gone. Managed topics map to Kafka topics through the Kafka topic
+//! naming, and deleted topics are removed through the admin API of the cluster.
+
+use agemo_connector::{MonitorMessage, PubSubAction};
+use async_trait::async_trait;
+use log::{info, warn};
+use rdkafka::{
+    admin::{AdminClient, AdminOptions},
+    client::DefaultClientContext,
+    config::ClientConfig,
+    consumer::{BaseConsumer, Consumer, StreamConsumer},
+    groups::GroupList,
+    producer::{FutureProducer, FutureRecord},
+    util::Timeout,
+    Message,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    time::{Duration, Instant},
+};
+use url::Url;
+
+use crate::{
+    error::AgemoError,
+    pubsub_connector::{self, BrokerConnection, ConnectorConfig, PubSubConnector},
+};
+
+/// Protocol type of the consumer groups of Kafka consumers, whose member metadata lists the
+/// topics the member subscribes to.
+const CONSUMER_PROTOCOL_TYPE: &str = "consumer";
+/// Prefix of the internal topics of the cluster, such as `__consumer_offsets`, which are never
+/// tracked.
+const INTERNAL_TOPIC_PREFIX: &str = "__";
+/// Interval at which the consumer groups of the cluster are polled.
+const GROUP_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Time to wait for the cluster to list its consumer groups.
+const GROUP_LIST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time to wait for the cluster to acknowledge a produced message.
+const PRODUCE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time subscribers are given to consume the deletion message of a topic before the topic is
+/// removed, along with the messages it holds.
+const DELETION_GRACE_PERIOD: Duration = Duration::from_secs(2);
+/// Payload of the probe messages published on self-test topics.
+const PROBE_PAYLOAD: &[u8] = b"agemo self-test probe";
+
+/// Returns the topics a member of a consumer group subscribes to, decoded from the member
+/// metadata of the Kafka consumer protocol: a version, followed by an array of topic names, each
+/// a 16 bit length followed by the name. Returns `None` if the metadata is malformed.
+///
+/// # Arguments
+///
+/// * `metadata` - The member metadata reported by the cluster.
+fn subscribed_topics(metadata: &[u8]) -> Option<Vec<String>> {
+    let mut rest = metadata.get(2..)?;
+
+    let count = i32::from_be_bytes(rest.get(..4)?.try_into().ok()?);
+    rest = &rest[4..];
+
+    let mut topics = Vec::new();
+    for _ in 0..count.max(0) {
+        let len = i16::from_be_bytes(rest.get(..2)?.try_into().ok()?);
+        let len = usize::try_from(len).ok()?;
+        let name = rest.get(2..2 + len)?;
+        topics.push(String::from_utf8(name.to_vec()).ok()?);
+        rest = &rest[2 + len..];
+    }
+
+    Some(topics)
+}
+
+/// Returns the topics each consumer group of the cluster subscribes to, by group id. Groups that
+/// are not groups of Kafka consumers, the groups of the service's own clients and internal topics
+/// are left out.
+///
+/// # Arguments
+///
+/// * `groups` - The consumer groups listed by the cluster.
+/// * `ignored_group_ids` - Ids of the groups of the service's own clients.
+fn group_subscriptions(
+    groups: &GroupList,
+    ignored_group_ids: &[String],
+) -> HashMap<String, HashSet<String>> {
+    groups
+        .groups()
+        .iter()
+        .filter(|group| {
+            group.protocol_type() == CONSUMER_PROTOCOL_TYPE
+                && !ignored_group_ids.iter().any(|id| id == group.name())
+        })
+        .map(|group| {
+            let topics = group
+                .members()
+                .iter()
+                .filter_map(|member| member.metadata())
+                .filter_map(subscribed_topics)
+                .flatten()
+                .filter(|topic| !topic.starts_with(INTERNAL_TOPIC_PREFIX))
+                .collect();
+
+            (group.name().to_string(), topics)
+        })
+        .collect()
+}
+
+/// Subscriptions of the consumer groups of the cluster, each group tracked as a subscriber of the
+/// topics its members subscribe to.
+#[derive(Debug, Default)]
+struct GroupSubscriptions {
+    /// The topics each group subscribes to, by group id.
+    groups: HashMap<String, HashSet<String>>,
+}
+
+impl GroupSubscriptions {
+    /// Replaces the tracked subscriptions with the subscriptions found by the latest poll of the
+    /// cluster. Returns a subscribe message for each topic a group joined, and an unsubscribe
+    /// message for each topic a group left.
+    ///
+    /// # Arguments
+    ///
+    /// * `groups` - The topics each group subscribes to, by group id.
+    fn update(&mut self, mut groups: HashMap<String, HashSet<String>>) -> Vec<MonitorMessage> {
+        groups.retain(|_, topics| !topics.is_empty());
+
+        let mut messages = Vec::new();
+        for (group, topics) in &self.groups {
+            let current = groups.get(group);
+            for topic in topics {
+                if !current.is_some_and(|current| current.contains(topic)) {
+                    info!("Removed a subscriber from topic '{topic}'.");
+                    messages.push(MonitorMessage {
+                        context: topic.clone(),
+                        action: PubSubAction::Unsubscribe,
+                        client_id: Some(group.clone()),
+                        identity: None,
+                    });
+                }
+            }
+        }
+
+        for (group, topics) in &groups {
+            let previous = self.groups.get(group);
+            for topic in topics {
+                if !previous.is_some_and(|previous| previous.contains(topic)) {
+                    info!("Added a subscriber to topic '{topic}'.");
+                    messages.push(MonitorMessage {
+                        context: topic.clone(),
+                        action: PubSubAction::Subscribe,
+                        client_id: Some(group.clone()),
+                        identity: None,
+                    });
+                }
+            }
+        }
+
+        self.groups = groups;
+        messages
+    }
+}
+
+/// Returns the bootstrap brokers of the cluster, as a comma separated list. The brokers of the
+/// Kafka connector settings take precedence over the host and port of the messaging uri, such as
+/// `kafka://localhost:9092`.
+///
+/// # Arguments
+///
+/// * `config` - Configuration of the connector.
+fn bootstrap_servers(config: &ConnectorConfig) -> Result<String, AgemoError> {
+    if let Some(kafka) = config
+        .kafka
+        .as_ref()
+        .filter(|kafka| !kafka.brokers.is_empty())
+    {
+        return Ok(kafka.brokers.join(","));
+    }
+
+    let url = Url::parse(&config.uri).map_err(|err| {
+        AgemoError::InvalidConfiguration(format!("invalid messaging uri '{}': {err}", config.uri))
+    })?;
+    match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => Ok(format!("{host}:{port}")),
+        _ => Err(AgemoError::InvalidConfiguration(format!(
+            "messaging uri '{}' has no host and port, set 'connector.kafka.brokers' instead",
+            config.uri
+        ))),
+    }
+}
+
+/// Removes a topic from the cluster through its admin API.
+///
+/// # Arguments
+///
+/// * `admin` - The admin client of the cluster.
+/// * `topic` - The topic to remove.
+async fn remove_topic(
+    admin: &AdminClient<DefaultClientContext>,
+    topic: &str,
+) -> Result<(), AgemoError> {
+    let results = admin
+        .delete_topics(&[topic], &AdminOptions::new())
+        .await
+        .map_err(|err| AgemoError::Broker(format!("unable to delete topic '{topic}': {err}")))?;
+
+    for result in results {
+        if let Err((topic, code)) = result {
+            return Err(AgemoError::Broker(format!(
+                "unable to delete topic '{topic}': {code}"
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Kafka connector, connecting to a cluster as an admin, producer and consumer client.
+pub struct KafkaConnector {
+    client_id: String,
+    /// Options shared by the clients of the connector.
+    client_config: ClientConfig,
+    /// Shared with the removals of deleted topics, which are scheduled after a grace period.
+    admin: Arc<AdminClient<DefaultClientContext>>,
+    producer: FutureProducer,
+    /// Ids of the groups of the service's own clients, whose subscriptions are not tracked.
+    ignored_group_ids: Vec<String>,
+    /// Stops the thread polling the consumer groups, once the connector is dropped.
+    stop_polling: Arc<AtomicBool>,
+    /// Reports whether the consumer groups can be polled to the topic manager.
+    broker_connection: BrokerConnection,
+}
+
+impl KafkaConnector {
+    /// Publishes a message on a topic, waiting for the cluster to acknowledge it.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic to publish on.
+    /// * `payload` - The message to publish.
+    async fn produce(&self, topic: &str, payload: &[u8]) -> Result<(), AgemoError> {
+        self.producer
+            .send(
+                FutureRecord::<(), [u8]>::to(topic).payload(payload),
+                Timeout::After(PRODUCE_TIMEOUT),
+            )
+            .await
+            .map_err(|(err, _)| {
+                AgemoError::Broker(format!("unable to publish to '{topic}': {err}"))
+            })?;
+
+        Ok(())
+    }
+}
+
+impl Drop for KafkaConnector {
+    fn drop(&mut self) {
+        // A restarted connector polls the consumer groups anew, so the subscribers must not be
+        // tracked twice.
+        self.stop_polling.store(true, Ordering::Relaxed);
+    }
+}
+
+#[async_trait]
+impl PubSubConnector for KafkaConnector {
+    type Config = ConnectorConfig;
+    type Error = AgemoError;
+
+    async fn connect(config: ConnectorConfig) -> Result<Self, AgemoError> {
+        let bootstrap_servers = bootstrap_servers(&config)?;
+        let mut client_config = ClientConfig::new();
+        client_config
+            .set("bootstrap.servers", &bootstrap_servers)
+            .set("client.id", &config.client_id);
+        if let Some(credentials) = &config.credentials {
+            if let (Some(username), Some(secret)) = (&credentials.username, credentials.secret()) {
+                client_config
+                    .set("security.protocol", "SASL_PLAINTEXT")
+                    .set("sasl.mechanism", "PLAIN")
+                    .set("sasl.username", username)
+                    .set("sasl.password", secret);
+            }
+        }
+
+        let admin: AdminClient<DefaultClientContext> = client_config.create().map_err(|err| {
+            AgemoError::Connection(format!("unable to create the Kafka admin client: {err}"))
+        })?;
+        let producer: FutureProducer = client_config.create().map_err(|err| {
+            AgemoError::Connection(format!("unable to create the Kafka producer: {err}"))
+        })?;
+
+        let mut ignored_group_ids = config.ignored_client_ids.clone();
+        ignored_group_ids.push(config.client_id.clone());
+        if let Some(group_id) = config
+            .kafka
+            .as_ref()
+            .and_then(|kafka| kafka.group_id.clone())
+        {
+            ignored_group_ids.push(group_id);
+        }
+
+        info!("Created the clients of Kafka cluster {bootstrap_servers}");
+
+        Ok(KafkaConnector {
+            client_id: config.client_id,
+            client_config,
+            admin: Arc::new(admin),
+            producer,
+            ignored_group_ids,
+            stop_polling: Arc::new(AtomicBool::new(false)),
+            broker_connection: config.broker_connection,
+        })
+    }
+
+    async fn monitor_topics(
+        &mut self,
+        cb_channel: mpsc::Sender<MonitorMessage>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let consumer: BaseConsumer = self.client_config.create()?;
+        let ignored_group_ids = self.ignored_group_ids.clone();
+        let broker_connection = self.broker_connection.clone();
+
+        // Groups are only polled by the latest monitor.
+        self.stop_polling.store(true, Ordering::Relaxed);
+        self.stop_polling = Arc::new(AtomicBool::new(false));
+        let stop_polling = self.stop_polling.clone();
+
+        // Listing the consumer groups blocks, so the groups are polled on a thread of their own.
+        std::thread::spawn(move || {
+            let mut subscriptions = GroupSubscriptions::default();
+            let mut connected = None;
+
+            loop {
+                if stop_polling.load(Ordering::Relaxed) {
+                    info!("Stopped polling the consumer groups of the Kafka cluster.");
+                    return;
+                }
+
+                let groups = consumer.fetch_group_list(None, GROUP_LIST_TIMEOUT);
+
+                // Topics aren't timed out while their subscriptions can't be polled.
+                if connected != Some(groups.is_ok()) {
+                    connected = Some(groups.is_ok());
+                    if let Err(err) = &groups {
+                        warn!("Unable to list the consumer groups of the cluster: {err}");
+                    }
+
+                    broker_connection.report(groups.is_ok());
+                }
+
+                if let Ok(groups) = groups {
+                    let delivered = subscriptions
+                        .update(group_subscriptions(&groups, &ignored_group_ids))
+                        .into_iter()
+                        .all(|message| {
+                            pubsub_connector::update_topic_information(message, cb_channel.clone())
+                                .is_ok()
+                        });
+                    if !delivered {
+                        break;
+                    }
+                }
+
+                std::thread::sleep(GROUP_POLL_INTERVAL);
+            }
+
+            warn!("Kafka consumer group polling stopped, subscribers are no longer tracked.");
+        });
+
+        Ok(())
+    }
+
+    async fn delete_topic(
+        &self,
+        topic: String,
+        deletion_msg: Arc<str>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.produce(&topic, deletion_msg.as_bytes()).await?;
+
+        // Removing the topic also removes the deletion message, so subscribers are given time to
+        // consume it first. The removal is scheduled, so that the connector loop isn't held up.
+        let admin = self.admin.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(DELETION_GRACE_PERIOD).await;
+
+            if let Err(err) = remove_topic(&admin, &topic).await {
+                warn!("Unable to remove the deleted topic '{topic}': {err}");
+            }
+        });
+
+        Ok(())
+    }
+
+    async fn probe_topic(
+        &self,
+        topic: String,
+        timeout: Duration,
+    ) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+        // The probe consumer reads the topic from the start, so that the probe message isn't
+        // missed while the consumer joins its group.
+        let consumer: StreamConsumer = self
+            .client_config
+            .clone()
+            .set(
+                "group.id",
+                format!("{}-probe-{}", self.client_id, uuid::Uuid::new_v4()),
+            )
+            .set("auto.offset.reset", "earliest")
+            .set("enable.auto.commit", "false")
+            .create()?;
+        consumer.subscribe(&[topic.as_str()])?;
+
+        let start = Instant::now();
+        self.produce(&topic, PROBE_PAYLOAD).await?;
+
+        let delivery = async {
+            loop {
+                let message = consumer.recv().await.map_err(|err| {
+                    AgemoError::Broker(format!("unable to read from '{topic}': {err}"))
+                })?;
+                if message.payload() == Some(PROBE_PAYLOAD) {
+                    return Ok::<Duration, AgemoError>(start.elapsed());
+                }
+            }
+        };
+
+        let result = tokio::time::timeout(timeout, delivery).await.map_err(|_| {
+            AgemoError::Broker(format!(
+                "probe message on '{topic}' was not delivered within {timeout:?}"
+            ))
+        });
+        drop(consumer);
+
+        if let Err(err) = remove_topic(&self.admin, &topic).await {
+            warn!("Unable to remove the self-test topic '{topic}': {err}");
+        }
+
+        Ok(result??)
+    }
+}
+
+#[cfg(test)]
+mod kafka_connector_tests {
+    use super::*;
+
+    /// Encodes member metadata of the Kafka consumer protocol.
+    fn member_metadata(topics: &[&str]) -> Vec<u8> {
+        let mut metadata = 1i16.to_be_bytes().to_vec();
+        metadata.extend((topics.len() as i32).to_be_bytes());
+        for topic in topics {
+            metadata.extend((topic.len() as i16).to_be_bytes());
+            metadata.extend(topic.as_bytes());
+        }
+        // User data, which is ignored.
+        metadata.extend(0i32.to_be_bytes());
+
+        metadata
+    }
+
+    fn topics(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn subscribed_topics_test() {
+        assert_eq!(
+            Some(vec!["vehicle.speed".to_string(), "cabin.temp".to_string()]),
+            subscribed_topics(&member_metadata(&["vehicle.speed", "cabin.temp"]))
+        );
+        assert_eq!(Some(Vec::new()), subscribed_topics(&member_metadata(&[])));
+
+        // Truncated metadata is rejected.
+        let metadata = member_metadata(&["vehicle.speed"]);
+        assert_eq!(None, subscribed_topics(&metadata[..10]));
+        assert_eq!(None, subscribed_topics(&[0]));
+    }
+
+    #[test]
+    fn group_subscriptions_update_test() {
+        let mut subscriptions = GroupSubscriptions::default();
+
+        let messages = subscriptions.update(HashMap::from([
+            ("hvac".to_string(), topics(&["cabin.temp"])),
+            ("dashboard".to_string(), topics(&[])),
+        ]));
+        assert_eq!(1, messages.len());
+        assert_eq!("cabin.temp", messages[0].context);
+        assert_eq!(PubSubAction::Subscribe, messages[0].action);
+        assert_eq!(Some("hvac".to_string()), messages[0].client_id);
+
+        // Unchanged subscriptions are not reported again.
+        assert!(subscriptions
+            .update(HashMap::from([(
+                "hvac".to_string(),
+                topics(&["cabin.temp"])
+            )]))
+            .is_empty());
+
+        let mut messages: Vec<(String, PubSubAction)> = subscriptions
+            .update(HashMap::from([(
+                "hvac".to_string(),
+                topics(&["vehicle.speed"]),
+            )]))
+            .into_iter()
+            .map(|message| (message.context, message.action))
+            .collect();
+        messages.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            vec![
+                ("cabin.temp".to_string(), PubSubAction::Unsubscribe),
+                ("vehicle.speed".to_string(), PubSubAction::Subscribe),
+            ],
+            messages
+        );
+
+        // A group that is gone unsubscribes from its topics.
+        let messages = subscriptions.update(HashMap::new());
+        assert_eq!(1, messages.len());
+        assert_eq!("vehicle.speed", messages[0].context);
+        assert_eq!(PubSubAction::Unsubscribe, messages[0].action);
+    }
+
+    #[tokio::test]
+    async fn polling_stops_when_connector_is_dropped_test() {
+        let mut connector = KafkaConnector::connect(ConnectorConfig {
+            client_id: "agemo".to_string(),
+            uri: "kafka://localhost:1".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let (sender, receiver) = mpsc::channel();
+        connector.monitor_topics(sender).await.unwrap();
+
+        drop(connector);
+
+        // The polling thread drops its sender once it stops.
+        loop {
+            match receiver.recv_timeout(GROUP_LIST_TIMEOUT * 3) {
+                Ok(_) => continue,
+                Err(err) => {
+                    assert_eq!(mpsc::RecvTimeoutError::Disconnected, err);
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,405 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Implements the [`PubSubConnector`][`crate::pubsub_connector`] trait for a message bus inside
+//! the service, used when `messaging_uri` is `memory://`.
+//!
+//! The loopback connector lets development and test environments run the service without a
+//! messaging broker, to exercise the gRPC and admin APIs of the service on their own. The
+//! [`LoopbackBus`] only carries the service's own traffic, such as self-test probes and the
+//! deletion messages of topics, as clients in other processes can't reach it. The connector
+//! reports each subscription to the bus as a subscriber of its topic, so the bus behaves like a
+//! broker towards the topic manager.
+
+use agemo_connector::{MonitorMessage, PubSubAction};
+use async_trait::async_trait;
+use common::shared_state::SharedState;
+use log::info;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use url::Url;
+
+use crate::{
+    error::AgemoError,
+    pubsub_connector::{self, BrokerConnection, ConnectorConfig, PubSubConnector},
+};
+
+/// Scheme of the `messaging_uri` that selects the loopback connector.
+pub const MEMORY_SCHEME: &str = "memory";
+
+/// Returns whether a messaging uri names the message bus inside the service.
+///
+/// # Arguments
+///
+/// * `messaging_uri` - The uri of the messaging broker.
+pub fn is_memory_uri(messaging_uri: &str) -> bool {
+    Url::parse(messaging_uri).is_ok_and(|url| url.scheme() == MEMORY_SCHEME)
+}
+
+/// A subscriber of a topic on the bus.
+struct BusSubscriber {
+    /// Id of the subscription, unique on the bus.
+    id: u64,
+    /// Client id of the subscriber.
+    client_id: String,
+    /// Channel the messages of the topic are delivered on.
+    sender: UnboundedSender<Vec<u8>>,
+}
+
+/// Where the bus reports subscribes and unsubscribes, once a connector monitors the bus.
+struct BusMonitor {
+    /// Channel used to forward updates to the topic manager.
+    cb_channel: mpsc::Sender<MonitorMessage>,
+    /// Ids of the service's own clients, whose subscriptions are not reported.
+    ignored_client_ids: Vec<String>,
+}
+
+/// Message bus inside the service. Delivers every message published on a topic to the current
+/// subscribers of the topic.
+#[derive(Default)]
+pub struct LoopbackBus {
+    /// The subscribers of each topic.
+    subscribers: SharedState<HashMap<String, Vec<BusSubscriber>>>,
+    /// Where subscribes and unsubscribes are reported, if the bus is monitored.
+    monitor: SharedState<Option<BusMonitor>>,
+    /// Id of the next subscription.
+    next_id: AtomicU64,
+}
+
+impl LoopbackBus {
+    /// Returns the bus shared by the connectors of the service.
+    pub fn global() -> Arc<LoopbackBus> {
+        static BUS: OnceLock<Arc<LoopbackBus>> = OnceLock::new();
+
+        BUS.get_or_init(|| Arc::new(LoopbackBus::default())).clone()
+    }
+
+    /// Subscribes to a topic. The subscription ends when the returned [`LoopbackSubscription`] is
+    /// dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - The bus to subscribe on.
+    /// * `client_id` - Client id of the subscriber.
+    /// * `topic` - The topic to subscribe to.
+    pub fn subscribe(bus: &Arc<LoopbackBus>, client_id: &str, topic: &str) -> LoopbackSubscription {
+        let id = bus.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = unbounded_channel();
+
+        bus.subscribers
+            .lock()
+            .entry(topic.to_string())
+            .or_default()
+            .push(BusSubscriber {
+                id,
+                client_id: client_id.to_string(),
+                sender,
+            });
+        bus.report(topic, PubSubAction::Subscribe, client_id);
+
+        LoopbackSubscription {
+            bus: bus.clone(),
+            id,
+            topic: topic.to_string(),
+            receiver,
+        }
+    }
+
+    /// Publishes a message on a topic. Returns the number of subscribers the message was
+    /// delivered to.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic to publish on.
+    /// * `payload` - The payload of the message.
+    pub fn publish(&self, topic: &str, payload: &[u8]) -> usize {
+        self.subscribers.lock().get(topic).map_or(0, |subscribers| {
+            subscribers
+                .iter()
+                .filter(|subscriber| subscriber.sender.send(payload.to_vec()).is_ok())
+                .count()
+        })
+    }
+
+    /// Reports the subscribes and unsubscribes on the bus to the topic manager from now on.
+    ///
+    /// # Arguments
+    ///
+    /// * `cb_channel` - Channel used to forward updates to the topic manager.
+    /// * `ignored_client_ids` - Ids of the service's own clients, whose subscriptions are not
+    ///                          reported.
+    fn set_monitor(
+        &self,
+        cb_channel: mpsc::Sender<MonitorMessage>,
+        ignored_client_ids: Vec<String>,
+    ) {
+        *self.monitor.lock() = Some(BusMonitor {
+            cb_channel,
+            ignored_client_ids,
+        });
+    }
+
+    /// Delivers a last message to the subscribers of a topic and ends their subscriptions.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic to close.
+    /// * `payload` - The payload of the last message.
+    fn close_topic(&self, topic: &str, payload: &[u8]) {
+        let subscribers = self.subscribers.lock().remove(topic);
+
+        for subscriber in subscribers.into_iter().flatten() {
+            let _ = subscriber.sender.send(payload.to_vec());
+        }
+    }
+
+    /// Removes a subscription, reporting the unsubscribe if the topic was not closed first.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic of the subscription.
+    /// * `id` - Id of the subscription.
+    fn unsubscribe(&self, topic: &str, id: u64) {
+        let removed = {
+            let mut subscribers = self.subscribers.lock();
+            let Some(topic_subscribers) = subscribers.get_mut(topic) else {
+                return;
+            };
+            let Some(index) = topic_subscribers
+                .iter()
+                .position(|subscriber| subscriber.id == id)
+            else {
+                return;
+            };
+
+            let removed = topic_subscribers.remove(index);
+            if topic_subscribers.is_empty() {
+                subscribers.remove(topic);
+            }
+
+            removed
+        };
+
+        self.report(topic, PubSubAction::Unsubscribe, &removed.client_id);
+    }
+
+    /// Reports a subscribe or unsubscribe to the topic manager, if the bus is monitored.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic of the update.
+    /// * `action` - The action of the update.
+    /// * `client_id` - Client id of the subscriber.
+    fn report(&self, topic: &str, action: PubSubAction, client_id: &str) {
+        let monitor = self.monitor.lock();
+        let Some(monitor) = monitor.as_ref() else {
+            return;
+        };
+
+        if monitor.ignored_client_ids.iter().any(|id| id == client_id) {
+            return;
+        }
+
+        // The service no longer receives updates once it shuts down.
+        let _ = pubsub_connector::update_topic_information(
+            MonitorMessage {
+                context: topic.to_string(),
+                action,
+                client_id: Some(client_id.to_string()),
+                identity: None,
+            },
+            monitor.cb_channel.clone(),
+        );
+    }
+}
+
+/// A subscription to a topic on the [`LoopbackBus`], which ends when dropped.
+pub struct LoopbackSubscription {
+    /// The bus of the subscription.
+    bus: Arc<LoopbackBus>,
+    /// Id of the subscription.
+    id: u64,
+    /// The topic subscribed to.
+    topic: String,
+    /// Channel the messages of the topic are received on.
+    receiver: UnboundedReceiver<Vec<u8>>,
+}
+
+impl LoopbackSubscription {
+    /// Receives the payload of the next message on the topic. Returns None once the topic is
+    /// deleted and every message before the deletion message was received.
+    pub async fn recv(&mut self) -> Option<Vec<u8>> {
+        self.receiver.recv().await
+    }
+}
+
+impl Drop for LoopbackSubscription {
+    fn drop(&mut self) {
+        self.bus.unsubscribe(&self.topic, self.id);
+    }
+}
+
+/// Connector for the message bus inside the service.
+pub struct LoopbackConnector {
+    /// The bus shared by the connectors of the service.
+    bus: Arc<LoopbackBus>,
+    /// Client id of the service, used for its own subscriptions.
+    client_id: String,
+    /// Ids of the service's own clients, whose subscriptions are not reported.
+    ignored_client_ids: Vec<String>,
+    /// Reports the connection to the bus to the topic manager.
+    broker_connection: BrokerConnection,
+}
+
+#[async_trait]
+impl PubSubConnector for LoopbackConnector {
+    type Config = ConnectorConfig;
+    type Error = AgemoError;
+
+    async fn connect(config: ConnectorConfig) -> Result<Self, AgemoError> {
+        if !is_memory_uri(&config.uri) {
+            return Err(AgemoError::InvalidConfiguration(format!(
+                "the loopback connector needs a '{MEMORY_SCHEME}://' messaging uri, got '{}'",
+                config.uri
+            )));
+        }
+
+        info!(
+            "Using the in-memory message bus, clients outside the service's process can't connect."
+        );
+
+        let mut ignored_client_ids = config.ignored_client_ids;
+        ignored_client_ids.push(config.client_id.clone());
+
+        Ok(LoopbackConnector {
+            bus: LoopbackBus::global(),
+            client_id: config.client_id,
+            ignored_client_ids,
+            broker_connection: config.broker_connection,
+        })
+    }
+
+    async fn monitor_topics(
+        &mut self,
+        cb_channel: mpsc::Sender<MonitorMessage>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.bus
+            .set_monitor(cb_channel, self.ignored_client_ids.clone());
+        // The bus is never disconnected.
+        self.broker_connection.report(true);
+
+        Ok(())
+    }
+
+    async fn delete_topic(
+        &self,
+        topic: String,
+        deletion_msg: Arc<str>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.bus.close_topic(&topic, deletion_msg.as_bytes());
+
+        Ok(())
+    }
+
+    async fn probe_topic(
+        &self,
+        topic: String,
+        timeout: Duration,
+    ) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+        const PROBE_PAYLOAD: &[u8] = b"agemo self-test probe";
+
+        let mut subscription = LoopbackBus::subscribe(&self.bus, &self.client_id, &topic);
+
+        let start = Instant::now();
+        self.bus.publish(&topic, PROBE_PAYLOAD);
+        let delivery = async {
+            while let Some(payload) = subscription.recv().await {
+                if payload == PROBE_PAYLOAD {
+                    return Ok(start.elapsed());
+                }
+            }
+
+            Err(AgemoError::Broker(format!(
+                "topic '{topic}' was closed before the probe message was delivered"
+            )))
+        };
+
+        let result = tokio::time::timeout(timeout, delivery).await.map_err(|_| {
+            AgemoError::Broker(format!(
+                "probe message on '{topic}' was not delivered within {timeout:?}"
+            ))
+        })?;
+
+        Ok(result?)
+    }
+}
+
+#[cfg(test)]
+mod loopback_connector_tests {
+    use super::*;
+
+    fn monitored_bus() -> (Arc<LoopbackBus>, mpsc::Receiver<MonitorMessage>) {
+        let bus = Arc::new(LoopbackBus::default());
+        let (sender, receiver) = mpsc::channel();
+        bus.set_monitor(sender, vec!["service".to_string()]);
+
+        (bus, receiver)
+    }
+
+    #[test]
+    fn is_memory_uri_test() {
+        assert!(is_memory_uri("memory://"));
+        assert!(!is_memory_uri("mqtt://0.0.0.0:1883"));
+        assert!(!is_memory_uri("memory"));
+    }
+
+    #[tokio::test]
+    async fn subscriptions_are_reported_test() {
+        let (bus, receiver) = monitored_bus();
+
+        let subscription = LoopbackBus::subscribe(&bus, "subscriber", "vehicle/speed");
+        let message = receiver.try_recv().unwrap();
+        assert_eq!("vehicle/speed", message.context);
+        assert_eq!(PubSubAction::Subscribe, message.action);
+        assert_eq!(Some("subscriber".to_string()), message.client_id);
+        assert_eq!(1, bus.publish("vehicle/speed", b"42"));
+
+        drop(subscription);
+        let message = receiver.try_recv().unwrap();
+        assert_eq!(PubSubAction::Unsubscribe, message.action);
+        assert_eq!(0, bus.publish("vehicle/speed", b"42"));
+
+        // The service's own subscriptions are not reported.
+        let _service = LoopbackBus::subscribe(&bus, "service", "vehicle/speed");
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn publish_and_close_topic_test() {
+        let (bus, receiver) = monitored_bus();
+
+        let mut first = LoopbackBus::subscribe(&bus, "first", "vehicle/speed");
+        let mut second = LoopbackBus::subscribe(&bus, "second", "vehicle/speed");
+        assert_eq!(2, bus.publish("vehicle/speed", b"42"));
+        assert_eq!(0, bus.publish("vehicle/rpm", b"3000"));
+        assert_eq!(Some(b"42".to_vec()), first.recv().await);
+        assert_eq!(Some(b"42".to_vec()), second.recv().await);
+
+        bus.close_topic("vehicle/speed", b"TOPIC DELETED");
+        assert_eq!(Some(b"TOPIC DELETED".to_vec()), first.recv().await);
+        assert_eq!(None, first.recv().await);
+
+        // Subscriptions to a closed topic end without an unsubscribe.
+        while receiver.try_recv().is_ok() {}
+        drop(second);
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Implements the [`PubSubConnector`][`crate::pubsub_connector`] trait without a messaging broker.
+//!
+//! The mock connector records every deletion request it receives so that tests can verify the
+//! behavior of the pub sub service without a running broker.
+
+use agemo_connector::MonitorMessage;
+use async_trait::async_trait;
+use std::sync::{mpsc, Arc};
+
+use common::shared_state::SharedState;
+
+use crate::{
+    error::AgemoError,
+    pubsub_connector::{ConnectorConfig, PubSubConnector},
+};
+
+/// A deletion request recorded by the [`MockConnector`].
+#[derive(Clone, Debug)]
+pub struct RecordedDeletion {
+    /// The topic that was deleted.
+    pub topic: String,
+    /// The deletion message that would have been sent to subscribers.
+    pub deletion_msg: Arc<str>,
+}
+
+/// Connector that records requests instead of communicating with a messaging broker.
+pub struct MockConnector {
+    /// Channel provided by `monitor_topics`, used to inject monitor messages in tests.
+    pub cb_channel: Option<mpsc::Sender<MonitorMessage>>,
+    /// Deletion requests received by the connector, in order.
+    pub deletions: Arc<SharedState<Vec<RecordedDeletion>>>,
+}
+
+#[async_trait]
+impl PubSubConnector for MockConnector {
+    type Config = ConnectorConfig;
+    type Error = AgemoError;
+
+    async fn connect(_config: ConnectorConfig) -> Result<Self, AgemoError> {
+        Ok(MockConnector {
+            cb_channel: None,
+            deletions: Arc::new(SharedState::new(Vec::new())),
+        })
+    }
+
+    async fn monitor_topics(
+        &mut self,
+        cb_channel: mpsc::Sender<MonitorMessage>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.cb_channel = Some(cb_channel);
+
+        Ok(())
+    }
+
+    async fn delete_topic(
+        &self,
+        topic: String,
+        deletion_msg: Arc<str>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.deletions.lock().push(RecordedDeletion {
+            topic,
+            deletion_msg,
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod mock_connector_tests {
+    use std::time::Instant;
+
+    use agemo_connector::PubSubAction;
+
+    use super::*;
+
+    /// Pushes deletion requests through a channel and the connector the same way the service does.
+    async fn run_deletions(connector: &MockConnector, deletion_msg: &Arc<str>, count: usize) {
+        let (sender, receiver) = mpsc::channel::<MonitorMessage>();
+
+        for i in 0..count {
+            sender
+                .send(MonitorMessage {
+                    context: format!("topic_{i}"),
+                    action: PubSubAction::Delete,
+                    client_id: None,
+                    identity: None,
+                })
+                .unwrap();
+        }
+        drop(sender);
+
+        for msg in receiver.iter() {
+            connector
+                .delete_topic(msg.context, deletion_msg.clone())
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn deletion_message_is_shared_test() {
+        let connector = MockConnector::connect(ConnectorConfig::default())
+            .await
+            .unwrap();
+        let deletion_msg: Arc<str> = Arc::from("TOPIC DELETED");
+
+        run_deletions(&connector, &deletion_msg, 10).await;
+
+        let deletions = connector.deletions.lock();
+        assert_eq!(10, deletions.len());
+
+        // Every recorded deletion points at the original allocation.
+        for deletion in deletions.iter() {
+            assert!(Arc::ptr_eq(&deletion_msg, &deletion.deletion_msg));
+        }
+    }
+
+    /// Micro-benchmark of the deletion path. Run with:
+    /// `cargo test -p pub-sub-service deletion_path_benchmark -- --ignored --nocapture`
+    #[tokio::test]
+    #[ignore]
+    async fn deletion_path_benchmark() {
+        const ITERATIONS: usize = 100_000;
+
+        let connector = MockConnector::connect(ConnectorConfig::default())
+            .await
+            .unwrap();
+        let deletion_msg: Arc<str> = Arc::from("TOPIC DELETED");
+
+        let start = Instant::now();
+        run_deletions(&connector, &deletion_msg, ITERATIONS).await;
+        let elapsed = start.elapsed();
+
+        println!(
+            "deletion path: {ITERATIONS} deletions in {elapsed:?} ({} ns/deletion)",
+            elapsed.as_nanos() / ITERATIONS as u128
+        );
+
+        // Only the original reference and the recorded deletions hold the message.
+        assert_eq!(ITERATIONS + 1, Arc::strong_count(&deletion_msg));
+    }
+}
@@ -9,58 +9,312 @@
 //! directly with the Mosquitto MQTT broker. The pub sub service needs to communicate with the
 //! broker to monitor the state of topics generated by the service for publishers.
 
+use agemo_connector::{MonitorMessage, PubSubAction};
 use async_trait::async_trait;
+use common::constants;
+use common::shared_state::SharedState;
 use log::{error, info, warn};
 use paho_mqtt::{self as mqtt, MQTT_VERSION_5};
-use std::{process, sync::mpsc};
+use serde_derive::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+use uuid::Uuid;
 
-use crate::pubsub_connector::{self, MonitorMessage, PubSubAction, PubSubConnector};
+#[cfg(feature = "alloc-profiling")]
+use crate::alloc_profile::{self, Subsystem};
+use crate::{
+    error::AgemoError,
+    load_config::MqttConnectionSettings,
+    pubsub_connector::{
+        self, BrokerConnection, BrokerCredentials, BrokerStats, ClientIdCollisionDetector,
+        ConnectorConfig, MonitorHealth, PubSubConnector,
+    },
+    self_test::SELF_TEST_TOPIC_PREFIX,
+    subscriber_acl::SubscriberIdentification,
+};
 
+/// Prefix of the Mosquitto broker's reserved topics for log messages.
+const BROKER_LOG_PREFIX: &str = "$SYS/broker/log/";
 /// Mosquitto broker's reserved topic for subscribe related notifications.
 const SUBSCRIBE: &str = "$SYS/broker/log/M/subscribe";
 /// Mosquitto broker's reserved topic for unsubscribe related notifications.
 const UNSUBSCRIBE: &str = "$SYS/broker/log/M/unsubscribe";
-/// Constant topic used by a publisher's last will and testament for unclean disconnect.
-const LWT_PUBLISHER: &str = "publisher/disconnect";
+/// Mosquitto broker's reserved topic for notices, including the connection of clients.
+const NOTICES: &str = "$SYS/broker/log/N";
+/// Default topic used by a client's last will and testament for unclean disconnect.
+pub const DEFAULT_DISCONNECT_TOPIC: &str = constants::DISCONNECT_TOPIC;
+/// Mosquitto broker's reserved topic for the number of connected clients.
+const SYS_CLIENTS_CONNECTED: &str = "$SYS/broker/clients/connected";
+/// Mosquitto broker's reserved topic for the average messages received per minute.
+const SYS_MESSAGES_RECEIVED: &str = "$SYS/broker/load/messages/received/1min";
+/// Mosquitto broker's reserved topic for the average messages sent per minute.
+const SYS_MESSAGES_SENT: &str = "$SYS/broker/load/messages/sent/1min";
+/// Mosquitto broker's reserved topic for the total number of dropped messages.
+const SYS_MESSAGES_DROPPED: &str = "$SYS/broker/publish/messages/dropped";
+/// Mosquitto broker's reserved topics that report broker statistics, published every
+/// `sys_interval` seconds.
+const BROKER_STATS_TOPICS: [&str; 4] = [
+    SYS_CLIENTS_CONNECTED,
+    SYS_MESSAGES_RECEIVED,
+    SYS_MESSAGES_SENT,
+    SYS_MESSAGES_DROPPED,
+];
+/// Topic filter matching every topic outside of the broker's reserved `$` topics.
+const ALL_TOPICS: &str = "#";
+/// Time to wait for the broker to send the retained messages when listing topics.
+const RETAINED_COLLECTION_WINDOW: Duration = Duration::from_secs(1);
+/// Shortest interval between attempts to reconnect to the broker after the connection is lost.
+const MIN_RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+/// Longest interval between attempts to reconnect to the broker after the connection is lost.
+const MAX_RECONNECT_INTERVAL: Duration = Duration::from_secs(30);
+/// Mosquitto dynamic security plugin's topic for control commands.
+const DYNAMIC_SECURITY: &str = "$CONTROL/dynamic-security/v1";
+/// Prefix of the dynamic security roles created to restrict publishing on a generated topic.
+const PUBLISHER_ROLE_PREFIX: &str = "agemo-publisher-";
+/// Payload of the probe messages published on self-test topics.
+const PROBE_PAYLOAD: &[u8] = b"agemo self-test probe";
+
+/// The role of a client that has disconnected from the broker.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientRole {
+    /// The client was publishing to one or more topics.
+    Publisher,
+    /// The client was subscribed to one or more topics.
+    Subscriber,
+}
+
+/// Structured payload sent on the disconnect topic by a client's last will and testament.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct DisconnectPayload {
+    /// The id of the client that lost connection.
+    pub client_id: String,
+    /// The role of the client that lost connection.
+    pub role: ClientRole,
+    /// Time in seconds since the Unix epoch that the client registered its last will.
+    pub timestamp: u64,
+}
+
+impl DisconnectPayload {
+    /// Parses a disconnect payload.
+    ///
+    /// Supports the structured JSON payload as well as the legacy free-text payload
+    /// (`client_id: {id} has lost connection`), which is treated as a publisher disconnect.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The payload posted on the disconnect topic.
+    pub fn parse(payload: &str) -> Option<Self> {
+        if let Ok(disconnect_payload) = serde_json::from_str::<DisconnectPayload>(payload) {
+            return Some(disconnect_payload);
+        }
+
+        payload
+            .split_whitespace()
+            .nth(1)
+            .map(|client_id| DisconnectPayload {
+                client_id: client_id.to_string(),
+                role: ClientRole::Publisher,
+                timestamp: 0,
+            })
+    }
+}
+
+/// Alias for the channel that broker updates are forwarded to once monitoring has started.
+type MonitorChannel = Arc<SharedState<Option<mpsc::Sender<MonitorMessage>>>>;
+
+/// Alias for the probed topics, with the channel notified when their probe message arrives.
+type ProbedTopics = Arc<SharedState<HashMap<String, oneshot::Sender<()>>>>;
+
+/// Alias for the identities that clients authenticated as, by client id.
+type ClientIdentities = Arc<SharedState<HashMap<String, String>>>;
 
 /// Handles the connection to a Mosquitto MQTT v5 client.
 pub struct MqttFiveBrokerConnector {
     client: mqtt::AsyncClient,
+    disconnect_topic: String,
+    monitor_channel: MonitorChannel,
+    retained_topics: Arc<SharedState<HashSet<String>>>,
+    probed_topics: ProbedTopics,
+    monitor_qos: i32,
+    monitor_health: MonitorHealth,
+    subscriber_identification: SubscriberIdentification,
 }
 
 impl MqttFiveBrokerConnector {
-    /// Instantiates the Mosquitto broker connector with an mqtt client.
+    /// Builds the MQTT v5 connection options from the connector configuration.
     ///
     /// # Arguments
     ///
-    /// * `client_id` - Id used when creating a new mqtt client.
-    /// * `broker_uri` - The uri of the broker that the client is connecting to.
-    fn new(client_id: String, broker_uri: String) -> Self {
-        let create_opts = mqtt::CreateOptionsBuilder::new()
-            .server_uri(broker_uri)
-            .client_id(client_id)
-            .finalize();
+    /// * `settings` - Session expiry, clean start, keep alive and receive maximum options.
+    /// * `credentials` - Optional credentials used to authenticate with the broker.
+    fn build_connect_options(
+        settings: &MqttConnectionSettings,
+        credentials: Option<&BrokerCredentials>,
+    ) -> mqtt::ConnectOptions {
+        // Sets the last will and testament for pub sub monitor client if there is an unclean
+        // disconnect.
+        let lwt = mqtt::Message::new(
+            "pubsub_monitor_client",
+            "Monitor has lost connection",
+            mqtt::QOS_1,
+        );
 
-        let cli = mqtt::AsyncClient::new(create_opts).unwrap_or_else(|e| {
-            error!("Error creating the client: {e:?}");
-            process::exit(1);
-        });
+        let mut conn_builder = mqtt::ConnectOptionsBuilder::with_mqtt_version(MQTT_VERSION_5);
+        conn_builder
+            .clean_start(settings.clean_start)
+            .keep_alive_interval(Duration::from_secs(settings.keep_alive_interval_secs))
+            .properties(mqtt::properties![
+                mqtt::PropertyCode::SessionExpiryInterval => settings.session_expiry_interval_secs,
+                mqtt::PropertyCode::ReceiveMaximum => settings.receive_maximum
+            ])
+            .will_message(lwt)
+            .automatic_reconnect(MIN_RECONNECT_INTERVAL, MAX_RECONNECT_INTERVAL);
+
+        if let Some(credentials) = credentials {
+            if let Some(username) = &credentials.username {
+                conn_builder.user_name(username.as_str());
+            }
+
+            if let Some(secret) = credentials.secret() {
+                conn_builder.password(secret);
+            }
+        }
 
-        MqttFiveBrokerConnector { client: cli }
+        conn_builder.finalize()
+    }
+
+    /// Reports a change in the connection to the broker to the topic manager, if monitoring has
+    /// started.
+    ///
+    /// # Arguments
+    ///
+    /// * `monitor_channel` - The channel monitor messages are forwarded to.
+    /// * `broker_connection` - Reports the connection to the broker to the topic manager.
+    /// * `connected` - Whether the client is connected to the broker.
+    fn report_connection_state(
+        monitor_channel: &MonitorChannel,
+        broker_connection: &BrokerConnection,
+        connected: bool,
+    ) {
+        if monitor_channel.lock().is_some() {
+            broker_connection.report(connected);
+        }
+    }
+
+    /// Updates the broker statistics if the topic is one of the broker's statistics topics.
+    ///
+    /// Returns whether the topic is a statistics topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The reserved topic the broker posted to.
+    /// * `payload` - The value posted on the reserved topic.
+    /// * `stats` - The broker statistics to update.
+    fn handle_stats_update(topic: &str, payload: &str, stats: &mut BrokerStats) -> bool {
+        let value = payload.trim();
+
+        match topic {
+            SYS_CLIENTS_CONNECTED => stats.connected_clients = value.parse().ok(),
+            SYS_MESSAGES_RECEIVED => stats.messages_received_per_min = value.parse().ok(),
+            SYS_MESSAGES_SENT => stats.messages_sent_per_min = value.parse().ok(),
+            SYS_MESSAGES_DROPPED => stats.messages_dropped = value.parse().ok(),
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Returns the broker timestamp, in seconds since the Unix epoch, of a message the broker posted
+    /// to one of its log topics. Log messages are prefixed with the timestamp, as in
+    /// `1700000000: sub_1 1 topic_1`.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic the broker posted to.
+    /// * `payload` - The message posted on the topic.
+    fn broker_log_timestamp(topic: &str, payload: &str) -> Option<u64> {
+        if !topic.starts_with(BROKER_LOG_PREFIX) {
+            return None;
+        }
+
+        payload
+            .split_whitespace()
+            .next()?
+            .trim_end_matches(':')
+            .parse()
+            .ok()
+    }
+
+    /// Parses the notice the broker posts when a client connects, as in
+    /// `1700000000: New client connected from 127.0.0.1:50000 as sub_1 (p5, c1, k60, u'alice').`
+    ///
+    /// Returns the id of the client and the username it authenticated with, if any. Returns None
+    /// if the notice isn't about a connecting client.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The notice posted by the broker.
+    fn parse_connection_notice(payload: &str) -> Option<(String, Option<String>)> {
+        let (_, connection) = payload.split_once("New client connected from ")?;
+        let (_, client) = connection.split_once(" as ")?;
+        let (client_id, details) = client.split_once(' ')?;
+
+        let username = details
+            .split_once("u'")
+            .and_then(|(_, username)| username.split_once('\''))
+            .map(|(username, _)| username.to_string());
+
+        Some((client_id.to_string(), username))
+    }
+
+    /// Sets the identity the subscriber of an update authenticated as. The client id of the
+    /// subscriber is kept, as several clients may share an identity. The identity is left unset if
+    /// it is unknown.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The update.
+    /// * `identities` - The identities of the clients, by client id.
+    fn identify_subscriber(
+        mut message: MonitorMessage,
+        identities: &HashMap<String, String>,
+    ) -> MonitorMessage {
+        if matches!(
+            message.action,
+            PubSubAction::Subscribe | PubSubAction::Unsubscribe
+        ) {
+            message.identity = message
+                .client_id
+                .as_ref()
+                .and_then(|client_id| identities.get(client_id).cloned());
+        }
+
+        message
     }
 
     /// Maps an update notification from the Mosquitto messaging broker to a [`MonitorMessage`].
     ///
     /// This function translates updates sent to Mosquitto topics used to track subscribe and
-    /// unsubscribe events. In addition, it tracks updates sent to the publisher last will and
+    /// unsubscribe events. In addition, it tracks updates sent to the client last will and
     /// testament topic.
     ///
     /// # Arguments
     ///
     /// * `topic` - The reserved topics used by the broker to provide updates about active topics.
     /// * `payload` - The information posted on the reserved topic.
-    fn handle_subscription_update(topic: String, payload: String) -> Option<MonitorMessage> {
+    /// * `disconnect_topic` - The topic that clients publish their last will and testament to.
+    fn handle_subscription_update(
+        topic: String,
+        payload: String,
+        disconnect_topic: &str,
+    ) -> Option<MonitorMessage> {
         let msg_vec: Vec<&str> = payload.split_whitespace().collect();
+        let client_id = msg_vec.get(1).map(|id| id.to_string());
 
         match topic.as_str() {
             SUBSCRIBE => msg_vec
@@ -70,6 +324,8 @@ impl MqttFiveBrokerConnector {
                     MonitorMessage {
                         context: sub_topic.to_string(),
                         action: PubSubAction::Subscribe,
+                        client_id,
+                        identity: None,
                     }
                 })
                 .or_else(|| {
@@ -83,19 +339,30 @@ impl MqttFiveBrokerConnector {
                     MonitorMessage {
                         context: sub_topic.to_string(),
                         action: PubSubAction::Unsubscribe,
+                        client_id,
+                        identity: None,
                     }
                 })
                 .or_else(|| {
                     warn!("Invalid Unsubscribe: {payload}");
                     None
                 }),
-            LWT_PUBLISHER => msg_vec
-                .get(1)
-                .map(|publisher| {
-                    info!("LWT received from '{publisher}'.");
+            t if t == disconnect_topic => DisconnectPayload::parse(&payload)
+                .map(|disconnect| {
+                    info!(
+                        "LWT received from {:?} '{}'.",
+                        disconnect.role, disconnect.client_id
+                    );
+                    let action = match disconnect.role {
+                        ClientRole::Publisher => PubSubAction::PubDisconnect,
+                        ClientRole::Subscriber => PubSubAction::SubDisconnect,
+                    };
+
                     MonitorMessage {
-                        context: publisher.to_string(),
-                        action: PubSubAction::PubDisconnect,
+                        context: disconnect.client_id,
+                        action,
+                        client_id: None,
+                        identity: None,
                     }
                 })
                 .or_else(|| {
@@ -109,68 +376,141 @@ impl MqttFiveBrokerConnector {
         }
     }
 
-    /// Connects to the Mosquitto messaging broker.
+    /// Builds the dynamic security command that restricts publishing on a topic to a publisher.
     ///
-    /// This function handles client connection to the Mosquitto messaging broker.
+    /// Creates a role that allows publishing on the topic and grants it to the publisher. Relies
+    /// on the broker denying `publishClientSend` by default, so no other client can publish to
+    /// the topic.
     ///
     /// # Arguments
     ///
-    /// * `cb_channel` - Channel used to report back updates from the broker.
-    /// * `message_cb` - Function for how to handle the update message from the broker.
-    fn connect_client(
-        &self,
-        cb_channel: mpsc::Sender<MonitorMessage>,
-        message_cb: fn(MonitorMessage, mpsc::Sender<MonitorMessage>),
-    ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
-        // Sets the messaging callback that sends the monitor message to the given channel.
-        self.client
-            .set_message_callback(move |_cli: &mqtt::AsyncClient, msg| {
-                if let Some(msg) = msg {
-                    let topic = msg.topic().to_string();
-                    let payload = msg.payload_str().to_string();
+    /// * `topic` - The generated topic to restrict publishing on.
+    /// * `publisher_id` - The publisher allowed to publish, matched against broker usernames.
+    fn authorize_publisher_command(topic: &str, publisher_id: &str) -> serde_json::Value {
+        let rolename = format!("{PUBLISHER_ROLE_PREFIX}{topic}");
 
-                    if let Some(message) = Self::handle_subscription_update(topic, payload) {
-                        message_cb(message, cb_channel.clone());
-                    }
+        serde_json::json!({
+            "commands": [
+                {
+                    "command": "createRole",
+                    "rolename": rolename,
+                    "acls": [
+                        {
+                            "acltype": "publishClientSend",
+                            "topic": topic,
+                            "priority": 0,
+                            "allow": true
+                        }
+                    ]
+                },
+                {
+                    "command": "addClientRole",
+                    "username": publisher_id,
+                    "rolename": rolename
                 }
-            });
-
-        // Sets the last will and testament for pub sub monitor client if there is an unclean
-        // disconnect.
-        let lwt = mqtt::Message::new(
-            "pubsub_monitor_client",
-            "Monitor has lost connection",
-            mqtt::QOS_1,
-        );
+            ]
+        })
+    }
 
-        // Sets connection options.
-        let conn_opts = mqtt::ConnectOptionsBuilder::with_mqtt_version(MQTT_VERSION_5)
-            .clean_start(false)
-            .properties(mqtt::properties![mqtt::PropertyCode::SessionExpiryInterval => 3600])
-            .will_message(lwt)
-            .finalize();
+    /// Builds the dynamic security command that removes the publishing restriction on a topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The generated topic to remove the restriction from.
+    fn revoke_publisher_command(topic: &str) -> serde_json::Value {
+        serde_json::json!({
+            "commands": [
+                {
+                    "command": "deleteRole",
+                    "rolename": format!("{PUBLISHER_ROLE_PREFIX}{topic}")
+                }
+            ]
+        })
+    }
 
-        // Connects the client to the messaging broker.
-        if let Err(err) = self.client.connect(conn_opts).wait() {
-            error!("Unable to connect: {err}");
-            process::exit(1);
-        }
+    /// Handles a subscription to a given topic.
+    async fn subscribe(&self, topic_name: String) -> Result<(), AgemoError> {
+        self.client
+            .subscribe(topic_name.clone(), mqtt::QOS_1)
+            .await
+            .map_err(|err| {
+                AgemoError::Broker(format!("unable to subscribe to '{topic_name}': {err}"))
+            })?;
 
         Ok(())
     }
 
-    /// Handles a subscription to a given topic.
-    async fn subscribe(&mut self, topic_name: String) {
-        let _res = self.client.subscribe(topic_name.clone(), mqtt::QOS_1).await;
+    /// Subscribes to a topic the broker reports topic activity on.
+    ///
+    /// If the broker rejects the subscription, monitoring is reported as degraded and the
+    /// subscription is retried in the background until it is made, waiting
+    /// [`MIN_RECONNECT_INTERVAL`] between attempts and doubling the wait up to
+    /// [`MAX_RECONNECT_INTERVAL`].
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The monitor topic to subscribe to.
+    async fn subscribe_monitor_topic(&self, topic: String) {
+        let result = self
+            .client
+            .subscribe(topic.as_str(), self.monitor_qos)
+            .await;
+
+        if let Err(err) = result {
+            warn!("Monitoring is degraded, unable to subscribe to '{topic}', retrying: {err}");
+            self.monitor_health.record_failure(&topic, err.to_string());
+
+            tokio::spawn(Self::retry_monitor_subscription(
+                self.client.clone(),
+                topic,
+                self.monitor_qos,
+                self.monitor_health.clone(),
+            ));
+        } else {
+            self.monitor_health.record_success(&topic);
+        }
+    }
+
+    /// Retries a rejected subscription to a monitor topic with a backoff until it is made.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client to subscribe with.
+    /// * `topic` - The monitor topic to subscribe to.
+    /// * `qos` - The quality of service of the subscription.
+    /// * `monitor_health` - The health of the monitor subscriptions to update.
+    async fn retry_monitor_subscription(
+        client: mqtt::AsyncClient,
+        topic: String,
+        qos: i32,
+        monitor_health: MonitorHealth,
+    ) {
+        let mut backoff = MIN_RECONNECT_INTERVAL;
+
+        loop {
+            tokio::time::sleep(backoff).await;
+
+            match client.subscribe(topic.as_str(), qos).await {
+                Ok(_) => {
+                    info!("Subscribed to '{topic}', monitoring recovered.");
+                    monitor_health.record_success(&topic);
+                    return;
+                }
+                Err(err) => {
+                    monitor_health.record_failure(&topic, err.to_string());
+                    backoff = (backoff * 2).min(MAX_RECONNECT_INTERVAL);
+                }
+            }
+        }
     }
 
     /// Handles a publish of the given message to the given topic.
     async fn publish(
         &self,
         topic_name: String,
-        msg: String,
+        msg: &[u8],
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let msg = mqtt::Message::new(topic_name.clone(), msg, mqtt::QOS_1);
+        let msg = mqtt::Message::new(topic_name, msg, mqtt::QOS_1);
 
         self.client.publish(msg).await?;
 
@@ -180,27 +520,195 @@ impl MqttFiveBrokerConnector {
 
 #[async_trait]
 impl PubSubConnector for MqttFiveBrokerConnector {
-    fn new(client_id: String, uri: String) -> Self {
-        Self::new(client_id, uri)
+    type Config = ConnectorConfig;
+    type Error = AgemoError;
+
+    async fn connect(config: ConnectorConfig) -> Result<Self, AgemoError> {
+        let create_opts = mqtt::CreateOptionsBuilder::new()
+            .server_uri(config.uri.clone())
+            .client_id(config.client_id.clone())
+            .finalize();
+
+        let client = mqtt::AsyncClient::new(create_opts).map_err(|err| {
+            AgemoError::InvalidConfiguration(format!("unable to create the client: {err}"))
+        })?;
+
+        let monitor_channel: MonitorChannel = Arc::new(SharedState::new(None));
+        let cb_monitor_channel = monitor_channel.clone();
+        let disconnect_topic = config.disconnect_topic.clone();
+        let mut ignored_client_ids = config.ignored_client_ids.clone();
+        ignored_client_ids.push(config.client_id.clone());
+        let broker_stats = config.broker_stats.clone();
+        let activity_clock = config.activity_clock.clone();
+        let retained_topics = Arc::new(SharedState::new(HashSet::new()));
+        let cb_retained_topics = retained_topics.clone();
+        let probed_topics: ProbedTopics = Arc::new(SharedState::new(HashMap::new()));
+        let cb_probed_topics = probed_topics.clone();
+        let subscriber_identification = config.subscriber_identification;
+        let client_identities: ClientIdentities = Arc::new(SharedState::new(HashMap::new()));
+
+        // Sets the messaging callback that sends the monitor message to the monitor channel once
+        // monitoring has started.
+        client.set_message_callback(move |_cli: &mqtt::AsyncClient, msg| {
+            // The callback runs on a thread owned by the broker client library.
+            #[cfg(feature = "alloc-profiling")]
+            let _alloc_guard = alloc_profile::enter(Subsystem::Connector);
+
+            if let Some(msg) = msg {
+                let topic = msg.topic().to_string();
+
+                // Probe messages are only received while probing a self-test topic.
+                if msg.payload() == PROBE_PAYLOAD {
+                    if let Some(probe) = cb_probed_topics.lock().remove(&topic) {
+                        let _ = probe.send(());
+                        return;
+                    }
+                }
+
+                let payload = msg.payload_str().to_string();
+
+                if Self::handle_stats_update(&topic, &payload, &mut broker_stats.lock()) {
+                    return;
+                }
+
+                if let Some(activity_clock) = &activity_clock {
+                    if let Some(timestamp_secs) = Self::broker_log_timestamp(&topic, &payload) {
+                        activity_clock.observe(timestamp_secs);
+                    }
+                }
+
+                // Retained messages outside of the reserved topics are only received while listing
+                // the topics on the broker.
+                if msg.retained() && !topic.starts_with('$') {
+                    cb_retained_topics.lock().insert(topic);
+                    return;
+                }
+
+                // Connection notices are only received while subscribers are identified by their
+                // identity.
+                if topic == NOTICES {
+                    if let Some((client_id, username)) = Self::parse_connection_notice(&payload) {
+                        let mut identities = client_identities.lock();
+                        match username {
+                            Some(username) => identities.insert(client_id, username),
+                            None => identities.remove(&client_id),
+                        };
+                    }
+                    return;
+                }
+
+                if let Some(mut message) =
+                    Self::handle_subscription_update(topic, payload, &disconnect_topic)
+                {
+                    // Subscriptions made by the service itself, including this client, don't count
+                    // towards a topic.
+                    if message
+                        .client_id
+                        .as_ref()
+                        .is_some_and(|id| ignored_client_ids.contains(id))
+                    {
+                        return;
+                    }
+
+                    if subscriber_identification == SubscriberIdentification::Identity {
+                        message = Self::identify_subscriber(message, &client_identities.lock());
+                    }
+
+                    if let Some(sender) = cb_monitor_channel.lock().as_ref() {
+                        if let Err(err) =
+                            pubsub_connector::update_topic_information(message, sender.clone())
+                        {
+                            warn!("Unable to forward the update of '{}': {err}", err.0.context);
+                        }
+                    }
+                }
+            }
+        });
+
+        // Flags the broker repeatedly handing this client's session to another client, which
+        // happens when two clients share an id.
+        let cb_client_id = config.client_id.clone();
+        let cb_monitor_channel = monitor_channel.clone();
+        let cb_broker_connection = config.broker_connection.clone();
+        let mut collision_detector = ClientIdCollisionDetector::default();
+        client.set_disconnected_callback(move |_cli, _props, reason| {
+            Self::report_connection_state(&cb_monitor_channel, &cb_broker_connection, false);
+
+            if reason != mqtt::ReasonCode::SessionTakenOver {
+                warn!("Client '{cb_client_id}' was disconnected from the broker: {reason}");
+                return;
+            }
+
+            if collision_detector.record_takeover(Instant::now()) {
+                error!(
+                    "Client id '{cb_client_id}' is repeatedly taken over by another client. Another instance is likely using the same id, set a unique 'connector_client_id_prefix'."
+                );
+            } else {
+                warn!("Session of client '{cb_client_id}' was taken over by another client.");
+            }
+        });
+
+        // Reports the connection state so that topics aren't timed out during a broker outage.
+        let cb_client_id = config.client_id.clone();
+        let cb_monitor_channel = monitor_channel.clone();
+        let cb_broker_connection = config.broker_connection.clone();
+        client.set_connection_lost_callback(move |_cli| {
+            warn!("Client '{cb_client_id}' lost connection to the broker, reconnecting...");
+            Self::report_connection_state(&cb_monitor_channel, &cb_broker_connection, false);
+        });
+
+        let cb_monitor_channel = monitor_channel.clone();
+        let cb_broker_connection = config.broker_connection.clone();
+        client.set_connected_callback(move |_cli| {
+            Self::report_connection_state(&cb_monitor_channel, &cb_broker_connection, true);
+        });
+
+        // Connects the client to the messaging broker.
+        info!("Connecting to MQTT server...");
+        let conn_opts =
+            Self::build_connect_options(&config.mqtt_connection, config.credentials.as_ref());
+
+        client.connect(conn_opts).await.map_err(|err| {
+            AgemoError::Connection(format!("unable to connect to '{}': {err}", config.uri))
+        })?;
+
+        info!(
+            "Created client with id: {} and connection_uri: {}",
+            config.client_id, config.uri
+        );
+
+        Ok(MqttFiveBrokerConnector {
+            client,
+            disconnect_topic: config.disconnect_topic,
+            monitor_channel,
+            retained_topics,
+            probed_topics,
+            monitor_qos: config.mqtt_connection.monitor_qos,
+            monitor_health: config.monitor_health,
+            subscriber_identification,
+        })
     }
 
     async fn monitor_topics(
         &mut self,
         cb_channel: mpsc::Sender<MonitorMessage>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        // Connect to broker with mqtt client. pass message_cb that handles sending data back to subscriber
-        info!("Connecting to MQTT server...");
-        let message_cb = pubsub_connector::update_topic_information;
-        let result = Self::connect_client(self, cb_channel, message_cb);
+        *self.monitor_channel.lock() = Some(cb_channel);
 
-        // TODO: actually handle error
-        if result.is_err() {
-            error!("Failed to connect to MQTT server...");
+        // A rejected subscription degrades monitoring rather than failing it, and is retried.
+        self.subscribe_monitor_topic(SUBSCRIBE.to_string()).await;
+        self.subscribe_monitor_topic(UNSUBSCRIBE.to_string()).await;
+        self.subscribe_monitor_topic(self.disconnect_topic.clone())
+            .await;
+
+        // Clients announce the identity they authenticated as when they connect.
+        if self.subscriber_identification == SubscriberIdentification::Identity {
+            self.subscribe_monitor_topic(NOTICES.to_string()).await;
         }
 
-        Self::subscribe(self, SUBSCRIBE.to_string()).await;
-        Self::subscribe(self, UNSUBSCRIBE.to_string()).await;
-        Self::subscribe(self, LWT_PUBLISHER.to_string()).await;
+        for topic in BROKER_STATS_TOPICS {
+            self.subscribe_monitor_topic(topic.to_string()).await;
+        }
 
         Ok(())
     }
@@ -208,8 +716,325 @@ impl PubSubConnector for MqttFiveBrokerConnector {
     async fn delete_topic(
         &self,
         topic: String,
-        deletion_msg: String,
+        deletion_msg: Arc<str>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        Self::publish(self, topic, deletion_msg).await
+        Self::publish(self, topic, deletion_msg.as_bytes()).await
+    }
+
+    async fn authorize_publisher(
+        &self,
+        topic: String,
+        publisher_id: String,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        info!("Restricting publishing on topic '{topic}' to '{publisher_id}'.");
+        let command = Self::authorize_publisher_command(&topic, &publisher_id);
+
+        Self::publish(
+            self,
+            DYNAMIC_SECURITY.to_string(),
+            command.to_string().as_bytes(),
+        )
+        .await
+    }
+
+    async fn revoke_publisher(
+        &self,
+        topic: String,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let command = Self::revoke_publisher_command(&topic);
+
+        Self::publish(
+            self,
+            DYNAMIC_SECURITY.to_string(),
+            command.to_string().as_bytes(),
+        )
+        .await
+    }
+
+    async fn list_broker_topics(
+        &self,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        // Mosquitto doesn't expose its topic tree, so the topics are collected from the retained
+        // messages the broker sends on a temporary subscription to every topic.
+        self.retained_topics.lock().clear();
+
+        self.subscribe(ALL_TOPICS.to_string()).await?;
+        tokio::time::sleep(RETAINED_COLLECTION_WINDOW).await;
+        self.client.unsubscribe(ALL_TOPICS).await?;
+
+        let topics = self.retained_topics.lock().drain().collect();
+
+        Ok(topics)
+    }
+
+    async fn probe_topic(
+        &self,
+        topic: String,
+        timeout: Duration,
+    ) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+        let (sender, receiver) = oneshot::channel();
+        self.probed_topics.lock().insert(topic.clone(), sender);
+
+        let start = Instant::now();
+        let probe = async {
+            self.subscribe(topic.clone()).await?;
+            Self::publish(self, topic.clone(), PROBE_PAYLOAD).await?;
+
+            tokio::time::timeout(timeout, receiver)
+                .await
+                .map_err(|_| {
+                    AgemoError::Broker(format!(
+                        "probe message on '{topic}' was not delivered within {timeout:?}"
+                    ))
+                })?
+                .map_err(|_| AgemoError::Broker(format!("probe of '{topic}' was dropped")))?;
+
+            Ok::<Duration, Box<dyn std::error::Error + Send + Sync>>(start.elapsed())
+        };
+        let result = probe.await;
+
+        self.probed_topics.lock().remove(&topic);
+        if let Err(err) = self.client.unsubscribe(topic.clone()).await {
+            warn!("Unable to unsubscribe from self-test topic '{topic}': {err}");
+        }
+
+        result
+    }
+
+    async fn check_connection(
+        &self,
+        timeout: Duration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // A half-open connection still reports being connected, so a message is sent through
+        // the broker instead.
+        let topic = format!("{SELF_TEST_TOPIC_PREFIX}{}", Uuid::new_v4());
+
+        self.probe_topic(topic, timeout).await.map(|_| ())
+    }
+
+    async fn remove_orphan_topic(
+        &self,
+        topic: String,
+        deletion_msg: Arc<str>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Self::publish(self, topic.clone(), deletion_msg.as_bytes()).await?;
+
+        self.clear_retained_message(topic).await
+    }
+
+    async fn clear_retained_message(
+        &self,
+        topic: String,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // An empty retained message clears the message retained on the topic.
+        let clear_msg = mqtt::MessageBuilder::new()
+            .topic(topic)
+            .payload(Vec::<u8>::new())
+            .qos(mqtt::QOS_1)
+            .retained(true)
+            .finalize();
+
+        self.client.publish(clear_msg).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod mosquitto_connector_tests {
+    use super::*;
+
+    #[test]
+    fn parse_structured_disconnect_payload() {
+        let payload = r#"{"client_id":"sub_1","role":"subscriber","timestamp":1700000000}"#;
+
+        let expected = DisconnectPayload {
+            client_id: "sub_1".to_string(),
+            role: ClientRole::Subscriber,
+            timestamp: 1700000000,
+        };
+
+        assert_eq!(Some(expected), DisconnectPayload::parse(payload));
+    }
+
+    #[test]
+    fn parse_legacy_disconnect_payload() {
+        let payload = "client_id: pub_1 has lost connection";
+
+        let actual = DisconnectPayload::parse(payload).unwrap();
+
+        assert_eq!("pub_1", actual.client_id);
+        assert_eq!(ClientRole::Publisher, actual.role);
+    }
+
+    #[test]
+    fn handle_update_on_configured_disconnect_topic() {
+        let disconnect_topic = "agemo/disconnect";
+        let payload = r#"{"client_id":"sub_1","role":"subscriber","timestamp":0}"#;
+
+        let actual = MqttFiveBrokerConnector::handle_subscription_update(
+            disconnect_topic.to_string(),
+            payload.to_string(),
+            disconnect_topic,
+        )
+        .unwrap();
+
+        assert_eq!("sub_1", actual.context);
+        assert_eq!(PubSubAction::SubDisconnect, actual.action);
+
+        // The default topic is no longer monitored once a different topic is configured.
+        let ignored = MqttFiveBrokerConnector::handle_subscription_update(
+            DEFAULT_DISCONNECT_TOPIC.to_string(),
+            payload.to_string(),
+            disconnect_topic,
+        );
+
+        assert!(ignored.is_none());
+    }
+
+    #[test]
+    fn handle_subscribe_update_captures_subscriber() {
+        let payload = "1700000000: sub_1 1 topic_1";
+
+        let actual = MqttFiveBrokerConnector::handle_subscription_update(
+            SUBSCRIBE.to_string(),
+            payload.to_string(),
+            DEFAULT_DISCONNECT_TOPIC,
+        )
+        .unwrap();
+
+        assert_eq!("topic_1", actual.context);
+        assert_eq!(PubSubAction::Subscribe, actual.action);
+        assert_eq!(Some("sub_1".to_string()), actual.client_id);
+    }
+
+    #[test]
+    fn parse_connection_notice_captures_username() {
+        assert_eq!(
+            Some(("sub_1".to_string(), Some("alice".to_string()))),
+            MqttFiveBrokerConnector::parse_connection_notice(
+                "1700000000: New client connected from 127.0.0.1:50000 as sub_1 (p5, c1, k60, u'alice')."
+            )
+        );
+        assert_eq!(
+            Some(("sub_2".to_string(), None)),
+            MqttFiveBrokerConnector::parse_connection_notice(
+                "1700000000: New client connected from 127.0.0.1:50001 as sub_2 (p5, c1, k60)."
+            )
+        );
+        assert_eq!(
+            None,
+            MqttFiveBrokerConnector::parse_connection_notice(
+                "1700000000: Client sub_1 closed its connection."
+            )
+        );
+    }
+
+    #[test]
+    fn identify_subscriber_keeps_client_id() {
+        let identities = HashMap::from([("sub_1".to_string(), "alice".to_string())]);
+        let message = |action, context: &str, client_id: Option<&str>| MonitorMessage {
+            context: context.to_string(),
+            action,
+            client_id: client_id.map(str::to_string),
+            identity: None,
+        };
+
+        let actual = MqttFiveBrokerConnector::identify_subscriber(
+            message(PubSubAction::Subscribe, "topic_1", Some("sub_1")),
+            &identities,
+        );
+        assert_eq!(Some("sub_1".to_string()), actual.client_id);
+        assert_eq!(Some("alice".to_string()), actual.identity);
+
+        let actual = MqttFiveBrokerConnector::identify_subscriber(
+            message(PubSubAction::Subscribe, "topic_1", Some("sub_2")),
+            &identities,
+        );
+        assert_eq!(Some("sub_2".to_string()), actual.client_id);
+        assert_eq!(None, actual.identity);
+
+        // Disconnected subscribers are tracked by client id.
+        let actual = MqttFiveBrokerConnector::identify_subscriber(
+            message(PubSubAction::SubDisconnect, "sub_1", None),
+            &identities,
+        );
+        assert_eq!("sub_1", actual.context);
+        assert_eq!(None, actual.identity);
+    }
+
+    #[test]
+    fn broker_log_timestamp_parses_log_messages() {
+        assert_eq!(
+            Some(1700000000),
+            MqttFiveBrokerConnector::broker_log_timestamp(SUBSCRIBE, "1700000000: sub_1 1 topic_1")
+        );
+        assert_eq!(
+            Some(1700000005),
+            MqttFiveBrokerConnector::broker_log_timestamp(UNSUBSCRIBE, "1700000005: sub_1 topic_1")
+        );
+        assert_eq!(
+            None,
+            MqttFiveBrokerConnector::broker_log_timestamp(SUBSCRIBE, "not a timestamp")
+        );
+        assert_eq!(
+            None,
+            MqttFiveBrokerConnector::broker_log_timestamp(SYS_CLIENTS_CONNECTED, "1700000000: 5")
+        );
+    }
+
+    #[test]
+    fn authorize_publisher_command_grants_topic_to_publisher() {
+        let command = MqttFiveBrokerConnector::authorize_publisher_command("topic_1", "pub_1");
+
+        let commands = command["commands"].as_array().unwrap();
+        assert_eq!(2, commands.len());
+
+        assert_eq!("createRole", commands[0]["command"]);
+        assert_eq!("agemo-publisher-topic_1", commands[0]["rolename"]);
+        assert_eq!("publishClientSend", commands[0]["acls"][0]["acltype"]);
+        assert_eq!("topic_1", commands[0]["acls"][0]["topic"]);
+        assert!(commands[0]["acls"][0]["allow"].as_bool().unwrap());
+
+        assert_eq!("addClientRole", commands[1]["command"]);
+        assert_eq!("pub_1", commands[1]["username"]);
+        assert_eq!("agemo-publisher-topic_1", commands[1]["rolename"]);
+    }
+
+    #[test]
+    fn revoke_publisher_command_deletes_role() {
+        let command = MqttFiveBrokerConnector::revoke_publisher_command("topic_1");
+
+        assert_eq!("deleteRole", command["commands"][0]["command"]);
+        assert_eq!(
+            "agemo-publisher-topic_1",
+            command["commands"][0]["rolename"]
+        );
+    }
+
+    #[test]
+    fn handle_stats_update_records_broker_statistics() {
+        let mut stats = BrokerStats::default();
+
+        assert!(MqttFiveBrokerConnector::handle_stats_update(
+            SYS_CLIENTS_CONNECTED,
+            "3",
+            &mut stats
+        ));
+        assert!(MqttFiveBrokerConnector::handle_stats_update(
+            SYS_MESSAGES_RECEIVED,
+            "12.50",
+            &mut stats
+        ));
+        assert!(!MqttFiveBrokerConnector::handle_stats_update(
+            SUBSCRIBE,
+            "1 client_1 1 topic_1",
+            &mut stats
+        ));
+
+        assert_eq!(Some(3), stats.connected_clients);
+        assert_eq!(Some(12.5), stats.messages_received_per_min);
+        assert_eq!(None, stats.messages_sent_per_min);
+        assert_eq!(None, stats.messages_dropped);
     }
 }
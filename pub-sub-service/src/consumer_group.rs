@@ -0,0 +1,110 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Module containing the consumer groups of single consumer topics.
+//!
+//! A topic created in single consumer mode tracks the subscribers that claimed it, and designates
+//! one of them as the active consumer. Only the active consumer is expected to process messages
+//! on the topic, the others stand by in the order they claimed the topic and take over when the
+//! active consumer releases the topic or disconnects.
+
+/// The subscribers that claimed a single consumer topic.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConsumerGroup {
+    /// Members in the order they claimed the topic. The first member is the active consumer.
+    members: Vec<String>,
+}
+
+impl ConsumerGroup {
+    /// Returns the active consumer of the topic, if any subscriber claimed it.
+    pub fn active_consumer(&self) -> Option<&str> {
+        self.members.first().map(String::as_str)
+    }
+
+    /// Returns the number of subscribers in the group, including the active consumer.
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Returns whether no subscriber claimed the topic.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Claims the topic for a subscriber. A subscriber that is not yet in the group joins it, and
+    /// becomes the active consumer if there is none. Returns whether the subscriber is the active
+    /// consumer.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscriber_id` - The client id of the subscriber.
+    pub fn claim(&mut self, subscriber_id: String) -> bool {
+        if !self.members.contains(&subscriber_id) {
+            self.members.push(subscriber_id.clone());
+        }
+
+        self.active_consumer() == Some(subscriber_id.as_str())
+    }
+
+    /// Removes a subscriber from the group. If the subscriber was the active consumer, the
+    /// subscriber that has been waiting the longest takes over. Returns whether the active
+    /// consumer changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscriber_id` - The client id of the subscriber.
+    pub fn release(&mut self, subscriber_id: &str) -> bool {
+        match self
+            .members
+            .iter()
+            .position(|member| member == subscriber_id)
+        {
+            Some(index) => {
+                self.members.remove(index);
+                index == 0
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod consumer_group_tests {
+    use super::*;
+
+    #[test]
+    fn first_claim_is_active_test() {
+        let mut group = ConsumerGroup::default();
+        assert_eq!(None, group.active_consumer());
+
+        assert!(group.claim("sub_a".to_string()));
+        assert!(!group.claim("sub_b".to_string()));
+        assert_eq!(Some("sub_a"), group.active_consumer());
+        assert_eq!(2, group.len());
+
+        // Claiming again doesn't change the order of the group.
+        assert!(group.claim("sub_a".to_string()));
+        assert!(!group.claim("sub_b".to_string()));
+        assert_eq!(2, group.len());
+    }
+
+    #[test]
+    fn release_fails_over_in_claim_order_test() {
+        let mut group = ConsumerGroup::default();
+        group.claim("sub_a".to_string());
+        group.claim("sub_b".to_string());
+        group.claim("sub_c".to_string());
+
+        // Releasing a standby consumer doesn't change the active consumer.
+        assert!(!group.release("sub_b"));
+        assert_eq!(Some("sub_a"), group.active_consumer());
+
+        assert!(group.release("sub_a"));
+        assert_eq!(Some("sub_c"), group.active_consumer());
+
+        assert!(group.release("sub_c"));
+        assert!(group.is_empty());
+        assert!(!group.release("sub_c"));
+    }
+}
@@ -0,0 +1,216 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Deadlines of the gRPC requests handled by the service.
+//!
+//! Every request gets a processing budget, capped by the deadline the caller sent in the
+//! `grpc-timeout` header. The [`DeadlineInterceptor`] attaches the resulting [`RequestDeadline`]
+//! to the request. The handlers that change the topics wait on the shared topic state without
+//! yielding, so the server can't cancel them while they wait; they [`check`] the deadline once
+//! they hold the state, and leave the topics unchanged if the caller no longer waits for them.
+//!
+//! The handlers make no downstream calls on behalf of a request. The publisher callbacks are made
+//! by the topic manager, independently of any request, and are bounded by their own timeout.
+
+use std::time::{Duration, Instant};
+
+use tonic::{metadata::MetadataMap, service::Interceptor, Request, Status};
+
+/// Default maximum time the service spends processing a single request.
+pub const DEFAULT_REQUEST_BUDGET: Duration = Duration::from_secs(10);
+/// Header used by gRPC to carry the timeout of a request.
+const GRPC_TIMEOUT_HEADER: &str = "grpc-timeout";
+/// Maximum number of digits in a `grpc-timeout` value.
+const MAX_GRPC_TIMEOUT_DIGITS: usize = 8;
+
+/// The point in time after which the caller no longer waits for the response to a request.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RequestDeadline(pub Instant);
+
+impl RequestDeadline {
+    /// Returns a deadline exceeded status if the deadline has passed.
+    pub fn check(self) -> Result<(), Status> {
+        if self.0.saturating_duration_since(Instant::now()).is_zero() {
+            return Err(Status::deadline_exceeded("the request deadline has passed"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses the `grpc-timeout` header of a request. Returns None if the header is missing or
+/// malformed.
+///
+/// # Arguments
+///
+/// * `metadata` - The metadata of the request.
+pub fn grpc_timeout(metadata: &MetadataMap) -> Option<Duration> {
+    let value = metadata.get(GRPC_TIMEOUT_HEADER)?.to_str().ok()?;
+
+    if value.len() < 2 || value.len() > MAX_GRPC_TIMEOUT_DIGITS + 1 {
+        return None;
+    }
+
+    let (amount, unit) = value.split_at(value.len() - 1);
+    if !amount.bytes().all(|digit| digit.is_ascii_digit()) {
+        return None;
+    }
+
+    let amount: u64 = amount.parse().ok()?;
+    match unit {
+        "H" => Some(Duration::from_secs(amount * 60 * 60)),
+        "M" => Some(Duration::from_secs(amount * 60)),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
+
+/// Interceptor attaching a [`RequestDeadline`] to every request, at the end of the request budget
+/// or of the caller's timeout if that is sooner.
+#[derive(Clone, Copy, Debug)]
+pub struct DeadlineInterceptor {
+    /// Maximum time the service spends processing a single request.
+    budget: Duration,
+}
+
+impl DeadlineInterceptor {
+    /// Creates a new DeadlineInterceptor.
+    ///
+    /// # Arguments
+    ///
+    /// * `budget` - Maximum time the service spends processing a single request.
+    pub fn new(budget: Duration) -> Self {
+        DeadlineInterceptor { budget }
+    }
+}
+
+impl Default for DeadlineInterceptor {
+    fn default() -> Self {
+        DeadlineInterceptor::new(DEFAULT_REQUEST_BUDGET)
+    }
+}
+
+impl Interceptor for DeadlineInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let timeout = grpc_timeout(request.metadata())
+            .map_or(self.budget, |timeout| timeout.min(self.budget));
+
+        if timeout.is_zero() {
+            return Err(Status::deadline_exceeded(
+                "the request deadline has already passed",
+            ));
+        }
+
+        request
+            .extensions_mut()
+            .insert(RequestDeadline(Instant::now() + timeout));
+
+        Ok(request)
+    }
+}
+
+/// Returns the deadline of a request, or None if it has no deadline.
+///
+/// # Arguments
+///
+/// * `request` - The request to get the deadline of.
+pub fn deadline<T>(request: &Request<T>) -> Option<RequestDeadline> {
+    request.extensions().get::<RequestDeadline>().copied()
+}
+
+/// Returns a deadline exceeded status if the deadline of a request has passed, so that work the
+/// caller no longer waits for is not done. Called by handlers once they hold the shared state they
+/// waited on, as the request has been taken apart by then.
+///
+/// # Arguments
+///
+/// * `deadline` - The deadline of the request, if it has one.
+pub fn check(deadline: Option<RequestDeadline>) -> Result<(), Status> {
+    deadline.map_or(Ok(()), RequestDeadline::check)
+}
+
+#[cfg(test)]
+mod deadline_tests {
+    use super::*;
+
+    fn request_with_timeout(timeout: &str) -> Request<()> {
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert(GRPC_TIMEOUT_HEADER, timeout.parse().unwrap());
+        request
+    }
+
+    #[test]
+    fn grpc_timeout_test() {
+        let timeout = |value: &str| grpc_timeout(request_with_timeout(value).metadata());
+
+        assert_eq!(Some(Duration::from_secs(2 * 60 * 60)), timeout("2H"));
+        assert_eq!(Some(Duration::from_secs(3 * 60)), timeout("3M"));
+        assert_eq!(Some(Duration::from_secs(5)), timeout("5S"));
+        assert_eq!(Some(Duration::from_millis(250)), timeout("250m"));
+        assert_eq!(Some(Duration::from_micros(7)), timeout("7u"));
+        assert_eq!(Some(Duration::from_nanos(99999999)), timeout("99999999n"));
+
+        assert_eq!(None, timeout("5"));
+        assert_eq!(None, timeout("5s"));
+        assert_eq!(None, timeout("-5S"));
+        assert_eq!(None, timeout("123456789S"));
+        assert_eq!(None, grpc_timeout(Request::new(()).metadata()));
+    }
+
+    /// Returns the time left before the deadline of a request.
+    fn remaining<T>(request: &Request<T>) -> Duration {
+        deadline(request)
+            .unwrap()
+            .0
+            .saturating_duration_since(Instant::now())
+    }
+
+    #[test]
+    fn interceptor_caps_deadline_at_budget_test() {
+        let mut interceptor = DeadlineInterceptor::new(Duration::from_secs(1));
+
+        let request = interceptor.call(request_with_timeout("1H")).unwrap();
+        assert!(remaining(&request) <= Duration::from_secs(1));
+
+        let request = interceptor.call(Request::new(())).unwrap();
+        assert!(remaining(&request) <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn interceptor_honors_caller_timeout_test() {
+        let mut interceptor = DeadlineInterceptor::default();
+
+        let request = interceptor.call(request_with_timeout("100m")).unwrap();
+        assert!(remaining(&request) <= Duration::from_millis(100));
+        assert!(check(deadline(&request)).is_ok());
+
+        let err = interceptor.call(request_with_timeout("0S")).unwrap_err();
+        assert_eq!(tonic::Code::DeadlineExceeded, err.code());
+    }
+
+    #[test]
+    fn check_test() {
+        // Requests that didn't go through the interceptor have no deadline.
+        let request = Request::new(());
+        assert_eq!(None, deadline(&request));
+        assert!(check(deadline(&request)).is_ok());
+
+        let mut request = Request::new(());
+        request
+            .extensions_mut()
+            .insert(RequestDeadline(Instant::now()));
+        assert_eq!(
+            tonic::Code::DeadlineExceeded,
+            check(deadline(&request)).unwrap_err().code()
+        );
+
+        let deadline = RequestDeadline(Instant::now() + Duration::from_secs(60));
+        assert!(deadline.check().is_ok());
+    }
+}
@@ -0,0 +1,49 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Error type returned by fallible operations in the pub sub service.
+
+use std::fmt;
+
+/// Enum defining the errors that can occur in the pub sub service.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AgemoError {
+    /// The provided configuration is invalid or incomplete.
+    InvalidConfiguration(String),
+    /// A connection to an external service could not be established.
+    Connection(String),
+    /// The messaging broker rejected or failed a request.
+    Broker(String),
+    /// A recording of managed topics could not be written.
+    Recording(String),
+    /// State could not be written to or read from storage.
+    Storage(String),
+}
+
+impl fmt::Display for AgemoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AgemoError::InvalidConfiguration(msg) => write!(f, "invalid configuration: {msg}"),
+            AgemoError::Connection(msg) => write!(f, "connection error: {msg}"),
+            AgemoError::Broker(msg) => write!(f, "broker error: {msg}"),
+            AgemoError::Recording(msg) => write!(f, "recording error: {msg}"),
+            AgemoError::Storage(msg) => write!(f, "storage error: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for AgemoError {}
+
+#[cfg(test)]
+mod error_tests {
+    use super::*;
+
+    #[test]
+    fn error_converts_to_boxed_error_test() {
+        let err: Box<dyn std::error::Error + Send + Sync> =
+            AgemoError::Connection("broker unreachable".to_string()).into();
+
+        assert_eq!("connection error: broker unreachable", err.to_string());
+    }
+}
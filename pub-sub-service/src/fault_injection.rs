@@ -0,0 +1,150 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Fault injection hooks used to validate resilience to pub sub service hiccups.
+//!
+//! Only compiled with the `fault-injection` feature. The [`FaultInjector`] is consulted by the
+//! [`TopicManager`][crate::topic_manager::TopicManager] and [`PubSubImpl`][crate::pubsub_impl]
+//! and is controlled at runtime through the admin gRPC service. No faults are injected until a
+//! [`FaultConfig`] is set.
+
+use std::time::Duration;
+
+use common::shared_state::SharedState;
+use uuid::Uuid;
+
+use crate::pubsub_connector::{MonitorMessage, PubSubAction};
+
+/// The faults to inject into the pub sub service.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FaultConfig {
+    /// Percentage of monitor messages from the broker connector to drop, from 0 to 100. Only the
+    /// updates the broker reports are dropped, not the messages the service sends itself.
+    pub monitor_drop_percent: u32,
+    /// Delay added before processing each monitor message, delaying publisher callbacks.
+    pub callback_delay: Duration,
+    /// Percentage of `CreateTopic` requests to fail, from 0 to 100.
+    pub create_topic_failure_percent: u32,
+}
+
+/// Decides when to inject faults based on the current [`FaultConfig`].
+#[derive(Debug, Default)]
+pub struct FaultInjector {
+    config: SharedState<FaultConfig>,
+}
+
+impl FaultInjector {
+    /// Instantiates a new FaultInjector that doesn't inject any faults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the faults to inject. Percentages above 100 are treated as 100.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The faults to inject.
+    pub fn set_config(&self, config: FaultConfig) {
+        *self.config.lock() = FaultConfig {
+            monitor_drop_percent: config.monitor_drop_percent.min(100),
+            create_topic_failure_percent: config.create_topic_failure_percent.min(100),
+            ..config
+        };
+    }
+
+    /// Returns the faults currently being injected.
+    pub fn get_config(&self) -> FaultConfig {
+        *self.config.lock()
+    }
+
+    /// Returns whether a monitor message should be dropped. Messages sent by the service itself,
+    /// such as deletions and timeouts, are never dropped, as nothing would resend them.
+    ///
+    /// # Arguments
+    ///
+    /// * `msg` - The monitor message.
+    pub fn drop_monitor_message(&self, msg: &MonitorMessage) -> bool {
+        let from_broker = matches!(
+            msg.action,
+            PubSubAction::Subscribe
+                | PubSubAction::Unsubscribe
+                | PubSubAction::PubDisconnect
+                | PubSubAction::SubDisconnect
+        );
+
+        from_broker && Self::roll(self.get_config().monitor_drop_percent)
+    }
+
+    /// Returns the delay to add before processing the next monitor message.
+    pub fn callback_delay(&self) -> Duration {
+        self.get_config().callback_delay
+    }
+
+    /// Returns whether the next `CreateTopic` request should fail.
+    pub fn fail_create_topic(&self) -> bool {
+        Self::roll(self.get_config().create_topic_failure_percent)
+    }
+
+    /// Returns true with the given percent chance.
+    ///
+    /// # Arguments
+    ///
+    /// * `percent` - The chance of returning true, from 0 to 100.
+    fn roll(percent: u32) -> bool {
+        percent > 0 && Uuid::new_v4().as_u128() % 100 < u128::from(percent)
+    }
+}
+
+#[cfg(test)]
+mod fault_injection_tests {
+    use super::*;
+
+    fn message(action: PubSubAction) -> MonitorMessage {
+        MonitorMessage {
+            context: "topic".to_string(),
+            action,
+            client_id: None,
+            identity: None,
+        }
+    }
+
+    #[test]
+    fn no_faults_by_default_test() {
+        let injector = FaultInjector::new();
+
+        for _ in 0..100 {
+            assert!(!injector.drop_monitor_message(&message(PubSubAction::Subscribe)));
+            assert!(!injector.fail_create_topic());
+        }
+        assert_eq!(Duration::ZERO, injector.callback_delay());
+    }
+
+    #[test]
+    fn full_faults_always_inject_test() {
+        let injector = FaultInjector::new();
+        injector.set_config(FaultConfig {
+            monitor_drop_percent: 100,
+            callback_delay: Duration::from_millis(250),
+            create_topic_failure_percent: 250,
+        });
+
+        for _ in 0..100 {
+            assert!(injector.drop_monitor_message(&message(PubSubAction::Subscribe)));
+            assert!(injector.fail_create_topic());
+        }
+
+        // Messages sent by the service itself are never dropped.
+        for action in [
+            PubSubAction::Delete,
+            PubSubAction::Timeout,
+            PubSubAction::DeferredStop,
+        ] {
+            assert!(!injector.drop_monitor_message(&message(action)));
+        }
+        assert_eq!(Duration::from_millis(250), injector.callback_delay());
+
+        // Percentages are capped at 100.
+        assert_eq!(100, injector.get_config().create_topic_failure_percent);
+    }
+}
@@ -0,0 +1,124 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Module containing the compression of gRPC messages.
+//!
+//! Compression reduces the bandwidth used by metadata-heavy requests on constrained in-vehicle
+//! links. The service can accept compressed requests, compress its responses to clients that
+//! accept it, and compress the management callbacks made to publishers. Messages are only
+//! compressed if the service is built with the `grpc-compression` feature.
+
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use tonic::codec::CompressionEncoding;
+
+use crate::load_config::GrpcCompressionSettings;
+
+/// An encoding used to compress gRPC messages.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GrpcCompressionKind {
+    /// gzip compression.
+    Gzip,
+    /// zstd compression, which is faster than gzip for a similar compression ratio.
+    Zstd,
+}
+
+impl GrpcCompressionKind {
+    /// Returns every supported encoding.
+    pub fn all() -> Vec<Self> {
+        vec![GrpcCompressionKind::Gzip, GrpcCompressionKind::Zstd]
+    }
+
+    /// Returns the tonic encoding of the compression.
+    #[cfg(feature = "grpc-compression")]
+    pub fn encoding(self) -> CompressionEncoding {
+        match self {
+            GrpcCompressionKind::Gzip => CompressionEncoding::Gzip,
+            GrpcCompressionKind::Zstd => CompressionEncoding::Zstd,
+        }
+    }
+}
+
+/// Configures the compression of a gRPC server generated by tonic. The generated servers don't
+/// share a trait, so their `accept_compressed` and `send_compressed` methods are passed in. The
+/// server is returned as is if compression is not configured or the `grpc-compression` feature
+/// is not enabled.
+///
+/// # Arguments
+///
+/// * `server` - The generated server.
+/// * `settings` - The compression settings of the service.
+/// * `accept_compressed` - The `accept_compressed` method of the server.
+/// * `send_compressed` - The `send_compressed` method of the server.
+#[cfg_attr(not(feature = "grpc-compression"), allow(unused_variables))]
+pub fn configure_server<S>(
+    server: S,
+    settings: Option<&GrpcCompressionSettings>,
+    accept_compressed: impl Fn(S, CompressionEncoding) -> S,
+    send_compressed: impl Fn(S, CompressionEncoding) -> S,
+) -> S {
+    match settings {
+        #[cfg(feature = "grpc-compression")]
+        Some(settings) => {
+            let server = settings.accept.iter().fold(server, |server, kind| {
+                accept_compressed(server, kind.encoding())
+            });
+
+            match settings.send {
+                Some(kind) => send_compressed(server, kind.encoding()),
+                None => server,
+            }
+        }
+        _ => server,
+    }
+}
+
+#[cfg(test)]
+mod grpc_compression_tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_grpc_compression_settings_test() {
+        let settings: GrpcCompressionSettings =
+            serde_json::from_value(serde_json::json!({ "send": "zstd" })).unwrap();
+
+        assert_eq!(GrpcCompressionKind::all(), settings.accept);
+        assert_eq!(Some(GrpcCompressionKind::Zstd), settings.send);
+
+        let settings: GrpcCompressionSettings =
+            serde_json::from_value(serde_json::json!({ "accept": ["gzip"] })).unwrap();
+
+        assert_eq!(vec![GrpcCompressionKind::Gzip], settings.accept);
+        assert_eq!(None, settings.send);
+    }
+
+    #[cfg(feature = "grpc-compression")]
+    #[test]
+    fn configure_server_test() {
+        let settings = GrpcCompressionSettings {
+            accept: GrpcCompressionKind::all(),
+            send: Some(GrpcCompressionKind::Gzip),
+        };
+
+        let (accepted, sent) = configure_server(
+            (Vec::new(), Vec::new()),
+            Some(&settings),
+            |(mut accepted, sent): (Vec<_>, Vec<_>), encoding| {
+                accepted.push(encoding);
+                (accepted, sent)
+            },
+            |(accepted, mut sent), encoding| {
+                sent.push(encoding);
+                (accepted, sent)
+            },
+        );
+
+        assert_eq!(
+            vec![CompressionEncoding::Gzip, CompressionEncoding::Zstd],
+            accepted
+        );
+        assert_eq!(vec![CompressionEncoding::Gzip], sent);
+    }
+}
@@ -0,0 +1,290 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Module containing the provider of Chariott's intents, based on
+//! [`proto::intent_brokering::provider`].
+//!
+//! Bridges Chariott's intent API to the dynamically created topics, so that applications using
+//! Chariott's intent broker can find and subscribe to topics without calling the pub sub service
+//! directly. The following intents are fulfilled:
+//! - `Discover`: Returns the pub sub service, with the messaging broker it manages topics on.
+//! - `Read`: Returns the subscription information of the topic given as key.
+//! - `Subscribe`: Counts the channel as a subscriber of each topic given as source. Chariott's
+//!   intent API has no way to unsubscribe, so the subscription is tracked with a lease that the
+//!   application renews by subscribing again.
+
+use std::{collections::HashMap, sync::Arc};
+
+use common::protocol_kind::ProtocolKind;
+use common::shared_state::SharedState;
+use log::info;
+use tonic::{Request, Response, Status};
+
+use proto::intent_brokering::{
+    common::v1::{
+        discover_fulfillment::Service, fulfillment::Fulfillment, intent::Intent, value,
+        DiscoverFulfillment, ReadFulfillment, SubscribeFulfillment, SubscribeIntent, Value,
+    },
+    provider::v1::{provider_service_server::ProviderService, FulfillRequest, FulfillResponse},
+};
+
+use crate::{
+    providers::Clock,
+    pubsub_impl::{self, LeaseConfig},
+    topic_manager::ActiveTopicsMap,
+};
+
+/// Base structure for the provider of Chariott's intents.
+pub struct IntentProviderImpl {
+    /// Handle that points to a shared active topics map.
+    pub active_topics: Arc<SharedState<ActiveTopicsMap>>,
+    /// The uri of the messaging broker.
+    pub uri: String,
+    /// The messaging protocol used by the messaging broker.
+    pub protocol: ProtocolKind,
+    /// The uri of the pub sub service.
+    pub pub_sub_uri: String,
+    /// The kind of communication used by the pub sub service.
+    pub communication_kind: String,
+    /// The reference API file used to generate the pub sub service.
+    pub communication_reference: String,
+    /// Source of time used to expire subscription leases.
+    pub clock: Arc<dyn Clock>,
+    /// Configuration of subscription leases. Subscribe intents are rejected if not set.
+    pub lease_config: Option<LeaseConfig>,
+}
+
+impl IntentProviderImpl {
+    /// Fulfills a discover intent with the pub sub service and its messaging broker.
+    fn discover(&self) -> Fulfillment {
+        let metadata = HashMap::from([
+            ("brokerUri".to_string(), string_value(self.uri.clone())),
+            (
+                "brokerProtocol".to_string(),
+                string_value(self.protocol.to_string()),
+            ),
+        ]);
+
+        Fulfillment::Discover(DiscoverFulfillment {
+            services: vec![Service {
+                url: self.pub_sub_uri.clone(),
+                schema_kind: self.communication_kind.clone(),
+                schema_reference: self.communication_reference.clone(),
+                metadata,
+            }],
+        })
+    }
+
+    /// Fulfills a read intent with the subscription information of a topic, as a JSON object.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic to read the subscription information of.
+    fn read(&self, topic: &str) -> Result<Fulfillment, Status> {
+        let exists = self
+            .active_topics
+            .lock()
+            .get(topic)
+            .is_some_and(|metadata| !metadata.is_deleted());
+
+        if !exists {
+            return Err(Status::not_found(format!("topic '{topic}' does not exist")));
+        }
+
+        let info = serde_json::json!({
+            "topic": topic,
+            "brokerUri": self.uri,
+            "brokerProtocol": self.protocol.to_string(),
+        });
+
+        Ok(Fulfillment::Read(ReadFulfillment {
+            value: Some(string_value(info.to_string())),
+        }))
+    }
+
+    /// Fulfills a subscribe intent by starting or renewing the lease of the channel on each
+    /// topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `intent` - The channel and the topics it subscribes to.
+    fn subscribe(&self, intent: SubscribeIntent) -> Result<Fulfillment, Status> {
+        let Some(lease_config) = &self.lease_config else {
+            return Err(Status::failed_precondition(
+                "subscribe intents require subscription leases to be enabled",
+            ));
+        };
+
+        let now = self.clock.now();
+        for topic in intent.sources {
+            pubsub_impl::renew_lease(
+                &self.active_topics,
+                lease_config,
+                topic,
+                intent.channel_id.clone(),
+                now,
+            )?;
+        }
+
+        Ok(Fulfillment::Subscribe(SubscribeFulfillment {}))
+    }
+}
+
+/// Wraps a string in a Chariott value.
+///
+/// # Arguments
+///
+/// * `string` - The string to wrap.
+fn string_value(string: String) -> Value {
+    Value {
+        value: Some(value::Value::String(string)),
+    }
+}
+
+#[tonic::async_trait]
+impl ProviderService for IntentProviderImpl {
+    /// Fulfills an intent brokered by Chariott.
+    ///
+    /// Returns an unimplemented status for intents that the pub sub service does not provide.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The intent to fulfill.
+    async fn fulfill(
+        &self,
+        request: Request<FulfillRequest>,
+    ) -> Result<Response<FulfillResponse>, Status> {
+        let Some(intent) = request.into_inner().intent.and_then(|intent| intent.intent) else {
+            return Err(Status::invalid_argument("an intent is required"));
+        };
+
+        let fulfillment = match intent {
+            Intent::Discover(_) => self.discover(),
+            Intent::Read(read) => self.read(&read.key)?,
+            Intent::Subscribe(subscribe) => {
+                info!(
+                    "Got a subscribe intent from channel '{}'.",
+                    subscribe.channel_id
+                );
+                self.subscribe(subscribe)?
+            }
+            _ => return Err(Status::unimplemented("intent is not supported")),
+        };
+
+        Ok(Response::new(FulfillResponse {
+            fulfillment: Some(fulfillment),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod intent_provider_impl_tests {
+    use std::{sync::mpsc, time::Duration};
+
+    use proto::intent_brokering::common::v1::{self, ReadIntent};
+    use tonic::Code;
+
+    use super::*;
+    use crate::{
+        providers::ManualClock,
+        pubsub_connector::{MonitorMessage, PubSubAction},
+        topic_manager::TopicMetadata,
+    };
+
+    fn provider(lease_config: Option<LeaseConfig>) -> IntentProviderImpl {
+        let active_topics = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+        active_topics.lock().insert(
+            "topic-0".to_string(),
+            TopicMetadata::new("pub_test".to_string(), 0, None),
+        );
+
+        IntentProviderImpl {
+            active_topics,
+            uri: "test_broker".to_string(),
+            protocol: ProtocolKind::MqttV5,
+            pub_sub_uri: "http://0.0.0.0:50051".to_string(),
+            communication_kind: "grpc+proto".to_string(),
+            communication_reference: "pubsub.v1.pubsub.proto".to_string(),
+            clock: Arc::new(ManualClock::new()),
+            lease_config,
+        }
+    }
+
+    fn request(intent: Intent) -> Request<FulfillRequest> {
+        Request::new(FulfillRequest {
+            intent: Some(v1::Intent {
+                intent: Some(intent),
+            }),
+        })
+    }
+
+    #[tokio::test]
+    async fn fulfill_read_intent_test() {
+        let provider = provider(None);
+
+        let response = provider
+            .fulfill(request(Intent::Read(ReadIntent {
+                key: "topic-0".to_string(),
+            })))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let Some(Fulfillment::Read(read)) = response.fulfillment else {
+            panic!("expected a read fulfillment");
+        };
+        let Some(value::Value::String(info)) = read.value.and_then(|value| value.value) else {
+            panic!("expected a string value");
+        };
+        let info: serde_json::Value = serde_json::from_str(&info).unwrap();
+        assert_eq!("topic-0", info["topic"]);
+        assert_eq!("test_broker", info["brokerUri"]);
+
+        let status = provider
+            .fulfill(request(Intent::Read(ReadIntent {
+                key: "unknown".to_string(),
+            })))
+            .await
+            .unwrap_err();
+        assert_eq!(Code::NotFound, status.code());
+    }
+
+    #[tokio::test]
+    async fn fulfill_subscribe_intent_test() {
+        let subscribe_intent = || {
+            Intent::Subscribe(SubscribeIntent {
+                channel_id: "channel".to_string(),
+                sources: vec!["topic-0".to_string()],
+            })
+        };
+
+        // Subscriptions can't be tracked without leases.
+        let status = provider(None)
+            .fulfill(request(subscribe_intent()))
+            .await
+            .unwrap_err();
+        assert_eq!(Code::FailedPrecondition, status.code());
+
+        let (monitor_sender, monitor_receiver) = mpsc::channel::<MonitorMessage>();
+        let provider = provider(Some(LeaseConfig {
+            duration: Duration::from_secs(30),
+            monitor_sender,
+        }));
+
+        let response = provider
+            .fulfill(request(subscribe_intent()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(matches!(
+            response.fulfillment,
+            Some(Fulfillment::Subscribe(_))
+        ));
+
+        let actual = monitor_receiver.try_recv().unwrap();
+        assert_eq!("topic-0", actual.context);
+        assert_eq!(PubSubAction::Subscribe, actual.action);
+        assert_eq!(Some("channel".to_string()), actual.client_id);
+    }
+}
@@ -0,0 +1,234 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Continuous measurement of the latency of the messaging broker.
+//!
+//! The latency probe periodically asks the broker connector to publish a probe message on a
+//! dedicated managed topic and to wait for it to come back through its own subscription to the
+//! topic. The round trips of the latest probes are kept in a [`LatencyWindow`], whose percentiles
+//! are exported through the service metrics.
+
+use std::{
+    collections::VecDeque,
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+
+use common::shared_state::SharedState;
+use log::{info, warn};
+
+use crate::{
+    providers::Clock,
+    pubsub_connector::{MonitorMessage, PubSubAction},
+    topic_manager::{ActiveTopicsMap, TopicMetadata},
+};
+
+/// The managed topic the latency probe messages are published on.
+pub const LATENCY_PROBE_TOPIC: &str = "agemo/latency-probe";
+
+/// Client id recorded as the publisher of the latency probe topic.
+pub const LATENCY_PROBE_CLIENT_ID: &str = "agemo-latency-probe";
+
+/// Number of round trips the percentiles are computed over.
+pub const LATENCY_WINDOW_SIZE: usize = 256;
+
+/// Percentiles of the round trips of the latest probes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LatencyPercentiles {
+    /// The median round trip.
+    pub p50: Duration,
+    /// The 90th percentile round trip.
+    pub p90: Duration,
+    /// The 99th percentile round trip.
+    pub p99: Duration,
+    /// The number of round trips the percentiles are computed over.
+    pub samples: usize,
+}
+
+/// The round trips of the latest latency probes, and totals over every probe.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LatencyWindow {
+    /// The round trips of the latest delivered probes, oldest first.
+    round_trips: VecDeque<Duration>,
+    /// The number of probes delivered.
+    pub delivered: u64,
+    /// The sum of the round trips of every delivered probe.
+    pub total_round_trip: Duration,
+    /// The number of probes that were not delivered.
+    pub failures: u64,
+    /// Why the last failed probe was not delivered.
+    pub last_error: Option<String>,
+}
+
+impl LatencyWindow {
+    /// Records the result of a probe.
+    ///
+    /// # Arguments
+    ///
+    /// * `result` - How long the probe message took to come back, or why it did not.
+    pub fn record(&mut self, result: Result<Duration, String>) {
+        match result {
+            Ok(round_trip) => {
+                if self.round_trips.len() == LATENCY_WINDOW_SIZE {
+                    self.round_trips.pop_front();
+                }
+                self.round_trips.push_back(round_trip);
+                self.delivered += 1;
+                self.total_round_trip += round_trip;
+            }
+            Err(err) => {
+                self.failures += 1;
+                self.last_error = Some(err);
+            }
+        }
+    }
+
+    /// Returns the percentiles of the round trips of the latest probes, or `None` if no probe was
+    /// delivered.
+    pub fn percentiles(&self) -> Option<LatencyPercentiles> {
+        let mut round_trips: Vec<Duration> = self.round_trips.iter().copied().collect();
+        round_trips.sort();
+
+        // Nearest-rank percentile.
+        let percentile = |p: usize| {
+            let rank = (p * round_trips.len()).div_ceil(100).max(1);
+            round_trips[rank - 1]
+        };
+
+        (!round_trips.is_empty()).then(|| LatencyPercentiles {
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            samples: round_trips.len(),
+        })
+    }
+}
+
+/// Alias for a handle to the round trips of the latency probes, shared between the broker
+/// connector, which records them, and the admin service.
+pub type LatencyProbesHandle = Arc<SharedState<LatencyWindow>>;
+
+/// Creates the latency probe topic if it is not managed, and keeps it from timing out.
+///
+/// # Arguments
+///
+/// * `active_topics` - Handle that points to the shared active topics map.
+/// * `clock` - The clock used to timestamp the topic.
+fn ensure_probe_topic(active_topics: &SharedState<ActiveTopicsMap>, clock: &dyn Clock) {
+    let now = clock.now();
+    let mut active_topics = active_topics.lock();
+
+    match active_topics.get_mut(LATENCY_PROBE_TOPIC) {
+        Some(metadata) if !metadata.is_deleted() => metadata.reset_timeout_at(now),
+        _ => {
+            info!("Creating latency probe topic '{LATENCY_PROBE_TOPIC}'.");
+            active_topics.insert(
+                LATENCY_PROBE_TOPIC.to_string(),
+                TopicMetadata::new_at(LATENCY_PROBE_CLIENT_ID.to_string(), 0, None, now),
+            );
+        }
+    }
+}
+
+/// Periodically asks the broker connector to probe the latency probe topic, until the connector
+/// stops.
+///
+/// # Arguments
+///
+/// * `connector_sender` - Channel used to ask the broker connector to probe the topic.
+/// * `active_topics` - Handle that points to the shared active topics map.
+/// * `clock` - The clock used to timestamp the probe topic.
+/// * `interval` - Interval between two probes.
+pub async fn run_latency_probes(
+    connector_sender: mpsc::Sender<MonitorMessage>,
+    active_topics: Arc<SharedState<ActiveTopicsMap>>,
+    clock: Arc<dyn Clock>,
+    interval: Duration,
+) {
+    loop {
+        tokio::time::sleep(interval).await;
+
+        ensure_probe_topic(&active_topics, clock.as_ref());
+
+        let probe_msg = MonitorMessage {
+            context: LATENCY_PROBE_TOPIC.to_string(),
+            action: PubSubAction::LatencyProbe,
+            client_id: None,
+            identity: None,
+        };
+
+        if connector_sender.send(probe_msg).is_err() {
+            warn!("The broker connector stopped, no longer probing the broker latency.");
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod latency_probe_tests {
+    use super::*;
+    use crate::providers::ManualClock;
+
+    #[test]
+    fn latency_window_percentiles_test() {
+        let mut window = LatencyWindow::default();
+        assert_eq!(None, window.percentiles());
+
+        for millis in (1..=100).rev() {
+            window.record(Ok(Duration::from_millis(millis)));
+        }
+        window.record(Err("probe message was not delivered".to_string()));
+
+        let expected = LatencyPercentiles {
+            p50: Duration::from_millis(50),
+            p90: Duration::from_millis(90),
+            p99: Duration::from_millis(99),
+            samples: 100,
+        };
+        assert_eq!(Some(expected), window.percentiles());
+        assert_eq!(100, window.delivered);
+        assert_eq!(Duration::from_millis(5050), window.total_round_trip);
+        assert_eq!(1, window.failures);
+        assert_eq!(
+            Some("probe message was not delivered".to_string()),
+            window.last_error
+        );
+    }
+
+    #[test]
+    fn latency_window_keeps_latest_round_trips_test() {
+        let mut window = LatencyWindow::default();
+
+        window.record(Ok(Duration::from_secs(10)));
+        for _ in 0..LATENCY_WINDOW_SIZE {
+            window.record(Ok(Duration::from_millis(1)));
+        }
+
+        let percentiles = window.percentiles().unwrap();
+        assert_eq!(LATENCY_WINDOW_SIZE, percentiles.samples);
+        assert_eq!(Duration::from_millis(1), percentiles.p99);
+        assert_eq!(LATENCY_WINDOW_SIZE as u64 + 1, window.delivered);
+    }
+
+    #[test]
+    fn ensure_probe_topic_test() {
+        let clock = ManualClock::new();
+        let active_topics = SharedState::new(ActiveTopicsMap::new());
+
+        ensure_probe_topic(&active_topics, &clock);
+        assert_eq!(
+            LATENCY_PROBE_CLIENT_ID,
+            active_topics.lock()[LATENCY_PROBE_TOPIC].client_id
+        );
+
+        // A deleted probe topic is created again.
+        active_topics
+            .lock()
+            .get_mut(LATENCY_PROBE_TOPIC)
+            .unwrap()
+            .delete();
+        ensure_probe_topic(&active_topics, &clock);
+        assert!(!active_topics.lock()[LATENCY_PROBE_TOPIC].is_deleted());
+    }
+}
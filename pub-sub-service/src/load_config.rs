@@ -4,15 +4,31 @@
 
 //! Loads configuration from external files.
 
-use std::env;
+use std::{collections::HashMap, env, fmt};
 
 use clap::Parser;
-use common::config_utils::{self, ConfigFileMetadata, SvcConfigHomeMetadata};
+use common::{
+    action_casing::ActionCasing,
+    config_utils::{self, ConfigFileMetadata, SvcConfigHomeMetadata},
+    name_policy::DEFAULT_RESERVED_TOPIC_FILTERS,
+};
 use include_dir::{include_dir, Dir};
+use jsonschema::JSONSchema;
 use log::{debug, error};
 use proc_macros::ConfigSource;
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Schema, SchemaObject},
+    schema_for, JsonSchema,
+};
 use serde_derive::{Deserialize, Serialize};
 
+use crate::{
+    grpc_compression::GrpcCompressionKind, pubsub_connector::BrokerCredentials,
+    subscriber_acl::SubscriberIdentification, supervisor::ChannelFailurePolicy,
+    topic_name_codec::TopicNaming,
+};
+
 // Config file stems
 const CONFIG_FILE_STEM: &str = "pub_sub_service_settings";
 const CONSTANTS_FILE_STEM: &str = "constants";
@@ -23,6 +39,10 @@ const YAML_EXT: &str = "yaml";
 // Default config file marker
 const DEFAULT: &str = "default";
 
+// Config schema names
+const SETTINGS_SCHEMA: &str = "settings";
+const CONSTANTS_SCHEMA: &str = "constants";
+
 // Config directory consts
 const CONFIG_DIR: &str = "config";
 const DOT_AGEMO_DIR: &str = ".agemo";
@@ -60,13 +80,19 @@ pub struct CmdConfigOptions {
     /// The log level of the program.
     #[arg(short, long, default_value = "info")]
     pub log_level: String,
+    /// Prints the JSON Schema of the given configuration file and exits. Useful for validation
+    /// and autocomplete when editing configuration files.
+    #[arg(long, value_parser = [SETTINGS_SCHEMA, CONSTANTS_SCHEMA])]
+    pub print_config_schema: Option<String>,
 }
 
 /// Object that contains constants used for establishing connection between services.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct CommunicationConstants {
     /// The topic deletion message constant.
     pub topic_deletion_message: String,
+    /// The topic that clients publish their last will and testament to on unclean disconnect.
+    pub disconnect_topic: String,
     /// String constant for gRPC.
     pub grpc_kind: String,
     /// String constant for MQTT v5.
@@ -77,8 +103,601 @@ pub struct CommunicationConstants {
     pub retry_interval_secs: u64,
 }
 
+/// Object containing MQTT v5 connection options used when connecting to the messaging broker.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(default)]
+pub struct MqttConnectionSettings {
+    /// Interval in seconds that the broker keeps a session after the client disconnects.
+    pub session_expiry_interval_secs: u32,
+    /// Whether the broker should discard any existing session when the client connects.
+    pub clean_start: bool,
+    /// Interval in seconds between keep alive pings sent to the broker.
+    pub keep_alive_interval_secs: u64,
+    /// Maximum number of unacknowledged QoS 1 and QoS 2 messages the client accepts at once. Must
+    /// be at least 1.
+    pub receive_maximum: u16,
+    /// Quality of service, from 0 to 2, of the subscriptions to the topics the broker reports
+    /// topic activity on.
+    pub monitor_qos: i32,
+}
+
+impl Default for MqttConnectionSettings {
+    fn default() -> Self {
+        MqttConnectionSettings {
+            session_expiry_interval_secs: 3600,
+            clean_start: false,
+            keep_alive_interval_secs: 60,
+            receive_maximum: 65535,
+            monitor_qos: 1,
+        }
+    }
+}
+
+/// Object containing the topic names reserved for the messaging broker and other applications,
+/// which generated topics must not use.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(default)]
+pub struct ReservedNameSettings {
+    /// MQTT style topic filters of reserved topics, such as `$SYS/#`.
+    pub topic_filters: Vec<String>,
+    /// Regular expressions of reserved topics.
+    pub patterns: Vec<String>,
+}
+
+impl Default for ReservedNameSettings {
+    fn default() -> Self {
+        ReservedNameSettings {
+            topic_filters: DEFAULT_RESERVED_TOPIC_FILTERS
+                .iter()
+                .map(|filter| filter.to_string())
+                .collect(),
+            patterns: Vec::new(),
+        }
+    }
+}
+
+/// Object containing the settings used to identify the subscribers of topics and to count only the
+/// authorized ones.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct SubscriberAclSettings {
+    /// How the broker connector identifies subscribers. Defaults to their client id.
+    #[serde(default)]
+    pub identification: SubscriberIdentification,
+    /// Subscribers authorized on topics. If set, only authorized subscribers count towards
+    /// starting a topic, and the subscriptions of other subscribers are ignored. Every subscriber
+    /// counts if not set.
+    #[serde(default)]
+    pub authorized_subscribers: Option<Vec<SubscriberAclRule>>,
+}
+
+/// Object authorizing a subscriber on topics.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct SubscriberAclRule {
+    /// The id of the subscriber, as identified by the broker connector, or `*` for any identified
+    /// subscriber.
+    pub subscriber: String,
+    /// MQTT style filters of the topics the subscriber is authorized on.
+    pub topics: Vec<String>,
+}
+
+/// Object selecting a managed topic to record.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct RecordedTopicSettings {
+    /// A generated topic name, or the id of a publisher whose topics are all recorded.
+    pub selector: String,
+    /// JSON Schema of the topic's payloads, stored in the recording. Defaults to any JSON value.
+    #[serde(default)]
+    pub schema: Option<String>,
+}
+
+/// Object containing settings used to record managed topics to an MCAP file.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct RecorderSettings {
+    /// Path of the MCAP file that the recording is written to.
+    pub output_file: String,
+    /// The topics to record. Every managed topic is recorded if empty.
+    #[serde(default)]
+    pub topics: Vec<RecordedTopicSettings>,
+    /// Interval in seconds between checks for newly created topics to record.
+    #[serde(default = "default_refresh_interval_secs")]
+    pub refresh_interval_secs: u64,
+}
+
+/// Default interval in seconds between checks for newly created topics to record.
+fn default_refresh_interval_secs() -> u64 {
+    5
+}
+
+/// Enum defining the cloud services that topics can be bridged to.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CloudBridgeKind {
+    /// Eclipse Hono, through its device registry management API.
+    Hono,
+    /// Azure IoT Hub, through a bridge that manages its message routes.
+    IotHub,
+}
+
+/// Object containing settings used to bridge tagged topics to cloud telemetry ingestion.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct CloudBridgeSettings {
+    /// The cloud service the topics are bridged to.
+    pub kind: CloudBridgeKind,
+    /// Base uri of the HTTP API that provisions the bridged topics, such as
+    /// `http://hono-registry:28080`.
+    pub endpoint: String,
+    /// The Hono tenant the topics are registered in. Required for Hono.
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// The IoT Hub endpoint the routes of the topics deliver to. Required for IoT Hub.
+    #[serde(default)]
+    pub route_endpoint: Option<String>,
+    /// Bearer token sent with every request, if the API requires authentication.
+    #[serde(default)]
+    pub auth_token: Option<String>,
+    /// The tag that marks the topics to bridge. Defaults to `cloud_telemetry`.
+    #[serde(default = "default_cloud_bridge_tag")]
+    pub tag: String,
+}
+
+impl fmt::Debug for CloudBridgeSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The auth token is redacted so it can't leak through logs.
+        f.debug_struct("CloudBridgeSettings")
+            .field("kind", &self.kind)
+            .field("endpoint", &self.endpoint)
+            .field("tenant", &self.tenant)
+            .field("route_endpoint", &self.route_endpoint)
+            .field(
+                "auth_token",
+                &self.auth_token.as_ref().map(|_| "<redacted>"),
+            )
+            .field("tag", &self.tag)
+            .finish()
+    }
+}
+
+/// Default tag of the topics bridged to cloud telemetry ingestion.
+fn default_cloud_bridge_tag() -> String {
+    "cloud_telemetry".to_string()
+}
+
+/// Object containing the limits on the management callbacks made to publishers.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(default)]
+pub struct PublisherCallbackSettings {
+    /// Maximum number of callbacks in progress at once. The callbacks to a management uri are
+    /// still made one at a time, in order.
+    pub max_concurrent_callbacks: usize,
+    /// Number of consecutive failed callbacks to a management uri after which the uri is no
+    /// longer called for the cool-down period.
+    pub failure_threshold: u32,
+    /// Cool-down period in seconds before a single trial callback is made to a failing uri.
+    pub cooldown_secs: u64,
+    /// Time in seconds that callbacks to a management uri must keep failing before the topics of
+    /// the publisher are deleted. Topics are never deleted for failing callbacks if not set.
+    pub delete_after_secs: Option<u64>,
+    /// Casing of the actions sent to publishers, for publisher frameworks that expect lower-case
+    /// actions. Either "upper" or "lower".
+    #[schemars(schema_with = "action_casing_schema")]
+    pub action_casing: ActionCasing,
+    /// Maximum number of publishers labeled by their id in the callback metrics. Publishers past
+    /// the limit are counted together under the `other` label.
+    pub metrics_max_publishers: usize,
+    /// Minimum time in seconds a publisher keeps running after a START action before it may be
+    /// sent a STOP action. STOP actions are not delayed if 0.
+    pub min_active_secs: u64,
+    /// Time in seconds a topic must go without subscribers before its publisher is sent a STOP
+    /// action, so that a subscriber that reconnects within the window doesn't cause a STOP and
+    /// START. STOP actions are not delayed if 0.
+    pub stop_debounce_secs: u64,
+    /// Whether the publisher of a management uri whose callbacks recover after its circuit
+    /// breaker opened is sent a reconciliation callback for each of its topics, restating whether
+    /// the topic should be published on.
+    pub reconcile_on_recovery: bool,
+    /// Encoding used to compress the callbacks, for publishers that accept compressed requests.
+    /// Callbacks are not compressed if not set. Requires the `grpc-compression` feature.
+    pub compression: Option<GrpcCompressionKind>,
+    /// TLS and authentication options of the connections to the callback servers of publishers.
+    /// Callbacks are made over the scheme of the management uri without authentication if not
+    /// set.
+    pub security: Option<CallbackSecuritySettings>,
+    /// TLS and authentication options of the callback servers of specific publishers, by
+    /// publisher id. Replaces `security` for those publishers.
+    pub publisher_security: HashMap<String, CallbackSecuritySettings>,
+}
+
+/// Object containing the TLS and authentication options of the connections to publisher callback
+/// servers.
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(default)]
+pub struct CallbackSecuritySettings {
+    /// Path of a PEM file with the CA certificates that the certificates of callback servers are
+    /// verified against. Requires the `tls` feature.
+    pub ca_bundle: Option<String>,
+    /// Path of a PEM file with the client certificate presented to callback servers that require
+    /// mutual TLS. Requires `client_key` and the `tls` feature.
+    pub client_cert: Option<String>,
+    /// Path of a PEM file with the private key of the client certificate.
+    pub client_key: Option<String>,
+    /// Domain name that the certificates of callback servers are verified against, if it differs
+    /// from the host of their management uri.
+    pub domain_name: Option<String>,
+    /// Token sent as a bearer token in the `authorization` metadata of every callback.
+    pub bearer_token: Option<String>,
+}
+
+impl CallbackSecuritySettings {
+    /// Returns whether any of the TLS options is set.
+    pub fn uses_tls(&self) -> bool {
+        self.ca_bundle.is_some()
+            || self.client_cert.is_some()
+            || self.client_key.is_some()
+            || self.domain_name.is_some()
+    }
+}
+
+impl fmt::Debug for CallbackSecuritySettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The bearer token is redacted so it can't leak through logs.
+        f.debug_struct("CallbackSecuritySettings")
+            .field("ca_bundle", &self.ca_bundle)
+            .field("client_cert", &self.client_cert)
+            .field("client_key", &self.client_key)
+            .field("domain_name", &self.domain_name)
+            .field(
+                "bearer_token",
+                &self.bearer_token.as_ref().map(|_| "<redacted>"),
+            )
+            .finish()
+    }
+}
+
+impl Default for PublisherCallbackSettings {
+    fn default() -> Self {
+        PublisherCallbackSettings {
+            max_concurrent_callbacks: 1,
+            failure_threshold: 5,
+            cooldown_secs: 30,
+            delete_after_secs: None,
+            action_casing: ActionCasing::Upper,
+            metrics_max_publishers: 100,
+            min_active_secs: 0,
+            stop_debounce_secs: 0,
+            reconcile_on_recovery: true,
+            compression: None,
+            security: None,
+            publisher_security: HashMap::new(),
+        }
+    }
+}
+
+/// Object containing a topic template, which sets the policy of the topics of a data domain that
+/// publishers create by referencing the template by name.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(default)]
+pub struct TopicTemplate {
+    /// Prefix of the generated names of the topics, such as `vehicle/signals/`. Generated names
+    /// are not prefixed if not set.
+    pub prefix: Option<String>,
+    /// Quality of service, from 0 to 2, that publishers and subscribers should use on the topics.
+    pub qos: u8,
+    /// Time in seconds that the topics may go without subscribers before their publisher is sent
+    /// a STOP action. Defaults to the 30 seconds of other topics if not set.
+    pub ttl_secs: Option<u64>,
+    /// Kind of schema of the payloads of the topics, such as `json` or `protobuf`.
+    pub schema_kind: Option<String>,
+}
+
+impl Default for TopicTemplate {
+    fn default() -> Self {
+        TopicTemplate {
+            prefix: None,
+            qos: 1,
+            ttl_secs: None,
+            schema_kind: None,
+        }
+    }
+}
+
+/// Object containing a publisher that serves a subject, which the service creates topics for when
+/// subscribers request the subject.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct SubjectPublisher {
+    /// The id of the publisher, used as the publisher id of the topics created for it.
+    pub publisher_id: String,
+    /// Callback uri of the publisher, used to communicate updates from the service.
+    pub management_callback: String,
+}
+
+/// JSON Schema of the casing of the actions sent to publishers, which is defined in the `common`
+/// crate.
+fn action_casing_schema(_: &mut SchemaGenerator) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        enum_values: Some(vec!["upper".into(), "lower".into()]),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// Object containing settings used to find topics on the messaging broker that are unknown to the
+/// service.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct OrphanReconciliationSettings {
+    /// Interval in seconds between comparisons of the broker topics against the managed topics.
+    #[serde(default = "default_reconciliation_interval_secs")]
+    pub interval_secs: u64,
+    /// Whether orphaned topics are removed from the broker. Orphaned topics are only reported if
+    /// false.
+    #[serde(default)]
+    pub remove_orphans: bool,
+}
+
+/// Default interval in seconds between comparisons of the broker topics against managed topics.
+fn default_reconciliation_interval_secs() -> u64 {
+    300
+}
+
+/// Object containing settings used to measure the latency of the messaging broker.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct LatencyProbeSettings {
+    /// Interval in seconds between two probe messages.
+    #[serde(default = "default_latency_probe_interval_secs")]
+    pub interval_secs: u64,
+}
+
+/// Default interval in seconds between two latency probe messages.
+fn default_latency_probe_interval_secs() -> u64 {
+    10
+}
+
+/// Object containing settings used to provide the pub sub intents through Chariott's intent broker.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct ChariottIntentSettings {
+    /// The URI that Chariott's intent broker listens on for requests. (eg. "http://0.0.0.0:4243").
+    pub intent_broker_uri: String,
+    /// Interval in seconds between announcements of the service to the intent broker.
+    #[serde(default = "default_intent_announce_interval_secs")]
+    pub announce_interval_secs: u64,
+}
+
+/// Default interval in seconds between announcements of the service to the intent broker.
+fn default_intent_announce_interval_secs() -> u64 {
+    5
+}
+
+/// Object containing settings used to base topic timeouts on the activity observed on the
+/// messaging broker.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct ActivityClockSettings {
+    /// How many seconds longer than the local time that passed a gap between broker timestamps
+    /// must be to be treated as a suspension.
+    #[serde(default = "default_resume_gap_secs")]
+    pub resume_gap_secs: u64,
+    /// Path of the file that the last broker activity is persisted to, so that a suspension that
+    /// spans a service restart is recognized. Not persisted if not set.
+    #[serde(default)]
+    pub state_file: Option<String>,
+}
+
+/// Default number of seconds that a gap between broker timestamps must exceed the local time that
+/// passed by to be treated as a suspension.
+fn default_resume_gap_secs() -> u64 {
+    30
+}
+
+/// Object containing settings used to compress the gRPC messages of the service.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct GrpcCompressionSettings {
+    /// Encodings of the compressed requests the service accepts. Defaults to every supported
+    /// encoding.
+    #[serde(default = "GrpcCompressionKind::all")]
+    pub accept: Vec<GrpcCompressionKind>,
+    /// Encoding used to compress the responses of the service, to clients that accept it.
+    /// Responses are not compressed if not set.
+    #[serde(default)]
+    pub send: Option<GrpcCompressionKind>,
+}
+
+/// Object containing settings used to bound the number of topics held in memory by spilling the
+/// metadata of cold topics to disk.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct TopicSpillSettings {
+    /// The number of topics held in memory above which the topics without subscribers that have
+    /// been idle the longest are spilled to disk.
+    pub max_in_memory_topics: usize,
+    /// Path of the directory the spilled topics are stored in. Its contents are cleared when the
+    /// service starts.
+    pub directory: String,
+    /// The storage backend the spilled topics are stored in. Defaults to a JSON file per topic.
+    #[serde(default)]
+    pub store: StateStoreKind,
+}
+
+/// Enum defining the storage backends that topics can be persisted in.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StateStoreKind {
+    /// A JSON file per topic in a directory.
+    #[default]
+    JsonFile,
+    /// A sled embedded database in a directory. Requires the `sled-store` feature.
+    Sled,
+    /// A SQLite database file in a directory. Requires the `sqlite-store` feature.
+    Sqlite,
+}
+
+impl StateStoreKind {
+    /// Returns the name of the storage backend, as used in the configuration.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StateStoreKind::JsonFile => "json_file",
+            StateStoreKind::Sled => "sled",
+            StateStoreKind::Sqlite => "sqlite",
+        }
+    }
+}
+
+/// Object containing the settings of a broker connector loaded from a shared library.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct ConnectorLibrarySettings {
+    /// Path of the shared library implementing the connector.
+    pub path: String,
+    /// Options passed to the connector library as is, such as broker specific settings.
+    #[serde(default)]
+    pub options: HashMap<String, String>,
+    /// Topic naming of the broker the connector library connects to. Canonical topic names are
+    /// translated to it, so that generated topic names are the same whichever broker is used.
+    #[serde(default)]
+    pub topic_naming: TopicNaming,
+}
+
+/// The kind of messaging broker the service connects to.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectorKind {
+    /// An MQTT v5 broker, such as Mosquitto. Connected to by the built in connector unless a
+    /// connector library is set.
+    #[default]
+    Mqtt,
+    /// A Kafka cluster. Connected to by the built in connector if the service is built with the
+    /// `kafka` feature, unless a connector library is set.
+    Kafka,
+    /// A NATS server. Requires a connector library.
+    Nats,
+    /// A DDS domain. Joined by the built in connector if the service is built with the `dds`
+    /// feature, unless a connector library is set.
+    Dds,
+}
+
+impl ConnectorKind {
+    /// Returns the name of the kind, as used in the configuration.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConnectorKind::Mqtt => "mqtt",
+            ConnectorKind::Kafka => "kafka",
+            ConnectorKind::Nats => "nats",
+            ConnectorKind::Dds => "dds",
+        }
+    }
+
+    /// Returns whether the service has a built in connector for the broker.
+    pub fn has_built_in_connector(self) -> bool {
+        match self {
+            ConnectorKind::Mqtt => true,
+            ConnectorKind::Kafka => cfg!(feature = "kafka"),
+            ConnectorKind::Dds => cfg!(feature = "dds"),
+            ConnectorKind::Nats => false,
+        }
+    }
+
+    /// Returns the topic naming of the broker.
+    pub fn topic_naming(self) -> TopicNaming {
+        match self {
+            ConnectorKind::Mqtt => TopicNaming::Mqtt,
+            ConnectorKind::Kafka => TopicNaming::Kafka,
+            ConnectorKind::Nats => TopicNaming::Nats,
+            ConnectorKind::Dds => TopicNaming::Dds,
+        }
+    }
+}
+
+/// Object containing the options of a Kafka connector.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct KafkaConnectorSettings {
+    /// Addresses of the bootstrap brokers of the cluster, such as `localhost:9092`.
+    pub brokers: Vec<String>,
+    /// Consumer group of the connector, which is not tracked as a subscriber. Left to the
+    /// connector library if not set.
+    #[serde(default)]
+    pub group_id: Option<String>,
+}
+
+/// Object containing the options of a NATS connector.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct NatsConnectorSettings {
+    /// Path of the credentials file used to authenticate with the server.
+    #[serde(default)]
+    pub credentials_file: Option<String>,
+}
+
+/// Object containing the options of a DDS connector.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct DdsConnectorSettings {
+    /// Id of the DDS domain that the managed topics are published in.
+    #[serde(default)]
+    pub domain_id: u16,
+}
+
+/// Object containing the settings of the broker connector, with the options of each kind of
+/// connector in their own section. Only the section of the selected kind is used.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct ConnectorSettings {
+    /// The kind of messaging broker to connect to.
+    #[serde(default)]
+    pub kind: ConnectorKind,
+    /// Options of the MQTT v5 connector.
+    #[serde(default)]
+    pub mqtt: MqttConnectionSettings,
+    /// Options of the Kafka connector.
+    #[serde(default)]
+    pub kafka: Option<KafkaConnectorSettings>,
+    /// Options of the NATS connector.
+    #[serde(default)]
+    pub nats: Option<NatsConnectorSettings>,
+    /// Options of the DDS connector.
+    #[serde(default)]
+    pub dds: Option<DdsConnectorSettings>,
+    /// Shared library implementing the connector. Its topic naming is the one of the kind of
+    /// broker. Requires the `dynamic-connectors` feature.
+    #[serde(default)]
+    pub library: Option<ConnectorLibrarySettings>,
+}
+
+impl ConnectorSettings {
+    /// Returns the connector library, if set, with the options of the selected kind of connector
+    /// added to the library options. Options set in the library section take precedence.
+    pub fn connector_library(&self) -> Option<ConnectorLibrarySettings> {
+        let mut library_settings = self.library.clone()?;
+        library_settings.topic_naming = self.kind.topic_naming();
+
+        let mut kind_options = Vec::new();
+        match self.kind {
+            ConnectorKind::Mqtt => {}
+            ConnectorKind::Kafka => {
+                if let Some(kafka) = &self.kafka {
+                    kind_options.push(("brokers", Some(kafka.brokers.join(","))));
+                    kind_options.push(("group_id", kafka.group_id.clone()));
+                }
+            }
+            ConnectorKind::Nats => {
+                if let Some(nats) = &self.nats {
+                    kind_options.push(("credentials_file", nats.credentials_file.clone()));
+                }
+            }
+            ConnectorKind::Dds => {
+                if let Some(dds) = &self.dds {
+                    kind_options.push(("domain_id", Some(dds.domain_id.to_string())));
+                }
+            }
+        }
+
+        for (key, value) in kind_options {
+            if let Some(value) = value {
+                library_settings
+                    .options
+                    .entry(key.to_string())
+                    .or_insert(value);
+            }
+        }
+
+        Some(library_settings)
+    }
+}
+
 /// Object containing configuration settings to run the Pub Sub service.
-#[derive(Clone, Debug, Parser, Serialize, Deserialize)]
+#[derive(Clone, Debug, Parser, Serialize, Deserialize, JsonSchema)]
 pub struct Settings {
     /// The IP address and port number that the Pub Sub service listens on for requests.
     pub pub_sub_authority: String,
@@ -92,6 +711,204 @@ pub struct Settings {
     pub name: Option<String>,
     /// The current version of the Pub Sub Service.
     pub version: Option<String>,
+    /// The deployment ring the service runs in, such as "cloud" or "vehicle". Selects the delta
+    /// files that override the communication constants for the ring.
+    #[arg(skip)]
+    #[serde(default)]
+    pub deployment_ring: Option<String>,
+    /// Prefix of the id the service's broker client connects with. The instance id is added so
+    /// that several instances of the service can share a messaging broker.
+    #[arg(skip)]
+    #[serde(default)]
+    pub connector_client_id_prefix: Option<String>,
+    /// Stable id of this instance of the service, added to the broker client id. If not set, an
+    /// id is generated once and persisted next to the activity clock's state file.
+    #[arg(skip)]
+    #[serde(default)]
+    pub connector_instance_id: Option<String>,
+    /// MQTT v5 connection options used when connecting to the messaging broker.
+    #[arg(skip)]
+    #[serde(default)]
+    pub mqtt_connection: MqttConnectionSettings,
+    /// Credentials used to authenticate with the messaging broker.
+    #[arg(skip)]
+    #[serde(default)]
+    pub broker_credentials: Option<BrokerCredentials>,
+    /// Whether the messaging broker should reject publishes to a generated topic from clients
+    /// other than the publisher that created the topic.
+    #[arg(skip)]
+    #[serde(default)]
+    pub enforce_publisher_permissions: bool,
+    /// How subscribers are identified, and which of them count towards starting a topic. Every
+    /// subscriber counts, identified by its client id, if not set.
+    #[arg(skip)]
+    #[serde(default)]
+    pub subscriber_acl: Option<SubscriberAclSettings>,
+    /// Topic names that generated topics must not use. Reserves the broker's `$SYS` and
+    /// `$CONTROL` topics if not set.
+    #[arg(skip)]
+    #[serde(default)]
+    pub reserved_names: ReservedNameSettings,
+    /// Length in seconds of a subscription lease. If set, subscribers can renew leases on topics
+    /// and a subscriber whose lease expires is treated as unsubscribed.
+    #[arg(skip)]
+    #[serde(default)]
+    pub subscription_lease_secs: Option<u64>,
+    /// Time in seconds a placeholder topic, created by a subscription to a topic that no publisher
+    /// has created, may wait for a publisher before it is removed. Defaults to 10 minutes.
+    #[arg(skip)]
+    #[serde(default)]
+    pub placeholder_ttl_secs: Option<u64>,
+    /// Time in seconds a topic may go without subscribers before the service deletes it and sends
+    /// its publisher a DELETE action. Topics are only deleted by their publisher if not set.
+    #[arg(skip)]
+    #[serde(default)]
+    pub max_idle_secs: Option<u64>,
+    /// Periodically finds topics on the messaging broker that are unknown to the service, such as
+    /// topics left over from before a restart. Disabled if not set.
+    #[arg(skip)]
+    #[serde(default)]
+    pub orphan_reconciliation: Option<OrphanReconciliationSettings>,
+    /// Periodically measures the latency of the messaging broker with probe messages on a
+    /// dedicated managed topic. Disabled if not set.
+    #[arg(skip)]
+    #[serde(default)]
+    pub latency_probe: Option<LatencyProbeSettings>,
+    /// What the broker connector does once the channel it receives topic deletion requests on is
+    /// closed. Defaults to exiting the service.
+    #[arg(skip)]
+    #[serde(default)]
+    pub connector_channel_failure: ChannelFailurePolicy,
+    /// Records selected managed topics to an MCAP file. Requires the `mcap-recorder` feature.
+    #[arg(skip)]
+    #[serde(default)]
+    pub recorder: Option<RecorderSettings>,
+    /// Provisions a route to cloud telemetry ingestion for every topic created with the bridge
+    /// tag, and tears it down once the topic is deleted. Requires the `cloud-bridge` feature.
+    #[arg(skip)]
+    #[serde(default)]
+    pub cloud_bridge: Option<CloudBridgeSettings>,
+    /// Bases topic timeouts on the timestamps of messages from the messaging broker instead of
+    /// local time, which drifts across suspend and resume cycles. Uses local time if not set.
+    #[arg(skip)]
+    #[serde(default)]
+    pub activity_clock: Option<ActivityClockSettings>,
+    /// Listens for the sleep signals of systemd-logind over D-Bus to pause topic cleanup while the
+    /// system is suspended. Requires the `power-dbus` feature.
+    #[arg(skip)]
+    #[serde(default)]
+    pub logind_power_events: bool,
+    /// Broker connector loaded from a shared library, used in place of the built in connector.
+    /// Requires the `dynamic-connectors` feature.
+    #[arg(skip)]
+    #[serde(default)]
+    pub connector_library: Option<ConnectorLibrarySettings>,
+    /// Settings of the broker connector, with a section for the options of each kind of
+    /// connector. Takes precedence over `mqtt_connection` and `connector_library`.
+    #[arg(skip)]
+    #[serde(default)]
+    pub connector: Option<ConnectorSettings>,
+    /// Messaging kind of the broker connector to run, such as 'mosquitto', 'kafka', 'dds' or
+    /// 'library'.
+    /// Must name a connector the service was built with. If not set, the connector is selected
+    /// from the connector settings, defaulting to the Mosquitto connector.
+    #[arg(skip)]
+    #[serde(default)]
+    pub messaging_kind: Option<String>,
+    /// Maximum time in milliseconds the service spends processing a single gRPC request. A caller
+    /// that sends a shorter deadline gets that deadline instead. Defaults to 10 seconds.
+    #[arg(skip)]
+    #[serde(default)]
+    pub request_budget_ms: Option<u64>,
+    /// File a snapshot of the state of the service is written to if the service panics.
+    #[arg(skip)]
+    #[serde(default)]
+    pub state_dump_file: Option<String>,
+    /// Limits on the management callbacks made to publishers, including the circuit breaker that
+    /// stops calling a publisher whose callbacks keep failing.
+    #[arg(skip)]
+    #[serde(default)]
+    pub publisher_callbacks: PublisherCallbackSettings,
+    /// Topic templates by name, which publishers reference when creating a topic instead of
+    /// passing the policy of the topic themselves.
+    #[arg(skip)]
+    #[serde(default)]
+    pub topic_templates: HashMap<String, TopicTemplate>,
+    /// Publishers by subject, which the service creates topics for when subscribers request a
+    /// subject with `RequestTopic`.
+    #[arg(skip)]
+    #[serde(default)]
+    pub subject_publishers: HashMap<String, SubjectPublisher>,
+    /// Lets publishers register the subjects they serve with `RegisterPublisher`. Registered
+    /// publishers take precedence over the publishers in `subject_publishers`.
+    #[arg(skip)]
+    #[serde(default)]
+    pub publisher_registration: bool,
+    /// Bounds the number of topics held in memory by spilling the metadata of cold topics to
+    /// disk. Every topic is held in memory if not set.
+    #[arg(skip)]
+    #[serde(default)]
+    pub topic_spill: Option<TopicSpillSettings>,
+    /// Compression of the requests and responses of the gRPC services of the service. Messages are
+    /// not compressed if not set. Requires the `grpc-compression` feature.
+    #[arg(skip)]
+    #[serde(default)]
+    pub grpc_compression: Option<GrpcCompressionSettings>,
+    /// Provides the topics of the service through Chariott's intent broker, for applications that
+    /// use Chariott's intent API. Requires the `chariott-intents` feature.
+    #[arg(skip)]
+    #[serde(default)]
+    pub chariott_intents: Option<ChariottIntentSettings>,
+}
+
+impl Settings {
+    /// Returns the settings of the broker connector. Built from `mqtt_connection` and
+    /// `connector_library` if `connector` is not set.
+    pub fn connector_settings(&self) -> ConnectorSettings {
+        if let Some(connector_settings) = &self.connector {
+            return connector_settings.clone();
+        }
+
+        let kind = match self
+            .connector_library
+            .as_ref()
+            .map(|library| library.topic_naming)
+        {
+            Some(TopicNaming::Kafka) => ConnectorKind::Kafka,
+            Some(TopicNaming::Nats) => ConnectorKind::Nats,
+            Some(TopicNaming::Dds) => ConnectorKind::Dds,
+            Some(TopicNaming::Mqtt) | None => ConnectorKind::Mqtt,
+        };
+
+        ConnectorSettings {
+            kind,
+            mqtt: self.mqtt_connection.clone(),
+            kafka: None,
+            nats: None,
+            dds: None,
+            library: self.connector_library.clone(),
+        }
+    }
+}
+
+/// A layer of configuration that the settings are loaded from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigLayer {
+    /// The name of the layer.
+    pub name: &'static str,
+    /// Where the layer is loaded from.
+    pub source: String,
+    /// Whether the layer was found, and so contributes to the settings.
+    pub found: bool,
+}
+
+/// Returns the metadata of the service's home and config directories.
+fn svc_home_metadata() -> SvcConfigHomeMetadata {
+    SvcConfigHomeMetadata {
+        home_env_var: AGEMO_HOME_ENV_VAR.to_string(),
+        home_dir: DOT_AGEMO_DIR.to_string(),
+        config_dir: CONFIG_DIR.to_string(),
+    }
 }
 
 /// Load configuration given a file and commandline arguments.
@@ -99,10 +916,12 @@ pub struct Settings {
 /// # Arguments
 /// * `config_file_name` - Name of the config file to load override settings from.
 /// * `default_file_name` - Name of default config file to load settings from.
+/// * `deployment_ring` - Optional deployment ring whose delta files override the config files.
 /// * `args` - Optional commandline config arguments.
 pub fn load_config<T>(
     config_file_name: &str,
     default_file_name: &str,
+    deployment_ring: Option<&str>,
     args: Option<CmdConfigOptions>,
 ) -> Result<T, Box<dyn std::error::Error + Send + Sync>>
 where
@@ -112,18 +931,14 @@ where
     let default_config_file = ConfigFileMetadata::new(default_file_name)?;
 
     let default_dir = DEFAULT_DIR;
-
-    let svc_home_metadata = SvcConfigHomeMetadata {
-        home_env_var: AGEMO_HOME_ENV_VAR.to_string(),
-        home_dir: DOT_AGEMO_DIR.to_string(),
-        config_dir: CONFIG_DIR.to_string(),
-    };
+    let svc_home_metadata = svc_home_metadata();
 
     config_utils::load_config(
         &config_file,
         &default_config_file,
         &default_dir,
         &svc_home_metadata,
+        deployment_ring,
         args,
     )
 }
@@ -141,7 +956,8 @@ pub fn load_settings(
     let file_name = format!("{CONFIG_FILE_STEM}.{YAML_EXT}");
     let default_file_name = format!("{CONFIG_FILE_STEM}.{DEFAULT}.{YAML_EXT}");
 
-    let mut settings: Settings = load_config(&file_name, &default_file_name, Some(args))
+    let mut settings: Settings = load_config(&file_name, &default_file_name, None, Some(args))
+        .and_then(validate_config::<Settings>)
         .map_err(|e| {
             format!(
                 "Failed to load required configuration settings due to error: {e}. See --help for more details."
@@ -150,7 +966,7 @@ pub fn load_settings(
 
     debug!("settings config: {:?}", settings);
 
-    if settings.chariott_uri.is_some() {
+    if settings.chariott_uri.is_some() || settings.chariott_intents.is_some() {
         // Get version of the service for Chariott registration if not defined.
         if settings.version.is_none() {
             let version = env!(
@@ -162,12 +978,12 @@ pub fn load_settings(
 
         // Error if name or namespace are not set as they are needed for Chariott registration.
         if settings.namespace.is_none() {
-            error!("Namespace should be set in config if 'chariott_uri' is set.");
+            error!("Namespace should be set if 'chariott_uri' or 'chariott_intents' is set.");
             return Err(Box::from("Namespace not set"));
         }
 
         if settings.name.is_none() {
-            error!("Name should be set in config if 'chariott_uri' is set.");
+            error!("Name should be set if 'chariott_uri' or 'chariott_intents' is set.");
             return Err(Box::from("Name not set"));
         }
     }
@@ -175,16 +991,344 @@ pub fn load_settings(
     Ok(settings)
 }
 
+/// Returns the layers the settings are loaded from, from lowest to highest precedence.
+///
+/// # Arguments
+/// * `args` - Commandline config arguments.
+pub fn settings_layers(args: &CmdConfigOptions) -> Vec<ConfigLayer> {
+    let default_file_name = format!("{CONFIG_FILE_STEM}.{DEFAULT}.{YAML_EXT}");
+    let file_name = format!("{CONFIG_FILE_STEM}.{YAML_EXT}");
+
+    let file_layer = match config_utils::get_config_home_path_from_env(&svc_home_metadata()) {
+        Ok(config_path) => {
+            let path = config_path.join(file_name);
+
+            ConfigLayer {
+                name: "config file",
+                found: path.is_file(),
+                source: path.display().to_string(),
+            }
+        }
+        Err(e) => ConfigLayer {
+            name: "config file",
+            source: format!("unable to locate the config directory: {e}"),
+            found: false,
+        },
+    };
+
+    let overrides: Vec<&str> = [
+        ("--pub-sub-authority", args.pub_sub_authority.is_some()),
+        ("--messaging-uri", args.messaging_uri.is_some()),
+        ("--chariott-uri", args.chariott_uri.is_some()),
+        ("--namespace", args.namespace.is_some()),
+        ("--name", args.name.is_some()),
+        ("--version", args.version.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(option, set)| set.then_some(option))
+    .collect();
+
+    vec![
+        ConfigLayer {
+            name: "defaults",
+            source: format!("built in {default_file_name}"),
+            found: true,
+        },
+        file_layer,
+        ConfigLayer {
+            name: "command line",
+            source: overrides.join(", "),
+            found: !overrides.is_empty(),
+        },
+    ]
+}
+
 /// Load the constants.
 ///
 /// Will attempt to load a configuration from the constants file to an object 'T' where 'T' is an
-/// object representing a collection of constants. Returns error on failure.
-pub fn load_constants<T>() -> Result<T, Box<dyn std::error::Error + Send + Sync>>
+/// object representing a collection of constants. The constants of a deployment ring are
+/// overridden by the ring's delta files, such as `constants.cloud.yaml`. Returns error on failure.
+///
+/// # Arguments
+/// * `deployment_ring` - Optional deployment ring the service runs in.
+pub fn load_constants<T>(
+    deployment_ring: Option<&str>,
+) -> Result<T, Box<dyn std::error::Error + Send + Sync>>
 where
-    T: for<'de> serde::Deserialize<'de>,
+    T: for<'de> serde::Deserialize<'de> + JsonSchema,
 {
     let file_name = format!("{CONSTANTS_FILE_STEM}.{YAML_EXT}");
     let default_file_name = format!("{CONSTANTS_FILE_STEM}.{DEFAULT}.{YAML_EXT}");
 
-    load_config(&file_name, &default_file_name, None)
+    load_config(&file_name, &default_file_name, deployment_ring, None)
+        .and_then(validate_config::<T>)
+}
+
+/// Validates a loaded configuration against the JSON Schema of 'T' and converts it to 'T'.
+///
+/// Returns an error listing the path of every invalid entry on failure.
+///
+/// # Arguments
+/// * `config` - The loaded configuration.
+pub fn validate_config<T>(
+    config: serde_json::Value,
+) -> Result<T, Box<dyn std::error::Error + Send + Sync>>
+where
+    T: for<'de> serde::Deserialize<'de> + JsonSchema,
+{
+    let schema = serde_json::to_value(schema_for!(T))?;
+    let compiled_schema =
+        JSONSchema::compile(&schema).map_err(|e| format!("Invalid configuration schema: {e}"))?;
+
+    if let Err(errors) = compiled_schema.validate(&config) {
+        let messages: Vec<String> = errors
+            .map(|e| {
+                let path = e.instance_path.to_string();
+                let path = if path.is_empty() {
+                    "/".to_string()
+                } else {
+                    path
+                };
+
+                format!("'{path}': {e}")
+            })
+            .collect();
+
+        return Err(Box::from(format!(
+            "invalid configuration: {}",
+            messages.join(", ")
+        )));
+    }
+
+    Ok(serde_json::from_value(config)?)
+}
+
+/// Returns the JSON Schema of the given configuration file as a pretty printed string.
+///
+/// # Arguments
+/// * `schema_name` - The configuration file to describe, either "settings" or "constants".
+pub fn config_schema(
+    schema_name: &str,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let schema = match schema_name {
+        SETTINGS_SCHEMA => schema_for!(Settings),
+        CONSTANTS_SCHEMA => schema_for!(CommunicationConstants),
+        _ => {
+            return Err(Box::from(format!(
+                "Unknown configuration schema '{schema_name}'"
+            )))
+        }
+    };
+
+    Ok(serde_json::to_string_pretty(&schema)?)
+}
+
+#[cfg(test)]
+mod load_config_tests {
+    use super::*;
+
+    #[test]
+    fn mqtt_connection_settings_use_defaults_for_missing_fields_test() {
+        let settings: MqttConnectionSettings =
+            serde_json::from_str(r#"{"clean_start": true, "receive_maximum": 10}"#).unwrap();
+
+        let expected = MqttConnectionSettings {
+            clean_start: true,
+            receive_maximum: 10,
+            ..Default::default()
+        };
+        assert_eq!(expected, settings);
+        assert_eq!(3600, settings.session_expiry_interval_secs);
+        assert_eq!(60, settings.keep_alive_interval_secs);
+    }
+
+    #[test]
+    fn validate_config_reports_invalid_paths_test() {
+        let config = serde_json::json!({
+            "clean_start": "yes",
+            "receive_maximum": 10,
+        });
+
+        let err = validate_config::<MqttConnectionSettings>(config)
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("'/clean_start'"));
+        assert!(!err.contains("'/receive_maximum'"));
+    }
+
+    #[test]
+    fn validate_config_converts_valid_config_test() {
+        let config = serde_json::json!({ "receive_maximum": 10 });
+
+        let settings = validate_config::<MqttConnectionSettings>(config).unwrap();
+
+        assert_eq!(10, settings.receive_maximum);
+        assert_eq!(3600, settings.session_expiry_interval_secs);
+    }
+
+    #[test]
+    fn config_schema_describes_settings_test() {
+        let schema: serde_json::Value =
+            serde_json::from_str(&config_schema(SETTINGS_SCHEMA).unwrap()).unwrap();
+
+        let properties = schema["properties"].as_object().unwrap();
+        assert!(properties.contains_key("pub_sub_authority"));
+        assert!(properties.contains_key("mqtt_connection"));
+
+        assert!(config_schema("unknown").is_err());
+    }
+
+    #[test]
+    fn load_constants_for_deployment_ring_test() {
+        let default_consts = load_constants::<CommunicationConstants>(None).unwrap();
+        let cloud_consts = load_constants::<CommunicationConstants>(Some("cloud")).unwrap();
+        let vehicle_consts = load_constants::<CommunicationConstants>(Some("vehicle")).unwrap();
+
+        assert_eq!(30, cloud_consts.retry_interval_secs);
+        assert_eq!(2, vehicle_consts.retry_interval_secs);
+
+        // Only the constants in the delta files are overridden.
+        assert_eq!(default_consts.grpc_kind, cloud_consts.grpc_kind);
+        assert_eq!(
+            default_consts.retry_interval_secs,
+            load_constants::<CommunicationConstants>(Some("staging"))
+                .unwrap()
+                .retry_interval_secs
+        );
+    }
+
+    #[test]
+    fn settings_layers_test() {
+        let args = CmdConfigOptions::parse_from([
+            "pub-sub-service",
+            "--messaging-uri",
+            "mqtt://0.0.0.0:1883",
+            "--name",
+            "pubsub",
+        ]);
+
+        let layers = settings_layers(&args);
+
+        let names: Vec<&str> = layers.iter().map(|layer| layer.name).collect();
+        assert_eq!(vec!["defaults", "config file", "command line"], names);
+        assert!(layers[0].found);
+        assert!(layers[1].source.ends_with("pub_sub_service_settings.yaml"));
+        assert!(layers[2].found);
+        assert_eq!("--messaging-uri, --name", layers[2].source);
+
+        let layers = settings_layers(&CmdConfigOptions::parse_from(["pub-sub-service"]));
+        assert!(!layers[2].found);
+    }
+
+    fn settings(overrides: serde_json::Value) -> Settings {
+        let mut settings = serde_json::json!({
+            "pub_sub_authority": "0.0.0.0:50051",
+            "messaging_uri": "mqtt://0.0.0.0:1883",
+        });
+        settings
+            .as_object_mut()
+            .unwrap()
+            .extend(overrides.as_object().unwrap().clone());
+
+        validate_config(settings).unwrap()
+    }
+
+    #[test]
+    fn connector_settings_from_connector_section_test() {
+        let settings = settings(serde_json::json!({
+            "connector": {
+                "kind": "kafka",
+                "mqtt": { "keep_alive_interval_secs": 5 },
+                "kafka": { "brokers": ["kafka-0:9092", "kafka-1:9092"] },
+                "library": {
+                    "path": "libagemo_kafka.so",
+                    "options": { "group_id": "agemo" },
+                },
+            },
+            "mqtt_connection": { "keep_alive_interval_secs": 10 },
+        }));
+
+        let connector_settings = settings.connector_settings();
+        assert_eq!(ConnectorKind::Kafka, connector_settings.kind);
+        assert_eq!(5, connector_settings.mqtt.keep_alive_interval_secs);
+
+        let library_settings = connector_settings.connector_library().unwrap();
+        assert_eq!("libagemo_kafka.so", library_settings.path);
+        assert_eq!(TopicNaming::Kafka, library_settings.topic_naming);
+        assert_eq!(
+            HashMap::from([
+                (
+                    "brokers".to_string(),
+                    "kafka-0:9092,kafka-1:9092".to_string()
+                ),
+                ("group_id".to_string(), "agemo".to_string()),
+            ]),
+            library_settings.options
+        );
+    }
+
+    #[test]
+    fn connector_settings_from_legacy_settings_test() {
+        let mqtt_settings = settings(serde_json::json!({
+            "mqtt_connection": { "keep_alive_interval_secs": 10 },
+        }));
+
+        let connector_settings = mqtt_settings.connector_settings();
+        assert_eq!(ConnectorKind::Mqtt, connector_settings.kind);
+        assert_eq!(10, connector_settings.mqtt.keep_alive_interval_secs);
+        assert_eq!(None, connector_settings.connector_library());
+
+        let library_settings = settings(serde_json::json!({
+            "connector_library": { "path": "libagemo_nats.so", "topic_naming": "nats" },
+        }));
+        let connector_settings = library_settings.connector_settings();
+        assert_eq!(ConnectorKind::Nats, connector_settings.kind);
+        assert_eq!(
+            TopicNaming::Nats,
+            connector_settings.connector_library().unwrap().topic_naming
+        );
+    }
+
+    #[test]
+    fn invalid_connector_kind_test() {
+        let config = serde_json::json!({ "kind": "amqp" });
+
+        let err = validate_config::<ConnectorSettings>(config)
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("'/kind'"));
+    }
+
+    #[test]
+    fn subscriber_acl_settings_test() {
+        let acl_settings = settings(serde_json::json!({
+            "subscriber_acl": {
+                "identification": "identity",
+                "authorized_subscribers": [
+                    { "subscriber": "hvac", "topics": ["cabin/#"] },
+                ],
+            },
+        }));
+
+        let subscriber_acl = acl_settings.subscriber_acl.unwrap();
+        assert_eq!(
+            SubscriberIdentification::Identity,
+            subscriber_acl.identification
+        );
+        assert_eq!(
+            vec![SubscriberAclRule {
+                subscriber: "hvac".to_string(),
+                topics: vec!["cabin/#".to_string()],
+            }],
+            subscriber_acl.authorized_subscribers.unwrap()
+        );
+
+        let default_settings = settings(serde_json::json!({ "subscriber_acl": {} }));
+        assert_eq!(
+            Some(SubscriberAclSettings::default()),
+            default_settings.subscriber_acl
+        );
+    }
 }
@@ -9,38 +9,477 @@
 //! of a messaging broker (default is [Mosquitto MQTT broker](https://mosquitto.org/)), utilizing
 //! the broker's publish/subscribe functionality. In addition, the service allows for dynamic topic
 //! management, giving a publisher full control over the lifetime of the topic channel.
+//!
+//! Optional subsystems are gated behind cargo features so that embedded builds can compile out
+//! what they don't use:
+//! - `chariott`: Registration with Chariott.
+//! - `chariott-intents`: Providing the pub sub intents through Chariott's intent broker.
+//! - `mqtt`: The Mosquitto MQTT v5 broker connector.
+//! - `admin-api`: The admin gRPC service.
+//! - `fault-injection`: Fault injection hooks, controlled through the admin gRPC service.
+//! - `mcap-recorder`: Recording of selected managed topics to an MCAP file.
+//! - `tls`: TLS support for the gRPC server.
+//! - `metrics`: Service metrics in the Prometheus text format, served through the admin gRPC
+//!   service.
+//! - `power-dbus`: Listening for systemd-logind power events over D-Bus.
+//! - `dynamic-connectors`: Loading broker connectors from shared libraries.
+//! - `systemd`: Readiness, watchdog and shutdown notifications to the systemd service manager.
+//! - `alloc-profiling`: A counting allocator reporting allocation counts per subsystem through
+//!   the service metrics.
+//! - `grpc-compression`: gzip and zstd compression of gRPC messages and publisher callbacks.
 
 // Tells cargo to warn if a doc comment is missing and should be provided.
 #![warn(missing_docs)]
 
-use std::{str::FromStr, sync::mpsc};
+use std::{
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    time::Duration,
+};
 
 use clap::Parser;
+use common::{
+    name_policy::ReservedNamePolicy, protocol_kind::ProtocolKind, shared_state::SharedState, uri,
+};
 use env_logger::{Builder, Target};
 use log::{error, info, warn, LevelFilter};
 use pubsub_connector::PubSubConnector;
-use tonic::transport::Server;
-use topic_manager::TopicManager;
+use tonic::{service::interceptor::InterceptedService, transport::Server};
+use topic_manager::{GeneratedTopics, OrphanReconciliation, TopicManager};
 
+#[cfg(feature = "admin-api")]
+use proto::admin::v1::admin_server::AdminServer;
+#[cfg(feature = "chariott-intents")]
+use proto::intent_brokering::provider::v1::provider_service_server::ProviderServiceServer;
 use proto::pubsub::v1::pub_sub_server::PubSubServer;
 
+#[cfg(feature = "admin-api")]
+use crate::self_test::SelfTester;
 use crate::{
-    connectors::chariott_connector::{self, ServiceIdentifier},
+    callback_breaker::PublisherCallbacks,
+    callback_security::CallbackSecurity,
+    connector_registry::{ConnectorFactory, ConnectorRegistry, ConnectorRun},
+    error::AgemoError,
+    latency_probe::LatencyProbesHandle,
     load_config::{CmdConfigOptions, CommunicationConstants},
-    pubsub_connector::MonitorMessage,
+    providers::{BrokerActivityClock, SequenceEpochs, UuidTopicIdGenerator},
+    publisher_locator::{ConfiguredPublishers, PublisherDirectory, PublisherLocator},
+    pubsub_connector::{
+        BrokerConnection, BrokerStatsHandle, ConnectorConfig, MonitorHealth, MonitorMessage,
+        PubSubAction, RetainedTopics,
+    },
+    self_test::PendingProbes,
+    subscriber_acl::SubscriberAcl,
+    supervisor::{ChannelFailurePolicy, TaskExit},
+    topic_name_codec::{TopicNameCodec, TopicNaming},
+    topic_spill::TopicSpill,
+};
+#[cfg(feature = "chariott")]
+use crate::{
+    connectors::chariott_connector::{self, ServiceIdentifier},
+    load_config::Settings,
 };
 
+pub mod action_priority;
+#[cfg(feature = "admin-api")]
+pub mod admin_impl;
+#[cfg(feature = "alloc-profiling")]
+pub mod alloc_profile;
+pub mod callback_breaker;
+pub mod callback_security;
+#[cfg(feature = "cloud-bridge")]
+pub mod cloud_bridge;
+pub mod connector_registry;
 pub mod connectors;
+pub mod consumer_group;
+pub mod deadline;
+pub mod error;
+#[cfg(feature = "fault-injection")]
+pub mod fault_injection;
+pub mod grpc_compression;
+#[cfg(feature = "chariott-intents")]
+pub mod intent_provider_impl;
+pub mod latency_probe;
 pub mod load_config;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod power;
+#[cfg(feature = "chariott")]
+pub mod provider_metadata;
+pub mod providers;
+pub mod publisher_locator;
+pub mod publisher_metrics;
 pub mod pubsub_connector;
 pub mod pubsub_impl;
+#[cfg(feature = "mcap-recorder")]
+pub mod recorder;
+pub mod schema;
+pub mod self_test;
+pub mod startup;
+pub mod state_dump;
+pub mod state_store;
+pub mod subscriber_acl;
+pub mod supervisor;
+#[cfg(feature = "systemd")]
+pub mod systemd;
 pub mod topic_manager;
+pub mod topic_name_codec;
+pub mod topic_spill;
+#[cfg(feature = "admin-api")]
+pub mod topology;
+pub mod validation;
+
+/// Connects to the messaging broker and handles requests for the broker from the rest of the
+/// service until the request channel is closed, then ends as set by `channel_failure`.
+///
+/// # Arguments
+///
+/// * `run` - Everything the connector needs to run.
+#[cfg_attr(
+    not(any(
+        feature = "mqtt",
+        feature = "kafka",
+        feature = "dds",
+        feature = "dynamic-connectors"
+    )),
+    allow(dead_code)
+)]
+async fn run_connector<C>(run: ConnectorRun) -> TaskExit
+where
+    C: PubSubConnector<Config = ConnectorConfig> + Send + Sync + 'static,
+{
+    let ConnectorRun {
+        mut connector_config,
+        monitor_sender,
+        connector_receiver,
+        topic_deletion_message,
+        enforce_publisher_permissions,
+        orphan_reconciliation,
+        channel_failure,
+    } = run;
+    let self_test_probes = connector_config.self_test_probes.clone();
+    let latency_probes = connector_config.latency_probes.clone();
+    let monitor_health = connector_config.monitor_health.clone();
+    let retained_topics = connector_config.retained_topics.clone();
+    let deletion_messages = connector_config.deletion_messages.clone();
+    let codec = connector_config.topic_naming.codec();
+    // Reports changes to the broker connection, such as the result of re-validating it after a
+    // resume.
+    let broker_connection =
+        BrokerConnection::new(monitor_sender.clone(), connector_config.client_id.clone());
+    connector_config.broker_connection = broker_connection.clone();
+    let mut connector = match C::connect(connector_config).await {
+        Ok(connector) => connector,
+        Err(err) => {
+            error!("Unable to connect to the messaging broker: {err}");
+            process::exit(1);
+        }
+    };
+
+    // The topic manager only knows the canonical topic names.
+    let monitor_sender = topic_name_codec::decoding_sender(monitor_sender, codec.clone());
+
+    if let Err(err) = connector.monitor_topics(monitor_sender).await {
+        error!("Unable to monitor topics on the messaging broker: {err}");
+        process::exit(1);
+    }
+    monitor_health.record_monitoring();
+
+    // Shared with the orphan topic reconciliations, which run next to the connector loop.
+    let connector = Arc::new(connector);
+    let reconciling = Arc::new(AtomicBool::new(false));
+
+    loop {
+        let connector_msg = connector_receiver.lock().recv();
+
+        match connector_msg {
+            Ok(msg) if msg.action == PubSubAction::Register => {
+                let publisher_id = msg.client_id.unwrap_or_default();
+
+                if let Err(err) = connector
+                    .authorize_publisher(codec.encode(&msg.context), publisher_id)
+                    .await
+                {
+                    warn!(
+                        "Unable to restrict publishing on topic '{}': {err}",
+                        msg.context
+                    );
+                }
+            }
+            Ok(msg) if msg.action == PubSubAction::SelfTest => {
+                let topic = codec.encode(&msg.context);
+                let delivery = connector
+                    .probe_topic(topic.clone(), self_test::PROBE_TIMEOUT)
+                    .await
+                    .map_err(|err| err.to_string());
+                let deletion = connector
+                    .delete_topic(topic, topic_deletion_message.clone())
+                    .await
+                    .map_err(|err| err.to_string());
+
+                self_test_probes
+                    .report(&msg.context, self_test::ProbeResult { delivery, deletion });
+            }
+            Ok(msg) if msg.action == PubSubAction::LatencyProbe => {
+                // The latency probe topic is kept, so that the broker isn't asked to delete it
+                // on every probe.
+                let round_trip = connector
+                    .probe_topic(codec.encode(&msg.context), self_test::PROBE_TIMEOUT)
+                    .await
+                    .map_err(|err| err.to_string());
+
+                if let Err(err) = &round_trip {
+                    warn!("Unable to measure the latency of the messaging broker: {err}");
+                }
+
+                latency_probes.lock().record(round_trip);
+            }
+            Ok(msg) if msg.action == PubSubAction::PowerResume => {
+                // The connection may have been lost while the system was suspended, without the
+                // connector noticing.
+                let _revalidate_handle = tokio::spawn(revalidate_connection(
+                    connector.clone(),
+                    broker_connection.clone(),
+                ));
+            }
+            Ok(msg) if msg.action == PubSubAction::Reconcile => {
+                // Listing the broker topics takes a while, so the connector keeps handling
+                // requests meanwhile.
+                if reconciling.swap(true, Ordering::SeqCst) {
+                    info!(
+                        "Skipping orphan topic reconciliation, the previous one is still running."
+                    );
+                    continue;
+                }
+
+                let _reconcile_handle = tokio::spawn(reconcile_orphan_topics(
+                    connector.clone(),
+                    orphan_reconciliation.clone(),
+                    codec.clone(),
+                    topic_deletion_message.clone(),
+                    reconciling.clone(),
+                ));
+            }
+            Ok(msg) => {
+                let topic = codec.encode(&msg.context);
+
+                if enforce_publisher_permissions {
+                    if let Err(err) = connector.revoke_publisher(topic.clone()).await {
+                        warn!("Unable to release topic '{}': {err}", msg.context);
+                    }
+                }
+
+                // Publishers may override the deletion message of their topics, or opt out of it.
+                if let Some(deletion_message) = deletion_messages
+                    .take(&msg.context)
+                    .resolve(&topic_deletion_message)
+                {
+                    let _res = connector
+                        .delete_topic(topic.clone(), deletion_message)
+                        .await;
+                }
+
+                // The retained message would otherwise be delivered to subscribers of a topic
+                // with the same name.
+                if retained_topics.take(&msg.context) {
+                    if let Err(err) = connector.clear_retained_message(topic).await {
+                        warn!(
+                            "Unable to clear the retained message of topic '{}': {err}",
+                            msg.context
+                        );
+                    }
+                }
+            }
+            Err(err) => {
+                // Topics could no longer be deleted, so the connector must not end silently.
+                return channel_failure.task_exit(format!(
+                    "the request channel from the topic manager closed: {err}"
+                ));
+            }
+        }
+    }
+}
+
+/// Checks that the messaging broker can still be reached after the system resumed, retrying until
+/// it can, then reports the broker as connected so that topic timeouts resume.
+///
+/// # Arguments
+///
+/// * `connector` - The connector to the messaging broker.
+/// * `broker_connection` - Used to report the broker connection to the topic manager.
+async fn revalidate_connection<C>(connector: Arc<C>, broker_connection: BrokerConnection)
+where
+    C: PubSubConnector + Send + Sync + 'static,
+{
+    while let Err(err) = connector.check_connection(self_test::PROBE_TIMEOUT).await {
+        warn!(
+            "Unable to reach the messaging broker after the system resumed, retrying in \
+             {:?}: {err}",
+            power::RESUME_CHECK_INTERVAL
+        );
+        tokio::time::sleep(power::RESUME_CHECK_INTERVAL).await;
+    }
+
+    info!("Re-validated the connection to the messaging broker after the system resumed.");
+    broker_connection.report(true);
+}
+
+/// Reports the topics on the messaging broker that the service generated but no longer knows, and
+/// removes them if set by the reconciliation settings.
+///
+/// # Arguments
+///
+/// * `connector` - The connector to the messaging broker.
+/// * `reconciliation` - The topics known to the service, and what to do with orphan topics.
+/// * `codec` - The codec that maps canonical topic names to broker names.
+/// * `topic_deletion_message` - Message sent to subscribers of a removed topic.
+/// * `reconciling` - Whether a reconciliation is running, cleared once this one ends.
+async fn reconcile_orphan_topics<C>(
+    connector: Arc<C>,
+    reconciliation: OrphanReconciliation,
+    codec: Arc<dyn TopicNameCodec>,
+    topic_deletion_message: Arc<str>,
+    reconciling: Arc<AtomicBool>,
+) where
+    C: PubSubConnector + Send + Sync + 'static,
+{
+    match connector.list_broker_topics().await {
+        Ok(broker_topics) => {
+            for topic in reconciliation.find_orphans(broker_topics, codec.as_ref()) {
+                if !reconciliation.remove_orphans {
+                    warn!("Found topic '{topic}' on the broker that is unknown to the service.");
+                    continue;
+                }
+
+                info!("Removing topic '{topic}' that is unknown to the service.");
+                if let Err(err) = connector
+                    .remove_orphan_topic(topic.clone(), topic_deletion_message.clone())
+                    .await
+                {
+                    warn!("Unable to remove topic '{topic}': {err}");
+                }
+            }
+        }
+        Err(err) => warn!("Unable to list the topics on the messaging broker: {err}"),
+    }
+
+    reconciling.store(false, Ordering::SeqCst);
+}
+
+/// Runs the broker connector selected by the configuration until the request channel is closed.
+///
+/// # Arguments
+///
+/// * `connector_factory` - Starts the selected connector. None if the service was built without
+///                         the connector.
+/// * `run` - Everything the connector needs to run.
+async fn run_broker_connector(
+    connector_factory: Option<ConnectorFactory>,
+    run: ConnectorRun,
+) -> TaskExit {
+    match connector_factory {
+        Some(connector_factory) => connector_factory(run).await,
+        None => {
+            warn!("Built without a messaging broker connector, topics will not be monitored.");
+            TaskExit::Finished
+        }
+    }
+}
+
+/// Connects to Chariott and registers the service so that publishers can discover it.
+///
+/// # Arguments
+///
+/// * `settings` - The service settings, with the Chariott uri and service identifiers set.
+/// * `communication_consts` - Constants used to register the service.
+#[cfg(feature = "chariott")]
+async fn register_with_chariott(
+    settings: &Settings,
+    communication_consts: &CommunicationConstants,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Create service identifiers used to uniquely identify the service.
+    let service_identifier = ServiceIdentifier {
+        namespace: settings.namespace.clone().unwrap(),
+        name: settings.name.clone().unwrap(),
+        version: settings.version.clone().unwrap(),
+    };
+
+    // Connect to and register with Chariott.
+    let mut chariott_client = chariott_connector::connect_to_chariott_with_retry(
+        settings.chariott_uri.as_ref().unwrap(),
+        communication_consts.retry_interval_secs,
+    )
+    .await?;
+
+    // Register the metadata of the service as its own entry, so that clients can check that the
+    // service suits them before calling it.
+    let metadata_identifier = ServiceIdentifier {
+        namespace: service_identifier.namespace.clone(),
+        name: format!(
+            "{}.{}",
+            service_identifier.name,
+            provider_metadata::METADATA_SERVICE_SUFFIX
+        ),
+        version: service_identifier.version.clone(),
+    };
+    let metadata =
+        provider_metadata::ProviderMetadata::new(settings, &communication_consts.mqtt_v5_kind);
+
+    // Register the schema as its own entry, so that clients can find where to fetch it.
+    let schema_identifier = ServiceIdentifier {
+        namespace: service_identifier.namespace.clone(),
+        name: format!(
+            "{}.{}",
+            service_identifier.name,
+            schema::SCHEMA_SERVICE_SUFFIX
+        ),
+        version: service_identifier.version.clone(),
+    };
+
+    chariott_connector::register_with_chariott(
+        &mut chariott_client,
+        &settings.pub_sub_authority,
+        service_identifier,
+        &communication_consts.grpc_kind,
+        &communication_consts.pub_sub_reference,
+    )
+    .await?;
+
+    chariott_connector::register_with_chariott(
+        &mut chariott_client,
+        &settings.pub_sub_authority,
+        schema_identifier,
+        schema::SCHEMA_KIND,
+        &schema::schema_reference()?,
+    )
+    .await?;
+
+    chariott_connector::register_with_chariott(
+        &mut chariott_client,
+        &settings.pub_sub_authority,
+        metadata_identifier,
+        provider_metadata::METADATA_KIND,
+        &metadata.to_json()?,
+    )
+    .await
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     // Load command line arguments if any.
     let parsed_args = CmdConfigOptions::parse();
 
+    // Print the requested configuration schema instead of running the service.
+    if let Some(schema_name) = &parsed_args.print_config_schema {
+        println!("{}", load_config::config_schema(schema_name)?);
+        return Ok(());
+    }
+
     // Get log level. Defaults to info.
     let log_level =
         LevelFilter::from_str(&parsed_args.log_level).expect("Could not parse log level");
@@ -51,89 +490,544 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         .target(Target::Stdout)
         .init();
 
-    // Load settings in from config file.
+    // Load settings in from config file, and fail fast on common misconfigurations.
+    let config_layers = load_config::settings_layers(&parsed_args);
     let settings = load_config::load_settings(parsed_args)?;
-    let communication_consts = load_config::load_constants::<CommunicationConstants>()?;
+    let communication_consts =
+        load_config::load_constants::<CommunicationConstants>(settings.deployment_ring.as_deref())?;
+    if let Err(err) = startup::check_settings(&settings) {
+        error!("{err}");
+        return Err(err.into());
+    }
+
+    // Summarize how the service is set up, including whether the messaging broker is reachable.
+    let probe_uri = settings.messaging_uri.clone();
+    let broker_probe = tokio::task::spawn_blocking(move || {
+        startup::probe_broker(&probe_uri, startup::BROKER_PROBE_TIMEOUT)
+    })
+    .await?;
+    if let startup::BrokerProbe::Unreachable(reason) = &broker_probe {
+        warn!(
+            "The messaging broker at '{}' is not reachable ({reason}). Check that the broker is \
+             running and that 'messaging_uri' is correct.",
+            settings.messaging_uri
+        );
+    }
+    startup::StartupSummary::new(&settings, config_layers, broker_probe).log();
 
     // Initialize pub sub service
-    let topic_manager = TopicManager::new();
+    let activity_clock = settings
+        .activity_clock
+        .as_ref()
+        .map(|activity_clock_settings| {
+            Arc::new(BrokerActivityClock::new(
+                Duration::from_secs(activity_clock_settings.resume_gap_secs),
+                activity_clock_settings
+                    .state_file
+                    .as_ref()
+                    .map(PathBuf::from),
+            ))
+        });
+    let topic_manager = match &activity_clock {
+        Some(activity_clock) => TopicManager::with_clock(activity_clock.clone()),
+        None => TopicManager::new(),
+    }
+    .with_publisher_callbacks(
+        PublisherCallbacks::new(&settings.publisher_callbacks).with_security(
+            CallbackSecurity::from_settings(&settings.publisher_callbacks)?,
+        ),
+    )
+    .with_placeholder_ttl(
+        settings
+            .placeholder_ttl_secs
+            .map_or(topic_manager::DEFAULT_PLACEHOLDER_TTL, Duration::from_secs),
+    )
+    .with_max_idle(settings.max_idle_secs.map(Duration::from_secs));
+
+    // Count only the subscribers authorized on a topic towards starting it.
+    let topic_manager = match settings.subscriber_acl.as_ref().and_then(|subscriber_acl| {
+        subscriber_acl
+            .authorized_subscribers
+            .as_ref()
+            .map(|authorized_subscribers| (subscriber_acl.identification, authorized_subscribers))
+    }) {
+        Some((identification, authorized_subscribers)) => topic_manager
+            .with_subscriber_acl(SubscriberAcl::new(identification, authorized_subscribers)),
+        None => topic_manager,
+    };
+
+    // Spill the metadata of cold topics to disk once too many topics are held in memory.
+    let topic_manager = match &settings.topic_spill {
+        Some(topic_spill_settings) => {
+            let store = state_store::open_state_store(
+                topic_spill_settings.store,
+                PathBuf::from(&topic_spill_settings.directory),
+            )?;
+            topic_manager.with_topic_spill(Arc::new(TopicSpill::new(
+                store,
+                topic_spill_settings.max_in_memory_topics,
+            )))
+        }
+        None => topic_manager,
+    };
+
+    // Write a snapshot of the state of the service to a file if the service panics.
+    if let Some(state_dump_file) = &settings.state_dump_file {
+        state_dump::install_panic_hook(
+            topic_manager.get_state_dump_source(),
+            PathBuf::from(state_dump_file),
+        );
+    }
     let broker_uri = settings.messaging_uri.clone();
-    let broker_protocol = communication_consts.mqtt_v5_kind.clone();
+    let broker_protocol = communication_consts
+        .mqtt_v5_kind
+        .parse::<ProtocolKind>()
+        .map_err(|e| AgemoError::InvalidConfiguration(format!("invalid broker protocol: {e}")))?;
 
     info!("Setting up deletion channel...");
     let (deletion_sender, deletion_receiver) = mpsc::channel::<MonitorMessage>();
 
     info!("Getting sender from monitor...");
     let connector_sender = topic_manager.monitor(deletion_sender.clone()).await;
+    #[cfg(feature = "admin-api")]
+    let power_sender = connector_sender.clone();
+
+    // Pause topic cleanup while the system is suspended.
+    if settings.logind_power_events {
+        #[cfg(feature = "power-dbus")]
+        {
+            let logind_sender = connector_sender.clone();
+
+            let _power_handle = tokio::spawn(async move {
+                if let Err(err) = power::watch_logind(logind_sender).await {
+                    error!("Stopped listening for power events: {err}");
+                }
+            });
+        }
+
+        #[cfg(not(feature = "power-dbus"))]
+        warn!("'logind_power_events' is set, but the service was built without the 'power-dbus' feature.");
+    }
+
+    // Statistics reported by the messaging broker, shared with the admin service.
+    let broker_stats = BrokerStatsHandle::default();
+    // Self-test probes, shared between the admin service and the broker connector.
+    let self_test_probes = PendingProbes::default();
+    // Health of the broker connector's monitor subscriptions, shared with the admin service.
+    let monitor_health = MonitorHealth::default();
+    // Round trips of the latency probes, shared between the broker connector and the admin service.
+    let latency_probes = LatencyProbesHandle::default();
+    // Retained configuration topics, shared between the pub sub service and the broker connector.
+    let retained_topics = RetainedTopics::default();
+    // The connector section, or the legacy connector settings.
+    let connector_settings = settings.connector_settings();
+    let connector_library = connector_settings.connector_library();
+    // The broker connector, selected by the messaging kind among the connectors built in.
+    let connector_registry = ConnectorRegistry::built_in();
+    let messaging_kind = connector_registry.select(
+        settings.messaging_kind.as_deref(),
+        &settings.messaging_uri,
+        &connector_settings,
+    )?;
+    let connector_factory = connector_registry.get(&messaging_kind);
+    info!("Using the '{messaging_kind}' broker connector.");
+    // Options of the Kafka cluster, if the built in Kafka connector is used.
+    let kafka_settings = (messaging_kind == connector_registry::KAFKA)
+        .then(|| connector_settings.kafka.clone())
+        .flatten();
+    // Options of the DDS domain, if the built in DDS connector is used.
+    let dds_settings = (messaging_kind == connector_registry::DDS)
+        .then(|| connector_settings.dds.clone().unwrap_or_default());
+    // Topic naming of the messaging broker. Only connector libraries and the Kafka and DDS
+    // connectors use a naming other than MQTT's.
+    let topic_naming = match messaging_kind.as_str() {
+        connector_registry::LIBRARY => connector_library
+            .as_ref()
+            .map_or_else(TopicNaming::default, |library| library.topic_naming),
+        connector_registry::KAFKA => TopicNaming::Kafka,
+        connector_registry::DDS => TopicNaming::Dds,
+        _ => TopicNaming::default(),
+    };
+
+    // Generated topics must not collide with reserved topics, including the disconnect topic.
+    let mut reserved_topic_filters = settings.reserved_names.topic_filters.clone();
+    reserved_topic_filters.push(communication_consts.disconnect_topic.clone());
+    let name_policy =
+        ReservedNamePolicy::new(reserved_topic_filters, &settings.reserved_names.patterns)
+            .map_err(|e| {
+                AgemoError::InvalidConfiguration(format!("invalid reserved name pattern: {e}"))
+            })?;
 
     let addr = settings.pub_sub_authority.parse()?;
+    let lease_config = settings
+        .subscription_lease_secs
+        .map(|secs| pubsub_impl::LeaseConfig {
+            duration: Duration::from_secs(secs),
+            monitor_sender: connector_sender.clone(),
+        });
+
+    // Provider of Chariott's intents, bridging applications using Chariott's intent API to the
+    // managed topics.
+    #[cfg(feature = "chariott-intents")]
+    let intent_provider = settings.chariott_intents.as_ref().map(|_| {
+        let pub_sub_uri = uri::authority_uri(&settings.pub_sub_authority);
+
+        intent_provider_impl::IntentProviderImpl {
+            active_topics: topic_manager.get_active_topics_handle(),
+            uri: broker_uri.clone(),
+            protocol: broker_protocol,
+            pub_sub_uri,
+            communication_kind: communication_consts.grpc_kind.clone(),
+            communication_reference: communication_consts.pub_sub_reference.clone(),
+            clock: topic_manager.get_clock_handle(),
+            lease_config: lease_config.clone(),
+        }
+    });
+
+    // Publishers of the subjects that subscribers request topics for, which can register the
+    // subjects they serve if publisher registration is enabled.
+    let configured_publishers: Arc<dyn PublisherLocator> = Arc::new(ConfiguredPublishers::new(
+        settings.subject_publishers.clone(),
+    ));
+    let publisher_directory = settings
+        .publisher_registration
+        .then(|| Arc::new(PublisherDirectory::new(configured_publishers.clone())));
+    let publisher_locator: Arc<dyn PublisherLocator> = match &publisher_directory {
+        Some(publisher_directory) => publisher_directory.clone(),
+        None => configured_publishers,
+    };
+
     let pubsub = pubsub_impl::PubSubImpl {
         active_topics: topic_manager.get_active_topics_handle(),
         uri: broker_uri,
         protocol: broker_protocol,
+        topic_id_generator: Arc::new(UuidTopicIdGenerator),
+        clock: topic_manager.get_clock_handle(),
+        idempotency_cache: topic_manager.get_idempotency_cache_handle(),
+        topic_events: topic_manager.get_topic_events_handle(),
+        name_policy: Arc::new(name_policy),
+        permission_sender: settings
+            .enforce_publisher_permissions
+            .then(|| deletion_sender.clone()),
+        warm_sender: Some(connector_sender.clone()),
+        lease_config,
+        sequence_epochs: Arc::new(SequenceEpochs::default()),
+        retained_topics: retained_topics.clone(),
+        topic_name_codec: topic_naming.codec(),
+        topic_templates: Arc::new(settings.topic_templates.clone()),
+        publisher_locator,
+        publisher_directory,
+        topic_spill: topic_manager.get_topic_spill_handle(),
+        #[cfg(feature = "fault-injection")]
+        fault_injector: topic_manager.get_fault_injector_handle(),
+    };
+    // Local variables to pass to the broker monitor client. The deletion message is shared across
+    // every deletion so the message is only allocated once.
+    let topic_deletion_message: Arc<str> =
+        Arc::from(communication_consts.topic_deletion_message.as_str());
+    // The stable id of this instance of the service, which keeps the broker client id the same
+    // across restarts.
+    let instance_id = pubsub_connector::instance_id(
+        settings.connector_instance_id.as_deref(),
+        settings
+            .activity_clock
+            .as_ref()
+            .and_then(|activity_clock_settings| activity_clock_settings.state_file.as_ref())
+            .map(Path::new),
+    )?;
+    let connector_config = ConnectorConfig {
+        client_id: pubsub_connector::instance_client_id(
+            settings
+                .connector_client_id_prefix
+                .as_deref()
+                .unwrap_or(pubsub_connector::DEFAULT_CLIENT_ID_PREFIX),
+            instance_id.as_deref(),
+        ),
+        uri: settings.messaging_uri.clone(),
+        credentials: settings.broker_credentials.clone(),
+        disconnect_topic: communication_consts.disconnect_topic.clone(),
+        mqtt_connection: connector_settings.mqtt,
+        ignored_client_ids: Vec::new(),
+        subscriber_identification: settings
+            .subscriber_acl
+            .as_ref()
+            .map(|subscriber_acl| subscriber_acl.identification)
+            .unwrap_or_default(),
+        broker_stats: broker_stats.clone(),
+        activity_clock: activity_clock.clone(),
+        connector_library,
+        kafka: kafka_settings,
+        dds: dds_settings,
+        self_test_probes: self_test_probes.clone(),
+        retained_topics,
+        deletion_messages: topic_manager.get_deletion_messages_handle(),
+        topic_naming,
+        monitor_health: monitor_health.clone(),
+        latency_probes: latency_probes.clone(),
+        broker_connection: BrokerConnection::default(),
     };
 
-    // Local variable to pass to the broker monitor client.
-    let topic_deletion_message = communication_consts.topic_deletion_message.clone();
+    // Record the selected managed topics to an MCAP file in a separate thread.
+    #[cfg(feature = "mcap-recorder")]
+    let connector_config = match settings.recorder.clone() {
+        Some(recorder_settings) => {
+            let recorder_config = connector_config.clone();
+            let active_topics = topic_manager.get_active_topics_handle();
 
-    // Interface with messaging broker to monitor and clean up topics in a separate thread.
-    let _monitor_handle = tokio::spawn(async move {
-        let client_id = "pubsub_connector_client".to_string();
+            let _recorder_handle = tokio::spawn(async move {
+                match recorder::run_recorder(recorder_settings, recorder_config, active_topics)
+                    .await
+                {
+                    // The recording is finished once the service is stopped.
+                    Ok(()) => process::exit(0),
+                    // The service keeps serving its topics without the recording.
+                    Err(err) => error!("Recording of managed topics stopped: {err}"),
+                }
+            });
 
-        // This line will need to be changed if a different broker is used to utilize the correct connector.
-        let mut connector: connectors::mosquitto_connector::MqttFiveBrokerConnector =
-            PubSubConnector::new(client_id, settings.messaging_uri);
+            ConnectorConfig {
+                ignored_client_ids: vec![recorder::recorder_client_id(&connector_config.client_id)],
+                ..connector_config
+            }
+        }
+        None => connector_config,
+    };
 
-        let _connection_res = connector.monitor_topics(connector_sender).await;
-        loop {
-            let delete_msg = deletion_receiver.recv();
+    #[cfg(not(feature = "mcap-recorder"))]
+    if settings.recorder.is_some() {
+        warn!("'recorder' is set, but the service was built without the 'mcap-recorder' feature.");
+    }
 
-            match delete_msg {
-                Ok(msg) => {
-                    let _res = connector
-                        .delete_topic(msg.context, topic_deletion_message.clone())
-                        .await;
-                }
-                Err(err) => {
-                    error!("error from topic manager: {err}");
-                    info!("no longer able to delete topics..");
+    // Interface with messaging broker to monitor and clean up topics in a separate thread. The
+    // connector reconnects to the broker if it is restarted after a panic.
+    let deletion_receiver = Arc::new(SharedState::new(deletion_receiver));
+    let enforce_publisher_permissions = settings.enforce_publisher_permissions;
+    let orphan_reconciliation = OrphanReconciliation {
+        active_topics: topic_manager.get_active_topics_handle(),
+        topic_spill: topic_manager.get_topic_spill_handle(),
+        generated_topics: GeneratedTopics::new(
+            settings
+                .topic_templates
+                .values()
+                .filter_map(|template| template.prefix.clone()),
+        ),
+        remove_orphans: settings
+            .orphan_reconciliation
+            .as_ref()
+            .is_some_and(|reconciliation| reconciliation.remove_orphans),
+    };
+    let channel_failure = settings.connector_channel_failure;
+    let make_connector_loop = move || {
+        run_broker_connector(
+            connector_factory,
+            ConnectorRun {
+                connector_config: connector_config.clone(),
+                monitor_sender: connector_sender.clone(),
+                connector_receiver: deletion_receiver.clone(),
+                topic_deletion_message: topic_deletion_message.clone(),
+                enforce_publisher_permissions,
+                orphan_reconciliation: orphan_reconciliation.clone(),
+                channel_failure,
+            },
+        )
+    };
+    #[cfg(feature = "alloc-profiling")]
+    let make_connector_loop = move || {
+        alloc_profile::instrument(alloc_profile::Subsystem::Connector, make_connector_loop())
+    };
+    let _monitor_handle = topic_manager
+        .get_supervisor_handle()
+        .supervise("connector", make_connector_loop);
+
+    // Periodically ask the connector to look for topics on the broker unknown to the service.
+    if let Some(reconciliation) = &settings.orphan_reconciliation {
+        let reconcile_sender = deletion_sender.clone();
+        let interval = Duration::from_secs(reconciliation.interval_secs);
+
+        let _reconcile_handle = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+
+                let reconcile_msg = MonitorMessage {
+                    context: String::new(),
+                    action: PubSubAction::Reconcile,
+                    client_id: None,
+                    identity: None,
+                };
+
+                if reconcile_sender.send(reconcile_msg).is_err() {
                     break;
                 }
             }
-        }
-    });
+        });
+    }
+
+    // Provision cloud telemetry routes for the topics tagged for the cloud bridge.
+    #[cfg(feature = "cloud-bridge")]
+    if let Some(bridge_settings) = settings.cloud_bridge.clone() {
+        let _cloud_bridge_handle = tokio::spawn(cloud_bridge::run_cloud_bridge(
+            bridge_settings,
+            topic_manager.get_topic_events_handle(),
+            topic_manager.get_active_topics_handle(),
+        ));
+    }
+
+    #[cfg(not(feature = "cloud-bridge"))]
+    if settings.cloud_bridge.is_some() {
+        warn!(
+            "'cloud_bridge' is set, but the service was built without the 'cloud-bridge' feature."
+        );
+    }
+
+    // Periodically have the connector measure the latency of the messaging broker.
+    if let Some(latency_probe) = &settings.latency_probe {
+        let _latency_probe_handle = tokio::spawn(latency_probe::run_latency_probes(
+            deletion_sender.clone(),
+            topic_manager.get_active_topics_handle(),
+            topic_manager.get_clock_handle(),
+            Duration::from_secs(latency_probe.interval_secs),
+        ));
+    }
 
     // If Chariott is enabled then connect to Chariott and register the service.
     if settings.chariott_uri.is_some() {
-        // Create service identifiers used to uniquely identify the service.
-        let service_identifier = ServiceIdentifier {
-            namespace: settings.namespace.unwrap(),
-            name: settings.name.unwrap(),
-            version: settings.version.unwrap(),
-        };
-
-        // Connect to and register with Chariott.
-        let mut chariott_client = chariott_connector::connect_to_chariott_with_retry(
-            &settings.chariott_uri.unwrap(),
-            communication_consts.retry_interval_secs,
-        )
-        .await?;
+        #[cfg(feature = "chariott")]
+        register_with_chariott(&settings, &communication_consts).await?;
 
-        chariott_connector::register_with_chariott(
-            &mut chariott_client,
-            &settings.pub_sub_authority,
-            service_identifier,
-            &communication_consts.grpc_kind,
-            &communication_consts.pub_sub_reference,
-        )
-        .await?;
+        #[cfg(not(feature = "chariott"))]
+        warn!("'chariott_uri' is set, but the service was built without the 'chariott' feature.");
     }
 
-    // Grpc server for handling calls from clients.
-    Server::builder()
-        .add_service(PubSubServer::new(pubsub))
-        .serve(addr)
+    // If Chariott intents are enabled then keep the service announced to the intent broker.
+    if settings.chariott_intents.is_some() {
+        #[cfg(feature = "chariott-intents")]
+        {
+            let intent_settings = settings.chariott_intents.clone().unwrap();
+            let provider_authority = settings.pub_sub_authority.clone();
+            let service_identifier = ServiceIdentifier {
+                namespace: settings.namespace.clone().unwrap(),
+                name: settings.name.clone().unwrap(),
+                version: settings.version.clone().unwrap(),
+            };
+
+            let _intents_handle = tokio::spawn(async move {
+                if let Err(err) = chariott_connector::announce_intents_to_chariott(
+                    &intent_settings.intent_broker_uri,
+                    &provider_authority,
+                    service_identifier,
+                    Duration::from_secs(intent_settings.announce_interval_secs),
+                )
+                .await
+                {
+                    error!("Stopped announcing the service to Chariott's intent broker: {err}");
+                }
+            });
+        }
+
+        #[cfg(not(feature = "chariott-intents"))]
+        warn!("'chariott_intents' is set, but the 'chariott-intents' feature is not enabled.");
+    }
+
+    // Report the service as ready to systemd once the connector monitors the messaging broker, and
+    // ping the watchdog for as long as the service runs.
+    #[cfg(feature = "systemd")]
+    {
+        let ready_health = monitor_health.clone();
+        let _ready_handle = tokio::spawn(systemd::notify_ready_when(move || {
+            ready_health.is_monitoring()
+        }));
+
+        if let Some(timeout) = systemd::watchdog_timeout() {
+            let _watchdog_handle = topic_manager
+                .get_supervisor_handle()
+                .supervise("watchdog", move || systemd::run_watchdog(timeout));
+        }
+    }
+
+    // Grpc server for handling calls from clients. Every request is given a processing budget,
+    // capped by the deadline sent by the caller.
+    let request_budget = settings
+        .request_budget_ms
+        .map_or(deadline::DEFAULT_REQUEST_BUDGET, Duration::from_millis);
+    let deadline_interceptor = deadline::DeadlineInterceptor::new(request_budget);
+    let server = Server::builder().timeout(request_budget);
+
+    // Attribute the allocations made while handling gRPC requests.
+    #[cfg(feature = "alloc-profiling")]
+    let server = server.layer(tower::layer::layer_fn(|service| {
+        alloc_profile::InstrumentedService::new(alloc_profile::Subsystem::Grpc, service)
+    }));
+
+    // Compress the requests and responses of the services if configured.
+    let grpc_compression = settings.grpc_compression.as_ref();
+    #[cfg(not(feature = "grpc-compression"))]
+    if grpc_compression.is_some() || settings.publisher_callbacks.compression.is_some() {
+        warn!("gRPC compression is configured, but the 'grpc-compression' feature is not enabled.");
+    }
+
+    let mut server = server;
+    let server = server.add_service(InterceptedService::new(
+        grpc_compression::configure_server(
+            PubSubServer::new(pubsub),
+            grpc_compression,
+            PubSubServer::accept_compressed,
+            PubSubServer::send_compressed,
+        ),
+        deadline_interceptor,
+    ));
+
+    #[cfg(feature = "chariott-intents")]
+    let server = server.add_optional_service(intent_provider.map(|provider| {
+        InterceptedService::new(
+            grpc_compression::configure_server(
+                ProviderServiceServer::new(provider),
+                grpc_compression,
+                ProviderServiceServer::accept_compressed,
+                ProviderServiceServer::send_compressed,
+            ),
+            deadline_interceptor,
+        )
+    }));
+
+    #[cfg(feature = "admin-api")]
+    let admin_server = grpc_compression::configure_server(
+        AdminServer::new(admin_impl::AdminImpl {
+            active_topics: topic_manager.get_active_topics_handle(),
+            cleanup_stats: topic_manager.get_cleanup_stats_handle(),
+            clock: topic_manager.get_clock_handle(),
+            broker_stats,
+            topic_events: topic_manager.get_topic_events_handle(),
+            monitor_sender: power_sender,
+            state_dump: topic_manager.get_state_dump_source(),
+            supervisor: topic_manager.get_supervisor_handle(),
+            callback_breakers: topic_manager.get_publisher_callbacks_handle().breakers,
+            publisher_metrics: topic_manager.get_publisher_callbacks_handle().metrics,
+            self_tester: SelfTester::new(deletion_sender.clone(), self_test_probes),
+            monitor_health,
+            latency_probes,
+            topic_spill: topic_manager.get_topic_spill_handle(),
+            #[cfg(feature = "fault-injection")]
+            fault_injector: topic_manager.get_fault_injector_handle(),
+        }),
+        grpc_compression,
+        AdminServer::accept_compressed,
+        AdminServer::send_compressed,
+    );
+    #[cfg(feature = "admin-api")]
+    let server = server.add_service(InterceptedService::new(admin_server, deadline_interceptor));
+
+    #[cfg(feature = "systemd")]
+    server
+        .serve_with_shutdown(addr, systemd::shutdown_signal())
         .await?;
 
+    #[cfg(not(feature = "systemd"))]
+    server.serve(addr).await?;
+
+    // The last broker activity is only persisted periodically while running.
+    if let Some(activity_clock) = &activity_clock {
+        activity_clock.flush();
+    }
+
     Ok(())
 }
@@ -0,0 +1,564 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Renders the pub sub service metrics in the
+//! [Prometheus text exposition format](https://prometheus.io/docs/instrumenting/exposition_formats/).
+//!
+//! Topic lifecycle metrics are rendered alongside the statistics reported by the messaging broker,
+//! so that broker health can be correlated with the behavior of managed topics. Topic counts and
+//! callback statistics are also rendered per publisher, with the number of publisher labels
+//! bounded by the [`PublisherMetrics`]. Once the latency probe has run, the broker latency
+//! percentiles are rendered as a summary. With the `alloc-profiling` feature, the allocation counts
+//! of each subsystem are rendered too.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Write},
+};
+
+#[cfg(feature = "alloc-profiling")]
+use crate::alloc_profile::{self, AllocationCounts, Subsystem};
+use crate::{
+    callback_breaker::{BreakerState, BreakerStatus},
+    latency_probe::LatencyWindow,
+    publisher_metrics::{PublisherMetrics, CALLBACK_LATENCY_BUCKETS},
+    pubsub_connector::BrokerStats,
+    supervisor::TaskHealth,
+    topic_manager::{ActiveTopicsMap, CleanupReport},
+};
+
+/// Appends a single metric, with its help text and type, to the output.
+///
+/// # Arguments
+///
+/// * `out` - The rendered metrics.
+/// * `name` - The name of the metric.
+/// * `kind` - The Prometheus type of the metric.
+/// * `help` - Description of the metric.
+/// * `value` - The value of the metric.
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, value: impl Display) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    let _ = writeln!(out, "{name} {value}");
+}
+
+/// Escapes a label value, whose backslashes, double quotes and line feeds would otherwise end the
+/// value early.
+///
+/// # Arguments
+///
+/// * `value` - The label value.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Appends the topic counts and callback statistics of each publisher, labeled by publisher id,
+/// to the output.
+///
+/// # Arguments
+///
+/// * `out` - The rendered metrics.
+/// * `active_topics` - The topics currently managed by the service.
+/// * `publisher_metrics` - The statistics of the callbacks made to each publisher.
+fn write_publisher_metrics(
+    out: &mut String,
+    active_topics: &ActiveTopicsMap,
+    publisher_metrics: &PublisherMetrics,
+) {
+    let mut topic_counts: BTreeMap<String, u64> = BTreeMap::new();
+    for metadata in active_topics
+        .values()
+        .filter(|metadata| !metadata.is_deleted())
+    {
+        *topic_counts.entry(metadata.client_id.clone()).or_default() += 1;
+    }
+
+    let name = "agemo_publisher_topics";
+    let _ = writeln!(
+        out,
+        "# HELP {name} Number of managed topics owned by each publisher."
+    );
+    let _ = writeln!(out, "# TYPE {name} gauge");
+    for (publisher_id, count) in publisher_metrics.limit_labels(topic_counts) {
+        let publisher_id = escape_label(&publisher_id);
+        let _ = writeln!(out, "{name}{{publisher_id=\"{publisher_id}\"}} {count}");
+    }
+
+    let callback_stats = publisher_metrics.callback_stats();
+
+    let name = "agemo_publisher_callback_latency_seconds";
+    let _ = writeln!(
+        out,
+        "# HELP {name} Latency of the management callbacks made to each publisher."
+    );
+    let _ = writeln!(out, "# TYPE {name} histogram");
+    for (publisher_id, stats) in &callback_stats {
+        let publisher_id = escape_label(publisher_id);
+        let mut cumulative = 0;
+
+        for (bound, count) in CALLBACK_LATENCY_BUCKETS.iter().zip(stats.latency_buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{publisher_id=\"{publisher_id}\",le=\"{bound}\"}} {cumulative}"
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{publisher_id=\"{publisher_id}\",le=\"+Inf\"}} {}",
+            stats.callbacks
+        );
+        let _ = writeln!(
+            out,
+            "{name}_sum{{publisher_id=\"{publisher_id}\"}} {}",
+            stats.latency_sum.as_secs_f64()
+        );
+        let _ = writeln!(
+            out,
+            "{name}_count{{publisher_id=\"{publisher_id}\"}} {}",
+            stats.callbacks
+        );
+    }
+
+    let name = "agemo_publisher_callback_failures_total";
+    let _ = writeln!(
+        out,
+        "# HELP {name} Number of failed management callbacks to each publisher."
+    );
+    let _ = writeln!(out, "# TYPE {name} counter");
+    for (publisher_id, stats) in &callback_stats {
+        let publisher_id = escape_label(publisher_id);
+        let _ = writeln!(
+            out,
+            "{name}{{publisher_id=\"{publisher_id}\"}} {}",
+            stats.failures
+        );
+    }
+}
+
+/// Appends the allocation counts of each subsystem, labeled by subsystem, to the output.
+///
+/// # Arguments
+///
+/// * `out` - The rendered metrics.
+/// * `counts` - The allocation counts of each subsystem.
+#[cfg(feature = "alloc-profiling")]
+fn write_allocation_metrics(out: &mut String, counts: &[(Subsystem, AllocationCounts)]) {
+    let metrics: [(&str, &str, fn(&AllocationCounts) -> u64); 3] = [
+        (
+            "agemo_allocations_total",
+            "Number of heap allocations made by each subsystem.",
+            |counts| counts.allocations,
+        ),
+        (
+            "agemo_allocated_bytes_total",
+            "Number of bytes allocated on the heap by each subsystem.",
+            |counts| counts.allocated_bytes,
+        ),
+        (
+            "agemo_deallocations_total",
+            "Number of heap deallocations made by each subsystem.",
+            |counts| counts.deallocations,
+        ),
+    ];
+
+    for (name, help, value) in metrics {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} counter");
+
+        for (subsystem, subsystem_counts) in counts {
+            let _ = writeln!(
+                out,
+                "{name}{{subsystem=\"{subsystem}\"}} {}",
+                value(subsystem_counts)
+            );
+        }
+    }
+}
+
+/// Appends the broker latency measured by the latency probe to the output, as a summary of the
+/// round trips of the latest probes. Nothing is appended if no probe has run.
+///
+/// # Arguments
+///
+/// * `out` - The output to append to.
+/// * `latency_probes` - The round trips of the latency probes.
+fn write_latency_metrics(out: &mut String, latency_probes: &LatencyWindow) {
+    if latency_probes.delivered == 0 && latency_probes.failures == 0 {
+        return;
+    }
+
+    let name = "agemo_broker_latency_seconds";
+    let _ = writeln!(
+        out,
+        "# HELP {name} Round trip of probe messages through the messaging broker."
+    );
+    let _ = writeln!(out, "# TYPE {name} summary");
+    if let Some(percentiles) = latency_probes.percentiles() {
+        for (quantile, round_trip) in [
+            ("0.5", percentiles.p50),
+            ("0.9", percentiles.p90),
+            ("0.99", percentiles.p99),
+        ] {
+            let _ = writeln!(
+                out,
+                "{name}{{quantile=\"{quantile}\"}} {}",
+                round_trip.as_secs_f64()
+            );
+        }
+    }
+    let _ = writeln!(
+        out,
+        "{name}_sum {}",
+        latency_probes.total_round_trip.as_secs_f64()
+    );
+    let _ = writeln!(out, "{name}_count {}", latency_probes.delivered);
+
+    write_metric(
+        out,
+        "agemo_latency_probe_failures_total",
+        "counter",
+        "Number of probe messages not delivered by the messaging broker in time.",
+        latency_probes.failures,
+    );
+}
+
+/// Renders the service metrics. Broker statistics that have not been reported are left out.
+///
+/// # Arguments
+///
+/// * `active_topics` - The topics currently managed by the service.
+/// * `cleanup_report` - Statistics about recent topic cleanup activity.
+/// * `broker_stats` - The latest statistics reported by the messaging broker.
+/// * `task_health` - The health of each supervised background task, by task name.
+/// * `breakers` - The circuit breakers of the management uris with failed callbacks.
+/// * `publisher_metrics` - The statistics of the callbacks made to each publisher.
+/// * `latency_probes` - The round trips of the latency probes.
+pub fn render_metrics(
+    active_topics: &ActiveTopicsMap,
+    cleanup_report: &CleanupReport,
+    broker_stats: &BrokerStats,
+    task_health: &[(String, TaskHealth)],
+    breakers: &[BreakerStatus],
+    publisher_metrics: &PublisherMetrics,
+    latency_probes: &LatencyWindow,
+) -> String {
+    let mut out = String::new();
+
+    let live_topics = active_topics
+        .values()
+        .filter(|metadata| !metadata.is_deleted());
+    let subscribers: i64 = live_topics
+        .clone()
+        .map(|metadata| i64::from(metadata.count.max(0)))
+        .sum();
+
+    write_metric(
+        &mut out,
+        "agemo_active_topics",
+        "gauge",
+        "Number of managed topics that are not marked for deletion.",
+        live_topics.count(),
+    );
+    write_metric(
+        &mut out,
+        "agemo_topic_subscribers",
+        "gauge",
+        "Number of subscriptions across all managed topics.",
+        subscribers,
+    );
+    write_metric(
+        &mut out,
+        "agemo_topics_pending_deletion",
+        "gauge",
+        "Number of topics marked for deletion that have not yet been deleted.",
+        cleanup_report.pending_deletions,
+    );
+    write_metric(
+        &mut out,
+        "agemo_topics_reaped_last_hour",
+        "gauge",
+        "Number of topics deleted in the last hour.",
+        cleanup_report.topics_reaped,
+    );
+    write_metric(
+        &mut out,
+        "agemo_topic_average_idle_seconds",
+        "gauge",
+        "Average time topics deleted in the last hour went without subscribers.",
+        cleanup_report.average_idle_time.as_secs_f64(),
+    );
+    write_metric(
+        &mut out,
+        "agemo_placeholder_topics",
+        "gauge",
+        "Number of topics subscribed to that no publisher has created yet.",
+        cleanup_report.placeholder_topics,
+    );
+    write_metric(
+        &mut out,
+        "agemo_placeholder_topics_expired_total",
+        "counter",
+        "Number of placeholder topics removed as they found no publisher in time.",
+        cleanup_report.placeholders_expired,
+    );
+
+    write_metric(
+        &mut out,
+        "agemo_spilled_topics",
+        "gauge",
+        "Number of cold topics spilled to disk.",
+        cleanup_report.spilled_topics,
+    );
+
+    if let Some(connected_clients) = broker_stats.connected_clients {
+        write_metric(
+            &mut out,
+            "agemo_broker_connected_clients",
+            "gauge",
+            "Number of clients connected to the messaging broker.",
+            connected_clients,
+        );
+    }
+
+    if let Some(received) = broker_stats.messages_received_per_min {
+        write_metric(
+            &mut out,
+            "agemo_broker_messages_received_per_minute",
+            "gauge",
+            "Average number of messages received by the messaging broker per minute.",
+            received,
+        );
+    }
+
+    if let Some(sent) = broker_stats.messages_sent_per_min {
+        write_metric(
+            &mut out,
+            "agemo_broker_messages_sent_per_minute",
+            "gauge",
+            "Average number of messages sent by the messaging broker per minute.",
+            sent,
+        );
+    }
+
+    if let Some(dropped) = broker_stats.messages_dropped {
+        write_metric(
+            &mut out,
+            "agemo_broker_messages_dropped_total",
+            "counter",
+            "Number of messages dropped by the messaging broker.",
+            dropped,
+        );
+    }
+
+    write_metric(
+        &mut out,
+        "agemo_degraded",
+        "gauge",
+        "Whether a background task panicked or failed and has not yet recovered.",
+        u8::from(task_health.iter().any(|(_, health)| health.degraded)),
+    );
+
+    let name = "agemo_task_panics_total";
+    let _ = writeln!(
+        out,
+        "# HELP {name} Number of times each background task panicked and was restarted."
+    );
+    let _ = writeln!(out, "# TYPE {name} counter");
+    for (task, health) in task_health {
+        let _ = writeln!(out, "{name}{{task=\"{task}\"}} {}", health.panics);
+    }
+
+    let name = "agemo_task_failures_total";
+    let _ = writeln!(
+        out,
+        "# HELP {name} Number of times each background task failed without panicking."
+    );
+    let _ = writeln!(out, "# TYPE {name} counter");
+    for (task, health) in task_health {
+        let _ = writeln!(out, "{name}{{task=\"{task}\"}} {}", health.failures);
+    }
+
+    write_metric(
+        &mut out,
+        "agemo_callback_breakers_open",
+        "gauge",
+        "Number of publisher management uris with an open circuit breaker.",
+        breakers
+            .iter()
+            .filter(|breaker| breaker.state != BreakerState::Closed)
+            .count(),
+    );
+
+    let name = "agemo_callback_consecutive_failures";
+    let _ = writeln!(
+        out,
+        "# HELP {name} Number of consecutive failed callbacks to each publisher management uri."
+    );
+    let _ = writeln!(out, "# TYPE {name} gauge");
+    for breaker in breakers {
+        let _ = writeln!(
+            out,
+            "{name}{{uri=\"{}\"}} {}",
+            breaker.uri, breaker.consecutive_failures
+        );
+    }
+
+    write_publisher_metrics(&mut out, active_topics, publisher_metrics);
+    write_latency_metrics(&mut out, latency_probes);
+
+    #[cfg(feature = "alloc-profiling")]
+    write_allocation_metrics(&mut out, &alloc_profile::snapshot());
+
+    out
+}
+
+#[cfg(test)]
+mod metrics_tests {
+    use std::time::Duration;
+
+    use crate::topic_manager::TopicMetadata;
+
+    use super::*;
+
+    #[test]
+    fn render_metrics_test() {
+        let mut active_topics = ActiveTopicsMap::new();
+        active_topics.insert(
+            "topic_1".to_string(),
+            TopicMetadata::new("pub_1".to_string(), 2, None),
+        );
+
+        let mut deleted = TopicMetadata::new("pub_2".to_string(), 0, None);
+        deleted.delete();
+        active_topics.insert("topic_2".to_string(), deleted);
+
+        let cleanup_report = CleanupReport {
+            topics_reaped: 4,
+            average_idle_time: Duration::from_millis(1500),
+            pending_deletions: 1,
+            placeholder_topics: 2,
+            placeholders_expired: 5,
+            spilled_topics: 3,
+        };
+        let broker_stats = BrokerStats {
+            connected_clients: Some(3),
+            ..Default::default()
+        };
+
+        let task_health = vec![(
+            "monitor".to_string(),
+            TaskHealth {
+                panics: 2,
+                degraded: true,
+                last_panic: Some("monitor failed".to_string()),
+                ..Default::default()
+            },
+        )];
+
+        let breakers = vec![BreakerStatus {
+            uri: "http://0.0.0.0:50061".to_string(),
+            state: BreakerState::Open,
+            consecutive_failures: 5,
+            failing_for: Duration::from_secs(60),
+        }];
+
+        let mut publisher_metrics = PublisherMetrics::new(10);
+        publisher_metrics.record_callback("pub_1", Duration::from_millis(20), false);
+
+        let metrics = render_metrics(
+            &active_topics,
+            &cleanup_report,
+            &broker_stats,
+            &task_health,
+            &breakers,
+            &publisher_metrics,
+            &LatencyWindow::default(),
+        );
+
+        assert!(metrics.contains("# TYPE agemo_active_topics gauge\nagemo_active_topics 1\n"));
+        assert!(metrics.contains("agemo_topic_subscribers 2\n"));
+        assert!(metrics.contains("agemo_topics_pending_deletion 1\n"));
+        assert!(metrics.contains("agemo_topics_reaped_last_hour 4\n"));
+        assert!(metrics.contains("agemo_topic_average_idle_seconds 1.5\n"));
+        assert!(metrics.contains("agemo_placeholder_topics 2\n"));
+        assert!(metrics.contains("agemo_placeholder_topics_expired_total 5\n"));
+        assert!(metrics.contains("agemo_spilled_topics 3\n"));
+        assert!(metrics.contains("agemo_broker_connected_clients 3\n"));
+        assert!(!metrics.contains("agemo_broker_messages_dropped_total"));
+        assert!(metrics.contains("agemo_degraded 1\n"));
+        assert!(metrics.contains("agemo_callback_breakers_open 1\n"));
+        assert!(metrics
+            .contains("agemo_callback_consecutive_failures{uri=\"http://0.0.0.0:50061\"} 5\n"));
+        assert!(metrics.contains(
+            "# TYPE agemo_task_panics_total counter\nagemo_task_panics_total{task=\"monitor\"} 2\n"
+        ));
+        assert!(metrics.contains("agemo_task_failures_total{task=\"monitor\"} 0\n"));
+        // Deleted topics are not counted.
+        assert!(metrics.contains("agemo_publisher_topics{publisher_id=\"pub_1\"} 1\n#"));
+        let bucket = "agemo_publisher_callback_latency_seconds_bucket{publisher_id=\"pub_1\"";
+        assert!(metrics.contains(&format!("{bucket},le=\"0.01\"}} 0\n")));
+        assert!(metrics.contains(&format!("{bucket},le=\"0.025\"}} 1\n")));
+        assert!(metrics.contains(&format!("{bucket},le=\"+Inf\"}} 1\n")));
+        assert!(metrics.contains(
+            "agemo_publisher_callback_latency_seconds_count{publisher_id=\"pub_1\"} 1\n"
+        ));
+        assert!(
+            metrics.contains("agemo_publisher_callback_failures_total{publisher_id=\"pub_1\"} 1\n")
+        );
+        // The latency probe did not run.
+        assert!(!metrics.contains("agemo_broker_latency_seconds"));
+    }
+
+    #[test]
+    fn write_latency_metrics_test() {
+        let mut latency_probes = LatencyWindow::default();
+        latency_probes.record(Ok(Duration::from_millis(20)));
+        latency_probes.record(Ok(Duration::from_millis(30)));
+        latency_probes.record(Err("probe message was not delivered".to_string()));
+
+        let mut metrics = String::new();
+        write_latency_metrics(&mut metrics, &latency_probes);
+
+        assert!(metrics.contains(
+            "# TYPE agemo_broker_latency_seconds summary\nagemo_broker_latency_seconds{quantile=\"0.5\"} 0.02\n"
+        ));
+        assert!(metrics.contains("agemo_broker_latency_seconds{quantile=\"0.99\"} 0.03\n"));
+        assert!(metrics.contains("agemo_broker_latency_seconds_sum 0.05\n"));
+        assert!(metrics.contains("agemo_broker_latency_seconds_count 2\n"));
+        assert!(metrics.contains("agemo_latency_probe_failures_total 1\n"));
+    }
+
+    #[test]
+    fn escape_label_test() {
+        assert_eq!("pub_1", escape_label("pub_1"));
+        assert_eq!("a\\\"b\\\\c\\n", escape_label("a\"b\\c\n"));
+    }
+
+    #[cfg(feature = "alloc-profiling")]
+    #[test]
+    fn write_allocation_metrics_test() {
+        let counts = [(
+            Subsystem::Grpc,
+            AllocationCounts {
+                allocations: 10,
+                allocated_bytes: 2048,
+                deallocations: 7,
+            },
+        )];
+
+        let mut metrics = String::new();
+        write_allocation_metrics(&mut metrics, &counts);
+
+        assert!(metrics.contains(
+            "# TYPE agemo_allocations_total counter\nagemo_allocations_total{subsystem=\"grpc\"} 10\n"
+        ));
+        assert!(metrics.contains("agemo_allocated_bytes_total{subsystem=\"grpc\"} 2048\n"));
+        assert!(metrics.contains("agemo_deallocations_total{subsystem=\"grpc\"} 7\n"));
+    }
+}
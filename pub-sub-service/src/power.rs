@@ -0,0 +1,98 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Integration with the power state of the system.
+//!
+//! On suspend the [`TopicManager`][crate::topic_manager::TopicManager] pauses topic cleanup and
+//! publisher callbacks, and on resume it keeps topic timeouts paused until the broker connector
+//! re-validated the broker connection, so that a sleep cycle doesn't cause a mass deletion of
+//! topics. Power events are reported
+//! through the admin gRPC service or, with the `power-dbus` feature, from the
+//! `PrepareForSleep` signal emitted by systemd-logind.
+
+use std::time::Duration;
+
+use crate::pubsub_connector::{MonitorMessage, PubSubAction};
+
+#[cfg(feature = "power-dbus")]
+use std::sync::mpsc;
+
+#[cfg(feature = "power-dbus")]
+use futures::StreamExt;
+#[cfg(feature = "power-dbus")]
+use log::info;
+
+/// Interval at which the broker connection is checked after a resume, until the broker can be
+/// reached.
+pub const RESUME_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Creates the [`MonitorMessage`] reporting a power state change to the topic manager.
+///
+/// # Arguments
+///
+/// * `suspended` - Whether the system is suspending, or resuming otherwise.
+pub fn power_message(suspended: bool) -> MonitorMessage {
+    let action = if suspended {
+        PubSubAction::PowerSuspend
+    } else {
+        PubSubAction::PowerResume
+    };
+
+    MonitorMessage {
+        context: String::new(),
+        action,
+        client_id: None,
+        identity: None,
+    }
+}
+
+/// Proxy for the systemd-logind manager, used to listen for sleep signals.
+#[cfg(feature = "power-dbus")]
+#[zbus::dbus_proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait LogindManager {
+    /// Signal emitted before the system suspends with `start` set, and after it resumes without.
+    #[dbus_proxy(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+}
+
+/// Forwards the sleep signals emitted by systemd-logind to the topic manager until the D-Bus
+/// connection or the channel is closed.
+///
+/// # Arguments
+///
+/// * `sender` - Channel used to report power state changes to the topic manager.
+#[cfg(feature = "power-dbus")]
+pub async fn watch_logind(sender: mpsc::Sender<MonitorMessage>) -> Result<(), zbus::Error> {
+    let connection = zbus::Connection::system().await?;
+    let proxy = LogindManagerProxy::new(&connection).await?;
+    let mut sleep_signals = proxy.receive_prepare_for_sleep().await?;
+
+    info!("Listening for power events from systemd-logind.");
+
+    while let Some(signal) = sleep_signals.next().await {
+        let args = signal.args()?;
+
+        if sender.send(power_message(*args.start())).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod power_tests {
+    use super::*;
+
+    #[test]
+    fn power_message_test() {
+        assert_eq!(PubSubAction::PowerSuspend, power_message(true).action);
+        assert_eq!(PubSubAction::PowerResume, power_message(false).action);
+        assert!(power_message(true).context.is_empty());
+    }
+}
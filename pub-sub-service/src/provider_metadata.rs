@@ -0,0 +1,215 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Module describing the pub sub service to the clients that discover it through Chariott.
+//!
+//! Chariott only stores a uri and a communication kind and reference for each service, so the
+//! metadata is registered as its own entry with a JSON document as reference. Discovering clients
+//! can read it to check that the service and its messaging broker suit them before making any
+//! gRPC call.
+
+use std::net::IpAddr;
+
+use serde_derive::Serialize;
+use url::{Host, Url};
+
+use crate::load_config::Settings;
+
+/// The kind of the metadata registered with Chariott.
+pub const METADATA_KIND: &str = "application/json";
+
+/// Suffix added to the service name when registering the metadata with Chariott.
+pub const METADATA_SERVICE_SUFFIX: &str = "metadata";
+
+/// Capabilities of the pub sub service that are always available.
+const BASE_CAPABILITIES: &[&str] = &[
+    "idempotent-create",
+    "pre-warm",
+    "response-topics",
+    "retained-config-topics",
+    "sequence-numbers",
+    "single-consumer",
+    "subscriber-joined",
+];
+
+/// Cargo features the service can be built with, and whether they are enabled.
+const FEATURES: &[(&str, bool)] = &[
+    ("admin-api", cfg!(feature = "admin-api")),
+    ("alloc-profiling", cfg!(feature = "alloc-profiling")),
+    ("chariott", cfg!(feature = "chariott")),
+    ("chariott-intents", cfg!(feature = "chariott-intents")),
+    ("dynamic-connectors", cfg!(feature = "dynamic-connectors")),
+    ("fault-injection", cfg!(feature = "fault-injection")),
+    ("mcap-recorder", cfg!(feature = "mcap-recorder")),
+    ("metrics", cfg!(feature = "metrics")),
+    ("mqtt", cfg!(feature = "mqtt")),
+    ("power-dbus", cfg!(feature = "power-dbus")),
+    ("tls", cfg!(feature = "tls")),
+];
+
+/// Hint on where clients can reach the messaging broker from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum BrokerReachability {
+    /// The broker uri is a loopback address, so the broker is only reachable from the host of
+    /// the service.
+    LocalHost,
+    /// The broker listens on every interface, so clients should use the host they reach the
+    /// service on.
+    AnyInterface,
+    /// The broker is reachable at the host of its uri.
+    Host,
+    /// The broker uri could not be parsed.
+    Unknown,
+}
+
+impl BrokerReachability {
+    /// Returns where clients can reach a messaging broker from.
+    ///
+    /// # Arguments
+    ///
+    /// * `broker_uri` - The uri of the messaging broker.
+    pub fn of(broker_uri: &str) -> Self {
+        let Some(host) = Url::parse(broker_uri)
+            .ok()
+            .and_then(|url| url.host().map(|host| host.to_owned()))
+        else {
+            return BrokerReachability::Unknown;
+        };
+
+        // Hosts of uris with a scheme unknown to the url parser, like `mqtt`, are not parsed as
+        // ip addresses unless they are in brackets.
+        let ip = match host {
+            Host::Domain(domain) if domain == "localhost" => return BrokerReachability::LocalHost,
+            Host::Domain(domain) => match domain.parse::<IpAddr>() {
+                Ok(ip) => ip,
+                Err(_) => return BrokerReachability::Host,
+            },
+            Host::Ipv4(ip) => IpAddr::V4(ip),
+            Host::Ipv6(ip) => IpAddr::V6(ip),
+        };
+
+        if ip.is_loopback() {
+            BrokerReachability::LocalHost
+        } else if ip.is_unspecified() {
+            BrokerReachability::AnyInterface
+        } else {
+            BrokerReachability::Host
+        }
+    }
+}
+
+/// Metadata describing the pub sub service and its messaging broker.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderMetadata {
+    /// The messaging protocol used by the messaging broker.
+    pub broker_protocol: String,
+    /// The uri of the messaging broker.
+    pub broker_uri: String,
+    /// Hint on where clients can reach the messaging broker from.
+    pub broker_reachability: BrokerReachability,
+    /// The cargo features the service was built with.
+    pub features: Vec<String>,
+    /// The capabilities of the service, given the features it was built with and its
+    /// configuration.
+    pub capabilities: Vec<String>,
+}
+
+impl ProviderMetadata {
+    /// Creates the metadata of the service.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The service settings.
+    /// * `broker_protocol` - The messaging protocol used by the messaging broker.
+    pub fn new(settings: &Settings, broker_protocol: &str) -> Self {
+        let features = FEATURES
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(feature, _)| feature.to_string())
+            .collect();
+
+        let mut capabilities: Vec<String> = BASE_CAPABILITIES
+            .iter()
+            .map(|capability| capability.to_string())
+            .collect();
+        if settings.subscription_lease_secs.is_some() {
+            capabilities.push("subscription-leases".to_string());
+        }
+        if settings.enforce_publisher_permissions {
+            capabilities.push("publisher-permissions".to_string());
+        }
+        if cfg!(feature = "chariott-intents") && settings.chariott_intents.is_some() {
+            capabilities.push("chariott-intents".to_string());
+        }
+        capabilities.sort();
+
+        ProviderMetadata {
+            broker_protocol: broker_protocol.to_string(),
+            broker_uri: settings.messaging_uri.clone(),
+            broker_reachability: BrokerReachability::of(&settings.messaging_uri),
+            features,
+            capabilities,
+        }
+    }
+
+    /// Returns the metadata as a JSON document, used as the communication reference of the
+    /// metadata entry in Chariott.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+}
+
+#[cfg(test)]
+mod provider_metadata_tests {
+    use super::*;
+
+    #[test]
+    fn broker_reachability_test() {
+        assert_eq!(
+            BrokerReachability::LocalHost,
+            BrokerReachability::of("mqtt://localhost:1883")
+        );
+        assert_eq!(
+            BrokerReachability::LocalHost,
+            BrokerReachability::of("mqtt://127.0.0.1:1883")
+        );
+        assert_eq!(
+            BrokerReachability::LocalHost,
+            BrokerReachability::of("mqtt://[::1]:1883")
+        );
+        assert_eq!(
+            BrokerReachability::AnyInterface,
+            BrokerReachability::of("mqtt://0.0.0.0:1883")
+        );
+        assert_eq!(
+            BrokerReachability::Host,
+            BrokerReachability::of("mqtt://broker.vehicle:1883")
+        );
+        assert_eq!(
+            BrokerReachability::Unknown,
+            BrokerReachability::of("not a uri")
+        );
+    }
+
+    #[test]
+    fn provider_metadata_to_json_test() {
+        let metadata = ProviderMetadata {
+            broker_protocol: "mqtt_v5".to_string(),
+            broker_uri: "mqtt://0.0.0.0:1883".to_string(),
+            broker_reachability: BrokerReachability::AnyInterface,
+            features: vec!["mqtt".to_string()],
+            capabilities: vec!["pre-warm".to_string()],
+        };
+
+        let json: serde_json::Value = serde_json::from_str(&metadata.to_json().unwrap()).unwrap();
+
+        assert_eq!("mqtt_v5", json["brokerProtocol"]);
+        assert_eq!("mqtt://0.0.0.0:1883", json["brokerUri"]);
+        assert_eq!("ANY_INTERFACE", json["brokerReachability"]);
+        assert_eq!(serde_json::json!(["mqtt"]), json["features"]);
+        assert_eq!(serde_json::json!(["pre-warm"]), json["capabilities"]);
+    }
+}
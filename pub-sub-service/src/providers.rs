@@ -0,0 +1,507 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Injectable providers for generated topic names and time.
+//!
+//! The pub sub service generates topic names for publishers and tracks topic activity over time.
+//! Both are abstracted behind the [`TopicIdGenerator`] and [`Clock`] traits so that deterministic
+//! implementations can be injected, allowing tests to assert exact generated topic names and
+//! time-based behaviors. The epochs of topics' sequence numbers are generated by
+//! [`SequenceEpochs`].
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use common::shared_state::SharedState;
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Trait that provides the current time to the pub sub service.
+pub trait Clock: Send + Sync {
+    /// Returns the current [`Instant`].
+    fn now(&self) -> Instant;
+}
+
+/// Clock backed by the system's monotonic clock. Used by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock that only moves forward when explicitly advanced.
+#[derive(Debug)]
+pub struct ManualClock {
+    start: Instant,
+    offset: SharedState<Duration>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClock {
+    /// Instantiates a new ManualClock starting at the current [`Instant`].
+    pub fn new() -> Self {
+        ManualClock {
+            start: Instant::now(),
+            offset: SharedState::new(Duration::ZERO),
+        }
+    }
+
+    /// Moves the clock forward by the given duration.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - The amount of time to advance the clock by.
+    pub fn advance(&self, duration: Duration) {
+        *self.offset.lock() += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + *self.offset.lock()
+    }
+}
+
+/// Minimum local time between two writes of the last broker activity to the state file. A
+/// suspension that spans a restart is longer than this, so the lag of the file doesn't hide it.
+const ACTIVITY_PERSIST_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Last broker activity persisted by the [`BrokerActivityClock`].
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+struct ActivityState {
+    /// Timestamp, in seconds since the Unix epoch, of the last message observed from the broker.
+    last_activity_secs: u64,
+}
+
+/// Progress of the [`BrokerActivityClock`].
+#[derive(Debug)]
+struct ActivityProgress {
+    /// Time that the clock has advanced by up to the last observed broker message.
+    elapsed: Duration,
+    /// Broker timestamp, in seconds, of the last observed broker message.
+    last_activity_secs: Option<u64>,
+    /// Local time that the last broker message was observed at.
+    last_observed_at: Instant,
+    /// The latest time returned by the clock, so that it never moves backwards.
+    last_returned: Instant,
+    /// Local time that the last broker activity was persisted at, if it was.
+    last_persisted_at: Option<Instant>,
+}
+
+/// Clock that follows the activity observed on the messaging broker.
+///
+/// Local monotonic time drifts across the suspend and resume cycles that are common on vehicle
+/// ECUs. This clock instead advances by the time between the timestamps of messages from the
+/// broker, and only uses local time to move forward between messages.
+///
+/// A gap between broker timestamps that is much longer than the local time that passed means
+/// that the service was suspended. Time spent suspended doesn't count towards topic timeouts, so
+/// the clock recalibrates to the local time that passed instead. The last broker activity can be
+/// persisted, so that a suspension that spans a service restart is recognized too. It is written
+/// at most once per [`ACTIVITY_PERSIST_INTERVAL`], after a suspension and on shutdown, rather than
+/// for every broker message.
+#[derive(Debug)]
+pub struct BrokerActivityClock {
+    start: Instant,
+    resume_gap: Duration,
+    state_file: Option<PathBuf>,
+    progress: SharedState<ActivityProgress>,
+}
+
+impl BrokerActivityClock {
+    /// Instantiates a new BrokerActivityClock starting at the current [`Instant`].
+    ///
+    /// # Arguments
+    ///
+    /// * `resume_gap` - How much longer than the local time that passed a gap between broker
+    ///                  timestamps must be to be treated as a suspension.
+    /// * `state_file` - Optional file that the last broker activity is persisted to and loaded
+    ///                  from.
+    pub fn new(resume_gap: Duration, state_file: Option<PathBuf>) -> Self {
+        let start = Instant::now();
+
+        let last_activity_secs = state_file.as_ref().and_then(|path| {
+            let contents = fs::read_to_string(path).ok()?;
+
+            match serde_json::from_str::<ActivityState>(&contents) {
+                Ok(state) => Some(state.last_activity_secs),
+                Err(err) => {
+                    warn!(
+                        "Ignoring unreadable activity state in '{}': {err}",
+                        path.display()
+                    );
+                    None
+                }
+            }
+        });
+
+        BrokerActivityClock {
+            start,
+            resume_gap,
+            state_file,
+            progress: SharedState::new(ActivityProgress {
+                elapsed: Duration::ZERO,
+                last_activity_secs,
+                last_observed_at: start,
+                last_returned: start,
+                last_persisted_at: None,
+            }),
+        }
+    }
+
+    /// Records the timestamp of a message from the broker and advances the clock by the broker
+    /// time since the previous message. Returns the length of the suspension if one was detected.
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp_secs` - The broker timestamp of the message, in seconds since the Unix epoch.
+    pub fn observe(&self, timestamp_secs: u64) -> Option<Duration> {
+        self.observe_at(timestamp_secs, Instant::now())
+    }
+
+    /// Records the timestamp of a message from the broker observed at the given [`Instant`].
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp_secs` - The broker timestamp of the message, in seconds since the Unix epoch.
+    /// * `now` - The local time that the message was observed at.
+    pub fn observe_at(&self, timestamp_secs: u64, now: Instant) -> Option<Duration> {
+        let mut progress = self.progress.lock();
+        let local_gap = now.saturating_duration_since(progress.last_observed_at);
+        let mut suspension = None;
+
+        match progress.last_activity_secs {
+            // Messages can arrive slightly out of order, older timestamps are ignored.
+            Some(last_activity_secs) if timestamp_secs < last_activity_secs => return None,
+            Some(last_activity_secs) => {
+                let broker_gap = Duration::from_secs(timestamp_secs - last_activity_secs);
+
+                if broker_gap > local_gap + self.resume_gap {
+                    let suspended = broker_gap - local_gap;
+                    info!("Detected a suspension of {suspended:?}, recalibrating the clock.");
+                    progress.elapsed += local_gap;
+                    suspension = Some(suspended);
+                } else {
+                    progress.elapsed += broker_gap;
+                }
+            }
+            None => progress.elapsed += local_gap,
+        }
+
+        progress.last_activity_secs = Some(timestamp_secs);
+        progress.last_observed_at = now;
+
+        let persist_due = progress
+            .last_persisted_at
+            .map_or(true, |last_persisted_at| {
+                now.saturating_duration_since(last_persisted_at) >= ACTIVITY_PERSIST_INTERVAL
+            });
+        if self.state_file.is_some() && (persist_due || suspension.is_some()) {
+            progress.last_persisted_at = Some(now);
+            drop(progress);
+            self.persist(timestamp_secs);
+        }
+
+        suspension
+    }
+
+    /// Writes the last broker activity to the state file, if one is configured, regardless of
+    /// when it was last written. Called on shutdown.
+    pub fn flush(&self) {
+        let last_activity_secs = self.progress.lock().last_activity_secs;
+
+        if let Some(last_activity_secs) = last_activity_secs {
+            self.persist(last_activity_secs);
+        }
+    }
+
+    /// Writes the last broker activity to the state file, if one is configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `last_activity_secs` - The broker timestamp of the last observed message.
+    fn persist(&self, last_activity_secs: u64) {
+        let Some(path) = &self.state_file else {
+            return;
+        };
+
+        let state = ActivityState { last_activity_secs };
+        let result = serde_json::to_string(&state)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| fs::write(path, contents).map_err(|err| err.to_string()));
+
+        if let Err(err) = result {
+            warn!(
+                "Unable to persist activity state to '{}': {err}",
+                path.display()
+            );
+        }
+    }
+
+    /// Returns the clock's time as of the given local [`Instant`].
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current local time.
+    fn now_at(&self, now: Instant) -> Instant {
+        let mut progress = self.progress.lock();
+        let since_observed = now.saturating_duration_since(progress.last_observed_at);
+        let current = (self.start + progress.elapsed + since_observed).max(progress.last_returned);
+        progress.last_returned = current;
+
+        current
+    }
+}
+
+impl Clock for BrokerActivityClock {
+    fn now(&self) -> Instant {
+        self.now_at(Instant::now())
+    }
+}
+
+/// Trait that generates the names of dynamically created topics.
+pub trait TopicIdGenerator: Send + Sync {
+    /// Returns a new unique topic name.
+    fn generate(&self) -> String;
+}
+
+/// Generates topic names from random v4 UUIDs. Used by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UuidTopicIdGenerator;
+
+impl TopicIdGenerator for UuidTopicIdGenerator {
+    fn generate(&self) -> String {
+        Uuid::new_v4().to_string()
+    }
+}
+
+/// Generates predictable topic names in the form `{prefix}{n}`, where `n` starts at zero and
+/// increments with each generated topic.
+#[derive(Debug)]
+pub struct SequentialTopicIdGenerator {
+    prefix: String,
+    next: AtomicU64,
+}
+
+impl SequentialTopicIdGenerator {
+    /// Instantiates a new SequentialTopicIdGenerator.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix prepended to every generated topic name.
+    pub fn new(prefix: &str) -> Self {
+        SequentialTopicIdGenerator {
+            prefix: prefix.to_string(),
+            next: AtomicU64::new(0),
+        }
+    }
+}
+
+impl TopicIdGenerator for SequentialTopicIdGenerator {
+    fn generate(&self) -> String {
+        let n = self.next.fetch_add(1, Ordering::SeqCst);
+
+        format!("{}{n}", self.prefix)
+    }
+}
+
+/// Generates the epochs of topics' sequence numbers.
+///
+/// Epochs are the wall clock time in milliseconds, bumped when needed so that every epoch is
+/// greater than the previous one. Epochs keep increasing across restarts of the service as long
+/// as the wall clock does not move backwards.
+#[derive(Debug, Default)]
+pub struct SequenceEpochs {
+    last: AtomicU64,
+}
+
+impl SequenceEpochs {
+    /// Returns a new epoch, greater than every epoch returned before.
+    pub fn next(&self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| u64::try_from(since_epoch.as_millis()).unwrap_or(u64::MAX))
+            .unwrap_or_default();
+
+        let previous = self
+            .last
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+                Some(now.max(last.saturating_add(1)))
+            })
+            // The closure always returns a new epoch.
+            .unwrap_or_else(|last| last);
+
+        now.max(previous.saturating_add(1))
+    }
+}
+
+#[cfg(test)]
+mod providers_tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_only_moves_when_advanced() {
+        let clock = ManualClock::new();
+        let initial = clock.now();
+
+        assert_eq!(initial, clock.now());
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(initial + Duration::from_secs(5), clock.now());
+
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(initial + Duration::from_millis(5250), clock.now());
+    }
+
+    #[test]
+    fn broker_activity_clock_follows_broker_time() {
+        let clock = BrokerActivityClock::new(Duration::from_secs(30), None);
+        let start = clock.start;
+
+        clock.observe_at(1000, start);
+        assert_eq!(start, clock.now_at(start));
+
+        // The broker time between messages is used, even if local time drifted.
+        clock.observe_at(1010, start + Duration::from_secs(12));
+        assert_eq!(
+            start + Duration::from_secs(10),
+            clock.now_at(start + Duration::from_secs(12))
+        );
+
+        // Local time moves the clock forward between messages.
+        assert_eq!(
+            start + Duration::from_secs(13),
+            clock.now_at(start + Duration::from_secs(15))
+        );
+
+        // The clock never moves backwards, even when broker time lags behind.
+        clock.observe_at(1011, start + Duration::from_secs(15));
+        assert_eq!(
+            start + Duration::from_secs(13),
+            clock.now_at(start + Duration::from_secs(15))
+        );
+    }
+
+    #[test]
+    fn broker_activity_clock_skips_suspensions() {
+        let clock = BrokerActivityClock::new(Duration::from_secs(30), None);
+        let start = clock.start;
+
+        clock.observe_at(1000, start);
+
+        // An hour of broker time passed, but only five seconds of local time.
+        let suspension = clock.observe_at(4600, start + Duration::from_secs(5));
+        assert_eq!(Some(Duration::from_secs(3595)), suspension);
+        assert_eq!(
+            start + Duration::from_secs(5),
+            clock.now_at(start + Duration::from_secs(5))
+        );
+
+        // Older timestamps are ignored.
+        assert_eq!(None, clock.observe_at(1000, start + Duration::from_secs(6)));
+    }
+
+    #[test]
+    fn broker_activity_clock_persists_last_activity() {
+        let state_file =
+            std::env::temp_dir().join(format!("agemo-activity-{}.json", Uuid::new_v4()));
+
+        let clock = BrokerActivityClock::new(Duration::from_secs(30), Some(state_file.clone()));
+        clock.observe(1000);
+
+        // A new clock, such as after a restart, recognizes the suspension since the last activity.
+        let clock = BrokerActivityClock::new(Duration::from_secs(30), Some(state_file.clone()));
+        let start = clock.start;
+        assert!(clock.observe_at(5000, start).is_some());
+        assert_eq!(start, clock.now_at(start));
+
+        let _ = fs::remove_file(state_file);
+    }
+
+    #[test]
+    fn broker_activity_clock_debounces_persisting() {
+        let state_file =
+            std::env::temp_dir().join(format!("agemo-activity-{}.json", Uuid::new_v4()));
+        let read_state = || -> ActivityState {
+            serde_json::from_str(&fs::read_to_string(&state_file).unwrap()).unwrap()
+        };
+
+        let clock = BrokerActivityClock::new(Duration::from_secs(30), Some(state_file.clone()));
+        let start = clock.start;
+
+        clock.observe_at(1000, start);
+        assert_eq!(1000, read_state().last_activity_secs);
+
+        // Messages within the persist interval are not written.
+        clock.observe_at(1010, start + Duration::from_secs(10));
+        assert_eq!(1000, read_state().last_activity_secs);
+
+        clock.observe_at(1060, start + ACTIVITY_PERSIST_INTERVAL);
+        assert_eq!(1060, read_state().last_activity_secs);
+
+        // A suspension is written right away.
+        let resumed_at = start + ACTIVITY_PERSIST_INTERVAL + Duration::from_secs(1);
+        assert!(clock.observe_at(5000, resumed_at).is_some());
+        assert_eq!(5000, read_state().last_activity_secs);
+
+        clock.observe_at(5001, resumed_at + Duration::from_secs(1));
+        assert_eq!(5000, read_state().last_activity_secs);
+
+        // Flushing writes the latest activity.
+        clock.flush();
+        assert_eq!(5001, read_state().last_activity_secs);
+
+        let _ = fs::remove_file(state_file);
+    }
+
+    #[test]
+    fn sequential_generator_is_deterministic() {
+        let generator = SequentialTopicIdGenerator::new("topic-");
+
+        assert_eq!("topic-0", generator.generate());
+        assert_eq!("topic-1", generator.generate());
+        assert_eq!("topic-2", generator.generate());
+    }
+
+    #[test]
+    fn uuid_generator_produces_unique_uuids() {
+        let generator = UuidTopicIdGenerator;
+
+        let first = generator.generate();
+        let second = generator.generate();
+
+        assert!(Uuid::parse_str(&first).is_ok());
+        assert!(Uuid::parse_str(&second).is_ok());
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn sequence_epochs_always_increase() {
+        let epochs = SequenceEpochs::default();
+
+        let first = epochs.next();
+        assert!(first > 0);
+
+        // Epochs generated within the same millisecond still increase.
+        let mut last = first;
+        for _ in 0..100 {
+            let epoch = epochs.next();
+            assert!(epoch > last);
+            last = epoch;
+        }
+    }
+}
@@ -0,0 +1,182 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Locates the publishers of the subjects that subscribers request topics for.
+//!
+//! Subscribers that call `RequestTopic` ask for data on a subject instead of subscribing to a
+//! topic a publisher created. The service finds a publisher of the subject through a
+//! [`PublisherLocator`] and creates the topic on behalf of the publisher. Publishers are either
+//! configured in the service configuration, or register the subjects they serve with the
+//! [`PublisherDirectory`].
+
+use common::shared_state::SharedState;
+use log::{info, warn};
+use std::{collections::HashMap, sync::Arc};
+
+use crate::load_config::SubjectPublisher;
+
+/// Trait that finds the publisher serving a subject.
+pub trait PublisherLocator: Send + Sync {
+    /// Returns the publisher serving the given subject, or `None` if no publisher serves it.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject` - The subject requested by a subscriber.
+    fn locate(&self, subject: &str) -> Option<SubjectPublisher>;
+}
+
+/// Locates publishers from the `subject_publishers` section of the service configuration.
+#[derive(Clone, Debug, Default)]
+pub struct ConfiguredPublishers {
+    publishers: HashMap<String, SubjectPublisher>,
+}
+
+impl ConfiguredPublishers {
+    /// Creates a new ConfiguredPublishers instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `publishers` - The configured publishers by subject.
+    pub fn new(publishers: HashMap<String, SubjectPublisher>) -> Self {
+        ConfiguredPublishers { publishers }
+    }
+}
+
+impl PublisherLocator for ConfiguredPublishers {
+    fn locate(&self, subject: &str) -> Option<SubjectPublisher> {
+        self.publishers.get(subject).cloned()
+    }
+}
+
+/// Directory of the publishers that registered the subjects they serve with `RegisterPublisher`.
+///
+/// Registered publishers take precedence over the publishers located by the fallback locator, so
+/// that a running publisher can take over a subject from the service configuration.
+pub struct PublisherDirectory {
+    publishers: SharedState<HashMap<String, SubjectPublisher>>,
+    fallback: Arc<dyn PublisherLocator>,
+}
+
+impl PublisherDirectory {
+    /// Creates a new PublisherDirectory instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `fallback` - Locates the publishers of subjects that no publisher registered.
+    pub fn new(fallback: Arc<dyn PublisherLocator>) -> Self {
+        PublisherDirectory {
+            publishers: SharedState::new(HashMap::new()),
+            fallback,
+        }
+    }
+
+    /// Registers the subjects a publisher serves, replacing the subjects it registered before.
+    /// A publisher registering no subjects is removed from the directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `publisher` - The publisher to register.
+    /// * `subjects` - The subjects the publisher serves.
+    pub fn register(&self, publisher: SubjectPublisher, subjects: Vec<String>) {
+        let mut publishers = self.publishers.lock();
+
+        publishers.retain(|_, registered| registered.publisher_id != publisher.publisher_id);
+
+        for subject in subjects {
+            if let Some(previous) = publishers.insert(subject.clone(), publisher.clone()) {
+                warn!(
+                    "Publisher '{}' took over subject '{subject}' from '{}'.",
+                    publisher.publisher_id, previous.publisher_id
+                );
+            }
+        }
+
+        info!(
+            "Publisher '{}' serves {} registered subjects.",
+            publisher.publisher_id,
+            publishers
+                .values()
+                .filter(|registered| registered.publisher_id == publisher.publisher_id)
+                .count()
+        );
+    }
+}
+
+impl PublisherLocator for PublisherDirectory {
+    fn locate(&self, subject: &str) -> Option<SubjectPublisher> {
+        let registered = self.publishers.lock().get(subject).cloned();
+
+        registered.or_else(|| self.fallback.locate(subject))
+    }
+}
+
+#[cfg(test)]
+mod publisher_locator_tests {
+    use super::*;
+
+    #[test]
+    fn configured_publishers_locate_test() {
+        let publisher = SubjectPublisher {
+            publisher_id: "hvac".to_string(),
+            management_callback: "http://0.0.0.0:50061".to_string(),
+        };
+        let locator = ConfiguredPublishers::new(HashMap::from([(
+            "cabin/temperature".to_string(),
+            publisher.clone(),
+        )]));
+
+        assert_eq!(Some(publisher), locator.locate("cabin/temperature"));
+        assert_eq!(None, locator.locate("cabin/humidity"));
+    }
+
+    #[test]
+    fn publisher_directory_register_test() {
+        let publisher = |id: &str| SubjectPublisher {
+            publisher_id: id.to_string(),
+            management_callback: format!("http://{id}:50061"),
+        };
+        let fallback = ConfiguredPublishers::new(HashMap::from([(
+            "cabin/temperature".to_string(),
+            publisher("configured"),
+        )]));
+        let directory = PublisherDirectory::new(Arc::new(fallback));
+
+        // Subjects that no publisher registered fall back to the configured publishers.
+        assert_eq!(
+            Some(publisher("configured")),
+            directory.locate("cabin/temperature")
+        );
+        assert_eq!(None, directory.locate("cabin/humidity"));
+
+        directory.register(
+            publisher("hvac"),
+            vec![
+                "cabin/temperature".to_string(),
+                "cabin/humidity".to_string(),
+            ],
+        );
+        assert_eq!(
+            Some(publisher("hvac")),
+            directory.locate("cabin/temperature")
+        );
+        assert_eq!(Some(publisher("hvac")), directory.locate("cabin/humidity"));
+
+        // Registering again replaces the subjects of the publisher.
+        directory.register(publisher("hvac"), vec!["cabin/humidity".to_string()]);
+        assert_eq!(
+            Some(publisher("configured")),
+            directory.locate("cabin/temperature")
+        );
+
+        // The latest registration of a subject wins.
+        directory.register(publisher("climate"), vec!["cabin/humidity".to_string()]);
+        assert_eq!(
+            Some(publisher("climate")),
+            directory.locate("cabin/humidity")
+        );
+
+        directory.register(publisher("climate"), Vec::new());
+        assert_eq!(None, directory.locate("cabin/humidity"));
+    }
+}
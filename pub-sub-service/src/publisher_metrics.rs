@@ -0,0 +1,204 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Module containing the per-publisher statistics of the management callbacks made to publishers.
+//!
+//! The statistics are labeled by publisher id, so that a noisy publisher can be told apart from
+//! the others. To keep the number of labels bounded, only the first publishers up to a configured
+//! limit get their own label, and the callbacks of any other publisher are counted together under
+//! [`OTHER_PUBLISHERS`].
+
+use std::{collections::BTreeMap, time::Duration};
+
+/// Label of the publishers past the limit of labeled publishers.
+pub const OTHER_PUBLISHERS: &str = "other";
+
+/// Upper bounds in seconds of the buckets of the callback latency histograms.
+pub const CALLBACK_LATENCY_BUCKETS: [f64; 8] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0, 5.0];
+
+/// Statistics of the management callbacks made to a publisher.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PublisherCallbackStats {
+    /// The number of callbacks whose latency fell in each bucket of
+    /// [`CALLBACK_LATENCY_BUCKETS`]. Not cumulative, and callbacks slower than the last bucket are
+    /// only counted in `callbacks`.
+    pub latency_buckets: [u64; CALLBACK_LATENCY_BUCKETS.len()],
+    /// The total latency of the callbacks.
+    pub latency_sum: Duration,
+    /// The number of callbacks made.
+    pub callbacks: u64,
+    /// The number of callbacks that failed.
+    pub failures: u64,
+}
+
+impl PublisherCallbackStats {
+    /// Records a callback.
+    ///
+    /// # Arguments
+    ///
+    /// * `latency` - How long the callback took.
+    /// * `succeeded` - Whether the callback succeeded.
+    fn record(&mut self, latency: Duration, succeeded: bool) {
+        let latency_secs = latency.as_secs_f64();
+        if let Some(bucket) = CALLBACK_LATENCY_BUCKETS
+            .iter()
+            .position(|bound| latency_secs <= *bound)
+        {
+            self.latency_buckets[bucket] += 1;
+        }
+
+        self.latency_sum += latency;
+        self.callbacks += 1;
+
+        if !succeeded {
+            self.failures += 1;
+        }
+    }
+}
+
+/// The statistics of the management callbacks made to each publisher.
+#[derive(Clone, Debug)]
+pub struct PublisherMetrics {
+    /// Maximum number of publishers with their own label.
+    max_publishers: usize,
+    /// The callback statistics, by publisher label.
+    publishers: BTreeMap<String, PublisherCallbackStats>,
+}
+
+impl PublisherMetrics {
+    /// Creates new PublisherMetrics.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_publishers` - Maximum number of publishers with their own label. The callbacks of
+    ///                      other publishers are counted under [`OTHER_PUBLISHERS`].
+    pub fn new(max_publishers: usize) -> Self {
+        PublisherMetrics {
+            max_publishers,
+            publishers: BTreeMap::new(),
+        }
+    }
+
+    /// Records a callback made to a publisher.
+    ///
+    /// # Arguments
+    ///
+    /// * `publisher_id` - The id of the publisher that was called.
+    /// * `latency` - How long the callback took.
+    /// * `succeeded` - Whether the callback succeeded.
+    pub fn record_callback(&mut self, publisher_id: &str, latency: Duration, succeeded: bool) {
+        let labeled = self
+            .publishers
+            .keys()
+            .filter(|label| *label != OTHER_PUBLISHERS)
+            .count();
+
+        let label = if self.publishers.contains_key(publisher_id) || labeled < self.max_publishers {
+            publisher_id
+        } else {
+            OTHER_PUBLISHERS
+        };
+
+        self.publishers
+            .entry(label.to_string())
+            .or_default()
+            .record(latency, succeeded);
+    }
+
+    /// Returns the callback statistics of each publisher label, ordered by label.
+    pub fn callback_stats(&self) -> Vec<(String, PublisherCallbackStats)> {
+        self.publishers
+            .iter()
+            .map(|(label, stats)| (label.clone(), stats.clone()))
+            .collect()
+    }
+
+    /// Groups a count of each publisher under the publisher labels, keeping the publishers with
+    /// the greatest counts up to the limit of labeled publishers and adding up the others under
+    /// [`OTHER_PUBLISHERS`]. Returns the counts ordered by label.
+    ///
+    /// # Arguments
+    ///
+    /// * `counts` - The count of each publisher.
+    pub fn limit_labels(&self, counts: BTreeMap<String, u64>) -> Vec<(String, u64)> {
+        let mut counts: Vec<(String, u64)> = counts.into_iter().collect();
+        // Greatest counts first, ties broken by publisher id.
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let others: u64 = counts
+            .iter()
+            .skip(self.max_publishers)
+            .map(|(_, count)| count)
+            .sum();
+        let has_others = counts.len() > self.max_publishers;
+
+        counts.truncate(self.max_publishers);
+        if has_others {
+            counts.push((OTHER_PUBLISHERS.to_string(), others));
+        }
+
+        counts.sort_by(|a, b| a.0.cmp(&b.0));
+        counts
+    }
+}
+
+#[cfg(test)]
+mod publisher_metrics_tests {
+    use super::*;
+
+    #[test]
+    fn record_callback_limits_labels_test() {
+        let mut metrics = PublisherMetrics::new(2);
+
+        metrics.record_callback("pub_1", Duration::from_millis(3), true);
+        metrics.record_callback("pub_2", Duration::from_millis(40), false);
+        metrics.record_callback("pub_3", Duration::from_secs(10), false);
+        // Publishers that already have a label keep it.
+        metrics.record_callback("pub_1", Duration::from_millis(200), true);
+
+        let stats = metrics.callback_stats();
+        let labels: Vec<&str> = stats.iter().map(|(label, _)| label.as_str()).collect();
+        assert_eq!(vec![OTHER_PUBLISHERS, "pub_1", "pub_2"], labels);
+
+        let (_, other) = &stats[0];
+        assert_eq!(1, other.callbacks);
+        assert_eq!(1, other.failures);
+        // Slower than the last bucket.
+        assert_eq!(0, other.latency_buckets.iter().sum::<u64>());
+
+        let (_, pub_1) = &stats[1];
+        assert_eq!(2, pub_1.callbacks);
+        assert_eq!(0, pub_1.failures);
+        assert_eq!(1, pub_1.latency_buckets[0]);
+        assert_eq!(1, pub_1.latency_buckets[5]);
+        assert_eq!(Duration::from_millis(203), pub_1.latency_sum);
+    }
+
+    #[test]
+    fn limit_labels_test() {
+        let metrics = PublisherMetrics::new(2);
+        let counts = BTreeMap::from([
+            ("pub_1".to_string(), 1),
+            ("pub_2".to_string(), 7),
+            ("pub_3".to_string(), 3),
+            ("pub_4".to_string(), 2),
+        ]);
+
+        assert_eq!(
+            vec![
+                (OTHER_PUBLISHERS.to_string(), 3),
+                ("pub_2".to_string(), 7),
+                ("pub_3".to_string(), 3),
+            ],
+            metrics.limit_labels(counts)
+        );
+
+        let metrics = PublisherMetrics::new(0);
+        let counts = BTreeMap::from([("pub_1".to_string(), 1)]);
+        assert_eq!(
+            vec![(OTHER_PUBLISHERS.to_string(), 1)],
+            metrics.limit_labels(counts)
+        );
+    }
+}
@@ -2,33 +2,152 @@
 // Licensed under the MIT license.
 // SPDX-License-Identifier: MIT
 
-//! Module containing trait that a publish/subscribe connector must implement.
+//! Module containing the configuration and shared state handed to the publish/subscribe
+//! connectors.
 //!
-//! The [`PubSubConnector`] trait is required to be implemented by a broker connector to enable the
-//! dynamic topic management in the pub sub service. Below is a list of requirements that a broker
-//! must meet to be integrated with no changes to the service:
-//! - Broker must provide a way to monitor subscribe requests to a topic.
-//! - Broker must provide a way to monitor unsubscribe requests to a topic.
-//! - Broker must provide a way to monitor clients that disconnect from the broker unexpectedly.
-//!   This is to enable the service to publish a [`TOPIC_DELETED_MSG`] to notify subscribers to
-//!   drop the topic.
-//!
-//! If a broker you want to use does not meet the above requirements, please reach out via an
-//! issue on GitHub.
+//! The [`PubSubConnector`] trait that a broker connector must implement lives in the
+//! `agemo-connector` crate, so that connectors can be built against a versioned API. It is
+//! re-exported here. The updates a connector reports are converted to the [`MonitorMessage`]s
+//! handled by the topic manager, which also carry the actions raised by the service itself.
 
-use async_trait::async_trait;
-use std::sync::mpsc;
+pub use agemo_connector::{update_topic_information, PubSubConnector};
+use common::shared_state::SharedState;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fmt, fs, io,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    time::{Duration, Instant},
+};
 use strum_macros::{Display, EnumString};
+use uuid::Uuid;
 
-/// Enum defining the protocol type used by the messaging broker.
-#[derive(Debug, Clone, Copy, Display, EnumString, Eq, PartialEq)]
-pub enum PubSubProtocol {
-    /// Represents the MQTT protocol.
-    #[strum(serialize = "MQTT")]
-    Mqtt,
+use crate::{
+    error::AgemoError,
+    latency_probe::LatencyProbesHandle,
+    load_config::{
+        ConnectorLibrarySettings, DdsConnectorSettings, KafkaConnectorSettings,
+        MqttConnectionSettings,
+    },
+    providers::BrokerActivityClock,
+    self_test::PendingProbes,
+    subscriber_acl::SubscriberIdentification,
+    topic_name_codec::TopicNaming,
+};
+
+/// Default prefix of the id used to create the broker client.
+pub const DEFAULT_CLIENT_ID_PREFIX: &str = "pubsub_connector_client";
+/// Number of characters of a generated instance id.
+const INSTANCE_ID_LEN: usize = 8;
+/// Extension of the file that a generated instance id is persisted to, next to the state file.
+const INSTANCE_ID_EXTENSION: &str = "instance_id";
+/// Window in which repeated session takeovers are treated as a client id collision.
+const COLLISION_WINDOW: Duration = Duration::from_secs(60);
+/// Number of session takeovers within the collision window that flags a client id collision.
+const COLLISION_THRESHOLD: usize = 3;
+
+/// Creates a broker client id from the given prefix and the id of the service instance, so that
+/// several instances of the service can share a messaging broker while each instance keeps the
+/// same client id, and with it its broker session, across restarts.
+///
+/// # Arguments
+///
+/// * `prefix` - The prefix of the client id.
+/// * `instance_id` - The stable id of the service instance, if any. The prefix alone is used
+///                   otherwise.
+pub fn instance_client_id(prefix: &str, instance_id: Option<&str>) -> String {
+    match instance_id {
+        Some(instance_id) => format!("{prefix}_{instance_id}"),
+        None => prefix.to_string(),
+    }
+}
+
+/// Returns the stable id of the service instance. A configured id is used as is. Otherwise an id
+/// is generated once and persisted next to the state file, and read back on later starts.
+/// Returns `None` if no id is configured and no state file is set.
+///
+/// # Arguments
+///
+/// * `configured` - The instance id set in the settings, if any.
+/// * `state_file` - The file the service persists its state to, if any.
+pub fn instance_id(
+    configured: Option<&str>,
+    state_file: Option<&Path>,
+) -> Result<Option<String>, AgemoError> {
+    if let Some(configured) = configured {
+        return Ok(Some(configured.to_string()));
+    }
+
+    let Some(state_file) = state_file else {
+        return Ok(None);
+    };
+
+    let path = state_file.with_extension(INSTANCE_ID_EXTENSION);
+
+    match fs::read_to_string(&path) {
+        Ok(contents) if !contents.trim().is_empty() => {
+            return Ok(Some(contents.trim().to_string()))
+        }
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => {
+            return Err(AgemoError::Storage(format!(
+                "unable to read the instance id from '{}': {err}",
+                path.display()
+            )))
+        }
+    }
+
+    let generated = Uuid::new_v4().simple().to_string()[..INSTANCE_ID_LEN].to_string();
+
+    fs::write(&path, &generated).map_err(|err| {
+        AgemoError::Storage(format!(
+            "unable to persist the instance id to '{}': {err}",
+            path.display()
+        ))
+    })?;
+
+    Ok(Some(generated))
 }
 
-/// Enum representing an action that happens in the messaging broker.
+/// Detects a broker client being repeatedly disconnected because another client connected with
+/// the same id.
+#[derive(Debug, Default)]
+pub struct ClientIdCollisionDetector {
+    takeovers: VecDeque<Instant>,
+}
+
+impl ClientIdCollisionDetector {
+    /// Records that the client's session was taken over by another client with the same id.
+    ///
+    /// Returns whether the takeovers within the collision window indicate a client id collision.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time the session was taken over.
+    pub fn record_takeover(&mut self, now: Instant) -> bool {
+        while self
+            .takeovers
+            .front()
+            .is_some_and(|takeover| now.duration_since(*takeover) > COLLISION_WINDOW)
+        {
+            self.takeovers.pop_front();
+        }
+
+        self.takeovers.push_back(now);
+
+        self.takeovers.len() >= COLLISION_THRESHOLD
+    }
+}
+
+/// Enum representing an action handled by the topic manager. Includes the actions reported by the
+/// broker connector, see [`agemo_connector::PubSubAction`], and the actions raised by the service
+/// itself.
 #[derive(Clone, Copy, Debug, Display, EnumString, Eq, PartialEq)]
 pub enum PubSubAction {
     /// Represents a subscribe to a topic.
@@ -46,74 +165,592 @@ pub enum PubSubAction {
     /// Represents an unclean publisher disconnect.
     #[strum(serialize = "PUBDISCONNECT")]
     PubDisconnect,
+    /// Represents an unclean subscriber disconnect.
+    #[strum(serialize = "SUBDISCONNECT")]
+    SubDisconnect,
+    /// Represents the registration of a publisher as the only client allowed to publish to a
+    /// topic.
+    #[strum(serialize = "REGISTER")]
+    Register,
+    /// Represents a request to compare the topics on the broker against the managed topics.
+    #[strum(serialize = "RECONCILE")]
+    Reconcile,
+    /// Represents the connector (re)connecting to the messaging broker.
+    #[strum(serialize = "BROKERCONNECTED")]
+    BrokerConnected,
+    /// Represents the connector losing its connection to the messaging broker.
+    #[strum(serialize = "BROKERDISCONNECTED")]
+    BrokerDisconnected,
+    /// Represents the system preparing to suspend.
+    #[strum(serialize = "POWERSUSPEND")]
+    PowerSuspend,
+    /// Represents the system resuming from suspend.
+    #[strum(serialize = "POWERRESUME")]
+    PowerResume,
+    /// Represents a request to start a pre-warmed topic before it has subscribers.
+    #[strum(serialize = "WARM")]
+    Warm,
+    /// Represents a request to probe a self-test topic through the messaging broker.
+    #[strum(serialize = "SELFTEST")]
+    SelfTest,
+    /// Represents a STOP action delayed by the action hysteresis that is now due.
+    #[strum(serialize = "DEFERREDSTOP")]
+    DeferredStop,
+    /// Represents a placeholder topic that found no publisher within the placeholder TTL.
+    #[strum(serialize = "EXPIREPLACEHOLDER")]
+    ExpirePlaceholder,
+    /// Represents a request to measure the latency of the messaging broker on the latency probe
+    /// topic.
+    #[strum(serialize = "LATENCYPROBE")]
+    LatencyProbe,
+    /// Represents a topic that went without subscribers for longer than the maximum idle
+    /// duration.
+    #[strum(serialize = "IDLEEXPIRED")]
+    IdleExpired,
+    /// Represents a publisher adopting a topic that already has subscribers.
+    #[strum(serialize = "ADOPT")]
+    Adopt,
 }
 
-/// Structure defining a message returned from the broker connector when an action happens.
+/// Structure defining a message handled by the topic manager when an action happens.
 #[derive(Debug)]
 pub struct MonitorMessage {
     /// A string that provides the context relevant to the action that triggered the message.
     pub context: String,
-    /// The action that triggered the message from the broker connector.
+    /// The action that triggered the message.
     pub action: PubSubAction,
+    /// The id of the client that caused the action, if known. For subscribe and unsubscribe
+    /// actions this is the id of the subscriber.
+    pub client_id: Option<String>,
+    /// The identity the subscriber of a subscribe or unsubscribe action authenticated as, if the
+    /// connector identifies subscribers by their identity and it is known.
+    pub identity: Option<String>,
 }
 
-/// Trait that needs to be implmented by a broker connector for the pub sub service to get
-/// the necessary information from the messaging broker to implement dynamic topic management.
-#[async_trait]
-pub trait PubSubConnector {
-    /// Creates a new instance of the struct implementing this trait.
+impl TryFrom<agemo_connector::MonitorMessage> for MonitorMessage {
+    type Error = AgemoError;
+
+    /// Converts an update reported by the broker connector. Fails for actions added to the
+    /// connector API that the service doesn't handle.
     ///
     /// # Arguments
     ///
-    /// * `client_id` - Id to be used to create the broker client.
-    /// * `uri` - The uri of the broker that the client is connecting to.
-    fn new(client_id: String, uri: String) -> Self;
+    /// * `msg` - The update reported by the connector.
+    fn try_from(msg: agemo_connector::MonitorMessage) -> Result<Self, Self::Error> {
+        let action = match msg.action {
+            agemo_connector::PubSubAction::Subscribe => PubSubAction::Subscribe,
+            agemo_connector::PubSubAction::Unsubscribe => PubSubAction::Unsubscribe,
+            agemo_connector::PubSubAction::Timeout => PubSubAction::Timeout,
+            agemo_connector::PubSubAction::Delete => PubSubAction::Delete,
+            agemo_connector::PubSubAction::PubDisconnect => PubSubAction::PubDisconnect,
+            agemo_connector::PubSubAction::SubDisconnect => PubSubAction::SubDisconnect,
+            action => {
+                return Err(AgemoError::Broker(format!(
+                    "the connector reported unknown action '{action}'"
+                )))
+            }
+        };
 
-    /// Function that monitors the messaging broker for changes and forwards those changes back
-    /// over the callback channel.
+        Ok(MonitorMessage {
+            context: msg.context,
+            action,
+            client_id: msg.client_id,
+            identity: msg.identity,
+        })
+    }
+}
+
+/// Reports the state of the broker connector's connection to the messaging broker to the topic
+/// manager, so that topics aren't timed out while updates can't flow. Reports are dropped until
+/// the connector runs.
+#[derive(Clone, Debug, Default)]
+pub struct BrokerConnection {
+    /// The channel to the topic manager.
+    sender: Option<mpsc::Sender<MonitorMessage>>,
+    /// The client id of the connector.
+    client_id: String,
+}
+
+impl BrokerConnection {
+    /// Creates a new BrokerConnection.
     ///
-    /// This function monitors changes to topics and connections on the messaging broker. Every
-    /// update to the broker is sent to the provided callback channel in the format of a
-    /// [`MonitorMessage`]. The types of updates that are monitored are listed out in the
-    /// [`PubSubAction`] enum.
+    /// # Arguments
+    ///
+    /// * `sender` - The channel to the topic manager.
+    /// * `client_id` - The client id of the connector.
+    pub fn new(sender: mpsc::Sender<MonitorMessage>, client_id: String) -> Self {
+        BrokerConnection {
+            sender: Some(sender),
+            client_id,
+        }
+    }
+
+    /// Reports that the connector (re)connected to the messaging broker, or lost its connection
+    /// to it.
     ///
     /// # Arguments
     ///
-    /// * `cb_channel` - Callback channel used to forward messages from the connector to the rest
-    ///                  of the pub sub service logic.
-    async fn monitor_topics(
-        &mut self,
-        cb_channel: mpsc::Sender<MonitorMessage>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+    /// * `connected` - Whether the connector is connected to the broker.
+    pub fn report(&self, connected: bool) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        let action = if connected {
+            PubSubAction::BrokerConnected
+        } else {
+            PubSubAction::BrokerDisconnected
+        };
+
+        // The topic manager no longer receives updates once the service shuts down.
+        let _ = sender.send(MonitorMessage {
+            context: self.client_id.clone(),
+            action,
+            client_id: None,
+            identity: None,
+        });
+    }
+}
+
+/// Enum defining the protocol type used by the messaging broker.
+#[derive(Debug, Clone, Copy, Display, EnumString, Eq, PartialEq)]
+pub enum PubSubProtocol {
+    /// Represents the MQTT protocol.
+    #[strum(serialize = "MQTT")]
+    Mqtt,
+}
 
-    /// Function that deletes a topic from the messaging broker.
+/// Credentials used by a connector to authenticate with the messaging broker.
+#[derive(Clone, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+pub struct BrokerCredentials {
+    /// The username to authenticate with.
+    pub username: Option<String>,
+    /// The password to authenticate with.
+    pub password: Option<String>,
+    /// A token to authenticate with, such as a JWT. Takes precedence over the password for
+    /// brokers that accept tokens in place of a password.
+    pub token: Option<String>,
+}
+
+impl BrokerCredentials {
+    /// Returns the secret to authenticate with, preferring the token over the password.
+    pub fn secret(&self) -> Option<&str> {
+        self.token.as_deref().or(self.password.as_deref())
+    }
+}
+
+impl fmt::Debug for BrokerCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Secrets are redacted so credentials can't leak through logs.
+        f.debug_struct("BrokerCredentials")
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+/// Statistics reported by the messaging broker. Each statistic is None until the broker reports it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BrokerStats {
+    /// The number of clients currently connected to the broker.
+    pub connected_clients: Option<u64>,
+    /// The average number of messages received by the broker per minute.
+    pub messages_received_per_min: Option<f64>,
+    /// The average number of messages sent by the broker per minute.
+    pub messages_sent_per_min: Option<f64>,
+    /// The total number of messages the broker has dropped.
+    pub messages_dropped: Option<u64>,
+}
+
+/// Alias for a handle to the latest statistics reported by the messaging broker.
+pub type BrokerStatsHandle = Arc<SharedState<BrokerStats>>;
+
+/// Topics whose latest message is retained by the messaging broker. Shared between the pub sub
+/// service, which records the topics as they are created, and the broker connector, which clears
+/// their retained message once they are deleted.
+#[derive(Clone, Debug, Default)]
+pub struct RetainedTopics {
+    topics: Arc<SharedState<HashSet<String>>>,
+}
+
+impl RetainedTopics {
+    /// Records a topic whose latest message is retained.
     ///
-    /// This function deletes a topic from the messaging broker. In addition, it sends a topic
-    /// deletion message across the topic channel to inform any subscribers that the topic is being
-    /// deleted.
+    /// # Arguments
+    ///
+    /// * `topic` - The topic to record.
+    pub fn insert(&self, topic: &str) {
+        self.topics.lock().insert(topic.to_string());
+    }
+
+    /// Forgets a topic, returning whether its latest message was retained.
     ///
     /// # Arguments
     ///
-    /// * `topic` - Generated topic to be deleted from the service.
-    /// * `deletion_msg` - Deletion message to be sent to any subscribers on the given topic.
-    async fn delete_topic(
-        &self,
-        topic: String,
-        deletion_msg: String,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+    /// * `topic` - The topic to forget.
+    pub fn take(&self, topic: &str) -> bool {
+        self.topics.lock().remove(topic)
+    }
 }
 
-/// Function that is used to send a [`MonitorMessage`] to the given channel.
-///
-/// This function is a common function that can be utilized while implementing the
-/// [`PubSubConnector`] trait.
-///
-/// # Arguments
-///
-/// * `update_msg` - Message collected by the connector related to an update to a topic.
-/// * `channel` - Channel used to forward the given update_msg to the rest of the Pub Sub Service.
-pub fn update_topic_information(update_msg: MonitorMessage, channel: mpsc::Sender<MonitorMessage>) {
-    channel.send(update_msg).unwrap();
+/// The message sent to the subscribers of a topic when it is deleted.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeletionMessage {
+    /// The deletion message configured in the service.
+    #[default]
+    Default,
+    /// A message chosen by the publisher of the topic.
+    Custom(String),
+    /// No message is sent.
+    Suppressed,
+}
+
+impl DeletionMessage {
+    /// Creates the deletion message requested by a publisher when creating a topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message requested by the publisher, if any.
+    /// * `suppress` - Whether the publisher asked for no message to be sent.
+    pub fn from_request(message: Option<String>, suppress: bool) -> Self {
+        match (message, suppress) {
+            (_, true) => DeletionMessage::Suppressed,
+            (Some(message), false) => DeletionMessage::Custom(message),
+            (None, false) => DeletionMessage::Default,
+        }
+    }
+
+    /// Returns the message to send to the subscribers of the deleted topic, or `None` if no
+    /// message is sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `default` - The deletion message configured in the service.
+    pub fn resolve(&self, default: &Arc<str>) -> Option<Arc<str>> {
+        match self {
+            DeletionMessage::Default => Some(default.clone()),
+            DeletionMessage::Custom(message) => Some(Arc::from(message.as_str())),
+            DeletionMessage::Suppressed => None,
+        }
+    }
+}
+
+/// Deletion messages of the topics that are being deleted. Shared between the topic manager,
+/// which records the deletion message of a topic as it removes the topic, and the broker
+/// connector, which sends it to the subscribers of the topic.
+#[derive(Clone, Debug, Default)]
+pub struct DeletionMessages {
+    messages: Arc<SharedState<HashMap<String, DeletionMessage>>>,
+}
+
+impl DeletionMessages {
+    /// Records the deletion message of a topic. Topics that keep the default deletion message
+    /// are not recorded.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic being deleted.
+    /// * `message` - The deletion message of the topic.
+    pub fn insert(&self, topic: &str, message: DeletionMessage) {
+        if message != DeletionMessage::Default {
+            self.messages.lock().insert(topic.to_string(), message);
+        }
+    }
+
+    /// Forgets a topic, returning its deletion message.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic to forget.
+    pub fn take(&self, topic: &str) -> DeletionMessage {
+        self.messages.lock().remove(topic).unwrap_or_default()
+    }
+}
+
+/// Health of the broker connector's subscriptions to the topics the messaging broker reports
+/// topic activity on. Shared between the broker connector, which records the subscriptions it
+/// failed to make while it retries them, and the admin service, which reports monitoring as
+/// degraded until every subscription is made.
+#[derive(Clone, Debug, Default)]
+pub struct MonitorHealth {
+    failed_subscriptions: Arc<SharedState<BTreeMap<String, String>>>,
+    monitoring: Arc<AtomicBool>,
+}
+
+impl MonitorHealth {
+    /// Records that the connector connected to the broker and started monitoring its topics.
+    pub fn record_monitoring(&self) {
+        self.monitoring.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether the connector has connected to the broker and started monitoring its
+    /// topics.
+    pub fn is_monitoring(&self) -> bool {
+        self.monitoring.load(Ordering::SeqCst)
+    }
+
+    /// Records a failed subscription to a monitor topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The monitor topic.
+    /// * `error` - The reason the subscription failed.
+    pub fn record_failure(&self, topic: &str, error: String) {
+        self.failed_subscriptions
+            .lock()
+            .insert(topic.to_string(), error);
+    }
+
+    /// Records a successful subscription to a monitor topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The monitor topic.
+    pub fn record_success(&self, topic: &str) {
+        self.failed_subscriptions.lock().remove(topic);
+    }
+
+    /// Returns the monitor topics the connector is not subscribed to, with the reason the last
+    /// subscription failed, ordered by topic.
+    pub fn failed_subscriptions(&self) -> Vec<(String, String)> {
+        self.failed_subscriptions
+            .lock()
+            .iter()
+            .map(|(topic, error)| (topic.clone(), error.clone()))
+            .collect()
+    }
+
+    /// Returns whether a subscription to a monitor topic failed, in which case topic activity may
+    /// go unnoticed.
+    pub fn is_degraded(&self) -> bool {
+        !self.failed_subscriptions.lock().is_empty()
+    }
+}
+
+/// Configuration used to create a broker connector.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectorConfig {
+    /// Id to be used to create the broker client.
+    pub client_id: String,
+    /// The uri of the broker that the client is connecting to.
+    pub uri: String,
+    /// Optional credentials used to authenticate with the broker.
+    pub credentials: Option<BrokerCredentials>,
+    /// The topic that clients publish their last will and testament to on unclean disconnect.
+    pub disconnect_topic: String,
+    /// MQTT v5 connection options. Ignored by connectors for brokers that don't use MQTT.
+    pub mqtt_connection: MqttConnectionSettings,
+    /// Ids of the service's own clients, whose subscriptions are not tracked as subscribers.
+    pub ignored_client_ids: Vec<String>,
+    /// How the connector identifies the subscribers of subscribe and unsubscribe updates, if
+    /// supported.
+    pub subscriber_identification: SubscriberIdentification,
+    /// Handle that the connector updates with statistics reported by the broker, if supported.
+    pub broker_stats: BrokerStatsHandle,
+    /// Clock that the connector reports the timestamps of broker messages to, if supported.
+    pub activity_clock: Option<Arc<BrokerActivityClock>>,
+    /// Shared library implementing the connector. Only used by the dynamic connector.
+    pub connector_library: Option<ConnectorLibrarySettings>,
+    /// Options of the Kafka cluster to connect to, if the broker is a Kafka cluster. Only used by
+    /// the Kafka connector.
+    pub kafka: Option<KafkaConnectorSettings>,
+    /// Options of the DDS domain to join, if the broker is a DDS domain. Only used by the DDS
+    /// connector.
+    pub dds: Option<DdsConnectorSettings>,
+    /// Self-test probes waiting for the result of probing their test topic.
+    pub self_test_probes: PendingProbes,
+    /// Topics whose retained message is cleared when they are deleted.
+    pub retained_topics: RetainedTopics,
+    /// Deletion messages of the topics that don't use the default deletion message.
+    pub deletion_messages: DeletionMessages,
+    /// Topic naming of the broker. Topics are encoded to broker names before they are handed to
+    /// the connector, and the topics the connector reports are decoded back.
+    pub topic_naming: TopicNaming,
+    /// Health of the connector's subscriptions to the broker's monitor topics, if supported.
+    pub monitor_health: MonitorHealth,
+    /// Round trips of the latency probes, recorded as the connector probes the latency probe
+    /// topic.
+    pub latency_probes: LatencyProbesHandle,
+    /// Reports the connection to the messaging broker to the topic manager.
+    pub broker_connection: BrokerConnection,
+}
+
+#[cfg(test)]
+mod broker_credentials_tests {
+    use super::*;
+
+    #[test]
+    fn secret_prefers_token_over_password_test() {
+        let mut credentials = BrokerCredentials {
+            username: Some("user".to_string()),
+            password: Some("password".to_string()),
+            token: None,
+        };
+        assert_eq!(Some("password"), credentials.secret());
+
+        credentials.token = Some("token".to_string());
+        assert_eq!(Some("token"), credentials.secret());
+
+        assert_eq!(None, BrokerCredentials::default().secret());
+    }
+
+    #[test]
+    fn debug_redacts_secrets_test() {
+        let credentials = BrokerCredentials {
+            username: Some("user".to_string()),
+            password: Some("password".to_string()),
+            token: Some("token".to_string()),
+        };
+
+        let output = format!("{credentials:?}");
+        assert!(output.contains("user"));
+        assert!(!output.contains("password\""));
+        assert!(!output.contains("token\""));
+    }
+}
+
+#[cfg(test)]
+mod client_id_tests {
+    use super::*;
+
+    #[test]
+    fn instance_client_id_test() {
+        assert_eq!(
+            "agemo_vehicle-1",
+            instance_client_id("agemo", Some("vehicle-1"))
+        );
+        assert_eq!("agemo", instance_client_id("agemo", None));
+    }
+
+    #[test]
+    fn instance_id_is_persisted_next_to_state_file_test() {
+        let state_file = std::env::temp_dir().join(format!("agemo-{}.json", Uuid::new_v4()));
+        let instance_id_file = state_file.with_extension(INSTANCE_ID_EXTENSION);
+
+        let first = instance_id(None, Some(&state_file)).unwrap().unwrap();
+        let second = instance_id(None, Some(&state_file)).unwrap().unwrap();
+
+        assert_eq!(INSTANCE_ID_LEN, first.len());
+        assert_eq!(first, second);
+        assert_eq!(first, fs::read_to_string(&instance_id_file).unwrap());
+
+        // A configured id takes precedence over the persisted one.
+        assert_eq!(
+            Some("vehicle-1".to_string()),
+            instance_id(Some("vehicle-1"), Some(&state_file)).unwrap()
+        );
+        assert_eq!(None, instance_id(None, None).unwrap());
+
+        let _ = fs::remove_file(instance_id_file);
+    }
+
+    #[test]
+    fn collision_detector_flags_repeated_takeovers_test() {
+        let mut detector = ClientIdCollisionDetector::default();
+        let start = Instant::now();
+
+        assert!(!detector.record_takeover(start));
+        assert!(!detector.record_takeover(start + Duration::from_secs(10)));
+        assert!(detector.record_takeover(start + Duration::from_secs(20)));
+
+        // Takeovers outside of the window are forgotten.
+        let later = start + COLLISION_WINDOW + Duration::from_secs(30);
+        assert!(!detector.record_takeover(later));
+    }
+}
+
+#[cfg(test)]
+mod retained_topics_tests {
+    use super::*;
+
+    #[test]
+    fn take_forgets_topic_test() {
+        let retained_topics = RetainedTopics::default();
+        retained_topics.insert("config-topic");
+
+        // Shared with the broker connector.
+        let connector_handle = retained_topics.clone();
+        assert!(connector_handle.take("config-topic"));
+        assert!(!retained_topics.take("config-topic"));
+        assert!(!retained_topics.take("other-topic"));
+    }
+}
+
+#[cfg(test)]
+mod deletion_messages_tests {
+    use super::*;
+
+    #[test]
+    fn from_request_test() {
+        assert_eq!(
+            DeletionMessage::Default,
+            DeletionMessage::from_request(None, false)
+        );
+        assert_eq!(
+            DeletionMessage::Custom("bye".to_string()),
+            DeletionMessage::from_request(Some("bye".to_string()), false)
+        );
+        assert_eq!(
+            DeletionMessage::Suppressed,
+            DeletionMessage::from_request(Some("bye".to_string()), true)
+        );
+    }
+
+    #[test]
+    fn take_resolves_deletion_message_test() {
+        let default: Arc<str> = Arc::from("TOPIC DELETED");
+        let deletion_messages = DeletionMessages::default();
+        deletion_messages.insert("custom", DeletionMessage::Custom("bye".to_string()));
+        deletion_messages.insert("suppressed", DeletionMessage::Suppressed);
+        deletion_messages.insert("default", DeletionMessage::Default);
+
+        assert_eq!(
+            Some(Arc::from("bye")),
+            deletion_messages.take("custom").resolve(&default)
+        );
+        assert_eq!(None, deletion_messages.take("suppressed").resolve(&default));
+        assert_eq!(
+            Some(default.clone()),
+            deletion_messages.take("default").resolve(&default)
+        );
+
+        // Topics are forgotten once taken.
+        assert_eq!(DeletionMessage::Default, deletion_messages.take("custom"));
+    }
+}
+
+#[cfg(test)]
+mod monitor_health_tests {
+    use super::*;
+
+    #[test]
+    fn monitor_health_recovers_test() {
+        let monitor_health = MonitorHealth::default();
+        assert!(!monitor_health.is_degraded());
+        assert!(!monitor_health.is_monitoring());
+
+        // Shared with the broker connector.
+        let connector_handle = monitor_health.clone();
+        connector_handle.record_monitoring();
+        assert!(monitor_health.is_monitoring());
+        connector_handle.record_failure("$SYS/broker/log/M/subscribe", "not authorized".into());
+        connector_handle.record_failure("$SYS/broker/log/M/unsubscribe", "timed out".into());
+        assert!(monitor_health.is_degraded());
+
+        connector_handle.record_success("$SYS/broker/log/M/subscribe");
+        assert_eq!(
+            vec![(
+                "$SYS/broker/log/M/unsubscribe".to_string(),
+                "timed out".to_string()
+            )],
+            monitor_health.failed_subscriptions()
+        );
+
+        connector_handle.record_success("$SYS/broker/log/M/unsubscribe");
+        assert!(!monitor_health.is_degraded());
+    }
 }
 
 #[cfg(test)]
@@ -123,15 +760,84 @@ mod pubsub_action_tests {
     #[test]
     fn action_to_string() {
         assert_eq!("SUBSCRIBE".to_string(), PubSubAction::Subscribe.to_string());
+        assert_eq!("REGISTER".to_string(), PubSubAction::Register.to_string());
+        assert_eq!("RECONCILE".to_string(), PubSubAction::Reconcile.to_string());
+        assert_eq!(
+            "BROKERCONNECTED".to_string(),
+            PubSubAction::BrokerConnected.to_string()
+        );
+        assert_eq!(
+            "BROKERDISCONNECTED".to_string(),
+            PubSubAction::BrokerDisconnected.to_string()
+        );
+        assert_eq!(
+            "POWERSUSPEND".to_string(),
+            PubSubAction::PowerSuspend.to_string()
+        );
         assert_eq!(
-            "UNSUBSCRIBE".to_string(),
-            PubSubAction::Unsubscribe.to_string()
+            "POWERRESUME".to_string(),
+            PubSubAction::PowerResume.to_string()
         );
-        assert_eq!("TIMEOUT".to_string(), PubSubAction::Timeout.to_string());
-        assert_eq!("DELETE".to_string(), PubSubAction::Delete.to_string());
+        assert_eq!("WARM".to_string(), PubSubAction::Warm.to_string());
+        assert_eq!("SELFTEST".to_string(), PubSubAction::SelfTest.to_string());
         assert_eq!(
-            "PUBDISCONNECT".to_string(),
-            PubSubAction::PubDisconnect.to_string()
+            "DEFERREDSTOP".to_string(),
+            PubSubAction::DeferredStop.to_string()
         );
+        assert_eq!(
+            "EXPIREPLACEHOLDER".to_string(),
+            PubSubAction::ExpirePlaceholder.to_string()
+        );
+        assert_eq!(
+            "LATENCYPROBE".to_string(),
+            PubSubAction::LatencyProbe.to_string()
+        );
+        assert_eq!(
+            "IDLEEXPIRED".to_string(),
+            PubSubAction::IdleExpired.to_string()
+        );
+        assert_eq!("ADOPT".to_string(), PubSubAction::Adopt.to_string());
+    }
+
+    #[test]
+    fn connector_message_converts_test() {
+        let actual = MonitorMessage::try_from(agemo_connector::MonitorMessage {
+            context: "topic".to_string(),
+            action: agemo_connector::PubSubAction::Unsubscribe,
+            client_id: Some("sub_1".to_string()),
+            identity: Some("alice".to_string()),
+        })
+        .unwrap();
+
+        assert_eq!("topic", actual.context);
+        assert_eq!(PubSubAction::Unsubscribe, actual.action);
+        assert_eq!(Some("sub_1".to_string()), actual.client_id);
+        assert_eq!(Some("alice".to_string()), actual.identity);
+
+        // Connector actions keep their string form.
+        assert_eq!(
+            agemo_connector::PubSubAction::SubDisconnect.to_string(),
+            PubSubAction::SubDisconnect.to_string()
+        );
+    }
+
+    #[test]
+    fn broker_connection_reports_state_test() {
+        let (sender, receiver) = mpsc::channel::<MonitorMessage>();
+        let broker_connection = BrokerConnection::new(sender, "agemo".to_string());
+
+        broker_connection.report(false);
+        broker_connection.report(true);
+
+        let actual = receiver.recv().unwrap();
+        assert_eq!("agemo", actual.context);
+        assert_eq!(PubSubAction::BrokerDisconnected, actual.action);
+        assert_eq!(
+            PubSubAction::BrokerConnected,
+            receiver.recv().unwrap().action
+        );
+
+        // Reports are dropped until the connector runs.
+        BrokerConnection::default().report(true);
     }
 }
@@ -7,26 +7,198 @@
 //! Provides a gRPC endpoint for external services to interact with to create and manage
 //! dynamically created topics.
 
-use log::info;
-use std::sync::{Arc, Mutex};
+use common::{
+    name_policy::ReservedNamePolicy, payload_compression::PayloadCompression,
+    protocol_kind::ProtocolKind, shared_state::SharedState, topic_class::TopicClass,
+};
+use log::{error, info, warn};
+use std::{
+    collections::{
+        hash_map::Entry::{Occupied, Vacant},
+        HashMap,
+    },
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
+};
 use tonic::{Request, Response, Status};
-use uuid::Uuid;
 
 use proto::pubsub::v1::pub_sub_server::PubSub;
 use proto::pubsub::v1::{
+    AddTopicPublisherRequest, AddTopicPublisherResponse, AdoptTopicRequest, AdoptTopicResponse,
+    ClaimTopicRequest, ClaimTopicResponse, CreateResponseTopicRequest, CreateResponseTopicResponse,
     CreateTopicRequest, CreateTopicResponse, DeleteTopicRequest, DeleteTopicResponse,
+    GetSchemaRequest, GetSchemaResponse, RegisterPublisherRequest, RegisterPublisherResponse,
+    ReleaseTopicRequest, ReleaseTopicResponse, RenewSubscriptionLeaseRequest,
+    RenewSubscriptionLeaseResponse, RequestTopicRequest, RequestTopicResponse,
+};
+
+#[cfg(feature = "fault-injection")]
+use crate::fault_injection::FaultInjector;
+use crate::{
+    consumer_group::ConsumerGroup,
+    deadline,
+    load_config::{SubjectPublisher, TopicTemplate},
+    providers::{Clock, SequenceEpochs, TopicIdGenerator},
+    publisher_locator::{PublisherDirectory, PublisherLocator},
+    pubsub_connector::{DeletionMessage, MonitorMessage, PubSubAction, RetainedTopics},
+    schema,
+    topic_manager::{
+        ActiveTopicsMap, IdempotencyCache, IdempotencyState, TopicEvent, TopicEventKind,
+        TopicEventSender, TopicMetadata,
+    },
+    topic_name_codec::TopicNameCodec,
+    topic_spill::TopicSpill,
+    validation,
 };
 
-use crate::topic_manager::{ActiveTopicsMap, TopicMetadata};
+/// Quality of service of the topics that are not created from a topic template.
+const DEFAULT_QOS: u32 = 1;
+
+/// Claim of a publisher's idempotency key by a topic creation. The key is forgotten if the claim
+/// is dropped without being completed, so that a retry can create the topic.
+struct IdempotencyClaim<'a> {
+    /// The cache the key is claimed in.
+    cache: &'a SharedState<IdempotencyCache>,
+    /// The publisher id and the claimed key, until the claim is completed.
+    key: Option<(String, String)>,
+}
+
+impl IdempotencyClaim<'_> {
+    /// Records the topic created for the claimed key.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The created topic.
+    /// * `now` - The time the topic was created.
+    fn complete(mut self, topic: String, now: Instant) {
+        if let Some((publisher_id, idempotency_key)) = self.key.take() {
+            self.cache
+                .lock()
+                .insert(publisher_id, idempotency_key, topic, now);
+        }
+    }
+}
+
+impl Drop for IdempotencyClaim<'_> {
+    fn drop(&mut self) {
+        if let Some((publisher_id, idempotency_key)) = self.key.take() {
+            self.cache.lock().cancel(&publisher_id, &idempotency_key);
+        }
+    }
+}
+
+/// Configuration used to track subscriber presence with leases.
+#[derive(Clone, Debug)]
+pub struct LeaseConfig {
+    /// How long a lease lasts after it is renewed.
+    pub duration: Duration,
+    /// Channel used to report subscribers that start a lease to the topic manager.
+    pub monitor_sender: mpsc::Sender<MonitorMessage>,
+}
+
+/// Renews a subscriber's lease on a topic, counting the subscriber as a new subscriber if it has
+/// no lease and is not yet known to the topic.
+///
+/// # Arguments
+///
+/// * `active_topics` - Handle that points to the shared active topics map.
+/// * `lease_config` - Configuration of subscription leases.
+/// * `topic` - The topic to renew the lease on.
+/// * `subscriber_id` - The id of the subscriber.
+/// * `now` - The current time.
+pub fn renew_lease(
+    active_topics: &SharedState<ActiveTopicsMap>,
+    lease_config: &LeaseConfig,
+    topic: String,
+    subscriber_id: String,
+    now: Instant,
+) -> Result<(), Status> {
+    if subscriber_id.is_empty() {
+        return Err(Status::invalid_argument("a subscriber id is required"));
+    }
+
+    let expires_at = now + lease_config.duration;
+
+    let is_new_subscriber = {
+        let mut curr_topics = active_topics.lock();
+
+        let Some(metadata) = curr_topics
+            .get_mut(&topic)
+            .filter(|metadata| !metadata.is_deleted())
+        else {
+            return Err(Status::not_found(format!("topic '{topic}' does not exist")));
+        };
+
+        metadata.renew_lease(subscriber_id.clone(), expires_at)
+            && !metadata.has_subscriber(&subscriber_id)
+    };
+
+    // Count the subscriber the same way as a subscription reported by the broker.
+    if is_new_subscriber {
+        info!("Started a lease for '{subscriber_id}' on topic '{topic}'.");
+
+        let subscribe_msg = MonitorMessage {
+            context: topic,
+            action: PubSubAction::Subscribe,
+            client_id: Some(subscriber_id),
+            identity: None,
+        };
+
+        lease_config
+            .monitor_sender
+            .send(subscribe_msg)
+            .map_err(|err| Status::internal(format!("unable to start lease: {err}")))?;
+    }
+
+    Ok(())
+}
 
 /// Base structure for the pub sub gRPC service.
 pub struct PubSubImpl {
     /// Handle that points to a shared active topics map.
-    pub active_topics: Arc<Mutex<ActiveTopicsMap>>,
+    pub active_topics: Arc<SharedState<ActiveTopicsMap>>,
     /// The uri of the messaging broker.
     pub uri: String,
     /// The messaging protocol used by the messaging broker.
-    pub protocol: String,
+    pub protocol: ProtocolKind,
+    /// Generator used to name dynamically created topics.
+    pub topic_id_generator: Arc<dyn TopicIdGenerator>,
+    /// Source of time used to timestamp newly created topics.
+    pub clock: Arc<dyn Clock>,
+    /// Handle that points to the topics created for publishers' idempotency keys.
+    pub idempotency_cache: Arc<SharedState<IdempotencyCache>>,
+    /// Channel that topic lifecycle events are broadcast on.
+    pub topic_events: TopicEventSender,
+    /// Policy of the topic names that generated topics must not use.
+    pub name_policy: Arc<ReservedNamePolicy>,
+    /// Channel used to ask the broker connector to restrict publishing on newly created topics to
+    /// their publisher. Publishing is not restricted if not set.
+    pub permission_sender: Option<mpsc::Sender<MonitorMessage>>,
+    /// Channel used to ask the topic manager to start pre-warmed topics as soon as they are
+    /// created, and adopted topics that already have subscribers. Topics are not pre-warmed, and
+    /// adopted topics are not started, if not set.
+    pub warm_sender: Option<mpsc::Sender<MonitorMessage>>,
+    /// Configuration of subscription leases. Leases can't be renewed if not set.
+    pub lease_config: Option<LeaseConfig>,
+    /// Generator of the epochs of topics' sequence numbers.
+    pub sequence_epochs: Arc<SequenceEpochs>,
+    /// Topics whose retained message the broker connector clears once they are deleted.
+    pub retained_topics: RetainedTopics,
+    /// Codec mapping the generated topics to the topic names used on the messaging broker.
+    pub topic_name_codec: Arc<dyn TopicNameCodec>,
+    /// Templates of the topics of known data domains, by name, that publishers can reference when
+    /// creating a topic.
+    pub topic_templates: Arc<HashMap<String, TopicTemplate>>,
+    /// Locates the publishers of the subjects that subscribers request topics for.
+    pub publisher_locator: Arc<dyn PublisherLocator>,
+    /// Directory that publishers register the subjects they serve with. Publishers can't register
+    /// if not set.
+    pub publisher_directory: Option<Arc<PublisherDirectory>>,
+    /// Spill of the cold topics that are not held in memory. Topics are not spilled if not set.
+    pub topic_spill: Option<Arc<TopicSpill>>,
+    /// Injects failures into topic creation for resilience testing.
+    #[cfg(feature = "fault-injection")]
+    pub fault_injector: Arc<FaultInjector>,
 }
 
 #[tonic::async_trait]
@@ -34,7 +206,10 @@ impl PubSub for PubSubImpl {
     /// Creates a dynamic topic based on the given request for a publisher.
     ///
     /// This function creates a dynamic topic based on a [`CreateTopicRequest`]. Returns a
-    /// [`CreateTopicResponse`].
+    /// [`CreateTopicResponse`], or an invalid argument status if the request is malformed or
+    /// references an unknown topic template. If the request has an idempotency key that the
+    /// publisher recently used to create a topic that still exists, that topic is returned
+    /// instead. No topic is created once the deadline of the request has passed.
     ///
     /// # Arguments
     ///
@@ -43,37 +218,187 @@ impl PubSub for PubSubImpl {
         &self,
         request: Request<CreateTopicRequest>,
     ) -> Result<Response<CreateTopicResponse>, Status> {
+        let request_deadline = deadline::deadline(&request);
+
         let request_inner = request.into_inner();
-        let cb = request_inner.management_callback.clone();
+        let cb = validation::validate_create_topic_request(&request_inner)?;
+        if cb != request_inner.management_callback {
+            warn!(
+                "Normalized management callback '{}' to '{cb}'.",
+                request_inner.management_callback
+            );
+        }
+
         let pub_id = request_inner.publisher_id;
+        let idempotency_key = request_inner.idempotency_key;
+        let single_consumer = request_inner.single_consumer;
+        let pre_warm = request_inner.pre_warm && self.warm_sender.is_some();
+        let notify_subscriber_joined = request_inner.notify_subscriber_joined;
+        let sequence_numbers = request_inner.sequence_numbers;
+        // Unknown topic classes are rejected by the validation.
+        let topic_class = TopicClass::from_proto(request_inner.topic_class).unwrap_or_default();
+        // Unknown payload compressions are rejected by the validation.
+        let payload_compression =
+            PayloadCompression::from_proto(request_inner.payload_compression).unwrap_or_default();
+        let template_name = request_inner.topic_template;
+        let template = self.topic_template(&template_name)?;
+        let deletion_message = DeletionMessage::from_request(
+            request_inner.deletion_message,
+            request_inner.suppress_deletion_message,
+        );
+        let tags = request_inner.tags;
         info!("Got a request to create topic from '{pub_id}'.");
 
-        let gen_topic = Uuid::new_v4().to_string();
+        #[cfg(feature = "fault-injection")]
+        if self.fault_injector.fail_create_topic() {
+            warn!("Fault injection failed the request to create topic from '{pub_id}'.");
+            return Err(Status::unavailable("injected fault"));
+        }
+
+        let now = self.clock.now();
+
+        // Requests with an idempotency key claim the key before the topic is created, so that
+        // concurrent retries with the same key share a single topic. The cache is only locked to
+        // look up and claim the key, so that bursts of topic creations aren't serialized on it.
+        let idempotency_claim = if idempotency_key.is_empty() {
+            None
+        } else {
+            let mut idempotency_cache = self.idempotency_cache.lock();
+
+            // Waiting on the lock during a burst of retries may have used up the deadline.
+            deadline::check(request_deadline)?;
+
+            let existing_topic = match idempotency_cache.get(&pub_id, &idempotency_key, now) {
+                Some(IdempotencyState::Creating) => {
+                    return Err(Status::aborted(format!(
+                        "a request with idempotency key '{idempotency_key}' is already creating \
+                         the topic, retry it"
+                    )));
+                }
+                Some(IdempotencyState::Created(topic)) => {
+                    let mut curr_topics = self.active_topics.lock();
+                    self.restore_spilled(&mut curr_topics, &topic);
+
+                    curr_topics
+                        .get(&topic)
+                        .filter(|metadata| !metadata.is_deleted())
+                        .map(|metadata| {
+                            (
+                                topic,
+                                metadata.sequence_epoch(),
+                                metadata.topic_class(),
+                                metadata.payload_compression(),
+                                metadata.topic_template().map(str::to_string),
+                            )
+                        })
+                }
+                None => None,
+            };
+
+            if let Some((topic, sequence_epoch, topic_class, payload_compression, template_name)) =
+                existing_topic
+            {
+                drop(idempotency_cache);
+                info!("Returning topic '{topic}' already created for '{pub_id}'.");
+
+                // The topic keeps the template it was created with.
+                let template = template_name.and_then(|name| self.topic_templates.get(&name));
+
+                return Ok(Response::new(self.create_topic_response(
+                    topic,
+                    sequence_epoch,
+                    topic_class,
+                    payload_compression,
+                    template,
+                )));
+            }
+
+            idempotency_cache.begin(pub_id.clone(), idempotency_key.clone(), now);
+
+            Some(IdempotencyClaim {
+                cache: &self.idempotency_cache,
+                key: Some((pub_id.clone(), idempotency_key)),
+            })
+        };
+
+        // The topic name and its metadata are prepared before the active topics are locked, so
+        // that the lock is only held to insert the topic.
+        let prefix = template.and_then(|template| template.prefix.as_deref());
+        let gen_topic = self.generate_topic(prefix.unwrap_or_default())?;
+        let sequence_epoch = sequence_numbers.then(|| self.sequence_epochs.next());
+
+        let mut metadata = TopicMetadata::new_at(pub_id.clone(), 0, Some(cb), now);
+        if single_consumer {
+            metadata.enable_consumer_group();
+        }
+        if pre_warm {
+            metadata.enable_pre_warm();
+        }
+        if notify_subscriber_joined {
+            metadata.enable_subscriber_joined_notifications();
+        }
+        if let Some(epoch) = sequence_epoch {
+            metadata.enable_sequence_numbers(epoch);
+        }
+        metadata.set_topic_class(topic_class);
+        metadata.set_payload_compression(payload_compression);
+        metadata.set_deletion_message(deletion_message);
+        metadata.set_tags(tags);
+        if let Some(template) = template {
+            metadata.set_topic_template(template_name);
+            if let Some(ttl_secs) = template.ttl_secs {
+                metadata.set_idle_timeout(Duration::from_secs(ttl_secs));
+            }
+        }
 
         // Create new topic and add to active topics list. This will start tracking
         // the generated topic until the requestor decides to delete the topic.
         {
-            let metadata = TopicMetadata::new(pub_id, 0, Some(cb));
-            self.active_topics
-                .lock()
-                .unwrap()
-                .insert(gen_topic.clone(), metadata);
+            let mut curr_topics = self.active_topics.lock();
+            deadline::check(request_deadline)?;
+
+            match curr_topics.entry(gen_topic.clone()) {
+                Vacant(entry) => {
+                    entry.insert(metadata);
+                }
+                Occupied(_) => {
+                    error!("Generated topic '{gen_topic}' already exists.");
+                    return Err(Status::internal(format!(
+                        "generated topic '{gen_topic}' already exists"
+                    )));
+                }
+            }
         }
 
-        let reply = CreateTopicResponse {
-            generated_topic: gen_topic,
-            broker_uri: self.uri.clone(),
-            broker_protocol: self.protocol.clone(),
-        };
+        if topic_class.retains_messages() {
+            self.retained_topics.insert(&gen_topic);
+        }
 
-        Ok(Response::new(reply))
+        if let Some(idempotency_claim) = idempotency_claim {
+            idempotency_claim.complete(gen_topic.clone(), now);
+        }
+
+        self.announce_topic(&gen_topic, pub_id);
+
+        if pre_warm {
+            self.warm_topic(&gen_topic);
+        }
+
+        Ok(Response::new(self.create_topic_response(
+            gen_topic,
+            sequence_epoch,
+            topic_class,
+            payload_compression,
+            template,
+        )))
     }
 
     /// Deletes the given topic for a publisher.
     ///
     /// Deletes a topic for a publisher by marking the requested topic for deletion in the shared
     /// active topics list. This is then handled by the logic in the
-    /// [`TopicManager`][crate::topic_manager::TopicManager].
+    /// [`TopicManager`][crate::topic_manager::TopicManager]. The topic is kept once the deadline
+    /// of the request has passed.
     ///
     /// # Arguments
     ///
@@ -82,11 +407,15 @@ impl PubSub for PubSubImpl {
         &self,
         request: Request<DeleteTopicRequest>,
     ) -> Result<Response<DeleteTopicResponse>, Status> {
+        let request_deadline = deadline::deadline(&request);
+
         let request_inner = request.into_inner();
         let topic = request_inner.topic;
         info!("Got a request to delete topic '{topic}.'");
 
-        let mut curr_topics = self.active_topics.lock().unwrap();
+        let mut curr_topics = self.active_topics.lock();
+        deadline::check(request_deadline)?;
+        self.restore_spilled(&mut curr_topics, &topic);
 
         if let Some(t) = curr_topics.get_mut(&topic) {
             t.delete(); // Marks topic for deletion.
@@ -94,58 +423,1935 @@ impl PubSub for PubSubImpl {
 
         Ok(Response::new(DeleteTopicResponse {}))
     }
-}
 
-#[cfg(test)]
-mod pubsub_impl_tests {
-    use super::*;
+    /// Renews a subscriber's lease on a topic.
+    ///
+    /// A subscriber without a lease that is not yet known to the topic is counted as a new
+    /// subscriber. Once a lease expires, the subscriber is treated as unsubscribed by the
+    /// [`TopicManager`][crate::topic_manager::TopicManager]. Returns a
+    /// [`RenewSubscriptionLeaseResponse`] with how long the lease lasts.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The topic and subscriber to renew the lease for.
+    async fn renew_subscription_lease(
+        &self,
+        request: Request<RenewSubscriptionLeaseRequest>,
+    ) -> Result<Response<RenewSubscriptionLeaseResponse>, Status> {
+        let Some(lease_config) = &self.lease_config else {
+            return Err(Status::failed_precondition(
+                "subscription leases are not enabled",
+            ));
+        };
 
-    #[tokio::test]
-    async fn generate_topic_test() {
-        let expected_cb = "test_cb".to_string();
-        let expected_management_protocol = "test_mgmt_protocol".to_string();
-        let expected_pub_id = "pub_test".to_string();
-        let expected_uri = "test_broker".to_string();
-        let expected_protocol = "test_protocol".to_string();
-        let expected_metadata =
-            TopicMetadata::new(expected_pub_id.clone(), 0, Some(expected_cb.clone()));
+        let request_inner = request.into_inner();
 
-        let test_topic_map = Arc::new(Mutex::new(ActiveTopicsMap::new()));
+        self.restore_spilled(&mut self.active_topics.lock(), &request_inner.topic);
 
-        let pubsub = PubSubImpl {
-            active_topics: test_topic_map.clone(),
-            uri: expected_uri.clone(),
-            protocol: expected_protocol.clone(),
+        renew_lease(
+            &self.active_topics,
+            lease_config,
+            request_inner.topic,
+            request_inner.subscriber_id,
+            self.clock.now(),
+        )?;
+
+        let reply = RenewSubscriptionLeaseResponse {
+            lease_duration_ms: u64::try_from(lease_config.duration.as_millis()).unwrap_or(u64::MAX),
         };
 
-        let request = Request::new(CreateTopicRequest {
-            publisher_id: expected_pub_id.clone(),
-            management_callback: expected_cb.clone(),
-            management_protocol: expected_management_protocol.clone(),
-        });
+        Ok(Response::new(reply))
+    }
 
-        let result = pubsub.create_topic(request).await;
-        assert!(result.is_ok());
+    /// Gets the compiled schema of the pub sub service.
+    ///
+    /// Returns a [`GetSchemaResponse`] with the serialized `FileDescriptorSet` of the service and
+    /// the kind and reference the schema is registered with in Chariott.
+    ///
+    /// # Arguments
+    ///
+    /// * `_request` - Empty request for the schema.
+    async fn get_schema(
+        &self,
+        _request: Request<GetSchemaRequest>,
+    ) -> Result<Response<GetSchemaResponse>, Status> {
+        let schema_reference =
+            schema::schema_reference().map_err(|e| Status::internal(e.to_string()))?;
 
-        let response = result.unwrap();
-        let actual = response.into_inner();
-        assert!(Uuid::parse_str(&actual.generated_topic).is_ok());
-        assert_eq!(expected_uri, actual.broker_uri);
-        assert_eq!(expected_protocol, actual.broker_protocol);
+        let reply = GetSchemaResponse {
+            schema_kind: schema::SCHEMA_KIND.to_string(),
+            schema_reference,
+            file_descriptor_set: schema::file_descriptor_set().to_vec(),
+        };
 
-        // This block controls the lifetime of the lock.
-        {
-            let lock = test_topic_map.lock().unwrap();
-            assert!(lock.contains_key(&actual.generated_topic));
+        Ok(Response::new(reply))
+    }
 
-            let val = lock.get(&actual.generated_topic);
-            assert!(val.is_some());
-            let actual_metadata = val.unwrap();
-            assert_eq!(expected_metadata.count, actual_metadata.count);
-            assert_eq!(
-                expected_metadata.management_callback,
-                actual_metadata.management_callback,
+    /// Claims a single consumer topic for a subscriber.
+    ///
+    /// The subscriber joins the topic's consumer group, and becomes the active consumer if the
+    /// topic has none. Returns a [`ClaimTopicResponse`] with whether the subscriber is the active
+    /// consumer, or a failed precondition status if the topic is not a single consumer topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The topic and subscriber claiming the topic.
+    async fn claim_topic(
+        &self,
+        request: Request<ClaimTopicRequest>,
+    ) -> Result<Response<ClaimTopicResponse>, Status> {
+        let request_inner = request.into_inner();
+        let topic = request_inner.topic;
+        let subscriber_id = request_inner.subscriber_id;
+
+        if subscriber_id.is_empty() {
+            return Err(Status::invalid_argument("a subscriber id is required"));
+        }
+
+        let mut curr_topics = self.active_topics.lock();
+        self.restore_spilled(&mut curr_topics, &topic);
+        let group = Self::consumer_group(&mut curr_topics, &topic)?;
+
+        let active = group.claim(subscriber_id.clone());
+        if active {
+            info!("'{subscriber_id}' is the active consumer of topic '{topic}'.");
+        }
+
+        let reply = ClaimTopicResponse {
+            active,
+            active_consumer: group.active_consumer().unwrap_or_default().to_string(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Releases a single consumer topic for a subscriber.
+    ///
+    /// The subscriber leaves the topic's consumer group. If it was the active consumer, the
+    /// subscriber that has been standing by the longest takes over. Returns a
+    /// [`ReleaseTopicResponse`] with the active consumer after the release.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The topic and subscriber releasing the topic.
+    async fn release_topic(
+        &self,
+        request: Request<ReleaseTopicRequest>,
+    ) -> Result<Response<ReleaseTopicResponse>, Status> {
+        let request_inner = request.into_inner();
+        let topic = request_inner.topic;
+        let subscriber_id = request_inner.subscriber_id;
+
+        let mut curr_topics = self.active_topics.lock();
+        self.restore_spilled(&mut curr_topics, &topic);
+        let group = Self::consumer_group(&mut curr_topics, &topic)?;
+
+        if group.release(&subscriber_id) {
+            info!(
+                "'{subscriber_id}' released topic '{topic}', active consumer is now {:?}.",
+                group.active_consumer()
+            );
+        }
+
+        let reply = ReleaseTopicResponse {
+            active_consumer: group.active_consumer().unwrap_or_default().to_string(),
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Creates the response topic of a requester for a request topic.
+    ///
+    /// The response topic is published to by the publisher of the request topic, and is deleted
+    /// along with the request topic. Returns a [`CreateResponseTopicResponse`] with the response
+    /// topic, which is the same topic for every call by the same requester.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request topic and the requester to create a response topic for.
+    async fn create_response_topic(
+        &self,
+        request: Request<CreateResponseTopicRequest>,
+    ) -> Result<Response<CreateResponseTopicResponse>, Status> {
+        let request_inner = request.into_inner();
+        let request_topic = request_inner.request_topic;
+        let requester_id = request_inner.requester_id;
+
+        if requester_id.is_empty() {
+            return Err(Status::invalid_argument("a requester id is required"));
+        }
+
+        let now = self.clock.now();
+
+        let (response_topic, responder_id) = {
+            let mut curr_topics = self.active_topics.lock();
+            self.restore_spilled(&mut curr_topics, &request_topic);
+
+            let Some(request_metadata) = curr_topics
+                .get(&request_topic)
+                .filter(|metadata| !metadata.is_deleted())
+            else {
+                return Err(Status::not_found(format!(
+                    "topic '{request_topic}' does not exist"
+                )));
+            };
+
+            if request_metadata.get_request_topic().is_some() {
+                return Err(Status::invalid_argument(format!(
+                    "topic '{request_topic}' is a response topic"
+                )));
+            }
+
+            if let Some(response_topic) = request_metadata.get_response_topic(&requester_id) {
+                return Ok(Response::new(CreateResponseTopicResponse {
+                    response_topic: response_topic.to_string(),
+                }));
+            }
+
+            let responder_id = request_metadata.client_id.clone();
+            let response_topic = self.generate_topic("")?;
+
+            let mut metadata = TopicMetadata::new_at(responder_id.clone(), 0, None, now);
+            metadata.set_request_topic(request_topic.clone());
+            curr_topics.insert(response_topic.clone(), metadata);
+
+            if let Some(request_metadata) = curr_topics.get_mut(&request_topic) {
+                request_metadata.add_response_topic(requester_id.clone(), response_topic.clone());
+            }
+
+            (response_topic, responder_id)
+        };
+
+        info!(
+            "Created response topic '{response_topic}' on '{request_topic}' for '{requester_id}'."
+        );
+
+        self.announce_topic(&response_topic, responder_id);
+
+        Ok(Response::new(CreateResponseTopicResponse {
+            response_topic,
+        }))
+    }
+
+    /// Gets a topic on the subject requested by a subscriber.
+    ///
+    /// Returns the existing topic of the subject if there is one. Otherwise the publisher of the
+    /// subject is located, and a topic is created on its behalf. The publisher learns the subject
+    /// with the START action sent once the subscriber subscribes. Returns a not found status if no
+    /// publisher serves the subject.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The subject and the subscriber requesting it.
+    async fn request_topic(
+        &self,
+        request: Request<RequestTopicRequest>,
+    ) -> Result<Response<RequestTopicResponse>, Status> {
+        let request_deadline = deadline::deadline(&request);
+
+        let request_inner = request.into_inner();
+        let subject = request_inner.subject;
+        let subscriber_id = request_inner.subscriber_id;
+
+        if subject.is_empty() {
+            return Err(Status::invalid_argument("a subject is required"));
+        }
+        if subscriber_id.is_empty() {
+            return Err(Status::invalid_argument("a subscriber id is required"));
+        }
+
+        info!("Got a request for subject '{subject}' from '{subscriber_id}'.");
+
+        let now = self.clock.now();
+
+        let (topic, publisher_id) = {
+            let mut curr_topics = self.active_topics.lock();
+            deadline::check(request_deadline)?;
+            if let Some(topic_spill) = &self.topic_spill {
+                topic_spill.restore_subject(&mut curr_topics, &subject);
+            }
+
+            let existing_topic = curr_topics.iter().find(|(_, metadata)| {
+                !metadata.is_deleted() && metadata.subject() == Some(subject.as_str())
+            });
+
+            if let Some((topic, metadata)) = existing_topic {
+                info!("Returning topic '{topic}' already created for subject '{subject}'.");
+
+                return Ok(Response::new(RequestTopicResponse {
+                    broker_topic: self.topic_name_codec.encode(topic),
+                    generated_topic: topic.clone(),
+                    broker_uri: self.uri.clone(),
+                    broker_protocol_kind: self.protocol.to_proto(),
+                    publisher_id: metadata.client_id.clone(),
+                }));
+            }
+
+            let Some(publisher) = self.publisher_locator.locate(&subject) else {
+                return Err(Status::not_found(format!(
+                    "no publisher serves subject '{subject}'"
+                )));
+            };
+
+            let topic = self.generate_topic("")?;
+
+            let mut metadata = TopicMetadata::new_at(
+                publisher.publisher_id.clone(),
+                0,
+                Some(publisher.management_callback),
+                now,
             );
+            metadata.set_subject(subject.clone());
+            curr_topics.insert(topic.clone(), metadata);
+
+            (topic, publisher.publisher_id)
+        };
+
+        info!("Created topic '{topic}' on subject '{subject}' for '{publisher_id}'.");
+
+        self.announce_topic(&topic, publisher_id.clone());
+
+        Ok(Response::new(RequestTopicResponse {
+            broker_topic: self.topic_name_codec.encode(&topic),
+            generated_topic: topic,
+            broker_uri: self.uri.clone(),
+            broker_protocol_kind: self.protocol.to_proto(),
+            publisher_id,
+        }))
+    }
+
+    /// Registers the subjects a publisher serves.
+    ///
+    /// Subscribers that request one of the subjects with `RequestTopic` get a topic created on
+    /// behalf of the publisher. Returns an invalid argument status if the request is malformed,
+    /// or a failed precondition status if publisher registration is not enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The publisher and the subjects it serves.
+    async fn register_publisher(
+        &self,
+        request: Request<RegisterPublisherRequest>,
+    ) -> Result<Response<RegisterPublisherResponse>, Status> {
+        let Some(publisher_directory) = &self.publisher_directory else {
+            return Err(Status::failed_precondition(
+                "publisher registration is not enabled",
+            ));
+        };
+
+        let request_inner = request.into_inner();
+        let publisher_id = request_inner.publisher_id;
+
+        if publisher_id.is_empty() {
+            return Err(Status::invalid_argument("a publisher id is required"));
+        }
+        if request_inner.subjects.iter().any(String::is_empty) {
+            return Err(Status::invalid_argument("subjects must not be empty"));
+        }
+
+        let management_callback =
+            validation::validate_management_callback(&request_inner.management_callback)
+                .map_err(|err| Status::invalid_argument(format!("'managementCallback' {err}")))?;
+
+        info!(
+            "Got a registration of {} subjects from '{publisher_id}'.",
+            request_inner.subjects.len()
+        );
+
+        publisher_directory.register(
+            SubjectPublisher {
+                publisher_id,
+                management_callback,
+            },
+            request_inner.subjects,
+        );
+
+        Ok(Response::new(RegisterPublisherResponse {}))
+    }
+
+    /// Registers another client id that publishes on a topic, such as a standby publisher.
+    ///
+    /// A topic is only deleted on an unclean disconnect once every client id publishing on it has
+    /// disconnected. Returns an [`AddTopicPublisherResponse`] with the client ids that publish on
+    /// the topic, or a not found status if the topic doesn't exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The topic and the client id of the additional publisher.
+    async fn add_topic_publisher(
+        &self,
+        request: Request<AddTopicPublisherRequest>,
+    ) -> Result<Response<AddTopicPublisherResponse>, Status> {
+        let request_inner = request.into_inner();
+        let topic = request_inner.topic;
+        let publisher_id = request_inner.publisher_id;
+
+        if publisher_id.is_empty() {
+            return Err(Status::invalid_argument("a publisher id is required"));
+        }
+
+        let (added, publisher_ids) = {
+            let mut curr_topics = self.active_topics.lock();
+            self.restore_spilled(&mut curr_topics, &topic);
+
+            let metadata = curr_topics
+                .get_mut(&topic)
+                .filter(|metadata| !metadata.is_deleted())
+                .ok_or_else(|| Status::not_found(format!("topic '{topic}' does not exist")))?;
+
+            let added = metadata.add_publisher_client(publisher_id.clone());
+            let publisher_ids: Vec<String> = metadata
+                .publisher_client_ids()
+                .into_iter()
+                .map(str::to_string)
+                .collect();
+
+            (added, publisher_ids)
+        };
+
+        if added {
+            info!("'{publisher_id}' also publishes on topic '{topic}'.");
+            self.restrict_publishing(&topic, publisher_id);
+        }
+
+        Ok(Response::new(AddTopicPublisherResponse { publisher_ids }))
+    }
+
+    /// Brings a topic that already exists on the messaging broker under management for a
+    /// publisher.
+    ///
+    /// The publisher is sent updates on the topic as if it had created it. A topic that
+    /// subscribers subscribed to before it was adopted is started right away. Adopting a topic
+    /// again with the same publisher returns the same response. Returns an invalid argument status
+    /// if the request is malformed or the topic name is reserved, or an already exists status if
+    /// the topic is managed by another publisher.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The topic, the publisher adopting it and its management callback.
+    async fn adopt_topic(
+        &self,
+        request: Request<AdoptTopicRequest>,
+    ) -> Result<Response<AdoptTopicResponse>, Status> {
+        let request_deadline = deadline::deadline(&request);
+
+        let request_inner = request.into_inner();
+        let broker_topic = request_inner.topic;
+        let publisher_id = request_inner.publisher_id;
+
+        if broker_topic.is_empty() {
+            return Err(Status::invalid_argument("a topic is required"));
+        }
+        if publisher_id.is_empty() {
+            return Err(Status::invalid_argument("a publisher id is required"));
         }
+
+        let management_callback =
+            validation::validate_management_callback(&request_inner.management_callback)
+                .map_err(|err| Status::invalid_argument(format!("'managementCallback' {err}")))?;
+
+        let Some(topic) = self.topic_name_codec.decode(&broker_topic) else {
+            return Err(Status::invalid_argument(format!(
+                "topic '{broker_topic}' can't be managed on this messaging broker"
+            )));
+        };
+
+        if let Some(rule) = self.name_policy.reserved_by(&topic) {
+            return Err(Status::invalid_argument(format!(
+                "topic '{topic}' is reserved by '{rule}'"
+            )));
+        }
+
+        info!("Got a request to adopt topic '{topic}' from '{publisher_id}'.");
+
+        let now = self.clock.now();
+
+        let (adopted, subscriber_count) = {
+            let mut curr_topics = self.active_topics.lock();
+            deadline::check(request_deadline)?;
+            self.restore_spilled(&mut curr_topics, &topic);
+
+            match curr_topics.entry(topic.clone()) {
+                Vacant(entry) => {
+                    entry.insert(TopicMetadata::new_at(
+                        publisher_id.clone(),
+                        0,
+                        Some(management_callback),
+                        now,
+                    ));
+                    (true, 0)
+                }
+                Occupied(mut entry) => {
+                    let metadata = entry.get_mut();
+
+                    if metadata.is_deleted() {
+                        return Err(Status::failed_precondition(format!(
+                            "topic '{topic}' is being deleted"
+                        )));
+                    }
+
+                    // Subscribers may have subscribed before the topic was adopted, in which
+                    // case the topic was tracked as a placeholder.
+                    if metadata.is_placeholder() {
+                        metadata.client_id = publisher_id.clone();
+                        metadata.management_callback = Some(management_callback);
+                        (true, metadata.count)
+                    } else if metadata.client_id == publisher_id {
+                        (false, metadata.count)
+                    } else {
+                        return Err(Status::already_exists(format!(
+                            "topic '{topic}' is managed by '{}'",
+                            metadata.client_id
+                        )));
+                    }
+                }
+            }
+        };
+
+        if adopted {
+            info!("'{publisher_id}' adopted topic '{topic}' with {subscriber_count} subscribers.");
+            self.announce_topic(&topic, publisher_id);
+
+            if subscriber_count > 0 {
+                self.start_adopted_topic(&topic);
+            }
+        }
+
+        Ok(Response::new(AdoptTopicResponse {
+            topic,
+            broker_uri: self.uri.clone(),
+            broker_protocol_kind: self.protocol.to_proto(),
+            broker_topic,
+            subscriber_count,
+        }))
+    }
+}
+
+impl PubSubImpl {
+    /// Looks up the topic template referenced by a request. Returns `None` if the request
+    /// references no template, or an invalid argument status if the template is unknown.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the template, empty if the request references no template.
+    fn topic_template(&self, name: &str) -> Result<Option<&TopicTemplate>, Status> {
+        if name.is_empty() {
+            return Ok(None);
+        }
+
+        self.topic_templates
+            .get(name)
+            .map(Some)
+            .ok_or_else(|| Status::invalid_argument(format!("unknown topic template '{name}'")))
+    }
+
+    /// Builds the response to a request to create a topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The generated topic.
+    /// * `sequence_epoch` - The epoch of the sequence numbers of the topic, if requested.
+    /// * `topic_class` - The class of the topic.
+    /// * `payload_compression` - The compression of the payloads of the topic.
+    /// * `template` - The template the topic was created from, if any.
+    fn create_topic_response(
+        &self,
+        topic: String,
+        sequence_epoch: Option<u64>,
+        topic_class: TopicClass,
+        payload_compression: PayloadCompression,
+        template: Option<&TopicTemplate>,
+    ) -> CreateTopicResponse {
+        CreateTopicResponse {
+            broker_topic: self.topic_name_codec.encode(&topic),
+            generated_topic: topic,
+            broker_uri: self.uri.clone(),
+            broker_protocol: self.protocol.as_str().to_string(),
+            broker_protocol_kind: self.protocol.to_proto(),
+            sequence_epoch: sequence_epoch.unwrap_or_default(),
+            topic_class: topic_class.to_proto(),
+            qos: template.map_or(DEFAULT_QOS, |template| template.qos.into()),
+            schema_kind: template
+                .and_then(|template| template.schema_kind.clone())
+                .unwrap_or_default(),
+            payload_compression: payload_compression.to_proto(),
+        }
+    }
+
+    /// Generates the name of a new topic.
+    ///
+    /// Returns an internal status if the generated name is reserved. A reserved topic could
+    /// collide with broker-internal topics, which points to a topic generator that is
+    /// misconfigured or to a reserved prefix in a topic template.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix of the name, empty if the name is not prefixed.
+    fn generate_topic(&self, prefix: &str) -> Result<String, Status> {
+        let gen_topic = format!("{prefix}{}", self.topic_id_generator.generate());
+
+        if let Some(rule) = self.name_policy.reserved_by(&gen_topic) {
+            error!("Generated topic '{gen_topic}' is reserved by '{rule}'.");
+            return Err(Status::internal(format!(
+                "generated topic '{gen_topic}' is reserved by '{rule}'"
+            )));
+        }
+
+        Ok(gen_topic)
+    }
+
+    /// Announces a newly created topic to topic watchers, and restricts publishing on the topic
+    /// to its publisher.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The newly created topic.
+    /// * `publisher_id` - The id of the publisher of the topic.
+    fn announce_topic(&self, topic: &str, publisher_id: String) {
+        // There may be no watchers, in which case the event is dropped.
+        let _ = self.topic_events.send(TopicEvent::new(
+            TopicEventKind::Create,
+            topic.to_string(),
+            publisher_id.clone(),
+        ));
+
+        self.restrict_publishing(topic, publisher_id);
+    }
+
+    /// Allows a publisher to publish on a topic, if publishing on topics is restricted to their
+    /// publishers.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic.
+    /// * `publisher_id` - The id of a publisher of the topic.
+    fn restrict_publishing(&self, topic: &str, publisher_id: String) {
+        if let Some(permission_sender) = &self.permission_sender {
+            let register_msg = MonitorMessage {
+                context: topic.to_string(),
+                action: PubSubAction::Register,
+                client_id: Some(publisher_id),
+                identity: None,
+            };
+
+            if let Err(err) = permission_sender.send(register_msg) {
+                warn!("Unable to restrict publishing on topic '{topic}': {err}");
+            }
+        }
+    }
+
+    /// Asks the topic manager to start the publisher of a pre-warmed topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The newly created topic.
+    fn warm_topic(&self, topic: &str) {
+        let Some(warm_sender) = &self.warm_sender else {
+            return;
+        };
+
+        let warm_msg = MonitorMessage {
+            context: topic.to_string(),
+            action: PubSubAction::Warm,
+            client_id: None,
+            identity: None,
+        };
+
+        if let Err(err) = warm_sender.send(warm_msg) {
+            warn!("Unable to pre-warm topic '{topic}': {err}");
+        }
+    }
+
+    /// Asks the topic manager to start the publisher of an adopted topic that already has
+    /// subscribers.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The adopted topic.
+    fn start_adopted_topic(&self, topic: &str) {
+        let Some(warm_sender) = &self.warm_sender else {
+            return;
+        };
+
+        let adopt_msg = MonitorMessage {
+            context: topic.to_string(),
+            action: PubSubAction::Adopt,
+            client_id: None,
+            identity: None,
+        };
+
+        if let Err(err) = warm_sender.send(adopt_msg) {
+            warn!("Unable to start adopted topic '{topic}': {err}");
+        }
+    }
+
+    /// Restores a topic to the active topics map if it was spilled to disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `active_topics` - The locked active topics map.
+    /// * `topic` - The name of the topic.
+    fn restore_spilled(&self, active_topics: &mut ActiveTopicsMap, topic: &str) {
+        if let Some(topic_spill) = &self.topic_spill {
+            topic_spill.restore(active_topics, topic);
+        }
+    }
+
+    /// Returns the consumer group of a topic, or a status describing why the topic has none.
+    ///
+    /// # Arguments
+    ///
+    /// * `active_topics` - The locked active topics map.
+    /// * `topic` - The name of the topic.
+    fn consumer_group<'a>(
+        active_topics: &'a mut ActiveTopicsMap,
+        topic: &str,
+    ) -> Result<&'a mut ConsumerGroup, Status> {
+        let Some(metadata) = active_topics
+            .get_mut(topic)
+            .filter(|metadata| !metadata.is_deleted())
+        else {
+            return Err(Status::not_found(format!("topic '{topic}' does not exist")));
+        };
+
+        metadata.consumer_group_mut().ok_or_else(|| {
+            Status::failed_precondition(format!("topic '{topic}' is not a single consumer topic"))
+        })
+    }
+}
+
+#[cfg(test)]
+mod pubsub_impl_tests {
+    use tokio::sync::broadcast;
+    use uuid::Uuid;
+
+    use crate::load_config::SubjectPublisher;
+    use crate::providers::{
+        ManualClock, SequentialTopicIdGenerator, SystemClock, UuidTopicIdGenerator,
+    };
+    use crate::publisher_locator::ConfiguredPublishers;
+    use crate::topic_manager::TOPIC_EVENT_CAPACITY;
+    use crate::topic_name_codec::{MqttTopicNameCodec, TopicNaming};
+
+    use super::*;
+
+    /// Returns a PubSubImpl without any topic, with the defaults of the tests. Tests override the
+    /// fields they need with struct update syntax.
+    fn test_pubsub() -> PubSubImpl {
+        PubSubImpl {
+            active_topics: Arc::new(SharedState::new(ActiveTopicsMap::new())),
+            uri: "test_broker".to_string(),
+            protocol: ProtocolKind::MqttV5,
+            topic_id_generator: Arc::new(SequentialTopicIdGenerator::new("topic-")),
+            clock: Arc::new(SystemClock),
+            idempotency_cache: Arc::new(SharedState::new(IdempotencyCache::default())),
+            topic_events: broadcast::channel(TOPIC_EVENT_CAPACITY).0,
+            name_policy: Arc::new(ReservedNamePolicy::default()),
+            permission_sender: None,
+            warm_sender: None,
+            lease_config: None,
+            sequence_epochs: Arc::new(SequenceEpochs::default()),
+            retained_topics: RetainedTopics::default(),
+            topic_name_codec: Arc::new(MqttTopicNameCodec),
+            topic_templates: Arc::new(HashMap::new()),
+            publisher_locator: Arc::new(ConfiguredPublishers::default()),
+            publisher_directory: None,
+            topic_spill: None,
+            #[cfg(feature = "fault-injection")]
+            fault_injector: Arc::new(FaultInjector::new()),
+        }
+    }
+
+    /// Returns a request to create a topic for a publisher, with the defaults of the tests.
+    fn create_request(publisher_id: String, idempotency_key: String) -> CreateTopicRequest {
+        CreateTopicRequest {
+            publisher_id,
+            management_callback: "http://0.0.0.0:50061".to_string(),
+            management_protocol: "grpc+proto".to_string(),
+            idempotency_key,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn generate_topic_test() {
+        let expected_cb = "http://0.0.0.0:50061".to_string();
+        let expected_management_protocol = "grpc+proto".to_string();
+        let expected_pub_id = "pub_test".to_string();
+        let expected_uri = "test_broker".to_string();
+        let expected_protocol = ProtocolKind::MqttV5;
+        let expected_metadata =
+            TopicMetadata::new(expected_pub_id.clone(), 0, Some(expected_cb.clone()));
+
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+
+        let pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            uri: expected_uri.clone(),
+            protocol: expected_protocol,
+            topic_id_generator: Arc::new(UuidTopicIdGenerator),
+            ..test_pubsub()
+        };
+
+        let request = Request::new(CreateTopicRequest {
+            publisher_id: expected_pub_id.clone(),
+            management_callback: expected_cb.clone(),
+            management_protocol: expected_management_protocol.clone(),
+            ..Default::default()
+        });
+
+        let result = pubsub.create_topic(request).await;
+        assert!(result.is_ok());
+
+        let response = result.unwrap();
+        let actual = response.into_inner();
+        assert!(Uuid::parse_str(&actual.generated_topic).is_ok());
+        assert_eq!(expected_uri, actual.broker_uri);
+        assert_eq!(expected_protocol.to_string(), actual.broker_protocol);
+        assert_eq!(expected_protocol.to_proto(), actual.broker_protocol_kind);
+
+        // This block controls the lifetime of the lock.
+        {
+            let lock = test_topic_map.lock();
+            assert!(lock.contains_key(&actual.generated_topic));
+
+            let val = lock.get(&actual.generated_topic);
+            assert!(val.is_some());
+            let actual_metadata = val.unwrap();
+            assert_eq!(expected_metadata.count, actual_metadata.count);
+            assert_eq!(
+                expected_metadata.management_callback,
+                actual_metadata.management_callback,
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn create_topic_with_deletion_message_test() {
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+
+        let pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            topic_id_generator: Arc::new(UuidTopicIdGenerator),
+            ..test_pubsub()
+        };
+
+        let requests = [
+            (None, false, DeletionMessage::Default),
+            (
+                Some("bye".to_string()),
+                false,
+                DeletionMessage::Custom("bye".to_string()),
+            ),
+            (Some("bye".to_string()), true, DeletionMessage::Suppressed),
+        ];
+
+        for (deletion_message, suppress_deletion_message, expected) in requests {
+            let request = Request::new(CreateTopicRequest {
+                publisher_id: "pub_1".to_string(),
+                management_callback: "http://0.0.0.0:50061".to_string(),
+                management_protocol: "grpc+proto".to_string(),
+                deletion_message,
+                suppress_deletion_message,
+                ..Default::default()
+            });
+
+            let topic = pubsub
+                .create_topic(request)
+                .await
+                .unwrap()
+                .into_inner()
+                .generated_topic;
+
+            let lock = test_topic_map.lock();
+            assert_eq!(&expected, lock.get(&topic).unwrap().deletion_message());
+        }
+    }
+
+    #[tokio::test]
+    async fn generate_topic_with_injected_providers_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+
+        let pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            clock: clock.clone(),
+            ..test_pubsub()
+        };
+
+        for expected_topic in ["topic-0", "topic-1"] {
+            let request = Request::new(CreateTopicRequest {
+                publisher_id: "pub_test".to_string(),
+                management_callback: "http://0.0.0.0:50061".to_string(),
+                management_protocol: "grpc+proto".to_string(),
+                ..Default::default()
+            });
+
+            let actual = pubsub.create_topic(request).await.unwrap().into_inner();
+            assert_eq!(expected_topic, actual.generated_topic);
+        }
+
+        // Topics are timestamped with the injected clock.
+        let lock = test_topic_map.lock();
+        assert_eq!(clock.now(), lock.get("topic-0").unwrap().get_timeout());
+        assert_eq!(clock.now(), lock.get("topic-1").unwrap().get_timeout());
+    }
+
+    #[tokio::test]
+    async fn create_topic_registers_publisher_test() {
+        let (permission_sender, permission_receiver) = mpsc::channel::<MonitorMessage>();
+
+        let pubsub = PubSubImpl {
+            permission_sender: Some(permission_sender),
+            ..test_pubsub()
+        };
+
+        let request = Request::new(CreateTopicRequest {
+            publisher_id: "pub_test".to_string(),
+            management_callback: "http://0.0.0.0:50061".to_string(),
+            management_protocol: "grpc+proto".to_string(),
+            ..Default::default()
+        });
+
+        let _ = pubsub.create_topic(request).await.unwrap();
+
+        let actual = permission_receiver.try_recv().unwrap();
+        assert_eq!("topic-0", actual.context);
+        assert_eq!(PubSubAction::Register, actual.action);
+        assert_eq!(Some("pub_test".to_string()), actual.client_id);
+    }
+
+    #[tokio::test]
+    async fn create_topic_pre_warm_test() {
+        let (warm_sender, warm_receiver) = mpsc::channel::<MonitorMessage>();
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+
+        let pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            warm_sender: Some(warm_sender),
+            ..test_pubsub()
+        };
+
+        for pre_warm in [true, false] {
+            let request = Request::new(CreateTopicRequest {
+                publisher_id: "pub_test".to_string(),
+                management_callback: "http://0.0.0.0:50061".to_string(),
+                management_protocol: "grpc+proto".to_string(),
+                pre_warm,
+                ..Default::default()
+            });
+
+            let _ = pubsub.create_topic(request).await.unwrap();
+        }
+
+        // Only the pre-warmed topic is started right away.
+        let actual = warm_receiver.try_recv().unwrap();
+        assert_eq!("topic-0", actual.context);
+        assert_eq!(PubSubAction::Warm, actual.action);
+        assert!(warm_receiver.try_recv().is_err());
+
+        let lock = test_topic_map.lock();
+        assert!(lock.get("topic-0").unwrap().is_pre_warmed());
+        assert!(!lock.get("topic-1").unwrap().is_pre_warmed());
+    }
+
+    #[tokio::test]
+    async fn create_and_delete_topic_past_deadline_test() {
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+        test_topic_map.lock().insert(
+            "topic-0".to_string(),
+            TopicMetadata::new("pub_test".to_string(), 0, None),
+        );
+
+        let pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            ..test_pubsub()
+        };
+
+        let mut create_request = Request::new(CreateTopicRequest {
+            publisher_id: "pub_test".to_string(),
+            management_callback: "http://0.0.0.0:50061".to_string(),
+            management_protocol: "grpc+proto".to_string(),
+            ..Default::default()
+        });
+        create_request
+            .extensions_mut()
+            .insert(deadline::RequestDeadline(std::time::Instant::now()));
+
+        let err = pubsub.create_topic(create_request).await.unwrap_err();
+        assert_eq!(tonic::Code::DeadlineExceeded, err.code());
+
+        let mut delete_request = Request::new(DeleteTopicRequest {
+            topic: "topic-0".to_string(),
+        });
+        delete_request
+            .extensions_mut()
+            .insert(deadline::RequestDeadline(std::time::Instant::now()));
+
+        let err = pubsub.delete_topic(delete_request).await.unwrap_err();
+        assert_eq!(tonic::Code::DeadlineExceeded, err.code());
+
+        // Neither request changed the topics.
+        let lock = test_topic_map.lock();
+        assert_eq!(1, lock.len());
+        assert!(!lock.get("topic-0").unwrap().is_deleted());
+    }
+
+    #[tokio::test]
+    async fn renew_subscription_lease_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+        let (monitor_sender, monitor_receiver) = mpsc::channel::<MonitorMessage>();
+
+        test_topic_map.lock().insert(
+            "topic-0".to_string(),
+            TopicMetadata::new_at("pub_test".to_string(), 0, None, clock.now()),
+        );
+
+        let pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            clock: clock.clone(),
+            lease_config: Some(LeaseConfig {
+                duration: Duration::from_secs(30),
+                monitor_sender,
+            }),
+            ..test_pubsub()
+        };
+
+        let renew = |topic: &str| {
+            Request::new(RenewSubscriptionLeaseRequest {
+                topic: topic.to_string(),
+                subscriber_id: "sub_test".to_string(),
+            })
+        };
+
+        // The first renewal starts the lease and counts the subscriber.
+        let actual = pubsub
+            .renew_subscription_lease(renew("topic-0"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(30000, actual.lease_duration_ms);
+
+        let actual_msg = monitor_receiver.try_recv().unwrap();
+        assert_eq!("topic-0", actual_msg.context);
+        assert_eq!(PubSubAction::Subscribe, actual_msg.action);
+        assert_eq!(Some("sub_test".to_string()), actual_msg.client_id);
+
+        // Later renewals only extend the lease.
+        assert!(pubsub
+            .renew_subscription_lease(renew("topic-0"))
+            .await
+            .is_ok());
+        assert!(monitor_receiver.try_recv().is_err());
+
+        let err = pubsub
+            .renew_subscription_lease(renew("topic-1"))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::NotFound, err.code());
+    }
+
+    #[tokio::test]
+    async fn create_response_topic_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+
+        test_topic_map.lock().insert(
+            "request".to_string(),
+            TopicMetadata::new_at(
+                "pub_test".to_string(),
+                0,
+                Some("http://0.0.0.0:50061".to_string()),
+                clock.now(),
+            ),
+        );
+
+        let pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            clock: clock.clone(),
+            ..test_pubsub()
+        };
+
+        let create = |request_topic: &str, requester_id: &str| {
+            Request::new(CreateResponseTopicRequest {
+                request_topic: request_topic.to_string(),
+                requester_id: requester_id.to_string(),
+            })
+        };
+
+        let first = pubsub
+            .create_response_topic(create("request", "req_a"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("topic-0", first.response_topic);
+
+        // The same requester gets the same response topic, other requesters get their own.
+        let again = pubsub
+            .create_response_topic(create("request", "req_a"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(first, again);
+
+        let other = pubsub
+            .create_response_topic(create("request", "req_b"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("topic-1", other.response_topic);
+
+        {
+            let lock = test_topic_map.lock();
+            let response_metadata = lock.get("topic-0").unwrap();
+            assert_eq!(Some("request"), response_metadata.get_request_topic());
+            assert_eq!("pub_test", response_metadata.client_id);
+            assert_eq!(None, response_metadata.get_management_callback());
+        }
+
+        // Response topics can't be used as request topics.
+        let err = pubsub
+            .create_response_topic(create("topic-0", "req_a"))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::InvalidArgument, err.code());
+
+        let err = pubsub
+            .create_response_topic(create("missing", "req_a"))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::NotFound, err.code());
+    }
+
+    #[tokio::test]
+    async fn claim_and_release_topic_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+
+        {
+            let mut single_consumer_metadata =
+                TopicMetadata::new_at("pub_test".to_string(), 0, None, clock.now());
+            single_consumer_metadata.enable_consumer_group();
+
+            let mut map_lock = test_topic_map.lock();
+            map_lock.insert("topic-0".to_string(), single_consumer_metadata);
+            map_lock.insert(
+                "topic-1".to_string(),
+                TopicMetadata::new_at("pub_test".to_string(), 0, None, clock.now()),
+            );
+        }
+
+        let pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            clock: clock.clone(),
+            ..test_pubsub()
+        };
+
+        let claim = |topic: &str, subscriber_id: &str| {
+            Request::new(ClaimTopicRequest {
+                topic: topic.to_string(),
+                subscriber_id: subscriber_id.to_string(),
+            })
+        };
+
+        // The first subscriber to claim the topic becomes the active consumer.
+        let actual = pubsub
+            .claim_topic(claim("topic-0", "sub_a"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(actual.active);
+        assert_eq!("sub_a", actual.active_consumer);
+
+        let actual = pubsub
+            .claim_topic(claim("topic-0", "sub_b"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(!actual.active);
+        assert_eq!("sub_a", actual.active_consumer);
+
+        // Releasing the topic fails over to the standby subscriber.
+        let actual = pubsub
+            .release_topic(Request::new(ReleaseTopicRequest {
+                topic: "topic-0".to_string(),
+                subscriber_id: "sub_a".to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("sub_b", actual.active_consumer);
+
+        let actual = pubsub
+            .claim_topic(claim("topic-0", "sub_b"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(actual.active);
+
+        let err = pubsub
+            .claim_topic(claim("topic-1", "sub_a"))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::FailedPrecondition, err.code());
+
+        let err = pubsub
+            .claim_topic(claim("topic-2", "sub_a"))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::NotFound, err.code());
+
+        let err = pubsub.claim_topic(claim("topic-0", "")).await.unwrap_err();
+        assert_eq!(tonic::Code::InvalidArgument, err.code());
+    }
+
+    #[tokio::test]
+    async fn create_topic_with_idempotency_key_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+
+        let pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            clock: clock.clone(),
+            ..test_pubsub()
+        };
+
+        let create = |publisher_id: &str, idempotency_key: &str| {
+            Request::new(CreateTopicRequest {
+                publisher_id: publisher_id.to_string(),
+                management_callback: "http://0.0.0.0:50061".to_string(),
+                management_protocol: "grpc+proto".to_string(),
+                idempotency_key: idempotency_key.to_string(),
+                ..Default::default()
+            })
+        };
+
+        let first = pubsub
+            .create_topic(create("pub_test", "key_1"))
+            .await
+            .unwrap();
+        assert_eq!("topic-0", first.into_inner().generated_topic);
+
+        // A retry with the same key returns the same topic.
+        let retry = pubsub
+            .create_topic(create("pub_test", "key_1"))
+            .await
+            .unwrap();
+        assert_eq!("topic-0", retry.into_inner().generated_topic);
+        assert_eq!(1, test_topic_map.lock().len());
+
+        // A different publisher or key creates a new topic.
+        let other = pubsub
+            .create_topic(create("pub_other", "key_1"))
+            .await
+            .unwrap();
+        assert_eq!("topic-1", other.into_inner().generated_topic);
+
+        // A deleted topic is not returned again.
+        test_topic_map.lock().get_mut("topic-0").unwrap().delete();
+        let after_delete = pubsub
+            .create_topic(create("pub_test", "key_1"))
+            .await
+            .unwrap();
+        assert_eq!("topic-2", after_delete.into_inner().generated_topic);
+    }
+
+    #[tokio::test]
+    async fn create_topic_with_sequence_numbers_test() {
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+
+        let pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            ..test_pubsub()
+        };
+
+        let create = |sequence_numbers: bool| {
+            Request::new(CreateTopicRequest {
+                publisher_id: "pub_test".to_string(),
+                management_callback: "http://0.0.0.0:50061".to_string(),
+                management_protocol: "grpc+proto".to_string(),
+                sequence_numbers,
+                idempotency_key: "key_1".to_string(),
+                ..Default::default()
+            })
+        };
+
+        let first = pubsub
+            .create_topic(create(true))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(first.sequence_epoch > 0);
+        assert_eq!(
+            Some(first.sequence_epoch),
+            test_topic_map
+                .lock()
+                .get("topic-0")
+                .unwrap()
+                .sequence_epoch()
+        );
+
+        // A retry with the same key gets the same epoch.
+        let retry = pubsub
+            .create_topic(create(true))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("topic-0", retry.generated_topic);
+        assert_eq!(first.sequence_epoch, retry.sequence_epoch);
+
+        // A re-created topic gets a greater epoch.
+        test_topic_map.lock().get_mut("topic-0").unwrap().delete();
+        let recreated = pubsub
+            .create_topic(create(true))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("topic-1", recreated.generated_topic);
+        assert!(recreated.sequence_epoch > first.sequence_epoch);
+
+        // Topics without sequence numbers have no epoch.
+        test_topic_map.lock().get_mut("topic-1").unwrap().delete();
+        let unsequenced = pubsub
+            .create_topic(create(false))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(0, unsequenced.sequence_epoch);
+        assert_eq!(
+            None,
+            test_topic_map
+                .lock()
+                .get("topic-2")
+                .unwrap()
+                .sequence_epoch()
+        );
+    }
+
+    #[tokio::test]
+    async fn create_retained_config_topic_test() {
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+        let retained_topics = RetainedTopics::default();
+
+        let pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            retained_topics: retained_topics.clone(),
+            ..test_pubsub()
+        };
+
+        for topic_class in [TopicClass::ConfigRetained, TopicClass::Standard] {
+            let request = Request::new(CreateTopicRequest {
+                publisher_id: "pub_test".to_string(),
+                management_callback: "http://0.0.0.0:50061".to_string(),
+                management_protocol: "grpc+proto".to_string(),
+                topic_class: topic_class.to_proto(),
+                ..Default::default()
+            });
+
+            let response = pubsub.create_topic(request).await.unwrap().into_inner();
+            assert_eq!(topic_class.to_proto(), response.topic_class);
+        }
+
+        let lock = test_topic_map.lock();
+        assert_eq!(
+            TopicClass::ConfigRetained,
+            lock.get("topic-0").unwrap().topic_class()
+        );
+        assert_eq!(
+            TopicClass::Standard,
+            lock.get("topic-1").unwrap().topic_class()
+        );
+
+        // Only the retained message of the config topic is cleared on deletion.
+        assert!(retained_topics.take("topic-0"));
+        assert!(!retained_topics.take("topic-1"));
+    }
+
+    #[tokio::test]
+    async fn create_topic_returns_broker_topic_test() {
+        let pubsub = PubSubImpl {
+            topic_id_generator: Arc::new(SequentialTopicIdGenerator::new("vehicle/speed-")),
+            topic_name_codec: TopicNaming::Kafka.codec(),
+            ..test_pubsub()
+        };
+
+        let request = Request::new(CreateTopicRequest {
+            publisher_id: "pub_test".to_string(),
+            management_callback: "http://0.0.0.0:50061".to_string(),
+            management_protocol: "grpc+proto".to_string(),
+            ..Default::default()
+        });
+
+        let response = pubsub.create_topic(request).await.unwrap().into_inner();
+
+        // The generated topic is the same whichever broker is used.
+        assert_eq!("vehicle/speed-0", response.generated_topic);
+        assert_eq!("vehicle.speed-0", response.broker_topic);
+    }
+
+    #[tokio::test]
+    async fn get_schema_test() {
+        let pubsub = PubSubImpl {
+            topic_id_generator: Arc::new(UuidTopicIdGenerator),
+            ..test_pubsub()
+        };
+
+        let actual = pubsub
+            .get_schema(Request::new(GetSchemaRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(schema::SCHEMA_KIND, actual.schema_kind);
+        assert_eq!("pubsub.PubSub/GetSchema", actual.schema_reference);
+        assert_eq!(schema::file_descriptor_set(), actual.file_descriptor_set);
+    }
+
+    #[tokio::test]
+    async fn create_topic_rejects_invalid_request_test() {
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+
+        let pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            topic_id_generator: Arc::new(UuidTopicIdGenerator),
+            ..test_pubsub()
+        };
+
+        let request = Request::new(CreateTopicRequest {
+            publisher_id: "pub_test".to_string(),
+            management_callback: "not a uri".to_string(),
+            management_protocol: "grpc+proto".to_string(),
+            ..Default::default()
+        });
+
+        let status = pubsub.create_topic(request).await.unwrap_err();
+        assert_eq!(tonic::Code::InvalidArgument, status.code());
+
+        // The topic map is not touched by an invalid request.
+        assert!(test_topic_map.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn create_topic_normalizes_management_callback_test() {
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+
+        let pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            topic_id_generator: Arc::new(UuidTopicIdGenerator),
+            ..test_pubsub()
+        };
+
+        let request = Request::new(CreateTopicRequest {
+            publisher_id: "pub_test".to_string(),
+            management_callback: " 0.0.0.0:50061".to_string(),
+            management_protocol: "grpc+proto".to_string(),
+            ..Default::default()
+        });
+
+        let topic = pubsub
+            .create_topic(request)
+            .await
+            .unwrap()
+            .into_inner()
+            .generated_topic;
+
+        let actual = test_topic_map
+            .lock()
+            .get(&topic)
+            .unwrap()
+            .get_management_callback();
+        assert_eq!(Some("http://0.0.0.0:50061".to_string()), actual);
+    }
+
+    #[tokio::test]
+    async fn create_topic_rejects_reserved_topic_test() {
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+        let name_policy = ReservedNamePolicy::new(vec!["$SYS/#".to_string()], &[]).unwrap();
+
+        let pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            topic_id_generator: Arc::new(SequentialTopicIdGenerator::new("$SYS/topic-")),
+            name_policy: Arc::new(name_policy),
+            ..test_pubsub()
+        };
+
+        let request = Request::new(CreateTopicRequest {
+            publisher_id: "pub_test".to_string(),
+            management_callback: "http://0.0.0.0:50061".to_string(),
+            management_protocol: "grpc+proto".to_string(),
+            ..Default::default()
+        });
+
+        let status = pubsub.create_topic(request).await.unwrap_err();
+        assert_eq!(tonic::Code::Internal, status.code());
+        assert!(test_topic_map.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn create_topic_from_template_test() {
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+        let template = TopicTemplate {
+            prefix: Some("vehicle/signals/".to_string()),
+            qos: 0,
+            ttl_secs: Some(120),
+            schema_kind: Some("json".to_string()),
+        };
+
+        let pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            topic_templates: Arc::new(HashMap::from([("vehicle-signals".to_string(), template)])),
+            ..test_pubsub()
+        };
+
+        let create_request = |topic_template: &str, idempotency_key: &str| {
+            Request::new(CreateTopicRequest {
+                publisher_id: "pub_test".to_string(),
+                management_callback: "http://0.0.0.0:50061".to_string(),
+                management_protocol: "grpc+proto".to_string(),
+                topic_template: topic_template.to_string(),
+                idempotency_key: idempotency_key.to_string(),
+                ..Default::default()
+            })
+        };
+
+        let response = pubsub
+            .create_topic(create_request("vehicle-signals", "key"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("vehicle/signals/topic-0", response.generated_topic);
+        assert_eq!(0, response.qos);
+        assert_eq!("json", response.schema_kind);
+
+        {
+            let lock = test_topic_map.lock();
+            let metadata = lock.get("vehicle/signals/topic-0").unwrap();
+            assert_eq!(Some("vehicle-signals"), metadata.topic_template());
+            assert_eq!(Duration::from_secs(120), metadata.idle_timeout());
+        }
+
+        // A retry gets the same topic, with the settings of its template.
+        let response = pubsub
+            .create_topic(create_request("", "key"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("vehicle/signals/topic-0", response.generated_topic);
+        assert_eq!(0, response.qos);
+        assert_eq!("json", response.schema_kind);
+
+        // Topics created without a template keep the defaults.
+        let response = pubsub
+            .create_topic(create_request("", ""))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("topic-1", response.generated_topic);
+        assert_eq!(DEFAULT_QOS, response.qos);
+        assert!(response.schema_kind.is_empty());
+
+        let status = pubsub
+            .create_topic(create_request("cabin-climate", ""))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::InvalidArgument, status.code());
+        assert!(status.message().contains("'cabin-climate'"));
+        assert_eq!(2, test_topic_map.lock().len());
+    }
+
+    #[tokio::test]
+    async fn request_topic_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+        let publisher = SubjectPublisher {
+            publisher_id: "hvac".to_string(),
+            management_callback: "http://0.0.0.0:50061".to_string(),
+        };
+
+        let pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            clock: clock.clone(),
+            publisher_locator: Arc::new(ConfiguredPublishers::new(HashMap::from([(
+                "cabin/temperature".to_string(),
+                publisher,
+            )]))),
+            ..test_pubsub()
+        };
+
+        let request = |subject: &str, subscriber_id: &str| {
+            Request::new(RequestTopicRequest {
+                subject: subject.to_string(),
+                subscriber_id: subscriber_id.to_string(),
+            })
+        };
+
+        // The first request creates a topic on behalf of the publisher of the subject.
+        let response = pubsub
+            .request_topic(request("cabin/temperature", "sub_a"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("topic-0", response.generated_topic);
+        assert_eq!("topic-0", response.broker_topic);
+        assert_eq!("hvac", response.publisher_id);
+
+        {
+            let lock = test_topic_map.lock();
+            let metadata = lock.get("topic-0").unwrap();
+            assert_eq!("hvac", metadata.client_id);
+            assert_eq!(
+                Some("http://0.0.0.0:50061".to_string()),
+                metadata.get_management_callback()
+            );
+            assert_eq!(Some("cabin/temperature"), metadata.subject());
+        }
+
+        // Later requests for the subject share the topic.
+        let response = pubsub
+            .request_topic(request("cabin/temperature", "sub_b"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("topic-0", response.generated_topic);
+        assert_eq!(1, test_topic_map.lock().len());
+
+        // A deleted topic is replaced by a new one.
+        test_topic_map.lock().get_mut("topic-0").unwrap().delete();
+        let response = pubsub
+            .request_topic(request("cabin/temperature", "sub_a"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("topic-1", response.generated_topic);
+
+        let err = pubsub
+            .request_topic(request("cabin/humidity", "sub_a"))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::NotFound, err.code());
+
+        let err = pubsub
+            .request_topic(request("cabin/temperature", ""))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::InvalidArgument, err.code());
+    }
+
+    #[tokio::test]
+    async fn add_topic_publisher_test() {
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+        let (permission_sender, permission_receiver) = mpsc::channel::<MonitorMessage>();
+        test_topic_map.lock().insert(
+            "topic-0".to_string(),
+            TopicMetadata::new("active".to_string(), 0, None),
+        );
+
+        let pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            clock: Arc::new(ManualClock::new()),
+            permission_sender: Some(permission_sender),
+            ..test_pubsub()
+        };
+
+        let request = |topic: &str, publisher_id: &str| {
+            Request::new(AddTopicPublisherRequest {
+                topic: topic.to_string(),
+                publisher_id: publisher_id.to_string(),
+            })
+        };
+
+        let response = pubsub
+            .add_topic_publisher(request("topic-0", "standby"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(vec!["active", "standby"], response.publisher_ids);
+
+        // The standby publisher is allowed to publish on the topic.
+        let register = permission_receiver.try_recv().unwrap();
+        assert_eq!(PubSubAction::Register, register.action);
+        assert_eq!("topic-0", register.context);
+        assert_eq!(Some("standby".to_string()), register.client_id);
+
+        // Adding a publisher again changes nothing.
+        let response = pubsub
+            .add_topic_publisher(request("topic-0", "active"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(vec!["active", "standby"], response.publisher_ids);
+        assert!(permission_receiver.try_recv().is_err());
+
+        let err = pubsub
+            .add_topic_publisher(request("topic-1", "standby"))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::NotFound, err.code());
+
+        let err = pubsub
+            .add_topic_publisher(request("topic-0", ""))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::InvalidArgument, err.code());
+    }
+
+    #[tokio::test]
+    async fn adopt_topic_test() {
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+        let (warm_sender, warm_receiver) = mpsc::channel::<MonitorMessage>();
+
+        // A subscriber subscribed to the topic before it was adopted.
+        let mut placeholder = TopicMetadata::new(String::new(), 1, None);
+        placeholder.add_subscriber("dashboard".to_string());
+        test_topic_map
+            .lock()
+            .insert("vehicle/speed".to_string(), placeholder);
+
+        let pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            clock: Arc::new(ManualClock::new()),
+            warm_sender: Some(warm_sender),
+            ..test_pubsub()
+        };
+
+        let request = |topic: &str, publisher_id: &str| {
+            Request::new(AdoptTopicRequest {
+                topic: topic.to_string(),
+                publisher_id: publisher_id.to_string(),
+                management_callback: "http://0.0.0.0:50061".to_string(),
+            })
+        };
+
+        let response = pubsub
+            .adopt_topic(request("vehicle/speed", "legacy_pub"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("vehicle/speed", response.topic);
+        assert_eq!("vehicle/speed", response.broker_topic);
+        assert_eq!("test_broker", response.broker_uri);
+        assert_eq!(1, response.subscriber_count);
+
+        {
+            let curr_topics = test_topic_map.lock();
+            let metadata = curr_topics.get("vehicle/speed").unwrap();
+            assert_eq!("legacy_pub", metadata.client_id);
+            assert_eq!(
+                Some("http://0.0.0.0:50061".to_string()),
+                metadata.get_management_callback()
+            );
+            assert!(metadata.has_subscriber("dashboard"));
+        }
+
+        // The waiting subscriber gets the publisher started.
+        let adopt = warm_receiver.try_recv().unwrap();
+        assert_eq!(PubSubAction::Adopt, adopt.action);
+        assert_eq!("vehicle/speed", adopt.context);
+
+        // Adopting the topic again with the same publisher changes nothing.
+        let response = pubsub
+            .adopt_topic(request("vehicle/speed", "legacy_pub"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(1, response.subscriber_count);
+        assert!(warm_receiver.try_recv().is_err());
+
+        // A topic without subscribers is tracked without starting its publisher.
+        let response = pubsub
+            .adopt_topic(request("vehicle/rpm", "legacy_pub"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(0, response.subscriber_count);
+        assert!(warm_receiver.try_recv().is_err());
+        assert!(test_topic_map.lock().contains_key("vehicle/rpm"));
+
+        let err = pubsub
+            .adopt_topic(request("vehicle/speed", "other_pub"))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::AlreadyExists, err.code());
+
+        let err = pubsub
+            .adopt_topic(request("$SYS/broker/uptime", "legacy_pub"))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::InvalidArgument, err.code());
+
+        let err = pubsub
+            .adopt_topic(request("vehicle/speed", ""))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::InvalidArgument, err.code());
+    }
+
+    #[tokio::test]
+    async fn register_publisher_test() {
+        let test_topic_map = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+        let publisher_directory = Arc::new(PublisherDirectory::new(Arc::new(
+            ConfiguredPublishers::default(),
+        )));
+
+        let mut pubsub = PubSubImpl {
+            active_topics: test_topic_map.clone(),
+            clock: Arc::new(ManualClock::new()),
+            publisher_locator: publisher_directory.clone(),
+            ..test_pubsub()
+        };
+
+        let register = |management_callback: &str| {
+            Request::new(RegisterPublisherRequest {
+                publisher_id: "hvac".to_string(),
+                management_callback: management_callback.to_string(),
+                subjects: vec!["cabin/temperature".to_string()],
+            })
+        };
+
+        let err = pubsub
+            .register_publisher(register("http://0.0.0.0:50061"))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::FailedPrecondition, err.code());
+
+        pubsub.publisher_directory = Some(publisher_directory);
+
+        let err = pubsub
+            .register_publisher(register("ftp://0.0.0.0:50061"))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::InvalidArgument, err.code());
+
+        pubsub
+            .register_publisher(register("http://0.0.0.0:50061"))
+            .await
+            .unwrap();
+
+        // Subscribers requesting a registered subject get a topic of the publisher.
+        let response = pubsub
+            .request_topic(Request::new(RequestTopicRequest {
+                subject: "cabin/temperature".to_string(),
+                subscriber_id: "sub_a".to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("hvac", response.publisher_id);
+
+        let lock = test_topic_map.lock();
+        let metadata = lock.get(&response.generated_topic).unwrap();
+        assert_eq!(
+            Some("http://0.0.0.0:50061".to_string()),
+            metadata.get_management_callback()
+        );
+    }
+
+    #[tokio::test]
+    async fn create_topic_rejects_existing_topic_test() {
+        let pubsub = test_pubsub();
+        let existing = TopicMetadata::new("pub_a".to_string(), 1, None);
+        pubsub
+            .active_topics
+            .lock()
+            .insert("topic-0".to_string(), existing);
+
+        let request = create_request("pub_b".to_string(), String::new());
+        let err = pubsub
+            .create_topic(Request::new(request))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::Internal, err.code());
+
+        // The existing topic is left untouched.
+        let lock = pubsub.active_topics.lock();
+        assert_eq!("pub_a", lock.get("topic-0").unwrap().client_id);
+    }
+
+    #[tokio::test]
+    async fn create_topic_with_idempotency_key_in_progress_test() {
+        let pubsub = test_pubsub();
+        let now = pubsub.clock.now();
+        pubsub
+            .idempotency_cache
+            .lock()
+            .begin("pub_test".to_string(), "key".to_string(), now);
+
+        // A retry while the first request is creating the topic is told to try again.
+        let request = create_request("pub_test".to_string(), "key".to_string());
+        let err = pubsub
+            .create_topic(Request::new(request))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::Aborted, err.code());
+        assert!(pubsub.active_topics.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn create_topic_failure_releases_idempotency_key_test() {
+        let pubsub = test_pubsub();
+        pubsub.active_topics.lock().insert(
+            "topic-0".to_string(),
+            TopicMetadata::new("pub_a".to_string(), 1, None),
+        );
+
+        let request = create_request("pub_test".to_string(), "key".to_string());
+        let err = pubsub
+            .create_topic(Request::new(request.clone()))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::Internal, err.code());
+
+        // The key is released, so a retry creates the topic.
+        let now = pubsub.clock.now();
+        assert_eq!(
+            None,
+            pubsub.idempotency_cache.lock().get("pub_test", "key", now)
+        );
+        let response = pubsub
+            .create_topic(Request::new(request))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("topic-1", response.generated_topic);
+    }
+
+    #[tokio::test]
+    async fn create_topic_records_payload_compression_test() {
+        let pubsub = test_pubsub();
+        let request = CreateTopicRequest {
+            payload_compression: PayloadCompression::Gzip.to_proto(),
+            ..create_request("pub_test".to_string(), "key".to_string())
+        };
+
+        let response = pubsub
+            .create_topic(Request::new(request.clone()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            PayloadCompression::Gzip.to_proto(),
+            response.payload_compression
+        );
+        assert_eq!(
+            PayloadCompression::Gzip,
+            pubsub.active_topics.lock()["topic-0"].payload_compression()
+        );
+
+        // A retry returns the compression the topic was created with.
+        let retry = CreateTopicRequest {
+            payload_compression: PayloadCompression::None.to_proto(),
+            ..request
+        };
+        let response = pubsub
+            .create_topic(Request::new(retry))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("topic-0", response.generated_topic);
+        assert_eq!(
+            PayloadCompression::Gzip.to_proto(),
+            response.payload_compression
+        );
     }
 }
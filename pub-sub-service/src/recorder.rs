@@ -0,0 +1,404 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Records messages published on managed topics to an [MCAP](https://mcap.dev/) file.
+//!
+//! The recorder connects to the messaging broker with its own client, subscribes to the active
+//! topics selected in the [`RecorderSettings`] and writes every message it receives to the MCAP
+//! channel of its topic. Each channel carries the topic's publisher and management callback as
+//! metadata, along with the configured JSON Schema of the topic's payloads. Subscriptions made by
+//! the recorder are not counted as subscribers of a topic.
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::File,
+    io::{BufWriter, Seek, Write},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use common::shared_state::SharedState;
+use log::{info, warn};
+use paho_mqtt::{self as mqtt, MQTT_VERSION_5};
+
+use crate::{
+    error::AgemoError,
+    load_config::{RecordedTopicSettings, RecorderSettings},
+    pubsub_connector::ConnectorConfig,
+    topic_manager::{ActiveTopicsMap, TopicMetadata},
+};
+
+/// Suffix added to the connector's client id to create the recorder's client id.
+const RECORDER_CLIENT_ID_SUFFIX: &str = "recorder";
+/// Encoding of the recorded messages.
+const MESSAGE_ENCODING: &str = "json";
+/// Encoding of the schemas stored in the recording.
+const SCHEMA_ENCODING: &str = "jsonschema";
+/// Name of the schema used for topics without a configured schema.
+const DEFAULT_SCHEMA_NAME: &str = "agemo/payload";
+/// JSON Schema used for topics without a configured schema, which accepts any JSON value.
+const DEFAULT_SCHEMA: &str = "{}";
+/// Number of received messages buffered before the recorder processes them.
+const STREAM_BUFFER_SIZE: usize = 1024;
+
+/// Writes messages received on managed topics to an MCAP recording.
+pub struct McapRecorder<W: Write + Seek> {
+    writer: mcap::Writer<'static, W>,
+    selections: Vec<RecordedTopicSettings>,
+    channels: HashMap<String, u16>,
+    sequence: u32,
+}
+
+impl<W: Write + Seek> McapRecorder<W> {
+    /// Creates a new McapRecorder that writes to the given output.
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - Where the recording is written to.
+    /// * `selections` - The topics to record. Every topic is recorded if empty.
+    pub fn new(output: W, selections: Vec<RecordedTopicSettings>) -> Result<Self, AgemoError> {
+        let writer = mcap::Writer::new(output).map_err(|err| {
+            AgemoError::Recording(format!("unable to start the recording: {err}"))
+        })?;
+
+        Ok(McapRecorder {
+            writer,
+            selections,
+            channels: HashMap::new(),
+            sequence: 0,
+        })
+    }
+
+    /// Returns the selection that matches a topic by its name or publisher, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The generated topic name.
+    /// * `metadata` - The topic's metadata.
+    fn matching_selection(
+        &self,
+        topic: &str,
+        metadata: &TopicMetadata,
+    ) -> Option<&RecordedTopicSettings> {
+        self.selections.iter().find(|selection| {
+            selection.selector == topic || selection.selector == metadata.client_id
+        })
+    }
+
+    /// Returns whether a topic should be recorded.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The generated topic name.
+    /// * `metadata` - The topic's metadata.
+    pub fn is_selected(&self, topic: &str, metadata: &TopicMetadata) -> bool {
+        self.selections.is_empty() || self.matching_selection(topic, metadata).is_some()
+    }
+
+    /// Returns whether a topic has a channel in the recording.
+    pub fn is_recording(&self, topic: &str) -> bool {
+        self.channels.contains_key(topic)
+    }
+
+    /// Adds a channel for a topic to the recording.
+    ///
+    /// Returns false without changing the recording if the topic is not selected or already has
+    /// a channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The generated topic name.
+    /// * `metadata` - The topic's metadata, stored with the channel.
+    pub fn add_topic(&mut self, topic: &str, metadata: &TopicMetadata) -> Result<bool, AgemoError> {
+        if self.is_recording(topic) || !self.is_selected(topic, metadata) {
+            return Ok(false);
+        }
+
+        let schema = match self
+            .matching_selection(topic, metadata)
+            .and_then(|selection| selection.schema.as_ref().map(|s| (selection, s)))
+        {
+            Some((selection, schema)) => mcap::Schema {
+                name: selection.selector.clone(),
+                encoding: SCHEMA_ENCODING.to_string(),
+                data: Cow::Owned(schema.as_bytes().to_vec()),
+            },
+            None => mcap::Schema {
+                name: DEFAULT_SCHEMA_NAME.to_string(),
+                encoding: SCHEMA_ENCODING.to_string(),
+                data: Cow::Owned(DEFAULT_SCHEMA.as_bytes().to_vec()),
+            },
+        };
+
+        let mut channel_metadata = BTreeMap::new();
+        channel_metadata.insert("publisher_id".to_string(), metadata.client_id.clone());
+        if let Some(callback) = metadata.get_management_callback() {
+            channel_metadata.insert("management_callback".to_string(), callback);
+        }
+
+        let channel = mcap::Channel {
+            topic: topic.to_string(),
+            schema: Some(Arc::new(schema)),
+            message_encoding: MESSAGE_ENCODING.to_string(),
+            metadata: channel_metadata,
+        };
+
+        let channel_id = self.writer.add_channel(&channel).map_err(|err| {
+            AgemoError::Recording(format!("unable to add a channel for '{topic}': {err}"))
+        })?;
+        self.channels.insert(topic.to_string(), channel_id);
+
+        Ok(true)
+    }
+
+    /// Writes a message to the channel of its topic. Messages on unknown topics are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic the message was received on.
+    /// * `payload` - The message payload.
+    /// * `log_time` - Nanoseconds since the Unix epoch when the message was received.
+    pub fn record(&mut self, topic: &str, payload: &[u8], log_time: u64) -> Result<(), AgemoError> {
+        let Some(channel_id) = self.channels.get(topic).copied() else {
+            return Ok(());
+        };
+
+        let header = mcap::records::MessageHeader {
+            channel_id,
+            sequence: self.sequence,
+            log_time,
+            publish_time: log_time,
+        };
+        self.sequence = self.sequence.wrapping_add(1);
+
+        self.writer
+            .write_to_known_channel(&header, payload)
+            .map_err(|err| {
+                AgemoError::Recording(format!("unable to record a message on '{topic}': {err}"))
+            })
+    }
+
+    /// Writes the summary of the recording. No messages can be recorded afterwards.
+    pub fn finish(&mut self) -> Result<(), AgemoError> {
+        self.writer
+            .finish()
+            .map_err(|err| AgemoError::Recording(format!("unable to finish the recording: {err}")))
+    }
+}
+
+/// Returns the nanoseconds since the Unix epoch.
+fn now_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+/// Returns the id of the client the recorder uses to connect to the messaging broker.
+///
+/// # Arguments
+///
+/// * `connector_client_id` - The client id of the service's broker connector.
+pub fn recorder_client_id(connector_client_id: &str) -> String {
+    format!("{connector_client_id}_{RECORDER_CLIENT_ID_SUFFIX}")
+}
+
+/// Records the selected managed topics until the service is stopped with Ctrl+C, then finishes
+/// the recording. Returns an error if the recording stops before the service is stopped.
+///
+/// # Arguments
+///
+/// * `settings` - Where to record to and which topics to record.
+/// * `connector_config` - Configuration used to connect to the messaging broker.
+/// * `active_topics` - The topics currently managed by the service.
+pub async fn run_recorder(
+    settings: RecorderSettings,
+    connector_config: ConnectorConfig,
+    active_topics: Arc<SharedState<ActiveTopicsMap>>,
+) -> Result<(), AgemoError> {
+    let file = File::create(&settings.output_file).map_err(|err| {
+        AgemoError::InvalidConfiguration(format!(
+            "unable to create recording '{}': {err}",
+            settings.output_file
+        ))
+    })?;
+    let mut recorder = McapRecorder::new(BufWriter::new(file), settings.topics.clone())?;
+
+    let create_opts = mqtt::CreateOptionsBuilder::new()
+        .server_uri(connector_config.uri.clone())
+        .client_id(recorder_client_id(&connector_config.client_id))
+        .finalize();
+    let mut client = mqtt::AsyncClient::new(create_opts).map_err(|err| {
+        AgemoError::InvalidConfiguration(format!("unable to create the recorder client: {err}"))
+    })?;
+    let stream = client.get_stream(STREAM_BUFFER_SIZE);
+
+    let mut conn_builder = mqtt::ConnectOptionsBuilder::with_mqtt_version(MQTT_VERSION_5);
+    conn_builder.clean_start(true);
+    if let Some(credentials) = &connector_config.credentials {
+        if let Some(username) = &credentials.username {
+            conn_builder.user_name(username.as_str());
+        }
+
+        if let Some(secret) = credentials.secret() {
+            conn_builder.password(secret);
+        }
+    }
+
+    client
+        .connect(conn_builder.finalize())
+        .await
+        .map_err(|err| {
+            AgemoError::Connection(format!(
+                "unable to connect the recorder to '{}': {err}",
+                connector_config.uri
+            ))
+        })?;
+
+    info!("Recording managed topics to '{}'.", settings.output_file);
+
+    let mut refresh = tokio::time::interval(Duration::from_secs(settings.refresh_interval_secs));
+    let mut subscribed: HashSet<String> = HashSet::new();
+
+    let result = loop {
+        tokio::select! {
+            _ = refresh.tick() => {
+                let topics: Vec<(String, TopicMetadata)> = active_topics
+                    .lock()
+                    .iter()
+                    .filter(|(topic, metadata)| !metadata.is_deleted() || subscribed.contains(*topic))
+                    .map(|(topic, metadata)| (topic.clone(), metadata.clone()))
+                    .collect();
+
+                for (topic, metadata) in &topics {
+                    if recorder.add_topic(topic, metadata)? {
+                        match client.subscribe(topic.as_str(), mqtt::QOS_1).await {
+                            Ok(_) => {
+                                info!("Recording topic '{topic}'.");
+                                subscribed.insert(topic.clone());
+                            }
+                            Err(err) => warn!("Unable to record topic '{topic}': {err}"),
+                        }
+                    }
+                }
+
+                // Stop listening on topics that are no longer managed by the service.
+                let removed: Vec<String> = subscribed
+                    .iter()
+                    .filter(|topic| !topics.iter().any(|(active, _)| active == *topic))
+                    .cloned()
+                    .collect();
+
+                for topic in removed {
+                    let _ = client.unsubscribe(topic.as_str()).await;
+                    subscribed.remove(&topic);
+                }
+            }
+            msg = stream.recv() => {
+                match msg {
+                    Ok(Some(msg)) => recorder.record(msg.topic(), msg.payload(), now_nanos())?,
+                    Ok(None) => warn!("Recorder lost connection to the messaging broker."),
+                    Err(_) => break Err(AgemoError::Connection(
+                        "the recorder's message stream was closed".to_string(),
+                    )),
+                }
+            }
+            _ = tokio::signal::ctrl_c() => break Ok(()),
+        }
+    };
+
+    info!("Finishing the recording '{}'.", settings.output_file);
+    let _ = client.disconnect(None).await;
+
+    recorder.finish()?;
+
+    result
+}
+
+#[cfg(test)]
+mod recorder_tests {
+    use std::{env, fs, io::Cursor};
+
+    use uuid::Uuid;
+
+    use super::*;
+
+    /// Magic bytes that start every MCAP file.
+    const MCAP_MAGIC: &[u8] = b"\x89MCAP0\r\n";
+
+    fn selection(selector: &str) -> RecordedTopicSettings {
+        RecordedTopicSettings {
+            selector: selector.to_string(),
+            schema: None,
+        }
+    }
+
+    #[test]
+    fn is_selected_matches_topic_or_publisher_test() {
+        let output = Cursor::new(Vec::new());
+        let recorder =
+            McapRecorder::new(output, vec![selection("topic_1"), selection("pub_2")]).unwrap();
+
+        let pub_1 = TopicMetadata::new("pub_1".to_string(), 0, None);
+        let pub_2 = TopicMetadata::new("pub_2".to_string(), 0, None);
+
+        assert!(recorder.is_selected("topic_1", &pub_1));
+        assert!(recorder.is_selected("topic_2", &pub_2));
+        assert!(!recorder.is_selected("topic_3", &pub_1));
+    }
+
+    #[test]
+    fn empty_selection_records_every_topic_test() {
+        let output = Cursor::new(Vec::new());
+        let mut recorder = McapRecorder::new(output, Vec::new()).unwrap();
+        let metadata = TopicMetadata::new("pub_1".to_string(), 0, None);
+
+        assert!(recorder.add_topic("topic_1", &metadata).unwrap());
+        assert!(!recorder.add_topic("topic_1", &metadata).unwrap());
+        assert!(recorder.is_recording("topic_1"));
+    }
+
+    #[test]
+    fn record_writes_mcap_file_test() {
+        let path = env::temp_dir().join(format!("agemo_recorder_{}.mcap", Uuid::new_v4()));
+        let file = File::create(&path).unwrap();
+
+        let mut recorder = McapRecorder::new(
+            BufWriter::new(file),
+            vec![RecordedTopicSettings {
+                selector: "pub_1".to_string(),
+                schema: Some(r#"{"type":"number"}"#.to_string()),
+            }],
+        )
+        .unwrap();
+        let metadata = TopicMetadata::new(
+            "pub_1".to_string(),
+            0,
+            Some("http://0.0.0.0:50061".to_string()),
+        );
+
+        assert!(recorder.add_topic("topic_1", &metadata).unwrap());
+        recorder.record("topic_1", b"42", 1).unwrap();
+        recorder.record("unknown_topic", b"0", 2).unwrap();
+        recorder.finish().unwrap();
+        drop(recorder);
+
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let messages: Vec<_> = mcap::MessageStream::new(&bytes)
+            .unwrap()
+            .map(|msg| msg.unwrap())
+            .collect();
+
+        assert!(bytes.starts_with(MCAP_MAGIC));
+        assert_eq!(1, messages.len());
+        assert_eq!("topic_1", messages[0].channel.topic);
+        assert_eq!(b"42", messages[0].data.as_ref());
+        assert_eq!(
+            Some(&"pub_1".to_string()),
+            messages[0].channel.metadata.get("publisher_id")
+        );
+    }
+}
@@ -0,0 +1,83 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Module describing the schema of the pub sub service, built from the compiled proto descriptors.
+//!
+//! The schema is served through the `GetSchema` method and registered with Chariott, so that
+//! discovered clients can fetch the actual proto schema instead of relying on a file name.
+
+use prost::Message;
+use prost_types::FileDescriptorSet;
+
+use proto::pubsub::v1::FILE_DESCRIPTOR_SET;
+
+use crate::error::AgemoError;
+
+/// The kind of schema served by the pub sub service.
+pub const SCHEMA_KIND: &str = "grpc+descriptor_set";
+
+/// Suffix added to the service name when registering the schema with Chariott.
+pub const SCHEMA_SERVICE_SUFFIX: &str = "schema";
+
+/// Name of the method that serves the schema.
+const SCHEMA_METHOD: &str = "GetSchema";
+
+/// Returns the serialized `FileDescriptorSet` of the pub sub service.
+pub fn file_descriptor_set() -> &'static [u8] {
+    FILE_DESCRIPTOR_SET
+}
+
+/// Returns the reference to the method that serves the schema, in the form
+/// `<package>.<service>/<method>`.
+///
+/// The reference is read from the compiled descriptors so that it follows changes to the proto
+/// definition.
+pub fn schema_reference() -> Result<String, AgemoError> {
+    let descriptor_set = FileDescriptorSet::decode(FILE_DESCRIPTOR_SET).map_err(|e| {
+        AgemoError::InvalidConfiguration(format!("invalid file descriptor set: {e}"))
+    })?;
+
+    descriptor_set
+        .file
+        .iter()
+        .find_map(|file| {
+            file.service.iter().find_map(|service| {
+                service
+                    .method
+                    .iter()
+                    .any(|method| method.name() == SCHEMA_METHOD)
+                    .then(|| format!("{}.{}/{SCHEMA_METHOD}", file.package(), service.name()))
+            })
+        })
+        .ok_or_else(|| {
+            AgemoError::InvalidConfiguration(format!(
+                "no service in the file descriptor set provides '{SCHEMA_METHOD}'"
+            ))
+        })
+}
+
+#[cfg(test)]
+mod schema_tests {
+    use super::*;
+
+    #[test]
+    fn schema_reference_test() {
+        assert_eq!("pubsub.PubSub/GetSchema", schema_reference().unwrap());
+    }
+
+    #[test]
+    fn file_descriptor_set_decodes_test() {
+        let descriptor_set = FileDescriptorSet::decode(file_descriptor_set()).unwrap();
+
+        assert!(descriptor_set
+            .file
+            .iter()
+            .any(|file| file.name() == "pubsub.proto"));
+        // The imports are included, so that transcoding proxies can use the descriptor set.
+        assert!(descriptor_set
+            .file
+            .iter()
+            .any(|file| file.name() == "google/api/annotations.proto"));
+    }
+}
@@ -0,0 +1,348 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Self-test of the full loop of the pub sub service, used to validate a deployment.
+//!
+//! The self-test creates an internal test topic, has the broker connector publish a probe message
+//! on it and wait for the message to come back through its subscription, deletes the topic on the
+//! messaging broker and finally removes it from the managed topics. The outcome of every step is
+//! reported, so that operators can tell which part of the loop is broken.
+
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
+};
+
+use common::shared_state::SharedState;
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use crate::{
+    pubsub_connector::{MonitorMessage, PubSubAction},
+    topic_manager::{ActiveTopicsMap, TopicMetadata},
+};
+
+/// Prefix of the internal topics created by the self-test.
+pub const SELF_TEST_TOPIC_PREFIX: &str = "agemo/self-test/";
+
+/// Client id recorded as the publisher of the internal topics created by the self-test.
+pub const SELF_TEST_CLIENT_ID: &str = "agemo-self-test";
+
+/// Maximum time the messaging broker gets to deliver the probe message.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Maximum time the broker connector gets to report the result of a probe, on top of the probe
+/// timeout.
+const REPORT_GRACE: Duration = Duration::from_secs(2);
+
+/// Result of the probe of a test topic by the broker connector.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProbeResult {
+    /// How long the probe message took to come back, or why it did not.
+    pub delivery: Result<Duration, String>,
+    /// Whether the test topic was deleted from the messaging broker.
+    pub deletion: Result<(), String>,
+}
+
+/// The probes waiting for the broker connector to report their result, by test topic.
+#[derive(Clone, Debug, Default)]
+pub struct PendingProbes {
+    probes: Arc<SharedState<HashMap<String, oneshot::Sender<ProbeResult>>>>,
+}
+
+impl PendingProbes {
+    /// Registers a probe of a test topic, returning the receiver of its result.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The test topic.
+    fn register(&self, topic: &str) -> oneshot::Receiver<ProbeResult> {
+        let (sender, receiver) = oneshot::channel();
+        self.probes.lock().insert(topic.to_string(), sender);
+
+        receiver
+    }
+
+    /// Forgets the probe of a test topic, if its result was not reported.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The test topic.
+    fn forget(&self, topic: &str) {
+        self.probes.lock().remove(topic);
+    }
+
+    /// Reports the result of the probe of a test topic. Called by the broker connector.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The test topic.
+    /// * `result` - The result of the probe.
+    pub fn report(&self, topic: &str, result: ProbeResult) {
+        if let Some(sender) = self.probes.lock().remove(topic) {
+            // The self-test may have given up on the probe already.
+            let _ = sender.send(result);
+        }
+    }
+}
+
+/// Outcome of a step of the self-test.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelfTestStep {
+    /// The name of the step.
+    pub name: &'static str,
+    /// Whether the step passed.
+    pub passed: bool,
+    /// How long the step took.
+    pub duration: Duration,
+    /// Details on the outcome of the step, such as the reason it failed.
+    pub detail: String,
+}
+
+impl SelfTestStep {
+    /// Creates the outcome of a step.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the step.
+    /// * `duration` - How long the step took.
+    /// * `result` - The details of a passed step, or the reason it failed.
+    fn new(name: &'static str, duration: Duration, result: Result<String, String>) -> Self {
+        let passed = result.is_ok();
+        let detail = result.unwrap_or_else(|err| err);
+
+        SelfTestStep {
+            name,
+            passed,
+            duration,
+            detail,
+        }
+    }
+}
+
+/// Report of a self-test run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelfTestReport {
+    /// The internal test topic used.
+    pub topic: String,
+    /// The outcome of each step, in order.
+    pub steps: Vec<SelfTestStep>,
+}
+
+impl SelfTestReport {
+    /// Returns whether every step passed.
+    pub fn passed(&self) -> bool {
+        self.steps.iter().all(|step| step.passed)
+    }
+}
+
+/// Runs self-tests of the full loop of the service.
+#[derive(Clone, Debug)]
+pub struct SelfTester {
+    /// Channel used to ask the broker connector to probe test topics.
+    connector_sender: mpsc::Sender<MonitorMessage>,
+    /// The probes waiting for the broker connector.
+    probes: PendingProbes,
+}
+
+impl SelfTester {
+    /// Creates a new SelfTester.
+    ///
+    /// # Arguments
+    ///
+    /// * `connector_sender` - Channel used to ask the broker connector to probe test topics.
+    /// * `probes` - The probes waiting for the broker connector, shared with the connector.
+    pub fn new(connector_sender: mpsc::Sender<MonitorMessage>, probes: PendingProbes) -> Self {
+        SelfTester {
+            connector_sender,
+            probes,
+        }
+    }
+
+    /// Runs a self-test, returning the outcome of each step.
+    ///
+    /// # Arguments
+    ///
+    /// * `active_topics` - Handle that points to the shared active topics map.
+    /// * `now` - The current time, used to timestamp the test topic.
+    pub async fn run(
+        &self,
+        active_topics: &Arc<SharedState<ActiveTopicsMap>>,
+        now: Instant,
+    ) -> SelfTestReport {
+        let topic = format!("{SELF_TEST_TOPIC_PREFIX}{}", Uuid::new_v4());
+        let mut steps = Vec::new();
+
+        // Create the test topic the same way a publisher's topic is created.
+        let start = Instant::now();
+        active_topics.lock().insert(
+            topic.clone(),
+            TopicMetadata::new_at(SELF_TEST_CLIENT_ID.to_string(), 0, None, now),
+        );
+        let created = active_topics.lock().contains_key(&topic);
+        steps.push(SelfTestStep::new(
+            "create",
+            start.elapsed(),
+            if created {
+                Ok(format!("created topic '{topic}'"))
+            } else {
+                Err(format!("topic '{topic}' is not managed after creation"))
+            },
+        ));
+
+        // Have the broker connector publish and subscribe on the topic, then delete it.
+        let start = Instant::now();
+        let receiver = self.probes.register(&topic);
+        let probe_msg = MonitorMessage {
+            context: topic.clone(),
+            action: PubSubAction::SelfTest,
+            client_id: None,
+            identity: None,
+        };
+
+        let result = if self.connector_sender.send(probe_msg).is_err() {
+            Err("the broker connector is not running".to_string())
+        } else {
+            match tokio::time::timeout(PROBE_TIMEOUT + REPORT_GRACE, receiver).await {
+                Ok(Ok(result)) => Ok(result),
+                Ok(Err(_)) => Err("the broker connector dropped the probe".to_string()),
+                Err(_) => Err(format!(
+                    "the broker connector did not report within {:?}",
+                    PROBE_TIMEOUT + REPORT_GRACE
+                )),
+            }
+        };
+        self.probes.forget(&topic);
+
+        match result {
+            Ok(result) => {
+                steps.push(SelfTestStep::new(
+                    "deliver",
+                    start.elapsed(),
+                    result
+                        .delivery
+                        .map(|round_trip| format!("probe message delivered in {round_trip:?}")),
+                ));
+                steps.push(SelfTestStep::new(
+                    "delete",
+                    start.elapsed(),
+                    result
+                        .deletion
+                        .map(|_| "topic deleted from the messaging broker".to_string()),
+                ));
+            }
+            Err(err) => {
+                steps.push(SelfTestStep::new("deliver", start.elapsed(), Err(err)));
+                steps.push(SelfTestStep::new(
+                    "delete",
+                    Duration::ZERO,
+                    Err("skipped, the broker connector did not report".to_string()),
+                ));
+            }
+        }
+
+        // Remove the test topic from the managed topics.
+        let start = Instant::now();
+        let removed = active_topics.lock().remove(&topic).is_some();
+        steps.push(SelfTestStep::new(
+            "cleanup",
+            start.elapsed(),
+            if removed {
+                Ok("topic removed from the managed topics".to_string())
+            } else {
+                Err("topic was removed by someone else".to_string())
+            },
+        ));
+
+        SelfTestReport { topic, steps }
+    }
+}
+
+#[cfg(test)]
+mod self_test_tests {
+    use super::*;
+
+    /// Answers probes from the broker connector channel with the given result.
+    fn fake_connector(
+        receiver: mpsc::Receiver<MonitorMessage>,
+        probes: PendingProbes,
+        result: ProbeResult,
+    ) {
+        std::thread::spawn(move || {
+            for msg in receiver.iter() {
+                assert_eq!(PubSubAction::SelfTest, msg.action);
+                probes.report(&msg.context, result.clone());
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn self_test_passes_test() {
+        let (sender, receiver) = mpsc::channel::<MonitorMessage>();
+        let probes = PendingProbes::default();
+        fake_connector(
+            receiver,
+            probes.clone(),
+            ProbeResult {
+                delivery: Ok(Duration::from_millis(5)),
+                deletion: Ok(()),
+            },
+        );
+        let active_topics = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+
+        let report = SelfTester::new(sender, probes)
+            .run(&active_topics, Instant::now())
+            .await;
+
+        assert!(report.passed());
+        assert!(report.topic.starts_with(SELF_TEST_TOPIC_PREFIX));
+        let names: Vec<&str> = report.steps.iter().map(|step| step.name).collect();
+        assert_eq!(vec!["create", "deliver", "delete", "cleanup"], names);
+
+        // The test topic does not outlive the self-test.
+        assert!(active_topics.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn self_test_reports_failed_delivery_test() {
+        let (sender, receiver) = mpsc::channel::<MonitorMessage>();
+        let probes = PendingProbes::default();
+        fake_connector(
+            receiver,
+            probes.clone(),
+            ProbeResult {
+                delivery: Err("probe message was not delivered".to_string()),
+                deletion: Ok(()),
+            },
+        );
+        let active_topics = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+
+        let report = SelfTester::new(sender, probes)
+            .run(&active_topics, Instant::now())
+            .await;
+
+        assert!(!report.passed());
+        assert!(!report.steps[1].passed);
+        assert_eq!("probe message was not delivered", report.steps[1].detail);
+        assert!(report.steps[2].passed);
+    }
+
+    #[tokio::test]
+    async fn self_test_without_connector_test() {
+        let sender = mpsc::channel::<MonitorMessage>().0;
+        let active_topics = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+
+        let report = SelfTester::new(sender, PendingProbes::default())
+            .run(&active_topics, Instant::now())
+            .await;
+
+        assert!(!report.passed());
+        assert_eq!(
+            "the broker connector is not running",
+            report.steps[1].detail
+        );
+        assert!(report.steps[3].passed);
+    }
+}
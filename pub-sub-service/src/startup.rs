@@ -0,0 +1,665 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Startup diagnostics of the pub sub service.
+//!
+//! Checks the settings for common misconfigurations before any task is started, so that the
+//! service fails fast with an actionable error instead of failing mid-request, and logs a summary
+//! of how the service is set up.
+
+use std::{
+    fmt,
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use log::info;
+use url::Url;
+
+use crate::{
+    connectors::loopback_connector,
+    error::AgemoError,
+    load_config::{CloudBridgeKind, ConfigLayer, ConnectorKind, Settings},
+    topic_manager::DEFAULT_IDLE_TIMEOUT,
+    validation,
+};
+
+/// Maximum time to wait for the messaging broker to accept a connection when probing it.
+pub const BROKER_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Result of probing whether the messaging broker accepts connections.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BrokerProbe {
+    /// The broker accepted a connection in the given time.
+    Reachable(Duration),
+    /// The broker could not be connected to, for the given reason.
+    Unreachable(String),
+    /// The broker was not probed, for the given reason.
+    Skipped(String),
+}
+
+impl fmt::Display for BrokerProbe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BrokerProbe::Reachable(elapsed) => write!(f, "reachable in {elapsed:?}"),
+            BrokerProbe::Unreachable(reason) => write!(f, "unreachable: {reason}"),
+            BrokerProbe::Skipped(reason) => write!(f, "not probed: {reason}"),
+        }
+    }
+}
+
+/// Returns the default port of the given broker uri scheme, if known.
+///
+/// # Arguments
+///
+/// * `scheme` - The scheme of the broker uri.
+fn default_port(scheme: &str) -> Option<u16> {
+    match scheme {
+        "mqtt" | "tcp" => Some(1883),
+        "mqtts" | "ssl" => Some(8883),
+        "kafka" => Some(9092),
+        _ => None,
+    }
+}
+
+/// Probes whether the messaging broker accepts TCP connections. Blocks for up to `timeout`.
+///
+/// # Arguments
+///
+/// * `broker_uri` - The uri of the messaging broker.
+/// * `timeout` - Maximum time to wait for the broker to accept a connection.
+pub fn probe_broker(broker_uri: &str, timeout: Duration) -> BrokerProbe {
+    let url = match Url::parse(broker_uri) {
+        Ok(url) => url,
+        Err(err) => return BrokerProbe::Skipped(format!("invalid uri: {err}")),
+    };
+
+    if url.scheme() == loopback_connector::MEMORY_SCHEME {
+        return BrokerProbe::Skipped("the message bus runs inside the service".to_string());
+    }
+    let Some(host) = url.host_str() else {
+        return BrokerProbe::Skipped("the uri has no host".to_string());
+    };
+    let Some(port) = url.port().or_else(|| default_port(url.scheme())) else {
+        return BrokerProbe::Skipped(format!("no default port for scheme '{}'", url.scheme()));
+    };
+
+    // Hosts of uris with a scheme unknown to the url parser keep the brackets of ipv6 addresses.
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addr: Option<SocketAddr> = match (host, port).to_socket_addrs() {
+        Ok(mut addrs) => addrs.next(),
+        Err(err) => return BrokerProbe::Unreachable(format!("unable to resolve '{host}': {err}")),
+    };
+    let Some(addr) = addr else {
+        return BrokerProbe::Unreachable(format!("'{host}' did not resolve to any address"));
+    };
+
+    let start = Instant::now();
+    match TcpStream::connect_timeout(&addr, timeout) {
+        Ok(_) => BrokerProbe::Reachable(start.elapsed()),
+        Err(err) => BrokerProbe::Unreachable(err.to_string()),
+    }
+}
+
+/// Returns whether the given uri is an http uri with a host.
+///
+/// # Arguments
+///
+/// * `uri` - The uri to check.
+fn is_http_uri(uri: &str) -> bool {
+    Url::parse(uri)
+        .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.host_str().is_some())
+}
+
+/// Checks the settings for common misconfigurations.
+///
+/// Returns an [`AgemoError::InvalidConfiguration`] listing every problem found, with how to fix
+/// it.
+///
+/// # Arguments
+///
+/// * `settings` - The service settings.
+pub fn check_settings(settings: &Settings) -> Result<(), AgemoError> {
+    let mut problems = Vec::new();
+
+    if settings.pub_sub_authority.parse::<SocketAddr>().is_err() {
+        problems.push(format!(
+            "'pub_sub_authority' is '{}', expected an ip address and port such as \
+             '0.0.0.0:50051'",
+            settings.pub_sub_authority
+        ));
+    }
+
+    let connector_settings = settings.connector_settings();
+    // Names settings by their path in the connector section, if it is used.
+    let connector_path = |legacy: &str, path: &str| {
+        if settings.connector.is_some() {
+            format!("connector.{path}")
+        } else {
+            legacy.to_string()
+        }
+    };
+
+    // Connectors loaded from a shared library may use uris the built in connector does not, and
+    // the message bus inside the service has no host.
+    let has_host = Url::parse(&settings.messaging_uri).is_ok_and(|url| url.host_str().is_some());
+    if connector_settings.library.is_none()
+        && !has_host
+        && !loopback_connector::is_memory_uri(&settings.messaging_uri)
+    {
+        problems.push(format!(
+            "'messaging_uri' is '{}', expected a uri with a host such as 'mqtt://0.0.0.0:1883'",
+            settings.messaging_uri
+        ));
+    }
+
+    if let Some(chariott_uri) = &settings.chariott_uri {
+        if !is_http_uri(chariott_uri) {
+            problems.push(format!(
+                "'chariott_uri' is '{chariott_uri}', expected an http uri such as \
+                 'http://0.0.0.0:50000'"
+            ));
+        }
+    }
+
+    if let Some(intent_settings) = &settings.chariott_intents {
+        if !is_http_uri(&intent_settings.intent_broker_uri) {
+            problems.push(format!(
+                "'chariott_intents.intent_broker_uri' is '{}', expected an http uri such as \
+                 'http://0.0.0.0:4243'",
+                intent_settings.intent_broker_uri
+            ));
+        }
+    }
+
+    if let Some(library_settings) = &connector_settings.library {
+        if cfg!(feature = "dynamic-connectors") && !Path::new(&library_settings.path).is_file() {
+            problems.push(format!(
+                "'{}' is '{}', which is not a file",
+                connector_path("connector_library.path", "library.path"),
+                library_settings.path
+            ));
+        }
+    } else if !connector_settings.kind.has_built_in_connector() {
+        problems.push(format!(
+            "'connector.kind' is '{}', which has no built in connector, set 'connector.library' \
+             to a connector library for it",
+            connector_settings.kind.as_str()
+        ));
+    }
+
+    if connector_settings.kind == ConnectorKind::Kafka
+        && !connector_settings
+            .kafka
+            .as_ref()
+            .is_some_and(|kafka| !kafka.brokers.is_empty())
+    {
+        problems.push(
+            "'connector.kafka.brokers' is empty, list the bootstrap brokers of the cluster"
+                .to_string(),
+        );
+    }
+
+    if !(0..=2).contains(&connector_settings.mqtt.monitor_qos) {
+        problems.push(format!(
+            "'{}' is {}, expected a quality of service of 0, 1 or 2",
+            connector_path("mqtt_connection.monitor_qos", "mqtt.monitor_qos"),
+            connector_settings.mqtt.monitor_qos
+        ));
+    }
+
+    // A receive maximum of 0 is a protocol error in MQTT v5.
+    if connector_settings.mqtt.receive_maximum == 0 {
+        problems.push(format!(
+            "'{}' is 0, expected at least 1 unacknowledged message",
+            connector_path("mqtt_connection.receive_maximum", "mqtt.receive_maximum"),
+        ));
+    }
+
+    if settings.subscription_lease_secs == Some(0) {
+        problems.push(
+            "'subscription_lease_secs' is 0, set it to a positive number of seconds or remove \
+             it to disable subscription leases"
+                .to_string(),
+        );
+    }
+
+    if settings.placeholder_ttl_secs == Some(0) {
+        problems.push(
+            "'placeholder_ttl_secs' is 0, set it to a positive number of seconds or remove it \
+             to use the default time to live"
+                .to_string(),
+        );
+    }
+
+    if let Some(max_idle_secs) = settings.max_idle_secs {
+        if max_idle_secs <= DEFAULT_IDLE_TIMEOUT.as_secs() {
+            problems.push(format!(
+                "'max_idle_secs' is {max_idle_secs}, set it to more than the {} second idle \
+                 timeout so that publishers are reminded before their topics are deleted",
+                DEFAULT_IDLE_TIMEOUT.as_secs()
+            ));
+        }
+    }
+
+    if settings
+        .topic_spill
+        .as_ref()
+        .is_some_and(|topic_spill| topic_spill.max_in_memory_topics == 0)
+    {
+        problems.push(
+            "'topic_spill.max_in_memory_topics' is 0, set it to the number of topics to hold in \
+             memory"
+                .to_string(),
+        );
+    }
+
+    if settings.request_budget_ms == Some(0) {
+        problems.push(
+            "'request_budget_ms' is 0, set it to a positive number of milliseconds or remove it \
+             to use the default budget"
+                .to_string(),
+        );
+    }
+
+    if let Some(reconciliation) = &settings.orphan_reconciliation {
+        if reconciliation.interval_secs == 0 {
+            problems.push(
+                "'orphan_reconciliation.interval_secs' is 0, set it to a positive number of \
+                 seconds"
+                    .to_string(),
+            );
+        }
+    }
+
+    if let Some(bridge_settings) = &settings.cloud_bridge {
+        // The bridge only speaks plain HTTP, as TLS is terminated by the bridge or a sidecar.
+        if !is_http_uri(&bridge_settings.endpoint) || bridge_settings.endpoint.starts_with("https")
+        {
+            problems.push(format!(
+                "'cloud_bridge.endpoint' is '{}', expected an http uri such as \
+                 'http://hono-registry:28080'",
+                bridge_settings.endpoint
+            ));
+        }
+
+        match bridge_settings.kind {
+            CloudBridgeKind::Hono if bridge_settings.tenant.is_none() => {
+                problems.push(
+                    "'cloud_bridge.tenant' is not set, set it to the Hono tenant to register the \
+                     topics in"
+                        .to_string(),
+                );
+            }
+            CloudBridgeKind::IotHub if bridge_settings.route_endpoint.is_none() => {
+                problems.push(
+                    "'cloud_bridge.route_endpoint' is not set, set it to the IoT Hub endpoint the \
+                     routes deliver to"
+                        .to_string(),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    if settings
+        .recorder
+        .as_ref()
+        .is_some_and(|recorder| recorder.refresh_interval_secs == 0)
+    {
+        problems.push(
+            "'recorder.refresh_interval_secs' is 0, set it to a positive number of seconds"
+                .to_string(),
+        );
+    }
+
+    if let Some(latency_probe) = &settings.latency_probe {
+        if latency_probe.interval_secs == 0 {
+            problems.push(
+                "'latency_probe.interval_secs' is 0, set it to a positive number of seconds"
+                    .to_string(),
+            );
+        }
+    }
+
+    let mut template_names: Vec<&String> = settings.topic_templates.keys().collect();
+    template_names.sort();
+    for name in template_names {
+        let template = &settings.topic_templates[name];
+
+        if template.qos > 2 {
+            problems.push(format!(
+                "'topic_templates.{name}.qos' is {}, expected a quality of service of 0, 1 or 2",
+                template.qos
+            ));
+        }
+
+        if template.ttl_secs == Some(0) {
+            problems.push(format!(
+                "'topic_templates.{name}.ttl_secs' is 0, set it to a positive number of seconds \
+                 or remove it to use the default"
+            ));
+        }
+
+        if let (Some(ttl_secs), Some(max_idle_secs)) = (template.ttl_secs, settings.max_idle_secs) {
+            if ttl_secs >= max_idle_secs {
+                problems.push(format!(
+                    "'topic_templates.{name}.ttl_secs' is {ttl_secs}, which is not shorter than \
+                     'max_idle_secs', so publishers would not be reminded before their topics are \
+                     deleted"
+                ));
+            }
+        }
+
+        if let Some(prefix) = &template.prefix {
+            if prefix.contains(['+', '#']) {
+                problems.push(format!(
+                    "'topic_templates.{name}.prefix' is '{prefix}', which contains a wildcard"
+                ));
+            }
+        }
+    }
+
+    let mut subjects: Vec<&String> = settings.subject_publishers.keys().collect();
+    subjects.sort();
+    for subject in subjects {
+        let publisher = &settings.subject_publishers[subject];
+
+        if let Err(problem) =
+            validation::validate_management_callback(&publisher.management_callback)
+        {
+            problems.push(format!(
+                "'subject_publishers.{subject}.management_callback' is invalid: {problem}"
+            ));
+        }
+    }
+
+    if let Some(state_dump_file) = &settings.state_dump_file {
+        let parent = Path::new(state_dump_file)
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty());
+
+        if parent.is_some_and(|parent| !parent.is_dir()) {
+            problems.push(format!(
+                "the directory of 'state_dump_file' '{state_dump_file}' does not exist, create \
+                 it or change the path"
+            ));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(AgemoError::InvalidConfiguration(problems.join("; ")))
+    }
+}
+
+/// Summary of how the service is set up, logged on startup.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StartupSummary {
+    /// The version of the service.
+    pub version: String,
+    /// The address the service listens on.
+    pub pub_sub_authority: String,
+    /// The broker connector selected given the settings and the features of the build.
+    pub connector: String,
+    /// The uri of the messaging broker.
+    pub broker_uri: String,
+    /// Whether the messaging broker accepted a connection.
+    pub broker_probe: BrokerProbe,
+    /// The layers the settings were loaded from, from lowest to highest precedence.
+    pub config_layers: Vec<ConfigLayer>,
+    /// How the service is made available through Chariott.
+    pub chariott_mode: String,
+}
+
+impl StartupSummary {
+    /// Creates the startup summary of the service.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The service settings.
+    /// * `config_layers` - The layers the settings were loaded from.
+    /// * `broker_probe` - The result of probing the messaging broker.
+    pub fn new(
+        settings: &Settings,
+        config_layers: Vec<ConfigLayer>,
+        broker_probe: BrokerProbe,
+    ) -> Self {
+        StartupSummary {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            pub_sub_authority: settings.pub_sub_authority.clone(),
+            connector: connector_name(settings),
+            broker_uri: settings.messaging_uri.clone(),
+            broker_probe,
+            config_layers,
+            chariott_mode: chariott_mode(settings),
+        }
+    }
+
+    /// Logs the summary, one entry per line.
+    pub fn log(&self) {
+        info!("Starting the pub sub service v{}:", self.version);
+        info!("  listening on: {}", self.pub_sub_authority);
+        info!("  connector: {}", self.connector);
+        info!("  broker: {} ({})", self.broker_uri, self.broker_probe);
+        for layer in &self.config_layers {
+            let status = if layer.found { "loaded" } else { "not found" };
+            info!(
+                "  config layer '{}': {} ({status})",
+                layer.name, layer.source
+            );
+        }
+        info!("  chariott: {}", self.chariott_mode);
+    }
+}
+
+/// Returns the broker connector selected given the settings and the features of the build.
+///
+/// # Arguments
+///
+/// * `settings` - The service settings.
+fn connector_name(settings: &Settings) -> String {
+    match &settings.connector_settings().library {
+        Some(library_settings) if cfg!(feature = "dynamic-connectors") => {
+            format!("shared library '{}'", library_settings.path)
+        }
+        _ if cfg!(feature = "mqtt") => "Mosquitto MQTT v5".to_string(),
+        _ => "none, topics are not monitored".to_string(),
+    }
+}
+
+/// Returns how the service is made available through Chariott.
+///
+/// # Arguments
+///
+/// * `settings` - The service settings.
+fn chariott_mode(settings: &Settings) -> String {
+    let mut modes = Vec::new();
+
+    if let Some(chariott_uri) = &settings.chariott_uri {
+        modes.push(format!("registered with '{chariott_uri}'"));
+    }
+    if let Some(intent_settings) = &settings.chariott_intents {
+        modes.push(format!(
+            "providing intents through '{}'",
+            intent_settings.intent_broker_uri
+        ));
+    }
+
+    if modes.is_empty() {
+        "standalone".to_string()
+    } else {
+        modes.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod startup_tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn settings(overrides: serde_json::Value) -> Settings {
+        let mut settings = serde_json::json!({
+            "pub_sub_authority": "0.0.0.0:50051",
+            "messaging_uri": "mqtt://0.0.0.0:1883",
+        });
+        settings
+            .as_object_mut()
+            .unwrap()
+            .extend(overrides.as_object().unwrap().clone());
+
+        serde_json::from_value(settings).unwrap()
+    }
+
+    #[test]
+    fn check_settings_accepts_valid_settings_test() {
+        let settings = settings(serde_json::json!({
+            "chariott_uri": "http://0.0.0.0:50000",
+            "subscription_lease_secs": 30,
+        }));
+
+        assert!(check_settings(&settings).is_ok());
+    }
+
+    #[test]
+    fn check_settings_accepts_memory_uri_test() {
+        // The message bus inside the service has no host.
+        let settings = settings(serde_json::json!({ "messaging_uri": "memory://" }));
+
+        assert!(check_settings(&settings).is_ok());
+    }
+
+    #[test]
+    fn check_settings_reports_every_problem_test() {
+        let settings = settings(serde_json::json!({
+            "pub_sub_authority": "localhost",
+            "messaging_uri": "0.0.0.0:1883",
+            "chariott_uri": "0.0.0.0:50000",
+            "mqtt_connection": { "monitor_qos": 3, "receive_maximum": 0 },
+            "topic_templates": { "vehicle-signals": { "prefix": "vehicle/#", "ttl_secs": 0 } },
+            "subscription_lease_secs": 0,
+            "placeholder_ttl_secs": 0,
+            "max_idle_secs": 10,
+            "topic_spill": { "max_in_memory_topics": 0, "directory": "/tmp/agemo-spill" },
+            "subject_publishers": {
+                "cabin/temperature": { "publisher_id": "hvac", "management_callback": "ftp://hvac" }
+            },
+            "state_dump_file": "/nonexistent/agemo/state.json",
+            "recorder": { "output_file": "/tmp/agemo.mcap", "refresh_interval_secs": 0 },
+        }));
+
+        let Err(AgemoError::InvalidConfiguration(problems)) = check_settings(&settings) else {
+            panic!("expected an invalid configuration");
+        };
+
+        assert!(problems.contains("'pub_sub_authority' is 'localhost'"));
+        assert!(problems.contains("'messaging_uri' is '0.0.0.0:1883'"));
+        assert!(problems.contains("'chariott_uri' is '0.0.0.0:50000'"));
+        assert!(problems.contains("'mqtt_connection.monitor_qos' is 3"));
+        assert!(problems.contains("'mqtt_connection.receive_maximum' is 0"));
+        assert!(problems.contains("'topic_templates.vehicle-signals.ttl_secs' is 0"));
+        assert!(problems.contains("'topic_templates.vehicle-signals.prefix' is 'vehicle/#'"));
+        assert!(problems.contains("'subscription_lease_secs' is 0"));
+        assert!(problems.contains("'placeholder_ttl_secs' is 0"));
+        assert!(problems.contains("'max_idle_secs' is 10"));
+        assert!(problems.contains("'topic_spill.max_in_memory_topics' is 0"));
+        assert!(problems.contains("'subject_publishers.cabin/temperature.management_callback'"));
+        assert!(problems.contains("'state_dump_file'"));
+        assert!(problems.contains("'recorder.refresh_interval_secs' is 0"));
+    }
+
+    #[test]
+    fn check_settings_reports_connector_problems_test() {
+        let settings = settings(serde_json::json!({
+            "connector": { "kind": "kafka", "mqtt": { "monitor_qos": 3, "receive_maximum": 0 } },
+        }));
+
+        let Err(AgemoError::InvalidConfiguration(problems)) = check_settings(&settings) else {
+            panic!("expected an invalid configuration");
+        };
+
+        #[cfg(not(feature = "kafka"))]
+        assert!(problems.contains("'connector.kind' is 'kafka', which has no built in connector"));
+        assert!(problems.contains("'connector.kafka.brokers' is empty"));
+        assert!(problems.contains("'connector.mqtt.monitor_qos' is 3"));
+        assert!(problems.contains("'connector.mqtt.receive_maximum' is 0"));
+    }
+
+    #[cfg(not(feature = "dds"))]
+    #[test]
+    fn check_settings_reports_dds_without_feature_test() {
+        let dds_settings = settings(serde_json::json!({ "connector": { "kind": "dds" } }));
+
+        let Err(AgemoError::InvalidConfiguration(problems)) = check_settings(&dds_settings) else {
+            panic!("expected an invalid configuration");
+        };
+
+        assert!(problems.contains("'connector.kind' is 'dds', which has no built in connector"));
+    }
+
+    #[test]
+    fn check_settings_reports_cloud_bridge_problems_test() {
+        let hono_settings = settings(serde_json::json!({
+            "cloud_bridge": { "kind": "hono", "endpoint": "https://hono-registry:28080" },
+        }));
+
+        let Err(AgemoError::InvalidConfiguration(problems)) = check_settings(&hono_settings) else {
+            panic!("expected an invalid configuration");
+        };
+
+        assert!(problems.contains("'cloud_bridge.endpoint' is 'https://hono-registry:28080'"));
+        assert!(problems.contains("'cloud_bridge.tenant' is not set"));
+
+        let iot_hub_settings = settings(serde_json::json!({
+            "cloud_bridge": { "kind": "iot_hub", "endpoint": "http://0.0.0.0:8080" },
+        }));
+
+        let Err(AgemoError::InvalidConfiguration(problems)) = check_settings(&iot_hub_settings)
+        else {
+            panic!("expected an invalid configuration");
+        };
+
+        assert!(problems.contains("'cloud_bridge.route_endpoint' is not set"));
+    }
+
+    #[test]
+    fn probe_broker_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let probe = probe_broker(&format!("mqtt://127.0.0.1:{port}"), BROKER_PROBE_TIMEOUT);
+        assert!(matches!(probe, BrokerProbe::Reachable(_)));
+
+        drop(listener);
+        let probe = probe_broker(&format!("mqtt://127.0.0.1:{port}"), BROKER_PROBE_TIMEOUT);
+        assert!(matches!(probe, BrokerProbe::Unreachable(_)));
+
+        let probe = probe_broker("amqp://127.0.0.1", BROKER_PROBE_TIMEOUT);
+        assert!(matches!(probe, BrokerProbe::Skipped(_)));
+
+        let probe = probe_broker("memory://", BROKER_PROBE_TIMEOUT);
+        assert!(matches!(probe, BrokerProbe::Skipped(_)));
+    }
+
+    #[test]
+    fn chariott_mode_test() {
+        assert_eq!(
+            "standalone",
+            chariott_mode(&settings(serde_json::json!({})))
+        );
+
+        let settings = settings(serde_json::json!({
+            "chariott_uri": "http://0.0.0.0:50000",
+            "chariott_intents": { "intent_broker_uri": "http://0.0.0.0:4243" },
+        }));
+        assert_eq!(
+            "registered with 'http://0.0.0.0:50000', providing intents through \
+             'http://0.0.0.0:4243'",
+            chariott_mode(&settings)
+        );
+    }
+}
@@ -0,0 +1,461 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Snapshots of the internal state of the service, used for debugging.
+//!
+//! A [`StateSnapshot`] captures the active topics, the topics pending deletion, the publisher
+//! callback uris and the depths of the internal queues. Snapshots are served as JSON through the
+//! admin `DumpState` method, and can be written to a file when the service panics for post-mortem
+//! analysis.
+
+use std::{
+    fs, panic,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, MutexGuard,
+    },
+    time::{Duration, SystemTime},
+};
+
+use common::shared_state::SharedState;
+use log::{error, info};
+use serde_derive::Serialize;
+
+use crate::{
+    providers::Clock,
+    topic_manager::{
+        ActionRecord, ActiveTopicsMap, IdempotencyCache, TopicEventSender, TopicMetadata,
+    },
+    topic_spill::TopicSpill,
+};
+
+/// Snapshot of a lifecycle action taken on a topic.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ActionSnapshot {
+    /// The kind of action, such as `START` or `STOP`.
+    pub action: String,
+    /// The update that caused the action.
+    pub cause: String,
+    /// The outcome of the action, such as `DELIVERED` or `FAILED`.
+    pub outcome: String,
+    /// Details on the outcome. Empty if there are none.
+    pub detail: String,
+    /// Milliseconds since the Unix epoch when the outcome was recorded.
+    pub timestamp_ms: u64,
+}
+
+impl From<&ActionRecord> for ActionSnapshot {
+    fn from(record: &ActionRecord) -> Self {
+        ActionSnapshot {
+            action: record.kind.to_string(),
+            cause: record.cause.clone(),
+            outcome: record.outcome.to_string(),
+            detail: record.detail.clone(),
+            timestamp_ms: record.timestamp_ms,
+        }
+    }
+}
+
+/// Snapshot of a single topic.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TopicSnapshot {
+    /// The name of the topic.
+    pub topic: String,
+    /// The id of the publisher of the topic.
+    pub publisher_id: String,
+    /// The number of subscribers on the topic.
+    pub subscriber_count: i32,
+    /// The ids of the subscribers known to the service.
+    pub subscribers: Vec<String>,
+    /// The number of subscription leases held on the topic.
+    pub lease_count: usize,
+    /// Whether the topic is marked for deletion.
+    pub deleted: bool,
+    /// The uri the publisher is notified of topic actions on.
+    pub management_callback: Option<String>,
+    /// How long in milliseconds the topic has gone without subscribers.
+    pub idle_ms: u64,
+    /// The request topic, if the topic is a response topic.
+    pub request_topic: Option<String>,
+    /// The response topics created on the topic.
+    pub response_topics: Vec<String>,
+    /// The subject a subscriber requested the topic for, if the service created the topic on
+    /// behalf of its publisher.
+    pub subject: Option<String>,
+    /// The most recent lifecycle actions taken on the topic, oldest first.
+    pub action_history: Vec<ActionSnapshot>,
+    /// Whether the topic is cold and spilled to disk.
+    pub spilled: bool,
+}
+
+impl TopicSnapshot {
+    /// Captures the state of a topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The name of the topic.
+    /// * `metadata` - The metadata of the topic.
+    /// * `idle_time` - How long the topic has gone without subscribers.
+    pub fn new(topic: &str, metadata: &TopicMetadata, idle_time: Duration) -> Self {
+        let mut subscribers: Vec<String> = metadata.subscribers().cloned().collect();
+        subscribers.sort();
+
+        let mut response_topics: Vec<String> = metadata.response_topics().cloned().collect();
+        response_topics.sort();
+
+        TopicSnapshot {
+            topic: topic.to_string(),
+            publisher_id: metadata.client_id.clone(),
+            subscriber_count: metadata.count,
+            subscribers,
+            lease_count: metadata.lease_count(),
+            deleted: metadata.is_deleted(),
+            management_callback: metadata.get_management_callback(),
+            idle_ms: u64::try_from(idle_time.as_millis()).unwrap_or(u64::MAX),
+            request_topic: metadata.get_request_topic().map(str::to_string),
+            response_topics,
+            subject: metadata.subject().map(str::to_string),
+            action_history: metadata
+                .action_history()
+                .map(ActionSnapshot::from)
+                .collect(),
+            spilled: false,
+        }
+    }
+
+    /// Captures the state of a topic spilled to disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The name of the topic.
+    /// * `metadata` - The metadata of the topic, read back from the spill.
+    /// * `idle_time` - How long the topic has gone without subscribers.
+    pub fn spilled(topic: &str, metadata: &TopicMetadata, idle_time: Duration) -> Self {
+        TopicSnapshot {
+            spilled: true,
+            ..TopicSnapshot::new(topic, metadata, idle_time)
+        }
+    }
+}
+
+/// Depths of the internal queues of the service.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct QueueDepths {
+    /// The number of topic events buffered for the slowest watcher.
+    pub topic_events: usize,
+    /// The number of watchers of topic events.
+    pub topic_watchers: usize,
+    /// The number of idempotency keys remembered for topic creation retries.
+    pub idempotency_keys: Option<usize>,
+}
+
+/// Snapshot of the internal state of the service.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StateSnapshot {
+    /// Milliseconds since the Unix epoch when the snapshot was taken.
+    pub timestamp_ms: u64,
+    /// Whether every part of the state could be captured. Parts that were locked when the
+    /// snapshot was taken without waiting are left out, and so are spilled topics, which are read
+    /// back from disk.
+    pub complete: bool,
+    /// Whether the broker connector last reported being connected.
+    pub broker_connected: bool,
+    /// Whether the system last reported being suspended.
+    pub suspended: bool,
+    /// The topics known to the service, sorted by name.
+    pub topics: Vec<TopicSnapshot>,
+    /// The topics marked for deletion that have not yet been deleted.
+    pub pending_deletions: Vec<String>,
+    /// The depths of the internal queues.
+    pub queues: QueueDepths,
+}
+
+/// Handles to the state of the service captured in a [`StateSnapshot`].
+#[derive(Clone)]
+pub struct StateDumpSource {
+    /// Handle that points to a shared active topics map.
+    pub active_topics: Arc<SharedState<ActiveTopicsMap>>,
+    /// Handle that points to the topics created for publishers' idempotency keys.
+    pub idempotency_cache: Arc<SharedState<IdempotencyCache>>,
+    /// Channel that topic lifecycle events are broadcast on.
+    pub topic_events: TopicEventSender,
+    /// Whether the broker connector is connected.
+    pub broker_connected: Arc<AtomicBool>,
+    /// Whether the system is suspended.
+    pub power_suspended: Arc<AtomicBool>,
+    /// Source of time used to determine how long topics have been idle.
+    pub clock: Arc<dyn Clock>,
+    /// Spill of the cold topics that are not held in memory. Topics are not spilled if not set.
+    pub topic_spill: Option<Arc<TopicSpill>>,
+}
+
+/// Locks shared state. If `wait` is false, returns None instead of waiting for state that is
+/// locked.
+///
+/// # Arguments
+///
+/// * `state` - The state to lock.
+/// * `wait` - Whether to wait for the state if it is locked.
+fn lock<T>(state: &SharedState<T>, wait: bool) -> Option<MutexGuard<'_, T>> {
+    if wait {
+        Some(state.lock())
+    } else {
+        state.try_lock()
+    }
+}
+
+impl StateDumpSource {
+    /// Takes a snapshot of the state of the service.
+    ///
+    /// # Arguments
+    ///
+    /// * `wait` - Whether to wait for state that is locked. A snapshot taken while panicking must
+    ///            not wait, as the panicking thread may hold the lock.
+    pub fn snapshot(&self, wait: bool) -> StateSnapshot {
+        let now = self.clock.now();
+        let mut complete = true;
+
+        let mut topics = Vec::new();
+        let mut pending_deletions = Vec::new();
+        match lock(&self.active_topics, wait) {
+            Some(active_topics) => {
+                for (topic, metadata) in active_topics.iter() {
+                    topics.push(TopicSnapshot::new(
+                        topic,
+                        metadata,
+                        metadata.idle_duration(now),
+                    ));
+
+                    if metadata.is_deleted() {
+                        pending_deletions.push(topic.clone());
+                    }
+                }
+
+                // The spilled topics are read while the active topics are locked, so that a topic
+                // restored meanwhile isn't captured twice.
+                match &self.topic_spill {
+                    Some(topic_spill) if wait => {
+                        for (topic, metadata) in topic_spill.spilled_topics() {
+                            topics.push(TopicSnapshot::spilled(
+                                &topic,
+                                &metadata,
+                                metadata.idle_duration(now),
+                            ));
+                        }
+                    }
+                    Some(_) => complete = false,
+                    None => {}
+                }
+            }
+            None => complete = false,
+        }
+        topics.sort_by(|a, b| a.topic.cmp(&b.topic));
+        pending_deletions.sort();
+
+        let idempotency_keys = lock(&self.idempotency_cache, wait).map(|cache| cache.len());
+        complete &= idempotency_keys.is_some();
+
+        let timestamp_ms = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|duration| u64::try_from(duration.as_millis()).unwrap_or(u64::MAX))
+            .unwrap_or_default();
+
+        StateSnapshot {
+            timestamp_ms,
+            complete,
+            broker_connected: self.broker_connected.load(Ordering::SeqCst),
+            suspended: self.power_suspended.load(Ordering::SeqCst),
+            topics,
+            pending_deletions,
+            queues: QueueDepths {
+                topic_events: self.topic_events.len(),
+                topic_watchers: self.topic_events.receiver_count(),
+                idempotency_keys,
+            },
+        }
+    }
+
+    /// Takes a snapshot of the state of the service, serialized as JSON.
+    ///
+    /// # Arguments
+    ///
+    /// * `wait` - Whether to wait for state that is locked.
+    pub fn snapshot_json(&self, wait: bool) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(&self.snapshot(wait))
+    }
+}
+
+/// Installs a panic hook that writes a snapshot of the state of the service to a file before the
+/// previous panic hook runs.
+///
+/// # Arguments
+///
+/// * `source` - The state to capture.
+/// * `path` - The file the snapshot is written to, replacing any earlier snapshot.
+pub fn install_panic_hook(source: StateDumpSource, path: PathBuf) {
+    info!("Writing a state snapshot to '{}' on panic.", path.display());
+
+    let previous_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |panic_info| {
+        match source.snapshot_json(false) {
+            Ok(json) => match fs::write(&path, json) {
+                Ok(()) => error!("Wrote a state snapshot to '{}'.", path.display()),
+                Err(err) => error!("Unable to write a state snapshot: {err}"),
+            },
+            Err(err) => error!("Unable to serialize a state snapshot: {err}"),
+        }
+
+        previous_hook(panic_info);
+    }));
+}
+
+#[cfg(test)]
+mod state_dump_tests {
+    use std::time::Duration;
+
+    use tokio::sync::broadcast;
+
+    use crate::{
+        providers::ManualClock, state_store::JsonFileStore, topic_manager::TOPIC_EVENT_CAPACITY,
+    };
+
+    use super::*;
+
+    fn test_source(clock: Arc<ManualClock>) -> StateDumpSource {
+        StateDumpSource {
+            active_topics: Arc::new(SharedState::new(ActiveTopicsMap::new())),
+            idempotency_cache: Arc::new(SharedState::new(IdempotencyCache::default())),
+            topic_events: broadcast::channel(TOPIC_EVENT_CAPACITY).0,
+            broker_connected: Arc::new(AtomicBool::new(true)),
+            power_suspended: Arc::new(AtomicBool::new(false)),
+            clock,
+            topic_spill: None,
+        }
+    }
+
+    #[test]
+    fn snapshot_test() {
+        let clock = Arc::new(ManualClock::new());
+        let source = test_source(clock.clone());
+
+        {
+            let mut active_topics = source.active_topics.lock();
+
+            let mut active = TopicMetadata::new_at(
+                "pub_1".to_string(),
+                1,
+                Some("http://0.0.0.0:50061".to_string()),
+                clock.now(),
+            );
+            active.add_subscriber("sub_1".to_string());
+            active_topics.insert("topic-b".to_string(), active);
+
+            let mut pending = TopicMetadata::new_at("pub_2".to_string(), 0, None, clock.now());
+            pending.delete();
+            active_topics.insert("topic-a".to_string(), pending);
+        }
+        source.idempotency_cache.lock().insert(
+            "pub_1".to_string(),
+            "key".to_string(),
+            "topic-b".to_string(),
+            clock.now(),
+        );
+        let _watcher = source.topic_events.subscribe();
+
+        clock.advance(Duration::from_secs(2));
+        let actual = source.snapshot(true);
+
+        assert!(actual.complete);
+        assert!(actual.broker_connected);
+        assert!(!actual.suspended);
+        assert_eq!(vec!["topic-a".to_string()], actual.pending_deletions);
+        assert_eq!(
+            vec!["topic-a", "topic-b"],
+            actual
+                .topics
+                .iter()
+                .map(|topic| topic.topic.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        let topic_b = &actual.topics[1];
+        assert_eq!("pub_1", topic_b.publisher_id);
+        assert_eq!(1, topic_b.subscriber_count);
+        assert_eq!(vec!["sub_1".to_string()], topic_b.subscribers);
+        assert_eq!(
+            Some("http://0.0.0.0:50061".to_string()),
+            topic_b.management_callback
+        );
+
+        assert_eq!(1, actual.queues.topic_watchers);
+        assert_eq!(0, actual.queues.topic_events);
+        assert_eq!(Some(1), actual.queues.idempotency_keys);
+    }
+
+    #[test]
+    fn snapshot_without_waiting_skips_locked_state_test() {
+        let source = test_source(Arc::new(ManualClock::new()));
+        source.active_topics.lock().insert(
+            "topic-a".to_string(),
+            TopicMetadata::new("pub_1".to_string(), 0, None),
+        );
+
+        let _guard = source.active_topics.lock();
+        let actual = source.snapshot(false);
+
+        assert!(!actual.complete);
+        assert!(actual.topics.is_empty());
+        assert_eq!(Some(0), actual.queues.idempotency_keys);
+    }
+
+    #[test]
+    fn snapshot_includes_spilled_topics_test() {
+        let dir = std::env::temp_dir().join(format!("agemo-dump-spill-{}", std::process::id()));
+        let clock = Arc::new(ManualClock::new());
+        let topic_spill = Arc::new(TopicSpill::new(
+            Box::new(JsonFileStore::open(dir.clone()).unwrap()),
+            0,
+        ));
+        let source = StateDumpSource {
+            topic_spill: Some(topic_spill.clone()),
+            ..test_source(clock.clone())
+        };
+
+        {
+            let mut active_topics = source.active_topics.lock();
+            active_topics.insert(
+                "cold".to_string(),
+                TopicMetadata::new_at("pub_1".to_string(), 0, None, clock.now()),
+            );
+            topic_spill.spill_cold_topics(&mut active_topics, clock.now());
+        }
+
+        let actual = source.snapshot(true);
+        assert!(actual.complete);
+        assert_eq!(1, actual.topics.len());
+        assert_eq!("cold", actual.topics[0].topic);
+        assert_eq!("pub_1", actual.topics[0].publisher_id);
+        assert!(actual.topics[0].spilled);
+
+        // Spilled topics are not read back from disk without waiting.
+        let actual = source.snapshot(false);
+        assert!(!actual.complete);
+        assert!(actual.topics.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn snapshot_json_test() {
+        let source = test_source(Arc::new(ManualClock::new()));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&source.snapshot_json(true).unwrap()).unwrap();
+
+        assert_eq!(Some(true), json["complete"].as_bool());
+        assert!(json["topics"].as_array().unwrap().is_empty());
+        assert_eq!(Some(0), json["queues"]["topic_watchers"].as_u64());
+    }
+}
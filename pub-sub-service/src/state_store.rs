@@ -0,0 +1,408 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Storage backends for the metadata of topics persisted outside of memory.
+//!
+//! The [`StateStore`] trait abstracts over where persisted topics are kept, so that deployments
+//! can pick a backend that suits their filesystem or flash constraints. The backends are:
+//!
+//! * [`JsonFileStore`] - A JSON file per topic in a directory. Always available.
+//! * `SledStore` - A [sled](https://sled.rs) embedded database. Requires the `sled-store` feature.
+//! * `SqliteStore` - A table in a SQLite database file. Requires the `sqlite-store` feature.
+//!
+//! Every backend stores topics as JSON, so that a topic reads the same in each of them.
+
+use std::{
+    fmt::Debug,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "sqlite-store")]
+use common::shared_state::SharedState;
+
+use crate::{error::AgemoError, load_config::StateStoreKind, topic_spill::SpilledTopic};
+
+/// File extension of the files of the JSON file store.
+const JSON_FILE_EXTENSION: &str = "json";
+
+/// Name of the database file of the SQLite store.
+#[cfg(feature = "sqlite-store")]
+const SQLITE_FILE_NAME: &str = "topics.sqlite3";
+
+/// Key-value store of persisted topics, keyed by topic name.
+pub trait StateStore: Debug + Send + Sync {
+    /// Stores a topic, replacing any stored topic with the same name.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The name of the topic.
+    /// * `spilled` - The metadata of the topic.
+    fn put(&self, topic: &str, spilled: &SpilledTopic) -> Result<(), AgemoError>;
+
+    /// Returns a stored topic without removing it, or `None` if it is not stored.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The name of the topic.
+    fn get(&self, topic: &str) -> Result<Option<SpilledTopic>, AgemoError>;
+
+    /// Removes a topic from the store and returns it, or `None` if it is not stored.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The name of the topic.
+    fn take(&self, topic: &str) -> Result<Option<SpilledTopic>, AgemoError>;
+}
+
+/// Opens the store of the given kind in a directory, creating the directory if needed. Topics
+/// left over from a previous run of the service are removed, as they are no longer known.
+///
+/// # Arguments
+///
+/// * `kind` - The storage backend.
+/// * `directory` - The directory the topics are stored in.
+pub fn open_state_store(
+    kind: StateStoreKind,
+    directory: PathBuf,
+) -> Result<Box<dyn StateStore>, AgemoError> {
+    match kind {
+        StateStoreKind::JsonFile => Ok(Box::new(JsonFileStore::open(directory)?)),
+        #[cfg(feature = "sled-store")]
+        StateStoreKind::Sled => Ok(Box::new(SledStore::open(directory)?)),
+        #[cfg(feature = "sqlite-store")]
+        StateStoreKind::Sqlite => Ok(Box::new(SqliteStore::open(directory)?)),
+        #[allow(unreachable_patterns)]
+        kind => Err(AgemoError::InvalidConfiguration(format!(
+            "the '{}' state store requires the service to be built with the '{}-store' feature",
+            kind.as_str(),
+            kind.as_str()
+        ))),
+    }
+}
+
+/// Serializes a topic to JSON.
+///
+/// # Arguments
+///
+/// * `topic` - The name of the topic.
+/// * `spilled` - The metadata of the topic.
+fn encode(topic: &str, spilled: &SpilledTopic) -> Result<Vec<u8>, AgemoError> {
+    serde_json::to_vec(spilled)
+        .map_err(|err| AgemoError::Storage(format!("unable to serialize '{topic}': {err}")))
+}
+
+/// Deserializes a topic from JSON.
+///
+/// # Arguments
+///
+/// * `topic` - The name of the topic.
+/// * `json` - The serialized metadata of the topic.
+fn decode(topic: &str, json: &[u8]) -> Result<SpilledTopic, AgemoError> {
+    serde_json::from_slice(json)
+        .map_err(|err| AgemoError::Storage(format!("unable to deserialize '{topic}': {err}")))
+}
+
+/// Creates a directory and its parents if they don't exist.
+///
+/// # Arguments
+///
+/// * `directory` - The directory to create.
+fn create_directory(directory: &Path) -> Result<(), AgemoError> {
+    fs::create_dir_all(directory).map_err(|err| {
+        AgemoError::Storage(format!("unable to create '{}': {err}", directory.display()))
+    })
+}
+
+/// Store with a JSON file per topic in a directory.
+#[derive(Clone, Debug)]
+pub struct JsonFileStore {
+    directory: PathBuf,
+}
+
+impl JsonFileStore {
+    /// Opens the store in the given directory, creating the directory if needed. Files left over
+    /// from a previous run of the service are removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory the topics are stored in.
+    pub fn open(directory: PathBuf) -> Result<Self, AgemoError> {
+        create_directory(&directory)?;
+
+        let store = JsonFileStore { directory };
+        let entries = fs::read_dir(&store.directory).map_err(|err| {
+            AgemoError::Storage(format!(
+                "unable to read '{}': {err}",
+                store.directory.display()
+            ))
+        })?;
+
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path
+                .extension()
+                .is_some_and(|ext| ext == JSON_FILE_EXTENSION)
+            {
+                let _ = fs::remove_file(path);
+            }
+        }
+
+        Ok(store)
+    }
+
+    /// Returns the path of the file of a topic. Topic names are hex encoded, as they may contain
+    /// characters that are not allowed in file names.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The name of the topic.
+    fn path(&self, topic: &str) -> PathBuf {
+        let name: String = topic.bytes().map(|byte| format!("{byte:02x}")).collect();
+
+        self.directory.join(format!("{name}.{JSON_FILE_EXTENSION}"))
+    }
+}
+
+impl StateStore for JsonFileStore {
+    fn put(&self, topic: &str, spilled: &SpilledTopic) -> Result<(), AgemoError> {
+        fs::write(self.path(topic), encode(topic, spilled)?)
+            .map_err(|err| AgemoError::Storage(format!("unable to store '{topic}': {err}")))
+    }
+
+    fn get(&self, topic: &str) -> Result<Option<SpilledTopic>, AgemoError> {
+        match fs::read(self.path(topic)) {
+            Ok(json) => decode(topic, &json).map(Some),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(AgemoError::Storage(format!(
+                "unable to read '{topic}': {err}"
+            ))),
+        }
+    }
+
+    fn take(&self, topic: &str) -> Result<Option<SpilledTopic>, AgemoError> {
+        let path = self.path(topic);
+
+        let json = match fs::read(&path) {
+            Ok(json) => json,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(AgemoError::Storage(format!(
+                    "unable to restore '{topic}': {err}"
+                )))
+            }
+        };
+        let _ = fs::remove_file(&path);
+
+        decode(topic, &json).map(Some)
+    }
+}
+
+/// Store in a sled embedded database. Sled batches writes in a log, which suits flash storage
+/// better than a file per topic.
+#[cfg(feature = "sled-store")]
+#[derive(Clone, Debug)]
+pub struct SledStore {
+    db: sled::Db,
+}
+
+#[cfg(feature = "sled-store")]
+impl SledStore {
+    /// Opens the database in the given directory, creating it if needed. Topics left over from a
+    /// previous run of the service are removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory of the database.
+    pub fn open(directory: PathBuf) -> Result<Self, AgemoError> {
+        let db = sled::open(&directory).map_err(|err| {
+            AgemoError::Storage(format!("unable to open '{}': {err}", directory.display()))
+        })?;
+        db.clear().map_err(|err| {
+            AgemoError::Storage(format!("unable to clear '{}': {err}", directory.display()))
+        })?;
+
+        Ok(SledStore { db })
+    }
+}
+
+#[cfg(feature = "sled-store")]
+impl StateStore for SledStore {
+    fn put(&self, topic: &str, spilled: &SpilledTopic) -> Result<(), AgemoError> {
+        self.db
+            .insert(topic, encode(topic, spilled)?)
+            .map(|_| ())
+            .map_err(|err| AgemoError::Storage(format!("unable to store '{topic}': {err}")))
+    }
+
+    fn get(&self, topic: &str) -> Result<Option<SpilledTopic>, AgemoError> {
+        self.db
+            .get(topic)
+            .map_err(|err| AgemoError::Storage(format!("unable to read '{topic}': {err}")))?
+            .map(|json| decode(topic, &json))
+            .transpose()
+    }
+
+    fn take(&self, topic: &str) -> Result<Option<SpilledTopic>, AgemoError> {
+        self.db
+            .remove(topic)
+            .map_err(|err| AgemoError::Storage(format!("unable to restore '{topic}': {err}")))?
+            .map(|json| decode(topic, &json))
+            .transpose()
+    }
+}
+
+/// Store in a table of a SQLite database file.
+#[cfg(feature = "sqlite-store")]
+#[derive(Debug)]
+pub struct SqliteStore {
+    connection: SharedState<rusqlite::Connection>,
+}
+
+#[cfg(feature = "sqlite-store")]
+impl SqliteStore {
+    /// Opens the database file in the given directory, creating it if needed. Topics left over
+    /// from a previous run of the service are removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory of the database file.
+    pub fn open(directory: PathBuf) -> Result<Self, AgemoError> {
+        create_directory(&directory)?;
+
+        let path = directory.join(SQLITE_FILE_NAME);
+        let storage_err = |err: rusqlite::Error| {
+            AgemoError::Storage(format!("unable to open '{}': {err}", path.display()))
+        };
+
+        let connection = rusqlite::Connection::open(&path).map_err(storage_err)?;
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS topics (name TEXT PRIMARY KEY, metadata BLOB NOT NULL);
+                 DELETE FROM topics;",
+            )
+            .map_err(storage_err)?;
+
+        Ok(SqliteStore {
+            connection: SharedState::new(connection),
+        })
+    }
+}
+
+#[cfg(feature = "sqlite-store")]
+impl StateStore for SqliteStore {
+    fn put(&self, topic: &str, spilled: &SpilledTopic) -> Result<(), AgemoError> {
+        self.connection
+            .lock()
+            .execute(
+                "INSERT OR REPLACE INTO topics (name, metadata) VALUES (?1, ?2)",
+                rusqlite::params![topic, encode(topic, spilled)?],
+            )
+            .map(|_| ())
+            .map_err(|err| AgemoError::Storage(format!("unable to store '{topic}': {err}")))
+    }
+
+    fn get(&self, topic: &str) -> Result<Option<SpilledTopic>, AgemoError> {
+        use rusqlite::OptionalExtension;
+
+        self.connection
+            .lock()
+            .query_row(
+                "SELECT metadata FROM topics WHERE name = ?1",
+                [topic],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()
+            .map_err(|err| AgemoError::Storage(format!("unable to read '{topic}': {err}")))?
+            .map(|json| decode(topic, &json))
+            .transpose()
+    }
+
+    fn take(&self, topic: &str) -> Result<Option<SpilledTopic>, AgemoError> {
+        use rusqlite::OptionalExtension;
+
+        self.connection
+            .lock()
+            .query_row(
+                "DELETE FROM topics WHERE name = ?1 RETURNING metadata",
+                [topic],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()
+            .map_err(|err| AgemoError::Storage(format!("unable to restore '{topic}': {err}")))?
+            .map(|json| decode(topic, &json))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod state_store_tests {
+    use std::{process, time::Instant};
+
+    use crate::topic_manager::TopicMetadata;
+
+    use super::*;
+
+    fn store_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("agemo-store-{name}-{}", process::id()))
+    }
+
+    /// Stores and takes back topics, and checks that reopening the store forgets them.
+    fn assert_store_roundtrip(kind: StateStoreKind) {
+        let dir = store_dir(kind.as_str());
+        let store = open_state_store(kind, dir.clone()).unwrap();
+        let spilled =
+            TopicMetadata::new("pub_test".to_string(), 0, None).to_spilled(Instant::now());
+
+        store.put("vehicle/signals/topic-0", &spilled).unwrap();
+        assert_eq!(
+            Some(spilled.clone()),
+            store.get("vehicle/signals/topic-0").unwrap()
+        );
+        assert_eq!(
+            Some(spilled),
+            store.take("vehicle/signals/topic-0").unwrap()
+        );
+        assert_eq!(None, store.take("vehicle/signals/topic-0").unwrap());
+
+        // Topics stored by a previous run are forgotten.
+        store
+            .put(
+                "topic-1",
+                &TopicMetadata::new(String::new(), 0, None).to_spilled(Instant::now()),
+            )
+            .unwrap();
+        drop(store);
+        let store = open_state_store(kind, dir.clone()).unwrap();
+        assert_eq!(None, store.take("topic-1").unwrap());
+
+        drop(store);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn json_file_store_roundtrip_test() {
+        assert_store_roundtrip(StateStoreKind::JsonFile);
+    }
+
+    #[cfg(feature = "sled-store")]
+    #[test]
+    fn sled_store_roundtrip_test() {
+        assert_store_roundtrip(StateStoreKind::Sled);
+    }
+
+    #[cfg(feature = "sqlite-store")]
+    #[test]
+    fn sqlite_store_roundtrip_test() {
+        assert_store_roundtrip(StateStoreKind::Sqlite);
+    }
+
+    #[cfg(not(feature = "sled-store"))]
+    #[test]
+    fn open_state_store_requires_feature_test() {
+        let result = open_state_store(StateStoreKind::Sled, store_dir("missing-feature"));
+
+        assert!(matches!(result, Err(AgemoError::InvalidConfiguration(_))));
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Identification and authorization of the subscribers of topics.
+//!
+//! The broker connector identifies subscribers by their client id by default, and every
+//! subscription counts towards starting a topic. Where the messaging broker authenticates its
+//! clients, the connector can also identify subscribers by the identity they authenticated as,
+//! and the service can count only the subscribers authorized on a topic. A subscription attempt
+//! from any other subscriber is ignored, so it doesn't start the publisher of the topic. Several
+//! clients may share an identity, so subscribers are still counted by their client id.
+
+use common::name_policy::topic_matches_filter;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    load_config::SubscriberAclRule,
+    pubsub_connector::{MonitorMessage, PubSubAction},
+};
+
+/// Subscriber id of a rule that authorizes every identified subscriber.
+pub const ANY_SUBSCRIBER: &str = "*";
+
+/// How the broker connector identifies the subscriber of a subscribe or unsubscribe update.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriberIdentification {
+    /// Subscribers are identified by the client id they connected with.
+    #[default]
+    ClientId,
+    /// Subscribers are identified by the identity they authenticated as, such as their username.
+    /// Subscribers whose identity is unknown are reported without an identity.
+    Identity,
+}
+
+/// The subscribers authorized on topics.
+#[derive(Clone, Debug, Default)]
+pub struct SubscriberAcl {
+    /// How the subscribers named by the rules are identified.
+    identification: SubscriberIdentification,
+    /// The rules authorizing subscribers on topics.
+    rules: Vec<SubscriberAclRule>,
+}
+
+impl SubscriberAcl {
+    /// Creates a new SubscriberAcl.
+    ///
+    /// # Arguments
+    ///
+    /// * `identification` - How the subscribers named by the rules are identified.
+    /// * `rules` - The rules authorizing subscribers on topics.
+    pub fn new(identification: SubscriberIdentification, rules: &[SubscriberAclRule]) -> Self {
+        SubscriberAcl {
+            identification,
+            rules: rules.to_vec(),
+        }
+    }
+
+    /// Returns whether a subscriber is authorized on a topic. Subscribers without an id are never
+    /// authorized.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscriber` - The id of the subscriber, if known.
+    /// * `topic` - The topic.
+    pub fn is_authorized(&self, subscriber: Option<&str>, topic: &str) -> bool {
+        let Some(subscriber) = subscriber else {
+            return false;
+        };
+
+        self.rules.iter().any(|rule| {
+            (rule.subscriber == ANY_SUBSCRIBER || rule.subscriber == subscriber)
+                && rule
+                    .topics
+                    .iter()
+                    .any(|filter| topic_matches_filter(filter, topic))
+        })
+    }
+
+    /// Returns whether an update counts towards its topic. Subscribes and unsubscribes only count
+    /// if their subscriber is authorized on the topic, and every other update counts.
+    ///
+    /// # Arguments
+    ///
+    /// * `msg` - The update.
+    pub fn admits(&self, msg: &MonitorMessage) -> bool {
+        let subscriber = match self.identification {
+            SubscriberIdentification::ClientId => msg.client_id.as_deref(),
+            SubscriberIdentification::Identity => msg.identity.as_deref(),
+        };
+
+        match msg.action {
+            PubSubAction::Subscribe | PubSubAction::Unsubscribe => {
+                self.is_authorized(subscriber, &msg.context)
+            }
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod subscriber_acl_tests {
+    use super::*;
+
+    fn acl(identification: SubscriberIdentification) -> SubscriberAcl {
+        SubscriberAcl::new(
+            identification,
+            &[
+                SubscriberAclRule {
+                    subscriber: "hvac".to_string(),
+                    topics: vec!["cabin/#".to_string()],
+                },
+                SubscriberAclRule {
+                    subscriber: ANY_SUBSCRIBER.to_string(),
+                    topics: vec!["public/+".to_string()],
+                },
+            ],
+        )
+    }
+
+    #[test]
+    fn is_authorized_test() {
+        let acl = acl(SubscriberIdentification::ClientId);
+
+        assert!(acl.is_authorized(Some("hvac"), "cabin/temperature"));
+        assert!(!acl.is_authorized(Some("infotainment"), "cabin/temperature"));
+        assert!(acl.is_authorized(Some("infotainment"), "public/speed"));
+        assert!(!acl.is_authorized(None, "public/speed"));
+        assert!(!acl.is_authorized(Some("hvac"), "powertrain/rpm"));
+        assert!(!SubscriberAcl::default().is_authorized(Some("hvac"), "cabin/temperature"));
+    }
+
+    #[test]
+    fn admits_test() {
+        let acl = acl(SubscriberIdentification::ClientId);
+        let msg = |action, client_id: Option<&str>| MonitorMessage {
+            context: "cabin/temperature".to_string(),
+            action,
+            client_id: client_id.map(str::to_string),
+            identity: None,
+        };
+
+        assert!(acl.admits(&msg(PubSubAction::Subscribe, Some("hvac"))));
+        assert!(!acl.admits(&msg(PubSubAction::Subscribe, Some("infotainment"))));
+        assert!(!acl.admits(&msg(PubSubAction::Unsubscribe, None)));
+        assert!(acl.admits(&msg(PubSubAction::Timeout, None)));
+    }
+
+    #[test]
+    fn admits_by_identity_test() {
+        let acl = acl(SubscriberIdentification::Identity);
+        let msg = |client_id: &str, identity: Option<&str>| MonitorMessage {
+            context: "cabin/temperature".to_string(),
+            action: PubSubAction::Subscribe,
+            client_id: Some(client_id.to_string()),
+            identity: identity.map(str::to_string),
+        };
+
+        // Sessions sharing an identity are each authorized by it.
+        assert!(acl.admits(&msg("hvac_1", Some("hvac"))));
+        assert!(acl.admits(&msg("hvac_2", Some("hvac"))));
+        assert!(!acl.admits(&msg("hvac", None)));
+        assert!(!acl.admits(&msg("hvac", Some("infotainment"))));
+    }
+}
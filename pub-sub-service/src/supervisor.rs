@@ -0,0 +1,373 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Supervision of the background tasks of the service.
+//!
+//! A panic in a background task such as the topic monitor, the topic cleanup or the broker
+//! connector would otherwise silently stop that subsystem while the gRPC server keeps running.
+//! The [`Supervisor`] restarts a task that panicked with an exponential backoff, and reports the
+//! service as degraded until the restarted task has run for a while without panicking. A task can
+//! also end with a [`TaskExit`] asking to stay stopped.
+
+use std::{any::Any, collections::HashMap, future::Future, process, sync::Arc, time::Duration};
+
+use common::shared_state::SharedState;
+use log::{error, info, warn};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+/// Delay before a task that panicked is first restarted.
+pub const INITIAL_RESTART_BACKOFF: Duration = Duration::from_secs(1);
+/// Maximum delay before a task that keeps panicking is restarted.
+pub const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(60);
+/// How long a restarted task must run without panicking to be considered recovered.
+pub const STABLE_RUN_TIME: Duration = Duration::from_secs(30);
+
+/// Health of a supervised task.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TaskHealth {
+    /// The number of times the task panicked.
+    pub panics: u64,
+    /// Whether the task panicked and has not yet run for the stable run time since.
+    pub degraded: bool,
+    /// The message of the last panic of the task.
+    pub last_panic: Option<String>,
+    /// The number of times the task failed without panicking.
+    pub failures: u64,
+    /// The reason of the last failure of the task.
+    pub last_failure: Option<String>,
+    /// Whether the task failed and was not restarted.
+    pub stopped: bool,
+}
+
+/// How a supervised task ended.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TaskExit {
+    /// The task finished its work and is not restarted.
+    Finished,
+    /// The task failed and is not restarted. It is reported as degraded until the service
+    /// restarts.
+    Stop(String),
+}
+
+impl From<()> for TaskExit {
+    fn from(_: ()) -> Self {
+        TaskExit::Finished
+    }
+}
+
+/// What a background task does when a channel it receives requests on is closed. The senders of
+/// a closed channel are gone, so restarting the task alone would only find the channel closed
+/// again.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelFailurePolicy {
+    /// The service exits, so that the service manager restarts it.
+    #[default]
+    Exit,
+    /// The task stops.
+    Stop,
+}
+
+impl ChannelFailurePolicy {
+    /// Returns how a task ends after a channel it receives requests on closed. Exits the service
+    /// if the policy is [`ChannelFailurePolicy::Exit`].
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Why the channel closed.
+    pub fn task_exit(self, reason: String) -> TaskExit {
+        match self {
+            ChannelFailurePolicy::Stop => TaskExit::Stop(reason),
+            ChannelFailurePolicy::Exit => {
+                error!("{reason}. Exiting so that the service is restarted.");
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// Restarts background tasks that panic and tracks their health.
+#[derive(Clone, Debug)]
+pub struct Supervisor {
+    /// The health of each supervised task, by task name.
+    tasks: Arc<SharedState<HashMap<String, TaskHealth>>>,
+    /// Delay before a task that panicked is first restarted.
+    initial_backoff: Duration,
+    /// Maximum delay before a task that keeps panicking is restarted.
+    max_backoff: Duration,
+    /// How long a restarted task must run without panicking to be considered recovered.
+    stable_run_time: Duration,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the message of a panic, if it has one.
+///
+/// # Arguments
+///
+/// * `payload` - The payload the task panicked with.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+impl Supervisor {
+    /// Instantiates a new Supervisor with the default restart backoff.
+    pub fn new() -> Self {
+        Self::with_backoff(
+            INITIAL_RESTART_BACKOFF,
+            MAX_RESTART_BACKOFF,
+            STABLE_RUN_TIME,
+        )
+    }
+
+    /// Instantiates a new Supervisor with the given restart backoff.
+    ///
+    /// # Arguments
+    ///
+    /// * `initial_backoff` - Delay before a task that panicked is first restarted. The delay
+    ///                       doubles each time the task panics again before it recovered.
+    /// * `max_backoff` - Maximum delay before a task that keeps panicking is restarted.
+    /// * `stable_run_time` - How long a restarted task must run without panicking to be
+    ///                       considered recovered.
+    pub fn with_backoff(
+        initial_backoff: Duration,
+        max_backoff: Duration,
+        stable_run_time: Duration,
+    ) -> Self {
+        Supervisor {
+            tasks: Arc::new(SharedState::new(HashMap::new())),
+            initial_backoff,
+            max_backoff,
+            stable_run_time,
+        }
+    }
+
+    /// Returns whether any supervised task is degraded.
+    pub fn is_degraded(&self) -> bool {
+        self.tasks.lock().values().any(|health| health.degraded)
+    }
+
+    /// Returns the health of each supervised task, sorted by task name.
+    pub fn task_health(&self) -> Vec<(String, TaskHealth)> {
+        let mut tasks: Vec<(String, TaskHealth)> = self
+            .tasks
+            .lock()
+            .iter()
+            .map(|(name, health)| (name.clone(), health.clone()))
+            .collect();
+        tasks.sort_by(|a, b| a.0.cmp(&b.0));
+
+        tasks
+    }
+
+    /// Updates the health of a task.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the task.
+    /// * `update` - The update to apply to the health of the task.
+    fn update_health(&self, name: &str, update: impl FnOnce(&mut TaskHealth)) {
+        let mut tasks = self.tasks.lock();
+        update(tasks.entry(name.to_string()).or_default());
+    }
+
+    /// Spawns a task, and spawns it again with a backoff each time it panics. Supervision stops
+    /// once the task finishes, stops or is cancelled.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the task, used in logs and health reports.
+    /// * `make_task` - Creates the task to run. Called again for every restart, so any state the
+    ///                 task needs to survive a restart must be shared with the closure.
+    pub fn supervise<F, Fut>(&self, name: &str, mut make_task: F) -> JoinHandle<()>
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future + Send + 'static,
+        Fut::Output: Into<TaskExit>,
+    {
+        let supervisor = self.clone();
+        let name = name.to_string();
+        supervisor.update_health(&name, |_| {});
+
+        tokio::spawn(async move {
+            let mut backoff = supervisor.initial_backoff;
+
+            loop {
+                let task = make_task();
+                let mut handle = tokio::spawn(async move { Into::<TaskExit>::into(task.await) });
+
+                let result =
+                    match tokio::time::timeout(supervisor.stable_run_time, &mut handle).await {
+                        Ok(result) => result,
+                        Err(_) => {
+                            // The task ran long enough to be considered recovered.
+                            supervisor.update_health(&name, |health| {
+                                if health.degraded {
+                                    info!("Task '{name}' recovered.");
+                                    health.degraded = false;
+                                }
+                            });
+                            backoff = supervisor.initial_backoff;
+
+                            handle.await
+                        }
+                    };
+
+                match result {
+                    Ok(TaskExit::Finished) => {
+                        info!("Task '{name}' finished.");
+                        break;
+                    }
+                    Ok(TaskExit::Stop(reason)) => {
+                        error!("Task '{name}' failed: {reason}. Not restarting it.");
+
+                        supervisor.update_health(&name, |health| {
+                            health.failures += 1;
+                            health.degraded = true;
+                            health.last_failure = Some(reason);
+                            health.stopped = true;
+                        });
+                        break;
+                    }
+                    Err(err) if err.is_panic() => {
+                        let message = panic_message(err.into_panic());
+                        error!("Task '{name}' panicked: {message}. Restarting in {backoff:?}.");
+
+                        supervisor.update_health(&name, |health| {
+                            health.panics += 1;
+                            health.degraded = true;
+                            health.last_panic = Some(message);
+                        });
+
+                        tokio::time::sleep(backoff).await;
+                        backoff = (backoff * 2).min(supervisor.max_backoff);
+                    }
+                    Err(_) => {
+                        warn!("Task '{name}' was cancelled.");
+                        break;
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod supervisor_tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn test_supervisor() -> Supervisor {
+        Supervisor::with_backoff(
+            Duration::from_millis(10),
+            Duration::from_millis(40),
+            Duration::from_millis(200),
+        )
+    }
+
+    #[test]
+    fn panic_message_test() {
+        assert_eq!("static", panic_message(Box::new("static")));
+        assert_eq!("owned", panic_message(Box::new("owned".to_string())));
+        assert_eq!("unknown panic", panic_message(Box::new(5)));
+    }
+
+    #[tokio::test]
+    async fn supervise_finished_task_test() {
+        let supervisor = test_supervisor();
+
+        supervisor.supervise("task", || async {}).await.unwrap();
+
+        assert!(!supervisor.is_degraded());
+        assert_eq!(
+            vec![("task".to_string(), TaskHealth::default())],
+            supervisor.task_health()
+        );
+    }
+
+    #[tokio::test]
+    async fn supervise_restarts_panicked_task_test() {
+        let supervisor = test_supervisor();
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let task_runs = runs.clone();
+        let handle = supervisor.supervise("task", move || {
+            let runs = task_runs.clone();
+            async move {
+                if runs.fetch_add(1, Ordering::SeqCst) < 2 {
+                    panic!("task failed");
+                }
+
+                tokio::time::sleep(Duration::from_millis(400)).await;
+            }
+        });
+
+        // Both panics happen within the initial backoff and its doubling.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(3, runs.load(Ordering::SeqCst));
+        assert!(supervisor.is_degraded());
+
+        let health = &supervisor.task_health()[0].1;
+        assert_eq!(2, health.panics);
+        assert_eq!(Some("task failed".to_string()), health.last_panic);
+
+        // The task recovers once it has run for the stable run time.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!supervisor.is_degraded());
+        assert_eq!(2, supervisor.task_health()[0].1.panics);
+
+        handle.await.unwrap();
+        assert_eq!(3, runs.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn supervise_stopped_task_test() {
+        let supervisor = test_supervisor();
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let task_runs = runs.clone();
+        supervisor
+            .supervise("task", move || {
+                task_runs.fetch_add(1, Ordering::SeqCst);
+                async { ChannelFailurePolicy::Stop.task_exit("channel closed".to_string()) }
+            })
+            .await
+            .unwrap();
+
+        // The task is not restarted, and stays degraded.
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(1, runs.load(Ordering::SeqCst));
+        assert!(supervisor.is_degraded());
+
+        let health = &supervisor.task_health()[0].1;
+        assert_eq!(1, health.failures);
+        assert!(health.stopped);
+    }
+
+    #[test]
+    fn channel_failure_policy_test() {
+        assert_eq!(ChannelFailurePolicy::Exit, ChannelFailurePolicy::default());
+        assert_eq!(
+            TaskExit::Stop("closed".to_string()),
+            ChannelFailurePolicy::Stop.task_exit("closed".to_string())
+        );
+        assert_eq!(
+            ChannelFailurePolicy::Stop,
+            serde_json::from_str::<ChannelFailurePolicy>("\"stop\"").unwrap()
+        );
+    }
+}
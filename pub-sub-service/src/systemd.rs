@@ -0,0 +1,228 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Integration with the systemd service manager.
+//!
+//! When the service runs as a systemd unit with `Type=notify`, it reports `READY=1` once it is
+//! connected to the messaging broker and registered with Chariott, pings the watchdog while its
+//! background tasks are running, and reports `STOPPING=1` when it shuts down. Notifications are
+//! sent over the datagram socket that systemd passes in `NOTIFY_SOCKET`, and are skipped if the
+//! service is not started by systemd.
+
+use std::{
+    env, io,
+    os::unix::net::{SocketAddr, UnixDatagram},
+    process,
+    time::Duration,
+};
+
+use log::{debug, info, warn};
+use tokio::signal::unix::{signal, SignalKind};
+
+/// Environment variable that systemd passes the notification socket in.
+const NOTIFY_SOCKET_ENV: &str = "NOTIFY_SOCKET";
+/// Environment variable that systemd passes the watchdog timeout in, in microseconds.
+const WATCHDOG_USEC_ENV: &str = "WATCHDOG_USEC";
+/// Environment variable that systemd passes the pid of the process expected to ping the watchdog
+/// in.
+const WATCHDOG_PID_ENV: &str = "WATCHDOG_PID";
+/// How often readiness is checked while waiting to report the service as ready.
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Sends a notification to systemd. Returns false without sending anything if the service was
+/// not started by systemd.
+///
+/// # Arguments
+///
+/// * `state` - The newline-separated assignments to send, such as `READY=1`.
+pub fn notify(state: &str) -> io::Result<bool> {
+    let Some(socket) = env::var_os(NOTIFY_SOCKET_ENV) else {
+        return Ok(false);
+    };
+
+    send_notification(&socket.to_string_lossy(), state)?;
+
+    Ok(true)
+}
+
+/// Sends a notification to the given systemd notification socket.
+///
+/// # Arguments
+///
+/// * `socket` - The path of the socket, or its name prefixed with `@` for an abstract socket.
+/// * `state` - The newline-separated assignments to send.
+fn send_notification(socket: &str, state: &str) -> io::Result<()> {
+    let addr = match socket.strip_prefix('@') {
+        Some(name) => abstract_socket_addr(name)?,
+        None => SocketAddr::from_pathname(socket)?,
+    };
+
+    let datagram = UnixDatagram::unbound()?;
+    datagram.send_to_addr(state.as_bytes(), &addr)?;
+
+    Ok(())
+}
+
+/// Returns the address of an abstract socket. Abstract sockets are only supported on Linux.
+///
+/// # Arguments
+///
+/// * `name` - The name of the socket, without the leading `@`.
+#[cfg(target_os = "linux")]
+fn abstract_socket_addr(name: &str) -> io::Result<SocketAddr> {
+    use std::os::linux::net::SocketAddrExt;
+
+    SocketAddr::from_abstract_name(name)
+}
+
+/// Returns the address of an abstract socket. Abstract sockets are only supported on Linux.
+///
+/// # Arguments
+///
+/// * `_name` - The name of the socket, without the leading `@`.
+#[cfg(not(target_os = "linux"))]
+fn abstract_socket_addr(_name: &str) -> io::Result<SocketAddr> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "abstract sockets are only supported on Linux",
+    ))
+}
+
+/// Returns the watchdog timeout that systemd expects the service to ping the watchdog within, or
+/// `None` if the watchdog is not enabled for this process.
+pub fn watchdog_timeout() -> Option<Duration> {
+    parse_watchdog_timeout(
+        env::var(WATCHDOG_USEC_ENV).ok().as_deref(),
+        env::var(WATCHDOG_PID_ENV).ok().as_deref(),
+        process::id(),
+    )
+}
+
+/// Parses the watchdog timeout from the environment systemd passes to the service.
+///
+/// # Arguments
+///
+/// * `usec` - The value of `WATCHDOG_USEC`, if set.
+/// * `pid` - The value of `WATCHDOG_PID`, if set.
+/// * `own_pid` - The pid of this process.
+fn parse_watchdog_timeout(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    // The watchdog is meant for another process if the pid doesn't match.
+    if let Some(pid) = pid {
+        if pid.parse::<u32>().ok()? != own_pid {
+            return None;
+        }
+    }
+
+    let usec = usec?.parse::<u64>().ok().filter(|usec| *usec > 0)?;
+
+    Some(Duration::from_micros(usec))
+}
+
+/// Pings the systemd watchdog at half the watchdog timeout, so that a ping is never late. Runs
+/// until the notification socket goes away.
+///
+/// # Arguments
+///
+/// * `timeout` - The watchdog timeout.
+pub async fn run_watchdog(timeout: Duration) {
+    let interval = timeout / 2;
+    info!("Pinging the systemd watchdog every {interval:?}.");
+
+    loop {
+        tokio::time::sleep(interval).await;
+
+        match notify("WATCHDOG=1") {
+            Ok(true) => debug!("Pinged the systemd watchdog."),
+            Ok(false) => break,
+            Err(err) => warn!("Unable to ping the systemd watchdog: {err}"),
+        }
+    }
+}
+
+/// Reports the service as ready to systemd once the given readiness check passes.
+///
+/// # Arguments
+///
+/// * `is_ready` - Returns whether the service is ready, checked periodically.
+pub async fn notify_ready_when(is_ready: impl Fn() -> bool) {
+    while !is_ready() {
+        tokio::time::sleep(READY_POLL_INTERVAL).await;
+    }
+
+    match notify("READY=1\nSTATUS=Serving") {
+        Ok(true) => info!("Reported the service as ready to systemd."),
+        Ok(false) => {}
+        Err(err) => warn!("Unable to report the service as ready to systemd: {err}"),
+    }
+}
+
+/// Reports to systemd that the service is shutting down.
+pub fn notify_stopping() {
+    if let Err(err) = notify("STOPPING=1") {
+        warn!("Unable to report the service as stopping to systemd: {err}");
+    }
+}
+
+/// Waits until the service is asked to stop, by the SIGTERM systemd sends to stop the unit or by
+/// Ctrl+C, and reports to systemd that the service is stopping.
+pub async fn shutdown_signal() {
+    match signal(SignalKind::terminate()) {
+        Ok(mut sigterm) => {
+            tokio::select! {
+                _ = sigterm.recv() => {}
+                _ = tokio::signal::ctrl_c() => {}
+            }
+        }
+        Err(err) => {
+            warn!("Unable to listen for SIGTERM: {err}");
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+
+    info!("Shutting down...");
+    notify_stopping();
+}
+
+#[cfg(test)]
+mod systemd_tests {
+    use super::*;
+
+    #[test]
+    fn send_notification_test() {
+        let dir = env::temp_dir().join(format!("agemo-systemd-{}", process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notify.sock");
+        let _ = std::fs::remove_file(&path);
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        send_notification(path.to_str().unwrap(), "READY=1").unwrap();
+
+        let mut buf = [0u8; 64];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(b"READY=1", &buf[..len]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parse_watchdog_timeout_test() {
+        assert_eq!(
+            Some(Duration::from_secs(10)),
+            parse_watchdog_timeout(Some("10000000"), None, 42)
+        );
+        assert_eq!(
+            Some(Duration::from_secs(10)),
+            parse_watchdog_timeout(Some("10000000"), Some("42"), 42)
+        );
+
+        // The watchdog is meant for another process.
+        assert_eq!(
+            None,
+            parse_watchdog_timeout(Some("10000000"), Some("7"), 42)
+        );
+        assert_eq!(None, parse_watchdog_timeout(Some("0"), None, 42));
+        assert_eq!(None, parse_watchdog_timeout(Some("soon"), None, 42));
+        assert_eq!(None, parse_watchdog_timeout(None, None, 42));
+    }
+}
@@ -8,18 +8,46 @@
 //! with notifications to allow the publisher to make decisions on a topic that it is publishing to.
 
 use std::{
-    collections::{hash_map::Entry::Vacant, HashMap},
-    sync::{mpsc, Arc, Mutex},
-    time::{Duration, Instant},
+    collections::{hash_map::Entry::Vacant, HashMap, HashSet, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
-use log::{error, info, warn};
-use proto::publisher::v1::{
-    publisher_callback_client::PublisherCallbackClient, ManageTopicRequest,
+use common::{
+    action_casing::ActionCasing, payload_compression::PayloadCompression,
+    shared_state::SharedState, topic_class::TopicClass,
 };
+use futures::{stream, StreamExt};
+use log::{error, info, warn};
+use proto::publisher::v1::ManageTopicRequest;
+use strum_macros::Display;
+use tokio::sync::broadcast;
 use tonic::Request;
+use uuid::Uuid;
 
-use crate::pubsub_connector::{MonitorMessage, PubSubAction};
+#[cfg(feature = "alloc-profiling")]
+use crate::alloc_profile::{self, Subsystem};
+#[cfg(feature = "fault-injection")]
+use crate::fault_injection::FaultInjector;
+use crate::{
+    action_priority::PriorityReceiver,
+    callback_breaker::{BreakerDecision, CallbackBreakers, PublisherCallbacks},
+    callback_security::{self, CallbackClientConfig},
+    consumer_group::ConsumerGroup,
+    error::AgemoError,
+    grpc_compression::GrpcCompressionKind,
+    power,
+    providers::{Clock, SystemClock},
+    pubsub_connector::{DeletionMessage, DeletionMessages, MonitorMessage, PubSubAction},
+    state_dump::StateDumpSource,
+    subscriber_acl::SubscriberAcl,
+    supervisor::Supervisor,
+    topic_name_codec::TopicNameCodec,
+    topic_spill::{duration_ms, SpilledTopic, TopicSpill},
+};
 
 /// Metadata relevant to a dynamic topic.
 #[derive(Clone, Debug, PartialEq)]
@@ -29,9 +57,32 @@ pub struct TopicMetadata {
     /// The number of subscribers on a topic.
     pub count: i32,
     deleted: bool,
+    created_at: Instant,
     last_action: Instant,
+    idle_since: Option<Instant>,
     /// Callback uri information for the publisher.
     pub management_callback: Option<String>,
+    subscribers: HashSet<String>,
+    leases: HashMap<String, Instant>,
+    consumer_group: Option<ConsumerGroup>,
+    request_topic: Option<String>,
+    response_topics: HashMap<String, String>,
+    pre_warm: bool,
+    warm: bool,
+    notify_subscriber_joined: bool,
+    sequence_epoch: Option<u64>,
+    topic_class: TopicClass,
+    payload_compression: PayloadCompression,
+    topic_template: Option<String>,
+    subject: Option<String>,
+    standby_client_ids: Vec<String>,
+    deletion_message: DeletionMessage,
+    tags: Vec<String>,
+    idle_timeout: Duration,
+    started_at: Option<Instant>,
+    lifecycle_epoch: u64,
+    stop_due: Option<Instant>,
+    action_history: VecDeque<ActionRecord>,
 }
 
 impl TopicMetadata {
@@ -43,12 +94,52 @@ impl TopicMetadata {
     /// * `count` - The initial number of subscriptions on the topic.
     /// * `management_cb` - Callback uri for the publisher that created the topic.
     pub fn new(client_id: String, count: i32, management_cb: Option<String>) -> Self {
+        Self::new_at(client_id, count, management_cb, Instant::now())
+    }
+
+    /// Creates a new TopicMetadata instance with the last action set to the given [`Instant`].
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - The publisher's id.
+    /// * `count` - The initial number of subscriptions on the topic.
+    /// * `management_cb` - Callback uri for the publisher that created the topic.
+    /// * `now` - The time the topic was created.
+    pub fn new_at(
+        client_id: String,
+        count: i32,
+        management_cb: Option<String>,
+        now: Instant,
+    ) -> Self {
         TopicMetadata {
             client_id,
             count,
             deleted: false,
-            last_action: Instant::now(),
+            created_at: now,
+            last_action: now,
+            idle_since: (count <= 0).then_some(now),
             management_callback: management_cb,
+            subscribers: HashSet::new(),
+            leases: HashMap::new(),
+            consumer_group: None,
+            request_topic: None,
+            response_topics: HashMap::new(),
+            pre_warm: false,
+            warm: false,
+            notify_subscriber_joined: false,
+            sequence_epoch: None,
+            topic_class: TopicClass::default(),
+            payload_compression: PayloadCompression::default(),
+            topic_template: None,
+            subject: None,
+            standby_client_ids: Vec::new(),
+            deletion_message: DeletionMessage::Default,
+            tags: Vec::new(),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            started_at: None,
+            lifecycle_epoch: 0,
+            stop_due: None,
+            action_history: VecDeque::new(),
         }
     }
 
@@ -67,695 +158,5146 @@ impl TopicMetadata {
         self.deleted
     }
 
-    /// Resets the last action to the current [`Instant`].
-    pub fn reset_timeout(&mut self) {
-        self.last_action = Instant::now();
+    /// Resets the last action to the given [`Instant`].
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time of the latest action on the topic.
+    pub fn reset_timeout_at(&mut self, now: Instant) {
+        self.last_action = now;
     }
 
     /// Marks the topic for deletion.
     pub fn delete(&mut self) {
         self.deleted = true;
     }
-}
 
-/// Alias for a HashMap where the key is the topic name as a string
-/// and the value is the [`TopicMetadata`].
-pub type ActiveTopicsMap = HashMap<String, TopicMetadata>;
+    /// Marks the topic as idle from the given [`Instant`] if it is not already idle.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time the topic lost its last subscriber.
+    pub fn mark_idle_at(&mut self, now: Instant) {
+        self.idle_since.get_or_insert(now);
+    }
 
-/// Associates a topic with the publisher uri that is providing the topic updates.
-#[derive(Debug, PartialEq)]
-pub struct TopicManagementInfo {
-    topic: String,
-    uri: String,
-}
+    /// Marks the topic as having active subscribers.
+    pub fn mark_active(&mut self) {
+        self.idle_since = None;
+    }
 
-impl TopicManagementInfo {
-    /// Creates a new TopicManagementInfo instance.
+    /// Returns how long the topic has been without subscribers as of the given [`Instant`].
     ///
     /// # Arguments
     ///
-    /// * `topic` - The topic name.
-    /// * `uri` - The management uri for the topic.
-    pub fn new(topic: String, uri: String) -> Self {
-        TopicManagementInfo { topic, uri }
+    /// * `now` - The time to measure the idle duration at.
+    pub fn idle_duration(&self, now: Instant) -> Duration {
+        self.idle_since
+            .map(|idle_since| now.saturating_duration_since(idle_since))
+            .unwrap_or_default()
     }
-}
 
-/// Enum that is used to describe an action to take on a topic with the relevant topic information.
-#[derive(Debug, PartialEq)]
-pub enum TopicAction {
-    /// Start enum.
-    Start(TopicManagementInfo),
-    /// Stop enum.
-    Stop(TopicManagementInfo),
-    /// Delete enum.
-    Delete(TopicManagementInfo),
-}
+    /// Returns whether the topic is a placeholder, created by a subscription to a topic that no
+    /// publisher has created.
+    pub fn is_placeholder(&self) -> bool {
+        self.client_id.is_empty()
+            && self.management_callback.is_none()
+            && self.request_topic.is_none()
+    }
 
-/// Structure that has metadata for a given action on a topic, with a management uri to
-/// provide the update to.
-pub struct TopicActionMetadata {
-    /// Topic that the action is happening to.
-    pub topic: String,
-    /// Management uri for the publisher.
-    pub uri: String,
-    /// Action on the topic, represented by [`TopicAction`].
-    pub action: String,
-}
+    /// Returns how long the topic has existed as of the given [`Instant`].
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time to measure the age at.
+    pub fn age(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.created_at)
+    }
 
-impl TopicActionMetadata {
-    /// Creates a new TopicActionMetadata.
+    /// Returns whether the topic is cold: it has no subscribers, its publisher is stopped and
+    /// nothing is pending on it, so it can be spilled to disk.
+    pub fn is_cold(&self) -> bool {
+        !self.deleted
+            && self.count <= 0
+            && self.idle_since.is_some()
+            && self.subscribers.is_empty()
+            && self.leases.is_empty()
+            && self
+                .consumer_group
+                .as_ref()
+                .map_or(true, ConsumerGroup::is_empty)
+            && self.request_topic.is_none()
+            && self.response_topics.is_empty()
+            && !self.warm
+            && self.started_at.is_none()
+            && self.stop_due.is_none()
+            && !self.is_placeholder()
+    }
+
+    /// Converts the cold topic to its spilled form, with times relative to the given [`Instant`].
     ///
     /// # Arguments
     ///
-    /// * `action` - An action to convert to metadata.
-    pub fn new(action: TopicAction) -> Self {
-        match action {
-            TopicAction::Start(info) => TopicActionMetadata {
-                topic: info.topic,
-                uri: info.uri,
-                action: "START".to_string(),
-            },
-            TopicAction::Stop(info) => TopicActionMetadata {
-                topic: info.topic,
-                uri: info.uri,
-                action: "STOP".to_string(),
-            },
-            TopicAction::Delete(info) => TopicActionMetadata {
-                topic: info.topic,
-                uri: info.uri,
-                action: "DELETE".to_string(),
-            },
+    /// * `now` - The time the topic is spilled at.
+    pub fn to_spilled(&self, now: Instant) -> SpilledTopic {
+        SpilledTopic {
+            client_id: self.client_id.clone(),
+            management_callback: self.management_callback.clone(),
+            age_ms: duration_ms(self.age(now)),
+            since_last_action_ms: duration_ms(now.saturating_duration_since(self.last_action)),
+            idle_ms: duration_ms(self.idle_duration(now)),
+            single_consumer: self.consumer_group.is_some(),
+            pre_warm: self.pre_warm,
+            notify_subscriber_joined: self.notify_subscriber_joined,
+            sequence_epoch: self.sequence_epoch,
+            lifecycle_epoch: self.lifecycle_epoch,
+            topic_class: self.topic_class.to_proto(),
+            payload_compression: self.payload_compression.to_proto(),
+            topic_template: self.topic_template.clone(),
+            subject: self.subject.clone(),
+            standby_client_ids: self.standby_client_ids.clone(),
+            deletion_message: self.deletion_message.clone(),
+            tags: self.tags.clone(),
+            idle_timeout_ms: duration_ms(self.idle_timeout),
         }
     }
-}
 
-/// Handles the management of dynamic topics based on actions on the topic.
-///
-/// This structure handles the management logic of dynamic topics. It processes actions from the
-/// broker connector and from creation and deletion requests from publishers.
-pub struct TopicManager {
-    active_topics: Arc<Mutex<ActiveTopicsMap>>,
-}
+    /// Restores a cold topic from its spilled form. The action history of the topic is not
+    /// restored.
+    ///
+    /// # Arguments
+    ///
+    /// * `spilled` - The spilled form of the topic.
+    /// * `spilled_at` - The time the topic was spilled at.
+    pub fn from_spilled(spilled: SpilledTopic, spilled_at: Instant) -> Self {
+        let before = |ms: u64| {
+            spilled_at
+                .checked_sub(Duration::from_millis(ms))
+                .unwrap_or(spilled_at)
+        };
 
-impl Default for TopicManager {
-    fn default() -> Self {
-        Self::new()
+        let mut metadata = Self::new_at(
+            spilled.client_id,
+            0,
+            spilled.management_callback,
+            before(spilled.age_ms),
+        );
+        metadata.last_action = before(spilled.since_last_action_ms);
+        metadata.idle_since = Some(before(spilled.idle_ms));
+        if spilled.single_consumer {
+            metadata.enable_consumer_group();
+        }
+        metadata.pre_warm = spilled.pre_warm;
+        metadata.notify_subscriber_joined = spilled.notify_subscriber_joined;
+        metadata.sequence_epoch = spilled.sequence_epoch;
+        metadata.lifecycle_epoch = spilled.lifecycle_epoch;
+        metadata.topic_class = TopicClass::from_proto(spilled.topic_class).unwrap_or_default();
+        metadata.payload_compression =
+            PayloadCompression::from_proto(spilled.payload_compression).unwrap_or_default();
+        metadata.topic_template = spilled.topic_template;
+        metadata.subject = spilled.subject;
+        metadata.standby_client_ids = spilled.standby_client_ids;
+        metadata.deletion_message = spilled.deletion_message;
+        metadata.tags = spilled.tags;
+        metadata.idle_timeout = Duration::from_millis(spilled.idle_timeout_ms);
+
+        metadata
     }
-}
 
-impl TopicManager {
-    /// Instantiates a new TopicManager.
-    pub fn new() -> Self {
-        let active_topics = Arc::new(Mutex::new(ActiveTopicsMap::new()));
+    /// Records a known subscriber of the topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscriber_id` - The client id of the subscriber.
+    pub fn add_subscriber(&mut self, subscriber_id: String) {
+        self.subscribers.insert(subscriber_id);
+    }
 
-        TopicManager { active_topics }
+    /// Removes a known subscriber of the topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscriber_id` - The client id of the subscriber.
+    pub fn remove_subscriber(&mut self, subscriber_id: &str) {
+        self.subscribers.remove(subscriber_id);
     }
 
-    /// Returns a handle that points to the active topics list that tracks current known dynamic
-    /// topics.
-    pub fn get_active_topics_handle(&self) -> Arc<Mutex<ActiveTopicsMap>> {
-        self.active_topics.clone()
+    /// Returns the known subscribers of the topic.
+    pub fn subscribers(&self) -> impl Iterator<Item = &String> {
+        self.subscribers.iter()
     }
 
-    /// Updates a topic's metadata based on a [`MonitorMessage`].
+    /// Returns if the given client is a known subscriber of the topic.
     ///
     /// # Arguments
     ///
-    /// * `active_topics` - A handle to a shared memory HashMap containing list of topics and
-    ///                     associated metadata.
-    /// * `msg` - The message that contains information for updating a topic's state.
-    fn update_topic(
-        active_topics: Arc<Mutex<ActiveTopicsMap>>,
-        msg: MonitorMessage,
-    ) -> Option<TopicAction> {
-        let context = msg.context;
-        let action = msg.action;
+    /// * `subscriber_id` - The client id of the subscriber.
+    pub fn has_subscriber(&self, subscriber_id: &str) -> bool {
+        self.subscribers.contains(subscriber_id)
+    }
 
-        let mut map = active_topics.lock().unwrap();
+    /// Extends a subscriber's lease on the topic. Returns true if the subscriber had no lease.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscriber_id` - The client id of the subscriber.
+    /// * `expires_at` - The time the lease expires if it is not renewed again.
+    pub fn renew_lease(&mut self, subscriber_id: String, expires_at: Instant) -> bool {
+        self.leases.insert(subscriber_id, expires_at).is_none()
+    }
 
-        match action {
-            PubSubAction::Subscribe => {
-                if let Vacant(m) = map.entry(context.clone()) {
-                    // If a subscription happens we want to capture it, but leave the management_cb untouched
-                    // so when a suitable publisher comes along it can start publishing.
-                    let placeholder_metadata = TopicMetadata::new(String::new(), 1, None);
-                    m.insert(placeholder_metadata);
-                } else {
-                    let mut_val = map.get_mut(&context).unwrap();
-                    mut_val.count += 1;
-                    mut_val.reset_timeout();
+    /// Removes a subscriber's lease on the topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscriber_id` - The client id of the subscriber.
+    pub fn remove_lease(&mut self, subscriber_id: &str) {
+        self.leases.remove(subscriber_id);
+    }
 
-                    // Only want to return an action if there is only one subscriber and there is a publisher to notify.
-                    if let Some(management_uri) = mut_val.get_management_callback() {
-                        if mut_val.count == 1 {
-                            return Some(TopicAction::Start(TopicManagementInfo::new(
-                                context.clone(),
-                                management_uri,
-                            )));
-                        }
-                    }
-                }
+    /// Puts the topic in single consumer mode, where a [`ConsumerGroup`] tracks the subscribers
+    /// that claim the topic.
+    pub fn enable_consumer_group(&mut self) {
+        self.consumer_group
+            .get_or_insert_with(ConsumerGroup::default);
+    }
 
-                None
-            }
-            PubSubAction::Unsubscribe => {
-                if map.contains_key(&context) {
-                    let mut_val = map.get_mut(&context).unwrap();
-                    mut_val.count -= 1;
-                    mut_val.reset_timeout();
+    /// Pre-warms the topic, so that its publisher is started before the topic has subscribers
+    /// and only stopped once the topic timed out without subscribers.
+    pub fn enable_pre_warm(&mut self) {
+        self.pre_warm = true;
+    }
 
-                    // Only want to return an action if there are no longer any subscribers and a publisher to notify.
-                    if let Some(management_uri) = mut_val.get_management_callback() {
-                        if mut_val.count <= 0 {
-                            mut_val.count = 0; // Potential edge case with duplicate messages causing count to go below zero
+    /// Returns whether the topic is pre-warmed.
+    #[cfg(test)]
+    pub fn is_pre_warmed(&self) -> bool {
+        self.pre_warm
+    }
 
-                            return Some(TopicAction::Stop(TopicManagementInfo::new(
-                                context.clone(),
-                                management_uri,
-                            )));
-                        }
-                    }
-                }
+    /// Notifies the publisher of the topic when a subscriber joins after the publisher was started.
+    pub fn enable_subscriber_joined_notifications(&mut self) {
+        self.notify_subscriber_joined = true;
+    }
 
-                None
-            }
-            PubSubAction::Timeout => {
-                if map.contains_key(&context) {
-                    let mut_val = map.get_mut(&context).unwrap();
-                    mut_val.reset_timeout();
+    /// Has the publisher stamp the messages of the topic with sequence numbers of the given
+    /// epoch.
+    ///
+    /// # Arguments
+    ///
+    /// * `epoch` - The epoch of the sequence numbers of the topic.
+    pub fn enable_sequence_numbers(&mut self, epoch: u64) {
+        self.sequence_epoch = Some(epoch);
+    }
 
-                    // Only want to return an action if there is a publisher to notify.
-                    if let Some(management_uri) = mut_val.get_management_callback() {
-                        if mut_val.count <= 0 {
-                            mut_val.count = 0; // Potential edge case with duplicate messages causing count to go below zero
+    /// Returns the epoch of the sequence numbers of the topic, if the publisher stamps them.
+    pub fn sequence_epoch(&self) -> Option<u64> {
+        self.sequence_epoch
+    }
 
-                            return Some(TopicAction::Stop(TopicManagementInfo::new(
-                                context.clone(),
-                                management_uri,
-                            )));
-                        }
-                    }
-                }
+    /// Records that the publisher of the topic was started at the given [`Instant`], which begins
+    /// a new lifecycle epoch of the topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time the publisher was started.
+    fn start_at(&mut self, now: Instant) {
+        self.started_at = Some(now);
+        self.lifecycle_epoch += 1;
+    }
 
-                None
-            }
-            PubSubAction::Delete => map
-                .remove(&context)
-                .and_then(|metadata| metadata.get_management_callback())
-                .map(|management_uri| {
-                    TopicAction::Delete(TopicManagementInfo::new(context, management_uri))
-                }),
-            _ => {
-                warn!("Shouldn't be here! Invalid action: {action}");
-                None
-            }
-        }
+    /// Returns the lifecycle epoch of the topic, which increases each time its publisher is
+    /// started after being stopped. 0 if the publisher was never started.
+    pub fn lifecycle_epoch(&self) -> u64 {
+        self.lifecycle_epoch
     }
 
-    /// Notifies a publisher of the given action on a topic.
+    /// Sets the class of the topic.
     ///
     /// # Arguments
     ///
-    /// * `action` - The specific action to be taken on a topic.
-    async fn manage_topic(
-        action: TopicAction,
-    ) -> Result<TopicActionMetadata, Box<dyn std::error::Error + Send + Sync>> {
-        // Get action details
-        let action_metadata = TopicActionMetadata::new(action);
-        info!(
-            "Executing action '{}' on topic '{}'.",
-            action_metadata.action, action_metadata.topic
-        );
+    /// * `topic_class` - The class of the topic.
+    pub fn set_topic_class(&mut self, topic_class: TopicClass) {
+        self.topic_class = topic_class;
+    }
 
-        // No need to contact publisher if DELETE action as this is initated by the publisher.
-        if action_metadata.action == PubSubAction::Delete.to_string() {
-            return Ok(action_metadata);
-        }
+    /// Returns the class of the topic.
+    pub fn topic_class(&self) -> TopicClass {
+        self.topic_class
+    }
 
-        // Get information from publisher client
-        let uri = action_metadata.uri.clone();
-        let mut pub_client = PublisherCallbackClient::connect(uri).await?;
+    /// Sets the compression the publisher applies to the payloads of the topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload_compression` - The compression of the payloads of the topic.
+    pub fn set_payload_compression(&mut self, payload_compression: PayloadCompression) {
+        self.payload_compression = payload_compression;
+    }
 
-        let request = Request::new(ManageTopicRequest {
-            topic: action_metadata.topic.clone(),
-            action: action_metadata.action.clone(),
-        });
+    /// Returns the compression the publisher applies to the payloads of the topic.
+    pub fn payload_compression(&self) -> PayloadCompression {
+        self.payload_compression
+    }
 
-        let _response = pub_client.manage_topic_callback(request).await?;
+    /// Sets the name of the topic template the topic was created from.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic_template` - The name of the topic template.
+    pub fn set_topic_template(&mut self, topic_template: String) {
+        self.topic_template = Some(topic_template);
+    }
 
-        Ok(action_metadata)
+    /// Returns the name of the topic template the topic was created from, if any.
+    pub fn topic_template(&self) -> Option<&str> {
+        self.topic_template.as_deref()
     }
 
-    /// Internal function that periodically handles deletion of inactive topics.
+    /// Sets the message sent to the subscribers of the topic when it is deleted.
     ///
     /// # Arguments
     ///
-    /// * `active_topics_handle` - A handle to a shared memory HashMap containing list of topics
-    ///                            and associated metadata.
-    /// * `drop_sender` - The sender used to communicate a delete action request.
-    async fn cleanup_topics(
-        active_topics_handle: Arc<Mutex<ActiveTopicsMap>>,
-        drop_sender: mpsc::Sender<MonitorMessage>,
-    ) {
-        let active_topics = active_topics_handle.lock().unwrap();
+    /// * `deletion_message` - The deletion message of the topic.
+    pub fn set_deletion_message(&mut self, deletion_message: DeletionMessage) {
+        self.deletion_message = deletion_message;
+    }
 
-        let threshold = Duration::from_secs(30);
+    /// Returns the message sent to the subscribers of the topic when it is deleted.
+    pub fn deletion_message(&self) -> &DeletionMessage {
+        &self.deletion_message
+    }
 
-        for (topic, metadata) in active_topics.clone().into_iter() {
-            if metadata.is_deleted() {
-                // If the topic has been marked for deletion, then send a deletion action and move to the next topic.
-                info!("Removed topic '{topic}' as it is no longer being used.");
-                let _ = drop_sender.send(MonitorMessage {
-                    context: topic,
-                    action: PubSubAction::Delete,
-                });
-            } else if metadata.count == 0
-                && metadata.get_timeout().elapsed().as_secs() > threshold.as_secs()
-            {
-                // If count is 0 and the time since the last action is greater than the threshold, then notify to remove from list.
-                info!("Topic '{topic}' hit a timeout, reminding publisher.");
-                let _ = drop_sender.send(MonitorMessage {
-                    context: topic,
-                    action: PubSubAction::Timeout,
-                });
-            }
-        }
+    /// Sets the tags the publisher labeled the topic with.
+    ///
+    /// # Arguments
+    ///
+    /// * `tags` - The tags of the topic.
+    pub fn set_tags(&mut self, tags: Vec<String>) {
+        self.tags = tags;
     }
 
-    /// Processes a given [`MonitorMessage`] and updates topic state.
+    /// Returns the tags the publisher labeled the topic with.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Returns whether the publisher labeled the topic with a tag.
     ///
     /// # Arguments
     ///
-    /// * `msg` - The message that contains information for updating a topic's state.
-    /// * `active_topics_handle` - A handle to a shared memory HashMap containing list of topics
-    ///                            and associated metadata.
-    /// * `deletion_ch` - A channel used to handle a delete action from the publisher.
-    pub async fn handle_topic_action(
-        msg: MonitorMessage,
-        active_topics_handle: Arc<Mutex<ActiveTopicsMap>>,
-        deletion_ch: mpsc::Sender<MonitorMessage>,
-    ) {
-        if let Some(action) = Self::update_topic(active_topics_handle.clone(), msg) {
-            let result = Self::manage_topic(action).await;
-
-            match result {
-                Ok(action) => {
-                    if action.action == PubSubAction::Delete.to_string() {
-                        let _res = deletion_ch.send(MonitorMessage {
-                            context: action.topic,
-                            action: PubSubAction::Delete,
-                        });
-                    }
-                }
-                Err(err) => {
-                    error!("error executing action: {err}");
-                }
+    /// * `tag` - The tag.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|topic_tag| topic_tag == tag)
+    }
+
+    /// Sets how long the topic may go without subscribers before its publisher is reminded.
+    ///
+    /// # Arguments
+    ///
+    /// * `idle_timeout` - The idle timeout of the topic.
+    pub fn set_idle_timeout(&mut self, idle_timeout: Duration) {
+        self.idle_timeout = idle_timeout;
+    }
+
+    /// Returns how long the topic may go without subscribers before its publisher is reminded.
+    pub fn idle_timeout(&self) -> Duration {
+        self.idle_timeout
+    }
+
+    /// Returns when the STOP action delayed by the action hysteresis is due, if one is pending.
+    pub fn stop_due(&self) -> Option<Instant> {
+        self.stop_due
+    }
+
+    /// Records a lifecycle action taken on the topic, dropping the oldest record once the history
+    /// holds [`ACTION_HISTORY_CAPACITY`] records.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The action and its outcome.
+    pub fn record_action(&mut self, record: ActionRecord) {
+        if self.action_history.len() == ACTION_HISTORY_CAPACITY {
+            self.action_history.pop_front();
+        }
+
+        self.action_history.push_back(record);
+    }
+
+    /// Returns the most recent lifecycle actions taken on the topic, oldest first.
+    pub fn action_history(&self) -> impl Iterator<Item = &ActionRecord> {
+        self.action_history.iter()
+    }
+
+    /// Returns the consumer group of the topic, if it is in single consumer mode.
+    pub fn consumer_group_mut(&mut self) -> Option<&mut ConsumerGroup> {
+        self.consumer_group.as_mut()
+    }
+
+    /// Makes the topic the response topic of a request topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_topic` - The topic that requests answered on this topic are sent on.
+    pub fn set_request_topic(&mut self, request_topic: String) {
+        self.request_topic = Some(request_topic);
+    }
+
+    /// Returns the request topic if the topic is a response topic.
+    pub fn get_request_topic(&self) -> Option<&str> {
+        self.request_topic.as_deref()
+    }
+
+    /// Records the subject that a subscriber requested the topic for.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject` - The subject that the publisher serves on the topic.
+    pub fn set_subject(&mut self, subject: String) {
+        self.subject = Some(subject);
+    }
+
+    /// Returns the subject of the topic if a subscriber requested the topic.
+    pub fn subject(&self) -> Option<&str> {
+        self.subject.as_deref()
+    }
+
+    /// Registers another client id that publishes on the topic, such as a standby publisher.
+    /// Returns whether the client id was added, which it isn't if it already publishes on the
+    /// topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - The client id of the publisher.
+    pub fn add_publisher_client(&mut self, client_id: String) -> bool {
+        if self.has_publisher_client(&client_id) {
+            return false;
+        }
+
+        self.standby_client_ids.push(client_id);
+        true
+    }
+
+    /// Returns whether a client id publishes on the topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - The client id of the publisher.
+    pub fn has_publisher_client(&self, client_id: &str) -> bool {
+        self.client_id == client_id || self.standby_client_ids.iter().any(|id| id == client_id)
+    }
+
+    /// Returns the client ids of the standby publishers of the topic, in the order they take over.
+    pub fn standby_client_ids(&self) -> &[String] {
+        &self.standby_client_ids
+    }
+
+    /// Returns the client ids that publish on the topic, starting with the active publisher.
+    pub fn publisher_client_ids(&self) -> Vec<&str> {
+        std::iter::once(self.client_id.as_str())
+            .chain(self.standby_client_ids.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// Removes a client id that publishes on the topic, such as once it disconnected. If the
+    /// active publisher is removed, the standby publisher registered first takes over. Returns
+    /// whether the topic still has a publisher, which it doesn't if the last publisher was
+    /// removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - The client id of the publisher.
+    pub fn remove_publisher_client(&mut self, client_id: &str) -> bool {
+        self.standby_client_ids.retain(|id| id != client_id);
+
+        if self.client_id != client_id {
+            return true;
+        }
+
+        if self.standby_client_ids.is_empty() {
+            return false;
+        }
+
+        self.client_id = self.standby_client_ids.remove(0);
+        true
+    }
+
+    /// Records the response topic of a requester on a request topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `requester_id` - The id of the requester.
+    /// * `response_topic` - The topic the requester receives responses on.
+    pub fn add_response_topic(&mut self, requester_id: String, response_topic: String) {
+        self.response_topics.insert(requester_id, response_topic);
+    }
+
+    /// Returns the response topic of a requester on a request topic, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `requester_id` - The id of the requester.
+    pub fn get_response_topic(&self, requester_id: &str) -> Option<&str> {
+        self.response_topics.get(requester_id).map(String::as_str)
+    }
+
+    /// Returns the response topics of every requester on a request topic.
+    pub fn response_topics(&self) -> impl Iterator<Item = &String> {
+        self.response_topics.values()
+    }
+
+    /// Forgets a response topic of a request topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `response_topic` - The response topic to forget.
+    pub fn remove_response_topic(&mut self, response_topic: &str) {
+        self.response_topics
+            .retain(|_, topic| topic != response_topic);
+    }
+
+    /// Returns the number of subscription leases held on the topic.
+    pub fn lease_count(&self) -> usize {
+        self.leases.len()
+    }
+
+    /// Pushes back the expiry of every lease on the topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - The amount of time to extend the leases by.
+    pub fn extend_leases(&mut self, duration: Duration) {
+        for expires_at in self.leases.values_mut() {
+            *expires_at += duration;
+        }
+    }
+
+    /// Removes the leases that expired at the given time, returning their subscriber ids and when
+    /// they expired.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time.
+    pub fn take_expired_leases(&mut self, now: Instant) -> Vec<(String, Instant)> {
+        let expired: Vec<(String, Instant)> = self
+            .leases
+            .iter()
+            .filter(|(_, expires_at)| **expires_at <= now)
+            .map(|(subscriber_id, expires_at)| (subscriber_id.clone(), *expires_at))
+            .collect();
+
+        for (subscriber_id, _) in &expired {
+            self.leases.remove(subscriber_id);
+        }
+
+        expired
+    }
+}
+
+/// Alias for a HashMap where the key is the topic name as a string
+/// and the value is the [`TopicMetadata`].
+pub type ActiveTopicsMap = HashMap<String, TopicMetadata>;
+
+/// The window of time that cleanup activity is reported over.
+pub const CLEANUP_REPORT_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Summary of topic cleanup activity, used to tune timeout configuration.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CleanupReport {
+    /// The number of topics deleted within the [`CLEANUP_REPORT_WINDOW`].
+    pub topics_reaped: u64,
+    /// The average time that topics deleted within the [`CLEANUP_REPORT_WINDOW`] went without
+    /// subscribers before being deleted.
+    pub average_idle_time: Duration,
+    /// The number of topics that are marked for deletion but have not yet been deleted.
+    pub pending_deletions: u64,
+    /// The number of placeholder topics that are still waiting for a publisher.
+    pub placeholder_topics: u64,
+    /// The number of placeholder topics removed since the service started, as they found no
+    /// publisher within the placeholder TTL.
+    pub placeholders_expired: u64,
+    /// The number of cold topics spilled to disk.
+    pub spilled_topics: u64,
+}
+
+/// Records topic deletions so that cleanup activity can be reported.
+#[derive(Debug, Default)]
+pub struct CleanupStats {
+    /// Deleted topics as pairs of the deletion time and idle time before deletion, oldest first.
+    reaped: VecDeque<(Instant, Duration)>,
+    /// The number of placeholder topics removed since the service started.
+    placeholders_expired: u64,
+}
+
+impl CleanupStats {
+    /// Records the deletion of a topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `deleted_at` - The time the topic was deleted.
+    /// * `idle_time` - How long the topic went without subscribers before being deleted.
+    pub fn record(&mut self, deleted_at: Instant, idle_time: Duration) {
+        self.reaped.push_back((deleted_at, idle_time));
+        self.prune(deleted_at);
+    }
+
+    /// Records the removal of a placeholder topic that found no publisher.
+    pub fn record_placeholder_expired(&mut self) {
+        self.placeholders_expired += 1;
+    }
+
+    /// Drops recorded deletions that fall outside of the [`CLEANUP_REPORT_WINDOW`].
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time the window ends at.
+    fn prune(&mut self, now: Instant) {
+        while let Some((deleted_at, _)) = self.reaped.front() {
+            if now.saturating_duration_since(*deleted_at) <= CLEANUP_REPORT_WINDOW {
+                break;
             }
+
+            self.reaped.pop_front();
         }
     }
 
-    /// Continuously monitors a channel where updates to topics are sent as MonitorMessages.
+    /// Builds a [`CleanupReport`] of the cleanup activity as of the given [`Instant`].
     ///
     /// # Arguments
     ///
-    /// * `deletion_ch` - A channel used to handle a delete action from the publisher.
-    pub async fn monitor(
+    /// * `active_topics` - The current active topics, used to count pending deletions.
+    /// * `topic_spill` - The spill of the cold topics, if topics are spilled.
+    /// * `now` - The time the report is generated at.
+    pub fn report(
+        &mut self,
+        active_topics: &ActiveTopicsMap,
+        topic_spill: Option<&TopicSpill>,
+        now: Instant,
+    ) -> CleanupReport {
+        self.prune(now);
+
+        let topics_reaped = self.reaped.len() as u64;
+        let total_idle_time: Duration = self.reaped.iter().map(|(_, idle)| *idle).sum();
+        let average_idle_time = u32::try_from(self.reaped.len())
+            .ok()
+            .and_then(|count| total_idle_time.checked_div(count))
+            .unwrap_or_default();
+
+        let pending_deletions = active_topics
+            .values()
+            .filter(|metadata| metadata.is_deleted())
+            .count() as u64;
+
+        let placeholder_topics = active_topics
+            .values()
+            .filter(|metadata| metadata.is_placeholder())
+            .count() as u64;
+
+        CleanupReport {
+            topics_reaped,
+            average_idle_time,
+            pending_deletions,
+            placeholder_topics,
+            placeholders_expired: self.placeholders_expired,
+            spilled_topics: topic_spill.map_or(0, |topic_spill| topic_spill.spilled_count() as u64),
+        }
+    }
+}
+
+/// The names of the topics generated by the service, which are a UUID as generated by
+/// [`crate::providers::UuidTopicIdGenerator`], prefixed by the prefix of a topic template or not
+/// prefixed. Topics on the messaging broker that are named otherwise belong to other applications.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GeneratedTopics {
+    /// The prefixes of the generated names, including the empty prefix.
+    prefixes: Vec<String>,
+}
+
+impl Default for GeneratedTopics {
+    fn default() -> Self {
+        GeneratedTopics {
+            prefixes: vec![String::new()],
+        }
+    }
+}
+
+impl GeneratedTopics {
+    /// Creates a new GeneratedTopics instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefixes` - The prefixes of the topic templates. Names without a prefix are always
+    ///                generated.
+    pub fn new(prefixes: impl IntoIterator<Item = String>) -> Self {
+        let mut generated = GeneratedTopics::default();
+        generated.prefixes.extend(prefixes);
+        generated.prefixes.sort();
+        generated.prefixes.dedup();
+
+        generated
+    }
+
+    /// Returns whether the service could have generated the name of a topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The canonical name of the topic.
+    pub fn contains(&self, topic: &str) -> bool {
+        self.prefixes.iter().any(|prefix| {
+            topic
+                .strip_prefix(prefix.as_str())
+                .is_some_and(|id| Uuid::try_parse(id).is_ok())
+        })
+    }
+}
+
+/// Returns the topics on the messaging broker that the service generated but no longer knows,
+/// sorted by their broker name. Topics of other applications on a shared broker are never
+/// returned, and neither are spilled topics, which are still managed by the service.
+///
+/// # Arguments
+///
+/// * `broker_topics` - The broker names of the topics that hold state on the messaging broker.
+/// * `active_topics` - The topics currently managed by the service.
+/// * `topic_spill` - The spill of the cold topics that are not held in memory, if topics are
+///                   spilled.
+/// * `generated_topics` - The names of the topics generated by the service.
+/// * `codec` - The codec that maps broker names to the names of managed topics.
+pub fn find_orphan_topics(
+    broker_topics: Vec<String>,
+    active_topics: &ActiveTopicsMap,
+    topic_spill: Option<&TopicSpill>,
+    generated_topics: &GeneratedTopics,
+    codec: &dyn TopicNameCodec,
+) -> Vec<String> {
+    let mut orphans: Vec<String> = broker_topics
+        .into_iter()
+        .filter(|name| {
+            codec.decode(name).is_some_and(|topic| {
+                generated_topics.contains(&topic)
+                    && !active_topics.contains_key(&topic)
+                    && !topic_spill.is_some_and(|topic_spill| topic_spill.is_spilled(&topic))
+            })
+        })
+        .collect();
+    orphans.sort();
+    orphans.dedup();
+
+    orphans
+}
+
+/// The topics known to the service, compared against the topics on the messaging broker to find
+/// orphan topics, and what is done with the orphan topics.
+#[derive(Clone, Debug)]
+pub struct OrphanReconciliation {
+    /// Handle that points to the topics currently managed by the service.
+    pub active_topics: Arc<SharedState<ActiveTopicsMap>>,
+    /// Spill of the cold topics that are not held in memory. Topics are not spilled if not set.
+    pub topic_spill: Option<Arc<TopicSpill>>,
+    /// The names of the topics generated by the service.
+    pub generated_topics: GeneratedTopics,
+    /// Whether orphan topics are removed from the broker, rather than only reported.
+    pub remove_orphans: bool,
+}
+
+impl OrphanReconciliation {
+    /// Returns the orphan topics among the topics on the messaging broker, sorted by their broker
+    /// name. See [`find_orphan_topics`].
+    ///
+    /// # Arguments
+    ///
+    /// * `broker_topics` - The broker names of the topics that hold state on the messaging broker.
+    /// * `codec` - The codec that maps broker names to the names of managed topics.
+    pub fn find_orphans(
         &self,
-        deletion_ch: mpsc::Sender<MonitorMessage>,
-    ) -> mpsc::Sender<MonitorMessage> {
-        let (sender, receiver) = mpsc::channel::<MonitorMessage>();
+        broker_topics: Vec<String>,
+        codec: &dyn TopicNameCodec,
+    ) -> Vec<String> {
+        find_orphan_topics(
+            broker_topics,
+            &self.active_topics.lock(),
+            self.topic_spill.as_deref(),
+            &self.generated_topics,
+            codec,
+        )
+    }
+}
 
-        let active_topics_handle = self.get_active_topics_handle();
+/// How long the topic created for a publisher's idempotency key is remembered.
+pub const IDEMPOTENCY_KEY_TTL: Duration = Duration::from_secs(5 * 60);
 
-        let drop_sender = sender.clone();
+/// State of a publisher's idempotency key in the [`IdempotencyCache`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum IdempotencyState {
+    /// A request with the key is creating the topic.
+    Creating,
+    /// The topic created for the key.
+    Created(String),
+}
 
-        let _monitor_handle = tokio::spawn(async move {
-            loop {
-                let update = receiver.recv();
+/// Short lived cache of the topics created for idempotency keys, so that a publisher retrying a
+/// topic creation gets back the topic created by its first attempt.
+#[derive(Debug, Default)]
+pub struct IdempotencyCache {
+    /// Map of (publisher id, idempotency key) to the state of the key and when it was recorded.
+    entries: HashMap<(String, String), (IdempotencyState, Instant)>,
+}
 
-                match update {
-                    Ok(msg) => {
-                        // Check if the action was a disconnect, if so we need to gather the topics to clean up.
-                        if msg.action == PubSubAction::PubDisconnect {
-                            let mut topics_to_notify = Vec::<String>::new();
-                            info!("{} publisher disconnected", &msg.context);
+impl IdempotencyCache {
+    /// Returns the state of a publisher's idempotency key recorded within the
+    /// [`IDEMPOTENCY_KEY_TTL`], if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `publisher_id` - The id of the publisher that created the topic.
+    /// * `idempotency_key` - The key provided by the publisher.
+    /// * `now` - The current time.
+    pub fn get(
+        &mut self,
+        publisher_id: &str,
+        idempotency_key: &str,
+        now: Instant,
+    ) -> Option<IdempotencyState> {
+        self.prune(now);
 
-                            // Gets the list of topics to send Delete messages to.
-                            {
-                                let map = active_topics_handle.lock().unwrap();
+        self.entries
+            .get(&(publisher_id.to_string(), idempotency_key.to_string()))
+            .map(|(state, _)| state.clone())
+    }
 
-                                for (topic, metadata) in map.clone().into_iter() {
-                                    if metadata.client_id == msg.context {
-                                        topics_to_notify.push(topic.clone());
-                                    }
-                                }
-                            }
+    /// Records that a request with a publisher's idempotency key is creating the topic, so that
+    /// concurrent retries don't create a second topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `publisher_id` - The id of the publisher creating the topic.
+    /// * `idempotency_key` - The key provided by the publisher.
+    /// * `now` - The current time.
+    pub fn begin(&mut self, publisher_id: String, idempotency_key: String, now: Instant) {
+        self.entries.insert(
+            (publisher_id, idempotency_key),
+            (IdempotencyState::Creating, now),
+        );
+    }
 
-                            for topic in topics_to_notify {
-                                // for each topic, execute a DELETE action as the publisher is disconnected and won't publish again.
-                                let topic_action = MonitorMessage {
-                                    context: topic.clone(),
-                                    action: PubSubAction::Delete,
-                                };
+    /// Records the topic created for a publisher's idempotency key.
+    ///
+    /// # Arguments
+    ///
+    /// * `publisher_id` - The id of the publisher that created the topic.
+    /// * `idempotency_key` - The key provided by the publisher.
+    /// * `topic` - The created topic.
+    /// * `now` - The time the topic was created.
+    pub fn insert(
+        &mut self,
+        publisher_id: String,
+        idempotency_key: String,
+        topic: String,
+        now: Instant,
+    ) {
+        self.entries.insert(
+            (publisher_id, idempotency_key),
+            (IdempotencyState::Created(topic), now),
+        );
+    }
 
-                                // Clone sender for the deletion channel callback.
-                                let deletion_channel = deletion_ch.clone();
+    /// Forgets a publisher's idempotency key whose topic creation failed, so that a retry can
+    /// create the topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `publisher_id` - The id of the publisher creating the topic.
+    /// * `idempotency_key` - The key provided by the publisher.
+    pub fn cancel(&mut self, publisher_id: &str, idempotency_key: &str) {
+        let key = (publisher_id.to_string(), idempotency_key.to_string());
 
-                                Self::handle_topic_action(
-                                    topic_action,
-                                    active_topics_handle.clone(),
-                                    deletion_channel,
-                                )
-                                .await;
-                            }
-                        } else {
-                            // Clone sender for the deletion channel callback.
-                            let deletion_channel = deletion_ch.clone();
-
-                            Self::handle_topic_action(
-                                msg,
-                                active_topics_handle.clone(),
-                                deletion_channel,
-                            )
-                            .await;
-                        }
-                    }
-                    Err(err) => {
-                        error!("error from monitor: {err}");
-                        tokio::time::sleep(Duration::from_secs(2)).await;
-                    }
-                }
-            }
-        });
+        if matches!(
+            self.entries.get(&key),
+            Some((IdempotencyState::Creating, _))
+        ) {
+            self.entries.remove(&key);
+        }
+    }
 
-        let active_topics_handle = self.get_active_topics_handle();
+    /// Returns the number of remembered idempotency keys, including expired keys that have not
+    /// been dropped yet.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
 
-        let _cleanup_handle = tokio::spawn(async move {
-            loop {
-                let drop_sender = drop_sender.clone();
-                Self::cleanup_topics(active_topics_handle.clone(), drop_sender).await;
+    /// Returns whether no idempotency keys are remembered.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
 
-                tokio::time::sleep(Duration::from_secs(5)).await;
-            }
+    /// Drops entries older than the [`IDEMPOTENCY_KEY_TTL`].
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time.
+    fn prune(&mut self, now: Instant) {
+        self.entries.retain(|_, (_, created_at)| {
+            now.saturating_duration_since(*created_at) < IDEMPOTENCY_KEY_TTL
         });
+    }
+}
+
+/// Associates a topic with the publisher uri that is providing the topic updates.
+#[derive(Debug, PartialEq)]
+pub struct TopicManagementInfo {
+    topic: String,
+    uri: String,
+}
+
+impl TopicManagementInfo {
+    /// Creates a new TopicManagementInfo instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic name.
+    /// * `uri` - The management uri for the topic.
+    pub fn new(topic: String, uri: String) -> Self {
+        TopicManagementInfo { topic, uri }
+    }
+}
+
+/// Number of topic events buffered for each watcher before the oldest events are dropped.
+pub const TOPIC_EVENT_CAPACITY: usize = 256;
+
+/// Alias for the channel that topic lifecycle events are broadcast on.
+pub type TopicEventSender = broadcast::Sender<TopicEvent>;
+
+/// Enum defining the lifecycle events of a topic.
+#[derive(Clone, Copy, Debug, Display, Eq, PartialEq)]
+pub enum TopicEventKind {
+    /// The topic was created by a publisher.
+    #[strum(serialize = "CREATE")]
+    Create,
+    /// The topic gained its first subscriber.
+    #[strum(serialize = "START")]
+    Start,
+    /// A subscriber joined the topic after its publisher was started.
+    #[strum(serialize = "SUBSCRIBER_JOINED")]
+    SubscriberJoined,
+    /// The topic lost its last subscriber, or timed out without subscribers.
+    #[strum(serialize = "STOP")]
+    Stop,
+    /// The topic was deleted.
+    #[strum(serialize = "DELETE")]
+    Delete,
+}
+
+/// A lifecycle event of a topic, broadcast to watchers of the topics.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TopicEvent {
+    /// The kind of event.
+    pub kind: TopicEventKind,
+    /// The topic the event happened on.
+    pub topic: String,
+    /// The id of the publisher of the topic. Empty if the topic has no known publisher.
+    pub publisher_id: String,
+    /// Milliseconds since the Unix epoch when the event happened.
+    pub timestamp_ms: u64,
+}
+
+impl TopicEvent {
+    /// Creates a new TopicEvent that happened now.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The kind of event.
+    /// * `topic` - The topic the event happened on.
+    /// * `publisher_id` - The id of the publisher of the topic.
+    pub fn new(kind: TopicEventKind, topic: String, publisher_id: String) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or_default();
+
+        TopicEvent {
+            kind,
+            topic,
+            publisher_id,
+            timestamp_ms,
+        }
+    }
+
+    /// Returns whether the event passes the given filters. An empty filter matches every event.
+    ///
+    /// # Arguments
+    ///
+    /// * `publisher_id` - Only match events on topics of this publisher.
+    /// * `topic_prefix` - Only match events on topics starting with this prefix.
+    pub fn matches(&self, publisher_id: &str, topic_prefix: &str) -> bool {
+        (publisher_id.is_empty() || self.publisher_id == publisher_id)
+            && self.topic.starts_with(topic_prefix)
+    }
+}
+
+/// Number of lifecycle actions remembered for each topic.
+pub const ACTION_HISTORY_CAPACITY: usize = 16;
+
+/// Enum defining the outcomes of a lifecycle action on a topic.
+#[derive(Clone, Copy, Debug, Display, Eq, PartialEq)]
+pub enum ActionOutcome {
+    /// The publisher handled the management callback.
+    #[strum(serialize = "DELIVERED")]
+    Delivered,
+    /// The management callback to the publisher failed.
+    #[strum(serialize = "FAILED")]
+    Failed,
+    /// The management callback was not made, as the circuit breaker of the publisher is open.
+    #[strum(serialize = "SKIPPED")]
+    Skipped,
+    /// The action was delayed by the action hysteresis.
+    #[strum(serialize = "DELAYED")]
+    Delayed,
+    /// The topic was marked for deletion, and is deleted by the next topic cleanup.
+    #[strum(serialize = "SCHEDULED")]
+    Scheduled,
+}
+
+/// A lifecycle action taken on a topic, kept in the action history of the topic for diagnostics.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActionRecord {
+    /// The kind of action.
+    pub kind: TopicEventKind,
+    /// The update that caused the action, such as `UNSUBSCRIBE` or `TIMEOUT`.
+    pub cause: String,
+    /// The outcome of the action.
+    pub outcome: ActionOutcome,
+    /// Details on the outcome, such as the error of a failed callback. Empty if there are none.
+    pub detail: String,
+    /// Milliseconds since the Unix epoch when the outcome was recorded.
+    pub timestamp_ms: u64,
+}
+
+impl ActionRecord {
+    /// Creates a new ActionRecord with an outcome recorded now.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The kind of action.
+    /// * `cause` - The update that caused the action.
+    /// * `outcome` - The outcome of the action.
+    /// * `detail` - Details on the outcome.
+    pub fn new(
+        kind: TopicEventKind,
+        cause: String,
+        outcome: ActionOutcome,
+        detail: String,
+    ) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or_default();
+
+        ActionRecord {
+            kind,
+            cause,
+            outcome,
+            detail,
+            timestamp_ms,
+        }
+    }
+}
+
+/// Default time a placeholder topic may wait for a publisher before it is removed.
+pub const DEFAULT_PLACEHOLDER_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Default time a topic may go without subscribers before its publisher is reminded.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time an expired lease is kept for a subscriber that was not counted yet, waiting for its
+/// subscribe to be handled. The lease is dropped after that, as the subscribe was ignored or lost.
+pub const LEASE_SUBSCRIBE_GRACE: Duration = Duration::from_secs(30);
+
+/// Hysteresis applied to the STOP actions sent to publishers, so that a subscriber that keeps
+/// reconnecting doesn't cause a storm of START and STOP actions.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ActionHysteresis {
+    /// Minimum time a publisher keeps running after a START action.
+    min_active: Duration,
+    /// Time a topic must go without subscribers before its publisher is stopped.
+    stop_debounce: Duration,
+}
+
+impl ActionHysteresis {
+    /// Creates a new ActionHysteresis. STOP actions are not delayed if both durations are zero.
+    ///
+    /// # Arguments
+    ///
+    /// * `min_active` - Minimum time a publisher keeps running after a START action.
+    /// * `stop_debounce` - Time a topic must go without subscribers before its publisher is
+    ///                     stopped.
+    pub fn new(min_active: Duration, stop_debounce: Duration) -> Self {
+        ActionHysteresis {
+            min_active,
+            stop_debounce,
+        }
+    }
+
+    /// Returns when a STOP action is due for a topic that lost its last subscriber.
+    ///
+    /// # Arguments
+    ///
+    /// * `started_at` - The time the publisher of the topic was last started, if it was.
+    /// * `idle_since` - The time the topic lost its last subscriber.
+    pub fn stop_due(&self, started_at: Option<Instant>, idle_since: Instant) -> Instant {
+        let debounced = idle_since + self.stop_debounce;
+
+        started_at
+            .map(|started_at| debounced.max(started_at + self.min_active))
+            .unwrap_or(debounced)
+    }
+}
+
+/// Maximum time a publisher gets to handle a topic management callback. Sent to the publisher as
+/// the deadline of the callback. Callbacks are made in response to updates from the messaging
+/// broker rather than on behalf of a gRPC request, so there is no request deadline to inherit.
+pub const PUBLISHER_CALLBACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum number of actions handled at once when cleaning up the topics of a disconnected
+/// client.
+pub const CLEANUP_CONCURRENCY: usize = 8;
+
+/// Enum that is used to describe an action to take on a topic with the relevant topic information.
+#[derive(Debug, PartialEq)]
+pub enum TopicAction {
+    /// Start enum.
+    Start(TopicManagementInfo),
+    /// SubscriberJoined enum.
+    SubscriberJoined(TopicManagementInfo),
+    /// Stop enum.
+    Stop(TopicManagementInfo),
+    /// Delete enum.
+    Delete(TopicManagementInfo),
+    /// Expire enum, a deletion initiated by the service for a topic that was idle for too long.
+    Expire(TopicManagementInfo),
+}
+
+/// The state of a topic sent to its publisher along with an action.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct CallbackDetails {
+    /// The subject a subscriber requested the topic for, empty if the publisher created the topic.
+    subject: String,
+    /// The lifecycle epoch of the topic.
+    lifecycle_epoch: u64,
+    /// The number of subscribers on the topic.
+    subscriber_count: u32,
+    /// Whether the action restates the state of the topic rather than changing it.
+    reconciliation: bool,
+}
+
+impl CallbackDetails {
+    /// Creates the CallbackDetails of a topic from its metadata.
+    ///
+    /// # Arguments
+    ///
+    /// * `metadata` - The metadata of the topic.
+    /// * `reconciliation` - Whether the action restates the state of the topic.
+    fn of(metadata: &TopicMetadata, reconciliation: bool) -> Self {
+        CallbackDetails {
+            subject: metadata.subject().unwrap_or_default().to_string(),
+            lifecycle_epoch: metadata.lifecycle_epoch,
+            subscriber_count: metadata.count.max(0) as u32,
+            reconciliation,
+        }
+    }
+}
+
+/// Structure that has metadata for a given action on a topic, with a management uri to
+/// provide the update to.
+pub struct TopicActionMetadata {
+    /// Topic that the action is happening to.
+    pub topic: String,
+    /// Management uri for the publisher.
+    pub uri: String,
+    /// Action on the topic, represented by [`TopicAction`].
+    pub action: String,
+}
+
+impl TopicActionMetadata {
+    /// Creates a new TopicActionMetadata.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - An action to convert to metadata.
+    pub fn new(action: TopicAction) -> Self {
+        match action {
+            TopicAction::Start(info) => TopicActionMetadata {
+                topic: info.topic,
+                uri: info.uri,
+                action: "START".to_string(),
+            },
+            TopicAction::SubscriberJoined(info) => TopicActionMetadata {
+                topic: info.topic,
+                uri: info.uri,
+                action: "SUBSCRIBER_JOINED".to_string(),
+            },
+            TopicAction::Stop(info) => TopicActionMetadata {
+                topic: info.topic,
+                uri: info.uri,
+                action: "STOP".to_string(),
+            },
+            TopicAction::Delete(info) | TopicAction::Expire(info) => TopicActionMetadata {
+                topic: info.topic,
+                uri: info.uri,
+                action: "DELETE".to_string(),
+            },
+        }
+    }
+}
+
+/// Handles the management of dynamic topics based on actions on the topic.
+///
+/// This structure handles the management logic of dynamic topics. It processes actions from the
+/// broker connector and from creation and deletion requests from publishers.
+pub struct TopicManager {
+    active_topics: Arc<SharedState<ActiveTopicsMap>>,
+    cleanup_stats: Arc<SharedState<CleanupStats>>,
+    idempotency_cache: Arc<SharedState<IdempotencyCache>>,
+    topic_events: TopicEventSender,
+    broker_connected: Arc<AtomicBool>,
+    power_suspended: Arc<AtomicBool>,
+    clock: Arc<dyn Clock>,
+    supervisor: Supervisor,
+    publisher_callbacks: PublisherCallbacks,
+    placeholder_ttl: Duration,
+    max_idle: Option<Duration>,
+    topic_spill: Option<Arc<TopicSpill>>,
+    deletion_messages: DeletionMessages,
+    subscriber_acl: Option<Arc<SubscriberAcl>>,
+    #[cfg(feature = "fault-injection")]
+    fault_injector: Arc<FaultInjector>,
+}
+
+impl Default for TopicManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TopicManager {
+    /// Instantiates a new TopicManager.
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    /// Instantiates a new TopicManager that tracks topic activity with the given [`Clock`].
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - The source of time used for topic timeouts.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        let active_topics = Arc::new(SharedState::new(ActiveTopicsMap::new()));
+
+        TopicManager {
+            active_topics,
+            cleanup_stats: Arc::new(SharedState::new(CleanupStats::default())),
+            idempotency_cache: Arc::new(SharedState::new(IdempotencyCache::default())),
+            topic_events: broadcast::channel(TOPIC_EVENT_CAPACITY).0,
+            broker_connected: Arc::new(AtomicBool::new(true)),
+            power_suspended: Arc::new(AtomicBool::new(false)),
+            clock,
+            supervisor: Supervisor::new(),
+            publisher_callbacks: PublisherCallbacks::default(),
+            placeholder_ttl: DEFAULT_PLACEHOLDER_TTL,
+            max_idle: None,
+            topic_spill: None,
+            deletion_messages: DeletionMessages::default(),
+            subscriber_acl: None,
+            #[cfg(feature = "fault-injection")]
+            fault_injector: Arc::new(FaultInjector::new()),
+        }
+    }
+
+    /// Sets how long a placeholder topic, created by a subscription to a topic that no publisher
+    /// has created, may wait for a publisher before it is removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `placeholder_ttl` - The time to live of placeholder topics.
+    pub fn with_placeholder_ttl(mut self, placeholder_ttl: Duration) -> Self {
+        self.placeholder_ttl = placeholder_ttl;
+        self
+    }
+
+    /// Sets how long a topic may go without subscribers before the service deletes it and tells
+    /// its publisher with a DELETE action. Topics are only deleted by their publisher if not set.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_idle` - The maximum idle duration of topics, if any.
+    pub fn with_max_idle(mut self, max_idle: Option<Duration>) -> Self {
+        self.max_idle = max_idle;
+        self
+    }
+
+    /// Sets the limits on the management callbacks made to publishers.
+    ///
+    /// # Arguments
+    ///
+    /// * `publisher_callbacks` - The limits on publisher callbacks.
+    pub fn with_publisher_callbacks(mut self, publisher_callbacks: PublisherCallbacks) -> Self {
+        self.publisher_callbacks = publisher_callbacks;
+        self
+    }
+
+    /// Bounds the number of topics held in memory by spilling cold topics to disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic_spill` - Spills the cold topics past the limit.
+    pub fn with_topic_spill(mut self, topic_spill: Arc<TopicSpill>) -> Self {
+        self.topic_spill = Some(topic_spill);
+        self
+    }
+
+    /// Counts only the subscribers authorized on a topic towards starting it. The subscribes and
+    /// unsubscribes of other subscribers are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscriber_acl` - The subscribers authorized on topics.
+    pub fn with_subscriber_acl(mut self, subscriber_acl: SubscriberAcl) -> Self {
+        self.subscriber_acl = Some(Arc::new(subscriber_acl));
+        self
+    }
+
+    /// Returns a handle to the [`TopicSpill`] that cold topics are spilled to, if topics are
+    /// spilled.
+    pub fn get_topic_spill_handle(&self) -> Option<Arc<TopicSpill>> {
+        self.topic_spill.clone()
+    }
+
+    /// Returns a handle to the limits on the management callbacks made to publishers, including
+    /// the circuit breakers of the management uris.
+    pub fn get_publisher_callbacks_handle(&self) -> PublisherCallbacks {
+        self.publisher_callbacks.clone()
+    }
+
+    /// Returns a handle to the [`FaultInjector`] consulted when processing monitor messages.
+    #[cfg(feature = "fault-injection")]
+    pub fn get_fault_injector_handle(&self) -> Arc<FaultInjector> {
+        self.fault_injector.clone()
+    }
+
+    /// Returns a handle to the [`CleanupStats`] that record topic deletions.
+    pub fn get_cleanup_stats_handle(&self) -> Arc<SharedState<CleanupStats>> {
+        self.cleanup_stats.clone()
+    }
+
+    /// Returns a handle to the [`IdempotencyCache`] of topics created for idempotency keys.
+    pub fn get_idempotency_cache_handle(&self) -> Arc<SharedState<IdempotencyCache>> {
+        self.idempotency_cache.clone()
+    }
+
+    /// Returns a handle to the channel that topic lifecycle events are broadcast on.
+    pub fn get_topic_events_handle(&self) -> TopicEventSender {
+        self.topic_events.clone()
+    }
+
+    /// Returns a handle to the deletion messages of the topics being deleted, which the broker
+    /// connector sends to the subscribers of the topics.
+    pub fn get_deletion_messages_handle(&self) -> DeletionMessages {
+        self.deletion_messages.clone()
+    }
+
+    /// Returns a handle to the [`Clock`] used by the TopicManager.
+    pub fn get_clock_handle(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    /// Returns a handle to the [`Supervisor`] that restarts the background tasks of the
+    /// TopicManager if they panic.
+    pub fn get_supervisor_handle(&self) -> Supervisor {
+        self.supervisor.clone()
+    }
+
+    /// Returns handles to the state of the TopicManager captured in a state snapshot.
+    pub fn get_state_dump_source(&self) -> StateDumpSource {
+        StateDumpSource {
+            active_topics: self.active_topics.clone(),
+            idempotency_cache: self.idempotency_cache.clone(),
+            topic_events: self.topic_events.clone(),
+            broker_connected: self.broker_connected.clone(),
+            power_suspended: self.power_suspended.clone(),
+            clock: self.clock.clone(),
+            topic_spill: self.topic_spill.clone(),
+        }
+    }
+
+    /// Returns a handle that points to the active topics list that tracks current known dynamic
+    /// topics.
+    pub fn get_active_topics_handle(&self) -> Arc<SharedState<ActiveTopicsMap>> {
+        self.active_topics.clone()
+    }
+
+    /// Updates a topic's metadata based on a [`MonitorMessage`].
+    ///
+    /// # Arguments
+    ///
+    /// * `active_topics` - A handle to a shared memory HashMap containing list of topics and
+    ///                     associated metadata.
+    /// * `msg` - The message that contains information for updating a topic's state.
+    /// * `hysteresis` - The hysteresis applied to the STOP actions sent to publishers.
+    /// * `now` - The time the message is being processed at.
+    fn update_topic(
+        active_topics: Arc<SharedState<ActiveTopicsMap>>,
+        msg: MonitorMessage,
+        hysteresis: &ActionHysteresis,
+        now: Instant,
+    ) -> Option<TopicAction> {
+        let context = msg.context;
+        let action = msg.action;
+        let client_id = msg.client_id;
+
+        let mut map = active_topics.lock();
+
+        match action {
+            PubSubAction::Subscribe => {
+                if let Vacant(m) = map.entry(context.clone()) {
+                    // If a subscription happens we want to capture it, but leave the management_cb untouched
+                    // so when a suitable publisher comes along it can start publishing.
+                    let mut placeholder_metadata =
+                        TopicMetadata::new_at(String::new(), 1, None, now);
+                    if let Some(subscriber_id) = client_id {
+                        placeholder_metadata.add_subscriber(subscriber_id);
+                    }
+                    m.insert(placeholder_metadata);
+                } else {
+                    let mut_val = map.get_mut(&context).unwrap();
+
+                    // A subscriber is only counted once, such as when its lease started as the
+                    // broker reported its subscription.
+                    if client_id
+                        .as_ref()
+                        .is_some_and(|subscriber_id| mut_val.has_subscriber(subscriber_id))
+                    {
+                        return None;
+                    }
+
+                    mut_val.count += 1;
+                    mut_val.reset_timeout_at(now);
+                    mut_val.mark_active();
+                    if let Some(subscriber_id) = client_id {
+                        mut_val.add_subscriber(subscriber_id);
+                    }
+
+                    // A subscriber that comes back before a delayed STOP is due finds the
+                    // publisher still running.
+                    let stop_delayed = mut_val.stop_due.take().is_some();
+
+                    // Only want to return an action if there is only one subscriber and there is a publisher to notify.
+                    // A warm publisher was already started.
+                    if let Some(management_uri) = mut_val.get_management_callback() {
+                        if mut_val.count == 1 && !mut_val.warm && !stop_delayed {
+                            mut_val.warm = mut_val.pre_warm;
+                            mut_val.start_at(now);
+
+                            return Some(TopicAction::Start(TopicManagementInfo::new(
+                                context.clone(),
+                                management_uri,
+                            )));
+                        }
+
+                        // The publisher is already running, so it may want to catch up the late
+                        // joining subscriber.
+                        if mut_val.notify_subscriber_joined {
+                            return Some(TopicAction::SubscriberJoined(TopicManagementInfo::new(
+                                context.clone(),
+                                management_uri,
+                            )));
+                        }
+                    }
+                }
+
+                None
+            }
+            PubSubAction::Unsubscribe => {
+                if map.contains_key(&context) {
+                    let mut_val = map.get_mut(&context).unwrap();
+
+                    // A known subscriber that is no longer tracked was already counted out, such
+                    // as when its lease expired as it unsubscribed.
+                    if client_id
+                        .as_ref()
+                        .is_some_and(|subscriber_id| !mut_val.has_subscriber(subscriber_id))
+                    {
+                        return None;
+                    }
+
+                    mut_val.count -= 1;
+                    mut_val.reset_timeout_at(now);
+                    if mut_val.count <= 0 {
+                        mut_val.mark_idle_at(now);
+                    }
+                    if let Some(subscriber_id) = client_id {
+                        mut_val.remove_subscriber(&subscriber_id);
+                        mut_val.remove_lease(&subscriber_id);
+
+                        // A disconnected active consumer hands the topic to the next subscriber.
+                        if let Some(group) = mut_val.consumer_group_mut() {
+                            if group.release(&subscriber_id) {
+                                info!(
+                                    "Active consumer '{subscriber_id}' of topic '{context}' left, active consumer is now {:?}.",
+                                    group.active_consumer()
+                                );
+                            }
+                        }
+                    }
+
+                    // Only want to return an action if there are no longer any subscribers and a publisher to notify.
+                    // A pre-warmed topic is only stopped once it times out.
+                    if let Some(management_uri) = mut_val.get_management_callback() {
+                        if mut_val.count <= 0 {
+                            mut_val.count = 0; // Potential edge case with duplicate messages causing count to go below zero
+
+                            if mut_val.pre_warm {
+                                return None;
+                            }
+
+                            let stop_due = hysteresis.stop_due(mut_val.started_at, now);
+                            if stop_due > now {
+                                info!(
+                                    "Delaying STOP of topic '{context}' by {:?}.",
+                                    stop_due - now
+                                );
+                                mut_val.stop_due = Some(stop_due);
+                                mut_val.record_action(ActionRecord::new(
+                                    TopicEventKind::Stop,
+                                    action.to_string(),
+                                    ActionOutcome::Delayed,
+                                    format!("delayed by {:?}", stop_due - now),
+                                ));
+                                return None;
+                            }
+
+                            mut_val.started_at = None;
+
+                            return Some(TopicAction::Stop(TopicManagementInfo::new(
+                                context.clone(),
+                                management_uri,
+                            )));
+                        }
+                    }
+                }
+
+                None
+            }
+            PubSubAction::Timeout => {
+                if map.contains_key(&context) {
+                    let mut_val = map.get_mut(&context).unwrap();
+                    mut_val.reset_timeout_at(now);
+
+                    // Only want to return an action if there is a publisher to notify.
+                    if let Some(management_uri) = mut_val.get_management_callback() {
+                        if mut_val.count <= 0 {
+                            mut_val.count = 0; // Potential edge case with duplicate messages causing count to go below zero
+                            mut_val.warm = false;
+                            mut_val.started_at = None;
+                            mut_val.stop_due = None;
+
+                            return Some(TopicAction::Stop(TopicManagementInfo::new(
+                                context.clone(),
+                                management_uri,
+                            )));
+                        }
+                    }
+                }
+
+                None
+            }
+            PubSubAction::Warm => {
+                let mut_val = map.get_mut(&context)?;
+
+                // Only want to return an action if the publisher was not yet started.
+                if mut_val.pre_warm && !mut_val.warm && !mut_val.is_deleted() {
+                    let management_uri = mut_val.get_management_callback()?;
+                    mut_val.warm = true;
+                    mut_val.start_at(now);
+
+                    return Some(TopicAction::Start(TopicManagementInfo::new(
+                        context,
+                        management_uri,
+                    )));
+                }
+
+                None
+            }
+            PubSubAction::Adopt => {
+                let mut_val = map.get_mut(&context)?;
+
+                // Subscribers that joined before the topic was adopted are waiting on the
+                // publisher, unless it was started in the meantime.
+                if mut_val.count > 0 && mut_val.started_at.is_none() && !mut_val.is_deleted() {
+                    let management_uri = mut_val.get_management_callback()?;
+                    mut_val.start_at(now);
+
+                    return Some(TopicAction::Start(TopicManagementInfo::new(
+                        context,
+                        management_uri,
+                    )));
+                }
+
+                None
+            }
+            PubSubAction::DeferredStop => {
+                let mut_val = map.get_mut(&context)?;
+
+                // The delayed STOP was dropped if a subscriber came back in the meantime.
+                mut_val.stop_due.take()?;
+                let management_uri = mut_val.get_management_callback()?;
+                mut_val.started_at = None;
+
+                Some(TopicAction::Stop(TopicManagementInfo::new(
+                    context,
+                    management_uri,
+                )))
+            }
+            PubSubAction::ExpirePlaceholder => {
+                // A publisher may have taken over the topic in the meantime.
+                if map
+                    .get(&context)
+                    .is_some_and(|metadata| metadata.is_placeholder())
+                {
+                    map.remove(&context);
+                }
+
+                None
+            }
+            PubSubAction::Delete | PubSubAction::IdleExpired => {
+                let expired = action == PubSubAction::IdleExpired;
+
+                // A subscriber may have joined the idle topic in the meantime.
+                if expired && map.get(&context).is_some_and(|metadata| metadata.count > 0) {
+                    return None;
+                }
+
+                let metadata = map.remove(&context)?;
+
+                // Response topics are deleted along with their request topic.
+                for response_topic in metadata.response_topics() {
+                    if let Some(response_metadata) = map.get_mut(response_topic) {
+                        response_metadata.delete();
+                    }
+                }
+
+                if let Some(request_topic) = metadata.get_request_topic() {
+                    if let Some(request_metadata) = map.get_mut(request_topic) {
+                        request_metadata.remove_response_topic(&context);
+                    }
+
+                    // A response topic has no management callback, but its subscribers still need
+                    // to be told that it was deleted.
+                    return Some(TopicAction::Delete(TopicManagementInfo::new(
+                        context,
+                        String::new(),
+                    )));
+                }
+
+                metadata.get_management_callback().map(|management_uri| {
+                    let info = TopicManagementInfo::new(context, management_uri);
+                    if expired {
+                        TopicAction::Expire(info)
+                    } else {
+                        TopicAction::Delete(info)
+                    }
+                })
+            }
+            _ => {
+                warn!("Shouldn't be here! Invalid action: {action}");
+                None
+            }
+        }
+    }
+
+    /// Notifies a publisher of the given action on a topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The specific action to be taken on a topic.
+    /// * `details` - The state of the topic sent along with the action.
+    /// * `action_casing` - The casing of the action sent to the publisher.
+    /// * `compression` - The encoding used to compress the callback, if it is compressed.
+    /// * `security` - The TLS and authentication of the connection to the publisher, if any.
+    async fn manage_topic(
+        action: TopicAction,
+        details: CallbackDetails,
+        action_casing: ActionCasing,
+        compression: Option<GrpcCompressionKind>,
+        security: Option<Arc<CallbackClientConfig>>,
+    ) -> Result<TopicActionMetadata, Box<dyn std::error::Error + Send + Sync>> {
+        // No need to contact publisher if DELETE action as this is initated by the publisher.
+        let initiated_by_publisher = matches!(action, TopicAction::Delete(_));
+
+        // Get action details
+        let action_metadata = TopicActionMetadata::new(action);
+        info!(
+            "Executing action '{}' on topic '{}'.",
+            action_metadata.action, action_metadata.topic
+        );
+
+        if initiated_by_publisher {
+            return Ok(action_metadata);
+        }
+
+        // Get information from publisher client. The publisher is told the deadline of the
+        // callback, so that it can give up on work the service no longer waits for.
+        let uri = action_metadata.uri.clone();
+        let callback = async {
+            let mut pub_client =
+                callback_security::connect(uri.clone(), security.as_deref()).await?;
+
+            #[cfg(feature = "grpc-compression")]
+            if let Some(compression) = compression {
+                pub_client = pub_client
+                    .send_compressed(compression.encoding())
+                    .accept_compressed(compression.encoding());
+            }
+            #[cfg(not(feature = "grpc-compression"))]
+            let _ = compression;
+
+            let mut request = Request::new(ManageTopicRequest {
+                topic: action_metadata.topic.clone(),
+                action: action_casing.format(&action_metadata.action),
+                subject: details.subject,
+                lifecycle_epoch: details.lifecycle_epoch,
+                subscriber_count: details.subscriber_count,
+                reconciliation: details.reconciliation,
+            });
+            request.set_timeout(PUBLISHER_CALLBACK_TIMEOUT);
+            if let Some(security) = &security {
+                security.authorize(&mut request);
+            }
+
+            let _response = pub_client.manage_topic_callback(request).await?;
+
+            Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+        };
+
+        tokio::time::timeout(PUBLISHER_CALLBACK_TIMEOUT, callback)
+            .await
+            .map_err(|_| {
+                AgemoError::Connection(format!(
+                    "publisher at '{uri}' did not handle the callback within {PUBLISHER_CALLBACK_TIMEOUT:?}"
+                ))
+            })??;
+
+        Ok(action_metadata)
+    }
+
+    /// Marks the topics of a publisher whose callbacks kept failing for deletion. The topics are
+    /// deleted by the next topic cleanup.
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - The management uri of the publisher.
+    /// * `active_topics_handle` - A handle to a shared memory HashMap containing list of topics
+    ///                            and associated metadata.
+    fn delete_topics_of_callback(
+        uri: &str,
+        active_topics_handle: &Arc<SharedState<ActiveTopicsMap>>,
+    ) {
+        for (topic, metadata) in active_topics_handle.lock().iter_mut() {
+            if !metadata.is_deleted() && metadata.get_management_callback().as_deref() == Some(uri)
+            {
+                warn!("Deleting topic '{topic}' as the callbacks to '{uri}' keep failing.");
+                metadata.delete();
+                metadata.record_action(ActionRecord::new(
+                    TopicEventKind::Delete,
+                    "CALLBACK_FAILURES".to_string(),
+                    ActionOutcome::Scheduled,
+                    format!("the callbacks to '{uri}' keep failing"),
+                ));
+            }
+        }
+    }
+
+    /// Restates the state of the topics of a publisher whose callbacks recovered, as the actions
+    /// on its topics may have been skipped while its circuit breaker was open. Each topic that
+    /// should be published on is sent a START, and each other topic a STOP, marked as a
+    /// reconciliation. Stops at the first failed callback, which is recorded on the breaker.
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - The management uri of the publisher.
+    /// * `active_topics_handle` - A handle to a shared memory HashMap containing list of topics
+    ///                            and associated metadata.
+    /// * `breakers` - The circuit breakers of the management uris.
+    /// * `action_casing` - The casing of the actions sent to the publisher.
+    /// * `compression` - The encoding used to compress the callbacks, if they are compressed.
+    /// * `security` - The TLS and authentication of the connection to the publisher, if any.
+    /// * `now` - The time the callbacks recovered at.
+    async fn reconcile_publisher(
+        uri: &str,
+        active_topics_handle: &Arc<SharedState<ActiveTopicsMap>>,
+        breakers: &Arc<SharedState<CallbackBreakers>>,
+        action_casing: ActionCasing,
+        compression: Option<GrpcCompressionKind>,
+        security: Option<Arc<CallbackClientConfig>>,
+        now: Instant,
+    ) {
+        let mut actions: Vec<(String, TopicAction, CallbackDetails)> = active_topics_handle
+            .lock()
+            .iter()
+            .filter(|(_, metadata)| {
+                !metadata.is_deleted()
+                    && !metadata.is_placeholder()
+                    && metadata.management_callback.as_deref() == Some(uri)
+            })
+            .map(|(topic, metadata)| {
+                let info = TopicManagementInfo::new(topic.clone(), uri.to_string());
+                // A topic whose START was skipped while the breaker was open still has subscribers.
+                let action = if metadata.started_at.is_some() || metadata.count > 0 {
+                    TopicAction::Start(info)
+                } else {
+                    TopicAction::Stop(info)
+                };
+
+                (topic.clone(), action, CallbackDetails::of(metadata, true))
+            })
+            .collect();
+        actions.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+
+        info!(
+            "Callbacks to '{uri}' recovered, reconciling the state of {} topics.",
+            actions.len()
+        );
+
+        for (topic, action, details) in actions {
+            let kind = match action {
+                TopicAction::Start(_) => TopicEventKind::Start,
+                _ => TopicEventKind::Stop,
+            };
+
+            let result = Self::manage_topic(
+                action,
+                details,
+                action_casing,
+                compression,
+                security.clone(),
+            )
+            .await;
+
+            let (outcome, detail) = match &result {
+                Ok(_) => (ActionOutcome::Delivered, String::new()),
+                Err(err) => (ActionOutcome::Failed, err.to_string()),
+            };
+            Self::record_action(
+                &topic,
+                ActionRecord::new(kind, "RECONCILIATION".to_string(), outcome, detail),
+                active_topics_handle,
+            );
+
+            if result.is_ok() && kind == TopicEventKind::Start {
+                if let Some(metadata) = active_topics_handle.lock().get_mut(&topic) {
+                    if metadata.started_at.is_none() {
+                        metadata.start_at(now);
+                    }
+                }
+            }
+
+            if let Err(err) = result {
+                warn!("Unable to reconcile topic '{topic}' with the publisher at '{uri}': {err}");
+
+                let escalate = breakers.lock().record_failure(uri, now);
+                if escalate {
+                    Self::delete_topics_of_callback(uri, active_topics_handle);
+                }
+                return;
+            }
+        }
+    }
+
+    /// Records the outcome of a lifecycle action in the action history of a topic. Does nothing
+    /// if the topic no longer exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic the action was taken on.
+    /// * `record` - The action and its outcome.
+    /// * `active_topics_handle` - A handle to a shared memory HashMap containing list of topics
+    ///                            and associated metadata.
+    fn record_action(
+        topic: &str,
+        record: ActionRecord,
+        active_topics_handle: &Arc<SharedState<ActiveTopicsMap>>,
+    ) {
+        if let Some(metadata) = active_topics_handle.lock().get_mut(topic) {
+            metadata.record_action(record);
+        }
+    }
+
+    /// Removes a disconnected publisher client from the topics it publishes on. A topic with a
+    /// standby publisher fails over to it, while the topics left without a publisher are returned
+    /// so that they can be deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `active_topics` - The locked active topics map.
+    /// * `client_id` - The client id of the disconnected publisher.
+    pub fn release_publisher_client(
+        active_topics: &mut ActiveTopicsMap,
+        client_id: &str,
+    ) -> Vec<String> {
+        let mut orphaned = Vec::new();
+
+        for (topic, metadata) in active_topics.iter_mut() {
+            if !metadata.has_publisher_client(client_id) {
+                continue;
+            }
+
+            if metadata.remove_publisher_client(client_id) {
+                info!(
+                    "'{}' publishes on topic '{topic}' after '{client_id}' disconnected.",
+                    metadata.client_id
+                );
+            } else {
+                orphaned.push(topic.clone());
+            }
+        }
+
+        orphaned
+    }
+
+    /// Internal function that periodically handles deletion of inactive topics and expiry of
+    /// subscription leases.
+    ///
+    /// # Arguments
+    ///
+    /// * `active_topics_handle` - A handle to a shared memory HashMap containing list of topics
+    ///                            and associated metadata.
+    /// * `drop_sender` - The sender used to communicate a delete action request.
+    /// * `broker_connected` - Whether the broker connector is connected. Topics don't time out
+    ///                        while it isn't, as subscribe updates can't reach the service.
+    /// * `placeholder_ttl` - How long a placeholder topic may wait for a publisher.
+    /// * `max_idle` - How long a topic may go without subscribers before it is deleted, if ever.
+    /// * `now` - The time the cleanup is being executed at.
+    async fn cleanup_topics(
+        active_topics_handle: Arc<SharedState<ActiveTopicsMap>>,
+        drop_sender: mpsc::Sender<MonitorMessage>,
+        broker_connected: bool,
+        placeholder_ttl: Duration,
+        max_idle: Option<Duration>,
+        now: Instant,
+    ) {
+        let mut notifications = Vec::new();
+
+        // Collect the notifications while holding the lock, and send them once it is released.
+        {
+            let mut active_topics = active_topics_handle.lock();
+
+            for (topic, metadata) in active_topics.iter_mut() {
+                if metadata.is_deleted() {
+                    // If the topic has been marked for deletion, then send a deletion action and move to the next topic.
+                    info!("Removed topic '{topic}' as it is no longer being used.");
+                    notifications.push(MonitorMessage {
+                        context: topic.clone(),
+                        action: PubSubAction::Delete,
+                        client_id: None,
+                        identity: None,
+                    });
+                } else if broker_connected
+                    && metadata.count == 0
+                    && !metadata.is_placeholder()
+                    && max_idle.is_some_and(|max_idle| metadata.idle_duration(now) > max_idle)
+                {
+                    // The publisher didn't delete the topic despite the reminders, so the service
+                    // deletes it itself.
+                    warn!(
+                        "Topic '{topic}' had no subscribers for {:?}, deleting it.",
+                        metadata.idle_duration(now)
+                    );
+                    notifications.push(MonitorMessage {
+                        context: topic.clone(),
+                        action: PubSubAction::IdleExpired,
+                        client_id: None,
+                        identity: None,
+                    });
+                } else if broker_connected
+                    && metadata.count == 0
+                    && now
+                        .saturating_duration_since(metadata.get_timeout())
+                        .as_secs()
+                        > metadata.idle_timeout().as_secs()
+                {
+                    // If count is 0 and the time since the last action is greater than the threshold, then notify to remove from list.
+                    info!("Topic '{topic}' hit a timeout, reminding publisher.");
+                    notifications.push(MonitorMessage {
+                        context: topic.clone(),
+                        action: PubSubAction::Timeout,
+                        client_id: None,
+                        identity: None,
+                    });
+                }
+
+                // A placeholder that never found a publisher is forgotten, so that subscriptions to
+                // mistyped or stale topics don't accumulate.
+                if metadata.is_placeholder() && metadata.age(now) > placeholder_ttl {
+                    let mut subscribers: Vec<&String> = metadata.subscribers().collect();
+                    subscribers.sort();
+                    warn!(
+                        "Placeholder topic '{topic}' found no publisher within {placeholder_ttl:?}, removing it. subscriber_count={}, subscribers={subscribers:?}",
+                        metadata.count
+                    );
+                    notifications.push(MonitorMessage {
+                        context: topic.clone(),
+                        action: PubSubAction::ExpirePlaceholder,
+                        client_id: None,
+                        identity: None,
+                    });
+                }
+
+                // A STOP delayed by the action hysteresis is sent once it is due.
+                if metadata.stop_due().is_some_and(|stop_due| now >= stop_due) {
+                    notifications.push(MonitorMessage {
+                        context: topic.clone(),
+                        action: PubSubAction::DeferredStop,
+                        client_id: None,
+                        identity: None,
+                    });
+                }
+
+                // A subscriber whose lease expired is treated as if it unsubscribed from the topic.
+                // The lease of a subscriber whose subscribe was not handled yet is kept for a grace
+                // period, so that it expires once the subscriber is counted.
+                for (subscriber_id, expired_at) in metadata.take_expired_leases(now) {
+                    if metadata.has_subscriber(&subscriber_id) {
+                        info!("Lease of '{subscriber_id}' on topic '{topic}' expired.");
+                        notifications.push(MonitorMessage {
+                            context: topic.clone(),
+                            action: PubSubAction::Unsubscribe,
+                            client_id: Some(subscriber_id),
+                            identity: None,
+                        });
+                    } else if now.saturating_duration_since(expired_at) < LEASE_SUBSCRIBE_GRACE {
+                        metadata.renew_lease(subscriber_id, expired_at);
+                    }
+                }
+            }
+        }
+
+        for notification in notifications {
+            let _ = drop_sender.send(notification);
+        }
+    }
+
+    /// Records whether the broker connector is connected to the messaging broker.
+    ///
+    /// Topic timeouts are suspended while the connector is disconnected. On reconnect every topic
+    /// gets a full timeout period, as subscribe updates made during the outage may have been
+    /// missed.
+    ///
+    /// # Arguments
+    ///
+    /// * `connected` - Whether the connector reported being connected.
+    /// * `broker_connected` - The shared connection state consulted by the topic cleanup.
+    /// * `active_topics_handle` - A handle to a shared memory HashMap containing list of topics
+    ///                            and associated metadata.
+    /// * `now` - The time the connection state changed.
+    fn handle_connection_state(
+        connected: bool,
+        broker_connected: &AtomicBool,
+        active_topics_handle: Arc<SharedState<ActiveTopicsMap>>,
+        now: Instant,
+    ) {
+        let was_connected = broker_connected.swap(connected, Ordering::SeqCst);
+
+        if was_connected && !connected {
+            warn!("Lost connection to the messaging broker, suspending topic timeouts.");
+        } else if !was_connected && connected {
+            info!("Reconnected to the messaging broker, resuming topic timeouts.");
+
+            for metadata in active_topics_handle.lock().values_mut() {
+                metadata.reset_timeout_at(now);
+            }
+        }
+    }
+
+    /// Records whether the system is suspended. Returns true if the system resumed.
+    ///
+    /// While suspended, topic cleanup and publisher callbacks are paused. On resume every topic
+    /// gets a full timeout period and leases are extended by the time spent suspended, so that a
+    /// sleep cycle doesn't cause a mass deletion of topics. The connection may have been lost
+    /// while suspended without the connector noticing, so topic timeouts stay suspended until the
+    /// connector re-validated the connection and reports it as connected.
+    ///
+    /// # Arguments
+    ///
+    /// * `suspended` - Whether the system reported suspending.
+    /// * `power_suspended` - The shared power state consulted by the topic cleanup.
+    /// * `suspended_at` - The time the system suspended, if it is suspended.
+    /// * `broker_connected` - The shared connection state consulted by the topic cleanup.
+    /// * `active_topics_handle` - A handle to a shared memory HashMap containing list of topics
+    ///                            and associated metadata.
+    /// * `now` - The time the power state changed.
+    fn handle_power_state(
+        suspended: bool,
+        power_suspended: &AtomicBool,
+        suspended_at: &mut Option<Instant>,
+        broker_connected: &AtomicBool,
+        active_topics_handle: Arc<SharedState<ActiveTopicsMap>>,
+        now: Instant,
+    ) -> bool {
+        let was_suspended = power_suspended.swap(suspended, Ordering::SeqCst);
+
+        if suspended {
+            if !was_suspended {
+                warn!("System is suspending, pausing topic cleanup and publisher callbacks.");
+                *suspended_at = Some(now);
+            }
+
+            return false;
+        }
+
+        if !was_suspended {
+            return false;
+        }
+
+        let suspended_for = suspended_at
+            .take()
+            .map(|suspended_at| now.saturating_duration_since(suspended_at))
+            .unwrap_or_default();
+
+        for metadata in active_topics_handle.lock().values_mut() {
+            metadata.reset_timeout_at(now);
+            metadata.extend_leases(suspended_for);
+        }
+
+        broker_connected.store(false, Ordering::SeqCst);
+        info!(
+            "System resumed after {suspended_for:?}, re-validating the broker connection before \
+             resuming topic timeouts."
+        );
+
+        true
+    }
+
+    /// Processes a given [`MonitorMessage`] and updates topic state.
+    ///
+    /// # Arguments
+    ///
+    /// * `msg` - The message that contains information for updating a topic's state.
+    /// * `active_topics_handle` - A handle to a shared memory HashMap containing list of topics
+    ///                            and associated metadata.
+    /// * `deletion_ch` - A channel used to handle a delete action from the publisher.
+    /// * `deletion_messages` - The deletion messages of the topics being deleted, recorded for the
+    ///                         broker connector.
+    /// * `cleanup_stats` - A handle to the stats that record topic deletions.
+    /// * `topic_events` - The channel that topic lifecycle events are broadcast on.
+    /// * `publisher_callbacks` - The limits on the management callbacks made to publishers.
+    /// * `now` - The time the message is being processed at.
+    pub async fn handle_topic_action(
+        msg: MonitorMessage,
+        active_topics_handle: Arc<SharedState<ActiveTopicsMap>>,
+        deletion_ch: mpsc::Sender<MonitorMessage>,
+        deletion_messages: &DeletionMessages,
+        cleanup_stats: Arc<SharedState<CleanupStats>>,
+        topic_events: &TopicEventSender,
+        publisher_callbacks: &PublisherCallbacks,
+        now: Instant,
+    ) {
+        // Capture the publisher, subject and deletion message before the topic may be removed, and
+        // how long a topic went without subscribers before it is deleted.
+        let (publisher_id, mut details, deletion_message) = {
+            let active_topics = active_topics_handle.lock();
+            let metadata = active_topics.get(&msg.context);
+
+            if matches!(msg.action, PubSubAction::Delete | PubSubAction::IdleExpired) {
+                if let Some(metadata) = metadata {
+                    cleanup_stats
+                        .lock()
+                        .record(now, metadata.idle_duration(now));
+                }
+            }
+
+            if msg.action == PubSubAction::ExpirePlaceholder
+                && metadata.is_some_and(|metadata| metadata.is_placeholder())
+            {
+                cleanup_stats.lock().record_placeholder_expired();
+            }
+
+            metadata
+                .map(|metadata| {
+                    (
+                        metadata.client_id.clone(),
+                        CallbackDetails::of(metadata, false),
+                        metadata.deletion_message().clone(),
+                    )
+                })
+                .unwrap_or_default()
+        };
+        let cause = msg.action.to_string();
+
+        if let Some(action) = Self::update_topic(
+            active_topics_handle.clone(),
+            msg,
+            &publisher_callbacks.hysteresis,
+            now,
+        ) {
+            let (kind, info) = match &action {
+                TopicAction::Start(info) => (TopicEventKind::Start, info),
+                TopicAction::SubscriberJoined(info) => (TopicEventKind::SubscriberJoined, info),
+                TopicAction::Stop(info) => (TopicEventKind::Stop, info),
+                TopicAction::Delete(info) | TopicAction::Expire(info) => {
+                    (TopicEventKind::Delete, info)
+                }
+            };
+
+            // A START has begun a new lifecycle epoch of the topic, and the update may have changed
+            // its number of subscribers.
+            if let Some(metadata) = active_topics_handle.lock().get(&info.topic) {
+                details.lifecycle_epoch = metadata.lifecycle_epoch;
+                details.subscriber_count = metadata.count.max(0) as u32;
+            }
+
+            // There may be no watchers, in which case the event is dropped.
+            let _ = topic_events.send(TopicEvent::new(
+                kind,
+                info.topic.clone(),
+                publisher_id.clone(),
+            ));
+
+            // Deletions are initiated by the publisher, so the publisher is not called. Topics
+            // expired for being idle are deleted whether or not their publisher can be told.
+            if kind == TopicEventKind::Delete {
+                let topic = info.topic.clone();
+
+                if matches!(action, TopicAction::Expire(_)) {
+                    let action_casing = publisher_callbacks.action_casing;
+                    let compression = publisher_callbacks.compression;
+                    let security = publisher_callbacks.security.for_publisher(&publisher_id);
+                    let expired_topic = topic.clone();
+                    let mut turn = publisher_callbacks.queues.lock().enqueue(&info.uri);
+                    let _callback_handle = tokio::spawn(async move {
+                        turn.wait().await;
+
+                        if let Err(err) = Self::manage_topic(
+                            action,
+                            details,
+                            action_casing,
+                            compression,
+                            security,
+                        )
+                        .await
+                        {
+                            warn!("Unable to tell the publisher of idle topic '{expired_topic}' that it was deleted: {err}");
+                        }
+                    });
+                } else if let Err(err) = Self::manage_topic(
+                    action,
+                    details,
+                    publisher_callbacks.action_casing,
+                    publisher_callbacks.compression,
+                    None,
+                )
+                .await
+                {
+                    error!("error executing action: {err}");
+                    return;
+                }
+
+                deletion_messages.insert(&topic, deletion_message);
+                let _res = deletion_ch.send(MonitorMessage {
+                    context: topic,
+                    action: PubSubAction::Delete,
+                    client_id: None,
+                    identity: None,
+                });
+
+                return;
+            }
+
+            let topic = info.topic.clone();
+            let uri = info.uri.clone();
+            let decision = publisher_callbacks.breakers.lock().check(&uri, now);
+
+            match decision {
+                BreakerDecision::Call => {}
+                BreakerDecision::Skip => {
+                    warn!("Skipping '{kind}' callback on topic '{topic}', the circuit breaker of '{uri}' is open.");
+
+                    // The publisher never received the START, so the topic is not started. The
+                    // reconciliation once the callbacks recover starts it.
+                    if kind == TopicEventKind::Start {
+                        if let Some(metadata) = active_topics_handle.lock().get_mut(&topic) {
+                            metadata.started_at = None;
+                            metadata.warm = false;
+                        }
+                    }
+
+                    Self::record_action(
+                        &topic,
+                        ActionRecord::new(
+                            kind,
+                            cause,
+                            ActionOutcome::Skipped,
+                            format!("the circuit breaker of '{uri}' is open"),
+                        ),
+                        &active_topics_handle,
+                    );
+                    return;
+                }
+                BreakerDecision::Escalate => {
+                    Self::record_action(
+                        &topic,
+                        ActionRecord::new(
+                            kind,
+                            cause,
+                            ActionOutcome::Skipped,
+                            format!("the callbacks to '{uri}' kept failing"),
+                        ),
+                        &active_topics_handle,
+                    );
+                    Self::delete_topics_of_callback(&uri, &active_topics_handle);
+                    return;
+                }
+            }
+
+            // Wait for a callback slot, so that at most the configured number of callbacks are in
+            // progress at once.
+            let Ok(permit) = publisher_callbacks.permits.clone().acquire_owned().await else {
+                return;
+            };
+
+            // The callbacks to a management uri are made in the order they are queued, so that a
+            // STOP doesn't overtake the START before it. The queue is only joined once the permit
+            // is held, so the callback it waits for already holds a permit.
+            let mut turn = publisher_callbacks.queues.lock().enqueue(&uri);
+
+            let breakers = publisher_callbacks.breakers.clone();
+            let metrics = publisher_callbacks.metrics.clone();
+            let action_casing = publisher_callbacks.action_casing;
+            let compression = publisher_callbacks.compression;
+            let security = publisher_callbacks.security.for_publisher(&publisher_id);
+            let reconcile_on_recovery = publisher_callbacks.reconcile_on_recovery;
+            let _callback_handle = tokio::spawn(async move {
+                let _permit = permit;
+                turn.wait().await;
+
+                let started = Instant::now();
+                let result = Self::manage_topic(
+                    action,
+                    details,
+                    action_casing,
+                    compression,
+                    security.clone(),
+                )
+                .await;
+                metrics
+                    .lock()
+                    .record_callback(&publisher_id, started.elapsed(), result.is_ok());
+
+                let (outcome, detail) = match &result {
+                    Ok(_) => (ActionOutcome::Delivered, String::new()),
+                    Err(err) => (ActionOutcome::Failed, err.to_string()),
+                };
+                Self::record_action(
+                    &topic,
+                    ActionRecord::new(kind, cause, outcome, detail),
+                    &active_topics_handle,
+                );
+
+                match result {
+                    Ok(_) => {
+                        let recovered = breakers.lock().record_success(&uri);
+                        if recovered && reconcile_on_recovery {
+                            Self::reconcile_publisher(
+                                &uri,
+                                &active_topics_handle,
+                                &breakers,
+                                action_casing,
+                                compression,
+                                security,
+                                now,
+                            )
+                            .await;
+                        }
+                    }
+                    Err(err) => {
+                        error!("error executing action: {err}");
+
+                        let escalate = breakers.lock().record_failure(&uri, now);
+                        if escalate {
+                            Self::delete_topics_of_callback(&uri, &active_topics_handle);
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+    /// Handles actions on several topics concurrently, with at most [`CLEANUP_CONCURRENCY`]
+    /// actions in progress at once. Used to clean up the topics of a disconnected client without
+    /// waiting on each publisher in turn.
+    ///
+    /// # Arguments
+    ///
+    /// * `msgs` - The actions to handle, one per topic.
+    /// * `active_topics_handle` - A handle to a shared memory HashMap containing list of topics
+    ///                            and associated metadata.
+    /// * `deletion_ch` - A channel used to handle a delete action from the publisher.
+    /// * `deletion_messages` - The deletion messages of the topics being deleted, recorded for the
+    ///                         broker connector.
+    /// * `cleanup_stats` - A handle to the stats that record topic deletions.
+    /// * `topic_events` - The channel that topic lifecycle events are broadcast on.
+    /// * `publisher_callbacks` - The limits on the management callbacks made to publishers.
+    /// * `now` - The time the messages are being processed at.
+    pub async fn handle_topic_actions(
+        msgs: Vec<MonitorMessage>,
+        active_topics_handle: &Arc<SharedState<ActiveTopicsMap>>,
+        deletion_ch: &mpsc::Sender<MonitorMessage>,
+        deletion_messages: &DeletionMessages,
+        cleanup_stats: &Arc<SharedState<CleanupStats>>,
+        topic_events: &TopicEventSender,
+        publisher_callbacks: &PublisherCallbacks,
+        now: Instant,
+    ) {
+        stream::iter(msgs)
+            .for_each_concurrent(CLEANUP_CONCURRENCY, |msg| {
+                Self::handle_topic_action(
+                    msg,
+                    active_topics_handle.clone(),
+                    deletion_ch.clone(),
+                    deletion_messages,
+                    cleanup_stats.clone(),
+                    topic_events,
+                    publisher_callbacks,
+                    now,
+                )
+            })
+            .await;
+    }
+
+    /// Continuously monitors a channel where updates to topics are sent as MonitorMessages.
+    ///
+    /// # Arguments
+    ///
+    /// * `deletion_ch` - A channel used to handle a delete action from the publisher.
+    pub async fn monitor(
+        &self,
+        deletion_ch: mpsc::Sender<MonitorMessage>,
+    ) -> mpsc::Sender<MonitorMessage> {
+        let (sender, receiver) = mpsc::channel::<MonitorMessage>();
+
+        let active_topics_handle = self.get_active_topics_handle();
+        let cleanup_stats = self.get_cleanup_stats_handle();
+        let topic_events = self.get_topic_events_handle();
+        let broker_connected = self.broker_connected.clone();
+        let power_suspended = self.power_suspended.clone();
+        let clock = self.get_clock_handle();
+        let publisher_callbacks = self.get_publisher_callbacks_handle();
+        let topic_spill = self.get_topic_spill_handle();
+        let deletion_messages = self.get_deletion_messages_handle();
+        let subscriber_acl = self.subscriber_acl.clone();
+        #[cfg(feature = "fault-injection")]
+        let fault_injector = self.get_fault_injector_handle();
+
+        let drop_sender = sender.clone();
+        let replay_sender = sender.clone();
+
+        // The receiver is shared so that a restarted monitor loop picks up where it left off.
+        // Updates are handled most urgent first, so that a subscribe that starts a topic doesn't
+        // wait behind a batch of cleanup reminders.
+        let receiver = Arc::new(SharedState::new(PriorityReceiver::new(receiver)));
+
+        let make_monitor_loop = move || {
+            let receiver = receiver.clone();
+            let active_topics_handle = active_topics_handle.clone();
+            let cleanup_stats = cleanup_stats.clone();
+            let topic_events = topic_events.clone();
+            let broker_connected = broker_connected.clone();
+            let power_suspended = power_suspended.clone();
+            let clock = clock.clone();
+            let publisher_callbacks = publisher_callbacks.clone();
+            let topic_spill = topic_spill.clone();
+            let deletion_messages = deletion_messages.clone();
+            let subscriber_acl = subscriber_acl.clone();
+            #[cfg(feature = "fault-injection")]
+            let fault_injector = fault_injector.clone();
+            let deletion_ch = deletion_ch.clone();
+            let replay_sender = replay_sender.clone();
+
+            async move {
+                // Updates received while the system is suspended, processed once it resumes.
+                let mut deferred = Vec::<MonitorMessage>::new();
+                let mut suspended_at = None;
+
+                loop {
+                    let update = receiver.lock().recv();
+
+                    match update {
+                        Ok(msg) => {
+                            if msg.action == PubSubAction::PowerSuspend
+                                || msg.action == PubSubAction::PowerResume
+                            {
+                                let resumed = Self::handle_power_state(
+                                    msg.action == PubSubAction::PowerSuspend,
+                                    &power_suspended,
+                                    &mut suspended_at,
+                                    &broker_connected,
+                                    active_topics_handle.clone(),
+                                    clock.now(),
+                                );
+
+                                if resumed {
+                                    // The connector re-validates the broker connection, and
+                                    // reports it as connected once the broker can be reached.
+                                    let _ = deletion_ch.send(power::power_message(false));
+
+                                    for deferred_msg in deferred.drain(..) {
+                                        let _ = replay_sender.send(deferred_msg);
+                                    }
+                                }
+
+                                continue;
+                            }
+
+                            // The broker connection is still tracked while suspended.
+                            if power_suspended.load(Ordering::SeqCst)
+                                && msg.action != PubSubAction::BrokerConnected
+                                && msg.action != PubSubAction::BrokerDisconnected
+                            {
+                                deferred.push(msg);
+                                continue;
+                            }
+
+                            #[cfg(feature = "fault-injection")]
+                            {
+                                if fault_injector.drop_monitor_message(&msg) {
+                                    warn!("Fault injection dropped monitor message: {msg:?}");
+                                    continue;
+                                }
+
+                                let delay = fault_injector.callback_delay();
+                                if !delay.is_zero() {
+                                    tokio::time::sleep(delay).await;
+                                }
+                            }
+
+                            // Subscriptions of subscribers that aren't authorized on the topic
+                            // don't count towards it.
+                            if subscriber_acl
+                                .as_ref()
+                                .is_some_and(|subscriber_acl| !subscriber_acl.admits(&msg))
+                            {
+                                warn!(
+                                    "Ignored {} of unauthorized subscriber {:?} on topic '{}'.",
+                                    msg.action, msg.client_id, msg.context
+                                );
+                                continue;
+                            }
+
+                            // Check if the action was a disconnect, if so we need to gather the topics to clean up.
+                            if msg.action == PubSubAction::PubDisconnect {
+                                info!("{} publisher disconnected", &msg.context);
+
+                                // Gets the list of topics to send Delete messages to, including
+                                // the spilled topics of the publisher. Topics with a standby
+                                // publisher are kept. The lock is released before any action is
+                                // handled.
+                                let topics_to_notify: Vec<String> = {
+                                    let mut active_topics = active_topics_handle.lock();
+                                    if let Some(topic_spill) = &topic_spill {
+                                        topic_spill
+                                            .restore_publisher(&mut active_topics, &msg.context);
+                                    }
+
+                                    Self::release_publisher_client(&mut active_topics, &msg.context)
+                                };
+
+                                // for each topic, execute a DELETE action as the publisher is disconnected and won't publish again.
+                                let topic_actions = topics_to_notify
+                                    .into_iter()
+                                    .map(|topic| MonitorMessage {
+                                        context: topic,
+                                        action: PubSubAction::Delete,
+                                        client_id: None,
+                                        identity: None,
+                                    })
+                                    .collect();
+
+                                Self::handle_topic_actions(
+                                    topic_actions,
+                                    &active_topics_handle,
+                                    &deletion_ch,
+                                    &deletion_messages,
+                                    &cleanup_stats,
+                                    &topic_events,
+                                    &publisher_callbacks,
+                                    clock.now(),
+                                )
+                                .await;
+                            } else if msg.action == PubSubAction::BrokerConnected
+                                || msg.action == PubSubAction::BrokerDisconnected
+                            {
+                                Self::handle_connection_state(
+                                    msg.action == PubSubAction::BrokerConnected,
+                                    &broker_connected,
+                                    active_topics_handle.clone(),
+                                    clock.now(),
+                                );
+                            } else if msg.action == PubSubAction::SubDisconnect {
+                                info!("{} subscriber disconnected", &msg.context);
+
+                                // Gets the list of topics the subscriber was subscribed to. The
+                                // lock is released before any action is handled.
+                                let topics_to_release: Vec<String> = active_topics_handle
+                                    .lock()
+                                    .iter()
+                                    .filter(|(_, metadata)| metadata.has_subscriber(&msg.context))
+                                    .map(|(topic, _)| topic.clone())
+                                    .collect();
+
+                                // for each topic, execute an UNSUBSCRIBE action as the subscriber won't receive data again.
+                                let topic_actions = topics_to_release
+                                    .into_iter()
+                                    .map(|topic| MonitorMessage {
+                                        context: topic,
+                                        action: PubSubAction::Unsubscribe,
+                                        client_id: Some(msg.context.clone()),
+                                        identity: None,
+                                    })
+                                    .collect();
+
+                                Self::handle_topic_actions(
+                                    topic_actions,
+                                    &active_topics_handle,
+                                    &deletion_ch,
+                                    &deletion_messages,
+                                    &cleanup_stats,
+                                    &topic_events,
+                                    &publisher_callbacks,
+                                    clock.now(),
+                                )
+                                .await;
+                            } else {
+                                // A spilled topic is restored before the action is handled.
+                                if let Some(topic_spill) = &topic_spill {
+                                    topic_spill
+                                        .restore(&mut active_topics_handle.lock(), &msg.context);
+                                }
+
+                                // Clone sender for the deletion channel callback.
+                                let deletion_channel = deletion_ch.clone();
+
+                                Self::handle_topic_action(
+                                    msg,
+                                    active_topics_handle.clone(),
+                                    deletion_channel,
+                                    &deletion_messages,
+                                    cleanup_stats.clone(),
+                                    &topic_events,
+                                    &publisher_callbacks,
+                                    clock.now(),
+                                )
+                                .await;
+                            }
+                        }
+                        Err(err) => {
+                            error!("error from monitor: {err}");
+                            tokio::time::sleep(Duration::from_secs(2)).await;
+                        }
+                    }
+                }
+            }
+        };
+        #[cfg(feature = "alloc-profiling")]
+        let make_monitor_loop =
+            move || alloc_profile::instrument(Subsystem::TopicManager, make_monitor_loop());
+        let _monitor_handle = self.supervisor.supervise("monitor", make_monitor_loop);
+
+        let active_topics_handle = self.get_active_topics_handle();
+        let broker_connected = self.broker_connected.clone();
+        let power_suspended = self.power_suspended.clone();
+        let clock = self.get_clock_handle();
+        let placeholder_ttl = self.placeholder_ttl;
+        let max_idle = self.max_idle;
+        let topic_spill = self.get_topic_spill_handle();
+
+        let make_cleanup_loop = move || {
+            let active_topics_handle = active_topics_handle.clone();
+            let topic_spill = topic_spill.clone();
+            let broker_connected = broker_connected.clone();
+            let power_suspended = power_suspended.clone();
+            let clock = clock.clone();
+            let drop_sender = drop_sender.clone();
+
+            async move {
+                loop {
+                    if !power_suspended.load(Ordering::SeqCst) {
+                        let now = clock.now();
+                        let connected = broker_connected.load(Ordering::SeqCst);
+
+                        // Spilled topics are restored by the monitor before they are deleted.
+                        if connected {
+                            if let (Some(topic_spill), Some(max_idle)) = (&topic_spill, max_idle) {
+                                for topic in topic_spill.idle_topics(max_idle, now) {
+                                    let _ = drop_sender.send(MonitorMessage {
+                                        context: topic,
+                                        action: PubSubAction::IdleExpired,
+                                        client_id: None,
+                                        identity: None,
+                                    });
+                                }
+                            }
+                        }
+
+                        Self::cleanup_topics(
+                            active_topics_handle.clone(),
+                            drop_sender.clone(),
+                            connected,
+                            placeholder_ttl,
+                            max_idle,
+                            now,
+                        )
+                        .await;
+
+                        if let Some(topic_spill) = &topic_spill {
+                            topic_spill
+                                .spill_cold_topics(&mut active_topics_handle.lock(), clock.now());
+                        }
+                    }
+
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        };
+        #[cfg(feature = "alloc-profiling")]
+        let make_cleanup_loop =
+            move || alloc_profile::instrument(Subsystem::TopicManager, make_cleanup_loop());
+        let _cleanup_handle = self.supervisor.supervise("cleanup", make_cleanup_loop);
+
+        sender
+    }
+}
+
+#[cfg(test)]
+mod topic_action_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn initialize_topic_action_metadata_test() {
+        let expected_topic = "test".to_string();
+        let expected_mgmt_uri = "test.uri".to_string();
+        let expected_start_action = "START".to_string();
+        let expected_stop_action = "STOP".to_string();
+        let expected_delete_action = "DELETE".to_string();
+        let start_action = TopicAction::Start(TopicManagementInfo::new(
+            expected_topic.clone(),
+            expected_mgmt_uri.clone(),
+        ));
+
+        let start_action_metadata = TopicActionMetadata::new(start_action);
+
+        assert_eq!(expected_topic, start_action_metadata.topic);
+        assert_eq!(expected_mgmt_uri, start_action_metadata.uri);
+        assert_eq!(expected_start_action, start_action_metadata.action);
+
+        let joined_action = TopicAction::SubscriberJoined(TopicManagementInfo::new(
+            expected_topic.clone(),
+            expected_mgmt_uri.clone(),
+        ));
+
+        let joined_action_metadata = TopicActionMetadata::new(joined_action);
+
+        assert_eq!(expected_topic, joined_action_metadata.topic);
+        assert_eq!(expected_mgmt_uri, joined_action_metadata.uri);
+        assert_eq!("SUBSCRIBER_JOINED", joined_action_metadata.action);
+
+        let stop_action = TopicAction::Stop(TopicManagementInfo::new(
+            expected_topic.clone(),
+            expected_mgmt_uri.clone(),
+        ));
+
+        let stop_action_metadata = TopicActionMetadata::new(stop_action);
+
+        assert_eq!(expected_topic, stop_action_metadata.topic);
+        assert_eq!(expected_mgmt_uri, stop_action_metadata.uri);
+        assert_eq!(expected_stop_action, stop_action_metadata.action);
+
+        let delete_action = TopicAction::Delete(TopicManagementInfo::new(
+            expected_topic.clone(),
+            expected_mgmt_uri.clone(),
+        ));
+
+        let delete_action_metadata = TopicActionMetadata::new(delete_action);
+
+        assert_eq!(expected_topic, delete_action_metadata.topic);
+        assert_eq!(expected_mgmt_uri, delete_action_metadata.uri);
+        assert_eq!(expected_delete_action, delete_action_metadata.action);
+    }
+}
+
+#[cfg(test)]
+mod topic_manager_tests {
+    use std::str::FromStr;
+
+    use agemo_test_utils::{
+        monitor_sequence::{self, MonitorSequence},
+        publisher_callback::{FakePublisherCallback, RecordedCallback},
+    };
+
+    use crate::{
+        load_config::{PublisherCallbackSettings, SubscriberAclRule},
+        providers::ManualClock,
+        subscriber_acl::SubscriberIdentification,
+        topic_name_codec::{MqttTopicNameCodec, TopicNaming},
+    };
+
+    use super::*;
+
+    /// Feeds the updates of a canned sequence to the topic manager, returning the actions of the
+    /// resulting publisher callbacks.
+    fn run_sequence(
+        topic_map_handle: Arc<SharedState<ActiveTopicsMap>>,
+        sequence: &MonitorSequence,
+    ) -> Vec<String> {
+        sequence
+            .events
+            .iter()
+            .filter_map(|event| {
+                let message = MonitorMessage {
+                    context: event.context.clone(),
+                    action: PubSubAction::from_str(&event.action).unwrap(),
+                    client_id: event.client_id.clone(),
+                    identity: None,
+                };
+
+                TopicManager::update_topic(
+                    topic_map_handle.clone(),
+                    message,
+                    &ActionHysteresis::default(),
+                    Instant::now(),
+                )
+            })
+            .map(|action| TopicActionMetadata::new(action).action)
+            .collect()
+    }
+
+    #[test]
+    fn connector_actions_parse_test() {
+        for action in monitor_sequence::CONNECTOR_ACTIONS {
+            assert!(PubSubAction::from_str(action).is_ok(), "{action}");
+        }
+    }
+
+    #[test]
+    fn canned_sequences_test() {
+        let sequences = [
+            monitor_sequence::subscribe_then_unsubscribe("topic", "sub_1"),
+            monitor_sequence::subscriber_churn("topic", &["sub_1", "sub_2", "sub_3"]),
+        ];
+
+        for sequence in sequences {
+            let test_manager = TopicManager::new();
+            let topic_map_handle = test_manager.get_active_topics_handle();
+            topic_map_handle.lock().insert(
+                "topic".to_string(),
+                TopicMetadata::new("pub_1".to_string(), 0, Some("test.uri".to_string())),
+            );
+
+            assert_eq!(
+                sequence.expected_callbacks,
+                run_sequence(topic_map_handle, &sequence)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribe_topic_test() {
+        let test_manager = TopicManager::new();
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+        let initial_count = 1;
+        let expected_mgmt_uri = "test.uri".to_string();
+        let initial_metadata =
+            TopicMetadata::new(String::new(), initial_count, Some(expected_mgmt_uri));
+        let initial_time = initial_metadata.get_timeout();
+
+        // Insert existing topic
+        {
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(expected_topic.clone(), initial_metadata);
+        }
+
+        let message = MonitorMessage {
+            context: expected_topic.clone(),
+            action: PubSubAction::Subscribe,
+            client_id: None,
+            identity: None,
+        };
+
+        let actual_action = TopicManager::update_topic(
+            topic_map_handle.clone(),
+            message,
+            &ActionHysteresis::default(),
+            Instant::now(),
+        );
+        assert!(actual_action.is_none());
+
+        // Confirm last active time and count was updated
+        {
+            let map_lock = topic_map_handle.lock();
+            let actual_metadata = map_lock.get(&expected_topic).unwrap();
+
+            assert_ne!(initial_time, actual_metadata.get_timeout());
+            assert_eq!(initial_count + 1, actual_metadata.count);
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribe_topic_with_no_subs_test() {
+        let test_manager = TopicManager::new();
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+        let initial_count = 0;
+        let expected_mgmt_uri = "test.uri".to_string();
+        let initial_metadata = TopicMetadata::new(
+            String::new(),
+            initial_count,
+            Some(expected_mgmt_uri.clone()),
+        );
+        let initial_time = initial_metadata.get_timeout();
+
+        // Insert existing topic with no active subs
+        {
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(expected_topic.clone(), initial_metadata);
+        }
+
+        let message = MonitorMessage {
+            context: expected_topic.clone(),
+            action: PubSubAction::Subscribe,
+            client_id: None,
+            identity: None,
+        };
+
+        let actual_action = TopicManager::update_topic(
+            topic_map_handle.clone(),
+            message,
+            &ActionHysteresis::default(),
+            Instant::now(),
+        );
+        assert!(actual_action.is_some());
+
+        let expected_action_inner = TopicAction::Start(TopicManagementInfo::new(
+            expected_topic.clone(),
+            expected_mgmt_uri,
+        ));
+        assert_eq!(expected_action_inner, actual_action.unwrap());
+
+        // Confirm last active time and count was updated
+        {
+            let map_lock = topic_map_handle.lock();
+            let actual_metadata = map_lock.get(&expected_topic).unwrap();
+
+            assert_ne!(initial_time, actual_metadata.get_timeout());
+            assert_eq!(initial_count + 1, actual_metadata.count);
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribe_topic_with_no_active_topic_test() {
+        let test_manager = TopicManager::new();
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+        let initial_count = 1;
+        let expected_metadata = TopicMetadata::new(String::new(), initial_count, None);
+
+        let message = MonitorMessage {
+            context: expected_topic.clone(),
+            action: PubSubAction::Subscribe,
+            client_id: None,
+            identity: None,
+        };
+
+        let actual_action = TopicManager::update_topic(
+            topic_map_handle.clone(),
+            message,
+            &ActionHysteresis::default(),
+            Instant::now(),
+        );
+        assert!(actual_action.is_none());
+
+        // Confirm metadata matches expected
+        {
+            let map_lock = topic_map_handle.lock();
+            let actual_metadata = map_lock.get(&expected_topic).unwrap();
+
+            assert_eq!(expected_metadata.count, actual_metadata.count);
+            assert_eq!(
+                expected_metadata.management_callback,
+                actual_metadata.management_callback,
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn unsubscribe_topic_test() {
+        let test_manager = TopicManager::new();
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+        let initial_count = 2;
+        let expected_mgmt_uri = "test.uri".to_string();
+        let initial_metadata =
+            TopicMetadata::new(String::new(), initial_count, Some(expected_mgmt_uri));
+        let initial_time = initial_metadata.get_timeout();
+
+        // Insert existing topic
+        {
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(expected_topic.clone(), initial_metadata);
+        }
+
+        let message = MonitorMessage {
+            context: expected_topic.clone(),
+            action: PubSubAction::Unsubscribe,
+            client_id: None,
+            identity: None,
+        };
+
+        let actual_action = TopicManager::update_topic(
+            topic_map_handle.clone(),
+            message,
+            &ActionHysteresis::default(),
+            Instant::now(),
+        );
+        assert!(actual_action.is_none());
+
+        // Confirm last active time and count was updated
+        {
+            let map_lock = topic_map_handle.lock();
+            let actual_metadata = map_lock.get(&expected_topic).unwrap();
+
+            assert_ne!(initial_time, actual_metadata.get_timeout());
+            assert_eq!(initial_count - 1, actual_metadata.count);
+        }
+    }
+
+    #[tokio::test]
+    async fn unsubscribe_topic_with_one_sub_test() {
+        let test_manager = TopicManager::new();
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+        let initial_count = 1;
+        let expected_mgmt_uri = "test.uri".to_string();
+        let initial_metadata = TopicMetadata::new(
+            String::new(),
+            initial_count,
+            Some(expected_mgmt_uri.clone()),
+        );
+        let initial_time = initial_metadata.get_timeout();
+
+        // Insert existing topic
+        {
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(expected_topic.clone(), initial_metadata);
+        }
+
+        let message = MonitorMessage {
+            context: expected_topic.clone(),
+            action: PubSubAction::Unsubscribe,
+            client_id: None,
+            identity: None,
+        };
+
+        let actual_action = TopicManager::update_topic(
+            topic_map_handle.clone(),
+            message,
+            &ActionHysteresis::default(),
+            Instant::now(),
+        );
+        assert!(actual_action.is_some());
+
+        let expected_action_inner = TopicAction::Stop(TopicManagementInfo::new(
+            expected_topic.clone(),
+            expected_mgmt_uri,
+        ));
+        assert_eq!(expected_action_inner, actual_action.unwrap());
+
+        // Confirm last active time and count was updated
+        {
+            let map_lock = topic_map_handle.lock();
+            let actual_metadata = map_lock.get(&expected_topic).unwrap();
+
+            assert_ne!(initial_time, actual_metadata.get_timeout());
+            assert_eq!(initial_count - 1, actual_metadata.count);
+        }
+    }
+
+    #[tokio::test]
+    async fn unsubscribe_topic_with_no_subs_test() {
+        let test_manager = TopicManager::new();
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+        let initial_count = 0;
+        let expected_mgmt_uri = "test.uri".to_string();
+        let initial_metadata = TopicMetadata::new(
+            String::new(),
+            initial_count,
+            Some(expected_mgmt_uri.clone()),
+        );
+        let initial_time = initial_metadata.get_timeout();
+
+        // Insert existing topic
+        {
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(expected_topic.clone(), initial_metadata);
+        }
+
+        let message = MonitorMessage {
+            context: expected_topic.clone(),
+            action: PubSubAction::Unsubscribe,
+            client_id: None,
+            identity: None,
+        };
+
+        let actual_action = TopicManager::update_topic(
+            topic_map_handle.clone(),
+            message,
+            &ActionHysteresis::default(),
+            Instant::now(),
+        );
+        assert!(actual_action.is_some());
+
+        let expected_action_inner = TopicAction::Stop(TopicManagementInfo::new(
+            expected_topic.clone(),
+            expected_mgmt_uri,
+        ));
+        assert_eq!(expected_action_inner, actual_action.unwrap());
+
+        // Confirm last active time and count was updated
+        {
+            let map_lock = topic_map_handle.lock();
+            let actual_metadata = map_lock.get(&expected_topic).unwrap();
+
+            assert_ne!(initial_time, actual_metadata.get_timeout());
+            assert_eq!(initial_count, actual_metadata.count);
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribe_and_unsubscribe_tracks_subscriber_test() {
+        let test_manager = TopicManager::new();
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+        let expected_subscriber = "sub_test".to_string();
+
+        let subscribe = MonitorMessage {
+            context: expected_topic.clone(),
+            action: PubSubAction::Subscribe,
+            client_id: Some(expected_subscriber.clone()),
+            identity: None,
+        };
+
+        let _ = TopicManager::update_topic(
+            topic_map_handle.clone(),
+            subscribe,
+            &ActionHysteresis::default(),
+            Instant::now(),
+        );
+
+        {
+            let map_lock = topic_map_handle.lock();
+            let actual_metadata = map_lock.get(&expected_topic).unwrap();
+            assert!(actual_metadata.has_subscriber(&expected_subscriber));
+        }
+
+        let unsubscribe = MonitorMessage {
+            context: expected_topic.clone(),
+            action: PubSubAction::Unsubscribe,
+            client_id: Some(expected_subscriber.clone()),
+            identity: None,
+        };
+
+        let _ = TopicManager::update_topic(
+            topic_map_handle.clone(),
+            unsubscribe,
+            &ActionHysteresis::default(),
+            Instant::now(),
+        );
+
+        {
+            let map_lock = topic_map_handle.lock();
+            let actual_metadata = map_lock.get(&expected_topic).unwrap();
+            assert!(!actual_metadata.has_subscriber(&expected_subscriber));
+            assert_eq!(0, actual_metadata.count);
+        }
+    }
+
+    #[test]
+    fn action_hysteresis_stop_due_test() {
+        let started_at = Instant::now();
+        let hysteresis = ActionHysteresis::new(Duration::from_secs(20), Duration::from_secs(5));
+
+        // The publisher keeps running for the minimum active duration.
+        assert_eq!(
+            started_at + Duration::from_secs(20),
+            hysteresis.stop_due(Some(started_at), started_at + Duration::from_secs(1))
+        );
+        // Past it, the STOP is only debounced.
+        assert_eq!(
+            started_at + Duration::from_secs(35),
+            hysteresis.stop_due(Some(started_at), started_at + Duration::from_secs(30))
+        );
+        assert_eq!(
+            started_at + Duration::from_secs(5),
+            hysteresis.stop_due(None, started_at)
+        );
+
+        // STOP actions are not delayed by default.
+        assert_eq!(
+            started_at,
+            ActionHysteresis::default().stop_due(Some(started_at), started_at)
+        );
+    }
+
+    #[tokio::test]
+    async fn hysteresis_delays_stop_test() {
+        let test_manager = TopicManager::new();
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+        let expected_mgmt_uri = "test.uri".to_string();
+        let start = Instant::now();
+        topic_map_handle.lock().insert(
+            expected_topic.clone(),
+            TopicMetadata::new_at(String::new(), 0, Some(expected_mgmt_uri.clone()), start),
+        );
+
+        let hysteresis = ActionHysteresis::new(Duration::from_secs(20), Duration::from_secs(5));
+        let update = |action, secs| {
+            let message = MonitorMessage {
+                context: expected_topic.clone(),
+                action,
+                client_id: None,
+                identity: None,
+            };
+
+            TopicManager::update_topic(
+                topic_map_handle.clone(),
+                message,
+                &hysteresis,
+                start + Duration::from_secs(secs),
+            )
+        };
+        let info = || TopicManagementInfo::new(expected_topic.clone(), expected_mgmt_uri.clone());
+        let stop_due = || topic_map_handle.lock()[&expected_topic].stop_due();
+
+        assert_eq!(
+            Some(TopicAction::Start(info())),
+            update(PubSubAction::Subscribe, 0)
+        );
+
+        // A subscriber that reconnects doesn't stop and restart the publisher.
+        assert_eq!(None, update(PubSubAction::Unsubscribe, 1));
+        assert_eq!(Some(start + Duration::from_secs(20)), stop_due());
+        {
+            let map_lock = topic_map_handle.lock();
+            let record = map_lock[&expected_topic].action_history().last().unwrap();
+            assert_eq!(TopicEventKind::Stop, record.kind);
+            assert_eq!("UNSUBSCRIBE", record.cause);
+            assert_eq!(ActionOutcome::Delayed, record.outcome);
+        }
+        assert_eq!(None, update(PubSubAction::Subscribe, 2));
+        assert_eq!(None, stop_due());
+
+        // Past the minimum active duration, the STOP is debounced.
+        assert_eq!(None, update(PubSubAction::Unsubscribe, 30));
+        assert_eq!(Some(start + Duration::from_secs(35)), stop_due());
+        assert_eq!(
+            Some(TopicAction::Stop(info())),
+            update(PubSubAction::DeferredStop, 35)
+        );
+        assert_eq!(None, stop_due());
+
+        // A delayed STOP that was already sent is not sent again.
+        assert_eq!(None, update(PubSubAction::DeferredStop, 36));
+        assert_eq!(
+            Some(TopicAction::Start(info())),
+            update(PubSubAction::Subscribe, 40)
+        );
+    }
+
+    #[tokio::test]
+    async fn cleanup_topics_sends_due_deferred_stop_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_manager = TopicManager::with_clock(clock.clone());
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+        let hysteresis = ActionHysteresis::new(Duration::ZERO, Duration::from_secs(5));
+        topic_map_handle.lock().insert(
+            expected_topic.clone(),
+            TopicMetadata::new_at(String::new(), 1, Some("test.uri".to_string()), clock.now()),
+        );
+
+        let unsubscribe = MonitorMessage {
+            context: expected_topic.clone(),
+            action: PubSubAction::Unsubscribe,
+            client_id: None,
+            identity: None,
+        };
+        let action = TopicManager::update_topic(
+            topic_map_handle.clone(),
+            unsubscribe,
+            &hysteresis,
+            clock.now(),
+        );
+        assert!(action.is_none());
+
+        let (sender, receiver) = mpsc::channel::<MonitorMessage>();
+
+        // No STOP is expected before it is due.
+        clock.advance(Duration::from_secs(4));
+        TopicManager::cleanup_topics(
+            topic_map_handle.clone(),
+            sender.clone(),
+            true,
+            DEFAULT_PLACEHOLDER_TTL,
+            None,
+            clock.now(),
+        )
+        .await;
+        assert!(receiver.try_recv().is_err());
+
+        clock.advance(Duration::from_secs(1));
+        TopicManager::cleanup_topics(
+            topic_map_handle.clone(),
+            sender,
+            true,
+            DEFAULT_PLACEHOLDER_TTL,
+            None,
+            clock.now(),
+        )
+        .await;
+
+        let actual_msg = receiver.try_recv().unwrap();
+        assert_eq!(expected_topic, actual_msg.context);
+        assert_eq!(PubSubAction::DeferredStop, actual_msg.action);
+    }
+
+    #[tokio::test]
+    async fn pre_warmed_topic_test() {
+        let test_manager = TopicManager::new();
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+        let expected_mgmt_uri = "test.uri".to_string();
+        let mut initial_metadata =
+            TopicMetadata::new(String::new(), 0, Some(expected_mgmt_uri.clone()));
+        initial_metadata.enable_pre_warm();
+        topic_map_handle
+            .lock()
+            .insert(expected_topic.clone(), initial_metadata);
+
+        let update = |action| {
+            let message = MonitorMessage {
+                context: expected_topic.clone(),
+                action,
+                client_id: None,
+                identity: None,
+            };
+
+            TopicManager::update_topic(
+                topic_map_handle.clone(),
+                message,
+                &ActionHysteresis::default(),
+                Instant::now(),
+            )
+        };
+        let info = || TopicManagementInfo::new(expected_topic.clone(), expected_mgmt_uri.clone());
+
+        // The publisher is started before the topic has subscribers, and only once.
+        assert_eq!(Some(TopicAction::Start(info())), update(PubSubAction::Warm));
+        assert_eq!(None, update(PubSubAction::Warm));
+
+        // The first subscriber does not start the publisher again, and the last one leaving does
+        // not stop it.
+        assert_eq!(None, update(PubSubAction::Subscribe));
+        assert_eq!(None, update(PubSubAction::Unsubscribe));
+
+        // The publisher is stopped once the topic times out without subscribers, and started
+        // again by the next subscriber.
+        assert_eq!(
+            Some(TopicAction::Stop(info())),
+            update(PubSubAction::Timeout)
+        );
+        assert_eq!(
+            Some(TopicAction::Start(info())),
+            update(PubSubAction::Subscribe)
+        );
+        assert_eq!(None, update(PubSubAction::Unsubscribe));
+    }
+
+    #[tokio::test]
+    async fn adopted_topic_test() {
+        let test_manager = TopicManager::new();
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "vehicle/speed".to_string();
+        let expected_mgmt_uri = "test.uri".to_string();
+
+        let update = |action| {
+            let message = MonitorMessage {
+                context: expected_topic.clone(),
+                action,
+                client_id: None,
+                identity: None,
+            };
+
+            TopicManager::update_topic(
+                topic_map_handle.clone(),
+                message,
+                &ActionHysteresis::default(),
+                Instant::now(),
+            )
+        };
+
+        // A subscription to the topic before it is adopted only creates a placeholder.
+        assert_eq!(None, update(PubSubAction::Subscribe));
+
+        {
+            let mut curr_topics = topic_map_handle.lock();
+            let metadata = curr_topics.get_mut(&expected_topic).unwrap();
+            metadata.client_id = "legacy_pub".to_string();
+            metadata.management_callback = Some(expected_mgmt_uri.clone());
+        }
+
+        // The waiting subscriber starts the publisher once the topic is adopted, and only once.
+        assert_eq!(
+            Some(TopicAction::Start(TopicManagementInfo::new(
+                expected_topic.clone(),
+                expected_mgmt_uri.clone()
+            ))),
+            update(PubSubAction::Adopt)
+        );
+        assert_eq!(None, update(PubSubAction::Adopt));
+        assert_eq!(None, update(PubSubAction::Subscribe));
+    }
+
+    #[tokio::test]
+    async fn lifecycle_epoch_test() {
+        let test_manager = TopicManager::new();
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "vehicle/speed".to_string();
+        topic_map_handle.lock().insert(
+            expected_topic.clone(),
+            TopicMetadata::new("test_pub".to_string(), 0, Some("test.uri".to_string())),
+        );
+
+        let update = |action| {
+            let message = MonitorMessage {
+                context: expected_topic.clone(),
+                action,
+                client_id: None,
+                identity: None,
+            };
+
+            TopicManager::update_topic(
+                topic_map_handle.clone(),
+                message,
+                &ActionHysteresis::default(),
+                Instant::now(),
+            )
+        };
+        let lifecycle_epoch = || topic_map_handle.lock()[&expected_topic].lifecycle_epoch();
+
+        assert_eq!(0, lifecycle_epoch());
+
+        // Each start of the publisher begins a new epoch, which a stop doesn't change.
+        assert!(matches!(
+            update(PubSubAction::Subscribe),
+            Some(TopicAction::Start(_))
+        ));
+        assert_eq!(1, lifecycle_epoch());
+        assert!(matches!(
+            update(PubSubAction::Unsubscribe),
+            Some(TopicAction::Stop(_))
+        ));
+        assert_eq!(1, lifecycle_epoch());
+
+        update(PubSubAction::Subscribe);
+        update(PubSubAction::Subscribe);
+        assert_eq!(2, lifecycle_epoch());
+
+        // The epoch survives spilling the topic to disk.
+        let now = Instant::now();
+        let spilled = topic_map_handle.lock()[&expected_topic].to_spilled(now);
+        let restored = TopicMetadata::from_spilled(spilled, now);
+        assert_eq!(2, restored.lifecycle_epoch());
+    }
+
+    #[tokio::test]
+    async fn update_topic_expires_idle_topic_test() {
+        let test_manager = TopicManager::new();
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+        let expected_mgmt_uri = "test.uri".to_string();
+        topic_map_handle.lock().insert(
+            expected_topic.clone(),
+            TopicMetadata::new(String::new(), 1, Some(expected_mgmt_uri.clone())),
+        );
+
+        let update = || {
+            TopicManager::update_topic(
+                topic_map_handle.clone(),
+                MonitorMessage {
+                    context: expected_topic.clone(),
+                    action: PubSubAction::IdleExpired,
+                    client_id: None,
+                    identity: None,
+                },
+                &ActionHysteresis::default(),
+                Instant::now(),
+            )
+        };
+
+        // A topic that found a subscriber since the cleanup is kept.
+        assert!(update().is_none());
+        assert!(topic_map_handle.lock().contains_key(&expected_topic));
+
+        topic_map_handle
+            .lock()
+            .get_mut(&expected_topic)
+            .unwrap()
+            .count = 0;
+        assert_eq!(
+            Some(TopicAction::Expire(TopicManagementInfo::new(
+                expected_topic.clone(),
+                expected_mgmt_uri
+            ))),
+            update()
+        );
+        assert!(topic_map_handle.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn subscriber_joined_topic_test() {
+        let test_manager = TopicManager::new();
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+        let expected_mgmt_uri = "test.uri".to_string();
+        let mut initial_metadata =
+            TopicMetadata::new(String::new(), 0, Some(expected_mgmt_uri.clone()));
+        initial_metadata.enable_subscriber_joined_notifications();
+        topic_map_handle
+            .lock()
+            .insert(expected_topic.clone(), initial_metadata);
+
+        let update = |action| {
+            let message = MonitorMessage {
+                context: expected_topic.clone(),
+                action,
+                client_id: None,
+                identity: None,
+            };
+
+            TopicManager::update_topic(
+                topic_map_handle.clone(),
+                message,
+                &ActionHysteresis::default(),
+                Instant::now(),
+            )
+        };
+        let info = || TopicManagementInfo::new(expected_topic.clone(), expected_mgmt_uri.clone());
+
+        // The first subscriber starts the publisher, later subscribers are announced to it.
+        assert_eq!(
+            Some(TopicAction::Start(info())),
+            update(PubSubAction::Subscribe)
+        );
+        assert_eq!(
+            Some(TopicAction::SubscriberJoined(info())),
+            update(PubSubAction::Subscribe)
+        );
+
+        // A subscriber joining a pre-warmed topic joins a publisher that is already running.
+        assert!(update(PubSubAction::Unsubscribe).is_none());
+        assert!(matches!(
+            update(PubSubAction::Unsubscribe),
+            Some(TopicAction::Stop(_))
+        ));
+        topic_map_handle
+            .lock()
+            .get_mut(&expected_topic)
+            .unwrap()
+            .enable_pre_warm();
+        assert_eq!(Some(TopicAction::Start(info())), update(PubSubAction::Warm));
+        assert_eq!(
+            Some(TopicAction::SubscriberJoined(info())),
+            update(PubSubAction::Subscribe)
+        );
+    }
+
+    #[tokio::test]
+    async fn delete_request_topic_deletes_response_topics_test() {
+        let test_manager = TopicManager::new();
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let request_topic = "request".to_string();
+        let response_topic = "response".to_string();
+
+        {
+            let mut request_metadata =
+                TopicMetadata::new("pub_test".to_string(), 0, Some("test.uri".to_string()));
+            request_metadata.add_response_topic("req_test".to_string(), response_topic.clone());
+            let mut response_metadata = TopicMetadata::new("pub_test".to_string(), 0, None);
+            response_metadata.set_request_topic(request_topic.clone());
+
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(request_topic.clone(), request_metadata);
+            map_lock.insert(response_topic.clone(), response_metadata);
+        }
+
+        let delete = |topic: &String| MonitorMessage {
+            context: topic.clone(),
+            action: PubSubAction::Delete,
+            client_id: None,
+            identity: None,
+        };
+
+        let action = TopicManager::update_topic(
+            topic_map_handle.clone(),
+            delete(&request_topic),
+            &ActionHysteresis::default(),
+            Instant::now(),
+        );
+        assert!(matches!(action, Some(TopicAction::Delete(_))));
+        assert!(topic_map_handle
+            .lock()
+            .get(&response_topic)
+            .unwrap()
+            .is_deleted());
+
+        // The response topic is deleted on the broker even though it has no management callback.
+        let action = TopicManager::update_topic(
+            topic_map_handle.clone(),
+            delete(&response_topic),
+            &ActionHysteresis::default(),
+            Instant::now(),
+        );
+        assert_eq!(
+            Some(TopicAction::Delete(TopicManagementInfo::new(
+                response_topic,
+                String::new()
+            ))),
+            action
+        );
+        assert!(topic_map_handle.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn unsubscribe_fails_over_active_consumer_test() {
+        let test_manager = TopicManager::new();
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+
+        {
+            let mut metadata = TopicMetadata::new(String::new(), 2, None);
+            metadata.add_subscriber("sub_a".to_string());
+            metadata.add_subscriber("sub_b".to_string());
+            metadata.enable_consumer_group();
+            let group = metadata.consumer_group_mut().unwrap();
+            group.claim("sub_a".to_string());
+            group.claim("sub_b".to_string());
+
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(expected_topic.clone(), metadata);
+        }
+
+        let unsubscribe = MonitorMessage {
+            context: expected_topic.clone(),
+            action: PubSubAction::Unsubscribe,
+            client_id: Some("sub_a".to_string()),
+            identity: None,
+        };
+
+        let _ = TopicManager::update_topic(
+            topic_map_handle.clone(),
+            unsubscribe,
+            &ActionHysteresis::default(),
+            Instant::now(),
+        );
+
+        let mut map_lock = topic_map_handle.lock();
+        let group = map_lock
+            .get_mut(&expected_topic)
+            .unwrap()
+            .consumer_group_mut()
+            .unwrap();
+        assert_eq!(Some("sub_b"), group.active_consumer());
+        assert_eq!(1, group.len());
+    }
+
+    #[test]
+    fn release_publisher_client_fails_over_to_standby_test() {
+        let mut active_topics = ActiveTopicsMap::new();
+
+        let mut redundant = TopicMetadata::new("active".to_string(), 0, None);
+        assert!(redundant.add_publisher_client("standby".to_string()));
+        assert!(!redundant.add_publisher_client("active".to_string()));
+        active_topics.insert("redundant".to_string(), redundant);
+        active_topics.insert(
+            "single".to_string(),
+            TopicMetadata::new("active".to_string(), 0, None),
+        );
+        active_topics.insert(
+            "other".to_string(),
+            TopicMetadata::new("other".to_string(), 0, None),
+        );
+
+        let orphaned = TopicManager::release_publisher_client(&mut active_topics, "active");
+        assert_eq!(vec!["single".to_string()], orphaned);
+
+        let redundant = active_topics.get_mut("redundant").unwrap();
+        assert_eq!("standby", redundant.client_id);
+        assert_eq!(vec!["standby"], redundant.publisher_client_ids());
+
+        // The topic is deleted once its last publisher disconnects.
+        let orphaned = TopicManager::release_publisher_client(&mut active_topics, "standby");
+        assert_eq!(vec!["redundant".to_string()], orphaned);
+    }
+
+    #[tokio::test]
+    async fn cleanup_topics_with_manual_clock_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_manager = TopicManager::with_clock(clock.clone());
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+        let initial_metadata =
+            TopicMetadata::new_at(String::new(), 0, Some("test.uri".to_string()), clock.now());
+
+        // Insert existing topic with no active subs
+        {
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(expected_topic.clone(), initial_metadata);
+        }
+
+        let (sender, receiver) = mpsc::channel::<MonitorMessage>();
+
+        // No timeout is expected before the threshold has passed.
+        clock.advance(Duration::from_secs(30));
+        TopicManager::cleanup_topics(
+            topic_map_handle.clone(),
+            sender.clone(),
+            true,
+            DEFAULT_PLACEHOLDER_TTL,
+            None,
+            clock.now(),
+        )
+        .await;
+        assert!(receiver.try_recv().is_err());
+
+        // A timeout is expected once the threshold has passed.
+        clock.advance(Duration::from_secs(1));
+        TopicManager::cleanup_topics(
+            topic_map_handle.clone(),
+            sender,
+            true,
+            DEFAULT_PLACEHOLDER_TTL,
+            None,
+            clock.now(),
+        )
+        .await;
+
+        let actual_msg = receiver.try_recv().unwrap();
+        assert_eq!(expected_topic, actual_msg.context);
+        assert_eq!(PubSubAction::Timeout, actual_msg.action);
+    }
+
+    #[tokio::test]
+    async fn cleanup_topics_respects_idle_timeout_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_manager = TopicManager::with_clock(clock.clone());
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+        let mut initial_metadata =
+            TopicMetadata::new_at(String::new(), 0, Some("test.uri".to_string()), clock.now());
+        initial_metadata.set_idle_timeout(Duration::from_secs(120));
+
+        {
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(expected_topic.clone(), initial_metadata);
+        }
+
+        let (sender, receiver) = mpsc::channel::<MonitorMessage>();
+
+        // The default idle timeout doesn't apply to the topic.
+        clock.advance(DEFAULT_IDLE_TIMEOUT + Duration::from_secs(1));
+        TopicManager::cleanup_topics(
+            topic_map_handle.clone(),
+            sender.clone(),
+            true,
+            DEFAULT_PLACEHOLDER_TTL,
+            None,
+            clock.now(),
+        )
+        .await;
+        assert!(receiver.try_recv().is_err());
+
+        clock.advance(Duration::from_secs(90));
+        TopicManager::cleanup_topics(
+            topic_map_handle.clone(),
+            sender,
+            true,
+            DEFAULT_PLACEHOLDER_TTL,
+            None,
+            clock.now(),
+        )
+        .await;
+
+        let actual_msg = receiver.try_recv().unwrap();
+        assert_eq!(expected_topic, actual_msg.context);
+        assert_eq!(PubSubAction::Timeout, actual_msg.action);
+    }
+
+    #[tokio::test]
+    async fn cleanup_topics_expires_idle_topics_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_manager = TopicManager::with_clock(clock.clone());
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+        let max_idle = Some(Duration::from_secs(300));
+
+        {
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(
+                expected_topic.clone(),
+                TopicMetadata::new_at(String::new(), 0, Some("test.uri".to_string()), clock.now()),
+            );
+        }
+
+        let (sender, receiver) = mpsc::channel::<MonitorMessage>();
+
+        // The publisher is reminded until the maximum idle duration is reached.
+        clock.advance(DEFAULT_IDLE_TIMEOUT + Duration::from_secs(1));
+        TopicManager::cleanup_topics(
+            topic_map_handle.clone(),
+            sender.clone(),
+            true,
+            DEFAULT_PLACEHOLDER_TTL,
+            max_idle,
+            clock.now(),
+        )
+        .await;
+        assert_eq!(PubSubAction::Timeout, receiver.try_recv().unwrap().action);
+
+        clock.advance(Duration::from_secs(300));
+        TopicManager::cleanup_topics(
+            topic_map_handle.clone(),
+            sender,
+            true,
+            DEFAULT_PLACEHOLDER_TTL,
+            max_idle,
+            clock.now(),
+        )
+        .await;
+
+        let actual_msg = receiver.try_recv().unwrap();
+        assert_eq!(expected_topic, actual_msg.context);
+        assert_eq!(PubSubAction::IdleExpired, actual_msg.action);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn cleanup_topics_suspends_timeouts_while_disconnected_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_manager = TopicManager::with_clock(clock.clone());
+        let topic_map_handle = test_manager.get_active_topics_handle();
+
+        {
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(
+                "test".to_string(),
+                TopicMetadata::new_at(String::new(), 0, Some("test.uri".to_string()), clock.now()),
+            );
+        }
+
+        let (sender, receiver) = mpsc::channel::<MonitorMessage>();
+
+        // No timeout is sent while the broker is disconnected, even past the threshold.
+        clock.advance(Duration::from_secs(60));
+        TopicManager::cleanup_topics(
+            topic_map_handle.clone(),
+            sender.clone(),
+            false,
+            DEFAULT_PLACEHOLDER_TTL,
+            None,
+            clock.now(),
+        )
+        .await;
+        assert!(receiver.try_recv().is_err());
+
+        TopicManager::cleanup_topics(
+            topic_map_handle.clone(),
+            sender,
+            true,
+            DEFAULT_PLACEHOLDER_TTL,
+            None,
+            clock.now(),
+        )
+        .await;
+        assert_eq!(PubSubAction::Timeout, receiver.try_recv().unwrap().action);
+    }
+
+    #[tokio::test]
+    async fn cleanup_topics_expires_placeholder_topics_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_manager = TopicManager::with_clock(clock.clone());
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let cleanup_stats = test_manager.get_cleanup_stats_handle();
+        let ttl = Duration::from_secs(60);
+
+        // A subscription to a topic no publisher created leaves a placeholder.
+        let subscribe_msg = MonitorMessage {
+            context: "typo".to_string(),
+            action: PubSubAction::Subscribe,
+            client_id: Some("sub_1".to_string()),
+            identity: None,
+        };
+        TopicManager::update_topic(
+            topic_map_handle.clone(),
+            subscribe_msg,
+            &ActionHysteresis::default(),
+            clock.now(),
+        );
+        topic_map_handle.lock().insert(
+            "created".to_string(),
+            TopicMetadata::new_at(
+                "pub_1".to_string(),
+                1,
+                Some("test.uri".to_string()),
+                clock.now(),
+            ),
+        );
+        assert!(topic_map_handle.lock()["typo"].is_placeholder());
+        assert!(!topic_map_handle.lock()["created"].is_placeholder());
+
+        let (sender, receiver) = mpsc::channel::<MonitorMessage>();
+
+        clock.advance(ttl);
+        TopicManager::cleanup_topics(
+            topic_map_handle.clone(),
+            sender.clone(),
+            true,
+            ttl,
+            None,
+            clock.now(),
+        )
+        .await;
+        assert!(receiver.try_recv().is_err());
+
+        clock.advance(Duration::from_secs(1));
+        TopicManager::cleanup_topics(
+            topic_map_handle.clone(),
+            sender,
+            true,
+            ttl,
+            None,
+            clock.now(),
+        )
+        .await;
+
+        let actual_msg = receiver.try_recv().unwrap();
+        assert_eq!("typo", actual_msg.context);
+        assert_eq!(PubSubAction::ExpirePlaceholder, actual_msg.action);
+        assert!(receiver.try_recv().is_err());
+
+        TopicManager::handle_topic_action(
+            actual_msg,
+            topic_map_handle.clone(),
+            mpsc::channel().0,
+            &test_manager.get_deletion_messages_handle(),
+            cleanup_stats.clone(),
+            &test_manager.get_topic_events_handle(),
+            &test_manager.get_publisher_callbacks_handle(),
+            clock.now(),
+        )
+        .await;
+
+        let map_lock = topic_map_handle.lock();
+        assert!(!map_lock.contains_key("typo"));
+        assert!(map_lock.contains_key("created"));
+
+        let report = cleanup_stats.lock().report(&map_lock, None, clock.now());
+        assert_eq!(0, report.placeholder_topics);
+        assert_eq!(1, report.placeholders_expired);
+        assert_eq!(0, report.topics_reaped);
+    }
+
+    #[test]
+    fn handle_connection_state_resets_timeouts_on_reconnect_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_manager = TopicManager::with_clock(clock.clone());
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let broker_connected = test_manager.broker_connected.clone();
+
+        topic_map_handle.lock().insert(
+            "test".to_string(),
+            TopicMetadata::new_at(String::new(), 0, None, clock.now()),
+        );
+
+        clock.advance(Duration::from_secs(60));
+        TopicManager::handle_connection_state(
+            false,
+            &broker_connected,
+            topic_map_handle.clone(),
+            clock.now(),
+        );
+        assert!(!broker_connected.load(Ordering::SeqCst));
+
+        clock.advance(Duration::from_secs(60));
+        TopicManager::handle_connection_state(
+            true,
+            &broker_connected,
+            topic_map_handle.clone(),
+            clock.now(),
+        );
+        assert!(broker_connected.load(Ordering::SeqCst));
+
+        let map_lock = topic_map_handle.lock();
+        assert_eq!(clock.now(), map_lock.get("test").unwrap().get_timeout());
+    }
+
+    #[tokio::test]
+    async fn cleanup_topics_expires_leases_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_manager = TopicManager::with_clock(clock.clone());
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+        let expected_subscriber = "sub_1".to_string();
+
+        // Insert existing topic with a leased subscriber.
+        {
+            let mut metadata =
+                TopicMetadata::new_at(String::new(), 1, Some("test.uri".to_string()), clock.now());
+            metadata.add_subscriber(expected_subscriber.clone());
+            assert!(metadata.renew_lease(
+                expected_subscriber.clone(),
+                clock.now() + Duration::from_secs(10)
+            ));
+
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(expected_topic.clone(), metadata);
+        }
+
+        let (sender, receiver) = mpsc::channel::<MonitorMessage>();
+
+        // The lease has not expired yet.
+        clock.advance(Duration::from_secs(9));
+        TopicManager::cleanup_topics(
+            topic_map_handle.clone(),
+            sender.clone(),
+            true,
+            DEFAULT_PLACEHOLDER_TTL,
+            None,
+            clock.now(),
+        )
+        .await;
+        assert!(receiver.try_recv().is_err());
+
+        // An expired lease is reported as an unsubscribe, once.
+        clock.advance(Duration::from_secs(1));
+        TopicManager::cleanup_topics(
+            topic_map_handle.clone(),
+            sender.clone(),
+            true,
+            DEFAULT_PLACEHOLDER_TTL,
+            None,
+            clock.now(),
+        )
+        .await;
+
+        let actual_msg = receiver.try_recv().unwrap();
+        assert_eq!(expected_topic, actual_msg.context);
+        assert_eq!(PubSubAction::Unsubscribe, actual_msg.action);
+        assert_eq!(Some(expected_subscriber), actual_msg.client_id);
+
+        TopicManager::cleanup_topics(
+            topic_map_handle.clone(),
+            sender,
+            true,
+            DEFAULT_PLACEHOLDER_TTL,
+            None,
+            clock.now(),
+        )
+        .await;
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn cleanup_topics_keeps_lease_until_subscribe_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_manager = TopicManager::with_clock(clock.clone());
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+        let expected_subscriber = "sub_1".to_string();
+
+        // Insert existing topic with a lease whose subscribe was not handled yet.
+        {
+            let mut metadata =
+                TopicMetadata::new_at(String::new(), 0, Some("test.uri".to_string()), clock.now());
+            assert!(metadata.renew_lease(
+                expected_subscriber.clone(),
+                clock.now() + Duration::from_secs(10)
+            ));
+
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(expected_topic.clone(), metadata);
+        }
+
+        let (sender, receiver) = mpsc::channel::<MonitorMessage>();
+
+        // The lease expired before the subscriber was counted, so it is kept.
+        clock.advance(Duration::from_secs(10));
+        TopicManager::cleanup_topics(
+            topic_map_handle.clone(),
+            sender.clone(),
+            true,
+            DEFAULT_PLACEHOLDER_TTL,
+            None,
+            clock.now(),
+        )
+        .await;
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(
+            1,
+            topic_map_handle
+                .lock()
+                .get(&expected_topic)
+                .unwrap()
+                .lease_count()
+        );
+
+        let subscribe = MonitorMessage {
+            context: expected_topic.clone(),
+            action: PubSubAction::Subscribe,
+            client_id: Some(expected_subscriber.clone()),
+            identity: None,
+        };
+        let _ = TopicManager::update_topic(
+            topic_map_handle.clone(),
+            subscribe,
+            &ActionHysteresis::default(),
+            clock.now(),
+        );
+
+        // Once counted, the subscriber is unsubscribed.
+        TopicManager::cleanup_topics(
+            topic_map_handle.clone(),
+            sender,
+            true,
+            DEFAULT_PLACEHOLDER_TTL,
+            None,
+            clock.now(),
+        )
+        .await;
+
+        let actual_msg = receiver.try_recv().unwrap();
+        assert_eq!(PubSubAction::Unsubscribe, actual_msg.action);
+        assert_eq!(Some(expected_subscriber), actual_msg.client_id);
+    }
+
+    #[tokio::test]
+    async fn unsubscribe_counts_subscriber_once_test() {
+        let test_manager = TopicManager::new();
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+
+        {
+            let mut metadata = TopicMetadata::new(String::new(), 2, None);
+            metadata.add_subscriber("sub_1".to_string());
+            metadata.add_subscriber("sub_2".to_string());
+
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(expected_topic.clone(), metadata);
+        }
+
+        // The unsubscribe for an expired lease and the subscriber's own unsubscribe.
+        for _ in 0..2 {
+            let unsubscribe = MonitorMessage {
+                context: expected_topic.clone(),
+                action: PubSubAction::Unsubscribe,
+                client_id: Some("sub_1".to_string()),
+                identity: None,
+            };
+            let _ = TopicManager::update_topic(
+                topic_map_handle.clone(),
+                unsubscribe,
+                &ActionHysteresis::default(),
+                Instant::now(),
+            );
+        }
+
+        let map_lock = topic_map_handle.lock();
+        let actual_metadata = map_lock.get(&expected_topic).unwrap();
+        assert_eq!(1, actual_metadata.count);
+        assert!(actual_metadata.has_subscriber("sub_2"));
+    }
+
+    #[tokio::test]
+    async fn duplicate_subscribe_counts_subscriber_once_test() {
+        let test_manager = TopicManager::new();
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+        let expected_mgmt_uri = "test.uri".to_string();
+
+        {
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(
+                expected_topic.clone(),
+                TopicMetadata::new(String::new(), 0, Some(expected_mgmt_uri.clone())),
+            );
+        }
+
+        // The subscribe for a lease and the subscribe reported by the broker.
+        let mut actual_actions = Vec::new();
+        for _ in 0..2 {
+            let subscribe = MonitorMessage {
+                context: expected_topic.clone(),
+                action: PubSubAction::Subscribe,
+                client_id: Some("sub_1".to_string()),
+                identity: None,
+            };
+            actual_actions.push(TopicManager::update_topic(
+                topic_map_handle.clone(),
+                subscribe,
+                &ActionHysteresis::default(),
+                Instant::now(),
+            ));
+        }
+        assert!(matches!(actual_actions[0], Some(TopicAction::Start(_))));
+        assert!(actual_actions[1].is_none());
+        assert_eq!(
+            1,
+            topic_map_handle.lock().get(&expected_topic).unwrap().count
+        );
+
+        let unsubscribe = MonitorMessage {
+            context: expected_topic.clone(),
+            action: PubSubAction::Unsubscribe,
+            client_id: Some("sub_1".to_string()),
+            identity: None,
+        };
+        let actual_action = TopicManager::update_topic(
+            topic_map_handle.clone(),
+            unsubscribe,
+            &ActionHysteresis::default(),
+            Instant::now(),
+        );
+        assert_eq!(
+            Some(TopicAction::Stop(TopicManagementInfo::new(
+                expected_topic,
+                expected_mgmt_uri
+            ))),
+            actual_action
+        );
+    }
+
+    #[tokio::test]
+    async fn sessions_sharing_identity_counted_by_client_id_test() {
+        let test_manager = TopicManager::new();
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "cabin/temperature".to_string();
+        let expected_mgmt_uri = "test.uri".to_string();
+        let subscriber_acl = SubscriberAcl::new(
+            SubscriberIdentification::Identity,
+            &[SubscriberAclRule {
+                subscriber: "hvac".to_string(),
+                topics: vec!["cabin/#".to_string()],
+            }],
+        );
+
+        {
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(
+                expected_topic.clone(),
+                TopicMetadata::new(String::new(), 0, Some(expected_mgmt_uri.clone())),
+            );
+        }
+
+        // Two sessions of the same identity subscribe, then unsubscribe in turn.
+        let mut actual_actions = Vec::new();
+        for (action, client_id) in [
+            (PubSubAction::Subscribe, "hvac_1"),
+            (PubSubAction::Subscribe, "hvac_2"),
+            (PubSubAction::Unsubscribe, "hvac_1"),
+            (PubSubAction::Unsubscribe, "hvac_2"),
+        ] {
+            let msg = MonitorMessage {
+                context: expected_topic.clone(),
+                action,
+                client_id: Some(client_id.to_string()),
+                identity: Some("hvac".to_string()),
+            };
+            assert!(subscriber_acl.admits(&msg));
+
+            actual_actions.push(TopicManager::update_topic(
+                topic_map_handle.clone(),
+                msg,
+                &ActionHysteresis::default(),
+                Instant::now(),
+            ));
+        }
+
+        assert!(matches!(actual_actions[0], Some(TopicAction::Start(_))));
+        assert!(actual_actions[1].is_none());
+        // The other session is still subscribed.
+        assert!(actual_actions[2].is_none());
+        assert_eq!(
+            Some(TopicAction::Stop(TopicManagementInfo::new(
+                expected_topic,
+                expected_mgmt_uri
+            ))),
+            actual_actions[3]
+        );
+    }
+
+    #[tokio::test]
+    async fn cleanup_topics_drops_lease_after_grace_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_manager = TopicManager::with_clock(clock.clone());
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let expected_topic = "test".to_string();
+
+        // Insert existing topic with a lease whose subscribe is never handled.
+        {
+            let mut metadata =
+                TopicMetadata::new_at(String::new(), 0, Some("test.uri".to_string()), clock.now());
+            assert!(metadata.renew_lease("sub_1".to_string(), clock.now()));
+
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(expected_topic.clone(), metadata);
+        }
+
+        let (sender, receiver) = mpsc::channel::<MonitorMessage>();
+
+        clock.advance(LEASE_SUBSCRIBE_GRACE);
+        TopicManager::cleanup_topics(
+            topic_map_handle.clone(),
+            sender,
+            true,
+            DEFAULT_PLACEHOLDER_TTL,
+            None,
+            clock.now(),
+        )
+        .await;
+
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(
+            0,
+            topic_map_handle
+                .lock()
+                .get(&expected_topic)
+                .unwrap()
+                .lease_count()
+        );
+    }
+
+    #[tokio::test]
+    async fn cleanup_report_tracks_reaped_topics_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_manager = TopicManager::with_clock(clock.clone());
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let cleanup_stats = test_manager.get_cleanup_stats_handle();
+        let (deletion_sender, _deletion_receiver) = mpsc::channel::<MonitorMessage>();
+
+        // Insert two idle topics and one topic pending deletion.
+        {
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(
+                "idle_1".to_string(),
+                TopicMetadata::new_at(String::new(), 0, None, clock.now()),
+            );
+            clock.advance(Duration::from_secs(10));
+            map_lock.insert(
+                "idle_2".to_string(),
+                TopicMetadata::new_at(String::new(), 0, None, clock.now()),
+            );
+
+            let mut pending = TopicMetadata::new_at(String::new(), 0, None, clock.now());
+            pending.delete();
+            map_lock.insert("pending".to_string(), pending);
+        }
+
+        // Topics are deleted after 30 and 20 seconds without subscribers.
+        clock.advance(Duration::from_secs(20));
+        for topic in ["idle_1", "idle_2"] {
+            TopicManager::handle_topic_action(
+                MonitorMessage {
+                    context: topic.to_string(),
+                    action: PubSubAction::Delete,
+                    client_id: None,
+                    identity: None,
+                },
+                topic_map_handle.clone(),
+                deletion_sender.clone(),
+                &test_manager.get_deletion_messages_handle(),
+                cleanup_stats.clone(),
+                &test_manager.get_topic_events_handle(),
+                &test_manager.get_publisher_callbacks_handle(),
+                clock.now(),
+            )
+            .await;
+        }
+
+        let report = {
+            let map_lock = topic_map_handle.lock();
+            cleanup_stats.lock().report(&map_lock, None, clock.now())
+        };
+
+        let expected = CleanupReport {
+            topics_reaped: 2,
+            average_idle_time: Duration::from_secs(25),
+            pending_deletions: 1,
+            placeholder_topics: 0,
+            placeholders_expired: 0,
+            spilled_topics: 0,
+        };
+        assert_eq!(expected, report);
+
+        // Deletions older than the report window are no longer reported.
+        clock.advance(CLEANUP_REPORT_WINDOW + Duration::from_secs(1));
+        let report = {
+            let map_lock = topic_map_handle.lock();
+            cleanup_stats.lock().report(&map_lock, None, clock.now())
+        };
+        assert_eq!(0, report.topics_reaped);
+        assert_eq!(Duration::ZERO, report.average_idle_time);
+    }
+
+    #[tokio::test]
+    async fn manage_topic_on_delete_action() {
+        let delete_action = TopicAction::Delete(TopicManagementInfo::new(
+            "topic".to_string(),
+            "uri".to_string(),
+        ));
+
+        let ok_result = TopicManager::manage_topic(
+            delete_action,
+            CallbackDetails::default(),
+            ActionCasing::Upper,
+            None,
+            None,
+        )
+        .await;
+
+        // Expect that result is short circuited to ok. Since the Publisher connector is
+        // not mocked it will return an error if action does not match Delete.
+        assert!(ok_result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn manage_topic_sends_action_in_configured_casing_test() {
+        let publisher = FakePublisherCallback::new();
+        let (uri, server) = publisher.serve().await.unwrap();
+
+        let start_action = |topic: &str| {
+            TopicAction::Start(TopicManagementInfo::new(topic.to_string(), uri.clone()))
+        };
+        TopicManager::manage_topic(
+            start_action("upper"),
+            CallbackDetails::default(),
+            ActionCasing::Upper,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        TopicManager::manage_topic(
+            start_action("lower"),
+            CallbackDetails::default(),
+            ActionCasing::Lower,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        server.abort();
+
+        let expected = vec![
+            RecordedCallback {
+                topic: "upper".to_string(),
+                action: "START".to_string(),
+            },
+            RecordedCallback {
+                topic: "lower".to_string(),
+                action: "start".to_string(),
+            },
+        ];
+        assert_eq!(expected, publisher.callbacks());
+    }
+
+    #[cfg(feature = "grpc-compression")]
+    #[tokio::test]
+    async fn manage_topic_compresses_callback_test() {
+        let publisher = FakePublisherCallback::new();
+        let (uri, server) = publisher
+            .serve_with(|service| service.accept_compressed(GrpcCompressionKind::Zstd.encoding()))
+            .await
+            .unwrap();
+
+        let start_action = TopicAction::Start(TopicManagementInfo::new("topic".to_string(), uri));
+        TopicManager::manage_topic(
+            start_action,
+            CallbackDetails::default(),
+            ActionCasing::Upper,
+            Some(GrpcCompressionKind::Zstd),
+            None,
+        )
+        .await
+        .unwrap();
+        server.abort();
+
+        let expected = vec![RecordedCallback {
+            topic: "topic".to_string(),
+            action: "START".to_string(),
+        }];
+        assert_eq!(expected, publisher.callbacks());
+    }
+
+    #[test]
+    fn idempotency_cache_expires_entries_test() {
+        let clock = ManualClock::new();
+        let mut cache = IdempotencyCache::default();
+
+        cache.insert(
+            "pub_1".to_string(),
+            "key_1".to_string(),
+            "topic_1".to_string(),
+            clock.now(),
+        );
+
+        assert_eq!(
+            Some(IdempotencyState::Created("topic_1".to_string())),
+            cache.get("pub_1", "key_1", clock.now())
+        );
+        // Keys are scoped to the publisher.
+        assert_eq!(None, cache.get("pub_2", "key_1", clock.now()));
+
+        clock.advance(IDEMPOTENCY_KEY_TTL);
+        assert_eq!(None, cache.get("pub_1", "key_1", clock.now()));
+    }
+
+    #[test]
+    fn idempotency_cache_cancel_only_forgets_creating_keys_test() {
+        let now = Instant::now();
+        let mut cache = IdempotencyCache::default();
+
+        cache.begin("pub_1".to_string(), "key_1".to_string(), now);
+        assert_eq!(
+            Some(IdempotencyState::Creating),
+            cache.get("pub_1", "key_1", now)
+        );
+        cache.cancel("pub_1", "key_1");
+        assert_eq!(None, cache.get("pub_1", "key_1", now));
+
+        // A created topic is kept.
+        cache.insert(
+            "pub_1".to_string(),
+            "key_1".to_string(),
+            "topic_1".to_string(),
+            now,
+        );
+        cache.cancel("pub_1", "key_1");
+        assert_eq!(
+            Some(IdempotencyState::Created("topic_1".to_string())),
+            cache.get("pub_1", "key_1", now)
+        );
+    }
+
+    #[test]
+    fn generated_topics_test() {
+        let generated_topics = GeneratedTopics::new(vec!["vehicle/signals/".to_string()]);
+        let id = Uuid::new_v4();
+
+        assert!(generated_topics.contains(&id.to_string()));
+        assert!(generated_topics.contains(&format!("vehicle/signals/{id}")));
+        assert!(!generated_topics.contains(&format!("vehicle/status/{id}")));
+        assert!(!generated_topics.contains("vehicle/signals/speed"));
+        assert!(!generated_topics.contains("config/retained"));
+    }
+
+    #[test]
+    fn find_orphan_topics_test() {
+        let managed = Uuid::new_v4().to_string();
+        let orphan_a = Uuid::new_v4().to_string();
+        let orphan_b = Uuid::new_v4().to_string();
+
+        let mut active_topics = ActiveTopicsMap::new();
+        active_topics.insert(
+            managed.clone(),
+            TopicMetadata::new("pub_1".to_string(), 0, None),
+        );
+
+        let broker_topics = vec![
+            orphan_b.clone(),
+            managed,
+            orphan_a.clone(),
+            // Topics of other applications on the broker are never orphans.
+            "foreign_topic".to_string(),
+        ];
+
+        let mut expected = vec![orphan_a, orphan_b];
+        expected.sort();
+        assert_eq!(
+            expected,
+            find_orphan_topics(
+                broker_topics,
+                &active_topics,
+                None,
+                &GeneratedTopics::default(),
+                &MqttTopicNameCodec
+            )
+        );
+    }
+
+    #[test]
+    fn find_orphan_topics_decodes_broker_names_test() {
+        let managed = format!("vehicle/{}", Uuid::new_v4());
+        let orphan = format!("vehicle/{}", Uuid::new_v4());
+
+        let mut active_topics = ActiveTopicsMap::new();
+        active_topics.insert(
+            managed.clone(),
+            TopicMetadata::new("pub_1".to_string(), 0, None),
+        );
+
+        let codec = TopicNaming::Kafka.codec();
+        let broker_topics = vec![
+            codec.encode(&managed),
+            codec.encode(&orphan),
+            "foreign_topic".to_string(),
+        ];
 
-        sender
+        assert_eq!(
+            vec![codec.encode(&orphan)],
+            find_orphan_topics(
+                broker_topics,
+                &active_topics,
+                None,
+                &GeneratedTopics::new(vec!["vehicle/".to_string()]),
+                codec.as_ref()
+            )
+        );
     }
-}
 
-#[cfg(test)]
-mod topic_action_tests {
-    use super::*;
+    #[test]
+    fn find_orphan_topics_skips_spilled_topics_test() {
+        let dir = std::env::temp_dir().join(format!("agemo-orphan-spill-{}", std::process::id()));
+        let topic_spill = TopicSpill::new(
+            Box::new(crate::state_store::JsonFileStore::open(dir.clone()).unwrap()),
+            0,
+        );
+        let spilled = Uuid::new_v4().to_string();
+        let orphan = Uuid::new_v4().to_string();
 
-    #[tokio::test]
-    async fn initialize_topic_action_metadata_test() {
-        let expected_topic = "test".to_string();
-        let expected_mgmt_uri = "test.uri".to_string();
-        let expected_start_action = "START".to_string();
-        let expected_stop_action = "STOP".to_string();
-        let expected_delete_action = "DELETE".to_string();
-        let start_action = TopicAction::Start(TopicManagementInfo::new(
-            expected_topic.clone(),
-            expected_mgmt_uri.clone(),
-        ));
+        let mut active_topics = ActiveTopicsMap::new();
+        active_topics.insert(
+            spilled.clone(),
+            TopicMetadata::new("pub_1".to_string(), 0, None),
+        );
+        assert_eq!(
+            1,
+            topic_spill.spill_cold_topics(&mut active_topics, Instant::now())
+        );
+        assert!(active_topics.is_empty());
 
-        let start_action_metadata = TopicActionMetadata::new(start_action);
+        assert_eq!(
+            vec![orphan.clone()],
+            find_orphan_topics(
+                vec![spilled, orphan],
+                &active_topics,
+                Some(&topic_spill),
+                &GeneratedTopics::default(),
+                &MqttTopicNameCodec
+            )
+        );
 
-        assert_eq!(expected_topic, start_action_metadata.topic);
-        assert_eq!(expected_mgmt_uri, start_action_metadata.uri);
-        assert_eq!(expected_start_action, start_action_metadata.action);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 
-        let stop_action = TopicAction::Stop(TopicManagementInfo::new(
-            expected_topic.clone(),
-            expected_mgmt_uri.clone(),
-        ));
+    #[test]
+    fn topic_event_matches_filters_test() {
+        let event = TopicEvent::new(
+            TopicEventKind::Create,
+            "vehicle/speed".to_string(),
+            "pub_1".to_string(),
+        );
 
-        let stop_action_metadata = TopicActionMetadata::new(stop_action);
+        assert!(event.matches("", ""));
+        assert!(event.matches("pub_1", "vehicle/"));
+        assert!(!event.matches("pub_2", ""));
+        assert!(!event.matches("", "cabin/"));
+    }
 
-        assert_eq!(expected_topic, stop_action_metadata.topic);
-        assert_eq!(expected_mgmt_uri, stop_action_metadata.uri);
-        assert_eq!(expected_stop_action, stop_action_metadata.action);
+    #[tokio::test]
+    async fn handle_topic_action_broadcasts_event_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_manager = TopicManager::with_clock(clock.clone());
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let topic_events = test_manager.get_topic_events_handle();
+        let mut event_receiver = topic_events.subscribe();
+        let (deletion_sender, _deletion_receiver) = mpsc::channel::<MonitorMessage>();
 
-        let delete_action = TopicAction::Delete(TopicManagementInfo::new(
-            expected_topic.clone(),
-            expected_mgmt_uri.clone(),
-        ));
+        topic_map_handle.lock().insert(
+            "test".to_string(),
+            TopicMetadata::new_at(
+                "pub_1".to_string(),
+                0,
+                Some("test.uri".to_string()),
+                clock.now(),
+            ),
+        );
 
-        let delete_action_metadata = TopicActionMetadata::new(delete_action);
+        TopicManager::handle_topic_action(
+            MonitorMessage {
+                context: "test".to_string(),
+                action: PubSubAction::Delete,
+                client_id: None,
+                identity: None,
+            },
+            topic_map_handle,
+            deletion_sender,
+            &test_manager.get_deletion_messages_handle(),
+            test_manager.get_cleanup_stats_handle(),
+            &topic_events,
+            &test_manager.get_publisher_callbacks_handle(),
+            clock.now(),
+        )
+        .await;
 
-        assert_eq!(expected_topic, delete_action_metadata.topic);
-        assert_eq!(expected_mgmt_uri, delete_action_metadata.uri);
-        assert_eq!(expected_delete_action, delete_action_metadata.action);
+        let actual = event_receiver.try_recv().unwrap();
+        assert_eq!(TopicEventKind::Delete, actual.kind);
+        assert_eq!("test", actual.topic);
+        assert_eq!("pub_1", actual.publisher_id);
     }
-}
-
-#[cfg(test)]
-mod topic_manager_tests {
-    use super::*;
 
     #[tokio::test]
-    async fn subscribe_topic_test() {
-        let test_manager = TopicManager::new();
+    async fn handle_topic_action_records_deletion_message_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_manager = TopicManager::with_clock(clock.clone());
         let topic_map_handle = test_manager.get_active_topics_handle();
-        let expected_topic = "test".to_string();
-        let initial_count = 1;
-        let expected_mgmt_uri = "test.uri".to_string();
-        let initial_metadata =
-            TopicMetadata::new(String::new(), initial_count, Some(expected_mgmt_uri));
-        let initial_time = initial_metadata.get_timeout();
+        let deletion_messages = test_manager.get_deletion_messages_handle();
+        let (deletion_sender, deletion_receiver) = mpsc::channel::<MonitorMessage>();
 
-        // Insert existing topic
-        {
-            let mut map_lock = topic_map_handle.lock().unwrap();
-            map_lock.insert(expected_topic.clone(), initial_metadata);
-        }
+        let mut metadata = TopicMetadata::new_at(
+            "pub_1".to_string(),
+            0,
+            Some("test.uri".to_string()),
+            clock.now(),
+        );
+        metadata.set_deletion_message(DeletionMessage::Custom("bye".to_string()));
+        topic_map_handle.lock().insert("test".to_string(), metadata);
 
-        let message = MonitorMessage {
-            context: expected_topic.clone(),
-            action: PubSubAction::Subscribe,
-        };
+        TopicManager::handle_topic_action(
+            MonitorMessage {
+                context: "test".to_string(),
+                action: PubSubAction::Delete,
+                client_id: None,
+                identity: None,
+            },
+            topic_map_handle,
+            deletion_sender,
+            &deletion_messages,
+            test_manager.get_cleanup_stats_handle(),
+            &test_manager.get_topic_events_handle(),
+            &test_manager.get_publisher_callbacks_handle(),
+            clock.now(),
+        )
+        .await;
 
-        let actual_action = TopicManager::update_topic(topic_map_handle.clone(), message);
-        assert!(actual_action.is_none());
+        let deleted = deletion_receiver.try_recv().unwrap();
+        assert_eq!("test", deleted.context);
+        assert_eq!(
+            DeletionMessage::Custom("bye".to_string()),
+            deletion_messages.take("test")
+        );
+    }
 
-        // Confirm last active time and count was updated
-        {
-            let map_lock = topic_map_handle.lock().unwrap();
-            let actual_metadata = map_lock.get(&expected_topic).unwrap();
+    #[tokio::test]
+    async fn handle_topic_action_records_callback_metrics_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_manager = TopicManager::with_clock(clock.clone());
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let publisher_callbacks = test_manager.get_publisher_callbacks_handle();
+        let (deletion_sender, _deletion_receiver) = mpsc::channel::<MonitorMessage>();
 
-            assert_ne!(initial_time, actual_metadata.get_timeout());
-            assert_eq!(initial_count + 1, actual_metadata.count);
+        topic_map_handle.lock().insert(
+            "test".to_string(),
+            TopicMetadata::new_at(
+                "pub_1".to_string(),
+                0,
+                Some("test.uri".to_string()),
+                clock.now(),
+            ),
+        );
+
+        // The first subscriber starts the publisher, whose management uri can't be called.
+        TopicManager::handle_topic_action(
+            MonitorMessage {
+                context: "test".to_string(),
+                action: PubSubAction::Subscribe,
+                client_id: None,
+                identity: None,
+            },
+            topic_map_handle,
+            deletion_sender,
+            &test_manager.get_deletion_messages_handle(),
+            test_manager.get_cleanup_stats_handle(),
+            &test_manager.get_topic_events_handle(),
+            &publisher_callbacks,
+            clock.now(),
+        )
+        .await;
+
+        // The callback is made in a separate task.
+        for _ in 0..50 {
+            if !publisher_callbacks
+                .metrics
+                .lock()
+                .callback_stats()
+                .is_empty()
+            {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
         }
+
+        let stats = publisher_callbacks.metrics.lock().callback_stats();
+        assert_eq!(1, stats.len());
+        assert_eq!("pub_1", stats[0].0);
+        assert_eq!(1, stats[0].1.callbacks);
+        assert_eq!(1, stats[0].1.failures);
     }
 
     #[tokio::test]
-    async fn subscribe_topic_with_no_subs_test() {
+    async fn handle_topic_action_orders_callbacks_test() {
+        // The START is held up, so a STOP made without waiting for it would be received first.
+        let publisher =
+            FakePublisherCallback::new().with_delay("START", Duration::from_millis(500));
+        let (uri, server) = publisher.serve().await.unwrap();
         let test_manager = TopicManager::new();
         let topic_map_handle = test_manager.get_active_topics_handle();
-        let expected_topic = "test".to_string();
-        let initial_count = 0;
-        let expected_mgmt_uri = "test.uri".to_string();
-        let initial_metadata = TopicMetadata::new(
-            String::new(),
-            initial_count,
-            Some(expected_mgmt_uri.clone()),
+        let publisher_callbacks = PublisherCallbacks::new(&PublisherCallbackSettings {
+            max_concurrent_callbacks: 2,
+            ..Default::default()
+        });
+        let (deletion_sender, _deletion_receiver) = mpsc::channel::<MonitorMessage>();
+
+        topic_map_handle.lock().insert(
+            "test".to_string(),
+            TopicMetadata::new("pub_1".to_string(), 0, Some(uri)),
         );
-        let initial_time = initial_metadata.get_timeout();
 
-        // Insert existing topic with no active subs
-        {
-            let mut map_lock = topic_map_handle.lock().unwrap();
-            map_lock.insert(expected_topic.clone(), initial_metadata);
+        for action in [PubSubAction::Subscribe, PubSubAction::Unsubscribe] {
+            TopicManager::handle_topic_action(
+                MonitorMessage {
+                    context: "test".to_string(),
+                    action,
+                    client_id: None,
+                    identity: None,
+                },
+                topic_map_handle.clone(),
+                deletion_sender.clone(),
+                &test_manager.get_deletion_messages_handle(),
+                test_manager.get_cleanup_stats_handle(),
+                &test_manager.get_topic_events_handle(),
+                &publisher_callbacks,
+                Instant::now(),
+            )
+            .await;
         }
 
-        let message = MonitorMessage {
-            context: expected_topic.clone(),
-            action: PubSubAction::Subscribe,
-        };
-
-        let actual_action = TopicManager::update_topic(topic_map_handle.clone(), message);
-        assert!(actual_action.is_some());
+        let actual_actions: Vec<String> = publisher
+            .wait_for(2, Duration::from_secs(5))
+            .await
+            .into_iter()
+            .map(|callback| callback.action)
+            .collect();
+        server.abort();
 
-        let expected_action_inner = TopicAction::Start(TopicManagementInfo::new(
-            expected_topic.clone(),
-            expected_mgmt_uri,
-        ));
-        assert_eq!(expected_action_inner, actual_action.unwrap());
+        assert_eq!(vec!["START", "STOP"], actual_actions);
+    }
 
-        // Confirm last active time and count was updated
-        {
-            let map_lock = topic_map_handle.lock().unwrap();
-            let actual_metadata = map_lock.get(&expected_topic).unwrap();
+    #[tokio::test]
+    async fn handle_topic_actions_deletes_every_topic_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_manager = TopicManager::with_clock(clock.clone());
+        let topic_map_handle = test_manager.get_active_topics_handle();
+        let (deletion_sender, deletion_receiver) = mpsc::channel::<MonitorMessage>();
 
-            assert_ne!(initial_time, actual_metadata.get_timeout());
-            assert_eq!(initial_count + 1, actual_metadata.count);
+        // More topics than can be handled at once.
+        let topics: Vec<String> = (0..CLEANUP_CONCURRENCY * 2)
+            .map(|i| format!("test-{i}"))
+            .collect();
+        for topic in &topics {
+            topic_map_handle.lock().insert(
+                topic.clone(),
+                TopicMetadata::new_at(
+                    "pub_1".to_string(),
+                    0,
+                    Some("test.uri".to_string()),
+                    clock.now(),
+                ),
+            );
         }
+
+        let msgs = topics
+            .iter()
+            .map(|topic| MonitorMessage {
+                context: topic.clone(),
+                action: PubSubAction::Delete,
+                client_id: None,
+                identity: None,
+            })
+            .collect();
+
+        TopicManager::handle_topic_actions(
+            msgs,
+            &topic_map_handle,
+            &deletion_sender,
+            &test_manager.get_deletion_messages_handle(),
+            &test_manager.get_cleanup_stats_handle(),
+            &test_manager.get_topic_events_handle(),
+            &test_manager.get_publisher_callbacks_handle(),
+            clock.now(),
+        )
+        .await;
+
+        assert!(topic_map_handle.lock().is_empty());
+
+        let mut deleted: Vec<String> = deletion_receiver
+            .try_iter()
+            .map(|msg| msg.context)
+            .collect();
+        deleted.sort();
+        let mut expected = topics;
+        expected.sort();
+        assert_eq!(expected, deleted);
     }
 
-    #[tokio::test]
-    async fn subscribe_topic_with_no_active_topic_test() {
+    #[test]
+    fn delete_topics_of_callback_test() {
         let test_manager = TopicManager::new();
         let topic_map_handle = test_manager.get_active_topics_handle();
-        let expected_topic = "test".to_string();
-        let initial_count = 1;
-        let expected_metadata = TopicMetadata::new(String::new(), initial_count, None);
-
-        let message = MonitorMessage {
-            context: expected_topic.clone(),
-            action: PubSubAction::Subscribe,
-        };
 
-        let actual_action = TopicManager::update_topic(topic_map_handle.clone(), message);
-        assert!(actual_action.is_none());
-
-        // Confirm metadata matches expected
         {
-            let map_lock = topic_map_handle.lock().unwrap();
-            let actual_metadata = map_lock.get(&expected_topic).unwrap();
-
-            assert_eq!(expected_metadata.count, actual_metadata.count);
-            assert_eq!(
-                expected_metadata.management_callback,
-                actual_metadata.management_callback,
+            let mut map_lock = topic_map_handle.lock();
+            map_lock.insert(
+                "failing".to_string(),
+                TopicMetadata::new("pub_1".to_string(), 1, Some("failing.uri".to_string())),
+            );
+            map_lock.insert(
+                "healthy".to_string(),
+                TopicMetadata::new("pub_2".to_string(), 1, Some("healthy.uri".to_string())),
             );
         }
+
+        TopicManager::delete_topics_of_callback("failing.uri", &topic_map_handle);
+
+        let map_lock = topic_map_handle.lock();
+        assert!(map_lock.get("failing").unwrap().is_deleted());
+        assert!(!map_lock.get("healthy").unwrap().is_deleted());
+
+        let history: Vec<&ActionRecord> =
+            map_lock.get("failing").unwrap().action_history().collect();
+        assert_eq!(1, history.len());
+        assert_eq!(TopicEventKind::Delete, history[0].kind);
+        assert_eq!(ActionOutcome::Scheduled, history[0].outcome);
     }
 
     #[tokio::test]
-    async fn unsubscribe_topic_test() {
+    async fn reconcile_publisher_test() {
+        let publisher = FakePublisherCallback::new();
+        let (uri, server) = publisher.serve().await.unwrap();
         let test_manager = TopicManager::new();
         let topic_map_handle = test_manager.get_active_topics_handle();
-        let expected_topic = "test".to_string();
-        let initial_count = 2;
-        let expected_mgmt_uri = "test.uri".to_string();
-        let initial_metadata =
-            TopicMetadata::new(String::new(), initial_count, Some(expected_mgmt_uri));
-        let initial_time = initial_metadata.get_timeout();
+        let breakers = test_manager.get_publisher_callbacks_handle().breakers;
+        let now = Instant::now();
 
-        // Insert existing topic
         {
-            let mut map_lock = topic_map_handle.lock().unwrap();
-            map_lock.insert(expected_topic.clone(), initial_metadata);
+            let mut map_lock = topic_map_handle.lock();
+            let mut started = TopicMetadata::new("pub_1".to_string(), 2, Some(uri.clone()));
+            started.start_at(now);
+            map_lock.insert("started".to_string(), started);
+            map_lock.insert(
+                "idle".to_string(),
+                TopicMetadata::new("pub_1".to_string(), 0, Some(uri.clone())),
+            );
+            // A topic whose START was skipped while the breaker was open.
+            map_lock.insert(
+                "skipped".to_string(),
+                TopicMetadata::new("pub_1".to_string(), 1, Some(uri.clone())),
+            );
+            let mut deleted = TopicMetadata::new("pub_1".to_string(), 0, Some(uri.clone()));
+            deleted.delete();
+            map_lock.insert("deleted".to_string(), deleted);
+            map_lock.insert(
+                "other".to_string(),
+                TopicMetadata::new("pub_2".to_string(), 1, Some("other.uri".to_string())),
+            );
         }
 
-        let message = MonitorMessage {
-            context: expected_topic.clone(),
-            action: PubSubAction::Unsubscribe,
-        };
-
-        let actual_action = TopicManager::update_topic(topic_map_handle.clone(), message);
-        assert!(actual_action.is_none());
+        TopicManager::reconcile_publisher(
+            &uri,
+            &topic_map_handle,
+            &breakers,
+            ActionCasing::Upper,
+            None,
+            None,
+            now,
+        )
+        .await;
+        server.abort();
 
-        // Confirm last active time and count was updated
-        {
-            let map_lock = topic_map_handle.lock().unwrap();
-            let actual_metadata = map_lock.get(&expected_topic).unwrap();
+        // Only the live topics of the publisher are reconciled, in the order of their names.
+        let expected = vec![
+            RecordedCallback {
+                topic: "idle".to_string(),
+                action: "STOP".to_string(),
+            },
+            RecordedCallback {
+                topic: "skipped".to_string(),
+                action: "START".to_string(),
+            },
+            RecordedCallback {
+                topic: "started".to_string(),
+                action: "START".to_string(),
+            },
+        ];
+        assert_eq!(expected, publisher.callbacks());
 
-            assert_ne!(initial_time, actual_metadata.get_timeout());
-            assert_eq!(initial_count - 1, actual_metadata.count);
-        }
+        let map_lock = topic_map_handle.lock();
+        assert!(map_lock.get("skipped").unwrap().started_at.is_some());
+        let history: Vec<&ActionRecord> =
+            map_lock.get("started").unwrap().action_history().collect();
+        assert_eq!(1, history.len());
+        assert_eq!("RECONCILIATION", history[0].cause);
+        assert_eq!(ActionOutcome::Delivered, history[0].outcome);
+        assert_eq!(0, map_lock.get("other").unwrap().action_history().count());
     }
 
     #[tokio::test]
-    async fn unsubscribe_topic_with_one_sub_test() {
+    async fn reconcile_publisher_records_failure_test() {
+        let uri = "http://127.0.0.1:1".to_string();
         let test_manager = TopicManager::new();
         let topic_map_handle = test_manager.get_active_topics_handle();
-        let expected_topic = "test".to_string();
-        let initial_count = 1;
-        let expected_mgmt_uri = "test.uri".to_string();
-        let initial_metadata = TopicMetadata::new(
-            String::new(),
-            initial_count,
-            Some(expected_mgmt_uri.clone()),
-        );
-        let initial_time = initial_metadata.get_timeout();
+        let breakers = Arc::new(SharedState::new(CallbackBreakers::new(
+            1,
+            Duration::from_secs(30),
+            None,
+        )));
+        let now = Instant::now();
 
-        // Insert existing topic
-        {
-            let mut map_lock = topic_map_handle.lock().unwrap();
-            map_lock.insert(expected_topic.clone(), initial_metadata);
-        }
+        topic_map_handle.lock().insert(
+            "topic".to_string(),
+            TopicMetadata::new("pub_1".to_string(), 0, Some(uri.clone())),
+        );
 
-        let message = MonitorMessage {
-            context: expected_topic.clone(),
-            action: PubSubAction::Unsubscribe,
-        };
+        TopicManager::reconcile_publisher(
+            &uri,
+            &topic_map_handle,
+            &breakers,
+            ActionCasing::Upper,
+            None,
+            None,
+            now,
+        )
+        .await;
 
-        let actual_action = TopicManager::update_topic(topic_map_handle.clone(), message);
-        assert!(actual_action.is_some());
+        // The failed reconciliation opens the breaker again.
+        assert_eq!(BreakerDecision::Skip, breakers.lock().check(&uri, now));
 
-        let expected_action_inner = TopicAction::Stop(TopicManagementInfo::new(
-            expected_topic.clone(),
-            expected_mgmt_uri,
-        ));
-        assert_eq!(expected_action_inner, actual_action.unwrap());
+        let map_lock = topic_map_handle.lock();
+        let history: Vec<&ActionRecord> = map_lock.get("topic").unwrap().action_history().collect();
+        assert_eq!(ActionOutcome::Failed, history[0].outcome);
+    }
 
-        // Confirm last active time and count was updated
-        {
-            let map_lock = topic_map_handle.lock().unwrap();
-            let actual_metadata = map_lock.get(&expected_topic).unwrap();
+    #[test]
+    fn action_history_is_bounded_test() {
+        let mut metadata = TopicMetadata::new("pub_1".to_string(), 0, None);
 
-            assert_ne!(initial_time, actual_metadata.get_timeout());
-            assert_eq!(initial_count - 1, actual_metadata.count);
+        for i in 0..ACTION_HISTORY_CAPACITY + 2 {
+            metadata.record_action(ActionRecord::new(
+                TopicEventKind::Start,
+                "SUBSCRIBE".to_string(),
+                ActionOutcome::Delivered,
+                i.to_string(),
+            ));
         }
+
+        let details: Vec<&str> = metadata
+            .action_history()
+            .map(|record| record.detail.as_str())
+            .collect();
+        assert_eq!(ACTION_HISTORY_CAPACITY, details.len());
+        assert_eq!(Some(&"2"), details.first());
+        assert_eq!(
+            Some(&(ACTION_HISTORY_CAPACITY + 1).to_string().as_str()),
+            details.last()
+        );
     }
 
-    #[tokio::test]
-    async fn unsubscribe_topic_with_no_subs_test() {
-        let test_manager = TopicManager::new();
+    #[test]
+    fn handle_power_state_test() {
+        let clock = Arc::new(ManualClock::new());
+        let test_manager = TopicManager::with_clock(clock.clone());
         let topic_map_handle = test_manager.get_active_topics_handle();
-        let expected_topic = "test".to_string();
-        let initial_count = 0;
-        let expected_mgmt_uri = "test.uri".to_string();
-        let initial_metadata = TopicMetadata::new(
-            String::new(),
-            initial_count,
-            Some(expected_mgmt_uri.clone()),
-        );
-        let initial_time = initial_metadata.get_timeout();
+        let power_suspended = AtomicBool::new(false);
+        let broker_connected = AtomicBool::new(true);
+        let mut suspended_at = None;
 
-        // Insert existing topic
         {
-            let mut map_lock = topic_map_handle.lock().unwrap();
-            map_lock.insert(expected_topic.clone(), initial_metadata);
+            let mut metadata =
+                TopicMetadata::new_at(String::new(), 0, Some("test.uri".to_string()), clock.now());
+            metadata.renew_lease("sub_1".to_string(), clock.now() + Duration::from_secs(10));
+            topic_map_handle.lock().insert("test".to_string(), metadata);
         }
 
-        let message = MonitorMessage {
-            context: expected_topic.clone(),
-            action: PubSubAction::Unsubscribe,
-        };
-
-        let actual_action = TopicManager::update_topic(topic_map_handle.clone(), message);
-        assert!(actual_action.is_some());
+        // Suspending doesn't count as a resume.
+        assert!(!TopicManager::handle_power_state(
+            true,
+            &power_suspended,
+            &mut suspended_at,
+            &broker_connected,
+            topic_map_handle.clone(),
+            clock.now(),
+        ));
+        assert!(power_suspended.load(Ordering::SeqCst));
 
-        let expected_action_inner = TopicAction::Stop(TopicManagementInfo::new(
-            expected_topic.clone(),
-            expected_mgmt_uri,
+        // Resuming resets topic timeouts and extends leases by the time spent suspended.
+        clock.advance(Duration::from_secs(3600));
+        assert!(TopicManager::handle_power_state(
+            false,
+            &power_suspended,
+            &mut suspended_at,
+            &broker_connected,
+            topic_map_handle.clone(),
+            clock.now(),
         ));
-        assert_eq!(expected_action_inner, actual_action.unwrap());
+        assert!(!power_suspended.load(Ordering::SeqCst));
+        assert!(suspended_at.is_none());
 
-        // Confirm last active time and count was updated
-        {
-            let map_lock = topic_map_handle.lock().unwrap();
-            let actual_metadata = map_lock.get(&expected_topic).unwrap();
+        // Topic timeouts stay suspended until the broker connection is re-validated.
+        assert!(!broker_connected.load(Ordering::SeqCst));
 
-            assert_ne!(initial_time, actual_metadata.get_timeout());
-            assert_eq!(initial_count, actual_metadata.count);
-        }
-    }
+        let mut map_lock = topic_map_handle.lock();
+        let metadata = map_lock.get_mut("test").unwrap();
+        assert_eq!(clock.now(), metadata.get_timeout());
+        assert!(metadata.take_expired_leases(clock.now()).is_empty());
+        assert_eq!(
+            vec![("sub_1".to_string(), clock.now() + Duration::from_secs(10))],
+            metadata.take_expired_leases(clock.now() + Duration::from_secs(10))
+        );
+        drop(map_lock);
 
-    #[tokio::test]
-    async fn manage_topic_on_delete_action() {
-        let delete_action = TopicAction::Delete(TopicManagementInfo::new(
-            "topic".to_string(),
-            "uri".to_string(),
+        // A resume without a prior suspend is ignored.
+        assert!(!TopicManager::handle_power_state(
+            false,
+            &power_suspended,
+            &mut suspended_at,
+            &broker_connected,
+            topic_map_handle,
+            clock.now(),
         ));
-
-        let ok_result = TopicManager::manage_topic(delete_action).await;
-
-        // Expect that result is short circuited to ok. Since the Publisher connector is
-        // not mocked it will return an error if action does not match Delete.
-        assert!(ok_result.is_ok());
     }
 }
@@ -0,0 +1,345 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Translation between the canonical topic names used by the pub sub service and the topic names
+//! that are legal on a messaging broker.
+//!
+//! The service names topics with `/` separated segments, such as `agemo/self-test/{uuid}`, which
+//! is the topic syntax of MQTT. Other brokers use a different syntax, such as `.` separated
+//! segments for Kafka and NATS, or the restricted character set of DDS topic names. Each broker connector has a [`TopicNameCodec`] that maps the
+//! canonical names to broker names and back, so that the names generated by the service stay the
+//! same whichever connector is used.
+
+use std::{
+    fmt::Debug,
+    sync::{mpsc, Arc},
+    thread,
+};
+
+use log::warn;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::pubsub_connector::{MonitorMessage, PubSubAction};
+
+/// Separator of the segments of canonical topic names.
+const CANONICAL_SEPARATOR: char = '/';
+/// Prefix of an escaped byte in the topic names of delimited brokers.
+const ESCAPE: char = '_';
+
+/// Trait that maps canonical topic names to the topic names used on a messaging broker.
+pub trait TopicNameCodec: Debug + Send + Sync {
+    /// Returns the broker name of a canonical topic name.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The canonical topic name.
+    fn encode(&self, topic: &str) -> String;
+
+    /// Returns the canonical topic name of a broker name, or `None` if the name was not encoded
+    /// by this codec, such as the name of a topic that is not managed by the service.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The broker topic name.
+    fn decode(&self, name: &str) -> Option<String>;
+}
+
+/// Codec for MQTT brokers, whose topic syntax is the canonical one.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MqttTopicNameCodec;
+
+impl TopicNameCodec for MqttTopicNameCodec {
+    fn encode(&self, topic: &str) -> String {
+        topic.to_string()
+    }
+
+    fn decode(&self, name: &str) -> Option<String> {
+        Some(name.to_string())
+    }
+}
+
+/// Codec for brokers that separate the segments of topic names with another character, such as
+/// `.` for Kafka and NATS.
+///
+/// Only ascii alphanumeric characters and `-` are kept as is, which are legal on every supported
+/// broker and make up generated topic names. Any other byte is escaped as `_` followed by its two
+/// digit hex value, so that the separator and broker wildcards can't appear within a segment.
+#[derive(Clone, Copy, Debug)]
+pub struct DelimitedTopicNameCodec {
+    separator: char,
+}
+
+impl DelimitedTopicNameCodec {
+    /// Creates a new DelimitedTopicNameCodec.
+    ///
+    /// # Arguments
+    ///
+    /// * `separator` - The separator of the segments of broker topic names.
+    pub fn new(separator: char) -> Self {
+        DelimitedTopicNameCodec { separator }
+    }
+
+    /// Returns whether a character is kept as is in broker topic names.
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - The character.
+    fn is_plain(c: char) -> bool {
+        c.is_ascii_alphanumeric() || c == '-'
+    }
+}
+
+impl TopicNameCodec for DelimitedTopicNameCodec {
+    fn encode(&self, topic: &str) -> String {
+        let mut name = String::with_capacity(topic.len());
+
+        for c in topic.chars() {
+            if c == CANONICAL_SEPARATOR {
+                name.push(self.separator);
+            } else if Self::is_plain(c) {
+                name.push(c);
+            } else {
+                push_escaped(&mut name, c);
+            }
+        }
+
+        name
+    }
+
+    fn decode(&self, name: &str) -> Option<String> {
+        let mut bytes = Vec::with_capacity(name.len());
+        let mut chars = name.chars();
+
+        while let Some(c) = chars.next() {
+            if c == self.separator {
+                bytes.push(CANONICAL_SEPARATOR as u8);
+            } else if c == ESCAPE {
+                let hex: String = chars.by_ref().take(2).collect();
+                if hex.len() != 2 {
+                    return None;
+                }
+                bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+            } else if Self::is_plain(c) {
+                bytes.push(c as u8);
+            } else {
+                return None;
+            }
+        }
+
+        String::from_utf8(bytes).ok()
+    }
+}
+
+/// Codec for DDS, whose topic names may only contain ascii alphanumeric characters, `_` and `/`,
+/// and must not start with a digit.
+///
+/// Segments are separated with `/` as in canonical names. Any other character, including the `-`
+/// of generated topic names, is escaped as `_` followed by its two digit hex value, as is a digit
+/// at the start of the name.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DdsTopicNameCodec;
+
+impl TopicNameCodec for DdsTopicNameCodec {
+    fn encode(&self, topic: &str) -> String {
+        let mut name = String::with_capacity(topic.len());
+
+        for (index, c) in topic.chars().enumerate() {
+            let leading_digit = index == 0 && c.is_ascii_digit();
+            if c == CANONICAL_SEPARATOR || (c.is_ascii_alphanumeric() && !leading_digit) {
+                name.push(c);
+            } else {
+                push_escaped(&mut name, c);
+            }
+        }
+
+        name
+    }
+
+    fn decode(&self, name: &str) -> Option<String> {
+        let mut bytes = Vec::with_capacity(name.len());
+        let mut chars = name.chars();
+
+        while let Some(c) = chars.next() {
+            if c == ESCAPE {
+                let hex: String = chars.by_ref().take(2).collect();
+                if hex.len() != 2 {
+                    return None;
+                }
+                bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+            } else if c == CANONICAL_SEPARATOR || c.is_ascii_alphanumeric() {
+                bytes.push(c as u8);
+            } else {
+                return None;
+            }
+        }
+
+        String::from_utf8(bytes).ok()
+    }
+}
+
+/// Appends the escaped bytes of a character to a broker topic name.
+///
+/// # Arguments
+///
+/// * `name` - The broker topic name.
+/// * `c` - The character to escape.
+fn push_escaped(name: &mut String, c: char) {
+    let mut bytes = [0; 4];
+    for byte in c.encode_utf8(&mut bytes).bytes() {
+        name.push_str(&format!("{ESCAPE}{byte:02X}"));
+    }
+}
+
+/// The topic naming of a messaging broker.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TopicNaming {
+    /// `/` separated topic names, used as is.
+    #[default]
+    Mqtt,
+    /// `.` separated topic names, as used by Kafka.
+    Kafka,
+    /// `.` separated topic names, as used by NATS.
+    Nats,
+    /// `/` separated topic names restricted to the characters allowed by DDS.
+    Dds,
+}
+
+impl TopicNaming {
+    /// Returns the codec of the topic naming.
+    pub fn codec(self) -> Arc<dyn TopicNameCodec> {
+        match self {
+            TopicNaming::Mqtt => Arc::new(MqttTopicNameCodec),
+            TopicNaming::Kafka | TopicNaming::Nats => Arc::new(DelimitedTopicNameCodec::new('.')),
+            TopicNaming::Dds => Arc::new(DdsTopicNameCodec),
+        }
+    }
+}
+
+/// Returns a channel for the monitor messages of a broker connector that forwards them to the
+/// given channel, with the topics of subscribe and unsubscribe messages decoded to their canonical
+/// name. Topics that the codec did not encode are forwarded as is, and messages with actions the
+/// service doesn't handle are dropped.
+///
+/// # Arguments
+///
+/// * `sender` - The channel to forward the decoded messages to.
+/// * `codec` - The codec of the broker connector sending the messages.
+pub fn decoding_sender(
+    sender: mpsc::Sender<MonitorMessage>,
+    codec: Arc<dyn TopicNameCodec>,
+) -> mpsc::Sender<agemo_connector::MonitorMessage> {
+    let (decoding_sender, receiver) = mpsc::channel::<agemo_connector::MonitorMessage>();
+
+    // The thread stops once the connector drops the channel.
+    thread::spawn(move || {
+        for msg in receiver.iter() {
+            let mut msg = match MonitorMessage::try_from(msg) {
+                Ok(msg) => msg,
+                Err(err) => {
+                    warn!("Dropped update from the broker connector: {err}");
+                    continue;
+                }
+            };
+
+            if matches!(
+                msg.action,
+                PubSubAction::Subscribe | PubSubAction::Unsubscribe
+            ) {
+                if let Some(topic) = codec.decode(&msg.context) {
+                    msg.context = topic;
+                }
+            }
+
+            if sender.send(msg).is_err() {
+                break;
+            }
+        }
+    });
+
+    decoding_sender
+}
+
+#[cfg(test)]
+mod topic_name_codec_tests {
+    use super::*;
+
+    #[test]
+    fn mqtt_codec_keeps_names_test() {
+        let codec = TopicNaming::Mqtt.codec();
+
+        assert_eq!("agemo/self-test/1", codec.encode("agemo/self-test/1"));
+        assert_eq!(
+            Some("agemo/self-test/1".to_string()),
+            codec.decode("agemo/self-test/1")
+        );
+    }
+
+    #[test]
+    fn delimited_codec_round_trips_test() {
+        let codec = TopicNaming::Kafka.codec();
+
+        // Generated topic names are the same on every broker.
+        let generated = "8d3bd9a2-4b7e-4f3c-9a3e-2f1d0c9b8a7e";
+        assert_eq!(generated, codec.encode(generated));
+
+        assert_eq!("agemo.self-test.1", codec.encode("agemo/self-test/1"));
+        assert_eq!("a_2Eb_5Fc_2A", codec.encode("a.b_c*"));
+        assert_eq!("caf_C3_A9", codec.encode("café"));
+
+        for topic in ["agemo/self-test/1", "a.b_c*", "café", "", "/"] {
+            assert_eq!(Some(topic.to_string()), codec.decode(&codec.encode(topic)));
+        }
+    }
+
+    #[test]
+    fn delimited_codec_rejects_foreign_names_test() {
+        let codec = TopicNaming::Nats.codec();
+
+        assert_eq!(None, codec.decode("orders.*"));
+        assert_eq!(None, codec.decode("truncated_2"));
+        assert_eq!(None, codec.decode("bad_ZZ"));
+    }
+
+    #[test]
+    fn dds_codec_round_trips_test() {
+        let codec = TopicNaming::Dds.codec();
+
+        assert_eq!("agemo/self_2Dtest/a1", codec.encode("agemo/self-test/a1"));
+        assert_eq!("_38d3b_2D4f3c", codec.encode("8d3b-4f3c"));
+        assert_eq!("a_2Eb_5Fc", codec.encode("a.b_c"));
+        assert_eq!(None, codec.decode("rt/chatter-1"));
+
+        for topic in ["agemo/self-test/1", "8d3b-4f3c", "a.b_c*", "café", "", "/"] {
+            assert_eq!(Some(topic.to_string()), codec.decode(&codec.encode(topic)));
+        }
+    }
+
+    #[test]
+    fn decoding_sender_decodes_subscriptions_test() {
+        let (sender, receiver) = mpsc::channel::<MonitorMessage>();
+        let decoding_sender = decoding_sender(sender, TopicNaming::Kafka.codec());
+
+        for (context, action) in [
+            ("vehicle.speed", agemo_connector::PubSubAction::Subscribe),
+            ("client_1", agemo_connector::PubSubAction::PubDisconnect),
+        ] {
+            decoding_sender
+                .send(agemo_connector::MonitorMessage {
+                    context: context.to_string(),
+                    action,
+                    client_id: None,
+                    identity: None,
+                })
+                .unwrap();
+        }
+
+        let actual = receiver.recv().unwrap();
+        assert_eq!(PubSubAction::Subscribe, actual.action);
+        assert_eq!("vehicle/speed", actual.context);
+        // Only topics are decoded.
+        assert_eq!("client_1", receiver.recv().unwrap().context);
+    }
+}
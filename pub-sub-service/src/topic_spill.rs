@@ -0,0 +1,457 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Spilling of the metadata of cold topics to disk.
+//!
+//! Deployments with tens of thousands of ephemeral topics keep most of them idle at any time. The
+//! [`TopicSpill`] bounds the number of topics held in memory: once the active topics map grows
+//! past the limit, the cold topics that have been idle the longest are moved to a [`StateStore`]
+//! on disk. A cold topic has no subscribers and a stopped publisher, so nothing happens to it
+//! while it is spilled. It is restored to the active topics map as soon as it is referenced
+//! again, by a subscription, a request of its publisher or a disconnect of its publisher.
+
+use common::shared_state::SharedState;
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    pubsub_connector::DeletionMessage,
+    state_store::StateStore,
+    topic_manager::{ActiveTopicsMap, TopicMetadata},
+};
+
+/// Metadata of a spilled topic, as stored on disk. Times are stored relative to when the topic
+/// was spilled, and the action history of the topic is not kept.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpilledTopic {
+    /// The id of the publisher of the topic.
+    pub client_id: String,
+    /// Callback uri of the publisher.
+    pub management_callback: Option<String>,
+    /// How long in milliseconds the topic had existed.
+    pub age_ms: u64,
+    /// How long in milliseconds since the last action on the topic.
+    pub since_last_action_ms: u64,
+    /// How long in milliseconds the topic had gone without subscribers.
+    pub idle_ms: u64,
+    /// Whether the topic is a single consumer topic.
+    pub single_consumer: bool,
+    /// Whether the topic is pre-warmed.
+    pub pre_warm: bool,
+    /// Whether the publisher is notified of subscribers joining the started topic.
+    pub notify_subscriber_joined: bool,
+    /// The epoch of the sequence numbers of the topic, if the publisher stamps them.
+    pub sequence_epoch: Option<u64>,
+    /// The lifecycle epoch of the topic, so that it keeps increasing once the topic is restored.
+    #[serde(default)]
+    pub lifecycle_epoch: u64,
+    /// The class of the topic, as its proto value.
+    pub topic_class: i32,
+    /// The compression of the payloads of the topic, as its proto value.
+    #[serde(default)]
+    pub payload_compression: i32,
+    /// The name of the topic template the topic was created from.
+    pub topic_template: Option<String>,
+    /// The subject a subscriber requested the topic for.
+    pub subject: Option<String>,
+    /// The client ids of the standby publishers of the topic.
+    #[serde(default)]
+    pub standby_client_ids: Vec<String>,
+    /// The message sent to the subscribers of the topic when it is deleted.
+    #[serde(default)]
+    pub deletion_message: DeletionMessage,
+    /// The tags the publisher labeled the topic with.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// How long in milliseconds the topic may go without subscribers before its publisher is
+    /// reminded.
+    pub idle_timeout_ms: u64,
+}
+
+/// Converts a duration to whole milliseconds, saturating at `u64::MAX`.
+///
+/// # Arguments
+///
+/// * `duration` - The duration to convert.
+pub fn duration_ms(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+/// In-memory index entry of a spilled topic, used to find the topics to restore without reading
+/// the store.
+#[derive(Clone, Debug)]
+struct SpillIndexEntry {
+    client_id: String,
+    standby_client_ids: Vec<String>,
+    subject: Option<String>,
+    spilled_at: Instant,
+    idle_since: Instant,
+}
+
+/// Bounds the number of topics held in memory by spilling cold topics to a [`StateStore`].
+#[derive(Debug)]
+pub struct TopicSpill {
+    store: Box<dyn StateStore>,
+    max_in_memory_topics: usize,
+    index: SharedState<HashMap<String, SpillIndexEntry>>,
+}
+
+impl TopicSpill {
+    /// Creates a new TopicSpill instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store the cold topics are spilled to.
+    /// * `max_in_memory_topics` - The number of topics above which cold topics are spilled.
+    pub fn new(store: Box<dyn StateStore>, max_in_memory_topics: usize) -> Self {
+        TopicSpill {
+            store,
+            max_in_memory_topics,
+            index: SharedState::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the number of spilled topics.
+    pub fn spilled_count(&self) -> usize {
+        self.index.lock().len()
+    }
+
+    /// Returns whether a topic is spilled.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The name of the topic.
+    pub fn is_spilled(&self, topic: &str) -> bool {
+        self.index.lock().contains_key(topic)
+    }
+
+    /// Returns the metadata of a spilled topic, read back from the store without restoring the
+    /// topic, or `None` if the topic is not spilled.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The name of the topic.
+    pub fn get(&self, topic: &str) -> Option<TopicMetadata> {
+        let spilled_at = self.index.lock().get(topic)?.spilled_at;
+
+        match self.store.get(topic) {
+            Ok(spilled) => spilled.map(|spilled| TopicMetadata::from_spilled(spilled, spilled_at)),
+            Err(err) => {
+                warn!("Unable to read spilled topic '{topic}': {err}");
+                None
+            }
+        }
+    }
+
+    /// Returns the spilled topics and their metadata, read back from the store without restoring
+    /// the topics, sorted by name.
+    pub fn spilled_topics(&self) -> Vec<(String, TopicMetadata)> {
+        let mut topics: Vec<String> = self.index.lock().keys().cloned().collect();
+        topics.sort();
+
+        topics
+            .into_iter()
+            .filter_map(|topic| self.get(&topic).map(|metadata| (topic, metadata)))
+            .collect()
+    }
+
+    /// Spills the cold topics that have been idle the longest until at most the maximum number
+    /// of topics is held in memory, or no cold topics are left. Returns the number of spilled
+    /// topics.
+    ///
+    /// # Arguments
+    ///
+    /// * `active_topics` - The locked active topics map.
+    /// * `now` - The time the topics are spilled at.
+    pub fn spill_cold_topics(&self, active_topics: &mut ActiveTopicsMap, now: Instant) -> usize {
+        let excess = active_topics
+            .len()
+            .saturating_sub(self.max_in_memory_topics);
+        if excess == 0 {
+            return 0;
+        }
+
+        let mut cold_topics: Vec<(Duration, String)> = active_topics
+            .iter()
+            .filter(|(_, metadata)| metadata.is_cold())
+            .map(|(topic, metadata)| (metadata.idle_duration(now), topic.clone()))
+            .collect();
+        cold_topics.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+        let mut index = self.index.lock();
+        let mut spilled = 0;
+
+        for (_, topic) in cold_topics.into_iter().take(excess) {
+            let metadata = &active_topics[&topic];
+
+            if let Err(err) = self.store.put(&topic, &metadata.to_spilled(now)) {
+                warn!("Unable to spill topic '{topic}', keeping it in memory: {err}");
+                break;
+            }
+
+            index.insert(
+                topic.clone(),
+                SpillIndexEntry {
+                    client_id: metadata.client_id.clone(),
+                    standby_client_ids: metadata.standby_client_ids().to_vec(),
+                    subject: metadata.subject().map(str::to_string),
+                    spilled_at: now,
+                    idle_since: now.checked_sub(metadata.idle_duration(now)).unwrap_or(now),
+                },
+            );
+            active_topics.remove(&topic);
+            spilled += 1;
+        }
+
+        if spilled > 0 {
+            info!(
+                "Spilled {spilled} cold topics to disk, {} topics are spilled.",
+                index.len()
+            );
+        }
+
+        spilled
+    }
+
+    /// Returns the spilled topics that have gone without subscribers for longer than the given
+    /// duration.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_idle` - The maximum idle duration of topics.
+    /// * `now` - The time to measure the idle durations at.
+    pub fn idle_topics(&self, max_idle: Duration, now: Instant) -> Vec<String> {
+        self.index
+            .lock()
+            .iter()
+            .filter(|(_, entry)| now.saturating_duration_since(entry.idle_since) > max_idle)
+            .map(|(topic, _)| topic.clone())
+            .collect()
+    }
+
+    /// Restores a spilled topic to the active topics map. Returns whether the topic was restored.
+    ///
+    /// # Arguments
+    ///
+    /// * `active_topics` - The locked active topics map.
+    /// * `topic` - The name of the topic.
+    pub fn restore(&self, active_topics: &mut ActiveTopicsMap, topic: &str) -> bool {
+        let Some(entry) = self.index.lock().remove(topic) else {
+            return false;
+        };
+
+        self.restore_entry(active_topics, topic, entry)
+    }
+
+    /// Restores the spilled topics of a publisher to the active topics map. Returns the number
+    /// of restored topics.
+    ///
+    /// # Arguments
+    ///
+    /// * `active_topics` - The locked active topics map.
+    /// * `client_id` - The id of the publisher.
+    pub fn restore_publisher(&self, active_topics: &mut ActiveTopicsMap, client_id: &str) -> usize {
+        self.restore_where(active_topics, |_, entry| {
+            entry.client_id == client_id
+                || entry.standby_client_ids.iter().any(|id| id == client_id)
+        })
+    }
+
+    /// Restores the spilled topic of a subject to the active topics map. Returns whether a topic
+    /// was restored.
+    ///
+    /// # Arguments
+    ///
+    /// * `active_topics` - The locked active topics map.
+    /// * `subject` - The subject the topic was requested for.
+    pub fn restore_subject(&self, active_topics: &mut ActiveTopicsMap, subject: &str) -> bool {
+        self.restore_where(active_topics, |_, entry| {
+            entry.subject.as_deref() == Some(subject)
+        }) > 0
+    }
+
+    /// Restores the spilled topics of a publisher and with a prefix to the active topics map. An
+    /// empty filter matches every topic. Returns the number of restored topics.
+    ///
+    /// # Arguments
+    ///
+    /// * `active_topics` - The locked active topics map.
+    /// * `publisher_id` - The id of the publisher of the topics.
+    /// * `topic_prefix` - The prefix of the topics.
+    pub fn restore_matching(
+        &self,
+        active_topics: &mut ActiveTopicsMap,
+        publisher_id: &str,
+        topic_prefix: &str,
+    ) -> usize {
+        self.restore_where(active_topics, |topic, entry| {
+            (publisher_id.is_empty() || entry.client_id == publisher_id)
+                && topic.starts_with(topic_prefix)
+        })
+    }
+
+    /// Restores the spilled topics whose name and index entry match a predicate.
+    ///
+    /// # Arguments
+    ///
+    /// * `active_topics` - The locked active topics map.
+    /// * `predicate` - Selects the index entries of the topics to restore.
+    fn restore_where(
+        &self,
+        active_topics: &mut ActiveTopicsMap,
+        predicate: impl Fn(&str, &SpillIndexEntry) -> bool,
+    ) -> usize {
+        let entries: Vec<(String, SpillIndexEntry)> = {
+            let mut index = self.index.lock();
+            let topics: Vec<String> = index
+                .iter()
+                .filter(|(topic, entry)| predicate(topic, entry))
+                .map(|(topic, _)| topic.clone())
+                .collect();
+
+            topics
+                .into_iter()
+                .filter_map(|topic| index.remove(&topic).map(|entry| (topic, entry)))
+                .collect()
+        };
+
+        entries
+            .into_iter()
+            .filter(|(topic, entry)| self.restore_entry(active_topics, topic, entry.clone()))
+            .count()
+    }
+
+    /// Reads a topic removed from the index back from the store into the active topics map.
+    ///
+    /// # Arguments
+    ///
+    /// * `active_topics` - The locked active topics map.
+    /// * `topic` - The name of the topic.
+    /// * `entry` - The index entry of the topic.
+    fn restore_entry(
+        &self,
+        active_topics: &mut ActiveTopicsMap,
+        topic: &str,
+        entry: SpillIndexEntry,
+    ) -> bool {
+        match self.store.take(topic) {
+            Ok(Some(spilled)) => {
+                active_topics.insert(
+                    topic.to_string(),
+                    TopicMetadata::from_spilled(spilled, entry.spilled_at),
+                );
+                true
+            }
+            Ok(None) => {
+                warn!("Spilled topic '{topic}' is missing from the spill store.");
+                false
+            }
+            Err(err) => {
+                warn!("Unable to restore spilled topic '{topic}': {err}");
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod topic_spill_tests {
+    use std::{fs, path::PathBuf, process};
+
+    use common::payload_compression::PayloadCompression;
+
+    use crate::state_store::JsonFileStore;
+
+    use super::*;
+
+    fn spill_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("agemo-spill-{name}-{}", process::id()))
+    }
+
+    #[test]
+    fn spill_cold_topics_test() {
+        let dir = spill_dir("cold");
+        let spill = TopicSpill::new(Box::new(JsonFileStore::open(dir.clone()).unwrap()), 2);
+        let now = Instant::now();
+        let cb = Some("http://0.0.0.0:50061".to_string());
+
+        let mut active_topics = ActiveTopicsMap::new();
+        let mut oldest = TopicMetadata::new_at("pub_a".to_string(), 0, cb.clone(), now);
+        oldest.enable_sequence_numbers(7);
+        oldest.set_payload_compression(PayloadCompression::Zstd);
+        active_topics.insert("oldest".to_string(), oldest);
+        active_topics.insert(
+            "older".to_string(),
+            TopicMetadata::new_at(
+                "pub_b".to_string(),
+                0,
+                cb.clone(),
+                now + Duration::from_secs(1),
+            ),
+        );
+        active_topics.insert(
+            "recent".to_string(),
+            TopicMetadata::new_at(
+                "pub_a".to_string(),
+                0,
+                cb.clone(),
+                now + Duration::from_secs(2),
+            ),
+        );
+        // Topics with subscribers are never spilled.
+        let mut subscribed = TopicMetadata::new_at("pub_a".to_string(), 1, cb, now);
+        subscribed.add_subscriber("sub".to_string());
+        active_topics.insert("subscribed".to_string(), subscribed);
+
+        let later = now + Duration::from_secs(10);
+        assert_eq!(2, spill.spill_cold_topics(&mut active_topics, later));
+        assert_eq!(2, active_topics.len());
+        assert!(active_topics.contains_key("recent"));
+        assert!(active_topics.contains_key("subscribed"));
+        assert!(spill.is_spilled("oldest"));
+        assert!(spill.is_spilled("older"));
+        assert_eq!(2, spill.spilled_count());
+
+        // Spilled topics can be read without restoring them.
+        assert_eq!(
+            vec!["older".to_string(), "oldest".to_string()],
+            spill
+                .spilled_topics()
+                .into_iter()
+                .map(|(topic, _)| topic)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(7), spill.get("oldest").unwrap().sequence_epoch());
+        assert!(spill.get("recent").is_none());
+        assert!(spill.is_spilled("oldest"));
+        assert_eq!(
+            vec!["oldest".to_string()],
+            spill.idle_topics(Duration::from_secs(9), later)
+        );
+
+        // Nothing is spilled once the limit is met.
+        assert_eq!(0, spill.spill_cold_topics(&mut active_topics, later));
+
+        assert!(spill.restore(&mut active_topics, "oldest"));
+        let restored = &active_topics["oldest"];
+        assert_eq!("pub_a", restored.client_id);
+        assert_eq!(Some(7), restored.sequence_epoch());
+        assert_eq!(PayloadCompression::Zstd, restored.payload_compression());
+        assert_eq!(
+            Duration::from_secs(20),
+            restored.idle_duration(later + Duration::from_secs(10))
+        );
+        assert!(!spill.restore(&mut active_topics, "oldest"));
+
+        assert_eq!(1, spill.restore_publisher(&mut active_topics, "pub_b"));
+        assert!(active_topics.contains_key("older"));
+        assert_eq!(0, spill.spilled_count());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
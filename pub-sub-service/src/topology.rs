@@ -0,0 +1,285 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Export of the pub/sub topology of the service, for visualization.
+//!
+//! A [`Topology`] groups the active topics by publisher, with the subscribers of each topic. It is
+//! served through the admin `ExportTopology` method as JSON, or as a Graphviz DOT graph that can be
+//! rendered with `dot -Tsvg`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use serde_derive::Serialize;
+
+use crate::topic_manager::TopicMetadata;
+
+/// Formats the topology can be exported in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TopologyFormat {
+    /// A JSON object.
+    #[default]
+    Json,
+    /// A Graphviz DOT graph.
+    Dot,
+}
+
+impl TopologyFormat {
+    /// Returns the format for a value of the `TopologyFormat` proto enum, if it is known.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value of the proto enum.
+    pub fn from_proto(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(TopologyFormat::Json),
+            1 => Some(TopologyFormat::Dot),
+            _ => None,
+        }
+    }
+}
+
+/// A topic and its subscribers.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TopicNode {
+    /// The name of the topic.
+    pub topic: String,
+    /// The number of subscribers on the topic, including subscribers whose id is unknown.
+    pub subscriber_count: i32,
+    /// The ids of the subscribers known to the service, sorted.
+    pub subscribers: Vec<String>,
+}
+
+impl TopicNode {
+    /// Creates a new TopicNode.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The name of the topic.
+    /// * `metadata` - The metadata of the topic.
+    fn new(topic: &str, metadata: &TopicMetadata) -> Self {
+        let mut subscribers: Vec<String> = metadata.subscribers().cloned().collect();
+        subscribers.sort();
+
+        TopicNode {
+            topic: topic.to_string(),
+            subscriber_count: metadata.count,
+            subscribers,
+        }
+    }
+}
+
+/// A publisher and the topics it created.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PublisherNode {
+    /// The id of the publisher.
+    pub publisher_id: String,
+    /// The topics of the publisher, sorted by name.
+    pub topics: Vec<TopicNode>,
+}
+
+/// The publishers, topics and subscribers known to the service.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Topology {
+    /// The publishers, sorted by id.
+    pub publishers: Vec<PublisherNode>,
+    /// The topics without a known publisher, such as placeholder topics, sorted by name.
+    pub unpublished_topics: Vec<TopicNode>,
+}
+
+impl Topology {
+    /// Captures the topology of the given topics.
+    ///
+    /// # Arguments
+    ///
+    /// * `topics` - The topics and their metadata, such as the locked active topics map.
+    pub fn new<'a>(topics: impl IntoIterator<Item = (&'a String, &'a TopicMetadata)>) -> Self {
+        let mut publishers: BTreeMap<&str, Vec<TopicNode>> = BTreeMap::new();
+        let mut unpublished_topics = Vec::new();
+
+        for (topic, metadata) in topics {
+            let node = TopicNode::new(topic, metadata);
+
+            if metadata.client_id.is_empty() {
+                unpublished_topics.push(node);
+            } else {
+                publishers
+                    .entry(&metadata.client_id)
+                    .or_default()
+                    .push(node);
+            }
+        }
+
+        unpublished_topics.sort_by(|a, b| a.topic.cmp(&b.topic));
+
+        Topology {
+            publishers: publishers
+                .into_iter()
+                .map(|(publisher_id, mut topics)| {
+                    topics.sort_by(|a, b| a.topic.cmp(&b.topic));
+                    PublisherNode {
+                        publisher_id: publisher_id.to_string(),
+                        topics,
+                    }
+                })
+                .collect(),
+            unpublished_topics,
+        }
+    }
+
+    /// Returns the topology in the given format.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The format to export the topology in.
+    pub fn export(&self, format: TopologyFormat) -> Result<String, serde_json::Error> {
+        match format {
+            TopologyFormat::Json => serde_json::to_string_pretty(self),
+            TopologyFormat::Dot => Ok(self.to_dot()),
+        }
+    }
+
+    /// Returns the topology as a Graphviz DOT graph, with edges from publishers to their topics
+    /// and from topics to their subscribers.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph agemo {\n    rankdir=LR;\n");
+        let mut subscribers = BTreeSet::new();
+
+        let topics = self
+            .publishers
+            .iter()
+            .flat_map(|publisher| publisher.topics.iter())
+            .chain(&self.unpublished_topics);
+
+        for publisher in &self.publishers {
+            let _ = writeln!(
+                dot,
+                "    \"publisher:{0}\" [label=\"{0}\", shape=box];",
+                escape(&publisher.publisher_id)
+            );
+        }
+
+        for topic in topics.clone() {
+            let _ = writeln!(
+                dot,
+                "    \"topic:{0}\" [label=\"{0}\\n{1} subscribers\", shape=ellipse];",
+                escape(&topic.topic),
+                topic.subscriber_count
+            );
+            subscribers.extend(&topic.subscribers);
+        }
+
+        for subscriber in subscribers {
+            let _ = writeln!(
+                dot,
+                "    \"subscriber:{0}\" [label=\"{0}\", shape=diamond];",
+                escape(subscriber)
+            );
+        }
+
+        for publisher in &self.publishers {
+            for topic in &publisher.topics {
+                let _ = writeln!(
+                    dot,
+                    "    \"publisher:{}\" -> \"topic:{}\";",
+                    escape(&publisher.publisher_id),
+                    escape(&topic.topic)
+                );
+            }
+        }
+
+        for topic in topics {
+            for subscriber in &topic.subscribers {
+                let _ = writeln!(
+                    dot,
+                    "    \"topic:{}\" -> \"subscriber:{}\";",
+                    escape(&topic.topic),
+                    escape(subscriber)
+                );
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Escapes a string for use in a quoted DOT identifier.
+///
+/// # Arguments
+///
+/// * `value` - The string to escape.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod topology_tests {
+    use crate::topic_manager::ActiveTopicsMap;
+
+    use super::*;
+
+    fn active_topics() -> ActiveTopicsMap {
+        let mut active_topics = ActiveTopicsMap::new();
+
+        let mut gps = TopicMetadata::new("pub_1".to_string(), 2, None);
+        gps.add_subscriber("sub_1".to_string());
+        active_topics.insert("gps".to_string(), gps);
+
+        active_topics.insert(
+            "cabin".to_string(),
+            TopicMetadata::new("pub_1".to_string(), 0, None),
+        );
+
+        let mut placeholder = TopicMetadata::new(String::new(), 1, None);
+        placeholder.add_subscriber("sub_\"2\"".to_string());
+        active_topics.insert("typo".to_string(), placeholder);
+
+        active_topics
+    }
+
+    #[test]
+    fn topology_groups_topics_by_publisher_test() {
+        let topology = Topology::new(&active_topics());
+
+        assert_eq!(1, topology.publishers.len());
+        assert_eq!("pub_1", topology.publishers[0].publisher_id);
+        let topics: Vec<&str> = topology.publishers[0]
+            .topics
+            .iter()
+            .map(|topic| topic.topic.as_str())
+            .collect();
+        assert_eq!(vec!["cabin", "gps"], topics);
+        assert_eq!(2, topology.publishers[0].topics[1].subscriber_count);
+        assert_eq!(vec!["sub_1"], topology.publishers[0].topics[1].subscribers);
+
+        assert_eq!(1, topology.unpublished_topics.len());
+        assert_eq!("typo", topology.unpublished_topics[0].topic);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&topology.export(TopologyFormat::Json).unwrap()).unwrap();
+        assert_eq!(
+            Some("gps"),
+            json["publishers"][0]["topics"][1]["topic"].as_str()
+        );
+    }
+
+    #[test]
+    fn to_dot_test() {
+        let dot = Topology::new(&active_topics()).to_dot();
+
+        assert!(dot.starts_with("digraph agemo {\n"));
+        assert!(dot.contains("\"publisher:pub_1\" [label=\"pub_1\", shape=box];"));
+        assert!(dot.contains("\"topic:gps\" [label=\"gps\\n2 subscribers\", shape=ellipse];"));
+        assert!(dot.contains("\"publisher:pub_1\" -> \"topic:gps\";"));
+        assert!(dot.contains("\"topic:gps\" -> \"subscriber:sub_1\";"));
+        assert!(dot.contains("\"topic:typo\" -> \"subscriber:sub_\\\"2\\\"\";"));
+        assert!(!dot.contains("\"publisher:\""));
+        assert!(dot.ends_with("}\n"));
+
+        assert_eq!(None, TopologyFormat::from_proto(2));
+    }
+}
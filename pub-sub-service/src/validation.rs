@@ -0,0 +1,345 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Module containing validation of requests made to the pub sub service.
+//!
+//! Requests are validated before they change any state in the service, so that malformed requests
+//! are rejected with a detailed [`Status`] instead of failing later on.
+
+use common::{
+    payload_compression::PayloadCompression, protocol_kind::ProtocolKind, topic_class::TopicClass,
+    uri,
+};
+use tonic::Status;
+use url::Url;
+
+use proto::pubsub::v1::CreateTopicRequest;
+
+/// Management protocols that the service can use to reach a publisher.
+pub const SUPPORTED_MANAGEMENT_PROTOCOLS: [ProtocolKind; 1] = [ProtocolKind::Grpc];
+/// Uri schemes that a management callback can use.
+const SUPPORTED_CALLBACK_SCHEMES: [&str; 2] = ["http", "https"];
+/// Maximum number of tags of a topic.
+const MAX_TOPIC_TAGS: usize = 16;
+/// Maximum length in characters of a topic tag.
+const MAX_TOPIC_TAG_LEN: usize = 64;
+
+/// Validates a management callback uri.
+///
+/// Returns the normalized uri (see [`uri::normalize_uri`]), or a description of why the uri is
+/// invalid.
+///
+/// # Arguments
+///
+/// * `callback` - The management callback uri to validate.
+pub fn validate_management_callback(callback: &str) -> Result<String, String> {
+    let normalized = uri::normalize_uri(callback)
+        .map_err(|e| format!("'{callback}' is not a valid uri: {e}"))?;
+    let uri =
+        Url::parse(&normalized).map_err(|e| format!("'{callback}' is not a valid uri: {e}"))?;
+
+    if !SUPPORTED_CALLBACK_SCHEMES.contains(&uri.scheme()) {
+        return Err(format!(
+            "'{callback}' uses unsupported scheme '{}', expected one of {SUPPORTED_CALLBACK_SCHEMES:?}",
+            uri.scheme()
+        ));
+    }
+
+    if uri.host_str().map_or(true, str::is_empty) {
+        return Err(format!("'{callback}' has no host"));
+    }
+
+    Ok(normalized)
+}
+
+/// Validates the fields of a [`CreateTopicRequest`].
+///
+/// Returns the normalized management callback, or an `InvalidArgument` status describing every
+/// invalid field.
+///
+/// # Arguments
+///
+/// * `request` - The request to validate.
+pub fn validate_create_topic_request(request: &CreateTopicRequest) -> Result<String, Status> {
+    let mut errors = Vec::new();
+
+    if request.publisher_id.trim().is_empty() {
+        errors.push("'publisherId' must not be empty".to_string());
+    }
+
+    let management_callback = match validate_management_callback(&request.management_callback) {
+        Ok(management_callback) => Some(management_callback),
+        Err(err) => {
+            errors.push(format!("'managementCallback' {err}"));
+            None
+        }
+    };
+
+    // The enum takes precedence, the string is kept for publishers that do not set the enum.
+    match ProtocolKind::resolve(
+        request.management_protocol_kind,
+        &request.management_protocol,
+    ) {
+        Ok(protocol_kind) if SUPPORTED_MANAGEMENT_PROTOCOLS.contains(&protocol_kind) => {}
+        Ok(protocol_kind) => errors.push(format!(
+            "'managementProtocol' '{protocol_kind}' is not supported, expected one of {SUPPORTED_MANAGEMENT_PROTOCOLS:?}"
+        )),
+        Err(_) => errors.push(format!(
+            "'managementProtocol' '{}' is not supported, expected one of {SUPPORTED_MANAGEMENT_PROTOCOLS:?}",
+            request.management_protocol
+        )),
+    }
+
+    if TopicClass::from_proto(request.topic_class).is_none() {
+        errors.push(format!(
+            "'topicClass' {} is not a known topic class",
+            request.topic_class
+        ));
+    }
+
+    if PayloadCompression::from_proto(request.payload_compression).is_none() {
+        errors.push(format!(
+            "'payloadCompression' {} is not a known payload compression",
+            request.payload_compression
+        ));
+    }
+
+    if request.tags.len() > MAX_TOPIC_TAGS {
+        errors.push(format!(
+            "'tags' has {} tags, expected at most {MAX_TOPIC_TAGS}",
+            request.tags.len()
+        ));
+    }
+    for tag in &request.tags {
+        if tag.trim().is_empty() || tag.chars().count() > MAX_TOPIC_TAG_LEN {
+            errors.push(format!(
+                "'tags' entry '{tag}' must not be empty or longer than {MAX_TOPIC_TAG_LEN} characters"
+            ));
+        }
+    }
+
+    match management_callback {
+        Some(management_callback) if errors.is_empty() => Ok(management_callback),
+        _ => Err(Status::invalid_argument(format!(
+            "invalid CreateTopicRequest: {}",
+            errors.join("; ")
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod validation_tests {
+    use tonic::Code;
+
+    use super::*;
+
+    fn valid_request() -> CreateTopicRequest {
+        CreateTopicRequest {
+            publisher_id: "pub_test".to_string(),
+            management_callback: "http://0.0.0.0:50061".to_string(),
+            management_protocol: "grpc+proto".to_string(),
+            idempotency_key: String::new(),
+            management_protocol_kind: 0,
+            single_consumer: false,
+            pre_warm: false,
+            notify_subscriber_joined: false,
+            sequence_numbers: false,
+            topic_class: 0,
+            topic_template: String::new(),
+            deletion_message: None,
+            suppress_deletion_message: false,
+            tags: Vec::new(),
+            payload_compression: 0,
+        }
+    }
+
+    #[test]
+    fn valid_request_test() {
+        assert_eq!(
+            "http://0.0.0.0:50061",
+            validate_create_topic_request(&valid_request()).unwrap()
+        );
+    }
+
+    #[test]
+    fn empty_publisher_id_test() {
+        for publisher_id in ["", "   "] {
+            let request = CreateTopicRequest {
+                publisher_id: publisher_id.to_string(),
+                ..valid_request()
+            };
+
+            let status = validate_create_topic_request(&request).unwrap_err();
+            assert_eq!(Code::InvalidArgument, status.code());
+            assert!(status.message().contains("'publisherId'"));
+        }
+    }
+
+    #[test]
+    fn management_callback_test() {
+        assert_eq!(
+            Ok("http://0.0.0.0:50061".to_string()),
+            validate_management_callback("http://0.0.0.0:50061")
+        );
+        assert!(validate_management_callback("https://publisher.local/callback").is_ok());
+
+        // A uri without a scheme is normalized to the default scheme.
+        assert_eq!(
+            Ok("http://0.0.0.0:50061".to_string()),
+            validate_management_callback("0.0.0.0:50061")
+        );
+
+        // Unsupported scheme, missing host and unparseable uris are rejected.
+        assert!(validate_management_callback("mqtt://0.0.0.0:1883").is_err());
+        assert!(validate_management_callback("http://").is_err());
+        assert!(validate_management_callback("").is_err());
+        assert!(validate_management_callback("http://[::1").is_err());
+    }
+
+    #[test]
+    fn invalid_management_callback_test() {
+        let request = CreateTopicRequest {
+            management_callback: "not a uri".to_string(),
+            ..valid_request()
+        };
+
+        let status = validate_create_topic_request(&request).unwrap_err();
+        assert_eq!(Code::InvalidArgument, status.code());
+        assert!(status.message().contains("'managementCallback'"));
+    }
+
+    #[test]
+    fn supported_management_protocols_test() {
+        for management_protocol in ["grpc", "grpc+proto", "GRPC"] {
+            let request = CreateTopicRequest {
+                management_protocol: management_protocol.to_string(),
+                ..valid_request()
+            };
+
+            assert!(validate_create_topic_request(&request).is_ok());
+        }
+    }
+
+    #[test]
+    fn management_protocol_kind_test() {
+        // The enum alone is enough.
+        let request = CreateTopicRequest {
+            management_protocol: String::new(),
+            management_protocol_kind: ProtocolKind::Grpc.to_proto(),
+            ..valid_request()
+        };
+        assert!(validate_create_topic_request(&request).is_ok());
+
+        // The enum takes precedence over the string.
+        let request = CreateTopicRequest {
+            management_protocol_kind: ProtocolKind::MqttV5.to_proto(),
+            ..valid_request()
+        };
+        let status = validate_create_topic_request(&request).unwrap_err();
+        assert!(status.message().contains("'managementProtocol' 'mqtt_v5'"));
+    }
+
+    #[test]
+    fn unsupported_management_protocol_test() {
+        let request = CreateTopicRequest {
+            management_protocol: "mqtt_v5".to_string(),
+            ..valid_request()
+        };
+
+        let status = validate_create_topic_request(&request).unwrap_err();
+        assert_eq!(Code::InvalidArgument, status.code());
+        assert!(status.message().contains("'managementProtocol' 'mqtt_v5'"));
+    }
+
+    #[test]
+    fn topic_class_test() {
+        let request = CreateTopicRequest {
+            topic_class: TopicClass::ConfigRetained.to_proto(),
+            ..valid_request()
+        };
+        assert!(validate_create_topic_request(&request).is_ok());
+
+        let request = CreateTopicRequest {
+            topic_class: 7,
+            ..valid_request()
+        };
+        let status = validate_create_topic_request(&request).unwrap_err();
+        assert_eq!(Code::InvalidArgument, status.code());
+        assert!(status.message().contains("'topicClass' 7"));
+    }
+
+    #[test]
+    fn payload_compression_test() {
+        let request = CreateTopicRequest {
+            payload_compression: PayloadCompression::Zstd.to_proto(),
+            ..valid_request()
+        };
+        assert!(validate_create_topic_request(&request).is_ok());
+
+        let request = CreateTopicRequest {
+            payload_compression: 9,
+            ..valid_request()
+        };
+        let status = validate_create_topic_request(&request).unwrap_err();
+        assert_eq!(Code::InvalidArgument, status.code());
+        assert!(status.message().contains("'payloadCompression' 9"));
+    }
+
+    #[test]
+    fn tags_test() {
+        let request = CreateTopicRequest {
+            tags: vec!["cloud_telemetry".to_string()],
+            payload_compression: 0,
+            ..valid_request()
+        };
+        assert!(validate_create_topic_request(&request).is_ok());
+
+        let request = CreateTopicRequest {
+            tags: vec![String::new(), "x".repeat(MAX_TOPIC_TAG_LEN + 1)],
+            payload_compression: 0,
+            ..valid_request()
+        };
+        let status = validate_create_topic_request(&request).unwrap_err();
+        assert_eq!(Code::InvalidArgument, status.code());
+        assert!(status.message().contains("'tags' entry ''"));
+        assert!(status.message().contains(&format!(
+            "'tags' entry '{}'",
+            "x".repeat(MAX_TOPIC_TAG_LEN + 1)
+        )));
+
+        let request = CreateTopicRequest {
+            tags: vec!["tag".to_string(); MAX_TOPIC_TAGS + 1],
+            payload_compression: 0,
+            ..valid_request()
+        };
+        let status = validate_create_topic_request(&request).unwrap_err();
+        assert!(status.message().contains("'tags' has 17 tags"));
+    }
+
+    #[test]
+    fn reports_every_invalid_field_test() {
+        let request = CreateTopicRequest {
+            publisher_id: String::new(),
+            management_callback: String::new(),
+            management_protocol: String::new(),
+            idempotency_key: String::new(),
+            management_protocol_kind: 0,
+            single_consumer: false,
+            pre_warm: false,
+            notify_subscriber_joined: false,
+            sequence_numbers: false,
+            topic_class: 0,
+            topic_template: String::new(),
+            deletion_message: None,
+            suppress_deletion_message: false,
+            tags: Vec::new(),
+            payload_compression: 0,
+        };
+
+        let status = validate_create_topic_request(&request).unwrap_err();
+        assert!(status.message().contains("'publisherId'"));
+        assert!(status.message().contains("'managementCallback'"));
+        assert!(status.message().contains("'managementProtocol'"));
+    }
+}
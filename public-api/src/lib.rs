@@ -0,0 +1,99 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Checks the stable APIs against the snapshots in `snapshots`, so that a change to them can't be
+//! merged without updating them. See `docs/api-stability.md` for what a change to the snapshots
+//! means for the version of the crates.
+//!
+//! Reading the public API needs the rustdoc JSON output of a nightly toolchain, so the crate is
+//! kept out of the workspace and the checks run in CI with:
+//!
+//! ```shell
+//! rustup toolchain install nightly-2023-12-01 --profile minimal
+//! cargo test --manifest-path public-api/Cargo.toml
+//! ```
+//!
+//! After an intended change, run the same command with `UPDATE_EXPECT=1` to update the snapshots.
+
+#[cfg(test)]
+mod public_api_tests {
+    use std::path::Path;
+
+    use expect_test::expect_file;
+    use public_api::PublicApi;
+
+    /// Nightly toolchain that the rustdoc JSON is built with. The JSON format and the rendering of
+    /// the items change between nightlies, so the toolchain is pinned, and the CI job installs the
+    /// same one.
+    const NIGHTLY_TOOLCHAIN: &str = "nightly-2023-12-01";
+
+    /// Modules of the `common` crate whose items are re-exported by the stable API.
+    const STABLE_COMMON_MODULES: [&str; 5] = [
+        "common::action_casing",
+        "common::payload_compression",
+        "common::protocol_kind",
+        "common::subscription_metadata",
+        "common::topic_class",
+    ];
+
+    /// Reads the public API of a crate of the workspace, with its default features. Blanket, auto
+    /// trait and derived trait impls are left out, as they change with the toolchain rather than
+    /// with the crate.
+    ///
+    /// # Arguments
+    ///
+    /// * `manifest_path` - The manifest of the crate, relative to this crate.
+    fn public_api(manifest_path: &str) -> PublicApi {
+        let rustdoc_json = rustdoc_json::Builder::default()
+            .toolchain(NIGHTLY_TOOLCHAIN)
+            .manifest_path(Path::new(env!("CARGO_MANIFEST_DIR")).join(manifest_path))
+            .build()
+            .expect("failed to build the rustdoc JSON");
+
+        public_api::Builder::from_rustdoc_json(rustdoc_json)
+            .omit_blanket_impls(true)
+            .omit_auto_trait_impls(true)
+            .omit_auto_derived_impls(true)
+            .build()
+            .expect("failed to read the public API")
+    }
+
+    #[test]
+    fn client_public_api_test() {
+        expect_file!["../snapshots/agemo-client.txt"]
+            .assert_eq(&public_api("../client/Cargo.toml").to_string());
+    }
+
+    #[test]
+    fn common_stable_public_api_test() {
+        // Only the items of the re-exported modules are stable, the rest of the crate is internal.
+        let stable_items: String = public_api("../common/Cargo.toml")
+            .items()
+            .map(ToString::to_string)
+            .filter(|item| {
+                item.find("common::").map_or(false, |start| {
+                    STABLE_COMMON_MODULES
+                        .iter()
+                        .any(|module| item[start..].starts_with(module))
+                })
+            })
+            .map(|item| item + "\n")
+            .collect();
+
+        expect_file!["../snapshots/common.txt"].assert_eq(&stable_items);
+    }
+
+    #[test]
+    fn connector_public_api_test() {
+        expect_file!["../snapshots/agemo-connector.txt"]
+            .assert_eq(&public_api("../connector/Cargo.toml").to_string());
+    }
+
+    #[test]
+    fn proto_public_api_test() {
+        // Covers the clients and servers, which are generated by the default features.
+        expect_file!["../snapshots/agemo-proto.txt"]
+            .assert_eq(&public_api("../proto-build/Cargo.toml").to_string());
+    }
+}
@@ -5,14 +5,20 @@
 //! Chariott-enabled publisher example showing the process for creating and publishing to a dynamic
 //! topic following the Pub Sub Service model. Registers with Chariott to be discoverable.
 
+use std::time::Duration;
+
+use common::uri;
 use env_logger::{Builder, Target};
 use log::LevelFilter;
 use publisher_impl::PublisherImpl;
 use samples_common::{
     chariott_helper::{self, ChariottClient},
+    data_generator::GeneratorRegistry,
+    discovery_cache::{DiscoveryCache, ServiceQuery},
     load_config::{
-        load_settings, ChariottPublisherServiceSettings, CommunicationConstants, ServiceIdentifier,
-        CONFIG_FILE, CONSTANTS_FILE,
+        load_data_generator_configs, load_discovery_cache_settings, load_publish_options,
+        load_reserved_name_policy, load_settings, ChariottPublisherServiceSettings,
+        CommunicationConstants, ServiceIdentifier, CONFIG_FILE, CONSTANTS_FILE,
     },
     publisher_helper::DynamicPublisher,
 };
@@ -21,7 +27,7 @@ use samples_proto::{
     sample_publisher::v1::sample_publisher_server::SamplePublisherServer,
     service_registry::v1::{RegisterRequest, ServiceMetadata},
 };
-use tonic::{transport::Server, Request, Status};
+use tonic::{transport::Server, Request};
 
 mod publisher_impl;
 
@@ -41,7 +47,7 @@ async fn register_with_chariott(
     communication_kind: &str,
     communication_reference: &str,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let provider_uri_str = format!("http://{provider_authority}"); // Devskim: ignore DS137138
+    let provider_uri_str = uri::authority_uri(provider_authority);
 
     let service_metadata = ServiceMetadata {
         namespace: provider_identifier.namespace,
@@ -63,34 +69,6 @@ async fn register_with_chariott(
     Ok(())
 }
 
-/// Calls Chariott to get Pub Sub Service uri.
-///
-/// # Arguments
-///
-/// * `chariott_client` - The gRPC client for interacting with the Chariott service.
-/// * `namespace` - The namespace where the Pub Sub service is expected to be registered.
-/// * `retry_interval_secs` - The interval to wait before retrying the connection.
-/// * `communication_kind` - The expected kind of communication.
-/// * `communication_reference` - The expected reference API file.
-async fn get_pub_sub_uri_with_retry(
-    chariott_client: &mut ChariottClient,
-    namespace: &str,
-    retry_interval_secs: u64,
-    communication_kind: &str,
-    communication_reference: &str,
-) -> Result<String, Status> {
-    let service = chariott_helper::get_service_metadata_with_retry(
-        chariott_client,
-        namespace,
-        retry_interval_secs,
-        communication_kind,
-        communication_reference,
-    )
-    .await?;
-
-    Ok(service.uri)
-}
-
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     // Setup logging.
@@ -106,21 +84,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let addr = settings.publisher_authority.parse()?;
 
     // Attempt to connect with Chariott.
-    let mut chariott_client = chariott_helper::connect_to_chariott_with_retry(
+    let chariott_client = chariott_helper::connect_to_chariott_with_retry(
         &settings.chariott_uri,
         communication_consts.retry_interval_secs,
     )
     .await?;
+    let mut discovery = DiscoveryCache::new(
+        chariott_client,
+        Duration::from_secs(load_discovery_cache_settings().ttl_secs),
+        communication_consts.retry_interval_secs,
+    );
 
     // Wait for Pub Sub Service to register with Chariott.
-    let pub_sub_service_uri = get_pub_sub_uri_with_retry(
-        &mut chariott_client,
-        &settings.pub_sub_namespace,
-        communication_consts.retry_interval_secs,
-        &communication_consts.grpc_kind,
-        &communication_consts.pub_sub_reference,
-    )
-    .await?;
+    let pub_sub_service_uri = discovery
+        .resolve(&ServiceQuery::new(
+            &settings.pub_sub_namespace,
+            &communication_consts.grpc_kind,
+            &communication_consts.pub_sub_reference,
+        ))
+        .await?
+        .uri;
 
     // Instantiate the gRPC publisher implementation.
     let publisher: PublisherImpl = DynamicPublisher::new(
@@ -128,10 +111,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         pub_sub_service_uri,
         communication_consts.grpc_kind.clone(),
     );
+    let publisher = publisher
+        .with_disconnect_topic(communication_consts.disconnect_topic.clone())
+        .with_data_generators(
+            GeneratorRegistry::from_config(load_data_generator_configs())
+                .with_publish_options(load_publish_options()),
+        )
+        .with_name_policy(load_reserved_name_policy()?);
 
     // Register with Chariott.
     register_with_chariott(
-        &mut chariott_client,
+        discovery.client(),
         &settings.publisher_authority,
         settings.publisher_identifier.clone(),
         &communication_consts.grpc_kind,
@@ -8,11 +8,16 @@
 //! The DynamicPublisher trait defines three methods that execute on the three possible updates
 //! from the Pub Sub Service (START, STOP and DELETE).
 
-use log::info;
+use common::{
+    name_policy::ReservedNamePolicy, protocol_kind::ProtocolKind, shared_state::SharedState,
+};
+use log::{info, warn};
+use sample_mqtt_connector::client_connector::DEFAULT_DISCONNECT_TOPIC;
 use samples_common::{
-    data_generator,
+    data_generator::GeneratorRegistry,
     pub_sub_service_helper::{self, TopicAction},
     publisher_helper::{self, DynamicPublisher},
+    subscription_gate::SubscriptionGate,
     topic_store::{TopicMetadata, TopicStore},
 };
 use samples_proto::{
@@ -23,11 +28,8 @@ use samples_proto::{
         sample_publisher_server::SamplePublisher, SubscriptionInfoRequest, SubscriptionInfoResponse,
     },
 };
-use std::{
-    str::FromStr,
-    sync::{mpsc, Arc, Mutex},
-    time::Duration,
-};
+use std::{sync::Arc, time::Duration};
+use tokio_util::sync::CancellationToken;
 use tonic::{Request, Response, Status};
 
 /// Base structure for the publisher gRPC service.
@@ -40,9 +42,17 @@ pub struct PublisherImpl {
     /// The protocol used to communicate with the publisher.
     pub protocol: String,
     /// Store that maps the dynamically created topic to a topic known to the publisher.
-    pub topic_store: Arc<Mutex<TopicStore>>,
+    pub topic_store: Arc<SharedState<TopicStore>>,
     /// The uri of the Pub Sub Service.
     pub pub_sub_uri: String,
+    /// The topic the publisher's last will and testament is published to.
+    pub disconnect_topic: String,
+    /// Registry of the data generator used for each subject.
+    pub data_generators: Arc<GeneratorRegistry>,
+    /// Policy of the subject names that the publisher refuses to create topics for.
+    pub name_policy: Arc<ReservedNamePolicy>,
+    /// Gate deduplicating and rate limiting the topics created for subscription info requests.
+    pub subscription_gate: Arc<SubscriptionGate>,
 }
 
 impl PublisherImpl {
@@ -58,10 +68,55 @@ impl PublisherImpl {
             id: format!("pub_{}", uuid::Uuid::new_v4()),
             authority,
             protocol,
-            topic_store: Arc::new(Mutex::new(TopicStore::new())),
+            topic_store: Arc::new(SharedState::new(TopicStore::new())),
             pub_sub_uri,
+            disconnect_topic: DEFAULT_DISCONNECT_TOPIC.to_string(),
+            data_generators: Arc::new(GeneratorRegistry::new()),
+            name_policy: Arc::new(ReservedNamePolicy::default()),
+            subscription_gate: Arc::new(SubscriptionGate::default()),
         }
     }
+
+    /// Sets the topic the publisher's last will and testament is published to.
+    ///
+    /// # Arguments
+    ///
+    /// * `disconnect_topic` - The topic to publish the last will and testament to.
+    pub fn with_disconnect_topic(mut self, disconnect_topic: String) -> Self {
+        self.disconnect_topic = disconnect_topic;
+        self
+    }
+
+    /// Sets the registry of the data generator used for each subject.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_generators` - The registry to create data generators from.
+    pub fn with_data_generators(mut self, data_generators: GeneratorRegistry) -> Self {
+        self.data_generators = Arc::new(data_generators);
+        self
+    }
+
+    /// Sets the policy of the subject names that the publisher refuses to create topics for.
+    ///
+    /// # Arguments
+    ///
+    /// * `name_policy` - The policy of reserved subject names.
+    pub fn with_name_policy(mut self, name_policy: ReservedNamePolicy) -> Self {
+        self.name_policy = Arc::new(name_policy);
+        self
+    }
+
+    /// Sets the gate deduplicating and rate limiting the topics created for subscription info
+    /// requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscription_gate` - The gate to create topics through.
+    pub fn with_subscription_gate(mut self, subscription_gate: SubscriptionGate) -> Self {
+        self.subscription_gate = Arc::new(subscription_gate);
+        self
+    }
 }
 
 impl DynamicPublisher for PublisherImpl {
@@ -83,30 +138,26 @@ impl DynamicPublisher for PublisherImpl {
     /// * `topic` - The topic known to the publisher that is associated with the generated topic.
     /// * `generated_topic` - The generated topic from the Pub Sub Service.
     fn on_start_action(&self, topic: String, generated_topic: String) {
-        // Initialize a client with a disconnect channel
-        let (send, recv) = mpsc::channel::<String>();
-
-        let topic_metadata: Option<TopicMetadata>;
+        let activated_topic: Option<(TopicMetadata, CancellationToken)>;
 
-        // Activate topic in store.
+        // Activate topic in store, which provides the token that stops publishing.
         {
-            topic_metadata = self
-                .topic_store
-                .lock()
-                .unwrap()
-                .activate_topic(&topic, send);
+            activated_topic = self.topic_store.lock().activate_topic(&topic);
         }
 
-        let client_info = topic_metadata.unwrap().subscription_info;
+        let (topic_metadata, cancellation_token) = activated_topic.unwrap();
+        let client_info = topic_metadata.subscription_info;
 
-        // Start publishing in a separate thread. Uses a simple data generator from the common folder.
+        // Start publishing in a separate thread. Uses the data generator configured for the topic.
+        let generator = self.data_generators.create(&topic);
         let _handle = publisher_helper::handle_publish_loop(
             generated_topic,
             topic,
-            recv,
+            cancellation_token,
             self.id.clone(),
             client_info,
-            data_generator::get_data,
+            self.disconnect_topic.clone(),
+            generator,
         );
     }
 
@@ -117,7 +168,7 @@ impl DynamicPublisher for PublisherImpl {
     /// * `topic` - The topic known to the publisher that is associated with the generated topic.
     /// * `generated_topic` - The generated topic from the Pub Sub Service.
     fn on_stop_action(&self, topic: String, generated_topic: String) {
-        let topic_store = self.topic_store.lock().unwrap();
+        let topic_store = self.topic_store.lock();
 
         // Deactivate topic in store, which stops publishing to the passed in topic.
         topic_store.deactivate_topic(&topic);
@@ -154,7 +205,7 @@ impl DynamicPublisher for PublisherImpl {
         // so just remove the topic from the active lists.
 
         // Remove topic from lists.
-        let topic_store = self.topic_store.lock().unwrap();
+        let topic_store = self.topic_store.lock();
 
         topic_store.deactivate_topic(&topic);
         topic_store.remove_topic(&topic, &generated_topic);
@@ -178,7 +229,7 @@ impl PublisherCallback for PublisherImpl {
     ) -> Result<Response<ManageTopicResponse>, Status> {
         // Unwrap the request.
         let manage_req = request.into_inner();
-        let action = TopicAction::from_str(manage_req.action.as_str())
+        let action = TopicAction::parse(&manage_req.action)
             .map_err(|e| Status::not_found(format!("no valid action was found: {e}")))?;
         let generated_topic = manage_req.topic;
 
@@ -188,12 +239,36 @@ impl PublisherCallback for PublisherImpl {
             topic = self
                 .topic_store
                 .lock()
-                .unwrap()
                 .get_generated_topic_mapping(&generated_topic)?;
         }
 
         info!("Executing action '{action}' for topic '({topic}) {generated_topic}'.");
 
+        // A reconciliation restates the state the topic should be in after the service could not
+        // reach the publisher, so a topic that is already in that state is left as is.
+        if manage_req.reconciliation {
+            let current_action = self
+                .topic_store
+                .lock()
+                .get_topic_metadata(&topic)
+                .map(|topic_metadata| topic_metadata.action);
+            if current_action.as_ref() == Some(&action) {
+                info!(
+                    "Topic '{generated_topic}' already matches the reconciled action, with {} subscribers.",
+                    manage_req.subscriber_count
+                );
+                return Ok(Response::new(ManageTopicResponse {}));
+            }
+        }
+
+        // A START begins a new lifecycle epoch of the topic, which subscribers learn from the
+        // subscription metadata.
+        if action == TopicAction::Start && manage_req.lifecycle_epoch > 0 {
+            self.topic_store
+                .lock()
+                .set_lifecycle_epoch(&topic, manage_req.lifecycle_epoch);
+        }
+
         // Execute action for a topic based on the type.
         match action {
             TopicAction::Start => self.on_start_action(topic, generated_topic),
@@ -226,25 +301,44 @@ impl SamplePublisher for PublisherImpl {
         let requested_topic = request.into_inner().subject;
         info!("Got request for subscription info on subject '{requested_topic}'.");
 
+        // Reserved subjects could collide with broker-internal topics.
+        if let Some(rule) = self.name_policy.reserved_by(&requested_topic) {
+            warn!("Refusing subject '{requested_topic}' reserved by '{rule}'.");
+            return Err(Status::invalid_argument(format!(
+                "subject '{requested_topic}' is reserved by '{rule}'"
+            )));
+        }
+
+        // Requests for the same subject wait for each other, so that a burst of requests for a
+        // new subject creates a single topic that the later requests reuse.
+        let _subject_guard = self.subscription_gate.lock_subject(&requested_topic).await;
+
         // If there is already a dynamic topic created for the subject then shortcut and return
         // that subscription info.
         {
-            if let Some(topic_metadata) = self
-                .topic_store
-                .lock()
-                .unwrap()
-                .get_topic_metadata(&requested_topic)
+            if let Some(topic_metadata) =
+                self.topic_store.lock().get_topic_metadata(&requested_topic)
             {
                 return Ok(Response::new(topic_metadata.subscription_info));
             }
         }
 
+        // Limit how often topics are created, so that a burst of requests for many subjects can't
+        // flood the Pub Sub Service.
+        if !self.subscription_gate.try_acquire_creation() {
+            warn!("Rate limiting topic creation for subject '{requested_topic}'.");
+            return Err(Status::resource_exhausted(
+                "too many topics are being created, retry later",
+            ));
+        }
+
         // Otherwise, call Pub Sub Service and get the topic and subscription information.
         let topic_subscription_info = pub_sub_service_helper::create_topic(
             self.pub_sub_uri.clone(),
             self.id.clone(),
             self.authority.clone(),
-            String::from("grpc"),
+            ProtocolKind::Grpc,
+            self.data_generators.payload_compression(&requested_topic),
         )
         .await?;
 
@@ -252,7 +346,6 @@ impl SamplePublisher for PublisherImpl {
         {
             self.topic_store
                 .lock()
-                .unwrap()
                 .add_topic(requested_topic, topic_subscription_info.clone());
         }
 
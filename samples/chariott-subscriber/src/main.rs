@@ -6,51 +6,25 @@
 //! topic following the Pub Sub Service model. Calls Chariott's service discovery to get publisher
 //! uri information.
 
-use std::{env, sync::Arc};
+use std::{env, sync::Arc, time::Duration};
 
-use async_std::sync::Mutex;
 use env_logger::{Builder, Target};
-use log::{info, LevelFilter};
+use log::{info, warn, LevelFilter};
+use tokio::sync::Mutex;
 
 use samples_common::{
-    chariott_helper::{self, ChariottClient},
+    chariott_helper,
+    discovery_cache::{DiscoveryCache, ServiceQuery},
     load_config::{
+        load_discovery_cache_settings, load_recording_file, load_resubscribe_settings,
         load_settings, ChariottSubscriberServiceSettings, CommunicationConstants, CONFIG_FILE,
         CONSTANTS_FILE,
     },
+    recorder::MessageRecorder,
     subscriber_helper::{self, BrokerRef, TopicRef, EMPTY_TOPIC, SHUTDOWN},
 };
-use tonic::Status;
 use uuid::Uuid;
 
-/// Gets the publisher uri from Chariott.
-///
-/// # Arguments
-///
-/// * `chariott_client` - The Chariott client.
-/// * `namespace` - The namespace to get publisher information about.
-/// * `retry_interval_secs` - The interval to wait before retrying the connection.
-/// * `communication_kind` - The expected kind of communication.
-/// * `communication_reference` - The expected reference API file.
-async fn get_publisher_uri_with_retry(
-    chariott_client: &mut ChariottClient,
-    namespace: &str,
-    retry_interval_secs: u64,
-    communication_kind: &str,
-    communication_reference: &str,
-) -> Result<String, Status> {
-    let service = chariott_helper::get_service_metadata_with_retry(
-        chariott_client,
-        namespace,
-        retry_interval_secs,
-        communication_kind,
-        communication_reference,
-    )
-    .await?;
-
-    Ok(service.uri)
-}
-
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     // Setup logging.
@@ -82,29 +56,47 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let namespace = env::args().nth(2).unwrap_or(default_namespace);
 
     // Attempt to connect to Chariott.
-    let mut chariott_client = chariott_helper::connect_to_chariott_with_retry(
+    let chariott_client = chariott_helper::connect_to_chariott_with_retry(
         &settings.chariott_uri,
         communication_consts.retry_interval_secs,
     )
     .await?;
-
-    // Wait for publisher service to register with Chariott.
-    let publisher_uri = get_publisher_uri_with_retry(
-        &mut chariott_client,
-        &namespace,
+    let mut discovery = DiscoveryCache::new(
+        chariott_client,
+        Duration::from_secs(load_discovery_cache_settings().ttl_secs),
         communication_consts.retry_interval_secs,
+    );
+    let publisher_query = ServiceQuery::new(
+        &namespace,
         &communication_consts.grpc_kind,
         &settings.publisher_reference,
-    )
-    .await?;
+    );
+
+    // Wait for publisher service to register with Chariott.
+    let mut publisher_uri = discovery.resolve(&publisher_query).await?.uri;
 
-    // Get subscription information.
-    let info = subscriber_helper::get_subscription_info(
+    // Get subscription information. The publisher may have moved since it was discovered, in
+    // which case it is discovered again.
+    let info = match subscriber_helper::get_subscription_info(
         &publisher_uri,
         &subject,
         &communication_consts.mqtt_v5_kind,
     )
-    .await?;
+    .await
+    {
+        Ok(info) => info,
+        Err(err) if err.is_transient() => {
+            warn!("Unable to reach the publisher at '{publisher_uri}': {err}. Discovering it again...");
+            publisher_uri = discovery.re_resolve(&publisher_query).await?.uri;
+            subscriber_helper::get_subscription_info(
+                &publisher_uri,
+                &subject,
+                &communication_consts.mqtt_v5_kind,
+            )
+            .await?
+        }
+        Err(err) => return Err(err.into()),
+    };
     {
         let mut topic = topic_handle.lock().await;
         topic.topic = info.topic.clone();
@@ -112,25 +104,89 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
     // Set up the topic stream to receive messages on from the broker (MQTT v5 in this case).
     let id = format!("sub_{}", Uuid::new_v4());
-    let stream = subscriber_helper::get_subscription_stream(
+    let mut stream = subscriber_helper::get_subscription_stream(
         id,
         info.uri,
+        communication_consts.disconnect_topic.clone(),
         topic_handle.clone(),
-        broker_handle,
+        broker_handle.clone(),
     )
     .await?;
 
+    // Record the received messages to a file if a recording file is configured.
+    let mut recorder = match load_recording_file() {
+        Some(path) => {
+            info!("Recording received messages to '{path}'.");
+            Some(MessageRecorder::new(&path)?)
+        }
+        None => None,
+    };
+
+    // Whether to ask the publisher for a new topic when the topic is deleted.
+    let resubscribe_settings = load_resubscribe_settings();
+
     // Print out the messages received by the subscription.
     // This loop will not break unless the stream is broken by the client.
-    for msg in stream.into_iter() {
-        info!("({subject}) {}: {}", msg.topic, msg.payload);
-
-        // If deletion message is sent over the subscription then end the program.
-        if msg.payload == communication_consts.topic_deletion_message {
-            let mut topic = topic_handle.lock().await;
-            topic.topic = EMPTY_TOPIC.to_string();
-            let _ = shutdown_sender.send(SHUTDOWN.to_string());
+    loop {
+        let mut topic_deleted = false;
+
+        for msg in stream.iter() {
+            info!("({subject}) {}: {}", msg.topic, msg.payload);
+
+            if let Some(recorder) = recorder.as_mut() {
+                if let Err(err) = recorder.record(&subject, &msg) {
+                    warn!("Unable to record message: {err}");
+                }
+            }
+
+            // If deletion message is sent over the subscription then either resubscribe or end the
+            // program.
+            if msg.payload == communication_consts.topic_deletion_message {
+                if resubscribe_settings.enabled {
+                    topic_deleted = true;
+                    break;
+                }
+
+                let mut topic = topic_handle.lock().await;
+                topic.topic = EMPTY_TOPIC.to_string();
+                let _ = shutdown_sender.send(SHUTDOWN.to_string());
+            }
         }
+
+        if !topic_deleted {
+            break;
+        }
+
+        // The cached publisher is reused unless its time to live has passed.
+        publisher_uri = discovery.resolve(&publisher_query).await?.uri;
+
+        stream = match subscriber_helper::resubscribe(
+            &publisher_uri,
+            &subject,
+            &communication_consts.mqtt_v5_kind,
+            communication_consts.disconnect_topic.clone(),
+            &resubscribe_settings,
+            topic_handle.clone(),
+            broker_handle.clone(),
+        )
+        .await
+        {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!("Unable to resubscribe through '{publisher_uri}': {err}. Discovering the publisher again...");
+                publisher_uri = discovery.re_resolve(&publisher_query).await?.uri;
+                subscriber_helper::resubscribe(
+                    &publisher_uri,
+                    &subject,
+                    &communication_consts.mqtt_v5_kind,
+                    communication_consts.disconnect_topic.clone(),
+                    &resubscribe_settings,
+                    topic_handle.clone(),
+                    broker_handle.clone(),
+                )
+                .await?
+            }
+        };
     }
 
     Ok(())
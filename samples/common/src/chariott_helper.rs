@@ -4,7 +4,7 @@
 
 //! Collection of methods and enums to help with connection to the Pub Sub Service.
 
-use std::{thread, time::Duration};
+use std::time::Duration;
 
 use log::{info, warn};
 use samples_proto::service_registry::v1::{
@@ -25,32 +25,25 @@ pub async fn connect_to_chariott_with_retry(
     chariott_uri: &str,
     retry_interval_secs: u64,
 ) -> Result<ChariottClient, Box<dyn std::error::Error + Send + Sync>> {
-    let mut client_opt: Option<ChariottClient> = None;
-    let mut reason = String::new();
-
-    while client_opt.is_none() {
-        client_opt = match ServiceRegistryClient::connect(chariott_uri.to_string()).await {
-            Ok(client) => Some(client),
+    loop {
+        let reason = match ServiceRegistryClient::connect(chariott_uri.to_string()).await {
+            Ok(client) => {
+                info!("Successfully connected to Chariott.");
+                return Ok(client);
+            }
             Err(e) => {
                 let status = Status::from_error(Box::new(e));
                 if status.code() == Code::Unavailable {
-                    reason = String::from("No chariott service found");
+                    String::from("No chariott service found")
                 } else {
-                    reason = format!("Chariott request failed with '{status:?}'");
-                };
-                None
+                    format!("Chariott request failed with '{status:?}'")
+                }
             }
-        }
-        .or_else(|| {
-            warn!("{reason}, retrying in {retry_interval_secs} seconds...");
-            thread::sleep(Duration::from_secs(retry_interval_secs));
-            None
-        });
-    }
-
-    info!("Successfully connected to Chariott.");
+        };
 
-    Ok(client_opt.unwrap())
+        warn!("{reason}, retrying in {retry_interval_secs} seconds...");
+        tokio::time::sleep(Duration::from_secs(retry_interval_secs)).await;
+    }
 }
 
 /// Helper function for getting service metadata from Chariott. Retries on failure.
@@ -69,39 +62,30 @@ pub async fn get_service_metadata_with_retry(
     communication_kind: &str,
     communication_reference: &str,
 ) -> Result<ServiceMetadata, Status> {
-    // Check if the service exists, and if not, wait for service to register with Chariott.
-    let mut service = None;
-
-    while service.is_none() {
+    // Check if the service exists, and if not, wait for service to register with Chariott. The
+    // wait doesn't block the runtime, so that other tasks keep running while it retries.
+    loop {
         let request = Request::new(DiscoverByNamespaceRequest {
             namespace: namespace.to_string(),
         });
-        let mut reason =
-            format!("No service found at namespace '{namespace}' that meets the requirements");
 
-        service = match chariott_client.discover_by_namespace(request).await {
-            Ok(response) => response
-                .into_inner()
-                .services
-                .into_iter()
-                .filter(|svc| {
+        let reason = match chariott_client.discover_by_namespace(request).await {
+            Ok(response) => {
+                let service = response.into_inner().services.into_iter().find(|svc| {
                     svc.communication_kind == communication_kind
                         && svc.communication_reference == communication_reference
-                })
-                .collect::<Vec<ServiceMetadata>>()
-                .first()
-                .cloned(),
-            Err(status) => {
-                reason = format!("Chariott request failed with '{status:?}'");
-                None
+                });
+
+                if let Some(service) = service {
+                    return Ok(service);
+                }
+
+                format!("No service found at namespace '{namespace}' that meets the requirements")
             }
-        }
-        .or_else(|| {
-            warn!("{reason}, retrying in {retry_interval_secs} seconds...");
-            thread::sleep(Duration::from_secs(retry_interval_secs));
-            None
-        });
-    }
+            Err(status) => format!("Chariott request failed with '{status:?}'"),
+        };
 
-    Ok(service.unwrap())
+        warn!("{reason}, retrying in {retry_interval_secs} seconds...");
+        tokio::time::sleep(Duration::from_secs(retry_interval_secs)).await;
+    }
 }
@@ -2,14 +2,652 @@
 // Licensed under the MIT license.
 // SPDX-License-Identifier: MIT
 
-//! Simple module that generates data for examples.
+//! Module that generates data for examples.
+//!
+//! Data is produced by a [`DataGenerator`]. The [`GeneratorRegistry`] selects the generator used
+//! for each subject a publisher provides, either from configuration (see [`GeneratorConfig`]) or
+//! from custom generators registered in code. Subjects without a generator publish the
+//! milliseconds since the Unix epoch. The publish interval and payload shape of a subject can be
+//! configured separately with [`PublishOptions`].
 
-use std::time::SystemTime;
+use std::{
+    collections::HashMap,
+    f64::consts::PI,
+    fmt, fs,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
 
-/// Example data as int.
-pub fn get_data() -> i64 {
-    let val = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .expect("Time went backwards");
-    val.as_millis() as i64
+use common::payload_compression::PayloadCompression;
+use log::{error, info};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::recorder;
+
+/// The default time to wait between publishing two values.
+pub const DEFAULT_PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Trait implemented by a source of sample data.
+pub trait DataGenerator: Send {
+    /// Returns the next value to publish.
+    fn next_value(&mut self) -> String;
+
+    /// Returns the time to wait after publishing the last value before publishing the next one.
+    fn next_delay(&self) -> Duration {
+        DEFAULT_PUBLISH_INTERVAL
+    }
+}
+
+/// Alias for a function that creates a new generator for a subject.
+pub type GeneratorFactory = Arc<dyn Fn() -> Box<dyn DataGenerator> + Send + Sync>;
+
+/// Configuration of a built in generator, selected by the `type` field.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GeneratorConfig {
+    /// Milliseconds since the Unix epoch.
+    Timestamp,
+    /// A sine wave over time.
+    SineWave {
+        /// The peak deviation from the offset.
+        amplitude: f64,
+        /// The time in seconds of one full cycle. Must be positive.
+        period_secs: f64,
+        /// The value the wave oscillates around.
+        #[serde(default)]
+        offset: f64,
+    },
+    /// A value that increases by a step on every publish, wrapping back to the start after max.
+    Ramp {
+        /// The first value.
+        start: f64,
+        /// The amount added on every publish. Must be positive.
+        step: f64,
+        /// The value after which the ramp starts over.
+        max: f64,
+    },
+    /// A value that moves up or down by a random amount on every publish.
+    RandomWalk {
+        /// The first value.
+        start: f64,
+        /// The largest change between two values.
+        max_step: f64,
+    },
+    /// Values replayed in a loop from a column of a CSV file.
+    CsvReplay {
+        /// Path to the CSV file.
+        path: String,
+        /// Zero based index of the column to replay.
+        #[serde(default)]
+        column: usize,
+        /// Whether the first line of the file is a header.
+        #[serde(default = "default_has_header")]
+        has_header: bool,
+    },
+    /// GPS positions replayed in a loop from a CSV file with latitude and longitude columns.
+    GpsTrace {
+        /// Path to the CSV file.
+        path: String,
+        /// Whether the first line of the file is a header.
+        #[serde(default = "default_has_header")]
+        has_header: bool,
+    },
+    /// Payloads replayed in a loop from a subscriber recording, keeping their original timing.
+    Recording {
+        /// Path to the JSON Lines recording.
+        path: String,
+        /// Only replay messages recorded for this subject. All messages are replayed if not set.
+        #[serde(default)]
+        subject: Option<String>,
+    },
+}
+
+/// Options shaping how the data of a subject is published, applied on top of its generator.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct PublishOptions {
+    /// The time in milliseconds to wait between publishing two values. Uses the interval of the
+    /// generator if not set.
+    pub interval_ms: Option<u64>,
+    /// The largest random amount of milliseconds added to or removed from each interval.
+    pub jitter_ms: u64,
+    /// Template of the published payload, such as a JSON object. The `{{value}}`, `{{timestamp}}`,
+    /// `{{sequence}}` and `{{subject}}` placeholders are replaced with the generated value, the
+    /// milliseconds since the Unix epoch, the number of values published before and the subject.
+    /// The generated value is published as is if not set.
+    pub payload_template: Option<String>,
+    /// The compression applied to the published payloads. Payloads are published uncompressed if
+    /// not set.
+    pub compression: PayloadCompression,
+}
+
+/// Default for whether a CSV file has a header line.
+fn default_has_header() -> bool {
+    true
+}
+
+impl GeneratorConfig {
+    /// Creates the generator described by the configuration.
+    ///
+    /// Returns an error if a parameter of the generator is out of range, or if a file backing the
+    /// generator can't be read or has no values.
+    pub fn create(
+        &self,
+    ) -> Result<Box<dyn DataGenerator>, Box<dyn std::error::Error + Send + Sync>> {
+        let generator: Box<dyn DataGenerator> = match self.clone() {
+            GeneratorConfig::Timestamp => Box::new(TimestampGenerator),
+            GeneratorConfig::SineWave {
+                amplitude,
+                period_secs,
+                offset,
+            } => {
+                // A period of 0 divides by zero.
+                if period_secs.is_nan() || period_secs <= 0.0 {
+                    return Err(Box::from(format!(
+                        "The period of a sine wave must be positive, got {period_secs}"
+                    )));
+                }
+
+                Box::new(SineWaveGenerator {
+                    amplitude,
+                    period_secs,
+                    offset,
+                    start: Instant::now(),
+                })
+            }
+            GeneratorConfig::Ramp { start, step, max } => {
+                // A ramp that doesn't go up never reaches max to start over.
+                if step.is_nan() || step <= 0.0 {
+                    return Err(Box::from(format!(
+                        "The step of a ramp must be positive, got {step}"
+                    )));
+                }
+
+                Box::new(RampGenerator {
+                    start,
+                    step,
+                    max,
+                    current: start,
+                })
+            }
+            GeneratorConfig::RandomWalk { start, max_step } => Box::new(RandomWalkGenerator {
+                current: start,
+                max_step,
+                rng: XorShift::from_time(),
+            }),
+            GeneratorConfig::CsvReplay {
+                path,
+                column,
+                has_header,
+            } => {
+                let values = read_csv(&path, has_header)?
+                    .into_iter()
+                    .filter_map(|row| row.get(column).cloned())
+                    .collect();
+
+                Box::new(ReplayGenerator::new(values, &path)?)
+            }
+            GeneratorConfig::GpsTrace { path, has_header } => {
+                let values = read_csv(&path, has_header)?
+                    .into_iter()
+                    .filter_map(|row| {
+                        let latitude: f64 = row.first()?.parse().ok()?;
+                        let longitude: f64 = row.get(1)?.parse().ok()?;
+
+                        Some(
+                            serde_json::json!({ "latitude": latitude, "longitude": longitude })
+                                .to_string(),
+                        )
+                    })
+                    .collect();
+
+                Box::new(ReplayGenerator::new(values, &path)?)
+            }
+            GeneratorConfig::Recording { path, subject } => {
+                let messages = recorder::read_recording(&path)?
+                    .into_iter()
+                    .filter(|msg| subject.as_ref().map_or(true, |s| *s == msg.subject))
+                    .map(|msg| (msg.timestamp_ms, msg.payload))
+                    .collect();
+
+                Box::new(RecordingGenerator::new(messages, &path)?)
+            }
+        };
+
+        Ok(generator)
+    }
+}
+
+/// Reads the rows of a CSV file as trimmed fields.
+///
+/// # Arguments
+///
+/// * `path` - Path to the CSV file.
+/// * `has_header` - Whether to skip the first line of the file.
+fn read_csv(
+    path: &str,
+    has_header: bool,
+) -> Result<Vec<Vec<String>>, Box<dyn std::error::Error + Send + Sync>> {
+    let contents = fs::read_to_string(path)?;
+
+    Ok(contents
+        .lines()
+        .skip(usize::from(has_header))
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            line.split(',')
+                .map(|field| field.trim().to_string())
+                .collect()
+        })
+        .collect())
+}
+
+/// Generates the milliseconds since the Unix epoch.
+pub struct TimestampGenerator;
+
+impl DataGenerator for TimestampGenerator {
+    fn next_value(&mut self) -> String {
+        let val = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("Time went backwards");
+
+        format!("{}", val.as_millis() as i64)
+    }
+}
+
+/// Generates a sine wave over time.
+pub struct SineWaveGenerator {
+    amplitude: f64,
+    period_secs: f64,
+    offset: f64,
+    start: Instant,
+}
+
+impl DataGenerator for SineWaveGenerator {
+    fn next_value(&mut self) -> String {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let value = self.offset + self.amplitude * (2.0 * PI * elapsed / self.period_secs).sin();
+
+        format!("{value:.3}")
+    }
+}
+
+/// Generates a value that ramps from a start value to a max value, then starts over.
+pub struct RampGenerator {
+    start: f64,
+    step: f64,
+    max: f64,
+    current: f64,
+}
+
+impl DataGenerator for RampGenerator {
+    fn next_value(&mut self) -> String {
+        let value = self.current;
+
+        self.current += self.step;
+        if self.current > self.max {
+            self.current = self.start;
+        }
+
+        format!("{value}")
+    }
+}
+
+/// Generates a value that moves up or down by a random amount on every call.
+pub struct RandomWalkGenerator {
+    current: f64,
+    max_step: f64,
+    rng: XorShift,
+}
+
+impl DataGenerator for RandomWalkGenerator {
+    fn next_value(&mut self) -> String {
+        // Maps the random value to a step between -max_step and max_step.
+        let unit = self.rng.next_f64() * 2.0 - 1.0;
+        self.current += unit * self.max_step;
+
+        format!("{:.3}", self.current)
+    }
+}
+
+/// Replays a list of values in a loop.
+pub struct ReplayGenerator {
+    values: Vec<String>,
+    index: usize,
+}
+
+impl ReplayGenerator {
+    /// Creates a new ReplayGenerator. Returns an error if there are no values to replay.
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - The values to replay.
+    /// * `source` - Description of where the values came from, used in the error.
+    pub fn new(
+        values: Vec<String>,
+        source: &str,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if values.is_empty() {
+            return Err(Box::from(format!("No values to replay from '{source}'")));
+        }
+
+        Ok(ReplayGenerator { values, index: 0 })
+    }
+}
+
+impl DataGenerator for ReplayGenerator {
+    fn next_value(&mut self) -> String {
+        let value = self.values[self.index].clone();
+        self.index = (self.index + 1) % self.values.len();
+
+        value
+    }
+}
+
+/// Replays recorded payloads in a loop, waiting the recorded time between them.
+pub struct RecordingGenerator {
+    messages: Vec<(u64, String)>,
+    index: usize,
+}
+
+impl RecordingGenerator {
+    /// Creates a new RecordingGenerator. Returns an error if there are no messages to replay.
+    ///
+    /// # Arguments
+    ///
+    /// * `messages` - The receive time in milliseconds and payload of each recorded message.
+    /// * `source` - Description of where the messages came from, used in the error.
+    pub fn new(
+        messages: Vec<(u64, String)>,
+        source: &str,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if messages.is_empty() {
+            return Err(Box::from(format!("No messages to replay from '{source}'")));
+        }
+
+        Ok(RecordingGenerator { messages, index: 0 })
+    }
+}
+
+impl DataGenerator for RecordingGenerator {
+    fn next_value(&mut self) -> String {
+        let (_, payload) = self.messages[self.index].clone();
+        self.index = (self.index + 1) % self.messages.len();
+
+        payload
+    }
+
+    fn next_delay(&self) -> Duration {
+        // The index points at the next message, so the delay is the gap from the one before it.
+        // The default interval is used when the recording starts over.
+        if self.index == 0 {
+            return DEFAULT_PUBLISH_INTERVAL;
+        }
+
+        let (previous, _) = self.messages[self.index - 1];
+        let (next, _) = self.messages[self.index];
+
+        Duration::from_millis(next.saturating_sub(previous))
+    }
+}
+
+/// Applies the [`PublishOptions`] of a subject to the generator producing its data.
+pub struct ShapedGenerator {
+    inner: Box<dyn DataGenerator>,
+    subject: String,
+    options: PublishOptions,
+    sequence: u64,
+    jitter_ms: i64,
+    rng: XorShift,
+}
+
+impl ShapedGenerator {
+    /// Creates a new ShapedGenerator.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The generator producing the values.
+    /// * `subject` - The subject the values are published for.
+    /// * `options` - The options shaping how the values are published.
+    pub fn new(inner: Box<dyn DataGenerator>, subject: &str, options: PublishOptions) -> Self {
+        ShapedGenerator {
+            inner,
+            subject: subject.to_string(),
+            options,
+            sequence: 0,
+            jitter_ms: 0,
+            rng: XorShift::from_time(),
+        }
+    }
+}
+
+impl DataGenerator for ShapedGenerator {
+    fn next_value(&mut self) -> String {
+        let value = self.inner.next_value();
+
+        // Picks the jitter of the delay following this value, between -jitter_ms and jitter_ms.
+        let jitter_ms = self.options.jitter_ms as f64;
+        self.jitter_ms = ((self.rng.next_f64() * 2.0 - 1.0) * jitter_ms).round() as i64;
+
+        let payload = match &self.options.payload_template {
+            Some(template) => {
+                let timestamp = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map(|duration| duration.as_millis())
+                    .unwrap_or_default();
+
+                template
+                    .replace("{{value}}", &value)
+                    .replace("{{timestamp}}", &timestamp.to_string())
+                    .replace("{{sequence}}", &self.sequence.to_string())
+                    .replace("{{subject}}", &self.subject)
+            }
+            None => value,
+        };
+
+        self.sequence += 1;
+
+        payload
+    }
+
+    fn next_delay(&self) -> Duration {
+        let interval = self
+            .options
+            .interval_ms
+            .map(Duration::from_millis)
+            .unwrap_or_else(|| self.inner.next_delay());
+        let jitter = Duration::from_millis(self.jitter_ms.unsigned_abs());
+
+        if self.jitter_ms < 0 {
+            interval.saturating_sub(jitter)
+        } else {
+            interval.saturating_add(jitter)
+        }
+    }
+}
+
+/// Minimal xorshift pseudo random number generator, good enough for sample data.
+struct XorShift(u64);
+
+impl XorShift {
+    /// Seeds the generator from the current time.
+    fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or_default();
+
+        // The state must never be zero.
+        XorShift(nanos | 1)
+    }
+
+    /// Returns a random value between 0 and 1.
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Registry that selects the [`DataGenerator`] used for each subject.
+#[derive(Clone, Default)]
+pub struct GeneratorRegistry {
+    factories: HashMap<String, GeneratorFactory>,
+    publish_options: HashMap<String, PublishOptions>,
+}
+
+impl fmt::Debug for GeneratorRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GeneratorRegistry")
+            .field("subjects", &self.factories.keys().collect::<Vec<_>>())
+            .field("publish_options", &self.publish_options)
+            .finish()
+    }
+}
+
+impl GeneratorRegistry {
+    /// Creates an empty registry, where every subject uses the timestamp generator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry from the configured generator for each subject.
+    ///
+    /// # Arguments
+    ///
+    /// * `configs` - Map of subject to the configuration of its generator.
+    pub fn from_config(configs: HashMap<String, GeneratorConfig>) -> Self {
+        let mut registry = Self::new();
+
+        for (subject, config) in configs {
+            registry.register(
+                &subject,
+                Arc::new(move || {
+                    config.create().unwrap_or_else(|err| {
+                        error!("Unable to create generator {config:?}, using timestamps: {err}");
+                        Box::new(TimestampGenerator)
+                    })
+                }),
+            );
+        }
+
+        registry
+    }
+
+    /// Sets the options shaping how the data of each subject is published.
+    ///
+    /// # Arguments
+    ///
+    /// * `publish_options` - Map of subject to the options shaping how its data is published.
+    pub fn with_publish_options(
+        mut self,
+        publish_options: HashMap<String, PublishOptions>,
+    ) -> Self {
+        self.publish_options = publish_options;
+        self
+    }
+
+    /// Registers the generator factory used for a subject, replacing any existing one.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject` - The subject that the generator produces data for.
+    /// * `factory` - Function that creates a new generator for the subject.
+    pub fn register(&mut self, subject: &str, factory: GeneratorFactory) {
+        self.factories.insert(subject.to_string(), factory);
+    }
+
+    /// Creates a new generator for the given subject, defaulting to the timestamp generator. The
+    /// publish options of the subject, if any, are applied to the generator.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject` - The subject to generate data for.
+    pub fn create(&self, subject: &str) -> Box<dyn DataGenerator> {
+        let generator: Box<dyn DataGenerator> = match self.factories.get(subject) {
+            Some(factory) => {
+                info!("Using the configured data generator for subject '{subject}'.");
+                factory()
+            }
+            None => Box::new(TimestampGenerator),
+        };
+
+        match self.publish_options.get(subject) {
+            Some(options) => {
+                info!("Using the configured publish options for subject '{subject}'.");
+                Box::new(ShapedGenerator::new(generator, subject, options.clone()))
+            }
+            None => generator,
+        }
+    }
+
+    /// Returns the compression applied to the payloads published for the given subject.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject` - The subject to get the compression of.
+    pub fn payload_compression(&self, subject: &str) -> PayloadCompression {
+        self.publish_options
+            .get(subject)
+            .map(|options| options.compression)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod data_generator_tests {
+    use super::*;
+
+    #[test]
+    fn ramp_wraps_after_max_test() {
+        let config = GeneratorConfig::Ramp {
+            start: 0.0,
+            step: 1.0,
+            max: 2.0,
+        };
+        let mut generator = config.create().unwrap();
+
+        let values: Vec<String> = (0..4).map(|_| generator.next_value()).collect();
+        assert_eq!(vec!["0", "1", "2", "0"], values);
+    }
+
+    #[test]
+    fn ramp_rejects_non_positive_step_test() {
+        for step in [0.0, -1.0, f64::NAN] {
+            let config = GeneratorConfig::Ramp {
+                start: 0.0,
+                step,
+                max: 10.0,
+            };
+
+            assert!(config.create().is_err());
+        }
+    }
+
+    #[test]
+    fn sine_wave_rejects_non_positive_period_test() {
+        for period_secs in [0.0, -1.0, f64::NAN] {
+            let config = GeneratorConfig::SineWave {
+                amplitude: 1.0,
+                period_secs,
+                offset: 0.0,
+            };
+
+            assert!(config.create().is_err());
+        }
+    }
+
+    #[test]
+    fn sine_wave_starts_at_offset_test() {
+        let config = GeneratorConfig::SineWave {
+            amplitude: 1.0,
+            period_secs: 3600.0,
+            offset: 5.0,
+        };
+        let mut generator = config.create().unwrap();
+
+        assert_eq!("5.000", generator.next_value());
+    }
 }
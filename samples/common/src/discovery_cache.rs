@@ -0,0 +1,171 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Cache of the services discovered through Chariott, shared by the Chariott-enabled samples.
+//!
+//! A discovered service is reused until its time to live passes, instead of querying Chariott
+//! each time it is needed. A client that fails to connect to a cached service re-resolves it, as
+//! the service may have moved. Watchers of a service are notified whenever its uri changes.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use log::info;
+use samples_proto::service_registry::v1::ServiceMetadata;
+use tokio::sync::watch;
+use tonic::Status;
+
+use crate::chariott_helper::{self, ChariottClient};
+
+/// Default time a discovered service is reused before Chariott is queried again.
+pub const DEFAULT_DISCOVERY_TTL: Duration = Duration::from_secs(60);
+
+/// The requirements of a service to discover.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ServiceQuery {
+    /// The namespace the service is registered under.
+    pub namespace: String,
+    /// The required kind of communication of the service.
+    pub communication_kind: String,
+    /// The required reference file of the service.
+    pub communication_reference: String,
+}
+
+impl ServiceQuery {
+    /// Creates a new ServiceQuery.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace` - The namespace the service is registered under.
+    /// * `communication_kind` - The required kind of communication of the service.
+    /// * `communication_reference` - The required reference file of the service.
+    pub fn new(namespace: &str, communication_kind: &str, communication_reference: &str) -> Self {
+        ServiceQuery {
+            namespace: namespace.to_string(),
+            communication_kind: communication_kind.to_string(),
+            communication_reference: communication_reference.to_string(),
+        }
+    }
+}
+
+/// A discovered service and when it must be discovered again.
+struct CachedService {
+    /// The metadata of the service.
+    service: ServiceMetadata,
+    /// The time after which the service is discovered again.
+    expires_at: Instant,
+}
+
+/// Cache of the services discovered through Chariott.
+pub struct DiscoveryCache {
+    /// The Chariott client.
+    client: ChariottClient,
+    /// How long a discovered service is reused.
+    ttl: Duration,
+    /// The interval to wait before retrying a failed discovery.
+    retry_interval_secs: u64,
+    /// The discovered services.
+    services: HashMap<ServiceQuery, CachedService>,
+    /// The watchers of the uri of each service.
+    watchers: HashMap<ServiceQuery, watch::Sender<Option<String>>>,
+}
+
+impl DiscoveryCache {
+    /// Creates a new DiscoveryCache.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The Chariott client.
+    /// * `ttl` - How long a discovered service is reused.
+    /// * `retry_interval_secs` - The interval to wait before retrying a failed discovery.
+    pub fn new(client: ChariottClient, ttl: Duration, retry_interval_secs: u64) -> Self {
+        DiscoveryCache {
+            client,
+            ttl,
+            retry_interval_secs,
+            services: HashMap::new(),
+            watchers: HashMap::new(),
+        }
+    }
+
+    /// Returns the Chariott client, such as to register a service.
+    pub fn client(&mut self) -> &mut ChariottClient {
+        &mut self.client
+    }
+
+    /// Returns a service, discovering it through Chariott if it is not cached or its time to live
+    /// has passed. Waits for the service to register with Chariott if it is not yet registered.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The requirements of the service.
+    pub async fn resolve(&mut self, query: &ServiceQuery) -> Result<ServiceMetadata, Status> {
+        if let Some(cached) = self.services.get(query) {
+            if Instant::now() < cached.expires_at {
+                return Ok(cached.service.clone());
+            }
+        }
+
+        let service = chariott_helper::get_service_metadata_with_retry(
+            &mut self.client,
+            &query.namespace,
+            self.retry_interval_secs,
+            &query.communication_kind,
+            &query.communication_reference,
+        )
+        .await?;
+
+        let previous = self.services.insert(
+            query.clone(),
+            CachedService {
+                service: service.clone(),
+                expires_at: Instant::now() + self.ttl,
+            },
+        );
+
+        if previous.map_or(true, |previous| previous.service.uri != service.uri) {
+            info!("Discovered '{}' at '{}'.", query.namespace, service.uri);
+
+            if let Some(watcher) = self.watchers.get(query) {
+                watcher.send_replace(Some(service.uri.clone()));
+            }
+        }
+
+        Ok(service)
+    }
+
+    /// Discovers a service again, regardless of its time to live. Used after failing to connect
+    /// to the cached service, as it may have moved.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The requirements of the service.
+    pub async fn re_resolve(&mut self, query: &ServiceQuery) -> Result<ServiceMetadata, Status> {
+        if let Some(cached) = self.services.get_mut(query) {
+            cached.expires_at = Instant::now();
+        }
+
+        self.resolve(query).await
+    }
+
+    /// Returns a receiver of the uri of a service, which changes each time the service is
+    /// discovered at a new uri. Holds None until the service is first discovered.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The requirements of the service.
+    pub fn watch(&mut self, query: &ServiceQuery) -> watch::Receiver<Option<String>> {
+        let uri = self
+            .services
+            .get(query)
+            .map(|cached| cached.service.uri.clone());
+
+        self.watchers
+            .entry(query.clone())
+            .or_insert_with(|| watch::channel(uri).0)
+            .subscribe()
+    }
+}
@@ -5,8 +5,12 @@
 pub mod chariott_helper;
 pub mod config_utils;
 pub mod data_generator;
+pub mod discovery_cache;
 pub mod load_config;
 pub mod pub_sub_service_helper;
 pub mod publisher_helper;
+pub mod recorder;
+pub mod request_response_helper;
 pub mod subscriber_helper;
+pub mod subscription_gate;
 pub mod topic_store;
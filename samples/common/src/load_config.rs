@@ -4,9 +4,16 @@
 
 //! Loads configuration from external files.
 
+use common::name_policy::{ReservedNamePolicy, DEFAULT_RESERVED_TOPIC_FILTERS};
+use sample_mqtt_connector::client_connector::{ClientCredentials, ConnectionOptions};
 use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-use crate::config_utils;
+use crate::{
+    config_utils,
+    data_generator::{GeneratorConfig, PublishOptions},
+    discovery_cache::DEFAULT_DISCOVERY_TTL,
+};
 
 pub const CONFIG_FILE: &str = "samples_settings";
 pub const CONSTANTS_FILE: &str = "constants";
@@ -27,6 +34,8 @@ pub struct ServiceIdentifier {
 pub struct CommunicationConstants {
     /// The topic deletion message constant.
     pub topic_deletion_message: String,
+    /// The topic that clients publish their last will and testament to on unclean disconnect.
+    pub disconnect_topic: String,
     /// String constant for gRPC.
     pub grpc_kind: String,
     /// String constant for MQTT v5.
@@ -79,6 +88,328 @@ pub struct SimpleSubscriberServiceSettings {
     pub publisher_authority: String,
 }
 
+/// Object that contains the MQTT v5 options used by sample clients to connect to the broker.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MqttConnectionSettings {
+    /// Options applied when a sample client connects to the messaging broker.
+    #[serde(default)]
+    pub mqtt_connection: ConnectionOptions,
+    /// Credentials used by a sample client to authenticate with the messaging broker.
+    #[serde(default)]
+    pub broker_credentials: Option<ClientCredentials>,
+}
+
+/// Load the MQTT v5 connection options.
+///
+/// Falls back to the default options if the settings file does not provide them.
+pub fn load_connection_options() -> ConnectionOptions {
+    load_settings::<MqttConnectionSettings>(CONFIG_FILE)
+        .map(|settings| settings.mqtt_connection)
+        .unwrap_or_default()
+}
+
+/// Load the credentials used to authenticate with the messaging broker.
+///
+/// Returns `None` if the settings file does not provide credentials.
+pub fn load_broker_credentials() -> Option<ClientCredentials> {
+    load_settings::<MqttConnectionSettings>(CONFIG_FILE)
+        .ok()
+        .and_then(|settings| settings.broker_credentials)
+}
+
+/// Object that contains the data generator configured for each subject a publisher provides.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DataGeneratorSettings {
+    /// Map of subject to the configuration of the generator producing its data.
+    #[serde(default)]
+    pub data_generators: HashMap<String, GeneratorConfig>,
+}
+
+/// Load the data generator configured for each subject.
+///
+/// Returns an empty map if the settings file does not configure any generators.
+pub fn load_data_generator_configs() -> HashMap<String, GeneratorConfig> {
+    load_settings::<DataGeneratorSettings>(CONFIG_FILE)
+        .map(|settings| settings.data_generators)
+        .unwrap_or_default()
+}
+
+/// Object that contains the publish options configured for each subject a publisher provides.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PublishOptionsSettings {
+    /// Map of subject to the options shaping how its data is published.
+    #[serde(default)]
+    pub publish_options: HashMap<String, PublishOptions>,
+}
+
+/// Load the publish options configured for each subject.
+///
+/// Returns an empty map if the settings file does not configure any publish options.
+pub fn load_publish_options() -> HashMap<String, PublishOptions> {
+    load_settings::<PublishOptionsSettings>(CONFIG_FILE)
+        .map(|settings| settings.publish_options)
+        .unwrap_or_default()
+}
+
+/// Object that contains the subject names the publisher samples refuse to create topics for.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReservedNameSettings {
+    /// MQTT style topic filters of reserved subjects, such as `$SYS/#`.
+    pub topic_filters: Vec<String>,
+    /// Regular expressions of reserved subjects.
+    pub patterns: Vec<String>,
+}
+
+impl Default for ReservedNameSettings {
+    fn default() -> Self {
+        ReservedNameSettings {
+            topic_filters: DEFAULT_RESERVED_TOPIC_FILTERS
+                .iter()
+                .map(|filter| filter.to_string())
+                .collect(),
+            patterns: Vec::new(),
+        }
+    }
+}
+
+/// Object that contains the reserved subject names configured for the publisher samples.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ReservedNameConfig {
+    /// The reserved subject names.
+    #[serde(default)]
+    pub reserved_names: ReservedNameSettings,
+}
+
+/// Load the policy of subject names the publisher samples refuse to create topics for.
+///
+/// Reserves the broker's `$SYS` and `$CONTROL` topics if the settings file does not configure any
+/// reserved names. Returns an error if a configured pattern is not a valid regular expression.
+pub fn load_reserved_name_policy(
+) -> Result<ReservedNamePolicy, Box<dyn std::error::Error + Send + Sync>> {
+    let settings = load_settings::<ReservedNameConfig>(CONFIG_FILE)
+        .map(|config| config.reserved_names)
+        .unwrap_or_default();
+
+    Ok(ReservedNamePolicy::new(
+        settings.topic_filters,
+        &settings.patterns,
+    )?)
+}
+
+/// Object that contains how long the Chariott-enabled samples reuse a discovered service.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscoveryCacheSettings {
+    /// Time in seconds a discovered service is reused before Chariott is queried again.
+    pub ttl_secs: u64,
+}
+
+impl Default for DiscoveryCacheSettings {
+    fn default() -> Self {
+        DiscoveryCacheSettings {
+            ttl_secs: DEFAULT_DISCOVERY_TTL.as_secs(),
+        }
+    }
+}
+
+/// Object that contains the discovery cache settings configured for the Chariott samples.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DiscoveryCacheConfig {
+    /// How long a discovered service is reused.
+    #[serde(default)]
+    pub discovery_cache: DiscoveryCacheSettings,
+}
+
+/// Load how long the Chariott-enabled samples reuse a discovered service.
+///
+/// Falls back to the default time to live if the settings file does not configure it.
+pub fn load_discovery_cache_settings() -> DiscoveryCacheSettings {
+    load_settings::<DiscoveryCacheConfig>(CONFIG_FILE)
+        .map(|config| config.discovery_cache)
+        .unwrap_or_default()
+}
+
+/// Object that contains how a subscriber reacts to the deletion of its topic.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResubscribeSettings {
+    /// Whether the subscriber asks the publisher for a new topic when its topic is deleted,
+    /// instead of shutting down.
+    pub enabled: bool,
+    /// Number of times the publisher is asked for a new topic before giving up.
+    pub max_attempts: u32,
+    /// Interval in seconds between two requests for a new topic.
+    pub retry_interval_secs: u64,
+}
+
+impl Default for ResubscribeSettings {
+    fn default() -> Self {
+        ResubscribeSettings {
+            enabled: false,
+            max_attempts: 5,
+            retry_interval_secs: 5,
+        }
+    }
+}
+
+/// Object that contains the resubscribe settings configured for the subscriber samples.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ResubscribeConfig {
+    /// How a subscriber reacts to the deletion of its topic.
+    #[serde(default)]
+    pub resubscribe: ResubscribeSettings,
+}
+
+/// Load how a subscriber reacts to the deletion of its topic.
+///
+/// Falls back to shutting down on topic deletion if the settings file does not configure it.
+pub fn load_resubscribe_settings() -> ResubscribeSettings {
+    load_settings::<ResubscribeConfig>(CONFIG_FILE)
+        .map(|config| config.resubscribe)
+        .unwrap_or_default()
+}
+
+/// A failure that the flaky publisher sample goes through at the end of a run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PublisherFailure {
+    /// The publisher drops its broker connection without disconnecting and stops answering
+    /// management callbacks, as if its process crashed.
+    Crash,
+    /// The publisher disconnects cleanly and restarts on the same port.
+    Restart,
+    /// The publisher disconnects cleanly and restarts on the next port.
+    PortChange,
+}
+
+/// Object that contains how the flaky publisher sample fails and restarts.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlakyPublisherSettings {
+    /// Number of seconds the publisher runs before each failure.
+    pub run_secs: u64,
+    /// Number of seconds the publisher stays down after each failure.
+    pub restart_delay_secs: u64,
+    /// The failures the publisher goes through, in order. The publisher deletes its topic and
+    /// exits after a last run once every failure has happened.
+    pub failures: Vec<PublisherFailure>,
+}
+
+impl Default for FlakyPublisherSettings {
+    fn default() -> Self {
+        FlakyPublisherSettings {
+            run_secs: 15,
+            restart_delay_secs: 3,
+            failures: vec![
+                PublisherFailure::Restart,
+                PublisherFailure::PortChange,
+                PublisherFailure::Crash,
+            ],
+        }
+    }
+}
+
+/// Object that contains the settings of the flaky publisher sample.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FlakyPublisherConfig {
+    /// How the flaky publisher fails and restarts.
+    #[serde(default)]
+    pub flaky_publisher: FlakyPublisherSettings,
+}
+
+/// Load how the flaky publisher sample fails and restarts.
+///
+/// Falls back to the default failures if the settings file does not configure them.
+pub fn load_flaky_publisher_settings() -> FlakyPublisherSettings {
+    load_settings::<FlakyPublisherConfig>(CONFIG_FILE)
+        .map(|config| config.flaky_publisher)
+        .unwrap_or_default()
+}
+
+/// Object that contains how the topic controller sample reconciles topic resources.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TopicControllerSettings {
+    /// Kubernetes namespace whose topic resources are reconciled. Every namespace is reconciled if
+    /// not set.
+    pub namespace: Option<String>,
+    /// Number of seconds between reconciliations.
+    pub reconcile_interval_secs: u64,
+}
+
+impl Default for TopicControllerSettings {
+    fn default() -> Self {
+        TopicControllerSettings {
+            namespace: None,
+            reconcile_interval_secs: 10,
+        }
+    }
+}
+
+/// Object that contains the settings of the topic controller sample.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TopicControllerConfig {
+    /// How the topic controller reconciles topic resources.
+    #[serde(default)]
+    pub topic_controller: TopicControllerSettings,
+}
+
+/// Load how the topic controller sample reconciles topic resources.
+///
+/// Falls back to the default settings if the settings file does not configure them.
+pub fn load_topic_controller_settings() -> TopicControllerSettings {
+    load_settings::<TopicControllerConfig>(CONFIG_FILE)
+        .map(|config| config.topic_controller)
+        .unwrap_or_default()
+}
+
+/// Object that contains the client-side metrics of the publisher and subscriber samples.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientMetricsSettings {
+    /// Whether publishers wrap their payloads in an envelope carrying the time they were
+    /// published at, which subscribers unwrap to measure the latency of the messages.
+    pub timestamped_payloads: bool,
+    /// Interval in seconds between reports of the messages received, missed and their latency,
+    /// logged by the subscribers. Not reported if not set.
+    pub report_interval_secs: Option<u64>,
+}
+
+/// Object that contains the client-side metrics settings of the samples.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ClientMetricsConfig {
+    /// The client-side metrics of the samples.
+    #[serde(default)]
+    pub client_metrics: ClientMetricsSettings,
+}
+
+/// Load the client-side metrics settings of the samples.
+///
+/// Falls back to no metrics if the settings file does not configure them.
+pub fn load_client_metrics_settings() -> ClientMetricsSettings {
+    load_settings::<ClientMetricsConfig>(CONFIG_FILE)
+        .map(|config| config.client_metrics)
+        .unwrap_or_default()
+}
+
+/// Object that contains the file a subscriber records received messages to.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RecordingSettings {
+    /// Path to the JSON Lines file that received messages are appended to.
+    #[serde(default)]
+    pub recording_file: Option<String>,
+}
+
+/// Load the file that a subscriber records received messages to.
+///
+/// Returns None if recording is not configured.
+pub fn load_recording_file() -> Option<String> {
+    load_settings::<RecordingSettings>(CONFIG_FILE)
+        .ok()
+        .and_then(|settings| settings.recording_file)
+}
+
 /// Load the settings.
 ///
 /// Will attempt to load a configuration from the settings file to an object 'T'.
@@ -4,17 +4,32 @@
 
 //! Collection of methods and enums to help with connection to the Pub Sub Service.
 
-use log::error;
-use serde_json::{json, Value};
+use std::{str::FromStr, time::Duration};
+
+use common::{
+    action_casing::normalize_action,
+    payload_compression::PayloadCompression,
+    protocol_kind::ProtocolKind,
+    subscription_metadata::{SchemaReference, SubscriptionMetadata},
+    uri,
+};
+use log::{error, warn};
 use strum_macros::{Display, EnumString};
 
 use samples_proto::{
     pubsub::v1::{
-        pub_sub_client::PubSubClient, CreateTopicRequest, DeleteTopicRequest, DeleteTopicResponse,
+        pub_sub_client::PubSubClient, CreateResponseTopicRequest, CreateTopicRequest,
+        CreateTopicResponse, DeleteTopicRequest, DeleteTopicResponse,
     },
     sample_publisher::v1::SubscriptionInfoResponse,
 };
-use tonic::{Request, Response, Status};
+use tonic::{Code, Request, Response, Status};
+use uuid::Uuid;
+
+/// Number of attempts made to create a topic before giving up.
+const CREATE_TOPIC_ATTEMPTS: u32 = 3;
+/// Time to wait between attempts to create a topic.
+const CREATE_TOPIC_RETRY_INTERVAL: Duration = Duration::from_secs(1);
 
 /// Actions that are returned from the Pub Sub Service.
 #[derive(Clone, EnumString, Display, Debug, PartialEq)]
@@ -33,46 +48,143 @@ pub enum TopicAction {
     Delete,
 }
 
+impl TopicAction {
+    /// Parses an action sent by the Pub Sub Service, regardless of the casing it is sent in.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The action to parse.
+    pub fn parse(action: &str) -> Result<Self, strum::ParseError> {
+        Self::from_str(&normalize_action(action))
+    }
+}
+
 /// Handles creation request to Pub Sub Service.
 ///
+/// Retries the request if the Pub Sub Service can't be reached. Every attempt carries the same
+/// idempotency key, so a retry after a request that timed out returns the topic created by that
+/// request instead of a duplicate topic.
+///
 /// # Arguments
 ///
 /// * `pub_sub_uri` - URI of the Pub Sub Service. (ex. "http://\[::1\]:50051")
 /// * `client_id` - The client id of the service calling the method.
 /// * `management_authority` - The management authority of the service calling the method.
 /// * `management_protocol` - The protocol used by the given management callback.
+/// * `payload_compression` - The compression the publisher applies to the payloads of the topic.
 pub async fn create_topic(
     pub_sub_uri: String,
     client_id: String,
     management_authority: String,
-    management_protocol: String,
+    management_protocol: ProtocolKind,
+    payload_compression: PayloadCompression,
 ) -> Result<SubscriptionInfoResponse, Status> {
-    let mut ps_client = PubSubClient::connect(pub_sub_uri).await.map_err(|e| {
-        error!("Error connecting to pub sub wrapper client: {e:?}");
-        Status::from_error(Box::new(e))
-    })?;
+    create_topic_with_idempotency_key(
+        pub_sub_uri,
+        client_id,
+        management_authority,
+        management_protocol,
+        Uuid::new_v4().to_string(),
+        payload_compression,
+    )
+    .await
+}
 
-    let request = Request::new(CreateTopicRequest {
+/// Handles creation request to Pub Sub Service with the given idempotency key.
+///
+/// A publisher that restarts can pass the key it created its topic with to get the same topic
+/// back, as long as the topic was not deleted and the key was used within the last few minutes.
+/// The topic keeps the management callback it was created with.
+///
+/// # Arguments
+///
+/// * `pub_sub_uri` - URI of the Pub Sub Service. (ex. "http://\[::1\]:50051")
+/// * `client_id` - The client id of the service calling the method.
+/// * `management_authority` - The management authority of the service calling the method.
+/// * `management_protocol` - The protocol used by the given management callback.
+/// * `idempotency_key` - The key identifying the topic creation across retries and restarts.
+/// * `payload_compression` - The compression the publisher applies to the payloads of the topic.
+pub async fn create_topic_with_idempotency_key(
+    pub_sub_uri: String,
+    client_id: String,
+    management_authority: String,
+    management_protocol: ProtocolKind,
+    idempotency_key: String,
+    payload_compression: PayloadCompression,
+) -> Result<SubscriptionInfoResponse, Status> {
+    let request = CreateTopicRequest {
         publisher_id: client_id,
-        management_callback: format!("http://{management_authority}"), // Devskim: ignore DS137138
-        management_protocol,
-    });
+        management_callback: uri::authority_uri(&management_authority),
+        management_protocol: management_protocol.to_string(),
+        idempotency_key,
+        management_protocol_kind: management_protocol.to_proto(),
+        single_consumer: false,
+        pre_warm: false,
+        notify_subscriber_joined: false,
+        sequence_numbers: false,
+        topic_class: 0,
+        topic_template: String::new(),
+        deletion_message: None,
+        suppress_deletion_message: false,
+        tags: Vec::new(),
+        payload_compression: payload_compression.to_proto(),
+    };
 
-    // Add returned information to the topic maps.
-    let topic_info = ps_client.create_topic(request).await?.into_inner();
+    let mut attempt = 1;
+    let topic_info = loop {
+        match try_create_topic(pub_sub_uri.clone(), request.clone()).await {
+            Ok(topic_info) => break topic_info,
+            Err(status)
+                if attempt < CREATE_TOPIC_ATTEMPTS
+                    && matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded) =>
+            {
+                warn!("Attempt {attempt} to create a topic failed, retrying: {status}");
+                attempt += 1;
+                tokio::time::sleep(CREATE_TOPIC_RETRY_INTERVAL).await;
+            }
+            Err(status) => return Err(status),
+        }
+    };
 
-    let generated_topic = topic_info.generated_topic;
-    let subscription_metadata = json!({ "topic": generated_topic }).to_string();
+    let mut subscription_metadata = SubscriptionMetadata::new(topic_info.generated_topic);
+    subscription_metadata.qos = u8::try_from(topic_info.qos).ok();
+    subscription_metadata.schema =
+        (!topic_info.schema_kind.is_empty()).then(|| SchemaReference::new(topic_info.schema_kind));
+    subscription_metadata.compression =
+        PayloadCompression::from_proto(topic_info.payload_compression)
+            .filter(|compression| compression.is_compressed());
 
     let topic_subscription_info = SubscriptionInfoResponse {
         protocol_kind: topic_info.broker_protocol,
         subscription_uri: topic_info.broker_uri,
-        subscription_metadata,
+        subscription_metadata: subscription_metadata.to_json(),
+        subscription_protocol_kind: topic_info.broker_protocol_kind,
     };
 
     Ok(topic_subscription_info)
 }
 
+/// Makes a single topic creation request to the Pub Sub Service.
+///
+/// # Arguments
+///
+/// * `pub_sub_uri` - URI of the Pub Sub Service. (ex. "http://\[::1\]:50051")
+/// * `request` - The topic creation request.
+async fn try_create_topic(
+    pub_sub_uri: String,
+    request: CreateTopicRequest,
+) -> Result<CreateTopicResponse, Status> {
+    let mut ps_client = PubSubClient::connect(pub_sub_uri).await.map_err(|e| {
+        error!("Error connecting to pub sub wrapper client: {e:?}");
+        Status::unavailable(e.to_string())
+    })?;
+
+    Ok(ps_client
+        .create_topic(Request::new(request))
+        .await?
+        .into_inner())
+}
+
 /// Handles deletion request to Pub Sub Service.
 ///
 /// # Arguments
@@ -94,10 +206,38 @@ pub async fn delete_topic(
     ps_client.delete_topic(request).await
 }
 
+/// Gets the response topic of a requester for a request topic from the Pub Sub Service.
+///
+/// # Arguments
+///
+/// * `pub_sub_uri` - URI of the Pub Sub Service. (ex. "http://\[::1\]:50051")
+/// * `request_topic` - The generated topic that requests are sent on.
+/// * `requester_id` - The id of the requester.
+pub async fn create_response_topic(
+    pub_sub_uri: String,
+    request_topic: String,
+    requester_id: String,
+) -> Result<String, Status> {
+    let mut ps_client = PubSubClient::connect(pub_sub_uri).await.map_err(|e| {
+        error!("Error connecting to pub sub wrapper client: {e:?}");
+        Status::unavailable(e.to_string())
+    })?;
+
+    let request = Request::new(CreateResponseTopicRequest {
+        request_topic,
+        requester_id,
+    });
+
+    Ok(ps_client
+        .create_response_topic(request)
+        .await?
+        .into_inner()
+        .response_topic)
+}
+
 // Get the generated topic name from the Subscription Response.
 pub fn get_topic_from_subscription_response(sub_response: &SubscriptionInfoResponse) -> String {
-    serde_json::from_str::<Value>(&sub_response.subscription_metadata).unwrap()["topic"]
-        .as_str()
+    SubscriptionMetadata::from_json(&sub_response.subscription_metadata)
         .unwrap()
-        .to_string()
+        .topic
 }
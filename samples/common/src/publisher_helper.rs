@@ -4,15 +4,20 @@
 
 //! Collection of methods and objects to help with execution as a publisher.
 
+use common::subscription_metadata::SubscriptionMetadata;
 use log::info;
 use sample_mqtt_connector::{
-    client_connector::PubSubConnectorClient, mqtt_five_client_connector::MqttFiveClientConnector,
+    client_connector::{ClientRole, PubSubConnectorClient},
+    mqtt_five_client_connector::MqttFiveClientConnector,
 };
-use std::{sync::mpsc, time::Duration};
+use std::time::Duration;
 use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
 use samples_proto::sample_publisher::v1::SubscriptionInfoResponse;
 
+use crate::{data_generator::DataGenerator, load_config};
+
 /// Trait that defines a set of methods that a publisher should implement to enable dynamic topic
 /// management.
 pub trait DynamicPublisher {
@@ -53,51 +58,71 @@ pub trait DynamicPublisher {
     fn on_delete_action(&self, topic: String, generated_topic: String);
 }
 
-/// Spawns a task that publishes simulated data until the Receiver is dropped.
+/// Spawns a task that publishes simulated data until the cancellation token is cancelled.
 ///
 /// # Arguments
 ///
 /// * `generated_topic` - The generated topic that will be published to.
 /// * `known_topic` - The topic that is associated with the data that is requested to be published.
-/// * `recv` - The Receiver for the mpcs stream used to stop publishing to a topic.
+/// * `cancellation_token` - The token used to stop publishing to a topic.
 /// * `pub_id` - The client id of the publisher that is starting to publish.
 /// * `client_info` - The info used to connect and publish to the messaging broker.
-/// * `data_fn` - The function gathering the data to publish.
-pub fn handle_publish_loop<F>(
+/// * `disconnect_topic` - The topic the publisher's last will and testament is published to.
+/// * `generator` - The generator producing the data to publish.
+pub fn handle_publish_loop(
     generated_topic: String,
     known_topic: String,
-    recv: mpsc::Receiver<String>,
+    cancellation_token: CancellationToken,
     pub_id: String,
     client_info: SubscriptionInfoResponse,
-    data_fn: F,
-) -> JoinHandle<()>
-where
-    F: Fn() -> i64 + Send + Sync + 'static,
-{
+    disconnect_topic: String,
+    mut generator: Box<dyn DataGenerator>,
+) -> JoinHandle<()> {
     tokio::spawn(async move {
-        let client: MqttFiveClientConnector =
-            PubSubConnectorClient::new(pub_id.clone(), client_info.subscription_uri.clone());
+        let client: MqttFiveClientConnector = PubSubConnectorClient::new(
+            pub_id.clone(),
+            client_info.subscription_uri.clone(),
+            load_config::load_broker_credentials(),
+        );
+        let client = client
+            .with_last_will(disconnect_topic, ClientRole::Publisher)
+            .with_connection_options(load_config::load_connection_options())
+            .with_timestamped_payloads(
+                load_config::load_client_metrics_settings().timestamped_payloads,
+            );
         let _response = client.connect();
 
+        let metadata = SubscriptionMetadata::from_json(&client_info.subscription_metadata).ok();
+
+        // Payloads are compressed as declared when the topic was created.
+        if let Some(compression) = metadata.as_ref().and_then(|metadata| metadata.compression) {
+            info!("Compressing the payloads of topic '{generated_topic}' with {compression}.");
+            client.enable_payload_compression(&generated_topic, compression);
+        }
+
+        // Messages carry the lifecycle epoch of the topic, so that subscribers can tell them apart
+        // from messages published before the publisher was restarted.
+        if let Some(epoch) = metadata
+            .as_ref()
+            .and_then(|metadata| metadata.lifecycle_epoch)
+        {
+            info!("Publishing on topic '{generated_topic}' in lifecycle epoch {epoch}.");
+            client.set_lifecycle_epoch(&generated_topic, epoch);
+        }
+
         // Create messages and publish them.
         info!("Publishing on the topic '({known_topic}) {generated_topic}'.");
 
         loop {
-            let data = data_fn();
-            let message = format!("{data}");
-
-            let _res = client
-                .publish(generated_topic.clone(), message.to_string())
-                .await;
+            let message = generator.next_value();
 
-            tokio::time::sleep(Duration::from_secs(1)).await;
+            let _res = client.publish(generated_topic.clone(), message).await;
 
-            // Only break out of the loop once the connection has been closed.
-            match recv.try_recv() {
-                Ok(val) => info!("{val:?}"),
-                Err(mpsc::TryRecvError::Empty) => continue,
-                Err(mpsc::TryRecvError::Disconnected) => break,
-            };
+            // Stop as soon as the topic is deactivated, instead of after the delay.
+            tokio::select! {
+                _ = cancellation_token.cancelled() => break,
+                _ = tokio::time::sleep(generator.next_delay()) => {}
+            }
         }
 
         // Disconnect from the broker.
@@ -0,0 +1,106 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Records the messages a subscriber receives so they can be replayed later.
+//!
+//! Recordings are JSON Lines files, with one [`RecordedMessage`] per line. A recording is replayed
+//! with its original timing by configuring a `recording` data generator for a publisher subject
+//! (see [`crate::data_generator::GeneratorConfig`]).
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    time::SystemTime,
+};
+
+use sample_mqtt_connector::client_connector::PubSubMessage;
+use serde_derive::{Deserialize, Serialize};
+
+/// A message received by a subscriber along with the time it was received.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct RecordedMessage {
+    /// Milliseconds since the Unix epoch when the message was received.
+    pub timestamp_ms: u64,
+    /// The subject the subscriber requested.
+    pub subject: String,
+    /// The topic the message was received on.
+    pub topic: String,
+    /// The message payload.
+    pub payload: String,
+}
+
+/// Appends received messages to a JSON Lines recording.
+pub struct MessageRecorder {
+    writer: BufWriter<File>,
+}
+
+impl MessageRecorder {
+    /// Opens the recording at the given path, appending to it if it already exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the recording file.
+    pub fn new(path: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(MessageRecorder {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    /// Records a message with the current time.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject` - The subject the subscriber requested.
+    /// * `msg` - The message received from the broker.
+    pub fn record(
+        &mut self,
+        subject: &str,
+        msg: &PubSubMessage,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_millis() as u64;
+
+        let entry = RecordedMessage {
+            timestamp_ms,
+            subject: subject.to_string(),
+            topic: msg.topic.clone(),
+            payload: msg.payload.clone(),
+        };
+
+        serde_json::to_writer(&mut self.writer, &entry)?;
+        self.writer.write_all(b"\n")?;
+
+        // Flush every message so the recording is complete if the subscriber is stopped.
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Reads the messages from a recording, in the order they were received.
+///
+/// # Arguments
+///
+/// * `path` - Path to the recording file.
+pub fn read_recording(
+    path: &str,
+) -> Result<Vec<RecordedMessage>, Box<dyn std::error::Error + Send + Sync>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut messages = Vec::new();
+
+    for line in reader.lines() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        messages.push(serde_json::from_str(&line)?);
+    }
+
+    Ok(messages)
+}
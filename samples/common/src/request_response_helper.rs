@@ -0,0 +1,135 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Collection of methods and objects to help with request/response interactions over the
+//! messaging broker.
+//!
+//! A publisher answers requests sent on a topic it created. Each requester gets its own response
+//! topic for that request topic from the Pub Sub Service, and sends the response topic along with
+//! every request. Requests and responses are matched with a correlation id.
+
+use std::{
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use sample_mqtt_connector::client_connector::{PubSubConnectorClient, PubSubMessage};
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A request sent on a request topic.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RequestEnvelope {
+    /// Id used to match the response to the request.
+    pub correlation_id: String,
+    /// The topic the response is expected on.
+    pub response_topic: String,
+    /// The body of the request.
+    pub payload: String,
+}
+
+/// A response sent on a response topic.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ResponseEnvelope {
+    /// Id of the request that is responded to.
+    pub correlation_id: String,
+    /// The body of the response.
+    pub payload: String,
+}
+
+/// Sends a request on a request topic. Returns the correlation id of the request.
+///
+/// # Arguments
+///
+/// * `client` - The broker client used to publish the request.
+/// * `request_topic` - The topic to send the request on.
+/// * `response_topic` - The requester's response topic, from
+///                      [`create_response_topic`][crate::pub_sub_service_helper::create_response_topic].
+/// * `payload` - The body of the request.
+pub async fn send_request<T: PubSubConnectorClient + Sync>(
+    client: &T,
+    request_topic: String,
+    response_topic: String,
+    payload: String,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let correlation_id = Uuid::new_v4().to_string();
+    let request = RequestEnvelope {
+        correlation_id: correlation_id.clone(),
+        response_topic,
+        payload,
+    };
+
+    client
+        .publish(request_topic, serde_json::to_string(&request)?)
+        .await?;
+
+    Ok(correlation_id)
+}
+
+/// Waits for the response to a request on the stream of the requester's response topic. Responses
+/// to other requests are skipped. Returns the body of the response.
+///
+/// # Arguments
+///
+/// * `receiver` - The stream of the requester's response topic.
+/// * `correlation_id` - The correlation id returned by [`send_request`].
+/// * `timeout` - How long to wait for the response.
+pub fn wait_for_response(
+    receiver: &Receiver<PubSubMessage>,
+    correlation_id: &str,
+    timeout: Duration,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let message = receiver.recv_timeout(remaining).map_err(|e| match e {
+            RecvTimeoutError::Timeout => {
+                format!("no response to request '{correlation_id}' within {timeout:?}")
+            }
+            RecvTimeoutError::Disconnected => "the response stream was closed".to_string(),
+        })?;
+
+        // Anything that isn't a response, like a topic deletion message, is skipped.
+        if let Ok(response) = serde_json::from_str::<ResponseEnvelope>(&message.payload) {
+            if response.correlation_id == correlation_id {
+                return Ok(response.payload);
+            }
+        }
+    }
+}
+
+/// Parses a request received on a request topic.
+///
+/// # Arguments
+///
+/// * `message` - The message received on the request topic.
+pub fn parse_request(message: &PubSubMessage) -> Result<RequestEnvelope, serde_json::Error> {
+    serde_json::from_str(&message.payload)
+}
+
+/// Sends the response to a request on the requester's response topic.
+///
+/// # Arguments
+///
+/// * `client` - The broker client used to publish the response.
+/// * `request` - The request that is responded to.
+/// * `payload` - The body of the response.
+pub async fn respond<T: PubSubConnectorClient + Sync>(
+    client: &T,
+    request: &RequestEnvelope,
+    payload: String,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let response = ResponseEnvelope {
+        correlation_id: request.correlation_id.clone(),
+        payload,
+    };
+
+    client
+        .publish(
+            request.response_topic.clone(),
+            serde_json::to_string(&response)?,
+        )
+        .await
+}
@@ -5,28 +5,116 @@
 //! Collection of methods and objects to help with execution as a subscriber.
 
 use std::{
-    process,
+    collections::HashMap,
+    fmt, process,
     sync::{
         mpsc::{self, Receiver, Sender},
-        Arc,
+        Arc, OnceLock,
     },
+    time::Duration,
 };
 
-use async_std::sync::Mutex;
-use log::{error, info};
+use common::{
+    protocol_kind::{ParseProtocolKindError, ProtocolKind},
+    subscription_metadata::SubscriptionMetadata,
+};
+use log::{info, warn};
 use sample_mqtt_connector::{
-    client_connector::{PubSubConnectorClient, PubSubMessage},
+    client_connector::{ClientRole, PubSubConnectorClient, PubSubMessage},
+    client_metrics::ClientMetrics,
     mqtt_five_client_connector::MqttFiveClientConnector,
 };
 use samples_proto::sample_publisher::v1::{
     sample_publisher_client::SamplePublisherClient, SubscriptionInfoRequest,
+    SubscriptionInfoResponse,
 };
-use serde_json::Value;
+use tokio::sync::Mutex;
+use tonic::Code;
+use uuid::Uuid;
+
+use crate::load_config::{self, ResubscribeSettings};
 
 /// Shutdown constant used to tell the service to shut down over an mpsc channel.
 pub const SHUTDOWN: &str = "shutdown";
 /// Empty topic constant used to initialize the [`TopicRef`].
 pub const EMPTY_TOPIC: &str = "";
+/// Number of attempts made to get the subscription information before giving up.
+const SUBSCRIPTION_INFO_ATTEMPTS: u32 = 5;
+/// Delay before the first retry to get the subscription information, doubled on each retry.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Enum defining the errors that can occur while getting the subscription information.
+#[derive(Debug)]
+pub enum SubscriberError {
+    /// The publisher could not be reached.
+    Connect(tonic::transport::Error),
+    /// The publisher failed the request for subscription information.
+    Request(tonic::Status),
+    /// The expected protocol is not a known protocol.
+    InvalidProtocol(ParseProtocolKindError),
+    /// The publisher offers the subscription over a protocol the subscriber can't handle.
+    UnsupportedProtocol {
+        /// The protocol the subscriber can handle.
+        expected: ProtocolKind,
+        /// The protocol the publisher offered.
+        actual: String,
+    },
+    /// The subscription metadata returned by the publisher is malformed.
+    InvalidMetadata(String),
+}
+
+impl SubscriberError {
+    /// Returns whether the error is likely to go away on retry, such as while the publisher is
+    /// starting up or throttling requests.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            SubscriberError::Connect(_) => true,
+            SubscriberError::Request(status) => matches!(
+                status.code(),
+                Code::Unavailable | Code::ResourceExhausted | Code::DeadlineExceeded
+            ),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for SubscriberError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubscriberError::Connect(err) => write!(f, "unable to reach the publisher: {err}"),
+            SubscriberError::Request(status) => {
+                write!(f, "publisher failed the request: {}", status.message())
+            }
+            SubscriberError::InvalidProtocol(err) => write!(f, "invalid expected protocol: {err}"),
+            SubscriberError::UnsupportedProtocol { expected, actual } => write!(
+                f,
+                "expected protocol {expected}, but the publisher offered '{actual}'"
+            ),
+            SubscriberError::InvalidMetadata(msg) => {
+                write!(f, "invalid subscription metadata: {msg}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SubscriberError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SubscriberError::Connect(err) => Some(err),
+            SubscriberError::Request(status) => Some(status),
+            SubscriberError::InvalidProtocol(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// A reference used to share the topics subscribed to over a single connection between the
+/// shutdown task and main thread.
+#[derive(Debug, Default)]
+pub struct TopicsRef {
+    /// Map of subject to the topic subscribed to for it.
+    pub topics: HashMap<String, String>,
+}
 
 /// A reference used to share the connector instance between the shutdown task and main thread.
 pub struct BrokerRef {
@@ -50,6 +138,9 @@ pub struct SubscriptionInfo {
 
 /// Gets the subscription information from the publisher client.
 ///
+/// Transient failures, such as the publisher not being up yet, are retried with an increasing
+/// delay before giving up.
+///
 /// # Arguments
 ///
 /// * `pub_uri` - The uri of the publisher of the data.
@@ -59,34 +150,86 @@ pub async fn get_subscription_info(
     pub_uri: &str,
     subject: &str,
     expected_protocol: &str,
-) -> Result<SubscriptionInfo, Box<dyn std::error::Error + Send + Sync>> {
+) -> Result<SubscriptionInfo, SubscriberError> {
     info!("Requesting subject: {}", subject);
 
-    let pub_client_result = SamplePublisherClient::connect(pub_uri.to_string()).await;
+    let expected_protocol = expected_protocol
+        .parse::<ProtocolKind>()
+        .map_err(SubscriberError::InvalidProtocol)?;
+
+    let mut attempt = 1;
+    let mut retry_delay = INITIAL_RETRY_DELAY;
+    let sub_info = loop {
+        match request_subscription_info(pub_uri, subject).await {
+            Ok(sub_info) => break sub_info,
+            Err(err) if err.is_transient() && attempt < SUBSCRIPTION_INFO_ATTEMPTS => {
+                warn!(
+                    "Unable to get subscription info (attempt {attempt} of {SUBSCRIPTION_INFO_ATTEMPTS}): {err}. Retrying in {retry_delay:?}..."
+                );
+                tokio::time::sleep(retry_delay).await;
+                attempt += 1;
+                retry_delay *= 2;
+            }
+            Err(err) => return Err(err),
+        }
+    };
+
+    // If protocol returned is something the subscriber can't handle, then fail. Publishers that
+    // only set the protocol string are still understood.
+    match ProtocolKind::resolve(sub_info.subscription_protocol_kind, &sub_info.protocol_kind) {
+        Ok(protocol) if protocol == expected_protocol => {}
+        _ => {
+            return Err(SubscriberError::UnsupportedProtocol {
+                expected: expected_protocol,
+                actual: sub_info.protocol_kind,
+            })
+        }
+    }
 
-    // TODO: Handle error
-    let mut pub_client = pub_client_result.unwrap();
+    // Process subscription metadata to get topic name to subscribe to.
+    let topic = parse_subscription_topic(&sub_info.subscription_metadata)?;
+
+    Ok(SubscriptionInfo {
+        uri: sub_info.subscription_uri,
+        topic,
+    })
+}
+
+/// Makes a single request for the subscription information to the publisher.
+///
+/// # Arguments
+///
+/// * `pub_uri` - The uri of the publisher of the data.
+/// * `subject` - The subject to request data about.
+async fn request_subscription_info(
+    pub_uri: &str,
+    subject: &str,
+) -> Result<SubscriptionInfoResponse, SubscriberError> {
+    let mut pub_client = SamplePublisherClient::connect(pub_uri.to_string())
+        .await
+        .map_err(SubscriberError::Connect)?;
 
-    // Get subscription info from publisher.
     let sub_request = SubscriptionInfoRequest {
         subject: subject.to_string(),
     };
-    let sub_response = pub_client.get_subscription_info(sub_request).await?;
-    let sub_info = sub_response.into_inner();
-    let protocol = sub_info.protocol_kind;
-    let uri = sub_info.subscription_uri;
-
-    // If protocol returned is something the subscriber can't handle, then exit.
-    if protocol != *expected_protocol {
-        error!("Unable to communicate with pub sub, expected protocol mqtt, but protocol is {protocol}.");
-        process::exit(1);
-    }
 
-    // Process subscription metadata to get topic name to subscribe to.
-    let metadata_json: Value = serde_json::from_str(&sub_info.subscription_metadata).unwrap();
-    let topic = metadata_json["topic"].as_str().unwrap().to_string();
+    pub_client
+        .get_subscription_info(sub_request)
+        .await
+        .map(|sub_response| sub_response.into_inner())
+        .map_err(SubscriberError::Request)
+}
 
-    Ok(SubscriptionInfo { uri, topic })
+/// Gets the topic to subscribe to from the subscription metadata returned by the publisher.
+///
+/// # Arguments
+///
+/// * `metadata` - The subscription metadata, in the JSON representation of
+///                [`SubscriptionMetadata`].
+fn parse_subscription_topic(metadata: &str) -> Result<String, SubscriberError> {
+    SubscriptionMetadata::from_json(metadata)
+        .map(|metadata| metadata.topic)
+        .map_err(|e| SubscriberError::InvalidMetadata(e.to_string()))
 }
 
 /// Gets the subscription stream from the broker.
@@ -95,33 +238,199 @@ pub async fn get_subscription_info(
 ///
 /// * `client_id` - The id of the subscriber service.
 /// * `uri` - The uri of the messaging broker.
+/// * `disconnect_topic` - The topic the subscriber's last will and testament is published to.
 /// * `topic_handle` - The shared reference handle of the topic.
 /// * `broker_handle` - The shared reference handle of the broker.
 pub async fn get_subscription_stream(
     client_id: String,
     uri: String,
+    disconnect_topic: String,
     topic_handle: Arc<Mutex<TopicRef>>,
     broker_handle: Arc<Mutex<BrokerRef>>,
 ) -> Result<Receiver<PubSubMessage>, Box<dyn std::error::Error + Send + Sync>> {
     let topic = topic_handle.lock().await;
+
+    connect_to_broker(client_id, uri, disconnect_topic, broker_handle.clone()).await?;
+
+    // A stream is returned from the subscribe call, which is used to get the messages from the broker.
+    subscribe_to_topic(&topic.topic, broker_handle).await
+}
+
+/// Resubscribes to a subject after its topic was deleted. Releases the deleted topic and its
+/// connection, then asks the publisher for a new topic until it provides one or the configured
+/// attempts run out. Returns the stream of the new topic.
+///
+/// # Arguments
+///
+/// * `pub_uri` - The uri of the publisher of the data.
+/// * `subject` - The subject to request data about.
+/// * `expected_protocol` - The protocol expected for the subscription.
+/// * `disconnect_topic` - The topic the subscriber's last will and testament is published to.
+/// * `settings` - The number of attempts and the interval between them.
+/// * `topic_handle` - The shared reference handle of the topic.
+/// * `broker_handle` - The shared reference handle of the broker.
+pub async fn resubscribe(
+    pub_uri: &str,
+    subject: &str,
+    expected_protocol: &str,
+    disconnect_topic: String,
+    settings: &ResubscribeSettings,
+    topic_handle: Arc<Mutex<TopicRef>>,
+    broker_handle: Arc<Mutex<BrokerRef>>,
+) -> Result<Receiver<PubSubMessage>, Box<dyn std::error::Error + Send + Sync>> {
+    // The new topic may be offered on another broker, so the connection is not reused.
+    {
+        let mut topic = topic_handle.lock().await;
+        let mut broker = broker_handle.lock().await;
+
+        if let Some(client) = broker.client.take() {
+            if topic.topic.ne(EMPTY_TOPIC) {
+                let _ = client.unsubscribe(topic.topic.clone()).await;
+            }
+
+            let _ = client.disconnect().await;
+        }
+
+        topic.topic = EMPTY_TOPIC.to_string();
+    }
+
+    let mut attempt = 1;
+    let info = loop {
+        match get_subscription_info(pub_uri, subject, expected_protocol).await {
+            Ok(info) => break info,
+            Err(err) if attempt < settings.max_attempts => {
+                warn!(
+                    "Unable to resubscribe to '{subject}' (attempt {attempt} of {}): {err}. Retrying in {} seconds...",
+                    settings.max_attempts, settings.retry_interval_secs
+                );
+                tokio::time::sleep(Duration::from_secs(settings.retry_interval_secs)).await;
+                attempt += 1;
+            }
+            Err(err) => return Err(Box::new(err)),
+        }
+    };
+
+    info!("Resubscribing to '{subject}' on topic '{}'.", info.topic);
+    {
+        let mut topic = topic_handle.lock().await;
+        topic.topic = info.topic;
+    }
+
+    let client_id = format!("sub_{}", Uuid::new_v4());
+    get_subscription_stream(
+        client_id,
+        info.uri,
+        disconnect_topic,
+        topic_handle,
+        broker_handle,
+    )
+    .await
+}
+
+/// Returns the client-side metrics shared by every broker connection of the subscriber, so that
+/// they add up across resubscribes. The metrics are logged at the given interval, starting with
+/// the first call.
+///
+/// # Arguments
+///
+/// * `interval_secs` - Interval in seconds between two reports of the metrics.
+fn client_metrics(interval_secs: u64) -> Arc<ClientMetrics> {
+    static CLIENT_METRICS: OnceLock<Arc<ClientMetrics>> = OnceLock::new();
+
+    CLIENT_METRICS
+        .get_or_init(|| {
+            let metrics = Arc::new(ClientMetrics::default());
+            let report_metrics = metrics.clone();
+
+            tokio::spawn(async move {
+                let interval = Duration::from_secs(interval_secs.max(1));
+                loop {
+                    tokio::time::sleep(interval).await;
+                    info!("Client metrics: {}", report_metrics.report());
+                }
+            });
+
+            metrics
+        })
+        .clone()
+}
+
+/// Connects to the broker, sharing the connection through the broker handle so that several
+/// topics can be subscribed to over it.
+///
+/// # Arguments
+///
+/// * `client_id` - The id of the subscriber service.
+/// * `uri` - The uri of the messaging broker.
+/// * `disconnect_topic` - The topic the subscriber's last will and testament is published to.
+/// * `broker_handle` - The shared reference handle of the broker.
+pub async fn connect_to_broker(
+    client_id: String,
+    uri: String,
+    disconnect_topic: String,
+    broker_handle: Arc<Mutex<BrokerRef>>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let mut broker = broker_handle.lock().await;
 
-    broker.client = Some(PubSubConnectorClient::new(client_id, uri));
+    let metrics_settings = load_config::load_client_metrics_settings();
+    let client: MqttFiveClientConnector =
+        PubSubConnectorClient::new(client_id, uri, load_config::load_broker_credentials());
+    let client = client
+        .with_last_will(disconnect_topic, ClientRole::Subscriber)
+        .with_connection_options(load_config::load_connection_options())
+        .with_timestamped_payloads(metrics_settings.timestamped_payloads);
+    broker.client = Some(match metrics_settings.report_interval_secs {
+        Some(interval_secs) => client.with_metrics(client_metrics(interval_secs)),
+        None => client,
+    });
     broker
         .client
         .as_ref()
         .expect("broker exists.")
         .connect()
-        .await?;
+        .await
+}
 
-    // A stream is returned from the subscribe call, which is used to get the messages from the broker.
-    info!("Subscribing...");
-    broker
+/// Subscribes to a topic over the connection shared through the broker handle. Returns the stream
+/// of messages received on the topic.
+///
+/// # Arguments
+///
+/// * `topic` - The topic to subscribe to.
+/// * `broker_handle` - The shared reference handle of the connected broker.
+pub async fn subscribe_to_topic(
+    topic: &str,
+    broker_handle: Arc<Mutex<BrokerRef>>,
+) -> Result<Receiver<PubSubMessage>, Box<dyn std::error::Error + Send + Sync>> {
+    let broker = broker_handle.lock().await;
+    let client = broker
         .client
         .as_ref()
-        .expect("broker exists.")
-        .subscribe(topic.topic.clone())
-        .await
+        .ok_or("Not connected to the broker")?;
+
+    info!("Subscribing to '{topic}'...");
+    client.subscribe(topic.to_string()).await
+}
+
+/// Unsubscribes from a topic over the connection shared through the broker handle, ending the
+/// stream of messages received on the topic.
+///
+/// # Arguments
+///
+/// * `topic` - The topic to unsubscribe from.
+/// * `broker_handle` - The shared reference handle of the connected broker.
+pub async fn unsubscribe_from_topic(
+    topic: &str,
+    broker_handle: Arc<Mutex<BrokerRef>>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let broker = broker_handle.lock().await;
+    let client = broker
+        .client
+        .as_ref()
+        .ok_or("Not connected to the broker")?;
+
+    info!("Unsubscribing from '{topic}'...");
+    client.unsubscribe(topic.to_string()).await
 }
 
 /// Gracefully shuts down the sample when Ctrl+C is called.
@@ -180,3 +489,52 @@ pub async fn handle_ctrlc_shutdown(
 
     topic_deleted_sender
 }
+
+/// Gracefully shuts down a sample subscribed to several topics over a single connection when
+/// Ctrl+C is called, or when a shutdown is sent over the returned sender.
+///
+/// # Arguments
+///
+/// * `broker_handle` - The shared reference handle of the broker.
+/// * `topics_handle` - The shared reference handle of the subscribed topics.
+pub async fn handle_multi_topic_ctrlc_shutdown(
+    broker_handle: Arc<Mutex<BrokerRef>>,
+    topics_handle: Arc<Mutex<TopicsRef>>,
+) -> Sender<String> {
+    let (shutdown_sender, shutdown_receiver) = mpsc::channel::<String>();
+
+    // Used to shut down the program once every topic is closed by the publisher.
+    let topics_closed_sender = shutdown_sender.clone();
+
+    // This captures the ctrl+c used to end a program and allows for the subscriber to gracefully exit.
+    ctrlc::set_handler(move || {
+        let _ = shutdown_sender.send(SHUTDOWN.to_string());
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    // Handles the graceful shutdown of the subscriber. If there is a broker client then it
+    // unsubscribes from every remaining topic and disconnects the client before closing the
+    // program.
+    let _shutdown_handle = tokio::spawn(async move {
+        let shutdown_recv = shutdown_receiver
+            .recv()
+            .unwrap_or_else(|_| SHUTDOWN.to_string());
+
+        info!("{shutdown_recv} request received.");
+        let topics = topics_handle.lock().await;
+        let broker = broker_handle.lock().await;
+        if let Some(client) = broker.client.as_ref() {
+            for topic in topics.topics.values() {
+                info!("Unsubscribing from '{topic}'...");
+                let _ = client.unsubscribe(topic.clone()).await;
+            }
+
+            info!("Disconnecting...");
+            let _ = client.disconnect().await;
+        }
+
+        process::exit(0);
+    });
+
+    topics_closed_sender
+}
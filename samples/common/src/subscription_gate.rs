@@ -0,0 +1,243 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Throttles the topics a publisher creates in response to subscription info requests.
+//!
+//! Requests for the same subject are handled one at a time, so that a burst of requests for a new
+//! subject creates a single topic that the later requests reuse. Topic creation is also rate
+//! limited with a token bucket, so that a burst of requests for many subjects can't flood the Pub
+//! Sub Service.
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use common::shared_state::SharedState;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+/// Default number of topics that can be created in a burst.
+pub const DEFAULT_MAX_BURST: u32 = 10;
+/// Default interval at which the ability to create another topic is regained.
+pub const DEFAULT_REFILL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Alias for the map of subjects to the lock serializing requests for that subject.
+type SubjectLocks = HashMap<String, Arc<AsyncMutex<()>>>;
+
+/// Token bucket limiting how often topics are created.
+#[derive(Debug)]
+struct TokenBucket {
+    max_burst: u32,
+    refill_interval: Duration,
+    tokens: u32,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Takes a token if one is available at the given time, refilling the bucket first. The
+    /// refill interval must not be zero.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time.
+    fn try_take(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        let refills =
+            (elapsed.as_nanos() / self.refill_interval.as_nanos()).min(u128::from(self.max_burst));
+        let refills = u32::try_from(refills).unwrap_or(self.max_burst);
+
+        if refills > 0 {
+            self.tokens = self.tokens.saturating_add(refills).min(self.max_burst);
+
+            // Time isn't banked once the bucket is full.
+            self.last_refill = if self.tokens == self.max_burst {
+                now
+            } else {
+                self.last_refill + self.refill_interval * refills
+            };
+        }
+
+        if self.tokens == 0 {
+            return false;
+        }
+
+        self.tokens -= 1;
+        true
+    }
+}
+
+/// Holds the lock of a subject, releasing it when dropped.
+pub struct SubjectGuard {
+    subject: String,
+    subject_locks: Arc<SharedState<SubjectLocks>>,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for SubjectGuard {
+    fn drop(&mut self) {
+        let mut subject_locks = self.subject_locks.lock();
+
+        // Forget the lock once no other request is waiting on it, so the map doesn't grow with
+        // every subject ever requested.
+        if let Some(guard) = self.guard.take() {
+            let idle = Arc::strong_count(OwnedMutexGuard::mutex(&guard)) == 2;
+            drop(guard);
+
+            if idle {
+                subject_locks.remove(&self.subject);
+            }
+        }
+    }
+}
+
+/// Deduplicates and rate limits the topic creations of a publisher.
+#[derive(Debug)]
+pub struct SubscriptionGate {
+    subject_locks: Arc<SharedState<SubjectLocks>>,
+    rate_limiter: SharedState<TokenBucket>,
+}
+
+impl SubscriptionGate {
+    /// Creates a new SubscriptionGate.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_burst` - The number of topics that can be created in a burst.
+    /// * `refill_interval` - The interval at which the ability to create another topic is
+    ///                       regained. Topic creation is not rate limited if zero.
+    pub fn new(max_burst: u32, refill_interval: Duration) -> Self {
+        SubscriptionGate {
+            subject_locks: Arc::new(SharedState::new(HashMap::new())),
+            rate_limiter: SharedState::new(TokenBucket {
+                max_burst,
+                refill_interval,
+                tokens: max_burst,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until no other request for the subject is in flight, then holds the subject until
+    /// the returned guard is dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject` - The requested subject.
+    pub async fn lock_subject(&self, subject: &str) -> SubjectGuard {
+        let subject_lock = self
+            .subject_locks
+            .lock()
+            .entry(subject.to_string())
+            .or_default()
+            .clone();
+
+        SubjectGuard {
+            subject: subject.to_string(),
+            subject_locks: self.subject_locks.clone(),
+            guard: Some(subject_lock.lock_owned().await),
+        }
+    }
+
+    /// Returns whether another topic may be created now, consuming the allowance if so.
+    pub fn try_acquire_creation(&self) -> bool {
+        let mut rate_limiter = self.rate_limiter.lock();
+
+        rate_limiter.refill_interval.is_zero() || rate_limiter.try_take(Instant::now())
+    }
+}
+
+impl Default for SubscriptionGate {
+    fn default() -> Self {
+        SubscriptionGate::new(DEFAULT_MAX_BURST, DEFAULT_REFILL_INTERVAL)
+    }
+}
+
+#[cfg(test)]
+mod subscription_gate_tests {
+    use super::*;
+
+    fn bucket(max_burst: u32, refill_interval: Duration, now: Instant) -> TokenBucket {
+        TokenBucket {
+            max_burst,
+            refill_interval,
+            tokens: max_burst,
+            last_refill: now,
+        }
+    }
+
+    #[test]
+    fn token_bucket_refills_over_time_test() {
+        let now = Instant::now();
+        let mut bucket = bucket(2, Duration::from_millis(500), now);
+
+        assert!(bucket.try_take(now));
+        assert!(bucket.try_take(now));
+        assert!(!bucket.try_take(now));
+
+        // A token is regained every refill interval.
+        assert!(!bucket.try_take(now + Duration::from_millis(499)));
+        assert!(bucket.try_take(now + Duration::from_millis(500)));
+        assert!(!bucket.try_take(now + Duration::from_millis(500)));
+
+        // Refills are capped at the burst size.
+        let later = now + Duration::from_secs(60);
+        assert!(bucket.try_take(later));
+        assert!(bucket.try_take(later));
+        assert!(!bucket.try_take(later));
+    }
+
+    #[test]
+    fn token_bucket_keeps_partial_refill_test() {
+        let now = Instant::now();
+        let mut bucket = bucket(2, Duration::from_millis(500), now);
+        assert!(bucket.try_take(now));
+        assert!(bucket.try_take(now));
+
+        // The time past the last refill counts towards the next one.
+        assert!(bucket.try_take(now + Duration::from_millis(750)));
+        assert!(bucket.try_take(now + Duration::from_millis(1000)));
+        assert!(!bucket.try_take(now + Duration::from_millis(1000)));
+    }
+
+    #[test]
+    fn zero_refill_interval_disables_rate_limit_test() {
+        let gate = SubscriptionGate::new(0, Duration::ZERO);
+
+        for _ in 0..100 {
+            assert!(gate.try_acquire_creation());
+        }
+    }
+
+    #[tokio::test]
+    async fn lock_subject_serializes_same_subject_test() {
+        let gate = Arc::new(SubscriptionGate::default());
+        let first = gate.lock_subject("gps").await;
+
+        // A second request for the same subject waits for the first.
+        let waiting_gate = gate.clone();
+        let mut second = tokio::spawn(async move { waiting_gate.lock_subject("gps").await });
+        assert!(tokio::time::timeout(Duration::from_millis(50), &mut second)
+            .await
+            .is_err());
+
+        // Other subjects are not held up.
+        let other = tokio::time::timeout(Duration::from_secs(1), gate.lock_subject("speed"))
+            .await
+            .unwrap();
+        drop(other);
+
+        drop(first);
+        let second = tokio::time::timeout(Duration::from_secs(1), second)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // The lock of the subject is kept while a request holds it, and forgotten after.
+        assert!(gate.subject_locks.lock().contains_key("gps"));
+        assert!(!gate.subject_locks.lock().contains_key("speed"));
+        drop(second);
+        assert!(gate.subject_locks.lock().is_empty());
+    }
+}
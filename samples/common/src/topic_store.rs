@@ -9,14 +9,13 @@
 //! given by the Pub Sub Service. The store handles the addition and removal of topics from the
 //! topic store.
 
-use std::{
-    collections::HashMap,
-    sync::{mpsc, Arc, Mutex},
-    time::Instant,
-};
+use std::{collections::HashMap, sync::Arc, time::Instant};
 
+use common::shared_state::SharedState;
+use common::subscription_metadata::SubscriptionMetadata;
 use samples_proto::sample_publisher::v1::SubscriptionInfoResponse;
 
+use tokio_util::sync::CancellationToken;
 use tonic::Status;
 
 use crate::pub_sub_service_helper::{self, TopicAction};
@@ -35,8 +34,8 @@ pub struct TopicMetadata {
     pub last_active: Instant,
     /// The relevant subscription information for subscribing to the topic.
     pub subscription_info: SubscriptionInfoResponse,
-    /// The channel that is opened when a topic is active.
-    pub active_sender: Option<mpsc::Sender<String>>,
+    /// The token that stops publishing to the topic, set while the topic is active.
+    pub cancellation_token: Option<CancellationToken>,
 }
 
 impl TopicMetadata {
@@ -51,15 +50,14 @@ impl TopicMetadata {
             action: TopicAction::Init,
             last_active: Instant::now(),
             subscription_info,
-            active_sender: None,
+            cancellation_token: None,
         }
     }
 
     /// Deactivates topic, stopping publishing and setting last active timestamp to this instant.
     pub fn deactivate_topic(&mut self) {
-        if let Some(sender) = self.active_sender.to_owned() {
-            drop(sender);
-            self.active_sender = None;
+        if let Some(cancellation_token) = self.cancellation_token.take() {
+            cancellation_token.cancel();
             self.last_active = Instant::now();
         }
     }
@@ -69,17 +67,17 @@ impl TopicMetadata {
 #[derive(Clone, Debug, Default)]
 pub struct TopicStore {
     /// Maps topics with the topic metadata.
-    topics_map: Arc<Mutex<TopicsMap>>,
+    topics_map: Arc<SharedState<TopicsMap>>,
     /// Maps the generated topics with the known topics in the `topics_map`.
-    generated_topics_map: Arc<Mutex<GeneratedTopicsMap>>,
+    generated_topics_map: Arc<SharedState<GeneratedTopicsMap>>,
 }
 
 impl TopicStore {
     /// Creates a new instance of the topic store.
     pub fn new() -> Self {
         TopicStore {
-            topics_map: Arc::new(Mutex::new(TopicsMap::new())),
-            generated_topics_map: Arc::new(Mutex::new(GeneratedTopicsMap::new())),
+            topics_map: Arc::new(SharedState::new(TopicsMap::new())),
+            generated_topics_map: Arc::new(SharedState::new(GeneratedTopicsMap::new())),
         }
     }
 
@@ -91,8 +89,8 @@ impl TopicStore {
     /// * `subscription_info` - An object that contains information about how to subscribe to a
     ///                         topic.
     pub fn add_topic(&self, topic: String, subscription_info: SubscriptionInfoResponse) {
-        let mut topics = self.topics_map.lock().unwrap();
-        let mut generated_topics = self.generated_topics_map.lock().unwrap();
+        let mut topics = self.topics_map.lock();
+        let mut generated_topics = self.generated_topics_map.lock();
         let generated_topic =
             pub_sub_service_helper::get_topic_from_subscription_response(&subscription_info);
 
@@ -106,7 +104,7 @@ impl TopicStore {
     ///
     /// * `topic` - The topic to get metadata about.
     pub fn get_topic_metadata(&self, topic: &str) -> Option<TopicMetadata> {
-        self.topics_map.lock().unwrap().get(topic).cloned()
+        self.topics_map.lock().get(topic).cloned()
     }
 
     /// Return the associated topic to the publisher since the pub sub service only knows about the
@@ -119,7 +117,6 @@ impl TopicStore {
     pub fn get_generated_topic_mapping(&self, generated_topic: &str) -> Result<String, Status> {
         self.generated_topics_map
             .lock()
-            .unwrap()
             .get(generated_topic)
             .map(|topic| (*topic).clone())
             .ok_or_else(|| Status::not_found(generated_topic))
@@ -131,33 +128,54 @@ impl TopicStore {
     ///
     /// * `topic` - The topic to reset the `last_active` time for.
     pub fn deactivate_topic(&self, topic: &str) {
-        if let Some(topic_metadata) = self.topics_map.lock().unwrap().get_mut(topic) {
+        if let Some(topic_metadata) = self.topics_map.lock().get_mut(topic) {
             topic_metadata.deactivate_topic();
         }
     }
 
-    /// Activates topic, adding the channel for deactivation when the time comes to the topic
-    /// metadata. Returns the updated topic metadata.
+    /// Activates topic, adding a new cancellation token for deactivation when the time comes to
+    /// the topic metadata. A topic that was already active stops its previous publishing first.
+    /// Returns the updated topic metadata and the token that the publishing task stops on.
     ///
     /// # Arguments
     ///
     /// * `topic` - The topic to update the `action` field for.
-    /// * `sender` - The sender side of an mpsc channel used to stop the publishing thread.
-    pub fn activate_topic(
-        &self,
-        topic: &str,
-        sender: mpsc::Sender<String>,
-    ) -> Option<TopicMetadata> {
-        self.topics_map
-            .lock()
-            .unwrap()
-            .get_mut(topic)
-            .map(|topic_metadata| {
-                topic_metadata.active_sender = Some(sender);
-                topic_metadata.action = TopicAction::Start;
-                topic_metadata.last_active = Instant::now();
-                topic_metadata.clone()
-            })
+    pub fn activate_topic(&self, topic: &str) -> Option<(TopicMetadata, CancellationToken)> {
+        self.topics_map.lock().get_mut(topic).map(|topic_metadata| {
+            let cancellation_token = CancellationToken::new();
+            if let Some(previous_token) = topic_metadata
+                .cancellation_token
+                .replace(cancellation_token.clone())
+            {
+                previous_token.cancel();
+            }
+            topic_metadata.action = TopicAction::Start;
+            topic_metadata.last_active = Instant::now();
+            (topic_metadata.clone(), cancellation_token)
+        })
+    }
+
+    /// Records the lifecycle epoch of a topic in its subscription metadata, so that subscribers
+    /// asking for the topic learn the epoch of the running publisher. Subscription metadata that
+    /// can't be parsed is left as is.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic to record the lifecycle epoch of.
+    /// * `epoch` - The lifecycle epoch the Pub Sub Service sent with the START action.
+    pub fn set_lifecycle_epoch(&self, topic: &str, epoch: u64) {
+        let mut topics = self.topics_map.lock();
+        let Some(topic_metadata) = topics.get_mut(topic) else {
+            return;
+        };
+
+        let subscription_info = &mut topic_metadata.subscription_info;
+        if let Ok(mut metadata) =
+            SubscriptionMetadata::from_json(&subscription_info.subscription_metadata)
+        {
+            metadata.lifecycle_epoch = Some(epoch);
+            subscription_info.subscription_metadata = metadata.to_json();
+        }
     }
 
     /// Removes the topic from the topic store.
@@ -167,8 +185,8 @@ impl TopicStore {
     /// * `topic` - The topic to remove.
     /// * `generated_topic` - The generated topic associated with the topic above.
     pub fn remove_topic(&self, topic: &str, generated_topic: &str) {
-        let mut topics = self.topics_map.lock().unwrap();
-        let mut generated_topics = self.generated_topics_map.lock().unwrap();
+        let mut topics = self.topics_map.lock();
+        let mut generated_topics = self.generated_topics_map.lock();
 
         topics.remove(topic);
         generated_topics.remove(generated_topic);
@@ -5,9 +5,18 @@
 //! Describes a trait that should be implemented for a messaging broker to allow connections from
 //! publishers and subscribers.
 
-use std::sync::mpsc::Receiver;
+use std::{fmt, sync::mpsc::Receiver};
 
 use async_trait::async_trait;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::sequence::SequenceNumber;
+
+/// Default topic used by a client's last will and testament for unclean disconnect.
+pub const DEFAULT_DISCONNECT_TOPIC: &str = "publisher/disconnect";
+
+/// Name of the MQTT v5 user property carrying the lifecycle epoch of the topic of a message.
+pub const LIFECYCLE_EPOCH_PROPERTY: &str = "agemo-lifecycle-epoch";
 
 /// Trait implementation needed for communicating with a messaging broker. Utilized by both
 /// publishers and subscribers to handle outgoing and incomming messages.
@@ -19,7 +28,8 @@ pub trait PubSubConnectorClient {
     ///
     /// * `client_id` - Id to be used to create the broker client.
     /// * `uri` - The uri of the broker that the client is connecting to.
-    fn new(client_id: String, uri: String) -> Self;
+    /// * `credentials` - Optional credentials used to authenticate with the broker.
+    fn new(client_id: String, uri: String, credentials: Option<ClientCredentials>) -> Self;
 
     /// Function that initiates the connection with the messaging broker.
     async fn connect(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
@@ -49,7 +59,7 @@ pub trait PubSubConnectorClient {
         topic: String,
     ) -> Result<Receiver<PubSubMessage>, Box<dyn std::error::Error + Send + Sync>>;
 
-    /// Function that unsubscribes from a topic.
+    /// Function that unsubscribes from a topic, ending the stream returned when subscribing to it.
     ///
     /// # Arguments
     ///
@@ -64,4 +74,86 @@ pub trait PubSubConnectorClient {
 pub struct PubSubMessage {
     pub topic: String,
     pub payload: String,
+    /// The sequence number of the message, if its publisher stamps one.
+    pub sequence: Option<SequenceNumber>,
+    /// The lifecycle epoch of the topic the message was published in, if its publisher stamps
+    /// one. Changes when the publisher is restarted.
+    pub lifecycle_epoch: Option<u64>,
+}
+
+/// The role a client plays, reported to the Pub Sub Service when the client disconnects.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientRole {
+    /// The client publishes to one or more topics.
+    #[default]
+    Publisher,
+    /// The client subscribes to one or more topics.
+    Subscriber,
+}
+
+/// Credentials used by a client to authenticate with the messaging broker.
+#[derive(Clone, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ClientCredentials {
+    /// The username to authenticate with.
+    pub username: Option<String>,
+    /// The password to authenticate with.
+    pub password: Option<String>,
+    /// A token to authenticate with, such as a JWT. Takes precedence over the password for
+    /// brokers that accept tokens in place of a password.
+    pub token: Option<String>,
+}
+
+impl ClientCredentials {
+    /// Returns the secret to authenticate with, preferring the token over the password.
+    pub fn secret(&self) -> Option<&str> {
+        self.token.as_deref().or(self.password.as_deref())
+    }
+}
+
+impl fmt::Debug for ClientCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Secrets are redacted so credentials can't leak through logs.
+        f.debug_struct("ClientCredentials")
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+/// MQTT v5 options applied when a client connects to the messaging broker.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ConnectionOptions {
+    /// Interval in seconds that the broker keeps a session after the client disconnects.
+    pub session_expiry_interval_secs: u32,
+    /// Whether the broker should discard any existing session when the client connects.
+    pub clean_start: bool,
+    /// Interval in seconds between keep alive pings sent to the broker.
+    pub keep_alive_interval_secs: u64,
+    /// Maximum number of unacknowledged QoS 1 and QoS 2 messages the client accepts at once.
+    pub receive_maximum: u16,
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        ConnectionOptions {
+            session_expiry_interval_secs: 3600,
+            clean_start: false,
+            keep_alive_interval_secs: 60,
+            receive_maximum: 65535,
+        }
+    }
+}
+
+/// Structured payload sent on the disconnect topic by a client's last will and testament.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct DisconnectPayload {
+    /// The id of the client that lost connection.
+    pub client_id: String,
+    /// The role of the client that lost connection.
+    pub role: ClientRole,
+    /// Time in seconds since the Unix epoch that the client registered its last will.
+    pub timestamp: u64,
 }
@@ -0,0 +1,220 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Client-side metrics, used by load tests built on the samples to report message loss and
+//! latency as seen by subscribers.
+//!
+//! A client connector reports the messages it receives, the subscriptions it restores after
+//! reconnecting and the connections it loses to [`ClientMetricsHooks`]. Latency is measured from
+//! the time publishers embed in a [`PayloadEnvelope`] around each payload, so publishers and
+//! subscribers must both enable timestamped payloads, and their clocks must be in sync.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use common::shared_state::SharedState;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    client_connector::PubSubMessage,
+    sequence::{GapDetector, SequenceCheck},
+};
+
+/// Returns the current time in milliseconds since the Unix epoch.
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Envelope around a published payload, carrying the time it was published at.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PayloadEnvelope {
+    /// Time in milliseconds since the Unix epoch that the payload was published at.
+    pub sent_at_ms: u64,
+    /// The published payload.
+    pub payload: String,
+}
+
+impl PayloadEnvelope {
+    /// Wraps a payload in an envelope stamped with the current time, and returns the envelope
+    /// as JSON.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The payload to wrap.
+    pub fn wrap(payload: String) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&PayloadEnvelope {
+            sent_at_ms: now_ms(),
+            payload,
+        })
+    }
+
+    /// Parses an envelope from a received payload, or returns `None` if the payload is not an
+    /// envelope.
+    ///
+    /// # Arguments
+    ///
+    /// * `raw` - The received payload.
+    pub fn parse(raw: &str) -> Option<Self> {
+        serde_json::from_str(raw).ok()
+    }
+
+    /// Returns the time between the payload being published and now.
+    pub fn latency(&self) -> Duration {
+        Duration::from_millis(now_ms().saturating_sub(self.sent_at_ms))
+    }
+}
+
+/// Callbacks a client connector makes as it receives messages and loses or restores its
+/// connection. Called from the client's callback thread, so implementations must not block.
+pub trait ClientMetricsHooks: Send + Sync {
+    /// Called for every message received on a subscribed topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The received message, with the envelope removed from its payload.
+    /// * `latency` - Time between the message being published and received, if its payload
+    ///               was in an envelope.
+    fn message_received(&self, _message: &PubSubMessage, _latency: Option<Duration>) {}
+
+    /// Called when a subscription is restored after the client reconnected to the broker.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic subscribed to again.
+    fn resubscribed(&self, _topic: &str) {}
+
+    /// Called when the client loses its connection to the broker.
+    fn connection_lost(&self) {}
+}
+
+/// Latency statistics of the received messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    /// The number of messages with a measured latency.
+    pub count: u64,
+    /// The sum of the latencies.
+    pub total: Duration,
+    /// The smallest latency.
+    pub min: Duration,
+    /// The largest latency.
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    /// Records the latency of a message.
+    ///
+    /// # Arguments
+    ///
+    /// * `latency` - The latency of the message.
+    fn record(&mut self, latency: Duration) {
+        self.min = if self.count == 0 {
+            latency
+        } else {
+            self.min.min(latency)
+        };
+        self.max = self.max.max(latency);
+        self.total += latency;
+        self.count += 1;
+    }
+
+    /// Returns the average latency, or zero if no latency was measured.
+    pub fn average(&self) -> Duration {
+        u32::try_from(self.count)
+            .ok()
+            .filter(|count| *count > 0)
+            .map(|count| self.total / count)
+            .unwrap_or_default()
+    }
+}
+
+/// Snapshot of the metrics recorded by [`ClientMetrics`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClientMetricsReport {
+    /// The number of messages received.
+    pub received: u64,
+    /// The number of messages missed, going by the gaps in the sequence numbers of the received
+    /// messages.
+    pub missed: u64,
+    /// The number of subscriptions restored after reconnecting.
+    pub resubscribes: u64,
+    /// The number of times the connection to the broker was lost.
+    pub connection_drops: u64,
+    /// The latency of the messages whose payload was in an envelope.
+    pub latency: LatencyStats,
+}
+
+impl fmt::Display for ClientMetricsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "received {}, missed {}, resubscribes {}, connection drops {}",
+            self.received, self.missed, self.resubscribes, self.connection_drops
+        )?;
+
+        if self.latency.count > 0 {
+            write!(
+                f,
+                ", latency avg {:?} min {:?} max {:?}",
+                self.latency.average(),
+                self.latency.min,
+                self.latency.max
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// [`ClientMetricsHooks`] that count the received messages, detect the missed ones from their
+/// sequence numbers and aggregate their latency.
+#[derive(Debug, Default)]
+pub struct ClientMetrics {
+    report: SharedState<ClientMetricsReport>,
+    gaps: SharedState<HashMap<String, GapDetector>>,
+}
+
+impl ClientMetrics {
+    /// Returns a snapshot of the metrics recorded so far.
+    pub fn report(&self) -> ClientMetricsReport {
+        self.report.lock().clone()
+    }
+}
+
+impl ClientMetricsHooks for ClientMetrics {
+    fn message_received(&self, message: &PubSubMessage, latency: Option<Duration>) {
+        let missed = message.sequence.map_or(0, |sequence| {
+            match self
+                .gaps
+                .lock()
+                .entry(message.topic.clone())
+                .or_default()
+                .check(sequence)
+            {
+                SequenceCheck::Gap { missed } => missed,
+                _ => 0,
+            }
+        });
+
+        let mut report = self.report.lock();
+        report.received += 1;
+        report.missed += missed;
+        if let Some(latency) = latency {
+            report.latency.record(latency);
+        }
+    }
+
+    fn resubscribed(&self, _topic: &str) {
+        self.report.lock().resubscribes += 1;
+    }
+
+    fn connection_lost(&self) {
+        self.report.lock().connection_drops += 1;
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Compression of message payloads.
+//!
+//! Publishers of topics created with a payload compression compress each payload and name the
+//! compression in a user property of the message. Subscribers decompress the payloads of messages
+//! carrying the property, so that they don't have to know the compression of a topic in advance.
+
+use std::io::{self, Read, Write};
+
+use common::payload_compression::PayloadCompression;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+/// Name of the MQTT v5 user property carrying the compression of a message's payload.
+pub const COMPRESSION_PROPERTY: &str = "agemo-compression";
+
+/// Compresses a payload.
+///
+/// # Arguments
+///
+/// * `compression` - The compression to apply.
+/// * `payload` - The payload to compress.
+pub fn compress(compression: PayloadCompression, payload: &[u8]) -> io::Result<Vec<u8>> {
+    match compression {
+        PayloadCompression::None => Ok(payload.to_vec()),
+        PayloadCompression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(payload)?;
+            encoder.finish()
+        }
+        PayloadCompression::Zstd => zstd::encode_all(payload, zstd::DEFAULT_COMPRESSION_LEVEL),
+        _ => Err(unsupported(compression)),
+    }
+}
+
+/// Decompresses a payload.
+///
+/// # Arguments
+///
+/// * `compression` - The compression the payload was compressed with.
+/// * `payload` - The compressed payload.
+pub fn decompress(compression: PayloadCompression, payload: &[u8]) -> io::Result<Vec<u8>> {
+    match compression {
+        PayloadCompression::None => Ok(payload.to_vec()),
+        PayloadCompression::Gzip => {
+            let mut decompressed = Vec::new();
+            GzDecoder::new(payload).read_to_end(&mut decompressed)?;
+            Ok(decompressed)
+        }
+        PayloadCompression::Zstd => zstd::decode_all(payload),
+        _ => Err(unsupported(compression)),
+    }
+}
+
+/// Returns the error for a compression added to the Pub Sub Service after this connector.
+///
+/// # Arguments
+///
+/// * `compression` - The unsupported compression.
+fn unsupported(compression: PayloadCompression) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("unsupported payload compression '{compression}'"),
+    )
+}
+
+#[cfg(test)]
+mod compression_tests {
+    use super::*;
+
+    #[test]
+    fn compression_round_trips_test() {
+        let payload = r#"{"subject": "gps", "value": 42.0}"#.repeat(10);
+
+        for compression in [
+            PayloadCompression::None,
+            PayloadCompression::Gzip,
+            PayloadCompression::Zstd,
+        ] {
+            let compressed = compress(compression, payload.as_bytes()).unwrap();
+            if compression.is_compressed() {
+                assert!(compressed.len() < payload.len());
+            }
+
+            let decompressed = decompress(compression, &compressed).unwrap();
+            assert_eq!(payload.as_bytes(), decompressed.as_slice());
+        }
+    }
+
+    #[test]
+    fn decompress_rejects_corrupt_payload_test() {
+        assert!(decompress(PayloadCompression::Gzip, b"not gzip").is_err());
+        assert!(decompress(PayloadCompression::Zstd, b"not zstd").is_err());
+    }
+}
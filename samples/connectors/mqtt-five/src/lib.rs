@@ -3,4 +3,7 @@
 // SPDX-License-Identifier: MIT
 
 pub mod client_connector;
+pub mod client_metrics;
+pub mod compression;
 pub mod mqtt_five_client_connector;
+pub mod sequence;
@@ -5,35 +5,213 @@
 //! A MQTT v5 client that implements the [PubSubConnectorClient] trait.
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     io::ErrorKind,
     process,
     sync::{
+        atomic::{AtomicBool, Ordering},
         mpsc::{self, Receiver, Sender},
-        Arc, Mutex,
+        Arc,
     },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use async_trait::async_trait;
-use log::{error, info};
+use common::payload_compression::PayloadCompression;
+use common::shared_state::SharedState;
+use log::{error, info, warn};
 use paho_mqtt::{self as mqtt, MQTT_VERSION_5};
 
-use crate::client_connector::{PubSubConnectorClient, PubSubMessage};
+use crate::{
+    client_connector::{
+        ClientCredentials, ClientRole, ConnectionOptions, DisconnectPayload, PubSubConnectorClient,
+        PubSubMessage, DEFAULT_DISCONNECT_TOPIC, LIFECYCLE_EPOCH_PROPERTY,
+    },
+    client_metrics::{ClientMetricsHooks, PayloadEnvelope},
+    compression::{self, COMPRESSION_PROPERTY},
+    sequence::{SequenceNumber, SequenceStamper, EPOCH_PROPERTY, SEQUENCE_PROPERTY},
+};
 
 /// Alias that maps a topic to a sender stream.
 type Subscriptions = HashMap<String, Sender<PubSubMessage>>;
 
+/// Alias for the metric hooks shared with the client's callbacks.
+type MetricsHandle = Arc<SharedState<Option<Arc<dyn ClientMetricsHooks>>>>;
+
 /// Implementation of an MQTT v5 client.
 pub struct MqttFiveClientConnector {
     /// Underlying client that handles the mqtt connection.
     client: mqtt::AsyncClient,
     /// Handle to shared subscription map.
-    subscriptions: Arc<Mutex<Subscriptions>>,
+    subscriptions: Arc<SharedState<Subscriptions>>,
+    /// Topic that the client's last will and testament is published to.
+    disconnect_topic: String,
+    /// Role reported in the client's last will and testament.
+    role: ClientRole,
+    /// MQTT v5 options applied when connecting to the broker.
+    connection_options: ConnectionOptions,
+    /// Credentials used to authenticate with the broker.
+    credentials: Option<ClientCredentials>,
+    /// Sequence numbers stamped on the messages published to topics with a sequence epoch.
+    sequences: SequenceStamper,
+    /// Topics whose messages are published with the retained flag.
+    retained_topics: SharedState<HashSet<String>>,
+    /// Compressions applied to the payloads published to each topic.
+    compressed_topics: SharedState<HashMap<String, PayloadCompression>>,
+    /// Lifecycle epochs stamped on the messages published to each topic.
+    lifecycle_epochs: SharedState<HashMap<String, u64>>,
+    /// Hooks reported to as messages are received and the connection is lost or restored.
+    metrics: MetricsHandle,
+    /// Whether payloads are wrapped in a [`PayloadEnvelope`] when published, and unwrapped when
+    /// received.
+    timestamped_payloads: Arc<AtomicBool>,
+}
+
+impl MqttFiveClientConnector {
+    /// Sets the topic and role used for the client's last will and testament. Must be called
+    /// before connecting to take effect.
+    ///
+    /// # Arguments
+    ///
+    /// * `disconnect_topic` - The topic to publish the last will and testament to.
+    /// * `role` - The role of the client, reported in the last will and testament.
+    pub fn with_last_will(mut self, disconnect_topic: String, role: ClientRole) -> Self {
+        self.disconnect_topic = disconnect_topic;
+        self.role = role;
+        self
+    }
+
+    /// Sets the MQTT v5 options used when connecting to the broker. Must be called before
+    /// connecting to take effect.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection_options` - Session expiry, clean start, keep alive and receive maximum
+    ///                          options for the connection.
+    pub fn with_connection_options(mut self, connection_options: ConnectionOptions) -> Self {
+        self.connection_options = connection_options;
+        self
+    }
+
+    /// Reports the messages received, the subscriptions restored after reconnecting and the
+    /// connections lost to the given hooks.
+    ///
+    /// # Arguments
+    ///
+    /// * `metrics` - The hooks to report to.
+    pub fn with_metrics(self, metrics: Arc<dyn ClientMetricsHooks>) -> Self {
+        *self.metrics.lock() = Some(metrics);
+        self
+    }
+
+    /// Wraps published payloads in a [`PayloadEnvelope`] carrying the time they were published
+    /// at, and unwraps received payloads to measure their latency. Publishers and subscribers of
+    /// a topic must agree on whether payloads are timestamped.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether payloads are timestamped.
+    pub fn with_timestamped_payloads(self, enabled: bool) -> Self {
+        self.timestamped_payloads.store(enabled, Ordering::SeqCst);
+        self
+    }
+
+    /// Stamps the messages published to a topic with sequence numbers of the given epoch, as
+    /// user properties. Called with the sequence epoch returned when creating the topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic to stamp the messages of.
+    /// * `epoch` - The sequence epoch the Pub Sub Service assigned to the topic.
+    pub fn enable_sequence_numbers(&self, topic: &str, epoch: u64) {
+        self.sequences.set_epoch(topic, epoch);
+    }
+
+    /// Stops stamping the messages published to a topic with sequence numbers.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic to stop stamping the messages of.
+    pub fn disable_sequence_numbers(&self, topic: &str) {
+        self.sequences.remove(topic);
+    }
+
+    /// Publishes the messages of a topic with the retained flag, so that the broker delivers the
+    /// latest message to every subscriber that joins later. Called for topics created with the
+    /// `config/retained` topic class.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic to retain the messages of.
+    pub fn enable_retained_messages(&self, topic: &str) {
+        self.retained_topics.lock().insert(topic.to_string());
+    }
+
+    /// Compresses the payloads published to a topic, naming the compression in a user property
+    /// so that subscribers decompress them. Called with the payload compression the topic was
+    /// created with.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic to compress the payloads of.
+    /// * `compression` - The compression to apply.
+    pub fn enable_payload_compression(&self, topic: &str, compression: PayloadCompression) {
+        let mut compressed_topics = self.compressed_topics.lock();
+
+        if compression.is_compressed() {
+            compressed_topics.insert(topic.to_string(), compression);
+        } else {
+            compressed_topics.remove(topic);
+        }
+    }
+
+    /// Stamps the messages published to a topic with the lifecycle epoch of the topic, as a user
+    /// property. Called with the lifecycle epoch the Pub Sub Service sent with the START action.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic to stamp the messages of.
+    /// * `epoch` - The lifecycle epoch of the topic.
+    pub fn set_lifecycle_epoch(&self, topic: &str, epoch: u64) {
+        self.lifecycle_epochs
+            .lock()
+            .insert(topic.to_string(), epoch);
+    }
+}
+
+/// Returns the payload of a received message, decompressed if the message names the compression
+/// of its payload. Returns None if the payload can't be decompressed.
+///
+/// # Arguments
+///
+/// * `msg` - The received message.
+fn received_payload(msg: &mqtt::Message) -> Option<String> {
+    let Some(name) = msg.properties().find_user_property(COMPRESSION_PROPERTY) else {
+        return Some(msg.payload_str().to_string());
+    };
+
+    let decompressed = name
+        .parse::<PayloadCompression>()
+        .map_err(|err| err.to_string())
+        .and_then(|compression| {
+            compression::decompress(compression, msg.payload()).map_err(|err| err.to_string())
+        });
+
+    match decompressed {
+        Ok(payload) => Some(String::from_utf8_lossy(&payload).into_owned()),
+        Err(err) => {
+            warn!(
+                "Dropping message on '{}' with a payload that can't be decompressed: {err}",
+                msg.topic()
+            );
+            None
+        }
+    }
 }
 
 #[async_trait]
 impl PubSubConnectorClient for MqttFiveClientConnector {
-    fn new(client_id: String, uri: String) -> Self {
+    fn new(client_id: String, uri: String, credentials: Option<ClientCredentials>) -> Self {
         let host = uri.clone();
 
         let create_opts = mqtt::CreateOptionsBuilder::new()
@@ -46,48 +224,128 @@ impl PubSubConnectorClient for MqttFiveClientConnector {
             process::exit(1); // TODO: gracefully handle with retry?
         });
 
-        let subscriptions = Arc::new(Mutex::new(Subscriptions::new()));
+        let subscriptions = Arc::new(SharedState::new(Subscriptions::new()));
+        let metrics: MetricsHandle = Arc::new(SharedState::new(None));
+        let timestamped_payloads = Arc::new(AtomicBool::new(false));
 
         let cb_subscriptions = subscriptions.clone();
+        let cb_metrics = metrics.clone();
+        let cb_timestamped_payloads = timestamped_payloads.clone();
 
         cli.set_message_callback(move |_cli, msg| {
             if let Some(msg) = msg {
                 let topic = msg.topic();
-                let payload = msg.payload_str();
+                let Some(payload) = received_payload(&msg) else {
+                    return;
+                };
 
-                let sub_lock = cb_subscriptions.lock().unwrap();
+                let sub_lock = cb_subscriptions.lock();
 
                 if let Some(topic_ch) = sub_lock.get(topic) {
+                    let envelope = cb_timestamped_payloads
+                        .load(Ordering::SeqCst)
+                        .then(|| PayloadEnvelope::parse(&payload))
+                        .flatten();
+                    let latency = envelope.as_ref().map(PayloadEnvelope::latency);
+
+                    let properties = msg.properties();
                     let message = PubSubMessage {
                         topic: topic.to_string(),
-                        payload: payload.to_string(),
+                        payload: envelope.map_or(payload, |e| e.payload),
+                        sequence: SequenceNumber::parse(
+                            properties.find_user_property(EPOCH_PROPERTY).as_deref(),
+                            properties.find_user_property(SEQUENCE_PROPERTY).as_deref(),
+                        ),
+                        lifecycle_epoch: properties
+                            .find_user_property(LIFECYCLE_EPOCH_PROPERTY)
+                            .and_then(|epoch| epoch.parse().ok()),
                     };
 
+                    if let Some(metrics) = cb_metrics.lock().as_ref() {
+                        metrics.message_received(&message, latency);
+                    }
+
                     // TODO: handle send error.
                     let _res = topic_ch.send(message);
                 }
             }
         });
 
+        // A broker that discarded the session, such as after a clean start, forgets the
+        // subscriptions, so they are restored on every reconnect.
+        let cb_subscriptions = subscriptions.clone();
+        let cb_metrics = metrics.clone();
+        cli.set_connected_callback(move |cli| {
+            let topics: Vec<String> = cb_subscriptions.lock().keys().cloned().collect();
+
+            for topic in topics {
+                let _token = cli.subscribe(&topic, 1);
+
+                if let Some(metrics) = cb_metrics.lock().as_ref() {
+                    metrics.resubscribed(&topic);
+                }
+            }
+        });
+
+        let cb_metrics = metrics.clone();
+        cli.set_connection_lost_callback(move |_cli| {
+            if let Some(metrics) = cb_metrics.lock().as_ref() {
+                metrics.connection_lost();
+            }
+        });
+
         info!("Created client with id: {client_id} and connection_uri: {uri}");
 
         MqttFiveClientConnector {
             client: cli,
             subscriptions,
+            disconnect_topic: DEFAULT_DISCONNECT_TOPIC.to_string(),
+            role: ClientRole::default(),
+            connection_options: ConnectionOptions::default(),
+            credentials,
+            sequences: SequenceStamper::default(),
+            retained_topics: SharedState::new(HashSet::new()),
+            compressed_topics: SharedState::new(HashMap::new()),
+            lifecycle_epochs: SharedState::new(HashMap::new()),
+            metrics,
+            timestamped_payloads,
         }
     }
 
     async fn connect(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let id = self.client.client_id();
-        let lwt_string = format!("client_id: {} has lost connection", id);
-        // TODO: Make this more generic so that it can be used in the case of Subscriber disconnect.
-        let lwt = mqtt::Message::new("publisher/disconnect", lwt_string, mqtt::QOS_1);
-
-        let conn_opts = mqtt::ConnectOptionsBuilder::with_mqtt_version(MQTT_VERSION_5)
-            .clean_start(false)
-            .properties(mqtt::properties![mqtt::PropertyCode::SessionExpiryInterval => 3600])
-            .will_message(lwt)
-            .finalize();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let lwt_payload = serde_json::to_string(&DisconnectPayload {
+            client_id: self.client.client_id(),
+            role: self.role,
+            timestamp,
+        })?;
+        let lwt = mqtt::Message::new(self.disconnect_topic.clone(), lwt_payload, mqtt::QOS_1);
+
+        let options = &self.connection_options;
+        let mut conn_builder = mqtt::ConnectOptionsBuilder::with_mqtt_version(MQTT_VERSION_5);
+        conn_builder
+            .clean_start(options.clean_start)
+            .keep_alive_interval(Duration::from_secs(options.keep_alive_interval_secs))
+            .properties(mqtt::properties![
+                mqtt::PropertyCode::SessionExpiryInterval => options.session_expiry_interval_secs,
+                mqtt::PropertyCode::ReceiveMaximum => options.receive_maximum
+            ])
+            .will_message(lwt);
+
+        if let Some(credentials) = &self.credentials {
+            if let Some(username) = &credentials.username {
+                conn_builder.user_name(username.as_str());
+            }
+
+            if let Some(secret) = credentials.secret() {
+                conn_builder.password(secret);
+            }
+        }
+
+        let conn_opts = conn_builder.finalize();
 
         if let Err(err) = self.client.connect(conn_opts).wait() {
             error!("Unable to connect: {err}");
@@ -120,7 +378,59 @@ impl PubSubConnectorClient for MqttFiveClientConnector {
             }
         }
 
-        let msg = mqtt::Message::new(topic.clone(), payload, mqtt::QOS_1);
+        let payload = if self.timestamped_payloads.load(Ordering::SeqCst) {
+            PayloadEnvelope::wrap(payload)?
+        } else {
+            payload
+        };
+
+        let compression = self.compressed_topics.lock().get(&topic).copied();
+        let mut properties = mqtt::Properties::new();
+        let payload = match compression {
+            Some(compression) => {
+                properties.push_string_pair(
+                    mqtt::PropertyCode::UserProperty,
+                    COMPRESSION_PROPERTY,
+                    compression.as_str(),
+                )?;
+                compression::compress(compression, payload.as_bytes())?
+            }
+            None => payload.into_bytes(),
+        };
+
+        let retained = self.retained_topics.lock().contains(&topic);
+        let mut msg_builder = mqtt::MessageBuilder::new()
+            .topic(topic.clone())
+            .payload(payload)
+            .qos(mqtt::QOS_1)
+            .retained(retained);
+
+        if let Some(sequence) = self.sequences.next(&topic) {
+            properties.push_string_pair(
+                mqtt::PropertyCode::UserProperty,
+                EPOCH_PROPERTY,
+                &sequence.epoch.to_string(),
+            )?;
+            properties.push_string_pair(
+                mqtt::PropertyCode::UserProperty,
+                SEQUENCE_PROPERTY,
+                &sequence.number.to_string(),
+            )?;
+        }
+
+        if let Some(epoch) = self.lifecycle_epochs.lock().get(&topic) {
+            properties.push_string_pair(
+                mqtt::PropertyCode::UserProperty,
+                LIFECYCLE_EPOCH_PROPERTY,
+                &epoch.to_string(),
+            )?;
+        }
+
+        if !properties.is_empty() {
+            msg_builder = msg_builder.properties(properties);
+        }
+
+        let msg = msg_builder.finalize();
 
         self.client.publish(msg).await?;
 
@@ -138,7 +448,7 @@ impl PubSubConnectorClient for MqttFiveClientConnector {
             .await
             .map_err(|e| Box::new(std::io::Error::new(ErrorKind::Other, e.to_string())))?;
 
-        let mut sub_lock = self.subscriptions.lock().unwrap();
+        let mut sub_lock = self.subscriptions.lock();
         let (sender, receiver) = mpsc::channel::<PubSubMessage>();
 
         sub_lock.insert(topic.clone(), sender);
@@ -150,7 +460,10 @@ impl PubSubConnectorClient for MqttFiveClientConnector {
         &self,
         topic: String,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        self.client.unsubscribe(topic).await?;
+        self.client.unsubscribe(topic.clone()).await?;
+
+        // Dropping the sender ends the stream returned when subscribing to the topic.
+        self.subscriptions.lock().remove(&topic);
 
         Ok(())
     }
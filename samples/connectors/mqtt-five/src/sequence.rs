@@ -0,0 +1,159 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Per-topic sequence numbers, used by subscribers to detect dropped messages.
+//!
+//! Publishers of topics created with sequence numbers stamp each message with the sequence epoch
+//! the Pub Sub Service assigned to the topic and a sequence number that starts at 1 and increases
+//! with each message. The Pub Sub Service assigns a greater epoch each time a topic is created, so
+//! a subscriber seeing a new epoch knows that the sequence restarted rather than that messages
+//! were dropped.
+
+use std::collections::HashMap;
+
+use common::shared_state::SharedState;
+
+/// Name of the MQTT v5 user property carrying the sequence number of a message.
+pub const SEQUENCE_PROPERTY: &str = "agemo-seq";
+
+/// Name of the MQTT v5 user property carrying the sequence epoch of a message.
+pub const EPOCH_PROPERTY: &str = "agemo-seq-epoch";
+
+/// The sequence number of a message.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SequenceNumber {
+    /// The sequence epoch of the topic, assigned by the Pub Sub Service.
+    pub epoch: u64,
+    /// The position of the message in the epoch, starting at 1.
+    pub number: u64,
+}
+
+impl SequenceNumber {
+    /// Parses a sequence number from the values of its user properties, if both are valid.
+    ///
+    /// # Arguments
+    ///
+    /// * `epoch` - The value of the epoch property.
+    /// * `number` - The value of the sequence property.
+    pub fn parse(epoch: Option<&str>, number: Option<&str>) -> Option<Self> {
+        Some(SequenceNumber {
+            epoch: epoch?.parse().ok()?,
+            number: number?.parse().ok()?,
+        })
+    }
+}
+
+/// The sequence of a topic being published to.
+#[derive(Clone, Copy, Debug)]
+struct TopicSequence {
+    epoch: u64,
+    last: u64,
+}
+
+/// Stamps the messages published to topics with sequence numbers.
+///
+/// The sequence state lives with the publisher, so the Pub Sub Service stays out of the message
+/// path. Only topics that were given an epoch are stamped.
+#[derive(Debug, Default)]
+pub struct SequenceStamper {
+    sequences: SharedState<HashMap<String, TopicSequence>>,
+}
+
+impl SequenceStamper {
+    /// Sets the sequence epoch of a topic. The sequence of the topic restarts if the epoch is
+    /// new, such as when the topic was re-created, and carries on otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic to stamp the messages of.
+    /// * `epoch` - The sequence epoch the Pub Sub Service assigned to the topic.
+    pub fn set_epoch(&self, topic: &str, epoch: u64) {
+        let mut sequences = self.sequences.lock();
+
+        if sequences.get(topic).map(|sequence| sequence.epoch) != Some(epoch) {
+            sequences.insert(topic.to_string(), TopicSequence { epoch, last: 0 });
+        }
+    }
+
+    /// Stops stamping the messages of a topic, such as once it was deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic to stop stamping the messages of.
+    pub fn remove(&self, topic: &str) {
+        self.sequences.lock().remove(topic);
+    }
+
+    /// Returns the sequence number of the next message published to a topic, or `None` if the
+    /// topic has no epoch.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic the message is published to.
+    pub fn next(&self, topic: &str) -> Option<SequenceNumber> {
+        let mut sequences = self.sequences.lock();
+        let sequence = sequences.get_mut(topic)?;
+        sequence.last += 1;
+
+        Some(SequenceNumber {
+            epoch: sequence.epoch,
+            number: sequence.last,
+        })
+    }
+}
+
+/// Outcome of checking the sequence number of a received message.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SequenceCheck {
+    /// The message is the first one received, or the next one in the sequence.
+    InOrder,
+    /// Messages were missed before this message.
+    Gap {
+        /// The number of missed messages.
+        missed: u64,
+    },
+    /// The message is not newer than a message already received, such as a redelivered message.
+    Stale,
+    /// The topic was re-created with a new epoch, restarting the sequence with this message.
+    Restarted,
+}
+
+/// Detects gaps in the sequence numbers of the messages received on a topic.
+#[derive(Clone, Debug, Default)]
+pub struct GapDetector {
+    last: Option<SequenceNumber>,
+}
+
+impl GapDetector {
+    /// Checks the sequence number of a received message against the messages received before.
+    ///
+    /// # Arguments
+    ///
+    /// * `sequence` - The sequence number of the received message.
+    pub fn check(&mut self, sequence: SequenceNumber) -> SequenceCheck {
+        let Some(last) = self.last else {
+            self.last = Some(sequence);
+            return SequenceCheck::InOrder;
+        };
+
+        let check = if sequence.epoch > last.epoch {
+            // Messages at the start of the new epoch may have been missed too.
+            match sequence.number.saturating_sub(1) {
+                0 => SequenceCheck::Restarted,
+                missed => SequenceCheck::Gap { missed },
+            }
+        } else if sequence.epoch < last.epoch || sequence.number <= last.number {
+            return SequenceCheck::Stale;
+        } else if sequence.number == last.number + 1 {
+            SequenceCheck::InOrder
+        } else {
+            SequenceCheck::Gap {
+                missed: sequence.number - last.number - 1,
+            }
+        };
+
+        self.last = Some(sequence);
+        check
+    }
+}
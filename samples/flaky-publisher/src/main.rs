@@ -0,0 +1,143 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Flaky publisher example that deliberately restarts, changes ports and crashes, serving as
+//! living documentation of how the Pub Sub Service and subscribers react to a failing publisher.
+//!
+//! The publisher goes through the configured failures in order, one per run. Each run resumes the
+//! topic of the previous run by creating its topic with the same publisher id and idempotency key,
+//! which returns the previous topic unless the Pub Sub Service deleted it. After the last run, the
+//! publisher deletes its topic and exits.
+
+use std::{net::SocketAddr, time::Duration};
+
+use common::protocol_kind::ProtocolKind;
+use env_logger::{Builder, Target};
+use log::{info, warn, LevelFilter};
+use publisher_impl::FlakyPublisherImpl;
+use samples_common::{
+    data_generator::GeneratorRegistry,
+    load_config::{
+        load_data_generator_configs, load_flaky_publisher_settings, load_publish_options,
+        load_settings, CommunicationConstants, PublisherFailure, SimplePublisherServiceSettings,
+        CONFIG_FILE, CONSTANTS_FILE,
+    },
+    pub_sub_service_helper, publisher_helper,
+};
+use samples_proto::publisher::v1::publisher_callback_server::PublisherCallbackServer;
+use samples_proto::sample_publisher::v1::sample_publisher_server::SamplePublisherServer;
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+use tonic::transport::Server;
+use uuid::Uuid;
+
+mod publisher_impl;
+
+/// The subject the flaky publisher publishes data for.
+const SUBJECT: &str = "flaky";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Setup logging.
+    Builder::new()
+        .filter(None, LevelFilter::Info)
+        .target(Target::Stdout)
+        .init();
+
+    // Load in settings for service.
+    let settings = load_settings::<SimplePublisherServiceSettings>(CONFIG_FILE)?;
+    let communication_consts = load_settings::<CommunicationConstants>(CONSTANTS_FILE)?;
+    let flaky_settings = load_flaky_publisher_settings();
+    let generators = GeneratorRegistry::from_config(load_data_generator_configs())
+        .with_publish_options(load_publish_options());
+
+    // The publisher id and idempotency key outlive every run, as a publisher would persist them
+    // to resume its topic after a restart.
+    let publisher_id = format!("pub_{}", Uuid::new_v4());
+    let idempotency_key = Uuid::new_v4().to_string();
+    let mut addr: SocketAddr = settings.publisher_authority.parse()?;
+
+    // A last run without failure follows the configured failures.
+    let failures = flaky_settings.failures.into_iter().map(Some).chain([None]);
+
+    for (run, failure) in (1..).zip(failures) {
+        let subscription_info = pub_sub_service_helper::create_topic_with_idempotency_key(
+            settings.pub_sub_uri.clone(),
+            publisher_id.clone(),
+            addr.to_string(),
+            ProtocolKind::Grpc,
+            idempotency_key.clone(),
+            generators.payload_compression(SUBJECT),
+        )
+        .await?;
+        let generated_topic =
+            pub_sub_service_helper::get_topic_from_subscription_response(&subscription_info);
+        info!("Run {run} on '{addr}' is publishing to topic '{generated_topic}'.");
+
+        // Grpc server for handling calls from the pub sub service and subscribers.
+        let publisher = FlakyPublisherImpl {
+            run,
+            generated_topic: generated_topic.clone(),
+            subscription_info: subscription_info.clone(),
+        };
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel::<()>();
+        let server = tokio::spawn(
+            Server::builder()
+                .add_service(PublisherCallbackServer::new(publisher.clone()))
+                .add_service(SamplePublisherServer::new(publisher))
+                .serve_with_shutdown(addr, async {
+                    let _ = shutdown_receiver.await;
+                }),
+        );
+
+        let stop_token = CancellationToken::new();
+        let publish_loop = publisher_helper::handle_publish_loop(
+            generated_topic.clone(),
+            SUBJECT.to_string(),
+            stop_token.clone(),
+            publisher_id.clone(),
+            subscription_info,
+            communication_consts.disconnect_topic.clone(),
+            generators.create(SUBJECT),
+        );
+
+        tokio::time::sleep(Duration::from_secs(flaky_settings.run_secs)).await;
+
+        match failure {
+            Some(PublisherFailure::Crash) => {
+                // Neither the broker connection nor the server shut down cleanly, so the broker
+                // publishes the publisher's last will and the Pub Sub Service deletes its topic.
+                warn!("Crashing run {run}.");
+                publish_loop.abort();
+                server.abort();
+            }
+            Some(failure) => {
+                // Disconnecting cleanly keeps the broker from publishing the last will, so the
+                // topic outlives the run.
+                info!("Stopping run {run} for a {failure:?}.");
+                stop_token.cancel();
+                publish_loop.await?;
+                let _ = shutdown_sender.send(());
+                server.await??;
+
+                if failure == PublisherFailure::PortChange {
+                    addr.set_port(addr.port() + 1);
+                }
+            }
+            None => {
+                info!("Deleting topic '{generated_topic}' and exiting.");
+                stop_token.cancel();
+                publish_loop.await?;
+                pub_sub_service_helper::delete_topic(settings.pub_sub_uri, generated_topic).await?;
+                let _ = shutdown_sender.send(());
+                server.await??;
+                break;
+            }
+        }
+
+        tokio::time::sleep(Duration::from_secs(flaky_settings.restart_delay_secs)).await;
+    }
+
+    Ok(())
+}
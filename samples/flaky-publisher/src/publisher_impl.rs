@@ -0,0 +1,85 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Implements the server side of the [publisher.proto](proto::publisher) and
+//! [sample_publisher.proto](proto::sample_publisher) interfaces for a single run of the flaky
+//! publisher.
+//!
+//! The flaky publisher publishes for the whole run regardless of the actions sent by the Pub Sub
+//! Service, so that the effects of its failures are visible to subscribers. The actions are only
+//! logged, showing which callbacks reach the publisher across restarts.
+
+use log::{info, warn};
+use samples_common::pub_sub_service_helper::TopicAction;
+use samples_proto::{
+    publisher::v1::{
+        publisher_callback_server::PublisherCallback, ManageTopicRequest, ManageTopicResponse,
+    },
+    sample_publisher::v1::{
+        sample_publisher_server::SamplePublisher, SubscriptionInfoRequest, SubscriptionInfoResponse,
+    },
+};
+use tonic::{Request, Response, Status};
+
+/// Base structure for the gRPC service of a run of the flaky publisher.
+#[derive(Clone, Debug)]
+pub struct FlakyPublisherImpl {
+    /// The number of the run, starting at 1.
+    pub run: u32,
+    /// The generated topic the run publishes to.
+    pub generated_topic: String,
+    /// The info used to subscribe to the generated topic.
+    pub subscription_info: SubscriptionInfoResponse,
+}
+
+#[tonic::async_trait]
+impl PublisherCallback for FlakyPublisherImpl {
+    /// Logs the topic management actions sent by the Pub Sub Service.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - Contains a topic and relevant update information.
+    async fn manage_topic_callback(
+        &self,
+        request: Request<ManageTopicRequest>,
+    ) -> Result<Response<ManageTopicResponse>, Status> {
+        let manage_req = request.into_inner();
+        let action = TopicAction::parse(&manage_req.action)
+            .map_err(|e| Status::not_found(format!("no valid action was found: {e}")))?;
+
+        if manage_req.topic == self.generated_topic {
+            info!(
+                "Run {} received action '{action}' for topic '{}'.",
+                self.run, manage_req.topic
+            );
+        } else {
+            warn!(
+                "Run {} received action '{action}' for topic '{}' of a previous run.",
+                self.run, manage_req.topic
+            );
+        }
+
+        Ok(Response::new(ManageTopicResponse {}))
+    }
+}
+
+#[tonic::async_trait]
+impl SamplePublisher for FlakyPublisherImpl {
+    /// Provides the subscription information of the run's topic, whatever the requested subject.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the requested subject to get subscription information about.
+    async fn get_subscription_info(
+        &self,
+        request: Request<SubscriptionInfoRequest>,
+    ) -> Result<Response<SubscriptionInfoResponse>, Status> {
+        let subject = request.into_inner().subject;
+        info!(
+            "Run {} got request for subscription info on subject '{subject}'.",
+            self.run
+        );
+
+        Ok(Response::new(self.subscription_info.clone()))
+    }
+}
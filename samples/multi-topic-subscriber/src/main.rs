@@ -0,0 +1,194 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Multi-topic subscriber example showing how to subscribe to several subjects concurrently over a
+//! single connection to the messaging broker. Each topic gets its own stream, and a topic deleted
+//! by the publisher only ends its own stream.
+
+use std::{
+    env,
+    sync::{mpsc::Receiver, Arc},
+};
+
+use common::uri;
+use env_logger::{Builder, Target};
+use log::{info, warn, LevelFilter};
+use sample_mqtt_connector::client_connector::PubSubMessage;
+use samples_common::{
+    load_config::{
+        load_settings, CommunicationConstants, SimpleSubscriberServiceSettings, CONFIG_FILE,
+        CONSTANTS_FILE,
+    },
+    subscriber_helper::{self, BrokerRef, TopicsRef, SHUTDOWN},
+};
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
+use uuid::Uuid;
+
+/// Prints the messages received on a topic until the topic is deleted by the publisher or the
+/// stream is closed, then stops tracking the topic.
+///
+/// # Arguments
+///
+/// * `subject` - The subject that the topic provides data for.
+/// * `topic` - The topic the stream receives messages on.
+/// * `stream` - The stream of messages received on the topic.
+/// * `topic_deletion_message` - The message the publisher sends when it deletes the topic.
+/// * `broker_handle` - The shared reference handle of the broker.
+/// * `topics_handle` - The shared reference handle of the subscribed topics.
+async fn handle_topic_stream(
+    subject: String,
+    topic: String,
+    stream: Receiver<PubSubMessage>,
+    topic_deletion_message: String,
+    broker_handle: Arc<Mutex<BrokerRef>>,
+    topics_handle: Arc<Mutex<TopicsRef>>,
+) {
+    // Receiving from the stream blocks, so it is read on a blocking thread.
+    let stream_subject = subject.clone();
+    let deleted = tokio::task::spawn_blocking(move || {
+        for msg in stream.into_iter() {
+            info!("({stream_subject}) {}: {}", msg.topic, msg.payload);
+
+            if msg.payload == topic_deletion_message {
+                return true;
+            }
+        }
+
+        false
+    })
+    .await
+    .unwrap_or_default();
+
+    topics_handle.lock().await.topics.remove(&subject);
+
+    // Only the deleted topic is unsubscribed from, the other topics keep streaming.
+    if deleted {
+        info!("Topic '{topic}' for subject '{subject}' was deleted by the publisher.");
+
+        if let Err(err) = subscriber_helper::unsubscribe_from_topic(&topic, broker_handle).await {
+            warn!("Unable to unsubscribe from '{topic}': {err}");
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Setup logging.
+    Builder::new()
+        .filter(None, LevelFilter::Info)
+        .target(Target::Stdout)
+        .init();
+
+    // Load in settings for service.
+    let settings = load_settings::<SimpleSubscriberServiceSettings>(CONFIG_FILE)?;
+    let communication_consts = load_settings::<CommunicationConstants>(CONSTANTS_FILE)?;
+
+    // Instantiate shared broker and shared topics references.
+    let broker_handle: Arc<Mutex<BrokerRef>> = Arc::new(Mutex::new(BrokerRef { client: None }));
+    let topics_handle: Arc<Mutex<TopicsRef>> = Arc::new(Mutex::new(TopicsRef::default()));
+
+    // Setup shutdown watcher to smoothly shutdown service.
+    let shutdown_sender = subscriber_helper::handle_multi_topic_ctrlc_shutdown(
+        broker_handle.clone(),
+        topics_handle.clone(),
+    )
+    .await;
+
+    // Subjects to get data on. Each subject is only subscribed to once.
+    let mut subjects: Vec<String> = env::args().skip(1).collect();
+    if subjects.is_empty() {
+        subjects.push("test_topic".to_string());
+    }
+    subjects.sort();
+    subjects.dedup();
+
+    // Convert the publisher authority from the configuration settings to a uri.
+    let publisher_uri = uri::authority_uri(&settings.publisher_authority);
+
+    // Get subscription information from the publisher for each requested subject. All topics are
+    // subscribed to over one connection, so subjects offered on another broker are skipped.
+    let mut broker_uri: Option<String> = None;
+    let mut subscriptions = Vec::new();
+    for subject in subjects {
+        let info = match subscriber_helper::get_subscription_info(
+            &publisher_uri,
+            &subject,
+            &communication_consts.mqtt_v5_kind,
+        )
+        .await
+        {
+            Ok(info) => info,
+            Err(err) => {
+                warn!("Skipping subject '{subject}': {err}");
+                continue;
+            }
+        };
+
+        match &broker_uri {
+            Some(uri) if *uri != info.uri => {
+                warn!(
+                    "Skipping subject '{subject}': offered on broker '{}' instead of '{uri}'.",
+                    info.uri
+                );
+                continue;
+            }
+            Some(_) => {}
+            None => broker_uri = Some(info.uri),
+        }
+
+        subscriptions.push((subject, info.topic));
+    }
+
+    let broker_uri = broker_uri.ok_or("None of the requested subjects are available")?;
+
+    // Set up the shared connection to the broker (MQTT v5 in this case).
+    let id = format!("sub_{}", Uuid::new_v4());
+    subscriber_helper::connect_to_broker(
+        id,
+        broker_uri,
+        communication_consts.disconnect_topic.clone(),
+        broker_handle.clone(),
+    )
+    .await?;
+
+    // Handle the stream of each topic concurrently.
+    let mut streams = JoinSet::new();
+    for (subject, topic) in subscriptions {
+        let stream =
+            match subscriber_helper::subscribe_to_topic(&topic, broker_handle.clone()).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!("Unable to subscribe to '{topic}' for subject '{subject}': {err}");
+                    continue;
+                }
+            };
+
+        topics_handle
+            .lock()
+            .await
+            .topics
+            .insert(subject.clone(), topic.clone());
+
+        streams.spawn(handle_topic_stream(
+            subject,
+            topic,
+            stream,
+            communication_consts.topic_deletion_message.clone(),
+            broker_handle.clone(),
+            topics_handle.clone(),
+        ));
+    }
+
+    while streams.join_next().await.is_some() {}
+
+    // Once every topic is closed, disconnect and end the program.
+    info!("All topics are closed.");
+    let _ = shutdown_sender.send(SHUTDOWN.to_string());
+
+    // The shutdown task exits the program once disconnected.
+    std::future::pending::<()>().await;
+
+    Ok(())
+}
@@ -7,9 +7,9 @@ use std::{error::Error, path::Path};
 use tonic_build::configure;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("../../proto/pubsub/v1/pubsub.proto")?;
+    compile_pubsub_proto()?;
     tonic_build::compile_protos("../../proto/publisher/v1/publisher.proto")?;
-    tonic_build::compile_protos("../proto/sample_grpc/v1/sample_publisher.proto")?;
+    compile_sample_publisher_proto()?;
     compile_external_protos(
         "../../external/chariott/service_discovery/proto",
         "../../external/chariott/service_discovery/proto/core/v1/service_registry.proto",
@@ -18,6 +18,37 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// The pub sub proto imports the HTTP annotations used for gRPC-JSON transcoding from the third
+/// party protos.
+fn compile_pubsub_proto() -> Result<(), Box<dyn Error>> {
+    configure().compile(
+        &[Path::new("../../proto/pubsub/v1/pubsub.proto")],
+        &[
+            Path::new("../../proto/pubsub/v1"),
+            Path::new("../../proto/third_party"),
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// The sample publisher proto imports the pubsub proto for shared types, which are already
+/// generated in the `pubsub::v1` module.
+fn compile_sample_publisher_proto() -> Result<(), Box<dyn Error>> {
+    configure()
+        .extern_path(".pubsub", "crate::pubsub::v1")
+        .compile(
+            &[Path::new("../proto/sample_grpc/v1/sample_publisher.proto")],
+            &[
+                Path::new("../proto/sample_grpc/v1"),
+                Path::new("../../proto/pubsub/v1"),
+                Path::new("../../proto/third_party"),
+            ],
+        )?;
+
+    Ok(())
+}
+
 fn compile_external_protos(folder_path: &str, file_path: &str) -> Result<(), Box<dyn Error>> {
     configure().compile(&[Path::new(file_path)], &[Path::new(folder_path)])?;
 
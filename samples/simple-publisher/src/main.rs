@@ -9,7 +9,9 @@ use env_logger::{Builder, Target};
 use log::LevelFilter;
 use publisher_impl::PublisherImpl;
 use samples_common::{
+    data_generator::GeneratorRegistry,
     load_config::{
+        load_data_generator_configs, load_publish_options, load_reserved_name_policy,
         load_settings, CommunicationConstants, SimplePublisherServiceSettings, CONFIG_FILE,
         CONSTANTS_FILE,
     },
@@ -40,6 +42,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         settings.pub_sub_uri,
         communication_consts.grpc_kind,
     );
+    let publisher = publisher
+        .with_disconnect_topic(communication_consts.disconnect_topic)
+        .with_data_generators(
+            GeneratorRegistry::from_config(load_data_generator_configs())
+                .with_publish_options(load_publish_options()),
+        )
+        .with_name_policy(load_reserved_name_policy()?);
 
     // Grpc server for handling calls from clients.
     Server::builder()
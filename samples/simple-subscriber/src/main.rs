@@ -7,16 +7,18 @@
 
 use std::{env, sync::Arc};
 
-use async_std::sync::Mutex;
+use common::uri;
 use env_logger::{Builder, Target};
-use log::{info, LevelFilter};
+use log::{info, warn, LevelFilter};
 use samples_common::{
     load_config::{
-        load_settings, CommunicationConstants, SimpleSubscriberServiceSettings, CONFIG_FILE,
-        CONSTANTS_FILE,
+        load_recording_file, load_resubscribe_settings, load_settings, CommunicationConstants,
+        SimpleSubscriberServiceSettings, CONFIG_FILE, CONSTANTS_FILE,
     },
+    recorder::MessageRecorder,
     subscriber_helper::{self, BrokerRef, TopicRef, EMPTY_TOPIC, SHUTDOWN},
 };
+use tokio::sync::Mutex;
 use uuid::Uuid;
 
 #[tokio::main]
@@ -46,8 +48,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let subject = env::args().nth(1).unwrap_or(default_subject);
 
     // Convert the publisher authority from the configuration settings to a uri.
-    let publisher_authority = settings.publisher_authority;
-    let publisher_uri = format!("http://{publisher_authority}"); // Devskim: ignore DS137138
+    let publisher_uri = uri::authority_uri(&settings.publisher_authority);
 
     // Get subscription information from the publisher for the requested subject.
     let info = subscriber_helper::get_subscription_info(
@@ -63,26 +64,83 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
     // Set up the topic stream to receive messages on from the broker (MQTT v5 in this case).
     let id = format!("sub_{}", Uuid::new_v4());
-    let stream = subscriber_helper::get_subscription_stream(
+    let mut stream = subscriber_helper::get_subscription_stream(
         id,
         info.uri,
+        communication_consts.disconnect_topic.clone(),
         topic_handle.clone(),
-        broker_handle,
+        broker_handle.clone(),
     )
     .await?;
 
+    // Record the received messages to a file if a recording file is configured.
+    let mut recorder = match load_recording_file() {
+        Some(path) => {
+            info!("Recording received messages to '{path}'.");
+            Some(MessageRecorder::new(&path)?)
+        }
+        None => None,
+    };
+
+    // Whether to ask the publisher for a new topic when the topic is deleted.
+    let resubscribe_settings = load_resubscribe_settings();
+
+    // The lifecycle epoch of the topic, which changes when the publisher is restarted.
+    let mut lifecycle_epoch = None;
+
     // Print out the messages received by the subscription.
     // This loop will not break unless the stream is broken by the client.
-    for msg in stream.into_iter() {
-        // Record the message received on the stream.
-        info!("({subject}) {}: {}", msg.topic, msg.payload);
-
-        // If deletion message is sent over the subscription then end the program.
-        if msg.payload == communication_consts.topic_deletion_message {
-            let mut topic = topic_handle.lock().await;
-            topic.topic = EMPTY_TOPIC.to_string();
-            let _ = shutdown_sender.send(SHUTDOWN.to_string());
+    loop {
+        let mut topic_deleted = false;
+
+        for msg in stream.iter() {
+            if let Some(epoch) = msg.lifecycle_epoch {
+                if lifecycle_epoch.is_some_and(|last| last != epoch) {
+                    info!(
+                        "The publisher of '{}' was restarted, now in lifecycle epoch {epoch}.",
+                        msg.topic
+                    );
+                }
+                lifecycle_epoch = Some(epoch);
+            }
+
+            // Record the message received on the stream.
+            info!("({subject}) {}: {}", msg.topic, msg.payload);
+
+            if let Some(recorder) = recorder.as_mut() {
+                if let Err(err) = recorder.record(&subject, &msg) {
+                    warn!("Unable to record message: {err}");
+                }
+            }
+
+            // If deletion message is sent over the subscription then either resubscribe or end the
+            // program.
+            if msg.payload == communication_consts.topic_deletion_message {
+                if resubscribe_settings.enabled {
+                    topic_deleted = true;
+                    break;
+                }
+
+                let mut topic = topic_handle.lock().await;
+                topic.topic = EMPTY_TOPIC.to_string();
+                let _ = shutdown_sender.send(SHUTDOWN.to_string());
+            }
         }
+
+        if !topic_deleted {
+            break;
+        }
+
+        stream = subscriber_helper::resubscribe(
+            &publisher_uri,
+            &subject,
+            &communication_consts.mqtt_v5_kind,
+            communication_consts.disconnect_topic.clone(),
+            &resubscribe_settings,
+            topic_handle.clone(),
+            broker_handle.clone(),
+        )
+        .await?;
     }
 
     Ok(())
@@ -0,0 +1,219 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Access to the `AgemoTopic` custom resources through `kubectl`.
+//!
+//! The controller shells out to `kubectl`, so it uses whatever cluster and credentials the current
+//! kubeconfig context points to.
+
+use std::process::Command;
+
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+
+/// The resource type of the topic custom resources.
+const RESOURCE: &str = "agemotopics.agemo.eclipse.org";
+
+/// The finalizer that keeps a topic resource from being removed until its topic is deleted.
+pub const FINALIZER: &str = "agemo.eclipse.org/topic";
+
+/// The metadata of a topic resource.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceMetadata {
+    /// The name of the resource.
+    pub name: String,
+    /// The namespace of the resource.
+    pub namespace: String,
+    /// The unique id of the resource.
+    pub uid: String,
+    /// The finalizers of the resource.
+    #[serde(default)]
+    pub finalizers: Vec<String>,
+    /// When the deletion of the resource was requested, if it was.
+    #[serde(default)]
+    pub deletion_timestamp: Option<String>,
+}
+
+/// The desired topic described by a topic resource.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopicSpec {
+    /// The id of the publisher the topic is created for.
+    pub publisher_id: String,
+    /// The authority the publisher handles management callbacks on.
+    pub management_authority: String,
+}
+
+/// The observed state of a topic resource, written by the controller.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopicStatus {
+    /// The topic generated by the Pub Sub Service.
+    #[serde(default)]
+    pub generated_topic: Option<String>,
+    /// The uri of the messaging broker the topic is on.
+    #[serde(default)]
+    pub broker_uri: Option<String>,
+}
+
+/// A topic custom resource.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TopicResource {
+    /// The metadata of the resource.
+    pub metadata: ResourceMetadata,
+    /// The desired topic.
+    pub spec: TopicSpec,
+    /// The observed state of the resource.
+    #[serde(default)]
+    pub status: Option<TopicStatus>,
+}
+
+impl TopicResource {
+    /// Returns the topic the controller created for the resource, if it did.
+    pub fn generated_topic(&self) -> Option<&str> {
+        self.status
+            .as_ref()
+            .and_then(|status| status.generated_topic.as_deref())
+    }
+
+    /// Returns whether the deletion of the resource was requested.
+    pub fn is_deleting(&self) -> bool {
+        self.metadata.deletion_timestamp.is_some()
+    }
+
+    /// Returns whether the resource has the controller's finalizer.
+    pub fn has_finalizer(&self) -> bool {
+        self.metadata.finalizers.iter().any(|f| f == FINALIZER)
+    }
+}
+
+/// A list of topic resources, as returned by `kubectl get -o json`.
+#[derive(Debug, Deserialize)]
+struct TopicResourceList {
+    items: Vec<TopicResource>,
+}
+
+/// Runs `kubectl` with the given arguments and returns its standard output.
+///
+/// # Arguments
+///
+/// * `args` - The arguments passed to `kubectl`.
+fn kubectl(args: &[&str]) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    let output = Command::new("kubectl").args(args).output()?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "kubectl {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    Ok(output.stdout)
+}
+
+/// Lists the topic resources.
+///
+/// # Arguments
+///
+/// * `namespace` - The namespace of the resources, or every namespace if `None`.
+pub fn list_topics(
+    namespace: Option<&str>,
+) -> Result<Vec<TopicResource>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut args = vec!["get", RESOURCE, "-o", "json"];
+    match namespace {
+        Some(namespace) => args.extend(["-n", namespace]),
+        None => args.push("--all-namespaces"),
+    }
+
+    let list: TopicResourceList = serde_json::from_slice(&kubectl(&args)?)?;
+
+    Ok(list.items)
+}
+
+/// Applies a JSON merge patch to a topic resource.
+///
+/// # Arguments
+///
+/// * `resource` - The resource to patch.
+/// * `patch` - The merge patch.
+/// * `subresource` - The subresource to patch, such as `status`.
+fn patch(
+    resource: &TopicResource,
+    patch: serde_json::Value,
+    subresource: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let patch = patch.to_string();
+    let subresource = subresource.map(|subresource| format!("--subresource={subresource}"));
+
+    let mut args = vec![
+        "patch",
+        RESOURCE,
+        resource.metadata.name.as_str(),
+        "-n",
+        resource.metadata.namespace.as_str(),
+        "--type=merge",
+        "-p",
+        patch.as_str(),
+    ];
+    args.extend(subresource.as_deref());
+
+    kubectl(&args).map(|_| ())
+}
+
+/// Adds the controller's finalizer to a topic resource, so that the resource is only removed once
+/// the controller deleted its topic.
+///
+/// # Arguments
+///
+/// * `resource` - The resource to add the finalizer to.
+pub fn add_finalizer(
+    resource: &TopicResource,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut finalizers = resource.metadata.finalizers.clone();
+    finalizers.push(FINALIZER.to_string());
+
+    patch(
+        resource,
+        json!({ "metadata": { "finalizers": finalizers } }),
+        None,
+    )
+}
+
+/// Removes the controller's finalizer from a topic resource, letting Kubernetes remove it.
+///
+/// # Arguments
+///
+/// * `resource` - The resource to remove the finalizer from.
+pub fn remove_finalizer(
+    resource: &TopicResource,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let finalizers: Vec<&String> = resource
+        .metadata
+        .finalizers
+        .iter()
+        .filter(|f| *f != FINALIZER)
+        .collect();
+
+    patch(
+        resource,
+        json!({ "metadata": { "finalizers": finalizers } }),
+        None,
+    )
+}
+
+/// Writes the status of a topic resource.
+///
+/// # Arguments
+///
+/// * `resource` - The resource to write the status of.
+/// * `status` - The status of the resource.
+pub fn set_status(
+    resource: &TopicResource,
+    status: &TopicStatus,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    patch(resource, json!({ "status": status }), Some("status"))
+}
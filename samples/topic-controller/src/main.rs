@@ -0,0 +1,128 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Topic controller example that reconciles `AgemoTopic` Kubernetes custom resources against the
+//! Pub Sub Service, demonstrating declarative topic management for cloud-side deployments.
+//!
+//! Each resource describes a topic to create on behalf of a publisher. The controller creates the
+//! topic of a new resource and records it in the resource's status, and deletes the topic once the
+//! resource is deleted. A finalizer keeps a deleted resource around until its topic is deleted, so
+//! that no topic is leaked while the controller is down.
+
+use std::time::Duration;
+
+use common::{payload_compression::PayloadCompression, protocol_kind::ProtocolKind};
+use env_logger::{Builder, Target};
+use kubectl::{TopicResource, TopicStatus};
+use log::{error, info, LevelFilter};
+use samples_common::{
+    load_config::{
+        load_settings, load_topic_controller_settings, SimplePublisherServiceSettings, CONFIG_FILE,
+    },
+    pub_sub_service_helper,
+};
+
+mod kubectl;
+
+/// Reconciles a topic resource with the Pub Sub Service.
+///
+/// # Arguments
+///
+/// * `pub_sub_uri` - URI of the Pub Sub Service.
+/// * `resource` - The topic resource to reconcile.
+async fn reconcile(
+    pub_sub_uri: &str,
+    resource: &TopicResource,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let name = format!("{}/{}", resource.metadata.namespace, resource.metadata.name);
+
+    if resource.is_deleting() {
+        if !resource.has_finalizer() {
+            return Ok(());
+        }
+
+        if let Some(topic) = resource.generated_topic() {
+            info!("Deleting topic '{topic}' of '{name}'.");
+            pub_sub_service_helper::delete_topic(pub_sub_uri.to_string(), topic.to_string())
+                .await?;
+        }
+
+        return kubectl::remove_finalizer(resource);
+    }
+
+    if !resource.has_finalizer() {
+        kubectl::add_finalizer(resource)?;
+    }
+
+    if resource.generated_topic().is_some() {
+        return Ok(());
+    }
+
+    // The uid of the resource is used as the idempotency key, so that a retry after a failed
+    // status update returns the same topic.
+    let subscription_info = pub_sub_service_helper::create_topic_with_idempotency_key(
+        pub_sub_uri.to_string(),
+        resource.spec.publisher_id.clone(),
+        resource.spec.management_authority.clone(),
+        ProtocolKind::Grpc,
+        resource.metadata.uid.clone(),
+        PayloadCompression::None,
+    )
+    .await?;
+    let generated_topic =
+        pub_sub_service_helper::get_topic_from_subscription_response(&subscription_info);
+    info!(
+        "Created topic '{generated_topic}' of '{name}' for '{}'.",
+        resource.spec.publisher_id
+    );
+
+    kubectl::set_status(
+        resource,
+        &TopicStatus {
+            generated_topic: Some(generated_topic),
+            broker_uri: Some(subscription_info.subscription_uri),
+        },
+    )
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Setup logging.
+    Builder::new()
+        .filter(None, LevelFilter::Info)
+        .target(Target::Stdout)
+        .init();
+
+    // Load in settings for service.
+    let settings = load_settings::<SimplePublisherServiceSettings>(CONFIG_FILE)?;
+    let controller_settings = load_topic_controller_settings();
+    let interval = Duration::from_secs(controller_settings.reconcile_interval_secs);
+
+    info!(
+        "Reconciling topic resources in {} every {interval:?}.",
+        controller_settings
+            .namespace
+            .as_deref()
+            .unwrap_or("every namespace")
+    );
+
+    loop {
+        match kubectl::list_topics(controller_settings.namespace.as_deref()) {
+            Ok(resources) => {
+                // A resource that fails to reconcile is retried on the next pass.
+                for resource in &resources {
+                    if let Err(err) = reconcile(&settings.pub_sub_uri, resource).await {
+                        error!(
+                            "Unable to reconcile '{}/{}': {err}",
+                            resource.metadata.namespace, resource.metadata.name
+                        );
+                    }
+                }
+            }
+            Err(err) => error!("Unable to list topic resources: {err}"),
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
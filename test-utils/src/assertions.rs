@@ -0,0 +1,96 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Assertion helpers comparing what a connector reported to the expected topic lifecycle.
+
+use crate::{
+    monitor_sequence::{MonitorEvent, MonitorSequence, CONNECTOR_ACTIONS},
+    publisher_callback::RecordedCallback,
+};
+
+/// Asserts that every recorded update uses an action that connectors may report.
+///
+/// # Arguments
+///
+/// * `events` - The recorded updates.
+pub fn assert_valid_actions(events: &[MonitorEvent]) {
+    for event in events {
+        assert!(
+            CONNECTOR_ACTIONS.contains(&event.action.as_str()),
+            "connector reported unknown action '{}' for '{}'",
+            event.action,
+            event.context
+        );
+    }
+}
+
+/// Asserts that the recorded updates match the updates of a sequence, in order.
+///
+/// # Arguments
+///
+/// * `events` - The recorded updates.
+/// * `sequence` - The expected sequence.
+pub fn assert_sequence(events: &[MonitorEvent], sequence: &MonitorSequence) {
+    assert_valid_actions(events);
+    assert_eq!(
+        sequence.events, events,
+        "connector reported unexpected updates"
+    );
+}
+
+/// Asserts that the callbacks received for a topic match the callbacks expected by a sequence,
+/// in order. Callbacks for other topics are ignored.
+///
+/// # Arguments
+///
+/// * `callbacks` - The received callbacks.
+/// * `topic` - The topic of the sequence.
+/// * `sequence` - The expected sequence.
+pub fn assert_callbacks(callbacks: &[RecordedCallback], topic: &str, sequence: &MonitorSequence) {
+    let actual: Vec<&str> = callbacks
+        .iter()
+        .filter(|callback| callback.topic == topic)
+        .map(|callback| callback.action.as_str())
+        .collect();
+
+    assert_eq!(
+        sequence.expected_callbacks, actual,
+        "publisher received unexpected callbacks for topic '{topic}'"
+    );
+}
+
+#[cfg(test)]
+mod assertions_tests {
+    use crate::monitor_sequence::{self, START_CALLBACK, STOP_CALLBACK};
+
+    use super::*;
+
+    #[test]
+    fn assert_sequence_test() {
+        let sequence = monitor_sequence::subscribe_then_unsubscribe("topic", "sub_1");
+        assert_sequence(&sequence.events, &sequence);
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown action 'SUBSCRIBED'")]
+    fn assert_valid_actions_test() {
+        assert_valid_actions(&[MonitorEvent::new("SUBSCRIBED", "topic", None)]);
+    }
+
+    #[test]
+    fn assert_callbacks_test() {
+        let sequence = monitor_sequence::subscribe_then_unsubscribe("topic", "sub_1");
+        let callbacks = [
+            ("topic", START_CALLBACK),
+            ("other", START_CALLBACK),
+            ("topic", STOP_CALLBACK),
+        ]
+        .map(|(topic, action)| RecordedCallback {
+            topic: topic.to_string(),
+            action: action.to_string(),
+        });
+
+        assert_callbacks(&callbacks, "topic", &sequence);
+    }
+}
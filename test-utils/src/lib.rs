@@ -0,0 +1,21 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Utilities for testing broker connectors against the Pub Sub Service topic lifecycle.
+//!
+//! A connector drives the lifecycle of dynamic topics by reporting the updates it observes on the
+//! messaging broker. These utilities let connector authors verify that their connector reports
+//! the right updates and that the service turns them into the expected publisher callbacks:
+//! - [`monitor_sequence`]: Canned sequences of monitor updates and the callbacks they cause.
+//! - [`monitor_recorder`]: Records the updates a connector library reports through the C ABI.
+//! - [`publisher_callback`]: A fake publisher that records the callbacks of the service.
+//! - [`assertions`]: Helpers comparing recorded updates and callbacks to the expected ones.
+
+// Tells cargo to warn if a doc comment is missing and should be provided.
+#![warn(missing_docs)]
+
+pub mod assertions;
+pub mod monitor_recorder;
+pub mod monitor_sequence;
+pub mod publisher_callback;
@@ -0,0 +1,165 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Records the updates that a connector library reports through the connector C ABI.
+//!
+//! Pass [`MonitorRecorder::callback`] and [`MonitorRecorder::ctx`] to the library's
+//! `agemo_connector_monitor` function, drive the broker, and compare the recorded updates to a
+//! [`MonitorSequence`][crate::monitor_sequence::MonitorSequence].
+
+use std::{
+    ffi::{c_char, c_void, CStr},
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::monitor_sequence::MonitorEvent;
+
+/// Records the updates reported by a connector library, in order.
+#[derive(Debug, Default)]
+pub struct MonitorRecorder {
+    events: Mutex<Vec<MonitorEvent>>,
+    updated: Condvar,
+}
+
+impl MonitorRecorder {
+    /// Creates a new MonitorRecorder without any recorded updates.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the context to pass to the connector library along with [`Self::callback`]. The
+    /// recorder must outlive the connector it is passed to.
+    pub fn ctx(&self) -> *mut c_void {
+        self as *const Self as *mut c_void
+    }
+
+    /// Callback matching `agemo_monitor_cb` of the connector C ABI, recording each update in the
+    /// recorder passed as the context.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context returned by [`Self::ctx`].
+    /// * `action` - The action that happened on the broker.
+    /// * `context` - The topic or client the update is for.
+    /// * `client_id` - The client that caused the update, or null if unknown.
+    pub extern "C" fn callback(
+        ctx: *mut c_void,
+        action: *const c_char,
+        context: *const c_char,
+        client_id: *const c_char,
+    ) {
+        if ctx.is_null() || action.is_null() || context.is_null() {
+            return;
+        }
+
+        // SAFETY: The context is a live recorder and the strings are nul terminated, as required
+        // by the connector C ABI.
+        let (recorder, event) = unsafe {
+            let to_string = |value: *const c_char| CStr::from_ptr(value).to_string_lossy();
+
+            (
+                &*(ctx as *const Self),
+                MonitorEvent {
+                    action: to_string(action).into_owned(),
+                    context: to_string(context).into_owned(),
+                    client_id: (!client_id.is_null()).then(|| to_string(client_id).into_owned()),
+                },
+            )
+        };
+
+        recorder.record(event);
+    }
+
+    /// Records an update.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The update to record.
+    pub fn record(&self, event: MonitorEvent) {
+        self.events.lock().unwrap().push(event);
+        self.updated.notify_all();
+    }
+
+    /// Returns the updates recorded so far.
+    pub fn events(&self) -> Vec<MonitorEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// Waits until at least the given number of updates are recorded, returning the updates
+    /// recorded once the count is reached or the timeout passes.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - The number of updates to wait for.
+    /// * `timeout` - The maximum time to wait.
+    pub fn wait_for(&self, count: usize, timeout: Duration) -> Vec<MonitorEvent> {
+        let deadline = Instant::now() + timeout;
+        let mut events = self.events.lock().unwrap();
+
+        while events.len() < count {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+
+            events = self.updated.wait_timeout(events, remaining).unwrap().0;
+        }
+
+        events.clone()
+    }
+}
+
+#[cfg(test)]
+mod monitor_recorder_tests {
+    use std::{ffi::CString, sync::Arc, thread};
+
+    use super::*;
+
+    #[test]
+    fn callback_records_events_test() {
+        let recorder = MonitorRecorder::new();
+        let action = CString::new("SUBSCRIBE").unwrap();
+        let topic = CString::new("topic").unwrap();
+        let client_id = CString::new("sub_1").unwrap();
+
+        MonitorRecorder::callback(
+            recorder.ctx(),
+            action.as_ptr(),
+            topic.as_ptr(),
+            client_id.as_ptr(),
+        );
+        MonitorRecorder::callback(
+            recorder.ctx(),
+            action.as_ptr(),
+            topic.as_ptr(),
+            std::ptr::null(),
+        );
+
+        assert_eq!(
+            vec![
+                MonitorEvent::new("SUBSCRIBE", "topic", Some("sub_1")),
+                MonitorEvent::new("SUBSCRIBE", "topic", None),
+            ],
+            recorder.events()
+        );
+    }
+
+    #[test]
+    fn wait_for_test() {
+        let recorder = Arc::new(MonitorRecorder::new());
+        let background_recorder = recorder.clone();
+
+        let handle = thread::spawn(move || {
+            background_recorder.record(MonitorEvent::new("UNSUBSCRIBE", "topic", None));
+        });
+
+        let actual = recorder.wait_for(1, Duration::from_secs(5));
+        handle.join().unwrap();
+        assert_eq!(1, actual.len());
+
+        // Returns what was recorded once the timeout passes.
+        assert_eq!(1, recorder.wait_for(2, Duration::from_millis(10)).len());
+    }
+}
@@ -0,0 +1,193 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Canned sequences of the monitor updates a connector reports to the Pub Sub Service.
+//!
+//! Actions use the same string form as the service's `PubSubAction` and the connector C ABI.
+
+/// Action reported when a client subscribes to a topic.
+pub const SUBSCRIBE: &str = "SUBSCRIBE";
+/// Action reported when a client unsubscribes from a topic.
+pub const UNSUBSCRIBE: &str = "UNSUBSCRIBE";
+/// Action reported when a publisher disconnects uncleanly.
+pub const PUB_DISCONNECT: &str = "PUBDISCONNECT";
+/// Action reported when a subscriber disconnects uncleanly.
+pub const SUB_DISCONNECT: &str = "SUBDISCONNECT";
+/// Action reported when the connector (re)connects to the messaging broker.
+pub const BROKER_CONNECTED: &str = "BROKERCONNECTED";
+/// Action reported when the connector loses its connection to the messaging broker.
+pub const BROKER_DISCONNECTED: &str = "BROKERDISCONNECTED";
+
+/// Every action that a connector may report.
+pub const CONNECTOR_ACTIONS: [&str; 6] = [
+    SUBSCRIBE,
+    UNSUBSCRIBE,
+    PUB_DISCONNECT,
+    SUB_DISCONNECT,
+    BROKER_CONNECTED,
+    BROKER_DISCONNECTED,
+];
+
+/// Callback action sent to a publisher when its topic gains a first subscriber.
+pub const START_CALLBACK: &str = "START";
+/// Callback action sent to a publisher when its topic loses its last subscriber.
+pub const STOP_CALLBACK: &str = "STOP";
+
+/// A single update reported by a connector.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MonitorEvent {
+    /// The action that happened on the broker.
+    pub action: String,
+    /// The topic, or for disconnects and connection updates the client, the update is for.
+    pub context: String,
+    /// The client that caused the update, if known.
+    pub client_id: Option<String>,
+}
+
+impl MonitorEvent {
+    /// Creates a new MonitorEvent.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The action that happened on the broker.
+    /// * `context` - The topic or client the update is for.
+    /// * `client_id` - The client that caused the update, if known.
+    pub fn new(action: &str, context: &str, client_id: Option<&str>) -> Self {
+        MonitorEvent {
+            action: action.to_string(),
+            context: context.to_string(),
+            client_id: client_id.map(str::to_string),
+        }
+    }
+}
+
+/// A sequence of updates and the publisher callbacks the service sends in response.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MonitorSequence {
+    /// The updates, in the order a connector reports them.
+    pub events: Vec<MonitorEvent>,
+    /// The callback actions sent to the publisher of the topic, in order.
+    pub expected_callbacks: Vec<&'static str>,
+}
+
+/// A subscriber subscribes to a topic and later unsubscribes, starting and then stopping the
+/// publisher.
+///
+/// # Arguments
+///
+/// * `topic` - The topic subscribed to.
+/// * `subscriber_id` - The client id of the subscriber.
+pub fn subscribe_then_unsubscribe(topic: &str, subscriber_id: &str) -> MonitorSequence {
+    MonitorSequence {
+        events: vec![
+            MonitorEvent::new(SUBSCRIBE, topic, Some(subscriber_id)),
+            MonitorEvent::new(UNSUBSCRIBE, topic, Some(subscriber_id)),
+        ],
+        expected_callbacks: vec![START_CALLBACK, STOP_CALLBACK],
+    }
+}
+
+/// Several subscribers subscribe to a topic and unsubscribe in the same order. The publisher is
+/// only started by the first subscriber and stopped by the last.
+///
+/// # Arguments
+///
+/// * `topic` - The topic subscribed to.
+/// * `subscriber_ids` - The client ids of the subscribers.
+pub fn subscriber_churn(topic: &str, subscriber_ids: &[&str]) -> MonitorSequence {
+    let subscribes = subscriber_ids
+        .iter()
+        .map(|id| MonitorEvent::new(SUBSCRIBE, topic, Some(id)));
+    let unsubscribes = subscriber_ids
+        .iter()
+        .map(|id| MonitorEvent::new(UNSUBSCRIBE, topic, Some(id)));
+
+    MonitorSequence {
+        events: subscribes.chain(unsubscribes).collect(),
+        expected_callbacks: if subscriber_ids.is_empty() {
+            Vec::new()
+        } else {
+            vec![START_CALLBACK, STOP_CALLBACK]
+        },
+    }
+}
+
+/// A subscriber subscribes to a topic and then disconnects uncleanly, which the service treats
+/// like an unsubscribe.
+///
+/// # Arguments
+///
+/// * `topic` - The topic subscribed to.
+/// * `subscriber_id` - The client id of the subscriber.
+pub fn subscriber_disconnect(topic: &str, subscriber_id: &str) -> MonitorSequence {
+    MonitorSequence {
+        events: vec![
+            MonitorEvent::new(SUBSCRIBE, topic, Some(subscriber_id)),
+            MonitorEvent::new(SUB_DISCONNECT, subscriber_id, None),
+        ],
+        expected_callbacks: vec![START_CALLBACK, STOP_CALLBACK],
+    }
+}
+
+/// The publisher of a topic disconnects uncleanly. The service deletes the publisher's topics
+/// without calling back the publisher.
+///
+/// # Arguments
+///
+/// * `publisher_id` - The client id of the publisher.
+pub fn publisher_disconnect(publisher_id: &str) -> MonitorSequence {
+    MonitorSequence {
+        events: vec![MonitorEvent::new(PUB_DISCONNECT, publisher_id, None)],
+        expected_callbacks: Vec::new(),
+    }
+}
+
+/// The connector loses its connection to the broker and reconnects. Topics are not timed out
+/// while the connection is lost, and no publisher callbacks are sent.
+///
+/// # Arguments
+///
+/// * `connector_client_id` - The client id of the connector.
+pub fn broker_reconnect(connector_client_id: &str) -> MonitorSequence {
+    MonitorSequence {
+        events: vec![
+            MonitorEvent::new(BROKER_DISCONNECTED, connector_client_id, None),
+            MonitorEvent::new(BROKER_CONNECTED, connector_client_id, None),
+        ],
+        expected_callbacks: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod monitor_sequence_tests {
+    use super::*;
+
+    #[test]
+    fn subscriber_churn_test() {
+        let actual = subscriber_churn("topic", &["sub_1", "sub_2"]);
+
+        let actions: Vec<&str> = actual.events.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(
+            vec![SUBSCRIBE, SUBSCRIBE, UNSUBSCRIBE, UNSUBSCRIBE],
+            actions
+        );
+        assert_eq!(Some("sub_2".to_string()), actual.events[3].client_id);
+        assert_eq!(
+            vec![START_CALLBACK, STOP_CALLBACK],
+            actual.expected_callbacks
+        );
+
+        assert!(subscriber_churn("topic", &[]).expected_callbacks.is_empty());
+    }
+
+    #[test]
+    fn subscriber_disconnect_test() {
+        let actual = subscriber_disconnect("topic", "sub_1");
+
+        assert_eq!(
+            MonitorEvent::new(SUB_DISCONNECT, "sub_1", None),
+            actual.events[1]
+        );
+    }
+}
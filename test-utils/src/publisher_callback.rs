@@ -0,0 +1,185 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! A fake publisher that records the topic management callbacks of the Pub Sub Service.
+//!
+//! Serve a [`FakePublisherCallback`] and use its uri as the management callback of the topics
+//! created in a test, then compare the recorded callbacks to the expected ones.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use proto::publisher::v1::{
+    publisher_callback_server::{PublisherCallback, PublisherCallbackServer},
+    ManageTopicRequest, ManageTopicResponse,
+};
+use tokio::{net::TcpListener, sync::Notify, task::JoinHandle};
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{transport::Server, Request, Response, Status};
+
+/// A topic management callback received from the Pub Sub Service.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecordedCallback {
+    /// The topic the callback is for.
+    pub topic: String,
+    /// The action the publisher is asked to take, such as `START` or `STOP`.
+    pub action: String,
+}
+
+/// Publisher callback server that records every callback it receives.
+#[derive(Clone, Debug, Default)]
+pub struct FakePublisherCallback {
+    callbacks: Arc<Mutex<Vec<RecordedCallback>>>,
+    received: Arc<Notify>,
+    delay: Option<(String, Duration)>,
+}
+
+impl FakePublisherCallback {
+    /// Creates a new FakePublisherCallback without any recorded callbacks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delays the callbacks of an action before they are recorded and answered, such as to hold
+    /// up a `START` while the service decides the next action.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The action whose callbacks are delayed.
+    /// * `delay` - How long each callback is delayed.
+    pub fn with_delay(mut self, action: &str, delay: Duration) -> Self {
+        self.delay = Some((action.to_string(), delay));
+        self
+    }
+
+    /// Serves the fake publisher on an ephemeral local port until the returned task is aborted.
+    ///
+    /// Returns the uri to use as the management callback of topics, and the server task.
+    pub async fn serve(
+        &self,
+    ) -> Result<(String, JoinHandle<()>), Box<dyn std::error::Error + Send + Sync>> {
+        self.serve_with(|service| service).await
+    }
+
+    /// Serves the fake publisher like [`FakePublisherCallback::serve`], with the generated server
+    /// configured first, such as to accept compressed callbacks.
+    ///
+    /// # Arguments
+    ///
+    /// * `configure` - Configures the generated server.
+    pub async fn serve_with(
+        &self,
+        configure: impl FnOnce(PublisherCallbackServer<Self>) -> PublisherCallbackServer<Self>,
+    ) -> Result<(String, JoinHandle<()>), Box<dyn std::error::Error + Send + Sync>> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let uri = format!("http://{}", listener.local_addr()?);
+        let service = configure(PublisherCallbackServer::new(self.clone()));
+
+        let handle = tokio::spawn(async move {
+            let _ = Server::builder()
+                .add_service(service)
+                .serve_with_incoming(TcpListenerStream::new(listener))
+                .await;
+        });
+
+        Ok((uri, handle))
+    }
+
+    /// Returns the callbacks received so far.
+    pub fn callbacks(&self) -> Vec<RecordedCallback> {
+        self.callbacks.lock().unwrap().clone()
+    }
+
+    /// Waits until at least the given number of callbacks are received, returning the callbacks
+    /// received once the count is reached or the timeout passes.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - The number of callbacks to wait for.
+    /// * `timeout` - The maximum time to wait.
+    pub async fn wait_for(&self, count: usize, timeout: Duration) -> Vec<RecordedCallback> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            // Registers interest before checking, so a callback received in between isn't missed.
+            let received = self.received.notified();
+
+            let callbacks = self.callbacks();
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if callbacks.len() >= count || remaining.is_zero() {
+                return callbacks;
+            }
+
+            let _ = tokio::time::timeout(remaining, received).await;
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl PublisherCallback for FakePublisherCallback {
+    /// Records the callback, once delayed if its action is.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The topic and action of the callback.
+    async fn manage_topic_callback(
+        &self,
+        request: Request<ManageTopicRequest>,
+    ) -> Result<Response<ManageTopicResponse>, Status> {
+        let request_inner = request.into_inner();
+
+        if let Some((action, delay)) = &self.delay {
+            if *action == request_inner.action {
+                tokio::time::sleep(*delay).await;
+            }
+        }
+
+        self.callbacks.lock().unwrap().push(RecordedCallback {
+            topic: request_inner.topic,
+            action: request_inner.action,
+        });
+        self.received.notify_waiters();
+
+        Ok(Response::new(ManageTopicResponse {}))
+    }
+}
+
+#[cfg(test)]
+mod publisher_callback_tests {
+    use proto::publisher::v1::publisher_callback_client::PublisherCallbackClient;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn serve_records_callbacks_test() {
+        let publisher = FakePublisherCallback::new();
+        let (uri, handle) = publisher.serve().await.unwrap();
+
+        let mut client = PublisherCallbackClient::connect(uri).await.unwrap();
+        client
+            .manage_topic_callback(ManageTopicRequest {
+                topic: "topic".to_string(),
+                action: "START".to_string(),
+                subject: String::new(),
+                lifecycle_epoch: 0,
+                subscriber_count: 0,
+                reconciliation: false,
+            })
+            .await
+            .unwrap();
+
+        let expected = vec![RecordedCallback {
+            topic: "topic".to_string(),
+            action: "START".to_string(),
+        }];
+        assert_eq!(
+            expected,
+            publisher.wait_for(1, Duration::from_secs(5)).await
+        );
+
+        handle.abort();
+    }
+}